@@ -0,0 +1,43 @@
+#!/usr/bin/env bash
+#
+# check-features.sh
+#
+# Type checks the crate with each supported feature combination, so that a feature
+# which only compiles alongside the defaults is noticed. Features must stay additive:
+# `dom-only` in particular removes the LLM API that other features may use.
+#
+# Usage:
+#   scripts/check-features.sh [cargo check arguments...]
+#
+set -euo pipefail
+
+combinations=(
+    ""
+    "mock-llm"
+    "node"
+    "extension"
+    "cdp"
+    "--no-default-features"
+    "--no-default-features dom-only"
+    "--no-default-features dom-only,node"
+    "--no-default-features dom-only,extension"
+    "--no-default-features dom-only,cdp"
+    "--all-features"
+)
+
+cd "$(dirname "$0")/.."
+for combination in "${combinations[@]}"; do
+    args=()
+    for word in $combination; do
+        case "$word" in
+            --*) args+=("$word") ;;
+            *) args+=(--features "$word") ;;
+        esac
+    done
+    echo -e "\e[33m[INFO]\e[0m cargo check --lib ${args[*]:-(default features)}"
+    if ! cargo check --lib "${args[@]}" "$@"; then
+        echo -e "\e[31m[ERROR]\e[0m the crate does not build with: ${combination:-default features}" >&2
+        exit 1
+    fi
+done
+echo -e "\e[32m[SUCCESS]\e[0m all ${#combinations[@]} feature combinations build"
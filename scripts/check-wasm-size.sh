@@ -0,0 +1,36 @@
+#!/usr/bin/env bash
+#
+# check-wasm-size.sh
+#
+# Builds the WASM module with the size-optimized `release-size` profile and fails if
+# it is larger than a budget, so that growth of the embedded bundle is noticed.
+#
+# The size is that of the module cargo produces, before wasm-bindgen and wasm-opt,
+# which only make it smaller; compare budgets measured the same way.
+#
+# Usage:
+#   scripts/check-wasm-size.sh <max_kb> [cargo build arguments...]
+#
+# Examples:
+#   scripts/check-wasm-size.sh 4096                          # with the built-in LLM client
+#   scripts/check-wasm-size.sh 2048 --no-default-features    # core only, LLM provider set from JS
+#
+set -euo pipefail
+
+if [[ $# -lt 1 ]]; then
+    echo "Usage: $0 <max_kb> [cargo build arguments...]" >&2
+    exit 2
+fi
+max_kb="$1"
+shift
+
+cd "$(dirname "$0")/.."
+cargo build --lib --target wasm32-unknown-unknown --profile release-size "$@"
+
+wasm="target/wasm32-unknown-unknown/release-size/rustagent.wasm"
+size_kb=$(( $(wc -c < "$wasm") / 1024 ))
+if (( size_kb > max_kb )); then
+    echo -e "\e[31m[ERROR]\e[0m $wasm is ${size_kb} KB, over the budget of ${max_kb} KB" >&2
+    exit 1
+fi
+echo -e "\e[32m[SUCCESS]\e[0m $wasm is ${size_kb} KB, within the budget of ${max_kb} KB"
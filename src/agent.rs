@@ -1,18 +1,89 @@
-use crate::llm::call_llm_async; // Changed from call_llm
-use crate::dom_utils::{self, DomError}; // Import DOM utility functions and DomError
+#[cfg(not(feature = "dom-only"))]
+use crate::audit::{self, LlmAuditLog}; // Redacted log of the prompts and responses of LLM calls
+use crate::llm_error::LlmError;
+#[cfg(not(feature = "dom-only"))]
+use crate::llm_profiles::{self, LlmProfiles}; // Named LLM configurations and task hints
+#[cfg(not(feature = "dom-only"))]
+use crate::budget; // LLM call budget shared by every RustAgent on the page
+use crate::bot_signals; // Advisory bot-detection signal reports
+use crate::captcha::{self, CaptchaWait}; // Captcha detection and handoff
+use crate::capture; // Element screenshots
+use crate::conditions::{self, ConditionTime, TaskCondition}; // Pre- and postconditions of tasks
+use crate::dom_backend; // The window commands act on
+use crate::dom_diff; // DOM fingerprints and diffs between commands
+#[cfg(not(feature = "dom-only"))]
+use crate::disambiguate; // LLM picks among elements matching a selector
+use crate::dom_utils::{self, DomError, SuggestionPick, WaitConfig}; // Import DOM utility functions and DomError
+use crate::emulation; // Geolocation and time zone overrides
+#[cfg(not(feature = "dom-only"))]
+use crate::escalation::{self, Escalation, ModelEscalation}; // Planning retried with a stronger model
+use crate::graphql; // GraphQL queries sent from the page
+#[cfg(not(feature = "dom-only"))]
+use crate::guardrails::{self, GuardrailKind, GuardrailReport, GuardrailWarning}; // Checks of LLM plans confirmed by the host
+use crate::host_functions::{self, HostFunctions}; // Functions registered by the host, called by CALL_FUNCTION
+use crate::json_assert; // Deep comparison of JSON values by ASSERT_JSON
+#[cfg(not(feature = "dom-only"))]
+use crate::judge; // LLM_DECIDE / LLM_ASSERT questions about page content
+use crate::listen; // Event logs of LISTEN
+use crate::locale; // Translated command names
+use crate::login::{self, LoginForm}; // Login forms and outcome detection
+use crate::network; // WebSocket capture, performance timing and FETCH requests
+use crate::openapi::{self, ApiRegistry}; // OpenAPI operations called by CALL_API
+use crate::pacing::Pacing; // Human-like pacing of commands
+use crate::result_code::{self, TaskResult}; // Stable codes of task results
+use crate::settle::{self, SettleConfig}; // Waits for the page to settle after interactions
+use crate::soft_assert::SoftAssertions; // Assertion failures collected instead of failing tasks
+use crate::watch; // DOM mutations observed by WATCH
+#[cfg(not(feature = "dom-only"))]
+use crate::memory::ExperienceMemory; // Commands that succeeded, recalled for similar tasks
+#[cfg(not(feature = "dom-only"))]
+use crate::prompt_examples::PromptExamples; // Few-shot examples of the structured prompt
+use crate::page_errors; // Console and page error capture
+#[cfg(not(feature = "dom-only"))]
+use crate::redact::{RedactionConfig, RedactionMap}; // PII redaction of LLM prompts
+use crate::run_limits::{RunAction, RunGuard, RunLimitExceeded}; // Caps on what a single run may do
+use crate::sandbox; // Copies of containers that previews run in
+use crate::secrets::SecretStore; // Secrets substituted at execution time
+use crate::seo; // Search engine metadata audits and structured data extraction
+use crate::storage::Storage; // Persistent key-value storage for baselines
+use crate::tour; // Guided tour overlay rendering
+use crate::transaction::Journal; // Undo journal for transactional execution
+use crate::variables::VariableStore; // Variables substituted at execution time
+use crate::worker::DomProxy; // DOM proxying between a worker and the main thread
+use crate::LibError; // Errors of commands performed through the DOM proxy
+use wasm_bindgen::JsCast; // For awaiting the Promise returned by the user input handler
 use web_sys::console; // For logging unexpected parsing issues
-use serde::Deserialize; // For JSON deserialization
+use serde::{Deserialize, Serialize}; // For JSON (de)serialization
 use std::error::Error;
 use std::fmt;
+use unicode_segmentation::UnicodeSegmentation; // For truncating results between grapheme clusters
+#[cfg(not(feature = "dom-only"))]
+use futures_util::FutureExt; // For running batches of read-only commands in one pass
+#[cfg(not(feature = "dom-only"))]
+use std::collections::VecDeque;
 
 // Define AgentError enum
 #[derive(Debug)]
 pub enum AgentError {
     DomOperationFailed(DomError),
-    LlmCallFailed(String),
+    LlmCallFailed(String), // For LLM failures that are none of the kinds below
+    LlmRateLimited { message: String, retry_after_ms: Option<u64> }, // For LLM calls refused for too many requests
+    LlmContextLengthExceeded(String), // For prompts longer than the model accepts
+    LlmAuthFailed(String), // For LLM calls with a missing, invalid or unauthorized API key
+    LlmContentFiltered(String), // For prompts or responses blocked by the provider's content filter
+    LlmNetworkError(String), // For LLM requests that got no response
     InvalidLlmResponse(String),
     CommandParseError(String), // For errors during the parsing of direct string commands
     SerializationError(String), // For errors during serialization of results
+    PolicyViolation(String), // For commands refused by the configured execution policy
+    PlanValidationFailed(String), // For LLM command arrays rejected before execution; holds the JSON report
+    AssertionFailed(String), // For checks such as ASSERT_VISUAL_MATCH that ran but did not hold
+    BudgetExceeded(String), // For LLM calls refused because the shared budget is spent
+    ProxiedCommandFailed(LibError), // For DOM commands that failed on the main thread while proxied from a worker
+    UserInputUnavailable(String), // For REQUEST_USER_INPUT without a handler or cancelled, and captchas left unsolved
+    RunLimitExceeded(RunLimitExceeded), // For actions beyond the caps of the run, which abort it
+    ApiCallFailed { message: String, status: Option<u16> }, // For CALL_API requests that were refused, not made or answered with an error status, and failed GRAPHQL queries
+    HostFunctionFailed(String), // For CALL_FUNCTION calls of unknown functions, with invalid arguments, or whose handler failed
 }
 
 impl fmt::Display for AgentError {
@@ -20,9 +91,27 @@ impl fmt::Display for AgentError {
         match self {
             AgentError::DomOperationFailed(e) => write!(f, "DOM Operation Failed: {}", e),
             AgentError::LlmCallFailed(s) => write!(f, "LLM Call Failed: {}", s),
+            AgentError::LlmRateLimited { message, retry_after_ms: Some(ms) } => write!(f, "LLM Call Failed: Rate limited (retry after {}ms): {}", ms, message),
+            AgentError::LlmRateLimited { message, retry_after_ms: None } => write!(f, "LLM Call Failed: Rate limited: {}", message),
+            AgentError::LlmContextLengthExceeded(s) => write!(f, "LLM Call Failed: Context length exceeded: {}", s),
+            AgentError::LlmAuthFailed(s) => write!(f, "LLM Call Failed: Authentication failed: {}", s),
+            AgentError::LlmContentFiltered(s) => write!(f, "LLM Call Failed: Content filtered: {}", s),
+            AgentError::LlmNetworkError(s) => write!(f, "LLM Call Failed: Network error: {}", s),
             AgentError::InvalidLlmResponse(s) => write!(f, "Invalid LLM Response: {}", s),
             AgentError::CommandParseError(s) => write!(f, "Command Parse Error: {}", s),
             AgentError::SerializationError(s) => write!(f, "Serialization Error: {}", s),
+            AgentError::PolicyViolation(s) => write!(f, "Policy Violation: {}", s),
+            AgentError::PlanValidationFailed(s) => write!(f, "Plan Validation Failed: {}", s),
+            AgentError::AssertionFailed(s) => write!(f, "Assertion Failed: {}", s),
+            AgentError::BudgetExceeded(s) => write!(f, "Budget Exceeded: {}", s),
+            AgentError::UserInputUnavailable(s) => write!(f, "User Input Unavailable: {}", s),
+            AgentError::RunLimitExceeded(e) => write!(f, "Run Limit Exceeded: {}", e),
+            AgentError::ApiCallFailed { message, status: Some(status) } => write!(f, "API Call Failed: HTTP {}: {}", status, message),
+            AgentError::ApiCallFailed { message, status: None } => write!(f, "API Call Failed: {}", message),
+            AgentError::HostFunctionFailed(s) => write!(f, "Host Function Failed: {}", s),
+            AgentError::ProxiedCommandFailed(e) => {
+                write!(f, "Proxied Command Failed: {}", serde_json::to_string(e).unwrap_or_else(|_| format!("{:?}", e)))
+            }
         }
     }
 }
@@ -42,6 +131,19 @@ impl From<DomError> for AgentError {
     }
 }
 
+impl From<LlmError> for AgentError {
+    fn from(err: LlmError) -> Self {
+        match err {
+            LlmError::RateLimited { message, retry_after_ms } => AgentError::LlmRateLimited { message, retry_after_ms },
+            LlmError::ContextLengthExceeded(message) => AgentError::LlmContextLengthExceeded(message),
+            LlmError::AuthFailed(message) => AgentError::LlmAuthFailed(message),
+            LlmError::ContentFiltered(message) => AgentError::LlmContentFiltered(message),
+            LlmError::Network(message) => AgentError::LlmNetworkError(message),
+            LlmError::Other(message) => AgentError::LlmCallFailed(message),
+        }
+    }
+}
+
 
 // 1. Define AgentRole Enum
 /// Defines the specialized roles an `Agent` can take on.
@@ -78,9 +180,9 @@ pub struct Agent {
 /// from JSON. It ensures that incoming JSON strings like `"CLICK"`, `"TYPE"`, etc.,
 /// are correctly mapped to the corresponding enum variants (e.g., `DomCommandAction::Click`),
 /// regardless of the case used in the Rust code for the variant names themselves.
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
-enum DomCommandAction {
+pub(crate) enum DomCommandAction {
     /// Represents a click action on a DOM element.
     Click,
     /// Represents a typing action into a DOM element (e.g., an input field).
@@ -99,6 +201,10 @@ enum DomCommandAction {
     GetAllAttributes,
     /// Represents getting the current URL of the page.
     GetUrl,
+    /// Represents navigating the page to a URL. Subject to the origin allowlist in `ExecutionConfig`.
+    Navigate,
+    /// Represents listing the iframes of the page.
+    GetFrames,
     /// Represents checking if an element exists on the page.
     ElementExists,
     /// Represents waiting for an element to appear on the page within a timeout.
@@ -111,6 +217,190 @@ enum DomCommandAction {
     Hover,
     /// Represents getting all text from elements matching a selector, joined by a separator.
     GetAllText,
+    /// Represents evaluating an XPath expression that may yield a number, string, boolean or node-set.
+    XpathEval,
+    /// Represents showing a guided tour step on an element and waiting for the user to press "Next".
+    TourStep,
+    /// Represents showing a guided tour step on an element without waiting for the user.
+    TourShow,
+    /// Represents removing the currently displayed guided tour step.
+    TourEnd,
+    /// Represents evaluating a JavaScript snippet. Only allowed when enabled in `ExecutionConfig`.
+    EvalJs,
+    /// Represents injecting a stylesheet into the page.
+    InjectCss,
+    /// Represents removing one (or all) stylesheets previously injected with `InjectCss`.
+    RemoveInjectedCss,
+    /// Represents dismissing overlays (cookie banners, modals) that cover the page.
+    DismissOverlays,
+    /// Represents getting every attribute of a single element as a JSON object.
+    GetElementAttributes,
+    /// Represents getting the `data-*` attributes (`dataset`) of an element as a JSON object.
+    GetDataset,
+    /// Represents getting the (explicit or implicit) ARIA role of an element.
+    GetRole,
+    /// Represents getting the role and ARIA widget states (expanded, checked, etc.) of an element as JSON.
+    GetAriaState,
+    /// Represents checking if an element is enabled (not disabled natively, via a fieldset, or via ARIA).
+    IsEnabled,
+    /// Represents checking if an element accepts text input (enabled, not readonly).
+    IsEditable,
+    /// Represents checking if an element is selected (checked checkbox/radio, selected option, `aria-selected`).
+    IsSelected,
+    /// Represents a click that is skipped (instead of failing) when the element does not exist.
+    ClickIfExists,
+    /// Represents typing that is skipped (instead of failing) when the element does not exist.
+    TypeIfExists,
+    /// Represents clicking whatever element is rendered at given viewport coordinates.
+    ClickAt,
+    /// Represents clicking the center of an element's bounding box.
+    ClickCenter,
+    /// Represents moving the mouse pointer to an element or to viewport coordinates.
+    MouseMove,
+    /// Represents a mouse wheel scroll over an element.
+    Wheel,
+    /// Represents a finger tap on an element.
+    Tap,
+    /// Represents a single-finger swipe starting on an element.
+    Swipe,
+    /// Represents pressing and holding a finger on an element.
+    LongPress,
+    /// Represents typing a query into an autocomplete field and picking one of its suggestions.
+    TypeAndSelect,
+    /// Represents setting the value of a range input or ARIA slider.
+    SetRange,
+    /// Represents typing into a rich text editor (`contenteditable`, ProseMirror, Quill).
+    RichTextType,
+    /// Represents listing the messages captured from the page's WebSockets.
+    GetWsMessages,
+    /// Represents waiting for a captured WebSocket message containing a pattern.
+    WaitForWsMessage,
+    /// Represents reading navigation and paint timing from the Performance API.
+    GetPerfMetrics,
+    /// Represents listing the console errors and warnings captured since the last check.
+    GetConsoleErrors,
+    /// Represents overriding the position reported by `navigator.geolocation`.
+    SetGeolocation,
+    /// Represents evaluating a CSS media query with `matchMedia`.
+    GetMediaQuery,
+    /// Represents checking whether the page prefers a dark color scheme.
+    IsDarkMode,
+    /// Represents taking a PNG screenshot of an element.
+    CaptureElement,
+    /// Represents comparing a screenshot of an element with a stored baseline.
+    AssertVisualMatch,
+    /// Represents listing the elements changed by the previous command.
+    GetDomDiff,
+    /// Represents reporting the declared language of the page and the one detected from its text.
+    GetLang,
+    /// Represents moving focus with Tab presses until an element is focused.
+    TabTo,
+    /// Represents describing the focused element.
+    GetFocused,
+    /// Represents auditing the page's search engine metadata.
+    AuditSeo,
+    /// Represents collecting the page's JSON-LD and microdata.
+    ExtractStructuredData,
+    /// Represents searching with a search box and opening the result that best matches a text.
+    SearchAndOpen,
+    /// Represents filling in and submitting a login form, then waiting for its outcome.
+    Login,
+    /// Represents asking the user for a value, such as a one-time code, and storing it in a variable.
+    RequestUserInput,
+    /// Represents reporting the signals that may make a site take the agent for a bot.
+    CheckBotSignals,
+    /// Represents calling an operation of an OpenAPI spec registered by the host.
+    CallApi,
+    /// Represents sending a GraphQL query to an endpoint from the page, with the user's cookies.
+    GraphQl,
+    /// Represents making an HTTP request from the page and returning the response.
+    Fetch,
+    /// Represents calling a function registered by the host with typed arguments.
+    CallFunction,
+    /// Represents logging the events an element receives for a duration.
+    Listen,
+    /// Represents observing the DOM changes under an element for a duration.
+    Watch,
+    /// Represents comparing a JSON value, such as the result of the previous task, with an expected one.
+    AssertJson,
+}
+
+impl DomCommandAction {
+    /// The name of the command, as tasks write it (e.g. `CLICK`, `GETVALUE`).
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            DomCommandAction::Click => "CLICK",
+            DomCommandAction::Type => "TYPE",
+            DomCommandAction::Read => "READ",
+            DomCommandAction::GetValue => "GETVALUE",
+            DomCommandAction::GetAttribute => "GETATTRIBUTE",
+            DomCommandAction::SetAttribute => "SETATTRIBUTE",
+            DomCommandAction::SelectOption => "SELECTOPTION",
+            DomCommandAction::GetAllAttributes => "GET_ALL_ATTRIBUTES",
+            DomCommandAction::GetUrl => "GET_URL",
+            DomCommandAction::Navigate => "NAVIGATE",
+            DomCommandAction::GetFrames => "GET_FRAMES",
+            DomCommandAction::ElementExists => "ELEMENT_EXISTS",
+            DomCommandAction::WaitForElement => "WAIT_FOR_ELEMENT",
+            DomCommandAction::IsVisible => "IS_VISIBLE",
+            DomCommandAction::ScrollTo => "SCROLL_TO",
+            DomCommandAction::Hover => "HOVER",
+            DomCommandAction::GetAllText => "GET_ALL_TEXT",
+            DomCommandAction::XpathEval => "XPATH_EVAL",
+            DomCommandAction::TourStep => "TOUR_STEP",
+            DomCommandAction::TourShow => "TOUR_SHOW",
+            DomCommandAction::TourEnd => "TOUR_END",
+            DomCommandAction::EvalJs => "EVAL_JS",
+            DomCommandAction::InjectCss => "INJECT_CSS",
+            DomCommandAction::RemoveInjectedCss => "REMOVE_INJECTED_CSS",
+            DomCommandAction::DismissOverlays => "DISMISS_OVERLAYS",
+            DomCommandAction::GetElementAttributes => "GET_ELEMENT_ATTRIBUTES",
+            DomCommandAction::GetDataset => "GET_DATASET",
+            DomCommandAction::GetRole => "GET_ROLE",
+            DomCommandAction::GetAriaState => "GET_ARIA_STATE",
+            DomCommandAction::IsEnabled => "IS_ENABLED",
+            DomCommandAction::IsEditable => "IS_EDITABLE",
+            DomCommandAction::IsSelected => "IS_SELECTED",
+            DomCommandAction::ClickIfExists => "CLICK_IF_EXISTS",
+            DomCommandAction::TypeIfExists => "TYPE_IF_EXISTS",
+            DomCommandAction::ClickAt => "CLICK_AT",
+            DomCommandAction::ClickCenter => "CLICK_CENTER",
+            DomCommandAction::MouseMove => "MOUSE_MOVE",
+            DomCommandAction::Wheel => "WHEEL",
+            DomCommandAction::Tap => "TAP",
+            DomCommandAction::Swipe => "SWIPE",
+            DomCommandAction::LongPress => "LONG_PRESS",
+            DomCommandAction::TypeAndSelect => "TYPE_AND_SELECT",
+            DomCommandAction::SetRange => "SET_RANGE",
+            DomCommandAction::RichTextType => "RICH_TEXT_TYPE",
+            DomCommandAction::GetWsMessages => "GET_WS_MESSAGES",
+            DomCommandAction::WaitForWsMessage => "WAIT_FOR_WS_MESSAGE",
+            DomCommandAction::GetPerfMetrics => "GET_PERF_METRICS",
+            DomCommandAction::GetConsoleErrors => "GET_CONSOLE_ERRORS",
+            DomCommandAction::SetGeolocation => "SET_GEOLOCATION",
+            DomCommandAction::GetMediaQuery => "GET_MEDIA_QUERY",
+            DomCommandAction::IsDarkMode => "IS_DARK_MODE",
+            DomCommandAction::CaptureElement => "CAPTURE_ELEMENT",
+            DomCommandAction::AssertVisualMatch => "ASSERT_VISUAL_MATCH",
+            DomCommandAction::GetDomDiff => "GET_DOM_DIFF",
+            DomCommandAction::GetLang => "GET_LANG",
+            DomCommandAction::TabTo => "TAB_TO",
+            DomCommandAction::GetFocused => "GET_FOCUSED",
+            DomCommandAction::AuditSeo => "AUDIT_SEO",
+            DomCommandAction::ExtractStructuredData => "EXTRACT_STRUCTURED_DATA",
+            DomCommandAction::SearchAndOpen => "SEARCH_AND_OPEN",
+            DomCommandAction::Login => "LOGIN",
+            DomCommandAction::RequestUserInput => "REQUEST_USER_INPUT",
+            DomCommandAction::CheckBotSignals => "CHECK_BOT_SIGNALS",
+            DomCommandAction::CallApi => "CALL_API",
+            DomCommandAction::GraphQl => "GRAPHQL",
+            DomCommandAction::Fetch => "FETCH",
+            DomCommandAction::CallFunction => "CALL_FUNCTION",
+            DomCommandAction::Listen => "LISTEN",
+            DomCommandAction::Watch => "WATCH",
+            DomCommandAction::AssertJson => "ASSERT_JSON",
+        }
+    }
 }
 
 /// Represents a fully parsed and validated command, ready for direct execution by an agent.
@@ -120,28 +410,64 @@ enum DomCommandAction {
 /// after an LLM has proposed a command. It signifies that the command's action type
 /// is recognized and its essential components (like selector, and value/attribute_name
 /// if required by the action) are present in a structured way.
-#[derive(Debug, Clone)]
-struct DomCommand {
+///
+/// It is also the `command` of the DOM proxy protocol between a worker and the main thread
+/// (see `worker::DomProxy`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DomCommand {
     /// The specific DOM operation to be performed (e.g., Click, Type).
-    action: DomCommandAction,
-    /// The CSS selector (e.g., `css:#id`, `css:.class`) or XPath expression
-    /// (e.g., `xpath://div[@id='example']`) used to target the DOM element(s) for the action.
-    selector: String,
+    pub(crate) action: DomCommandAction,
+    /// The CSS selector (e.g., `css:#id`, `css:.class`), XPath expression
+    /// (e.g., `xpath://div[@id='example']`) or visible text (e.g., `text:Sign in`) used to target
+    /// the DOM element(s) for the action, or a fallback chain of them tried in order
+    /// (e.g., `[css:#buy, text:"Buy now"]`, see `dom_utils::selector_candidates`).
+    pub(crate) selector: String,
     /// An optional value associated with the action.
     /// This is used for commands like:
-    /// - `TYPE`: The text to be typed into an element.
+    /// - `TYPE` / `TYPE_IF_EXISTS` / `RICH_TEXT_TYPE`: The text to be typed into an element.
     /// - `SELECTOPTION`: The value of the option to be selected in a dropdown.
     /// - `SETATTRIBUTE`: The value to set for a specified attribute.
     /// - `WAIT_FOR_ELEMENT`: Optionally, the timeout in milliseconds.
+    /// - `CLICK_AT`: The viewport coordinates, as `"<x> <y>"`.
+    /// - `WHEEL`: The vertical scroll delta in pixels.
+    /// - `SWIPE`: The direction, optionally followed by the distance in pixels, as `"<direction> [distance]"`.
+    /// - `LONG_PRESS`: The optional hold duration in milliseconds.
+    /// - `TYPE_AND_SELECT`: The suggestion selector, the query and optionally the suggestion to pick,
+    ///   as `"<suggestion_selector> <query> [| <text|#position>]"`.
+    /// - `SEARCH_AND_OPEN`: The result selector, the search terms and optionally the text of the
+    ///   result to open, as `"<result_selector> <terms> [| <wanted_text>]"`.
+    /// - `LOGIN`: The other form and outcome selectors and the credentials, as
+    ///   `"<password_selector> <submit_selector> <success_selector> [failure_selector] | <username> <password>"`.
+    /// - `REQUEST_USER_INPUT`: The variable name and optionally the prompt, as `"<name> [prompt]"`.
+    /// - `CALL_API`: Optionally, the arguments of the operation as a JSON object, with the request
+    ///   body under `"body"`.
+    /// - `GRAPHQL`: The query and optionally its variables as a JSON object, as `"<query> [| <variables_json>]"`.
+    /// - `FETCH`: The HTTP method and optionally the request body, as `"<method> [body]"`.
+    /// - `CALL_FUNCTION`: Optionally, the arguments of the function as a JSON object.
+    /// - `LISTEN`: The event types and the duration, as `"<event>[,<event>...] <duration_ms>"`.
+    /// - `WATCH`: The duration in milliseconds.
+    /// - `ASSERT_JSON`: The values compared and the path, as `"<actual_json> <expected_json> [json_path]"`.
+    /// - `SET_RANGE`: The numeric value to set.
+    /// - `GET_WS_MESSAGES`: Optionally, text the socket URL must contain.
+    /// - `WAIT_FOR_WS_MESSAGE`: The text the message must contain.
+    /// - `GET_CONSOLE_ERRORS`: Optionally, `FAIL` to fail if a console error was captured.
+    /// - `SET_GEOLOCATION`: The position, as `"<latitude> <longitude> [accuracy_m]"`.
+    /// - `GET_MEDIA_QUERY`: The media query to evaluate.
+    /// - `ASSERT_VISUAL_MATCH`: The baseline key and the threshold, as `"<baseline_key> <threshold>"`.
+    /// - `TOUR_STEP` / `TOUR_SHOW`: The text shown in the tour tooltip.
+    /// - `EVAL_JS`: The JavaScript snippet to evaluate.
+    /// - `NAVIGATE`: The URL to open.
+    /// - `INJECT_CSS`: The CSS text to inject.
+    /// - `REMOVE_INJECTED_CSS`: Optionally, the id of the injected stylesheet to remove.
     /// For actions that do not require an explicit value (e.g., `CLICK`, `READ`, `GET_URL`), this is `None`.
-    value: Option<String>,
+    pub(crate) value: Option<String>,
     /// An optional attribute name.
     /// This is used for commands like:
     /// - `GETATTRIBUTE`: The name of the attribute whose value is to be read.
     /// - `SETATTRIBUTE`: The name of the attribute whose value is to be set.
     /// - `GET_ALL_ATTRIBUTES`: The name of the attribute to retrieve from all matching elements.
     /// For actions not operating on specific attributes (e.g., `CLICK`, `TYPE`, `READ`), this is `None`.
-    attribute_name: Option<String>,
+    pub(crate) attribute_name: Option<String>,
 }
 
 /// Represents a command request as deserialized from an LLM's JSON output.
@@ -152,6 +478,7 @@ struct DomCommand {
 /// in LLM output format (like case differences or minor structural deviations) before
 /// rigorous validation and conversion into a `DomCommand`.
 #[derive(Deserialize, Debug)]
+#[cfg(not(feature = "dom-only"))]
 struct LlmDomCommandRequest {
     /// The action to perform, represented as a string (e.g., "CLICK", "type", "readAttribute").
     /// This string will be parsed and validated to map to a specific `DomCommandAction`.
@@ -164,12 +491,33 @@ struct LlmDomCommandRequest {
     /// An optional attribute name, as provided by the LLM.
     /// Similar in purpose to `DomCommand::attribute_name`.
     attribute_name: Option<String>,
+    /// Why the LLM proposes the command, in a few words (e.g. "opens the login form"). Not part
+    /// of the `DomCommand`; it is added to the command's result and `LLM_COMMAND_EVENT`.
+    reason: Option<String>,
+}
+
+/// The LLM a run talks to, as passed to `AgentSystem::run_task`.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "dom-only", allow(dead_code))]
+pub struct LlmEndpoint<'a> {
+    pub api_key: &'a str,
+    pub api_url: &'a str,
+    pub model_name: &'a str,
 }
 
+/// Name of the `CustomEvent` dispatched on `window` after each LLM-proposed command has run. Its
+/// `detail` is `{"index", "action", "selector", "reason", "success"}`.
+#[cfg(not(feature = "dom-only"))]
+pub const LLM_COMMAND_EVENT: &str = "llm_command";
+/// Reasons given by the LLM are cut to this many characters.
+#[cfg(not(feature = "dom-only"))]
+const MAX_REASON_CHARS: usize = 200;
+
 /// A list of available direct DOM command strings with their expected arguments.
 /// This is used for generating prompts for the LLM and for user reference.
 // Array size should be updated if new commands are added.
-const AVAILABLE_DOM_COMMANDS: [&str; 15] = [
+#[cfg(not(feature = "dom-only"))]
+const AVAILABLE_DOM_COMMANDS: [&str; 69] = [
     "CLICK <selector>",
     "TYPE <selector> <text>",
     "READ <selector>",
@@ -179,12 +527,66 @@ const AVAILABLE_DOM_COMMANDS: [&str; 15] = [
     "SELECTOPTION <selector> <option_value>",
     "GET_ALL_ATTRIBUTES <selector> <attribute_name> (returns a JSON array of attribute values)",
     "GET_URL",
+    "NAVIGATE <url> (refused for origins outside the allowlist)",
+    "GET_FRAMES",
     "ELEMENT_EXISTS <selector>",
     "WAIT_FOR_ELEMENT <selector> [timeout_ms]",
     "IS_VISIBLE <selector>",
     "SCROLL_TO <selector>",
     "HOVER <selector>",
     "GET_ALL_TEXT <selector> [separator]",
+    "XPATH_EVAL <expression> (returns a JSON object with the result type and value)",
+    "TOUR_STEP <selector> <text>",
+    "TOUR_SHOW <selector> <text>",
+    "TOUR_END",
+    "INJECT_CSS <css_text> (returns the id of the injected stylesheet)",
+    "REMOVE_INJECTED_CSS [style_id] (removes all injected stylesheets if no id is given)",
+    "DISMISS_OVERLAYS (clicks close/accept buttons of covering overlays, or hides them)",
+    "GET_ELEMENT_ATTRIBUTES <selector> (returns a JSON object of all attributes of the element)",
+    "GET_DATASET <selector> (returns a JSON object of the element's data-* attributes)",
+    "GET_ROLE <selector>",
+    "GET_ARIA_STATE <selector> (returns a JSON object of role, expanded, checked, selected, pressed, disabled, hidden)",
+    "IS_ENABLED <selector>",
+    "IS_EDITABLE <selector>",
+    "IS_SELECTED <selector>",
+    "CLICK_IF_EXISTS <selector> (skipped instead of failing if the element is absent)",
+    "TYPE_IF_EXISTS <selector> <text> (skipped instead of failing if the element is absent)",
+    "CLICK_AT <x> <y> (viewport coordinates)",
+    "CLICK_CENTER <selector>",
+    "MOUSE_MOVE <selector|x,y>",
+    "WHEEL <selector> <delta_y>",
+    "TAP <selector>",
+    "SWIPE <selector> <left|right|up|down> [distance_px]",
+    "LONG_PRESS <selector> [duration_ms]",
+    "TYPE_AND_SELECT <input_selector> <suggestion_selector> <query> [| <text|#position>] (types the query, then picks a suggestion)",
+    "SET_RANGE <selector> <value> (range inputs and ARIA sliders)",
+    "RICH_TEXT_TYPE <selector> <text> (contenteditable, ProseMirror and Quill editors)",
+    "GET_WS_MESSAGES [url_filter] (requires enable_websocket_capture)",
+    "WAIT_FOR_WS_MESSAGE <pattern> (requires enable_websocket_capture)",
+    "GET_PERF_METRICS (returns navigation and paint timing as JSON)",
+    "GET_CONSOLE_ERRORS [FAIL] (requires enable_console_capture; FAIL fails the command if an error was logged)",
+    "SET_GEOLOCATION <latitude> <longitude> [accuracy_m] (position reported by navigator.geolocation)",
+    "GET_MEDIA_QUERY <query> (whether a CSS media query such as (max-width: 600px) matches)",
+    "IS_DARK_MODE",
+    "CAPTURE_ELEMENT <selector> (returns a PNG data URL of the element)",
+    "ASSERT_VISUAL_MATCH <selector> <baseline_key> <threshold> (requires set_storage or set_baseline_storage; threshold as 0.01 or 1%)",
+    "GET_DOM_DIFF (requires set_track_dom_changes; elements added, removed and changed by the previous command)",
+    "GET_LANG (returns the declared page language and the one detected from its text, as JSON)",
+    "TAB_TO <selector> [max_tabs] (presses Tab until the element is focused), or TAB_TO <count> (presses Tab count times)",
+    "GET_FOCUSED (returns the focused element's description, role and accessible name as JSON)",
+    "AUDIT_SEO (returns title, meta description, canonical link, h1 headings, structured data and issues found as JSON)",
+    "EXTRACT_STRUCTURED_DATA (returns the page's JSON-LD blocks and microdata items as JSON)",
+    "SEARCH_AND_OPEN <input_selector> <result_selector> <terms> [| <wanted_text>] (searches, then clicks the result most similar to the wanted text or the terms)",
+    "LOGIN <username_selector> <password_selector> <submit_selector> <success_selector> [failure_selector] | <username> {{secret.<name>}} (logs in and reports whether it worked)",
+    "REQUEST_USER_INPUT <variable_name> [prompt] (requires set_user_input_handler; asks the user, e.g. for a one-time code, and stores the reply for {{var.<variable_name>}})",
+    "CHECK_BOT_SIGNALS (returns the webdriver, headless and timing signals that may get the agent blocked as bot traffic, with advice, as JSON)",
+    "CALL_API <api>.<operation_id> [json_arguments] (requires register_api; calls an operation of the site's API and returns the status and response body)",
+    "GRAPHQL <endpoint_url> <query> [| <variables_json>] (sends the query from the page with the user's cookies and returns the JSON response)",
+    "FETCH <method> <url> [body] (makes an HTTP request from the page and returns its status, headers and body as JSON)",
+    "CALL_FUNCTION <function_name> [json_arguments] (requires register_function; calls a function of the application and returns its result)",
+    "LISTEN <selector> <event>[,<event>...] <duration_ms> (logs the events the element and its descendants receive for the duration, at most 60000, as JSON)",
+    "WATCH <selector> <duration_ms> (returns the nodes added and removed and the attributes and text changed under the element during the duration, at most 60000, as JSON)",
+    "ASSERT_JSON <actual_json> <expected_json> [json_path] (fails, listing the differences, unless the actual JSON, e.g. {{PREVIOUS_RESULT}}, or its part at the path equals the expected JSON)",
 ];
 
 /// Generates a structured prompt for the LLM, instructing it on how to respond
@@ -196,6 +598,7 @@ const AVAILABLE_DOM_COMMANDS: [&str; 15] = [
 /// - Instructions for formatting commands as JSON objects.
 /// - A list of available actions and their specific JSON schemas.
 /// - An example of a valid JSON array response.
+/// - Examples of similar tasks on this site, if any.
 /// - Guidance on when to respond with natural language instead of commands.
 ///
 /// # Arguments
@@ -204,14 +607,18 @@ const AVAILABLE_DOM_COMMANDS: [&str; 15] = [
 /// * `original_task`: The user's task string.
 /// * `_available_commands_list`: (Currently unused, but kept for potential future use where
 ///   the list of commands might be dynamically passed or filtered).
+/// * `examples`: The examples selected for the task (see `PromptExamples::prompt_section`), or
+///   an empty string.
 ///
 /// # Returns
 /// A formatted string to be used as the prompt for the LLM.
+#[cfg(not(feature = "dom-only"))]
 fn generate_structured_llm_prompt(
     agent_id: u32,
     agent_role: &AgentRole,
     original_task: &str,
-    _available_commands_list: &[&str] // Parameter kept for signature compatibility
+    _available_commands_list: &[&str], // Parameter kept for signature compatibility
+    examples: &str,
 ) -> String {
     // The list of actions should ideally be derived directly from DomCommandAction variants
     // or a single source of truth to avoid discrepancies. For now, it's manually listed.
@@ -225,23 +632,70 @@ fn generate_structured_llm_prompt(
         "SELECTOPTION",
         "GET_ALL_ATTRIBUTES",
         "GET_URL",
+        "NAVIGATE",
+        "GET_FRAMES",
         "ELEMENT_EXISTS",
         "WAIT_FOR_ELEMENT",
         "IS_VISIBLE",
         "SCROLL_TO",
         "HOVER",
         "GET_ALL_TEXT",
+        "XPATH_EVAL",
+        "GET_ELEMENT_ATTRIBUTES",
+        "GET_DATASET",
+        "GET_ROLE",
+        "GET_ARIA_STATE",
+        "IS_ENABLED",
+        "IS_EDITABLE",
+        "IS_SELECTED",
+        "CLICK_IF_EXISTS",
+        "TYPE_IF_EXISTS",
+        "CLICK_CENTER",
+        "MOUSE_MOVE",
+        "WHEEL",
+        "TAP",
+        "SWIPE",
+        "LONG_PRESS",
+        "TYPE_AND_SELECT",
+        "SET_RANGE",
+        "RICH_TEXT_TYPE",
+        "GET_WS_MESSAGES",
+        "WAIT_FOR_WS_MESSAGE",
+        "GET_PERF_METRICS",
+        "GET_CONSOLE_ERRORS",
+        "SET_GEOLOCATION",
+        "GET_MEDIA_QUERY",
+        "IS_DARK_MODE",
+        "CAPTURE_ELEMENT",
+        "ASSERT_VISUAL_MATCH",
+        "GET_DOM_DIFF",
+        "GET_LANG",
+        "TAB_TO",
+        "GET_FOCUSED",
+        "AUDIT_SEO",
+        "EXTRACT_STRUCTURED_DATA",
+        "SEARCH_AND_OPEN",
+        "LOGIN",
+        "REQUEST_USER_INPUT",
+        "CHECK_BOT_SIGNALS",
     ];
     let action_list_str = actions.join(", ");
+    // The task is quoted as a JSON string, so quotes and line breaks in it cannot end it early;
+    // non-ASCII text (accents, emoji, right-to-left scripts) is kept as is rather than escaped.
+    let quoted_task = serde_json::to_string(original_task).unwrap_or_else(|_| format!("\"{}\"", original_task));
 
     format!(
         "You are Agent {} ({:?}).\n\
-        The user wants to perform the following task: \"{}\"\n\n\
+        The user wants to perform the following task: {}\n\n\
         Analyze the task. If it can be broken down into a sequence of specific DOM actions, \
         respond with a JSON array of command objects. Each object must have an \"action\" and a \"selector\". \
-        The \"value\" field is required for TYPE, SETATTRIBUTE, and SELECTOPTION actions. \
+        The \"value\" field is required for TYPE, TYPE_IF_EXISTS, SETATTRIBUTE, SELECTOPTION, WHEEL, SWIPE, NAVIGATE, TYPE_AND_SELECT, SEARCH_AND_OPEN, LOGIN, REQUEST_USER_INPUT, SET_RANGE, RICH_TEXT_TYPE, WAIT_FOR_WS_MESSAGE, SET_GEOLOCATION, GET_MEDIA_QUERY, and ASSERT_VISUAL_MATCH actions. \
         The \"attribute_name\" field is required for GETATTRIBUTE and SETATTRIBUTE actions, and for GET_ALL_ATTRIBUTES. \
-        Ensure selectors are valid CSS selectors (e.g., \"css:#elementId\", \"css:.className\") or XPath expressions (e.g., \"xpath://div[@id='example']\").\n\n\
+        Ensure selectors are valid CSS selectors (e.g., \"css:#elementId\", \"css:.className\") or XPath expressions (e.g., \"xpath://div[@id='example']\"), or the visible text of an element (e.g., \"text:Sign in\") when no stable selector exists. \
+        When unsure which selector will match, give a list of candidates tried in order, e.g. \"[css:#buy, text:Buy now, xpath://form//button[1]]\". \
+        Copy placeholders such as {{{{secret.password}}}} into TYPE and LOGIN values unchanged; they are filled in when the command runs. \
+        Keep text from the task in values exactly as written, including accented letters, emoji and right-to-left scripts; do not transliterate or translate it. \
+        Give each command a \"reason\" field saying in a few words why it is needed, e.g. \"opens the login form\"; it is shown to the user reviewing the automation.\n\n\
         Available actions are: {}.\n\n\
         JSON schema for commands:\n\
         - Click: {{\"action\": \"CLICK\", \"selector\": \"<selector>\"}}\n\
@@ -253,23 +707,177 @@ fn generate_structured_llm_prompt(
         - Select Option: {{\"action\": \"SELECTOPTION\", \"selector\": \"<selector>\", \"value\": \"<option_value>\"}}\n\
         - Get All Attributes: {{\"action\": \"GET_ALL_ATTRIBUTES\", \"selector\": \"<selector>\", \"attribute_name\": \"<attr_name>\"}} (returns a JSON array of attribute values for all matching elements)\n\
         - Get URL: {{\"action\": \"GET_URL\"}} (gets the current page URL)\n\
+        - Navigate: {{\"action\": \"NAVIGATE\", \"selector\": \"\", \"value\": \"<url>\"}} (opens the URL, absolute or relative to the current page; may be refused by the origin allowlist)\n\
+        - Get Frames: {{\"action\": \"GET_FRAMES\"}} (lists the page's iframes as a JSON array of index, name, src and same_origin)\n\
         - Element Exists: {{\"action\": \"ELEMENT_EXISTS\", \"selector\": \"<selector>\"}} (checks if an element exists on the page, returns true or false)\n\
         - Wait For Element: {{\"action\": \"WAIT_FOR_ELEMENT\", \"selector\": \"<selector>\", \"value\": <timeout_in_milliseconds_optional>}} (waits for an element to exist, returns nothing on success or error on timeout/failure)\n\
         - Is Visible: {{\"action\": \"IS_VISIBLE\", \"selector\": \"<selector>\"}} (checks if an element is currently visible on the page, returns true or false)\n\
         - Scroll To: {{\"action\": \"SCROLL_TO\", \"selector\": \"<selector>\"}} (scrolls the page to make the element visible)\n\
         - Hover: {{\"action\": \"HOVER\", \"selector\": \"<selector>\"}}\n\
-        - Get All Text: {{\"action\": \"GET_ALL_TEXT\", \"selector\": \"<selector>\", \"value\": \"<separator_optional>\"}} (gets text from all matching elements, joined by separator; value is the separator string)\n\n\
+        - Get All Text: {{\"action\": \"GET_ALL_TEXT\", \"selector\": \"<selector>\", \"value\": \"<separator_optional>\"}} (gets text from all matching elements, joined by separator; value is the separator string)\n\
+        - XPath Eval: {{\"action\": \"XPATH_EVAL\", \"selector\": \"<xpath_expression>\"}} (evaluates any XPath expression, e.g. count(//li), string(//h1) or //a/@href; returns a JSON object with the result type and value)\n\
+        - Get Element Attributes: {{\"action\": \"GET_ELEMENT_ATTRIBUTES\", \"selector\": \"<selector>\"}} (returns a JSON object with every attribute of the element)\n\
+        - Get Dataset: {{\"action\": \"GET_DATASET\", \"selector\": \"<selector>\"}} (returns a JSON object of the element's data-* attributes, keyed like element.dataset)\n\
+        - Get Role: {{\"action\": \"GET_ROLE\", \"selector\": \"<selector>\"}} (returns the element's ARIA role, explicit or implicit)\n\
+        - Get ARIA State: {{\"action\": \"GET_ARIA_STATE\", \"selector\": \"<selector>\"}} (returns a JSON object with role, expanded, checked, selected, pressed, disabled and hidden states; use it to branch on widget state)\n\
+        - Is Enabled: {{\"action\": \"IS_ENABLED\", \"selector\": \"<selector>\"}} (checks if an element is enabled, including disabled fieldsets and aria-disabled, returns true or false)\n\
+        - Is Editable: {{\"action\": \"IS_EDITABLE\", \"selector\": \"<selector>\"}} (checks if an element accepts text input, i.e. enabled and not readonly, returns true or false)\n\
+        - Is Selected: {{\"action\": \"IS_SELECTED\", \"selector\": \"<selector>\"}} (checks if a checkbox/radio is checked, an option is selected, or aria-selected is true, returns true or false)\n\
+        - Click If Exists: {{\"action\": \"CLICK_IF_EXISTS\", \"selector\": \"<selector>\"}} (like CLICK, but reports a skipped outcome instead of an error if the element is absent; use for optional UI such as promo popups)\n\
+        - Type If Exists: {{\"action\": \"TYPE_IF_EXISTS\", \"selector\": \"<selector>\", \"value\": \"<text_to_type>\"}} (like TYPE, but skipped if the element is absent)\n\
+        - Click Center: {{\"action\": \"CLICK_CENTER\", \"selector\": \"<selector>\"}} (clicks the center point of the element's bounding box, hitting whatever is rendered on top there; use for canvas UIs or when CLICK does not work)\n\
+        - Mouse Move: {{\"action\": \"MOUSE_MOVE\", \"selector\": \"<selector_or_x,y>\"}} (moves the pointer to the element's center, or to viewport coordinates written as \"x,y\"; use for hover menus)\n\
+        - Wheel: {{\"action\": \"WHEEL\", \"selector\": \"<selector>\", \"value\": \"<delta_y_pixels>\"}} (scrolls with the mouse wheel over the element; positive values scroll down)\n\
+        - Tap: {{\"action\": \"TAP\", \"selector\": \"<selector>\"}} (touch tap; use on mobile layouts that only react to touch)\n\
+        - Swipe: {{\"action\": \"SWIPE\", \"selector\": \"<selector>\", \"value\": \"<left|right|up|down> [distance_px]\"}} (single-finger swipe starting at the element, e.g. for carousels)\n\
+        - Long Press: {{\"action\": \"LONG_PRESS\", \"selector\": \"<selector>\", \"value\": \"[duration_ms]\"}} (press and hold; \"value\" is optional)\n\
+        - Type And Select: {{\"action\": \"TYPE_AND_SELECT\", \"selector\": \"<input_selector>\", \"value\": \"<suggestion_selector> <query> | <suggestion_text_or_#position>\"}} (types the query into an autocomplete field, waits for the suggestions and clicks the chosen one, the first by default)\n\
+        - Set Range: {{\"action\": \"SET_RANGE\", \"selector\": \"<selector>\", \"value\": \"<number>\"}} (sets an <input type=range> or moves an ARIA slider with arrow keys; returns the resulting value)\n\
+        - Rich Text Type: {{\"action\": \"RICH_TEXT_TYPE\", \"selector\": \"<selector>\", \"value\": \"<text_to_type>\"}} (types into a contenteditable rich text editor, e.g. comment boxes, ProseMirror or Quill; use TYPE for input elements)\n\
+        - Get WS Messages: {{\"action\": \"GET_WS_MESSAGES\", \"selector\": \"\", \"value\": \"[url_filter]\"}} (lists the messages received by the page's WebSockets as JSON; \"value\" is optional)\n\
+        - Wait For WS Message: {{\"action\": \"WAIT_FOR_WS_MESSAGE\", \"selector\": \"\", \"value\": \"<text>\"}} (waits until a WebSocket message containing the text arrives, e.g. after sending a chat message)\n\
+        - Get Perf Metrics: {{\"action\": \"GET_PERF_METRICS\", \"selector\": \"\"}} (returns the page's load timing as JSON: TTFB, DOMContentLoaded, load, first and largest contentful paint)\n\
+        - Get Console Errors: {{\"action\": \"GET_CONSOLE_ERRORS\", \"selector\": \"\", \"value\": \"[FAIL]\"}} (lists the console errors and warnings logged since the last check; with \"FAIL\" the command fails if an error was logged)\n\
+        - Set Geolocation: {{\"action\": \"SET_GEOLOCATION\", \"selector\": \"\", \"value\": \"<latitude> <longitude> [accuracy_m]\"}} (makes the page's geolocation API report this position, e.g. before using a store locator)\n\
+        - Get Media Query: {{\"action\": \"GET_MEDIA_QUERY\", \"selector\": \"\", \"value\": \"<media_query>\"}} (tells whether a CSS media query such as (max-width: 600px) matches, e.g. to know if the mobile layout is shown)\n\
+        - Is Dark Mode: {{\"action\": \"IS_DARK_MODE\", \"selector\": \"\"}} (tells whether the page is shown with a dark color scheme)\n\
+        - Capture Element: {{\"action\": \"CAPTURE_ELEMENT\", \"selector\": \"<selector>\"}} (returns a PNG screenshot of the element as a data URL, e.g. as visual evidence)\n\
+        - Assert Visual Match: {{\"action\": \"ASSERT_VISUAL_MATCH\", \"selector\": \"<selector>\", \"value\": \"<baseline_key> <threshold>\"}} (fails if more than the threshold, e.g. 1%, of the element's pixels differ from the stored baseline; records the baseline on first use)\n\
+        - Get DOM Diff: {{\"action\": \"GET_DOM_DIFF\", \"selector\": \"\"}} (lists the elements the previous command added, removed and changed, e.g. to check that a click opened something)\n\
+        - Get Language: {{\"action\": \"GET_LANG\", \"selector\": \"\"}} (returns the language the page declares and the one its text is written in, e.g. to pick button texts in that language)\n\
+        - Tab To: {{\"action\": \"TAB_TO\", \"selector\": \"<selector>\", \"value\": \"<max_tabs_optional>\"}} (moves focus with the Tab key until the element is focused, as a keyboard user would; with an empty selector, presses Tab \"value\" times)\n\
+        - Get Focused: {{\"action\": \"GET_FOCUSED\", \"selector\": \"\"}} (describes the focused element: its tag, role and accessible name)\n\
+        - Audit SEO: {{\"action\": \"AUDIT_SEO\", \"selector\": \"\"}} (reports the page title, meta description, canonical link, h1 headings and structured data, with the problems found)\n\
+        - Extract Structured Data: {{\"action\": \"EXTRACT_STRUCTURED_DATA\", \"selector\": \"\"}} (returns the product, article, recipe, etc. data the page publishes as JSON-LD and microdata; prefer it to reading elements when the page has such data)\n\
+        - Search And Open: {{\"action\": \"SEARCH_AND_OPEN\", \"selector\": \"<search_input_selector>\", \"value\": \"<result_link_selector> <search_terms> | <wanted_result_text_optional>\"}} (types the terms, presses Enter, waits for the results shown on the page and clicks the one whose text is most similar to the wanted text, or to the terms; use it instead of reading the results and choosing yourself)\n\
+        - Login: {{\"action\": \"LOGIN\", \"selector\": \"<username_selector>\", \"value\": \"<password_selector> <submit_selector> <success_selector> <failure_selector_optional> | <username> {{{{secret.password}}}}\"}} (fills in and submits a login form, then waits for the element shown once logged in or the error shown on failure; the password must be a secret placeholder)\n\
+        - Request User Input: {{\"action\": \"REQUEST_USER_INPUT\", \"selector\": \"\", \"value\": \"<variable_name> <prompt>\"}} (pauses until the user answers the prompt, e.g. with a two-factor code, and stores the answer; later commands use it as {{{{var.<variable_name>}}}}, e.g. in a TYPE value)\n\
+        - Check Bot Signals: {{\"action\": \"CHECK_BOT_SIGNALS\", \"selector\": \"\"}} (reports what may make the site take the agent for a bot, e.g. the webdriver flag, a headless browser or commands run without delays, with advice; use it when a site blocks or challenges the agent)\n\n\
         Example of a JSON array response:\n\
         [\n\
-          {{\"action\": \"TYPE\", \"selector\": \"css:#username\", \"value\": \"testuser\"}},\n\
-          {{\"action\": \"CLICK\", \"selector\": \"xpath://button[@type='submit']\"}}\n\
+          {{\"action\": \"TYPE\", \"selector\": \"css:#username\", \"value\": \"testuser\", \"reason\": \"enters the user name\"}},\n\
+          {{\"action\": \"CLICK\", \"selector\": \"xpath://button[@type='submit']\", \"reason\": \"submits the login form\"}}\n\
         ]\n\n\
+        {}\
         If the task is a general question, a request for information not obtainable through DOM actions (e.g., current URL, page title if not in DOM, or a summary), \
         or if it cannot be mapped to the defined DOM commands, respond with a natural language text answer. Do not attempt to create new DOM command structures not listed.",
-        agent_id, agent_role, original_task, action_list_str
+        agent_id, agent_role, quoted_task, action_list_str, examples
     )
 }
 
+// Private helper that splits the value of `TYPE_AND_SELECT`, `"<suggestion_selector> <query> [| <pick>]"`,
+// into the suggestion selector, the query and the suggestion to pick (the first one by default).
+fn parse_type_and_select_value(value: &str) -> Option<(&str, &str, SuggestionPick)> {
+    let (suggestion_selector, rest) = value.trim().split_once(' ')?;
+    let (query, pick) = match rest.rsplit_once('|') {
+        Some((query, pick)) => (query.trim(), SuggestionPick::parse(pick)?),
+        None => (rest.trim(), SuggestionPick::Position(1)),
+    };
+    if query.is_empty() {
+        return None;
+    }
+    Some((suggestion_selector, query, pick))
+}
+
+// Private helper that splits the value of `SEARCH_AND_OPEN`, `"<result_selector> <terms> [| <wanted_text>]"`,
+// into the result selector, the search terms and the text results are scored against (the terms by default).
+fn parse_search_and_open_value(value: &str) -> Option<(&str, &str, &str)> {
+    let (result_selector, rest) = value.trim().split_once(' ')?;
+    let (terms, wanted) = match rest.rsplit_once('|') {
+        Some((terms, wanted)) => (terms.trim(), wanted.trim()),
+        None => (rest.trim(), rest.trim()),
+    };
+    if terms.is_empty() || wanted.is_empty() {
+        return None;
+    }
+    Some((result_selector, terms, wanted))
+}
+
+// Private helper that splits the value of `LOGIN`,
+// `"<password_selector> <submit_selector> <success_selector> [failure_selector] | <username> <password>"`,
+// into the form (whose username input is `username_selector`) and the credentials. The password is
+// everything after the username, so that it may contain spaces once its secret is substituted.
+fn parse_login_value<'a>(username_selector: &'a str, value: &'a str) -> Option<(LoginForm<'a>, &'a str, &'a str)> {
+    let (selectors, credentials) = value.split_once('|')?;
+    let (username, password) = credentials.trim().split_once(char::is_whitespace)?;
+    let password = password.trim();
+    let form = match selectors.split_whitespace().collect::<Vec<_>>()[..] {
+        [password_selector, submit_selector, success_selector] => LoginForm {
+            username_selector, password_selector, submit_selector, success_selector, failure_selector: None,
+        },
+        [password_selector, submit_selector, success_selector, failure_selector] => LoginForm {
+            username_selector, password_selector, submit_selector, success_selector, failure_selector: Some(failure_selector),
+        },
+        _ => return None,
+    };
+    if username_selector.is_empty() || password.is_empty() {
+        return None;
+    }
+    Some((form, username, password))
+}
+
+// Private helper that splits the value of `REQUEST_USER_INPUT`, `"<name> [prompt]"`, into the
+// variable name and the prompt shown to the user, which defaults to asking for the variable.
+fn parse_user_input_value(value: &str) -> Option<(&str, String)> {
+    let value = value.trim();
+    let (name, prompt) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+    if !VariableStore::is_valid_name(name) {
+        return None;
+    }
+    let prompt = match prompt.trim() {
+        "" => format!("Please enter {}", name),
+        prompt => prompt.to_string(),
+    };
+    Some((name, prompt))
+}
+
+// Private helper that splits the value of `FETCH`, `"<method> [body]"`, into the method, uppercased,
+// and the body. Returns `None` for unknown methods and for `GET` and `HEAD` requests with a body.
+fn parse_fetch_value(value: &str) -> Option<(String, Option<&str>)> {
+    let value = value.trim();
+    let (method, body) = match value.split_once(char::is_whitespace) {
+        Some((method, body)) => (method.to_uppercase(), Some(body.trim())),
+        None => (value.to_uppercase(), None),
+    };
+    if !network::FETCH_METHODS.contains(&method.as_str()) || (body.is_some() && matches!(method.as_str(), "GET" | "HEAD")) {
+        return None;
+    }
+    Some((method, body))
+}
+
+// Private helper that parses the value of `SET_GEOLOCATION`, `"<latitude> <longitude> [accuracy_m]"`,
+// checking that the coordinates are in range. The accuracy defaults to `DEFAULT_GEOLOCATION_ACCURACY_M`.
+fn parse_geolocation_value(value: &str) -> Option<(f64, f64, f64)> {
+    let numbers: Vec<f64> = value.split_whitespace().map(|part| part.parse::<f64>().ok()).collect::<Option<_>>()?;
+    let (latitude, longitude, accuracy) = match numbers[..] {
+        [latitude, longitude] => (latitude, longitude, emulation::DEFAULT_GEOLOCATION_ACCURACY_M),
+        [latitude, longitude, accuracy] => (latitude, longitude, accuracy),
+        _ => return None,
+    };
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) || accuracy.is_nan() || accuracy < 0.0 {
+        return None;
+    }
+    Some((latitude, longitude, accuracy))
+}
+
+// Private helper that parses the value of `ASSERT_VISUAL_MATCH`, `"<baseline_key> <threshold>"`.
+// The threshold is a fraction of pixels (`0.01`) or a percentage (`1%`); it is returned as a fraction.
+fn parse_visual_match_value(value: &str) -> Option<(&str, f64)> {
+    let (baseline_key, threshold) = value.trim().split_once(char::is_whitespace)?;
+    let threshold = threshold.trim();
+    let threshold = match threshold.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().ok()? / 100.0,
+        None => threshold.parse::<f64>().ok()?,
+    };
+    if !(0.0..=1.0).contains(&threshold) {
+        return None;
+    }
+    Some((baseline_key, threshold))
+}
+
+/// Stands for a selector fallback chain while `parse_dom_command` parses the rest of a command.
+const SELECTOR_CHAIN_PLACEHOLDER: &str = "css:#rustagent-selector-chain";
+
 /// Parses a raw task string to determine if it represents a direct, predefined DOM command.
 ///
 /// This function attempts to match the beginning of the `task` string (case-insensitively)
@@ -278,15 +886,67 @@ fn generate_structured_llm_prompt(
 /// by that specific command (such as CSS selectors, text values, attribute names).
 ///
 /// The parsing logic is tailored to each command:
-/// - Commands like `CLICK`, `READ`, `GETVALUE`, `ELEMENT_EXISTS`, `IS_VISIBLE`, `SCROLL_TO`
-///   expect a single argument: the selector.
+/// - Commands like `CLICK`, `READ`, `GETVALUE`, `ELEMENT_EXISTS`, `IS_VISIBLE`, `SCROLL_TO`,
+///   `GET_ELEMENT_ATTRIBUTES`, `GET_DATASET`, `GET_ROLE`, `GET_ARIA_STATE`, `IS_ENABLED`,
+///   `IS_EDITABLE`, `IS_SELECTED` expect a single argument: the selector.
+/// - Wherever a command expects a selector first, a fallback chain such as
+///   `[css:#buy, text:"Buy now", xpath://button[1]]` may stand for it, spaces included.
 /// - `GET_URL` expects no arguments.
+/// - `NAVIGATE` expects a URL, absolute or relative to the current page.
+/// - `XPATH_EVAL` expects an XPath expression, which may contain spaces.
+/// - `GET_FRAMES` expects no arguments.
 /// - `TYPE` expects a selector and the text to type.
+/// - `CLICK_IF_EXISTS` and `TYPE_IF_EXISTS` take the same arguments as `CLICK` and `TYPE`.
+/// - `CLICK_AT` expects two numeric viewport coordinates; `CLICK_CENTER` expects a selector.
+/// - `MOUSE_MOVE` expects a selector or `x,y` viewport coordinates.
+/// - `WHEEL` expects a selector and a numeric vertical delta.
+/// - `TAP` expects a selector; `SWIPE` expects a selector, a direction and an optional numeric distance;
+///   `LONG_PRESS` expects a selector and an optional numeric duration in milliseconds.
+/// - `TYPE_AND_SELECT` expects an input selector, a suggestion selector, the query and optionally
+///   `| <text>` or `| #<position>` to choose the suggestion.
+/// - `LOGIN` expects username, password and submit selectors, the selector of an element shown once
+///   logged in, optionally that of an error element, then `| <username> <password>`; the password
+///   must be a `{{secret.<name>}}` placeholder.
+/// - `REQUEST_USER_INPUT` expects a variable name and an optional prompt, which may contain spaces.
+/// - `CALL_API` expects an operation as `<api>.<operation_id>` and optionally its arguments as a
+///   JSON object, which may contain spaces.
+/// - `GRAPHQL` expects an endpoint URL, a query and optionally `| <variables_json>`.
+/// - `FETCH` expects an HTTP method, a URL and optionally a body, which may contain spaces; `GET`
+///   and `HEAD` requests take no body.
+/// - `CALL_FUNCTION` expects a function name and optionally its arguments as a JSON object, which
+///   may contain spaces.
+/// - `LISTEN` expects a selector, comma-separated event types and a duration in milliseconds.
+/// - `WATCH` expects a selector and a duration in milliseconds.
+/// - `ASSERT_JSON` expects the actual JSON, which may follow a label such as `Structured data:`,
+///   the expected JSON and optionally a JSON path such as `$.items[0]`.
+/// - `SEARCH_AND_OPEN` expects a search input selector, a result selector, the search terms and
+///   optionally `| <text>` describing the result to open.
+/// - `SET_RANGE` expects a selector and a numeric value.
+/// - `RICH_TEXT_TYPE` expects a selector and the text to type.
+/// - `GET_WS_MESSAGES` expects an optional URL filter; `WAIT_FOR_WS_MESSAGE` expects the text to
+///   wait for, which may contain spaces.
+/// - `GET_PERF_METRICS` expects no arguments.
+/// - `GET_CONSOLE_ERRORS` expects nothing or `FAIL`.
+/// - `SET_GEOLOCATION` expects a latitude, a longitude and an optional accuracy in meters.
+/// - `GET_MEDIA_QUERY` expects a media query, which may contain spaces; `IS_DARK_MODE` expects no arguments.
+/// - `CAPTURE_ELEMENT` expects a selector.
+/// - `ASSERT_VISUAL_MATCH` expects a selector, a baseline key and a threshold; the selector may
+///   contain spaces.
+/// - `GET_DOM_DIFF` and `GET_LANG` expect no arguments.
+/// - `TAB_TO` expects a selector, which may contain spaces, and an optional maximum number of Tab
+///   presses, or only a number of presses; `GET_FOCUSED` expects no arguments.
+/// - `AUDIT_SEO`, `EXTRACT_STRUCTURED_DATA` and `CHECK_BOT_SIGNALS` expect no arguments.
 /// - `GETATTRIBUTE` expects a selector and an attribute name.
 /// - `SETATTRIBUTE` expects a selector, an attribute name, and a value for the attribute.
 /// - `SELECTOPTION` expects a selector and the value of the option to select.
 /// - `GET_ALL_ATTRIBUTES` expects a selector and an attribute name.
 /// - `WAIT_FOR_ELEMENT` expects a selector and an optional timeout value (in milliseconds).
+/// - `TOUR_STEP` and `TOUR_SHOW` expect a selector and the text to display.
+/// - `TOUR_END` expects no arguments.
+/// - `EVAL_JS` expects the JavaScript snippet (the rest of the string, used as a function body).
+/// - `INJECT_CSS` expects the CSS text (the rest of the string).
+/// - `REMOVE_INJECTED_CSS` expects an optional stylesheet id.
+/// - `DISMISS_OVERLAYS` expects no arguments.
 ///
 /// If the command keyword is recognized and the subsequent arguments can be successfully
 /// parsed according to the command's requirements, a `DomCommand` struct is constructed
@@ -305,11 +965,26 @@ fn generate_structured_llm_prompt(
 ///   (e.g., "CLICK" with no selector, "TYPE selector" with no text to type).
 ///   A `None` result typically signifies that the task is not a direct command and
 ///   should be passed to an LLM for more sophisticated interpretation.
-fn parse_dom_command(task: &str) -> Option<DomCommand> {
+pub(crate) fn parse_dom_command(task: &str) -> Option<DomCommand> {
     let parts: Vec<&str> = task.splitn(2, ' ').collect();
     let command_str = parts.get(0).unwrap_or(&"").to_uppercase(); // Command matching is case-insensitive
+    // Translated command names from the locale dictionary stand for the English ones.
+    let command_str = locale::command_alias(&command_str).unwrap_or(command_str);
     let args_str = parts.get(1).unwrap_or(&"");
 
+    // A selector fallback chain may contain spaces, so the command is parsed with a placeholder in
+    // its place, which must end up as the selector. `ASSERT_JSON` takes no selector, and its JSON
+    // arrays are not chains.
+    if let Some(chain_len) = dom_utils::selector_chain_len(args_str).filter(|_| command_str != "ASSERT_JSON") {
+        let task = format!("{} {}{}", parts[0], SELECTOR_CHAIN_PLACEHOLDER, &args_str[chain_len..]);
+        let mut dom_command = parse_dom_command(&task)?;
+        if dom_command.selector != SELECTOR_CHAIN_PLACEHOLDER {
+            return None;
+        }
+        dom_command.selector = args_str[..chain_len].to_string();
+        return Some(dom_command);
+    }
+
     match command_str.as_str() {
         "CLICK" => {
             if args_str.is_empty() { return None; }
@@ -332,914 +1007,4369 @@ fn parse_dom_command(task: &str) -> Option<DomCommand> {
                 attribute_name: None,
             })
         }
-        "READ" => {
-            if args_str.is_empty() { return None; }
+        "RICH_TEXT_TYPE" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let text_to_type = sub_parts.get(1).unwrap_or(&"");
+            if selector.is_empty() || text_to_type.is_empty() { return None; }
             Some(DomCommand {
-                action: DomCommandAction::Read,
-                selector: args_str.to_string(),
-                value: None,
+                action: DomCommandAction::RichTextType,
+                selector: selector.to_string(),
+                value: Some(text_to_type.to_string()),
                 attribute_name: None,
             })
         }
-        "GETVALUE" => {
-            if args_str.is_empty() { return None; }
+        "GET_WS_MESSAGES" => {
+            let url_filter = args_str.trim();
             Some(DomCommand {
-                action: DomCommandAction::GetValue,
-                selector: args_str.to_string(),
+                action: DomCommandAction::GetWsMessages,
+                selector: "".to_string(),
+                value: if url_filter.is_empty() { None } else { Some(url_filter.to_string()) },
+                attribute_name: None,
+            })
+        }
+        "GET_PERF_METRICS" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("GET_PERF_METRICS command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::GetPerfMetrics,
+                selector: "".to_string(),
                 value: None,
                 attribute_name: None,
             })
         }
-        "GETATTRIBUTE" => {
-            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
-            let selector = sub_parts.get(0).unwrap_or(&"");
-            let attribute_name = sub_parts.get(1).unwrap_or(&"");
-            if selector.is_empty() || attribute_name.is_empty() { return None; }
+        "GET_DOM_DIFF" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("GET_DOM_DIFF command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
             Some(DomCommand {
-                action: DomCommandAction::GetAttribute,
-                selector: selector.to_string(),
+                action: DomCommandAction::GetDomDiff,
+                selector: "".to_string(),
                 value: None,
-                attribute_name: Some(attribute_name.to_string()),
+                attribute_name: None,
             })
         }
-        "SETATTRIBUTE" => {
-            let sub_parts: Vec<&str> = args_str.splitn(3, ' ').collect();
-            let selector = sub_parts.get(0).unwrap_or(&"");
-            let attribute_name = sub_parts.get(1).unwrap_or(&"");
-            let attribute_value = sub_parts.get(2).unwrap_or(&"");
-            if selector.is_empty() || attribute_name.is_empty() || attribute_value.is_empty() { return None; }
+        "TAB_TO" => {
+            let args = args_str.trim();
+            if args.is_empty() { return None; }
+            // A lone number is a count of Tab presses; otherwise a trailing number is the maximum.
+            if args.parse::<u32>().is_ok() {
+                return Some(DomCommand {
+                    action: DomCommandAction::TabTo,
+                    selector: "".to_string(),
+                    value: Some(args.to_string()),
+                    attribute_name: None,
+                });
+            }
+            let (selector, max_tabs) = match args.rsplit_once(char::is_whitespace) {
+                Some((selector, max_tabs)) if max_tabs.parse::<u32>().is_ok() => (selector.trim(), Some(max_tabs.to_string())),
+                _ => (args, None),
+            };
             Some(DomCommand {
-                action: DomCommandAction::SetAttribute,
+                action: DomCommandAction::TabTo,
                 selector: selector.to_string(),
-                value: Some(attribute_value.to_string()),
-                attribute_name: Some(attribute_name.to_string()),
+                value: max_tabs,
+                attribute_name: None,
             })
         }
-        "SELECTOPTION" => {
-            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
-            let selector = sub_parts.get(0).unwrap_or(&"");
-            let value = sub_parts.get(1).unwrap_or(&"");
-            if selector.is_empty() || value.is_empty() { return None; }
+        "GET_FOCUSED" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("GET_FOCUSED command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
             Some(DomCommand {
-                action: DomCommandAction::SelectOption,
-                selector: selector.to_string(),
-                value: Some(value.to_string()),
+                action: DomCommandAction::GetFocused,
+                selector: "".to_string(),
+                value: None,
                 attribute_name: None,
             })
         }
-        "GET_ALL_ATTRIBUTES" => { // Renamed from GETALLATTRIBUTES to GET_ALL_ATTRIBUTES for consistency
-            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
-            let selector = sub_parts.get(0).unwrap_or(&"");
-            let attribute_name = sub_parts.get(1).unwrap_or(&"");
-            if selector.is_empty() || attribute_name.is_empty() { return None; }
+        "AUDIT_SEO" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("AUDIT_SEO command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
             Some(DomCommand {
-                action: DomCommandAction::GetAllAttributes,
-                selector: selector.to_string(),
-                value: None, // Not used for this action
-                attribute_name: Some(attribute_name.to_string()),
+                action: DomCommandAction::AuditSeo,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
             })
         }
-        "GET_URL" => {
-            if !args_str.is_empty() { 
-                console::warn_1(&format!("GET_URL command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+        "CHECK_BOT_SIGNALS" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("CHECK_BOT_SIGNALS command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
             }
             Some(DomCommand {
-                action: DomCommandAction::GetUrl,
-                selector: "".to_string(), 
+                action: DomCommandAction::CheckBotSignals,
+                selector: "".to_string(),
                 value: None,
                 attribute_name: None,
             })
         }
-        "ELEMENT_EXISTS" => {
-            if args_str.is_empty() { 
-                return None; 
+        "EXTRACT_STRUCTURED_DATA" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("EXTRACT_STRUCTURED_DATA command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
             }
             Some(DomCommand {
-                action: DomCommandAction::ElementExists,
+                action: DomCommandAction::ExtractStructuredData,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "GET_LANG" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("GET_LANG command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::GetLang,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "ASSERT_VISUAL_MATCH" => {
+            // The key and the threshold are the last two arguments, so the selector may contain spaces.
+            let mut parts = args_str.rsplitn(3, char::is_whitespace);
+            let threshold = parts.next()?;
+            let baseline_key = parts.next()?;
+            let selector = parts.next()?.trim();
+            let value = format!("{} {}", baseline_key, threshold);
+            if selector.is_empty() || parse_visual_match_value(&value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::AssertVisualMatch,
+                selector: selector.to_string(),
+                value: Some(value),
+                attribute_name: None,
+            })
+        }
+        "CAPTURE_ELEMENT" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::CaptureElement,
                 selector: args_str.to_string(),
                 value: None,
                 attribute_name: None,
             })
         }
-        "WAIT_FOR_ELEMENT" => {
-            let parts: Vec<&str> = args_str.splitn(2, ' ').collect();
-            let selector_str = parts.get(0).unwrap_or(&"");
-            if selector_str.is_empty() { return None; }
-
-            let timeout_val = parts.get(1).and_then(|s| s.parse::<u32>().ok());
-
+        "GET_MEDIA_QUERY" => {
+            let query = args_str.trim();
+            if query.is_empty() { return None; }
             Some(DomCommand {
-                action: DomCommandAction::WaitForElement,
-                selector: selector_str.to_string(),
-                value: timeout_val.map(|v| v.to_string()), 
+                action: DomCommandAction::GetMediaQuery,
+                selector: "".to_string(),
+                value: Some(query.to_string()),
                 attribute_name: None,
             })
         }
-        "IS_VISIBLE" => {
+        "IS_DARK_MODE" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("IS_DARK_MODE command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::IsDarkMode,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "SET_GEOLOCATION" => {
+            parse_geolocation_value(args_str)?;
+            Some(DomCommand {
+                action: DomCommandAction::SetGeolocation,
+                selector: "".to_string(),
+                value: Some(args_str.trim().to_string()),
+                attribute_name: None,
+            })
+        }
+        "GET_CONSOLE_ERRORS" => {
+            let mode = args_str.trim().to_uppercase();
+            if !mode.is_empty() && mode != "FAIL" { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GetConsoleErrors,
+                selector: "".to_string(),
+                value: if mode.is_empty() { None } else { Some(mode) },
+                attribute_name: None,
+            })
+        }
+        "WAIT_FOR_WS_MESSAGE" => {
             if args_str.is_empty() { return None; }
             Some(DomCommand {
-                action: DomCommandAction::IsVisible,
+                action: DomCommandAction::WaitForWsMessage,
+                selector: "".to_string(),
+                value: Some(args_str.to_string()),
+                attribute_name: None,
+            })
+        }
+        "CLICK_IF_EXISTS" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::ClickIfExists,
                 selector: args_str.to_string(),
                 value: None,
                 attribute_name: None,
             })
         }
-        "SCROLL_TO" => {
+        "TYPE_IF_EXISTS" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let text_to_type = sub_parts.get(1).unwrap_or(&"");
+            if selector.is_empty() || text_to_type.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::TypeIfExists,
+                selector: selector.to_string(),
+                value: Some(text_to_type.to_string()),
+                attribute_name: None,
+            })
+        }
+        "CLICK_AT" => {
+            let coordinates: Vec<&str> = args_str.split_whitespace().collect();
+            if coordinates.len() != 2 || coordinates.iter().any(|c| c.parse::<f64>().is_err()) {
+                return None;
+            }
+            Some(DomCommand {
+                action: DomCommandAction::ClickAt,
+                selector: "".to_string(),
+                value: Some(coordinates.join(" ")), // Store "<x> <y>" in value field
+                attribute_name: None,
+            })
+        }
+        "CLICK_CENTER" => {
             if args_str.is_empty() { return None; }
             Some(DomCommand {
-                action: DomCommandAction::ScrollTo,
+                action: DomCommandAction::ClickCenter,
                 selector: args_str.to_string(),
                 value: None,
                 attribute_name: None,
             })
         }
-        "HOVER" => {
+        "MOUSE_MOVE" => {
+            if args_str.trim().is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::MouseMove,
+                selector: args_str.trim().to_string(), // Selector or "x,y" coordinates
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "WHEEL" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let delta_y = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || delta_y.parse::<f64>().is_err() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Wheel,
+                selector: selector.to_string(),
+                value: Some(delta_y.to_string()),
+                attribute_name: None,
+            })
+        }
+        "TAP" => {
             if args_str.is_empty() { return None; }
             Some(DomCommand {
-                action: DomCommandAction::Hover,
+                action: DomCommandAction::Tap,
                 selector: args_str.to_string(),
                 value: None,
                 attribute_name: None,
             })
         }
-        "GET_ALL_TEXT" => {
-            let mut parts = args_str.splitn(2, ' ');
-            let selector = parts.next().unwrap_or("");
-            let rest = parts.next().unwrap_or("").trim();
-
+        "SWIPE" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let swipe_args: Vec<&str> = sub_parts.get(1).unwrap_or(&"").split_whitespace().collect();
+            let direction_valid = swipe_args.first().is_some_and(|d| {
+                ["left", "right", "up", "down"].contains(&d.to_lowercase().as_str())
+            });
+            let distance_valid = match swipe_args.get(1) {
+                Some(distance) => distance.parse::<f64>().is_ok(),
+                None => true,
+            };
+            if selector.is_empty() || !direction_valid || !distance_valid || swipe_args.len() > 2 { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Swipe,
+                selector: selector.to_string(),
+                value: Some(swipe_args.join(" ")), // Store "<direction> [distance]" in value field
+                attribute_name: None,
+            })
+        }
+        "LONG_PRESS" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let duration = sub_parts.get(1).map(|d| d.trim()).filter(|d| !d.is_empty());
             if selector.is_empty() { return None; }
-
-            let separator_val: Option<String>;
-            if rest.starts_with('"') && rest.ends_with('"') {
-                if rest.len() >= 2 { // Ensure there are characters to strip
-                    separator_val = Some(rest[1..rest.len()-1].to_string());
-                } else { // Just quotes like ""
-                    separator_val = Some("".to_string());
-                }
-            } else if !rest.is_empty() {
-                separator_val = Some(rest.to_string());
-            } else {
-                separator_val = None; // No separator provided, will use default later
+            if let Some(duration) = duration {
+                if duration.parse::<u32>().is_err() { return None; }
             }
-
             Some(DomCommand {
-                action: DomCommandAction::GetAllText,
+                action: DomCommandAction::LongPress,
                 selector: selector.to_string(),
-                value: separator_val, // Store separator in value field
+                value: duration.map(|d| d.to_string()),
                 attribute_name: None,
             })
         }
-        _ => None,
-    }
-}
-
-pub struct AgentSystem {
-    agents: Vec<Agent>,
-}
-
-// Private helper function for direct DOM command execution
-async fn execute_direct_dom_command(
-    selected_agent: &Agent,
-    dom_command: &DomCommand,
-) -> Result<String, AgentError> {
-    console::log_1(
-        &format!(
-            "Agent {} ({:?}): Executing direct DOM command: {:?}",
-            selected_agent.id, selected_agent.role, dom_command
-        )
-        .into(),
-    );
-    match dom_command.action {
-        DomCommandAction::Click => {
-            dom_utils::click_element(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully clicked element with selector: '{}'",
-                selected_agent.id, selected_agent.role, dom_command.selector
-            ))
+        "TYPE_AND_SELECT" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || parse_type_and_select_value(value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::TypeAndSelect,
+                selector: selector.to_string(),
+                value: Some(value.to_string()), // Store "<suggestion_selector> <query> [| <pick>]" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::Type => {
-            let text_to_type = dom_command.value.as_deref().ok_or_else(|| {
-                AgentError::CommandParseError("TYPE command requires text value".to_string())
-            })?;
-            dom_utils::type_in_element(&dom_command.selector, text_to_type)?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully typed '{}' in element with selector: '{}'",
-                selected_agent.id, selected_agent.role, text_to_type, dom_command.selector
-            ))
+        "SEARCH_AND_OPEN" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || parse_search_and_open_value(value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::SearchAndOpen,
+                selector: selector.to_string(),
+                value: Some(value.to_string()), // Store "<result_selector> <terms> [| <wanted_text>]" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::Read => {
-            let text = dom_utils::get_element_text(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Text from element '{}': {}",
-                selected_agent.id, selected_agent.role, dom_command.selector, text
-            ))
+        "LOGIN" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            parse_login_value(selector, value)?;
+            Some(DomCommand {
+                action: DomCommandAction::Login,
+                selector: selector.to_string(),
+                value: Some(value.to_string()), // Store the other selectors and the credentials in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::GetValue => {
-            let value = dom_utils::get_element_value(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Value from element '{}': {}",
-                selected_agent.id, selected_agent.role, dom_command.selector, value
-            ))
+        "CALL_API" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if !selector.contains('.') { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::CallApi,
+                selector: selector.to_string(),
+                value: if value.is_empty() { None } else { Some(value.to_string()) }, // Store the JSON arguments in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::GetAttribute => {
-            let attribute_name = dom_command.attribute_name.as_deref().ok_or_else(|| {
-                AgentError::CommandParseError(
-                    "GETATTRIBUTE command requires attribute name".to_string(),
-                )
-            })?;
-            let value = dom_utils::get_element_attribute(&dom_command.selector, attribute_name)?;
-            Ok(format!(
-                "Agent {} ({:?}): Attribute '{}' from element '{}': {}",
-                selected_agent.id,
-                selected_agent.role,
-                attribute_name,
-                dom_command.selector,
-                value
-            ))
+        "CALL_FUNCTION" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::CallFunction,
+                selector: selector.to_string(),
+                value: if value.is_empty() { None } else { Some(value.to_string()) }, // Store the JSON arguments in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::SetAttribute => {
-            let attribute_name = dom_command.attribute_name.as_deref().ok_or_else(|| {
-                AgentError::CommandParseError(
-                    "SETATTRIBUTE command requires attribute name".to_string(),
-                )
-            })?;
-            let attribute_value = dom_command.value.as_deref().ok_or_else(|| {
-                AgentError::CommandParseError(
-                    "SETATTRIBUTE command requires attribute value".to_string(),
-                )
-            })?;
-            dom_utils::set_element_attribute(
-                &dom_command.selector,
-                attribute_name,
-                attribute_value,
-            )?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully set attribute '{}' to '{}' for element '{}'",
-                selected_agent.id,
-                selected_agent.role,
-                attribute_name,
-                attribute_value,
-                dom_command.selector
-            ))
+        "LISTEN" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || listen::parse_value(value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Listen,
+                selector: selector.to_string(),
+                value: Some(value.to_string()), // Store "<events> <duration_ms>" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::SelectOption => {
-            let value = dom_command.value.as_deref().ok_or_else(|| {
-                AgentError::CommandParseError("SELECTOPTION command requires option value".to_string())
-            })?;
-            dom_utils::select_dropdown_option(&dom_command.selector, value)?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully selected option '{}' for dropdown '{}'",
-                selected_agent.id, selected_agent.role, value, dom_command.selector
-            ))
+        "WATCH" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || watch::parse_duration(value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Watch,
+                selector: selector.to_string(),
+                value: Some(value.to_string()), // Store the duration in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::GetAllAttributes => {
-            let attribute_name = dom_command.attribute_name.as_deref().ok_or_else(|| {
-                AgentError::CommandParseError(
-                    "GET_ALL_ATTRIBUTES command requires attribute name".to_string(),
-                )
-            })?;
-            let json_string =
-                dom_utils::get_all_elements_attributes(&dom_command.selector, attribute_name)?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully retrieved attributes '{}' for elements matching selector '{}': {}",
-                selected_agent.id, selected_agent.role, attribute_name, dom_command.selector, json_string
-            ))
+        "ASSERT_JSON" => {
+            // The values are checked when the command runs, once `{{var.<name>}}` placeholders are
+            // substituted; a malformed assertion then fails rather than being taken for an LLM task.
+            let value = args_str.trim();
+            if value.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::AssertJson,
+                selector: "".to_string(),
+                value: Some(value.to_string()), // Store "<actual_json> <expected_json> [json_path]" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::GetUrl => {
-            let url = dom_utils::get_current_url()?;
-            Ok(format!(
-                "Agent {} ({:?}): Current URL is: {}",
-                selected_agent.id, selected_agent.role, url
-            ))
+        "FETCH" => {
+            let sub_parts: Vec<&str> = args_str.trim().splitn(3, ' ').collect();
+            let method = sub_parts.get(0).unwrap_or(&"");
+            let url = sub_parts.get(1).unwrap_or(&"");
+            let value = match sub_parts.get(2).map(|body| body.trim()).filter(|body| !body.is_empty()) {
+                Some(body) => format!("{} {}", method, body),
+                None => method.to_string(),
+            };
+            if url.is_empty() || parse_fetch_value(&value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Fetch,
+                selector: url.to_string(),
+                value: Some(value), // Store "<method> [body]" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::ElementExists => {
-            let exists = dom_utils::element_exists(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Element '{}' exists: {}",
-                selected_agent.id, selected_agent.role, dom_command.selector, exists
-            ))
+        "GRAPHQL" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || graphql::parse_value(value).is_none() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GraphQl,
+                selector: selector.to_string(),
+                value: Some(value.to_string()), // Store "<query> [| <variables_json>]" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::WaitForElement => {
-            let timeout_ms = dom_command.value.as_ref().and_then(|s| s.parse::<u32>().ok());
-            dom_utils::wait_for_element(&dom_command.selector, timeout_ms).await?;
-            Ok(format!(
-                "Agent {} ({:?}): Element '{}' appeared.",
-                selected_agent.id, selected_agent.role, dom_command.selector
-            ))
+        "REQUEST_USER_INPUT" => {
+            parse_user_input_value(args_str)?;
+            Some(DomCommand {
+                action: DomCommandAction::RequestUserInput,
+                selector: "".to_string(),
+                value: Some(args_str.trim().to_string()), // Store "<name> [prompt]" in value field
+                attribute_name: None,
+            })
         }
-        DomCommandAction::IsVisible => {
-            let visible = dom_utils::is_visible(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Element '{}' is visible: {}",
-                selected_agent.id, selected_agent.role, dom_command.selector, visible
-            ))
+        "SET_RANGE" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"").trim();
+            if selector.is_empty() || value.parse::<f64>().is_err() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::SetRange,
+                selector: selector.to_string(),
+                value: Some(value.to_string()),
+                attribute_name: None,
+            })
         }
-        DomCommandAction::ScrollTo => {
-            dom_utils::scroll_to(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully scrolled to element '{}'",
-                selected_agent.id, selected_agent.role, dom_command.selector
-            ))
+        "READ" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Read,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
         }
-        DomCommandAction::Hover => {
-            dom_utils::hover_element(&dom_command.selector)?;
-            Ok(format!(
-                "Agent {} ({:?}): Successfully hovered over element '{}'",
-                selected_agent.id, selected_agent.role, dom_command.selector
-            ))
+        "GETVALUE" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GetValue,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
         }
-        DomCommandAction::GetAllText => {
-            let separator = dom_command.value.as_deref().unwrap_or("\n"); // Default to newline if not provided
-            let text_content = dom_utils::get_all_text_from_elements(&dom_command.selector, separator)?;
-            Ok(format!(
-                "Agent {} ({:?}): Retrieved text from elements matching '{}' (separated by '{}'): \"{}\"",
-                selected_agent.id, selected_agent.role, dom_command.selector, separator.replace("\n", "\\n"), text_content
-            ))
+        "GETATTRIBUTE" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let attribute_name = sub_parts.get(1).unwrap_or(&"");
+            if selector.is_empty() || attribute_name.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GetAttribute,
+                selector: selector.to_string(),
+                value: None,
+                attribute_name: Some(attribute_name.to_string()),
+            })
+        }
+        "SETATTRIBUTE" => {
+            let sub_parts: Vec<&str> = args_str.splitn(3, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let attribute_name = sub_parts.get(1).unwrap_or(&"");
+            let attribute_value = sub_parts.get(2).unwrap_or(&"");
+            if selector.is_empty() || attribute_name.is_empty() || attribute_value.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::SetAttribute,
+                selector: selector.to_string(),
+                value: Some(attribute_value.to_string()),
+                attribute_name: Some(attribute_name.to_string()),
+            })
+        }
+        "SELECTOPTION" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let value = sub_parts.get(1).unwrap_or(&"");
+            if selector.is_empty() || value.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::SelectOption,
+                selector: selector.to_string(),
+                value: Some(value.to_string()),
+                attribute_name: None,
+            })
+        }
+        "GET_ALL_ATTRIBUTES" => { // Renamed from GETALLATTRIBUTES to GET_ALL_ATTRIBUTES for consistency
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let attribute_name = sub_parts.get(1).unwrap_or(&"");
+            if selector.is_empty() || attribute_name.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GetAllAttributes,
+                selector: selector.to_string(),
+                value: None, // Not used for this action
+                attribute_name: Some(attribute_name.to_string()),
+            })
+        }
+        "GET_URL" => {
+            if !args_str.is_empty() { 
+                console::warn_1(&format!("GET_URL command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::GetUrl,
+                selector: "".to_string(), 
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "NAVIGATE" => {
+            if args_str.trim().is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Navigate,
+                selector: "".to_string(),
+                value: Some(args_str.trim().to_string()), // Store the URL in value field
+                attribute_name: None,
+            })
+        }
+        "XPATH_EVAL" => {
+            if args_str.trim().is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::XpathEval,
+                selector: args_str.trim().to_string(), // The whole XPath expression, which may contain spaces
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "GET_FRAMES" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("GET_FRAMES command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::GetFrames,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "ELEMENT_EXISTS" => {
+            if args_str.is_empty() { 
+                return None; 
+            }
+            Some(DomCommand {
+                action: DomCommandAction::ElementExists,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "WAIT_FOR_ELEMENT" => {
+            let parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector_str = parts.get(0).unwrap_or(&"");
+            if selector_str.is_empty() { return None; }
+
+            let timeout_val = parts.get(1).and_then(|s| s.parse::<u32>().ok());
+
+            Some(DomCommand {
+                action: DomCommandAction::WaitForElement,
+                selector: selector_str.to_string(),
+                value: timeout_val.map(|v| v.to_string()), 
+                attribute_name: None,
+            })
+        }
+        "IS_VISIBLE" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::IsVisible,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "SCROLL_TO" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::ScrollTo,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "HOVER" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::Hover,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "GET_ALL_TEXT" => {
+            let mut parts = args_str.splitn(2, ' ');
+            let selector = parts.next().unwrap_or("");
+            let rest = parts.next().unwrap_or("").trim();
+
+            if selector.is_empty() { return None; }
+
+            let separator_val: Option<String>;
+            if rest.starts_with('"') && rest.ends_with('"') {
+                if rest.len() >= 2 { // Ensure there are characters to strip
+                    separator_val = Some(rest[1..rest.len()-1].to_string());
+                } else { // Just quotes like ""
+                    separator_val = Some("".to_string());
+                }
+            } else if !rest.is_empty() {
+                separator_val = Some(rest.to_string());
+            } else {
+                separator_val = None; // No separator provided, will use default later
+            }
+
+            Some(DomCommand {
+                action: DomCommandAction::GetAllText,
+                selector: selector.to_string(),
+                value: separator_val, // Store separator in value field
+                attribute_name: None,
+            })
+        }
+        "TOUR_STEP" | "TOUR_SHOW" => {
+            let sub_parts: Vec<&str> = args_str.splitn(2, ' ').collect();
+            let selector = sub_parts.get(0).unwrap_or(&"");
+            let text = sub_parts.get(1).unwrap_or(&"");
+            if selector.is_empty() || text.is_empty() { return None; }
+            let action = if command_str == "TOUR_STEP" {
+                DomCommandAction::TourStep
+            } else {
+                DomCommandAction::TourShow
+            };
+            Some(DomCommand {
+                action,
+                selector: selector.to_string(),
+                value: Some(text.to_string()), // Store tooltip text in value field
+                attribute_name: None,
+            })
+        }
+        "EVAL_JS" => {
+            if args_str.trim().is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::EvalJs,
+                selector: "".to_string(),
+                value: Some(args_str.to_string()), // Store the script in value field
+                attribute_name: None,
+            })
+        }
+        "INJECT_CSS" => {
+            if args_str.trim().is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::InjectCss,
+                selector: "".to_string(),
+                value: Some(args_str.to_string()), // Store the CSS text in value field
+                attribute_name: None,
+            })
+        }
+        "REMOVE_INJECTED_CSS" => {
+            let style_id = args_str.trim();
+            Some(DomCommand {
+                action: DomCommandAction::RemoveInjectedCss,
+                selector: "".to_string(),
+                value: if style_id.is_empty() { None } else { Some(style_id.to_string()) },
+                attribute_name: None,
+            })
+        }
+        "GET_ELEMENT_ATTRIBUTES" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GetElementAttributes,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "GET_DATASET" => {
+            if args_str.is_empty() { return None; }
+            Some(DomCommand {
+                action: DomCommandAction::GetDataset,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "GET_ROLE" | "GET_ARIA_STATE" => {
+            if args_str.is_empty() { return None; }
+            let action = if command_str == "GET_ROLE" {
+                DomCommandAction::GetRole
+            } else {
+                DomCommandAction::GetAriaState
+            };
+            Some(DomCommand {
+                action,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "IS_ENABLED" | "IS_EDITABLE" | "IS_SELECTED" => {
+            if args_str.is_empty() { return None; }
+            let action = match command_str.as_str() {
+                "IS_ENABLED" => DomCommandAction::IsEnabled,
+                "IS_EDITABLE" => DomCommandAction::IsEditable,
+                _ => DomCommandAction::IsSelected,
+            };
+            Some(DomCommand {
+                action,
+                selector: args_str.to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "DISMISS_OVERLAYS" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("DISMISS_OVERLAYS command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::DismissOverlays,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        "TOUR_END" => {
+            if !args_str.is_empty() {
+                console::warn_1(&format!("TOUR_END command received with unexpected arguments: '{}'. Arguments will be ignored.", args_str).into());
+            }
+            Some(DomCommand {
+                action: DomCommandAction::TourEnd,
+                selector: "".to_string(),
+                value: None,
+                attribute_name: None,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Runtime policy switches applied when executing DOM commands.
+///
+/// The defaults are the most restrictive settings; embedders opt in to riskier
+/// behaviour through the corresponding `RustAgent` setters.
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionConfig {
+    /// Whether the `EVAL_JS` command may run at all. Disabled by default.
+    pub allow_eval: bool,
+    /// Origins (e.g. `https://example.com`) on which `EVAL_JS` may run.
+    /// `EVAL_JS` is refused on every origin while this list is empty.
+    pub eval_allowed_origins: Vec<String>,
+    /// Origins (e.g. `https://example.com`) the agent may act on and navigate to. Every command
+    /// is refused on other origins, as are `NAVIGATE` and link clicks leading to them.
+    /// No restriction applies while this list is empty.
+    pub allowed_origins: Vec<String>,
+    /// Whether LLM command arrays are validated as a whole (see `validate_llm_plan`) before
+    /// any command runs. Disabled by default.
+    pub validate_plans: bool,
+    /// Whether LLM command arrays are checked for missing elements, navigation off the allowlist or
+    /// site, and made-up placeholder values before any command runs (see `check_guardrails`);
+    /// flagged plans only run if `confirmation_handler` confirms them. Disabled by default.
+    pub guardrails: bool,
+    /// Called as `handler(report_json)` with the `GuardrailReport` of a flagged plan; the plan runs
+    /// if it returns `true` (or a `Promise` resolving to `true`). Flagged plans are rejected while `None`.
+    pub confirmation_handler: Option<js_sys::Function>,
+    /// Whether the original values changed by `SETATTRIBUTE`, `TYPE` and `SELECTOPTION` (and the
+    /// input values changed by `TYPE_IF_EXISTS`, `TYPE_AND_SELECT`, `SEARCH_AND_OPEN` and `SET_RANGE`) are recorded,
+    /// so they can be restored with `AgentSystem::rollback_last_run`. Disabled by default.
+    pub transactional: bool,
+    #[cfg(not(feature = "dom-only"))]
+    /// Values replaced by placeholders in task text before it is sent to the LLM, and put back
+    /// in the LLM's response before it is executed or returned. Nothing is redacted by default.
+    pub redaction: RedactionConfig,
+    /// How long, in milliseconds, commands wait for a person to solve a captcha shown on the page
+    /// before they run (see `captcha::wait_while_captcha_shown`). Captchas are not looked for while
+    /// `None` (the default), nor in a worker.
+    pub captcha_timeout_ms: Option<u32>,
+    /// Values substituted for `{{var.<name>}}` placeholders in the selector and value of every
+    /// command when it runs, including the replies stored by `REQUEST_USER_INPUT`.
+    pub variables: VariableStore,
+    /// Called as `handler(prompt, name)` by `REQUEST_USER_INPUT` to ask the user for a value; it may
+    /// return a `Promise`. `REQUEST_USER_INPUT` fails while `None` (the default).
+    pub user_input_handler: Option<js_sys::Function>,
+    /// Values substituted for `{{secret.<name>}}` placeholders in `TYPE`, `TYPE_IF_EXISTS` and `LOGIN`
+    /// values when the command runs, and masked back into placeholders in every result.
+    pub secrets: SecretStore,
+    /// Maximum length, in characters, of a command result; longer results are truncated.
+    /// Unlimited while `None` (the default).
+    pub max_result_length: Option<usize>,
+    /// Whether the values entered by `TYPE`, `TYPE_IF_EXISTS`, `RICH_TEXT_TYPE`, `TYPE_AND_SELECT`
+    /// (its query), `SEARCH_AND_OPEN` (its search terms), `SETATTRIBUTE` and `SELECTOPTION` are replaced by `[redacted]` in results, including the descriptions of failed LLM-proposed
+    /// commands. Disabled by default.
+    pub redact_values_in_results: bool,
+    /// Delay between commands and cap on commands per minute. No pacing by default.
+    pub pacing: Pacing,
+    /// Delay between characters when `TYPE` and `TYPE_IF_EXISTS` type one character at a time with
+    /// keyboard and input events (see `dom_utils::type_like_human`). While `None` (the default),
+    /// the value is set at once.
+    pub typing_delay_ms: Option<u32>,
+    /// Default timeout and polling interval of `WAIT_FOR_ELEMENT`, `TYPE_AND_SELECT`,
+    /// `SEARCH_AND_OPEN`, `LOGIN`, `WAIT_FOR_WS_MESSAGE` and the conditions of tasks.
+    pub wait: WaitConfig,
+    /// Whether the page is fingerprinted before and after every command, so that results end with a
+    /// `changed_nodes` summary and `GET_DOM_DIFF` can list the changes (see `dom_diff`). Disabled by default.
+    pub track_dom_changes: bool,
+    /// While set, interactions (see `settles_after`) are followed by a bounded wait for the page to
+    /// stop changing (see `settle`), so that later commands find what they rendered. Not done in a
+    /// worker. `None` (the default) runs the next command at once.
+    pub auto_settle: Option<SettleConfig>,
+    /// Where `ASSERT_VISUAL_MATCH` loads and saves its baselines. The command fails while `None`
+    /// (the default).
+    pub baseline_storage: Option<Storage>,
+    /// When the agent runs in a Web Worker, the connection through which DOM commands are performed
+    /// on the main thread (see `worker::DomProxy`). Commands touch the DOM directly while `None` (the default).
+    pub dom_proxy: Option<DomProxy>,
+    #[cfg(not(feature = "dom-only"))]
+    /// Whether a command acting on one element asks the LLM which element it means when its
+    /// selector matches several, instead of acting on the first (see `disambiguate`). Not done in a
+    /// worker nor while `transactional` is set. Disabled by default.
+    pub disambiguate_matches: bool,
+    #[cfg(not(feature = "dom-only"))]
+    /// Tasks with the command arrays that carry them out on this site, included in the prompt of
+    /// similar tasks (see `prompt_examples`). None by default.
+    pub prompt_examples: PromptExamples,
+    #[cfg(not(feature = "dom-only"))]
+    /// Tasks whose LLM-proposed commands all succeeded, with their embeddings, recalled as examples
+    /// for similar tasks (see `memory`). Nothing is remembered until an embedding endpoint is set.
+    pub memory: ExperienceMemory,
+    /// Caps on the DOM mutations, navigations and LLM calls of a run; a run reaching one is aborted
+    /// (see `run_limits`). No caps by default.
+    pub run_limits: RunGuard,
+    /// Whether assertions that do not hold (`ASSERT_VISUAL_MATCH`, `ASSERT_JSON`, `LLM_ASSERT`) are
+    /// recorded in the run's assertion report instead of failing their task (see `soft_assert`).
+    /// Disabled by default.
+    pub soft_assertions: SoftAssertions,
+    /// Selector of the part of the page the agent is bound to. While set, the selector of every
+    /// command is resolved among the descendants of the first element matching it (see
+    /// `dom_utils::split_scope`), and commands acting on the whole page are refused, so that
+    /// several agents embedded in one page each keep to their own widget. `None` by default.
+    pub region: Option<String>,
+    /// OpenAPI specs whose operations `CALL_API` may call, by name (see `openapi`); their operations
+    /// are described in the prompt. None by default.
+    pub apis: ApiRegistry,
+    /// Functions of the host that `CALL_FUNCTION` may call, by name, with the JSON schema of their
+    /// arguments (see `host_functions`); they are described in the prompt. None by default.
+    pub functions: HostFunctions,
+    #[cfg(not(feature = "dom-only"))]
+    /// Named LLM configurations, used instead of the one given to `run_task` by tasks starting with
+    /// a `profile:<name>` hint (see `llm_profiles`). None by default.
+    pub llm_profiles: LlmProfiles,
+    #[cfg(not(feature = "dom-only"))]
+    /// Whether a task whose LLM plan fails (malformed JSON, a rejected plan, a command that cannot
+    /// be read or whose selector matches nothing) is planned again, with a stronger model once
+    /// enough plans failed, and the escalations of the current run (see `escalation`). No retries by default.
+    pub escalation: ModelEscalation,
+    #[cfg(not(feature = "dom-only"))]
+    /// The prompts and responses of the LLM calls made while planning, disambiguating and judging,
+    /// redacted, with timestamps and token estimates (see `audit`). Shared by clones.
+    pub llm_audit: LlmAuditLog,
+}
+
+pub struct AgentSystem {
+    agents: Vec<Agent>,
+    config: ExecutionConfig,
+    /// Original values recorded during the current run while `config.transactional` is set.
+    journal: Journal,
+}
+
+// Private helper that tells whether `origin` (e.g. `https://example.com`) is on an allowlist.
+// Entries may be written with a trailing slash.
+fn origin_allowed(allowed_origins: &[String], origin: &str) -> bool {
+    allowed_origins.iter().any(|allowed| allowed.trim_end_matches('/') == origin)
+}
+
+// Private helper that enforces the origin allowlist: no command runs on a page outside it, and
+// `NAVIGATE` and clicks on links may not lead outside it. `javascript:` URLs are always refused
+// by `NAVIGATE`, since they would bypass the `EVAL_JS` policy.
+fn check_origin_policy(dom_command: &DomCommand, config: &ExecutionConfig) -> Result<(), AgentError> {
+    if dom_command.action == DomCommandAction::Navigate {
+        let url = dom_command.value.as_deref().unwrap_or_default();
+        if dom_utils::resolve_url(url)?.protocol() == "javascript:" {
+            return Err(AgentError::PolicyViolation(
+                "NAVIGATE cannot open javascript: URLs. Use EVAL_JS instead.".to_string(),
+            ));
+        }
+    }
+    if config.allowed_origins.is_empty() {
+        return Ok(());
+    }
+    let origin = dom_backend::window()
+        .ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?
+        .location()
+        .origin()
+        .map_err(DomError::from)?;
+    if !origin_allowed(&config.allowed_origins, &origin) {
+        return Err(AgentError::PolicyViolation(format!(
+            "Commands are not allowed on origin '{}'. Add it with set_allowed_origins.",
+            origin
+        )));
+    }
+    let target = match dom_command.action {
+        DomCommandAction::Navigate => Some(dom_utils::resolve_url(dom_command.value.as_deref().unwrap_or_default())?.href()),
+        DomCommandAction::GraphQl | DomCommandAction::Fetch => Some(dom_utils::resolve_url(&dom_command.selector)?.href()),
+        DomCommandAction::Click | DomCommandAction::ClickIfExists | DomCommandAction::ClickCenter | DomCommandAction::Tap => {
+            dom_utils::link_target(&dom_command.selector)?
+        }
+        _ => None,
+    };
+    if let Some(target) = target {
+        let target_origin = dom_utils::resolve_url(&target)?.origin();
+        if !origin_allowed(&config.allowed_origins, &target_origin) {
+            return Err(AgentError::PolicyViolation(format!(
+                "{:?} would lead to origin '{}', which is not allowed. Add it with set_allowed_origins.",
+                dom_command.action, target_origin
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Private helper that enforces the eval policy: `EVAL_JS` needs `allow_eval` and the
+// current page origin must be on the allowlist.
+fn check_eval_policy(config: &ExecutionConfig) -> Result<(), AgentError> {
+    if !config.allow_eval {
+        return Err(AgentError::PolicyViolation(
+            "EVAL_JS is disabled. Enable it with allow_eval(true).".to_string(),
+        ));
+    }
+    let origin = dom_backend::window()
+        .ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?
+        .location()
+        .origin()
+        .map_err(DomError::from)?;
+    if origin_allowed(&config.eval_allowed_origins, &origin) {
+        Ok(())
+    } else {
+        Err(AgentError::PolicyViolation(format!(
+            "EVAL_JS is not allowed on origin '{}'. Add it with set_eval_allowed_origins.",
+            origin
+        )))
+    }
+}
+
+// Private helper that notes when a touch gesture fell back to pointer events only.
+fn touch_note(touch_supported: bool) -> &'static str {
+    if touch_supported {
+        ""
+    } else {
+        " (pointer events only; this browser cannot construct touch events)"
+    }
+}
+
+// Private helper that builds the outcome message of a tolerant (`*_IF_EXISTS`) command whose
+// element was absent. The JSON payload lets callers tell a skip apart from a performed action.
+pub(crate) fn skipped_outcome(action: &str, selector: &str) -> String {
+    let outcome = serde_json::json!({
+        "status": "skipped",
+        "action": action,
+        "selector": selector,
+        "reason": "element_not_found",
+    });
+    format!("Skipped: {}", outcome)
+}
+
+// Private helper that records, before a command runs, the original state it is about to change,
+// so that `AgentSystem::rollback_last_run` can restore it.
+fn record_original_state(dom_command: &DomCommand, journal: &Journal) {
+    match dom_command.action {
+        DomCommandAction::SetAttribute => {
+            if let Some(attribute_name) = dom_command.attribute_name.as_deref() {
+                journal.record_attribute(&dom_command.selector, attribute_name);
+            }
+        }
+        DomCommandAction::Type | DomCommandAction::TypeIfExists | DomCommandAction::TypeAndSelect | DomCommandAction::SearchAndOpen | DomCommandAction::SetRange => {
+            journal.record_input_value(&dom_command.selector)
+        }
+        DomCommandAction::SelectOption => journal.record_select_value(&dom_command.selector),
+        DomCommandAction::Login => {
+            journal.record_input_value(&dom_command.selector);
+            if let Some((form, _, _)) = parse_login_value(&dom_command.selector, dom_command.value.as_deref().unwrap_or_default()) {
+                journal.record_input_value(form.password_selector);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Private helper that substitutes `{{var.<name>}}` placeholders in the selector and value of a
+// command. Returns `None` if the command has no placeholder.
+fn resolve_variables(dom_command: &DomCommand, variables: &VariableStore) -> Result<Option<DomCommand>, AgentError> {
+    let in_value = dom_command.value.as_deref().is_some_and(VariableStore::has_placeholder);
+    if !in_value && !VariableStore::has_placeholder(&dom_command.selector) {
+        return Ok(None);
+    }
+    let selector = variables.substitute(&dom_command.selector).map_err(AgentError::CommandParseError)?;
+    let value = dom_command.value.as_deref().map(|value| variables.substitute(value)).transpose().map_err(AgentError::CommandParseError)?;
+    Ok(Some(DomCommand { selector, value, ..dom_command.clone() }))
+}
+
+// Private helper that asks the user for the value of a `REQUEST_USER_INPUT` variable through the
+// host's handler, waiting for it to answer, and stores the reply. The reply is not echoed, since it
+// is often a one-time code.
+async fn request_user_input(dom_command: &DomCommand, config: &ExecutionConfig) -> Result<String, AgentError> {
+    let (name, prompt) = parse_user_input_value(dom_command.value.as_deref().unwrap_or_default()).ok_or_else(|| {
+        AgentError::CommandParseError("REQUEST_USER_INPUT command requires a variable name".to_string())
+    })?;
+    let handler = config.user_input_handler.as_ref().ok_or_else(|| {
+        AgentError::UserInputUnavailable("REQUEST_USER_INPUT requires a handler. Call set_user_input_handler first.".to_string())
+    })?;
+    let unavailable = |e: wasm_bindgen::JsValue| {
+        AgentError::UserInputUnavailable(format!("The user input handler failed: {}", e.as_string().unwrap_or_else(|| format!("{:?}", e))))
+    };
+    let reply = handler.call2(&wasm_bindgen::JsValue::NULL, &prompt.as_str().into(), &name.into()).map_err(unavailable)?;
+    let reply = match reply.dyn_into::<js_sys::Promise>() {
+        Ok(promise) => wasm_bindgen_futures::JsFuture::from(promise).await.map_err(unavailable)?,
+        Err(reply) => reply,
+    };
+    let reply = reply.as_string().filter(|reply| !reply.is_empty()).ok_or_else(|| {
+        AgentError::UserInputUnavailable(format!("The user did not enter {}", name))
+    })?;
+    config.variables.set(name, &reply);
+    Ok(format!("Stored the user's input in {{{{var.{}}}}}", name))
+}
+
+// Private helper that pauses while a captcha is shown, until it is solved; a captcha still
+// unsolved after `timeout_ms` fails the command instead of letting it act on a blocked page.
+async fn pause_for_captcha(timeout_ms: u32) -> Result<(), AgentError> {
+    match captcha::wait_while_captcha_shown(timeout_ms).await? {
+        CaptchaWait::TimedOut(captcha) => Err(AgentError::UserInputUnavailable(format!(
+            "The {} captcha ({}) was not solved within {}ms",
+            captcha.provider, captcha.element, timeout_ms
+        ))),
+        CaptchaWait::NoCaptcha | CaptchaWait::Solved(_) => Ok(()),
+    }
+}
+
+// Private helper that substitutes `{{secret.<name>}}` placeholders in the value of a command.
+// Only typing commands and `LOGIN` accept secrets, since other commands echo their value in logs;
+// `LOGIN` requires its password to be one, so that passwords never appear in tasks.
+// Returns `None` if the command has no placeholder.
+fn resolve_secrets(dom_command: &DomCommand, secrets: &SecretStore) -> Result<Option<DomCommand>, AgentError> {
+    if dom_command.action == DomCommandAction::Login {
+        let password = parse_login_value(&dom_command.selector, dom_command.value.as_deref().unwrap_or_default())
+            .map(|(_, _, password)| password)
+            .unwrap_or_default();
+        if !SecretStore::has_placeholder(password) {
+            return Err(AgentError::PolicyViolation(
+                "The LOGIN password must be a secret placeholder such as {{secret.password}}; set it with set_secret.".to_string(),
+            ));
+        }
+    }
+    let value = match dom_command.value.as_deref() {
+        Some(value) if SecretStore::has_placeholder(value) => value,
+        _ => return Ok(None),
+    };
+    if !matches!(dom_command.action, DomCommandAction::Type | DomCommandAction::TypeIfExists | DomCommandAction::Login) {
+        return Err(AgentError::PolicyViolation(format!(
+            "Secrets can only be used in the value of TYPE, TYPE_IF_EXISTS and LOGIN, not {:?}.",
+            dom_command.action
+        )));
+    }
+    let value = secrets.substitute(value).map_err(AgentError::CommandParseError)?;
+    Ok(Some(DomCommand { value: Some(value), ..dom_command.clone() }))
+}
+
+// Private helper that performs a single validated `DomCommand` and returns its outcome message.
+// Both the direct command path and the LLM command path go through this function, so every
+// action is implemented once; callers add their own context (agent prefix, command index).
+// Variables and secrets are substituted here; secrets are masked again in the outcome, so that
+// neither the command callers log nor the results (which may reach the LLM through
+// {{PREVIOUS_RESULT}}) contain them. `REQUEST_USER_INPUT` is answered here rather than on the page,
+// so that the reply is stored where later commands are resolved, even in a worker, and `CALL_API`
+// and `CALL_FUNCTION` are made here with the APIs and functions registered there.
+// While `auto_settle` is set, interactions then wait for the page to settle, and a note is appended
+// to their result if it did not. While `track_dom_changes` is set, the page is fingerprinted around
+// the command, once settled, and a summary of the changes is appended to its result. Both are
+// appended after truncation so that they are always visible.
+async fn execute_dom_command(
+    dom_command: &DomCommand,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    let with_variables = resolve_variables(dom_command, &config.variables)?;
+    let dom_command = with_variables.as_ref().unwrap_or(dom_command);
+    if dom_command.action == DomCommandAction::RequestUserInput {
+        return request_user_input(dom_command, config).await;
+    }
+    if sandbox::is_active() && !stays_within_container(&dom_command.action) {
+        return Err(AgentError::PolicyViolation(format!(
+            "{:?} acts beyond the previewed container and cannot run in a preview.",
+            dom_command.action
+        )));
+    }
+    let in_region = scope_to_region(dom_command, config)?;
+    if let Some(action) = run_action(&dom_command.action) {
+        config.run_limits.charge(action).map_err(AgentError::RunLimitExceeded)?;
+    }
+    if dom_command.action == DomCommandAction::CallApi {
+        let outcome = openapi::call(&config.apis, &dom_command.selector, dom_command.value.as_deref(), &config.secrets).await;
+        return outcome.map(|message| sanitize_result(message, dom_command, config));
+    }
+    if dom_command.action == DomCommandAction::CallFunction {
+        let outcome = host_functions::call(&config.functions, &dom_command.selector, dom_command.value.as_deref()).await;
+        return outcome.map(|message| sanitize_result(config.secrets.mask(&message), dom_command, config));
+    }
+    let scoped = in_region.as_ref().unwrap_or(dom_command);
+    let resolved = resolve_secrets(scoped, &config.secrets)?;
+    let track_changes = config.track_dom_changes && dom_command.action != DomCommandAction::GetDomDiff;
+    let before = if track_changes { dom_diff::fingerprint().ok() } else { None };
+    // Read-only commands are not paced, so that consecutive ones run as one batch, without yielding
+    // to the page between them.
+    let paced = !is_read_only(&dom_command.action);
+    if paced {
+        // Only commands acting on the page wait for captchas; reading a page behind one is harmless.
+        if let Some(timeout_ms) = config.captcha_timeout_ms.filter(|_| config.dom_proxy.is_none()) {
+            pause_for_captcha(timeout_ms).await?;
+        }
+        config.pacing.before_command().await;
+    }
+    let command_to_perform = resolved.as_ref().unwrap_or(scoped);
+    let outcome = match &config.dom_proxy {
+        Some(proxy) => perform_through_proxy(proxy, command_to_perform).await,
+        None => perform_dom_command(command_to_perform, config, journal).await,
+    };
+    let outcome = match assertion_name(dom_command) {
+        Some(assertion) => soften_assertion(&assertion, outcome, config),
+        None => outcome,
+    };
+    let mut still_changing_after_ms = None;
+    if let Some(settle_config) = config.auto_settle.filter(|_| outcome.is_ok() && config.dom_proxy.is_none() && settles_after(&dom_command.action)) {
+        // A page that cannot be observed (e.g. one unloading) is not waited for.
+        if !settle::wait_for_settle(&settle_config).await.unwrap_or(true) {
+            still_changing_after_ms = Some(settle_config.timeout_ms);
+        }
+    }
+    // Commands are recorded with their secret placeholders, which are substituted again when applied.
+    if outcome.is_ok() && sandbox::is_active() {
+        if let Ok(command) = serde_json::to_value(dom_command) {
+            sandbox::record(command);
+        }
+    }
+    if paced {
+        config.pacing.after_command();
+    }
+    let outcome = if config.secrets.is_empty() {
+        outcome
+    } else {
+        outcome.map(|message| config.secrets.mask(&message))
+    };
+    let outcome = outcome.map(|message| sanitize_result(message, dom_command, config));
+    let outcome = match still_changing_after_ms {
+        Some(timeout_ms) => outcome.map(|message| format!("{} [page still changing after {}ms]", message, timeout_ms)),
+        None => outcome,
+    };
+    let Some(before) = before else { return outcome };
+    // A failed fingerprint (e.g. after NAVIGATE started unloading the page) only skips the summary.
+    let diff = match dom_diff::fingerprint() {
+        Ok(after) => dom_diff::diff(&before, &after),
+        Err(_) => return outcome,
+    };
+    let summary = diff.summary();
+    dom_diff::record_last_diff(diff);
+    outcome.map(|message| format!("{} [{}]", message, summary))
+}
+
+// Private helper that names the assertion a command makes, as listed in the assertion report, or
+// returns `None` if the command is not an assertion.
+fn assertion_name(dom_command: &DomCommand) -> Option<String> {
+    match dom_command.action {
+        DomCommandAction::AssertVisualMatch => Some(format!(
+            "ASSERT_VISUAL_MATCH {} {}",
+            dom_command.selector,
+            dom_command.value.as_deref().unwrap_or_default()
+        )),
+        DomCommandAction::AssertJson => {
+            let assertion = json_assert::parse_value(dom_command.value.as_deref().unwrap_or_default());
+            Some(format!("ASSERT_JSON {}", assertion.map(|assertion| assertion.path).unwrap_or_else(|_| "$".to_string())))
+        }
+        _ => None,
+    }
+}
+
+// Private helper that records the verdict of an assertion in soft-assert mode and turns its
+// failure into a result saying so, so that the task and the LLM plan it belongs to go on. Other
+// errors, such as an element that was not found, still fail the task.
+fn soften_assertion(assertion: &str, outcome: Result<String, AgentError>, config: &ExecutionConfig) -> Result<String, AgentError> {
+    if !config.soft_assertions.enabled {
+        return outcome;
+    }
+    match outcome {
+        Ok(message) => {
+            config.soft_assertions.record(assertion, None);
+            Ok(message)
+        }
+        Err(AgentError::AssertionFailed(message)) => {
+            config.soft_assertions.record(assertion, Some(&message));
+            Ok(format!("Soft assertion failed: {}", message))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Private helper that waits for a condition of a task (see `conditions`) to hold, running its
+// predicate every wait interval until it does or the condition's timeout (the wait timeout by
+// default) elapses. Returns a note for the task's result, e.g. `[post: ELEMENT_EXISTS css:#toast held
+// after 240ms]`, or `AgentError::AssertionFailed` with the last result of the predicate if the
+// condition did not hold in time. `task_result` is the result of the task a postcondition follows.
+async fn check_condition(
+    condition: &TaskCondition<'_>,
+    task_result: Option<&str>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    let predicate = parse_dom_command(condition.predicate)
+        .ok_or_else(|| AgentError::CommandParseError(format!("Invalid condition command '{}'", condition.predicate)))?;
+    // Predicates are checked quietly: they neither replace the DOM diff of the task nor ask the LLM
+    // which element they mean.
+    let mut quiet_config = config.clone();
+    quiet_config.track_dom_changes = false;
+    #[cfg(not(feature = "dom-only"))]
+    {
+        quiet_config.disambiguate_matches = false;
+    }
+    let timeout_ms = condition.timeout_ms.unwrap_or(config.wait.timeout_ms);
+    let started = js_sys::Date::now();
+    loop {
+        let last_check = match execute_dom_command(&predicate, &quiet_config, journal).await {
+            Ok(result) => match condition.holds(&result) {
+                Some(true) => return Ok(format!("[{} held after {}ms]", condition, (js_sys::Date::now() - started).round())),
+                Some(false) => result,
+                None => {
+                    return Err(AgentError::CommandParseError(format!(
+                        "Could not tell whether condition '{}' holds from its result: {}",
+                        condition, result
+                    )))
+                }
+            },
+            Err(e) => e.to_string(),
+        };
+        if js_sys::Date::now() - started >= timeout_ms as f64 {
+            let task_outcome = match task_result {
+                Some(result) => format!("the task succeeded with: {}", result),
+                None => "the task did not run".to_string(),
+            };
+            return Err(AgentError::AssertionFailed(format!(
+                "Condition '{}' did not hold within {}ms (last check: {}); {}",
+                condition, timeout_ms, last_check, task_outcome
+            )));
+        }
+        gloo_timers::future::TimeoutFuture::new(config.wait.interval_ms).await;
+    }
+}
+
+// Placeholder shown instead of an entered value while `redact_values_in_results` is set.
+const REDACTED_VALUE: &str = "[redacted]";
+
+// Private helper that returns the value `dom_command` enters in the page (text, attribute value,
+// option or autocomplete query), which `redact_values_in_results` hides.
+fn entered_value(dom_command: &DomCommand) -> Option<&str> {
+    match dom_command.action {
+        DomCommandAction::Type
+        | DomCommandAction::TypeIfExists
+        | DomCommandAction::RichTextType
+        | DomCommandAction::SetAttribute
+        | DomCommandAction::SelectOption => dom_command.value.as_deref(),
+        DomCommandAction::TypeAndSelect => {
+            parse_type_and_select_value(dom_command.value.as_deref().unwrap_or_default()).map(|(_, query, _)| query)
+        }
+        DomCommandAction::SearchAndOpen => {
+            parse_search_and_open_value(dom_command.value.as_deref().unwrap_or_default()).map(|(_, terms, _)| terms)
+        }
+        _ => None,
+    }
+}
+
+// Private helper that applies the result policy to the outcome message of a command: the entered
+// value is redacted if `redact_values_in_results` is set, then the message is truncated to
+// `max_result_length` characters. Characters are grapheme clusters, so an emoji sequence or a letter
+// with its combining marks is kept or cut whole.
+fn sanitize_result(message: String, dom_command: &DomCommand, config: &ExecutionConfig) -> String {
+    let message = match entered_value(dom_command) {
+        Some(value) if config.redact_values_in_results && !value.is_empty() => {
+            message.replace(&format!("'{}'", value), &format!("'{}'", REDACTED_VALUE))
+        }
+        _ => message,
+    };
+    match config.max_result_length {
+        Some(max_length) => match message.grapheme_indices(true).nth(max_length) {
+            Some((cut, _)) => {
+                let omitted = message[cut..].graphemes(true).count();
+                format!("{}... [truncated {} characters]", &message[..cut], omitted)
+            }
+            None => message,
+        },
+        None => message,
+    }
+}
+
+// Private helper that sends a `DomCommand` to the main thread through the DOM proxy and returns
+// its outcome there. Errors raised on the main thread are passed on as they are.
+async fn perform_through_proxy(proxy: &DomProxy, dom_command: &DomCommand) -> Result<String, AgentError> {
+    let command = serde_json::to_value(dom_command).map_err(|e| AgentError::SerializationError(e.to_string()))?;
+    proxy.perform(command).await?.map_err(AgentError::ProxiedCommandFailed)
+}
+
+// Private helper that types `text` at once, or one character at a time if human-like typing is enabled.
+async fn type_text(selector: &str, text: &str, config: &ExecutionConfig) -> Result<(), DomError> {
+    match config.typing_delay_ms {
+        Some(delay_ms) => dom_utils::type_like_human(selector, text, delay_ms).await,
+        None => dom_utils::type_in_element(selector, text),
+    }
+}
+
+// Private helper that performs a `DomCommand` whose secrets have been substituted. A selector
+// fallback chain is replaced by its first candidate matching an element, which the result names;
+// if none matches, the command runs with the whole chain (so that, e.g., `WAIT_FOR_ELEMENT` waits
+// for any candidate).
+async fn perform_dom_command(
+    dom_command: &DomCommand,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    match dom_utils::matching_candidate(&dom_command.selector)? {
+        Some(candidate) => {
+            let with_candidate = DomCommand { selector: candidate.clone(), ..dom_command.clone() };
+            let message = perform_action(&with_candidate, config, journal).await?;
+            Ok(format!("{} (matched selector '{}' of {})", message, candidate, dom_command.selector))
+        }
+        None => perform_action(dom_command, config, journal).await,
+    }
+}
+
+// Private helper that performs a `DomCommand` on the selector it names.
+async fn perform_action(
+    dom_command: &DomCommand,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    check_origin_policy(dom_command, config)?;
+    if config.transactional {
+        record_original_state(dom_command, journal);
+    }
+    match dom_command.action {
+        DomCommandAction::Click => {
+            dom_utils::click_element(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully clicked element with selector: '{}'",
+                dom_command.selector
+            ))
+        }
+        DomCommandAction::Type => {
+            let text_to_type = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("TYPE command requires text value".to_string())
+            })?;
+            type_text(&dom_command.selector, text_to_type, config).await?;
+            Ok(format!(
+                "Successfully typed '{}' in element with selector: '{}'",
+                text_to_type, dom_command.selector
+            ))
+        }
+        DomCommandAction::ClickIfExists => {
+            if !dom_utils::element_exists(&dom_command.selector)? {
+                return Ok(skipped_outcome("CLICK", &dom_command.selector));
+            }
+            dom_utils::click_element(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully clicked element with selector: '{}'",
+                dom_command.selector
+            ))
+        }
+        DomCommandAction::TypeIfExists => {
+            let text_to_type = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("TYPE_IF_EXISTS command requires text value".to_string())
+            })?;
+            if !dom_utils::element_exists(&dom_command.selector)? {
+                return Ok(skipped_outcome("TYPE", &dom_command.selector));
+            }
+            type_text(&dom_command.selector, text_to_type, config).await?;
+            Ok(format!(
+                "Successfully typed '{}' in element with selector: '{}'",
+                text_to_type, dom_command.selector
+            ))
+        }
+        DomCommandAction::ClickAt => {
+            let coordinates: Vec<f64> = dom_command
+                .value
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter_map(|c| c.parse::<f64>().ok())
+                .collect();
+            if coordinates.len() != 2 {
+                return Err(AgentError::CommandParseError(
+                    "CLICK_AT command requires numeric x and y coordinates".to_string(),
+                ));
+            }
+            let target = dom_utils::click_at(coordinates[0], coordinates[1])?;
+            Ok(format!(
+                "Successfully clicked at ({}, {}) on element {}",
+                coordinates[0], coordinates[1], target
+            ))
+        }
+        DomCommandAction::ClickCenter => {
+            let target = dom_utils::click_center(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully clicked center of element '{}' (hit {})",
+                dom_command.selector, target
+            ))
+        }
+        DomCommandAction::MouseMove => {
+            let hovered = dom_utils::mouse_move(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully moved mouse to '{}' (over {})",
+                dom_command.selector, hovered
+            ))
+        }
+        DomCommandAction::Wheel => {
+            let delta_y = dom_command
+                .value
+                .as_deref()
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .ok_or_else(|| {
+                    AgentError::CommandParseError("WHEEL command requires a numeric delta".to_string())
+                })?;
+            let scrolled = dom_utils::wheel(&dom_command.selector, delta_y)?;
+            if scrolled {
+                Ok(format!(
+                    "Successfully scrolled {}px with wheel over element '{}'",
+                    delta_y, dom_command.selector
+                ))
+            } else {
+                Ok(format!(
+                    "Wheel event over element '{}' was handled by the page (no default scrolling)",
+                    dom_command.selector
+                ))
+            }
+        }
+        DomCommandAction::Tap => {
+            let touch_supported = dom_utils::tap(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully tapped element '{}'{}",
+                dom_command.selector,
+                touch_note(touch_supported)
+            ))
+        }
+        DomCommandAction::Swipe => {
+            let swipe_value = dom_command.value.as_deref().unwrap_or_default();
+            let mut swipe_args = swipe_value.split_whitespace();
+            let direction = swipe_args.next().ok_or_else(|| {
+                AgentError::CommandParseError("SWIPE command requires a direction".to_string())
+            })?;
+            let distance = match swipe_args.next() {
+                Some(distance) => Some(distance.parse::<f64>().map_err(|_| {
+                    AgentError::CommandParseError(format!("SWIPE distance '{}' is not a number", distance))
+                })?),
+                None => None,
+            };
+            let touch_supported = dom_utils::swipe(&dom_command.selector, direction, distance).await?;
+            Ok(format!(
+                "Successfully swiped {} on element '{}'{}",
+                direction,
+                dom_command.selector,
+                touch_note(touch_supported)
+            ))
+        }
+        DomCommandAction::LongPress => {
+            let duration = match dom_command.value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+                Some(duration) => Some(duration.parse::<u32>().map_err(|_| {
+                    AgentError::CommandParseError(format!("LONG_PRESS duration '{}' is not a valid number of milliseconds", duration))
+                })?),
+                None => None,
+            };
+            let touch_supported = dom_utils::long_press(&dom_command.selector, duration).await?;
+            Ok(format!(
+                "Successfully long-pressed element '{}' for {}ms{}",
+                dom_command.selector,
+                duration.unwrap_or(dom_utils::DEFAULT_LONG_PRESS_MS),
+                touch_note(touch_supported)
+            ))
+        }
+        DomCommandAction::TypeAndSelect => {
+            let (suggestion_selector, query, pick) = parse_type_and_select_value(dom_command.value.as_deref().unwrap_or_default())
+                .ok_or_else(|| {
+                    AgentError::CommandParseError("TYPE_AND_SELECT command requires a suggestion selector and a query".to_string())
+                })?;
+            let delay_ms = config.typing_delay_ms.unwrap_or(0);
+            let picked = dom_utils::type_and_select(&dom_command.selector, query, suggestion_selector, &pick, delay_ms, None, &config.wait).await?;
+            Ok(format!(
+                "Successfully typed '{}' in element with selector: '{}' and selected suggestion '{}'",
+                query, dom_command.selector, picked
+            ))
+        }
+        DomCommandAction::SearchAndOpen => {
+            let (result_selector, terms, wanted) = parse_search_and_open_value(dom_command.value.as_deref().unwrap_or_default())
+                .ok_or_else(|| {
+                    AgentError::CommandParseError("SEARCH_AND_OPEN command requires a result selector and search terms".to_string())
+                })?;
+            let delay_ms = config.typing_delay_ms.unwrap_or(0);
+            let (opened, score) = dom_utils::search_and_open(&dom_command.selector, terms, result_selector, wanted, delay_ms, None, &config.wait).await?;
+            Ok(format!(
+                "Successfully searched for '{}' in element with selector: '{}' and opened result '{}' (score {:.2})",
+                terms, dom_command.selector, opened, score
+            ))
+        }
+        DomCommandAction::Login => {
+            let (form, username, password) = parse_login_value(&dom_command.selector, dom_command.value.as_deref().unwrap_or_default())
+                .ok_or_else(|| {
+                    AgentError::CommandParseError(
+                        "LOGIN command requires password, submit and success selectors, then '| <username> <password>'".to_string(),
+                    )
+                })?;
+            let outcome = login::login(&form, username, password, config.typing_delay_ms, None, &config.wait).await?;
+            let json_string = serde_json::to_string(&outcome).map_err(|e| AgentError::SerializationError(e.to_string()))?;
+            if outcome.success {
+                Ok(format!("Login succeeded: {}", json_string))
+            } else {
+                Err(AgentError::AssertionFailed(format!("Login failed: {}", json_string)))
+            }
+        }
+        DomCommandAction::Fetch => {
+            let (method, body) = dom_command.value.as_deref().and_then(parse_fetch_value).ok_or_else(|| {
+                AgentError::CommandParseError(format!("FETCH command requires a method ({})", network::FETCH_METHODS.join(", ")))
+            })?;
+            let response = network::fetch_url(&method, &dom_command.selector, body).await?;
+            Ok(format!("Fetch response: {}", response))
+        }
+        DomCommandAction::Listen => {
+            let (event_types, duration_ms) = dom_command.value.as_deref().and_then(listen::parse_value).ok_or_else(|| {
+                AgentError::CommandParseError(format!(
+                    "LISTEN command requires event types and a duration of at most {} ms",
+                    listen::MAX_LISTEN_DURATION_MS
+                ))
+            })?;
+            let report = listen::listen(&dom_command.selector, &event_types, duration_ms).await?;
+            Ok(format!("Events captured: {}", report))
+        }
+        DomCommandAction::Watch => {
+            let duration_ms = dom_command.value.as_deref().and_then(watch::parse_duration).ok_or_else(|| {
+                AgentError::CommandParseError(format!("WATCH command requires a duration of at most {} ms", watch::MAX_WATCH_DURATION_MS))
+            })?;
+            let report = watch::watch(&dom_command.selector, duration_ms).await?;
+            Ok(format!("Mutations observed: {}", report))
+        }
+        DomCommandAction::AssertJson => {
+            let assertion = json_assert::parse_value(dom_command.value.as_deref().unwrap_or_default()).map_err(AgentError::CommandParseError)?;
+            let differences = assertion.differences();
+            if differences.is_empty() {
+                Ok(format!("JSON at {} matches the expected value", assertion.path))
+            } else {
+                Err(AgentError::AssertionFailed(json_assert::describe_differences(&assertion.path, &differences)))
+            }
+        }
+        DomCommandAction::GraphQl => {
+            let (query, variables) = dom_command.value.as_deref().and_then(graphql::parse_value).ok_or_else(|| {
+                AgentError::CommandParseError("GRAPHQL command requires a query".to_string())
+            })?;
+            graphql::query(&dom_command.selector, query, variables).await
+        }
+        // Normally answered by `execute_dom_command`, before it would be proxied from a worker.
+        DomCommandAction::RequestUserInput => request_user_input(dom_command, config).await,
+        // Likewise, so that the APIs and secrets of the worker are used.
+        DomCommandAction::CallApi => {
+            openapi::call(&config.apis, &dom_command.selector, dom_command.value.as_deref(), &config.secrets).await
+        }
+        DomCommandAction::CallFunction => {
+            host_functions::call(&config.functions, &dom_command.selector, dom_command.value.as_deref()).await
+        }
+        DomCommandAction::CheckBotSignals => {
+            let report = bot_signals::check_bot_signals(&config.pacing, config.typing_delay_ms)?;
+            let json_string = serde_json::to_string(&report).map_err(|e| AgentError::SerializationError(e.to_string()))?;
+            Ok(format!("Bot signals: {}", json_string))
+        }
+        DomCommandAction::SetRange => {
+            let value = dom_command.value.as_deref().unwrap_or_default().trim();
+            let number = value.parse::<f64>().map_err(|_| {
+                AgentError::CommandParseError(format!("SET_RANGE value '{}' is not a number", value))
+            })?;
+            let result = dom_utils::set_range(&dom_command.selector, number)?;
+            Ok(format!("Successfully set range '{}' to {}", dom_command.selector, result))
+        }
+        DomCommandAction::RichTextType => {
+            let text_to_type = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("RICH_TEXT_TYPE command requires text value".to_string())
+            })?;
+            dom_utils::rich_text_type(&dom_command.selector, text_to_type)?;
+            Ok(format!(
+                "Successfully typed '{}' in rich text editor with selector: '{}'",
+                text_to_type, dom_command.selector
+            ))
+        }
+        DomCommandAction::GetWsMessages => {
+            let json_string = network::get_ws_messages(dom_command.value.as_deref())?;
+            Ok(format!("WebSocket messages: {}", json_string))
+        }
+        DomCommandAction::WaitForWsMessage => {
+            let pattern = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("WAIT_FOR_WS_MESSAGE command requires the text to wait for".to_string())
+            })?;
+            let message = network::wait_for_ws_message(pattern, None, &config.wait).await?;
+            Ok(format!("WebSocket message received: {}", message))
+        }
+        DomCommandAction::GetPerfMetrics => {
+            let json_string = network::get_perf_metrics().await?;
+            Ok(format!("Performance metrics: {}", json_string))
+        }
+        DomCommandAction::GetDomDiff => {
+            let json_string = dom_diff::get_last_diff()?;
+            Ok(format!("DOM changes of the previous command: {}", json_string))
+        }
+        DomCommandAction::GetLang => {
+            let json_string = dom_utils::get_page_language()?;
+            Ok(format!("Page language: {}", json_string))
+        }
+        DomCommandAction::TabTo => {
+            let count = match dom_command.value.as_deref() {
+                Some(value) => Some(value.trim().parse::<u32>().map_err(|_| {
+                    AgentError::CommandParseError(format!("TAB_TO value '{}' is not a number of Tab presses", value))
+                })?),
+                None => None,
+            };
+            if dom_command.selector.is_empty() {
+                let count = count.ok_or_else(|| {
+                    AgentError::CommandParseError("TAB_TO command requires a selector or a number of Tab presses".to_string())
+                })?;
+                dom_utils::press_tabs(count)?;
+                Ok(format!("Pressed Tab {} times; focused element: {}", count, dom_utils::get_focused()?))
+            } else {
+                match dom_utils::tab_to(&dom_command.selector, count)? {
+                    Some(tabs) => Ok(format!(
+                        "Focused element with selector '{}' after {} Tab presses",
+                        dom_command.selector, tabs
+                    )),
+                    None => Err(AgentError::AssertionFailed(format!(
+                        "Element '{}' was not focused by pressing Tab{}; focused element: {}",
+                        dom_command.selector,
+                        count.map(|count| format!(" {} times", count)).unwrap_or_default(),
+                        dom_utils::get_focused()?
+                    ))),
+                }
+            }
+        }
+        DomCommandAction::GetFocused => {
+            let json_string = dom_utils::get_focused()?;
+            Ok(format!("Focused element: {}", json_string))
+        }
+        DomCommandAction::AuditSeo => {
+            let json_string = seo::audit_seo()?;
+            Ok(format!("SEO audit: {}", json_string))
+        }
+        DomCommandAction::ExtractStructuredData => {
+            let data = seo::extract_structured_data()?;
+            let json_string = serde_json::to_string(&data).map_err(|e| AgentError::SerializationError(e.to_string()))?;
+            Ok(format!("Structured data: {}", json_string))
+        }
+        DomCommandAction::AssertVisualMatch => {
+            let value = dom_command.value.as_deref().unwrap_or_default();
+            let (baseline_key, threshold) = parse_visual_match_value(value).ok_or_else(|| {
+                AgentError::CommandParseError(format!(
+                    "ASSERT_VISUAL_MATCH command requires a baseline key and a threshold between 0 and 1 (or 0% and 100%), got '{}'",
+                    value
+                ))
+            })?;
+            let storage = config.baseline_storage.as_ref().ok_or_else(|| {
+                AgentError::CommandParseError(
+                    "ASSERT_VISUAL_MATCH requires baseline storage. Call set_storage or set_baseline_storage first.".to_string(),
+                )
+            })?;
+            match capture::compare_with_baseline(&dom_command.selector, baseline_key, storage).await? {
+                None => Ok(format!(
+                    "Recorded baseline '{}' for element '{}'",
+                    baseline_key, dom_command.selector
+                )),
+                Some(ratio) if ratio <= threshold => Ok(format!(
+                    "Element '{}' matches baseline '{}': {:.2}% of pixels differ (threshold {:.2}%)",
+                    dom_command.selector, baseline_key, ratio * 100.0, threshold * 100.0
+                )),
+                Some(ratio) => Err(AgentError::AssertionFailed(format!(
+                    "Element '{}' does not match baseline '{}': {:.2}% of pixels differ (threshold {:.2}%)",
+                    dom_command.selector, baseline_key, ratio * 100.0, threshold * 100.0
+                ))),
+            }
+        }
+        DomCommandAction::CaptureElement => {
+            let data_url = capture::capture_element(&dom_command.selector).await?;
+            Ok(format!("Screenshot of element '{}': {}", dom_command.selector, data_url))
+        }
+        DomCommandAction::GetMediaQuery => {
+            let query = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("GET_MEDIA_QUERY command requires a media query".to_string())
+            })?;
+            let matches = dom_utils::media_query_matches(query)?;
+            Ok(format!("Media query '{}' matches: {}", query, matches))
+        }
+        DomCommandAction::IsDarkMode => {
+            let dark = dom_utils::is_dark_mode()?;
+            Ok(format!("Dark mode: {}", dark))
+        }
+        DomCommandAction::SetGeolocation => {
+            let value = dom_command.value.as_deref().unwrap_or_default();
+            let (latitude, longitude, accuracy) = parse_geolocation_value(value).ok_or_else(|| {
+                AgentError::CommandParseError(format!(
+                    "SET_GEOLOCATION value '{}' is not '<latitude> <longitude> [accuracy_m]' with valid coordinates",
+                    value
+                ))
+            })?;
+            emulation::set_geolocation(latitude, longitude, accuracy)?;
+            Ok(format!("Geolocation set to {}, {} (accuracy {}m)", latitude, longitude, accuracy))
+        }
+        DomCommandAction::GetConsoleErrors => {
+            let fail_on_error = dom_command.value.as_deref().is_some_and(|mode| mode.eq_ignore_ascii_case("FAIL"));
+            let json_string = page_errors::get_console_errors(fail_on_error)?;
+            Ok(format!("Console errors and warnings: {}", json_string))
+        }
+        DomCommandAction::Read => {
+            let text = dom_utils::get_element_text(&dom_command.selector)?;
+            Ok(format!("Text from element '{}': {}", dom_command.selector, text))
+        }
+        DomCommandAction::GetValue => {
+            let value = dom_utils::get_element_value(&dom_command.selector)?;
+            Ok(format!("Value from element '{}': {}", dom_command.selector, value))
+        }
+        DomCommandAction::GetAttribute => {
+            let attribute_name = dom_command.attribute_name.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError(
+                    "GETATTRIBUTE command requires attribute name".to_string(),
+                )
+            })?;
+            let value = dom_utils::get_element_attribute(&dom_command.selector, attribute_name)?;
+            Ok(format!(
+                "Attribute '{}' from element '{}': {}",
+                attribute_name, dom_command.selector, value
+            ))
+        }
+        DomCommandAction::SetAttribute => {
+            let attribute_name = dom_command.attribute_name.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError(
+                    "SETATTRIBUTE command requires attribute name".to_string(),
+                )
+            })?;
+            let attribute_value = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError(
+                    "SETATTRIBUTE command requires attribute value".to_string(),
+                )
+            })?;
+            dom_utils::set_element_attribute(
+                &dom_command.selector,
+                attribute_name,
+                attribute_value,
+            )?;
+            Ok(format!(
+                "Successfully set attribute '{}' to '{}' for element '{}'",
+                attribute_name, attribute_value, dom_command.selector
+            ))
+        }
+        DomCommandAction::SelectOption => {
+            let value = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("SELECTOPTION command requires option value".to_string())
+            })?;
+            dom_utils::select_dropdown_option(&dom_command.selector, value)?;
+            Ok(format!(
+                "Successfully selected option '{}' for dropdown '{}'",
+                value, dom_command.selector
+            ))
+        }
+        DomCommandAction::GetAllAttributes => {
+            let attribute_name = dom_command.attribute_name.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError(
+                    "GET_ALL_ATTRIBUTES command requires attribute name".to_string(),
+                )
+            })?;
+            let json_string =
+                dom_utils::get_all_elements_attributes(&dom_command.selector, attribute_name)?;
+            Ok(format!(
+                "Successfully retrieved attributes '{}' for elements matching selector '{}': {}",
+                attribute_name, dom_command.selector, json_string
+            ))
+        }
+        DomCommandAction::GetUrl => {
+            let url = dom_utils::get_current_url()?;
+            Ok(format!("Current URL is: {}", url))
+        }
+        DomCommandAction::Navigate => {
+            let url = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("NAVIGATE command requires a URL".to_string())
+            })?;
+            let target = dom_utils::navigate(url)?;
+            Ok(format!("Navigating to: {}", target))
+        }
+        DomCommandAction::GetFrames => {
+            let frames_json = dom_utils::get_frames()?;
+            Ok(format!("Frames on page: {}", frames_json))
+        }
+        DomCommandAction::ElementExists => {
+            let exists = dom_utils::element_exists(&dom_command.selector)?;
+            Ok(format!("Element '{}' exists: {}", dom_command.selector, exists))
+        }
+        DomCommandAction::WaitForElement => {
+            let timeout_ms = dom_command.value.as_ref().and_then(|s| s.parse::<u32>().ok());
+            dom_utils::wait_for_element_with(&dom_command.selector, timeout_ms, &config.wait).await?;
+            Ok(format!("Element '{}' appeared.", dom_command.selector))
+        }
+        DomCommandAction::IsVisible => {
+            let visible = dom_utils::is_visible(&dom_command.selector)?;
+            Ok(format!("Element '{}' is visible: {}", dom_command.selector, visible))
+        }
+        DomCommandAction::ScrollTo => {
+            dom_utils::scroll_to(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully scrolled to element '{}'",
+                dom_command.selector
+            ))
+        }
+        DomCommandAction::Hover => {
+            dom_utils::hover_element(&dom_command.selector)?;
+            Ok(format!(
+                "Successfully hovered over element '{}'",
+                dom_command.selector
+            ))
+        }
+        DomCommandAction::GetAllText => {
+            let separator = dom_command.value.as_deref().unwrap_or("\n"); // Default to newline if not provided
+            let text_content = dom_utils::get_all_text_from_elements(&dom_command.selector, separator)?;
+            Ok(format!(
+                "Retrieved text from elements matching '{}' (separated by '{}'): \"{}\"",
+                dom_command.selector, separator.replace("\n", "\\n"), text_content
+            ))
+        }
+        DomCommandAction::XpathEval => {
+            let result_json = dom_utils::xpath_eval(&dom_command.selector)?;
+            Ok(format!("XPath '{}' evaluated to: {}", dom_command.selector, result_json))
+        }
+        DomCommandAction::TourStep => {
+            let text = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("TOUR_STEP command requires step text".to_string())
+            })?;
+            let acknowledged = tour::show_tour_step(&dom_command.selector, text, true).await?;
+            if acknowledged {
+                Ok(format!(
+                    "Tour step for element '{}' acknowledged by user",
+                    dom_command.selector
+                ))
+            } else {
+                Ok(format!(
+                    "Tour step for element '{}' was dismissed before the user continued",
+                    dom_command.selector
+                ))
+            }
+        }
+        DomCommandAction::TourShow => {
+            let text = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("TOUR_SHOW command requires step text".to_string())
+            })?;
+            tour::show_tour_step(&dom_command.selector, text, false).await?;
+            Ok(format!(
+                "Tour step shown for element '{}'",
+                dom_command.selector
+            ))
+        }
+        DomCommandAction::TourEnd => {
+            tour::end_tour()?;
+            Ok("Tour ended".to_string())
+        }
+        DomCommandAction::EvalJs => {
+            check_eval_policy(config)?;
+            let script = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("EVAL_JS command requires a script".to_string())
+            })?;
+            let json_result = dom_utils::eval_js(script)?;
+            Ok(format!("EVAL_JS result: {}", json_result))
+        }
+        DomCommandAction::InjectCss => {
+            let css_text = dom_command.value.as_deref().ok_or_else(|| {
+                AgentError::CommandParseError("INJECT_CSS command requires CSS text".to_string())
+            })?;
+            let style_id = dom_utils::inject_css(css_text)?;
+            Ok(format!("Successfully injected CSS with id '{}'", style_id))
+        }
+        DomCommandAction::RemoveInjectedCss => {
+            let removed = dom_utils::remove_injected_css(dom_command.value.clone())?;
+            Ok(format!("Successfully removed {} injected stylesheet(s)", removed))
+        }
+        DomCommandAction::GetElementAttributes => {
+            let json_string = dom_utils::get_element_attributes(&dom_command.selector)?;
+            Ok(format!(
+                "Attributes of element '{}': {}",
+                dom_command.selector, json_string
+            ))
+        }
+        DomCommandAction::GetDataset => {
+            let json_string = dom_utils::get_element_dataset(&dom_command.selector)?;
+            Ok(format!(
+                "Dataset of element '{}': {}",
+                dom_command.selector, json_string
+            ))
+        }
+        DomCommandAction::GetRole => {
+            let role = dom_utils::get_element_role(&dom_command.selector)?;
+            Ok(format!("Role of element '{}': {}", dom_command.selector, role))
+        }
+        DomCommandAction::GetAriaState => {
+            let json_string = dom_utils::get_aria_state(&dom_command.selector)?;
+            Ok(format!(
+                "ARIA state of element '{}': {}",
+                dom_command.selector, json_string
+            ))
+        }
+        DomCommandAction::IsEnabled => {
+            let enabled = dom_utils::is_enabled(&dom_command.selector)?;
+            Ok(format!("Element '{}' is enabled: {}", dom_command.selector, enabled))
+        }
+        DomCommandAction::IsEditable => {
+            let editable = dom_utils::is_editable(&dom_command.selector)?;
+            Ok(format!("Element '{}' is editable: {}", dom_command.selector, editable))
+        }
+        DomCommandAction::IsSelected => {
+            let selected = dom_utils::is_selected(&dom_command.selector)?;
+            Ok(format!("Element '{}' is selected: {}", dom_command.selector, selected))
+        }
+        DomCommandAction::DismissOverlays => {
+            let report = dom_utils::dismiss_overlays()?;
+            Ok(format!("Dismissed overlays: {}", report))
+        }
+    }
+}
+
+// Private helper that runs `dom_command` like `execute_dom_command`, except that while
+// `disambiguate_matches` is set and its selector matches several elements, the LLM picks the one
+// meant by `task` (see `disambiguate::pick`), the command acts on that one alone, and its result says
+// which match was picked. Commands that act on no element or on every match run as usual, as do
+// selectors matching at most one element.
+#[cfg(not(feature = "dom-only"))]
+async fn execute_picking_match(
+    dom_command: &DomCommand,
+    task: &str,
+    llm: LlmEndpoint<'_>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    // Rollback finds recorded elements by selector, so a picked element must not be recorded.
+    if !config.disambiguate_matches || config.transactional || config.dom_proxy.is_some() || !requires_existing_element(dom_command) {
+        return execute_dom_command(dom_command, config, journal).await;
+    }
+    let resolved = resolve_variables(dom_command, &config.variables)?.unwrap_or_else(|| dom_command.clone());
+    // An invalid selector is reported as usual when the command runs.
+    let descriptions = match dom_utils::describe_matches(&resolved.selector) {
+        Ok(descriptions) if descriptions.len() > 1 => descriptions,
+        _ => return execute_dom_command(dom_command, config, journal).await,
+    };
+    let selector = resolved.selector.clone();
+    let index = disambiguate::pick(task, &format!("{:?} {}", resolved.action, selector), &descriptions, llm, config).await?;
+    let pinned = dom_utils::pin_match(&selector, index)?;
+    let outcome = execute_dom_command(&DomCommand { selector: pinned.clone(), ..resolved }, config, journal).await;
+    dom_utils::unpin_matches();
+    Ok(format!(
+        "{} (picked match {} of {} for '{}': {})",
+        outcome?.replace(&pinned, &selector),
+        index + 1,
+        descriptions.len(),
+        selector,
+        descriptions[index]
+    ))
+}
+
+// Without the LLM, every command acts on the first match of its selector.
+#[cfg(feature = "dom-only")]
+async fn execute_picking_match(
+    dom_command: &DomCommand,
+    _task: &str,
+    _llm: LlmEndpoint<'_>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    execute_dom_command(dom_command, config, journal).await
+}
+
+// Private helper function for direct DOM command execution
+async fn execute_direct_dom_command(
+    selected_agent: &Agent,
+    dom_command: &DomCommand,
+    task: &str,
+    llm: LlmEndpoint<'_>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<String, AgentError> {
+    console::log_1(
+        &format!(
+            "Agent {} ({:?}): Executing direct DOM command: {:?}",
+            selected_agent.id, selected_agent.role, dom_command
+        )
+        .into(),
+    );
+    let message = execute_picking_match(dom_command, task, llm, config, journal).await?;
+    Ok(format!(
+        "Agent {} ({:?}): {}",
+        selected_agent.id, selected_agent.role, message
+    ))
+}
+
+// Private helper that converts one element of an LLM command array into a `DomCommand`,
+// checking the action name and the fields it requires. The error is a message naming the index.
+#[cfg(not(feature = "dom-only"))]
+fn llm_request_to_dom_command(index: usize, cmd_json_obj: &serde_json::Value) -> Result<DomCommand, String> {
+    parse_llm_command(index, cmd_json_obj).map(|(dom_command, _reason)| dom_command)
+}
+
+// Private helper that converts one element of an LLM command array like `llm_request_to_dom_command`,
+// also returning the reason the LLM gave for it, trimmed and cut to `MAX_REASON_CHARS`, if any.
+#[cfg(not(feature = "dom-only"))]
+fn parse_llm_command(index: usize, cmd_json_obj: &serde_json::Value) -> Result<(DomCommand, Option<String>), String> {
+    let llm_cmd_req = serde_json::from_value::<LlmDomCommandRequest>(cmd_json_obj.clone()).map_err(|e| {
+        format!(
+            "Command at index {} was malformed and could not be parsed: {}. Object: {}",
+            index, e, cmd_json_obj
+        )
+    })?;
+    let action_upper = llm_cmd_req.action.to_uppercase();
+    let dom_action = match action_upper.as_str() {
+        "CLICK" => DomCommandAction::Click,
+        "TYPE" => DomCommandAction::Type,
+        "READ" => DomCommandAction::Read,
+        "GETVALUE" => DomCommandAction::GetValue,
+        "GETATTRIBUTE" => DomCommandAction::GetAttribute,
+        "SETATTRIBUTE" => DomCommandAction::SetAttribute,
+        "SELECTOPTION" => DomCommandAction::SelectOption,
+        "GET_ALL_ATTRIBUTES" => DomCommandAction::GetAllAttributes,
+        "GET_URL" => DomCommandAction::GetUrl,
+        "NAVIGATE" => DomCommandAction::Navigate,
+        "GET_FRAMES" => DomCommandAction::GetFrames,
+        "ELEMENT_EXISTS" => DomCommandAction::ElementExists,
+        "WAIT_FOR_ELEMENT" => DomCommandAction::WaitForElement,
+        "IS_VISIBLE" => DomCommandAction::IsVisible,
+        "SCROLL_TO" => DomCommandAction::ScrollTo,
+        "HOVER" => DomCommandAction::Hover,
+        "GET_ALL_TEXT" => DomCommandAction::GetAllText,
+        "XPATH_EVAL" => DomCommandAction::XpathEval,
+        // Direct-only: page text could otherwise get arbitrary JavaScript run through the plan.
+        "EVAL_JS" => {
+            return Err(format!(
+                "Action EVAL_JS from LLM at index {} is refused; EVAL_JS can only be run as a direct task.",
+                index
+            ));
+        }
+        "GET_ELEMENT_ATTRIBUTES" => DomCommandAction::GetElementAttributes,
+        "GET_DATASET" => DomCommandAction::GetDataset,
+        "GET_ROLE" => DomCommandAction::GetRole,
+        "GET_ARIA_STATE" => DomCommandAction::GetAriaState,
+        "IS_ENABLED" => DomCommandAction::IsEnabled,
+        "IS_EDITABLE" => DomCommandAction::IsEditable,
+        "IS_SELECTED" => DomCommandAction::IsSelected,
+        "CLICK_IF_EXISTS" => DomCommandAction::ClickIfExists,
+        "TYPE_IF_EXISTS" => DomCommandAction::TypeIfExists,
+        "CLICK_CENTER" => DomCommandAction::ClickCenter,
+        "MOUSE_MOVE" => DomCommandAction::MouseMove,
+        "WHEEL" => DomCommandAction::Wheel,
+        "TAP" => DomCommandAction::Tap,
+        "SWIPE" => DomCommandAction::Swipe,
+        "LONG_PRESS" => DomCommandAction::LongPress,
+        "TYPE_AND_SELECT" => DomCommandAction::TypeAndSelect,
+        "SET_RANGE" => DomCommandAction::SetRange,
+        "RICH_TEXT_TYPE" => DomCommandAction::RichTextType,
+        "GET_WS_MESSAGES" => DomCommandAction::GetWsMessages,
+        "WAIT_FOR_WS_MESSAGE" => DomCommandAction::WaitForWsMessage,
+        "GET_PERF_METRICS" => DomCommandAction::GetPerfMetrics,
+        "GET_CONSOLE_ERRORS" => DomCommandAction::GetConsoleErrors,
+        "SET_GEOLOCATION" => DomCommandAction::SetGeolocation,
+        "GET_MEDIA_QUERY" => DomCommandAction::GetMediaQuery,
+        "IS_DARK_MODE" => DomCommandAction::IsDarkMode,
+        "CAPTURE_ELEMENT" => DomCommandAction::CaptureElement,
+        "ASSERT_VISUAL_MATCH" => DomCommandAction::AssertVisualMatch,
+        "GET_DOM_DIFF" => DomCommandAction::GetDomDiff,
+        "GET_LANG" => DomCommandAction::GetLang,
+        "TAB_TO" => DomCommandAction::TabTo,
+        "GET_FOCUSED" => DomCommandAction::GetFocused,
+        "AUDIT_SEO" => DomCommandAction::AuditSeo,
+        "EXTRACT_STRUCTURED_DATA" => DomCommandAction::ExtractStructuredData,
+        "SEARCH_AND_OPEN" => DomCommandAction::SearchAndOpen,
+        "LOGIN" => DomCommandAction::Login,
+        "REQUEST_USER_INPUT" => DomCommandAction::RequestUserInput,
+        "CHECK_BOT_SIGNALS" => DomCommandAction::CheckBotSignals,
+        "CALL_API" => DomCommandAction::CallApi,
+        "GRAPHQL" => DomCommandAction::GraphQl,
+        "FETCH" => DomCommandAction::Fetch,
+        "CALL_FUNCTION" => DomCommandAction::CallFunction,
+        "LISTEN" => DomCommandAction::Listen,
+        "WATCH" => DomCommandAction::Watch,
+        "ASSERT_JSON" => DomCommandAction::AssertJson,
+        _ => {
+            return Err(format!(
+                "Invalid action '{}' from LLM at index {}.",
+                llm_cmd_req.action, index
+            ));
+        }
+    };
+
+    let validation_error: Option<String> = match dom_action {
+        DomCommandAction::Type
+        | DomCommandAction::SetAttribute
+        | DomCommandAction::SelectOption
+        | DomCommandAction::EvalJs
+        | DomCommandAction::TypeIfExists
+        | DomCommandAction::Wheel
+        | DomCommandAction::Swipe
+        | DomCommandAction::Navigate
+        | DomCommandAction::TypeAndSelect
+        | DomCommandAction::SearchAndOpen
+        | DomCommandAction::Login
+        | DomCommandAction::RequestUserInput
+        | DomCommandAction::GraphQl
+        | DomCommandAction::Fetch
+        | DomCommandAction::Listen
+        | DomCommandAction::Watch
+        | DomCommandAction::AssertJson
+        | DomCommandAction::SetRange
+        | DomCommandAction::RichTextType
+        | DomCommandAction::WaitForWsMessage
+        | DomCommandAction::SetGeolocation
+        | DomCommandAction::GetMediaQuery
+        | DomCommandAction::AssertVisualMatch => {
+            if llm_cmd_req.value.is_none() {
+                Some(format!(
+                    "Action {:?} requires 'value'. Command index: {}. Request: {:?}",
+                    dom_action, index, llm_cmd_req
+                ))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    if let Some(err_msg) = validation_error {
+        return Err(err_msg);
+    }
+
+    let validation_error_attr: Option<String> = match dom_action {
+        DomCommandAction::GetAttribute
+        | DomCommandAction::SetAttribute
+        | DomCommandAction::GetAllAttributes => {
+            if llm_cmd_req.attribute_name.is_none() {
+                Some(format!("Action {:?} requires 'attribute_name'. Command index: {}. Request: {:?}", dom_action, index, llm_cmd_req))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    if let Some(err_msg) = validation_error_attr {
+        return Err(err_msg);
+    }
+
+    let reason = llm_cmd_req
+        .reason
+        .map(|reason| reason.trim().chars().take(MAX_REASON_CHARS).collect::<String>())
+        .filter(|reason| !reason.is_empty());
+    Ok((
+        DomCommand {
+            action: dom_action,
+            selector: llm_cmd_req.selector,
+            value: llm_cmd_req.value,
+            attribute_name: llm_cmd_req.attribute_name,
+        },
+        reason,
+    ))
+}
+
+// Private helper that dispatches `LLM_COMMAND_EVENT` on `window` once an LLM-proposed command has
+// run. Nothing is dispatched where there is no window (in a worker); failures are only logged.
+#[cfg(not(feature = "dom-only"))]
+fn dispatch_llm_command_event(index: usize, dom_command: &DomCommand, reason: Option<&str>, success: bool) {
+    let Some(window) = dom_backend::window() else { return };
+    let detail = serde_json::json!({
+        "index": index,
+        "action": format!("{:?}", dom_command.action),
+        "selector": dom_command.selector,
+        "reason": reason,
+        "success": success,
+    });
+    let dispatched = js_sys::JSON::parse(&detail.to_string()).and_then(|detail| {
+        let event_init = web_sys::CustomEventInit::new();
+        event_init.set_detail(&detail);
+        let event = web_sys::CustomEvent::new_with_event_init_dict(LLM_COMMAND_EVENT, &event_init)?;
+        window.dispatch_event(&event)
+    });
+    if let Err(e) = dispatched {
+        console::warn_1(&format!("Could not dispatch the {} event: {:?}", LLM_COMMAND_EVENT, e.as_string()).into());
+    }
+}
+
+/// How serious a problem found by plan validation is.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg(not(feature = "dom-only"))]
+pub enum PlanIssueSeverity {
+    /// The command cannot succeed as written; the plan is rejected.
+    Error,
+    /// The command may fail (e.g. its element only appears after an earlier step); the plan still runs.
+    Warning,
+}
+
+/// A single problem found while validating an LLM command array before execution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg(not(feature = "dom-only"))]
+pub struct PlanIssue {
+    /// Index of the offending command in the array.
+    pub index: usize,
+    /// The action as written in the command, or an empty string if it could not be read.
+    pub action: String,
+    /// The selector as written in the command, or an empty string if absent.
+    pub selector: String,
+    /// Whether the issue rejects the plan.
+    pub severity: PlanIssueSeverity,
+    /// A description of the problem.
+    pub message: String,
+}
+
+/// The consolidated result of validating an LLM command array.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg(not(feature = "dom-only"))]
+pub struct PlanValidationReport {
+    /// `false` if at least one issue has `Error` severity.
+    pub valid: bool,
+    /// Every issue found, in command order.
+    pub issues: Vec<PlanIssue>,
+}
+
+// Private helper that tells whether a command's selector must resolve to an element on the
+// current page. Commands without a selector, with coordinates instead, working on all matches
+// (zero is fine), or that tolerate or wait for absence are not checked.
+#[cfg(not(feature = "dom-only"))]
+fn requires_existing_element(dom_command: &DomCommand) -> bool {
+    match dom_command.action {
+        DomCommandAction::GetUrl
+        | DomCommandAction::Navigate
+        | DomCommandAction::GetFrames
+        | DomCommandAction::XpathEval
+        | DomCommandAction::EvalJs
+        | DomCommandAction::InjectCss
+        | DomCommandAction::RemoveInjectedCss
+        | DomCommandAction::DismissOverlays
+        | DomCommandAction::TourEnd
+        | DomCommandAction::GetWsMessages
+        | DomCommandAction::WaitForWsMessage
+        | DomCommandAction::GetPerfMetrics
+        | DomCommandAction::GetConsoleErrors
+        | DomCommandAction::SetGeolocation
+        | DomCommandAction::GetMediaQuery
+        | DomCommandAction::IsDarkMode
+        | DomCommandAction::GetDomDiff
+        | DomCommandAction::GetLang
+        | DomCommandAction::GetFocused
+        | DomCommandAction::AuditSeo
+        | DomCommandAction::ExtractStructuredData
+        | DomCommandAction::RequestUserInput
+        | DomCommandAction::CheckBotSignals
+        | DomCommandAction::CallApi
+        | DomCommandAction::GraphQl
+        | DomCommandAction::Fetch
+        | DomCommandAction::CallFunction
+        | DomCommandAction::AssertJson
+        | DomCommandAction::ClickAt
+        | DomCommandAction::ElementExists
+        | DomCommandAction::WaitForElement
+        | DomCommandAction::ClickIfExists
+        | DomCommandAction::TypeIfExists
+        | DomCommandAction::GetAllText
+        | DomCommandAction::GetAllAttributes => false,
+        DomCommandAction::MouseMove => {
+            let coordinates: Vec<&str> = dom_command.selector.split(',').collect();
+            !(coordinates.len() == 2 && coordinates.iter().all(|c| c.trim().parse::<f64>().is_ok()))
+        }
+        // Without a selector, `TAB_TO` only presses Tab a number of times.
+        DomCommandAction::TabTo => !dom_command.selector.is_empty(),
+        _ => true,
+    }
+}
+
+// Private helper that tells whether a command may change the page, so that elements
+// referenced by later commands could appear as a result (e.g. a click opening a dialog).
+#[cfg(not(feature = "dom-only"))]
+fn may_change_page(action: &DomCommandAction) -> bool {
+    matches!(
+        action,
+        DomCommandAction::Click
+            | DomCommandAction::Type
+            | DomCommandAction::SetAttribute
+            | DomCommandAction::SelectOption
+            | DomCommandAction::Hover
+            | DomCommandAction::EvalJs
+            | DomCommandAction::DismissOverlays
+            | DomCommandAction::ClickIfExists
+            | DomCommandAction::TypeIfExists
+            | DomCommandAction::ClickAt
+            | DomCommandAction::ClickCenter
+            | DomCommandAction::MouseMove
+            | DomCommandAction::Wheel
+            | DomCommandAction::Tap
+            | DomCommandAction::Swipe
+            | DomCommandAction::LongPress
+            | DomCommandAction::Navigate
+            | DomCommandAction::TypeAndSelect
+            | DomCommandAction::SearchAndOpen
+            | DomCommandAction::Login
+            | DomCommandAction::SetRange
+            | DomCommandAction::RichTextType
+            | DomCommandAction::TabTo
+    )
+}
+
+// Private helper that tells whether a command of an LLM plan runs in a batch with the read-only
+// commands next to it. Picking among several matches asks the LLM, and a DOM proxy answers from
+// the main thread, so neither completes synchronously.
+#[cfg(not(feature = "dom-only"))]
+fn runs_in_batch(dom_command: &DomCommand, config: &ExecutionConfig) -> bool {
+    is_read_only(&dom_command.action) && config.dom_proxy.is_none() && !config.disambiguate_matches
+}
+
+// Private helper that runs consecutive read-only commands in one synchronous pass, so that the page
+// gets no chance to run (and change the layout) between them. Read-only commands are not paced and
+// do not wait for the page to settle, so each completes when first polled. Should one not, the batch
+// ends before it and it runs as usual; reading the page again is harmless.
+#[cfg(not(feature = "dom-only"))]
+fn run_read_only_batch<'a>(
+    commands: impl Iterator<Item = &'a DomCommand>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> VecDeque<Result<String, AgentError>> {
+    commands.map_while(|dom_command| execute_dom_command(dom_command, config, journal).now_or_never()).collect()
+}
+
+// Private helper that tells whether a command only reads the page, synchronously. Such commands
+// are invisible to the page and its user, so pacing does not apply to them, and consecutive ones
+// in an LLM plan run in one batch (see `run_read_only_batch`).
+fn is_read_only(action: &DomCommandAction) -> bool {
+    matches!(
+        action,
+        DomCommandAction::Read
+            | DomCommandAction::GetValue
+            | DomCommandAction::GetAttribute
+            | DomCommandAction::GetAllAttributes
+            | DomCommandAction::GetUrl
+            | DomCommandAction::GetFrames
+            | DomCommandAction::ElementExists
+            | DomCommandAction::IsVisible
+            | DomCommandAction::GetAllText
+            | DomCommandAction::XpathEval
+            | DomCommandAction::GetElementAttributes
+            | DomCommandAction::GetDataset
+            | DomCommandAction::GetRole
+            | DomCommandAction::GetAriaState
+            | DomCommandAction::IsEnabled
+            | DomCommandAction::IsEditable
+            | DomCommandAction::IsSelected
+            | DomCommandAction::GetWsMessages
+            | DomCommandAction::GetConsoleErrors
+            | DomCommandAction::GetMediaQuery
+            | DomCommandAction::IsDarkMode
+            | DomCommandAction::GetDomDiff
+            | DomCommandAction::GetLang
+            | DomCommandAction::GetFocused
+            | DomCommandAction::AuditSeo
+            | DomCommandAction::ExtractStructuredData
+            | DomCommandAction::CheckBotSignals
+    )
+}
+
+// Private helper that tells whether a command is an interaction after which the page may keep
+// changing for a while (a menu opening, results loading), so that auto-settle waits for it.
+fn settles_after(action: &DomCommandAction) -> bool {
+    matches!(
+        action,
+        DomCommandAction::Click
+            | DomCommandAction::ClickIfExists
+            | DomCommandAction::ClickAt
+            | DomCommandAction::ClickCenter
+            | DomCommandAction::Tap
+            | DomCommandAction::Type
+            | DomCommandAction::TypeIfExists
+            | DomCommandAction::RichTextType
+            | DomCommandAction::SelectOption
+            | DomCommandAction::SetRange
+    )
+}
+
+// Private helper that tells whether a command stays within the container it selects, so that it
+// can run in a preview's sandbox or an agent's region. Navigation, scripts, styles, coordinates,
+// focus order and tours act on the whole page, and API calls and host functions beyond it.
+fn stays_within_container(action: &DomCommandAction) -> bool {
+    !matches!(
+        action,
+        DomCommandAction::Navigate
+            | DomCommandAction::EvalJs
+            | DomCommandAction::InjectCss
+            | DomCommandAction::RemoveInjectedCss
+            | DomCommandAction::DismissOverlays
+            | DomCommandAction::SetGeolocation
+            | DomCommandAction::ClickAt
+            | DomCommandAction::MouseMove
+            | DomCommandAction::Wheel
+            | DomCommandAction::TabTo
+            | DomCommandAction::TourStep
+            | DomCommandAction::TourShow
+            | DomCommandAction::TourEnd
+            | DomCommandAction::CallApi
+            | DomCommandAction::GraphQl
+            | DomCommandAction::Fetch
+            | DomCommandAction::CallFunction
+    )
+}
+
+// Private helper that tells whether the selector of a command names the element it acts on or
+// reads, rather than nothing or something else (a URL pattern, a media query, an XPath expression
+// evaluated on the whole page...). Commands reading the page as a whole run unscoped in a region.
+fn selects_element(action: &DomCommandAction) -> bool {
+    !matches!(
+        action,
+        DomCommandAction::GetUrl
+            | DomCommandAction::GetFrames
+            | DomCommandAction::XpathEval
+            | DomCommandAction::GetWsMessages
+            | DomCommandAction::WaitForWsMessage
+            | DomCommandAction::GetPerfMetrics
+            | DomCommandAction::GetConsoleErrors
+            | DomCommandAction::GetMediaQuery
+            | DomCommandAction::IsDarkMode
+            | DomCommandAction::GetDomDiff
+            | DomCommandAction::GetLang
+            | DomCommandAction::GetFocused
+            | DomCommandAction::AuditSeo
+            | DomCommandAction::ExtractStructuredData
+            | DomCommandAction::RequestUserInput
+            | DomCommandAction::CheckBotSignals
+            | DomCommandAction::CallApi
+            | DomCommandAction::GraphQl
+            | DomCommandAction::Fetch
+            | DomCommandAction::CallFunction
+            | DomCommandAction::AssertJson
+    )
+}
+
+// Private helper that returns the selector of `dom_command` scoped to `region` (see
+// `dom_utils::split_scope`), or `None` if it is left as it is: the command does not select an
+// element, has no selector, or is already scoped to the region (e.g. a command recorded in a
+// preview being applied).
+fn region_selector(dom_command: &DomCommand, region: &str) -> Option<String> {
+    let selector = dom_command.selector.trim();
+    let prefix = format!("{}{}", region.trim(), dom_utils::SCOPE_SEPARATOR);
+    if !selects_element(&dom_command.action) || selector.is_empty() || selector.starts_with(&prefix) {
+        return None;
+    }
+    Some(format!("{}{}", prefix, selector))
+}
+
+// Private helper that confines a command to the agent's region, if one is set: its selector is
+// resolved within the region, and commands acting on the whole page are refused.
+fn scope_to_region(dom_command: &DomCommand, config: &ExecutionConfig) -> Result<Option<DomCommand>, AgentError> {
+    let Some(region) = config.region.as_deref() else { return Ok(None) };
+    if !stays_within_container(&dom_command.action) {
+        return Err(AgentError::PolicyViolation(format!(
+            "{:?} acts beyond the agent's region '{}' and cannot run in it.",
+            dom_command.action, region
+        )));
+    }
+    Ok(region_selector(dom_command, region).map(|selector| DomCommand { selector, ..dom_command.clone() }))
+}
+
+// Private helper that returns a copy of an LLM command array with its selectors scoped to the
+// agent's region, for plan validation and guardrails to look for elements where they will run,
+// or `None` if no region is set. Commands that cannot be read are left as they are.
+#[cfg(not(feature = "dom-only"))]
+fn scope_plan_to_region(command_array: &[serde_json::Value], config: &ExecutionConfig) -> Option<Vec<serde_json::Value>> {
+    let region = config.region.as_deref()?;
+    Some(
+        command_array
+            .iter()
+            .enumerate()
+            .map(|(index, cmd_json_obj)| {
+                let selector = llm_request_to_dom_command(index, cmd_json_obj).ok().and_then(|dom_command| region_selector(&dom_command, region));
+                let mut scoped = cmd_json_obj.clone();
+                if let (Some(selector), Some(fields)) = (selector, scoped.as_object_mut()) {
+                    fields.insert("selector".to_string(), serde_json::Value::String(selector));
+                }
+                scoped
+            })
+            .collect(),
+    )
+}
+
+// Private helper that tells what a command counts as against the caps of the run: `NAVIGATE` is a
+// navigation and every other command acting on the page (or calling its API) a DOM mutation; reads
+// and waits are free.
+fn run_action(action: &DomCommandAction) -> Option<RunAction> {
+    match action {
+        DomCommandAction::Navigate => Some(RunAction::Navigation),
+        DomCommandAction::WaitForElement
+        | DomCommandAction::WaitForWsMessage
+        | DomCommandAction::GetPerfMetrics
+        | DomCommandAction::CaptureElement
+        | DomCommandAction::AssertVisualMatch
+        | DomCommandAction::RequestUserInput
+        | DomCommandAction::Listen
+        | DomCommandAction::Watch
+        | DomCommandAction::AssertJson => None,
+        action if is_read_only(action) => None,
+        _ => Some(RunAction::DomMutation),
+    }
+}
+
+/// Validates a whole LLM command array against the current page without executing it.
+///
+/// Every command is checked for a known action and the fields that action requires, and
+/// every selector that must target an element is checked to resolve now. A selector that
+/// does not resolve is accepted if an earlier `WAIT_FOR_ELEMENT` waits for it, reported as
+/// a warning if an earlier command may change the page (e.g. a click opening a dialog),
+/// and reported as an error otherwise.
+#[cfg(not(feature = "dom-only"))]
+pub fn validate_llm_plan(command_array: &[serde_json::Value]) -> PlanValidationReport {
+    let mut issues = Vec::new();
+    let mut awaited_selectors: Vec<String> = Vec::new();
+    let mut last_page_change: Option<usize> = None;
+
+    for (index, cmd_json_obj) in command_array.iter().enumerate() {
+        let field = |name: &str| cmd_json_obj.get(name).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        let mut issue = |severity: PlanIssueSeverity, message: String| {
+            issues.push(PlanIssue { index, action: field("action"), selector: field("selector"), severity, message });
+        };
+
+        let dom_command = match llm_request_to_dom_command(index, cmd_json_obj) {
+            Ok(dom_command) => dom_command,
+            Err(message) => {
+                issue(PlanIssueSeverity::Error, message);
+                continue;
+            }
+        };
+
+        if requires_existing_element(&dom_command) {
+            if dom_command.selector.trim().is_empty() {
+                issue(PlanIssueSeverity::Error, format!("Action {:?} requires a non-empty 'selector'.", dom_command.action));
+            } else if !awaited_selectors.contains(&dom_command.selector) {
+                match dom_utils::element_exists(&dom_command.selector) {
+                    Ok(true) => {}
+                    Ok(false) => match last_page_change {
+                        Some(step) => issue(
+                            PlanIssueSeverity::Warning,
+                            format!("Selector '{}' does not match any element yet; it may be created by command {}.", dom_command.selector, step),
+                        ),
+                        None => issue(
+                            PlanIssueSeverity::Error,
+                            format!("Selector '{}' does not match any element on the page.", dom_command.selector),
+                        ),
+                    },
+                    Err(e) => issue(PlanIssueSeverity::Error, format!("Selector '{}' could not be resolved: {}", dom_command.selector, e)),
+                }
+            }
+        }
+
+        if dom_command.action == DomCommandAction::WaitForElement {
+            awaited_selectors.push(dom_command.selector.clone());
+        }
+        if may_change_page(&dom_command.action) {
+            last_page_change = Some(index);
+        }
+    }
+
+    let valid = !issues.iter().any(|issue| issue.severity == PlanIssueSeverity::Error);
+    PlanValidationReport { valid, issues }
+}
+
+/// Checks an LLM command array for signs that the LLM got the page or the task wrong, without
+/// executing it: elements that are not on the page and that no earlier command waits for or may
+/// create, navigation (`NAVIGATE` and link clicks) leading outside the origin allowlist or, without
+/// one, off the current site, and entered values that look made up (see `guardrails::made_up_placeholder`).
+/// Commands that cannot be read are left to `validate_llm_plan` and execution to report.
+#[cfg(not(feature = "dom-only"))]
+pub fn check_guardrails(command_array: &[serde_json::Value], config: &ExecutionConfig) -> Vec<GuardrailWarning> {
+    let mut warnings = Vec::new();
+    let mut awaited_selectors: Vec<String> = Vec::new();
+    let mut page_may_change = false;
+    let current_origin = dom_backend::window().and_then(|window| window.location().origin().ok());
+
+    for (index, cmd_json_obj) in command_array.iter().enumerate() {
+        let Ok(dom_command) = llm_request_to_dom_command(index, cmd_json_obj) else { continue };
+        let mut warn = |kind: GuardrailKind, message: String| {
+            warnings.push(GuardrailWarning {
+                index,
+                action: format!("{:?}", dom_command.action),
+                selector: dom_command.selector.clone(),
+                kind,
+                message,
+            });
+        };
+
+        if requires_existing_element(&dom_command)
+            && !page_may_change
+            && !awaited_selectors.contains(&dom_command.selector)
+            && dom_utils::element_exists(&dom_command.selector).is_ok_and(|exists| !exists)
+        {
+            warn(GuardrailKind::MissingElement, format!("Selector '{}' does not match any element on the page.", dom_command.selector));
+        }
+
+        let target = match dom_command.action {
+            DomCommandAction::Navigate => dom_command.value.clone(),
+            DomCommandAction::Click | DomCommandAction::ClickIfExists | DomCommandAction::ClickCenter | DomCommandAction::Tap => {
+                dom_utils::link_target(&dom_command.selector).ok().flatten()
+            }
+            _ => None,
+        };
+        if let Some(target_origin) = target.and_then(|target| dom_utils::resolve_url(&target).ok()).map(|url| url.origin()) {
+            if !config.allowed_origins.is_empty() && !origin_allowed(&config.allowed_origins, &target_origin) {
+                warn(GuardrailKind::DisallowedNavigation, format!("Leads to origin '{}', which is not on the allowlist.", target_origin));
+            } else if config.allowed_origins.is_empty() && current_origin.as_deref().is_some_and(|origin| origin != target_origin) {
+                warn(GuardrailKind::OffsiteNavigation, format!("Leads away from this site to origin '{}'.", target_origin));
+            }
+        }
+
+        if let Some(placeholder) = entered_value(&dom_command).and_then(guardrails::made_up_placeholder) {
+            warn(GuardrailKind::PlaceholderValue, format!("The value contains '{}', which looks like a placeholder rather than real data.", placeholder));
+        }
+
+        if dom_command.action == DomCommandAction::WaitForElement {
+            awaited_selectors.push(dom_command.selector.clone());
+        }
+        page_may_change |= may_change_page(&dom_command.action);
+    }
+    warnings
+}
+
+// Private helper that asks the host's confirmation handler whether a plan flagged by the guardrails
+// may run. Without a handler, or if it fails or declines, the plan is rejected with the report.
+#[cfg(not(feature = "dom-only"))]
+async fn confirm_flagged_plan(warnings: Vec<GuardrailWarning>, config: &ExecutionConfig) -> Result<(), AgentError> {
+    // The host sees the report the rejected plan would fail with.
+    let question = serde_json::to_string(&GuardrailReport { confirmed: false, warnings })
+        .map_err(|e| AgentError::SerializationError(format!("Error serializing guardrail report: {}", e)))?;
+    let confirmed = match config.confirmation_handler.as_ref() {
+        None => false,
+        Some(handler) => match handler.call1(&wasm_bindgen::JsValue::NULL, &question.as_str().into()) {
+            Ok(reply) => match reply.dyn_into::<js_sys::Promise>() {
+                Ok(promise) => wasm_bindgen_futures::JsFuture::from(promise).await.is_ok_and(|reply| reply.as_bool() == Some(true)),
+                Err(reply) => reply.as_bool() == Some(true),
+            },
+            Err(e) => {
+                console::warn_1(&format!("The confirmation handler failed: {:?}", e.as_string()).into());
+                false
+            }
+        },
+    };
+    if confirmed {
+        console::log_1(&format!("Guardrail warnings confirmed by the host: {}", question).into());
+        return Ok(());
+    }
+    Err(AgentError::PlanValidationFailed(question))
+}
+
+// What `execute_llm_commands` did with a plan: the serialized results of its commands, and the
+// first failure showing that the plan itself was wrong (a command that could not be read, or whose
+// selector was invalid or matched nothing), for which the task may be planned again.
+#[cfg(not(feature = "dom-only"))]
+struct PlanOutcome {
+    results: String,
+    plan_failure: Option<String>,
+    // The code of the task's result (see `result_code`).
+    code: &'static str,
+}
+
+#[cfg(not(feature = "dom-only"))]
+impl PlanOutcome {
+    // The outcome of a response that is not a plan, e.g. a natural language answer.
+    fn answer(message: String) -> Self {
+        PlanOutcome { results: message, plan_failure: None, code: result_code::LLM_NATURAL_RESPONSE }
+    }
+}
+
+// Private helper that tells whether a command failed because of its selector rather than the page.
+#[cfg(not(feature = "dom-only"))]
+fn is_selector_failure(error: &AgentError) -> bool {
+    matches!(
+        error,
+        AgentError::DomOperationFailed(DomError::ElementNotFound { .. } | DomError::InvalidSelector { .. })
+    )
+}
+
+// Private helper function for executing a list of LLM-derived commands
+#[cfg(not(feature = "dom-only"))]
+async fn execute_llm_commands(
+    selected_agent: &Agent,
+    command_array: &[serde_json::Value],
+    task: &str,
+    llm: LlmEndpoint<'_>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<PlanOutcome, AgentError> {
+    let mut results: Vec<Result<String, String>> = Vec::new();
+    let mut plan_failure: Option<String> = None;
+
+    let scoped_plan = scope_plan_to_region(command_array, config);
+    let plan = scoped_plan.as_deref().unwrap_or(command_array);
+    if config.validate_plans {
+        let report = validate_llm_plan(plan);
+        let report_json = serde_json::to_string(&report)
+            .map_err(|e| AgentError::SerializationError(format!("Error serializing plan validation report: {}", e)))?;
+        if !report.valid {
+            return Err(AgentError::PlanValidationFailed(report_json));
+        }
+        if !report.issues.is_empty() {
+            console::warn_1(&format!("Agent {} ({:?}): Plan validation warnings: {}", selected_agent.id, selected_agent.role, report_json).into());
+        }
+    }
+    if config.guardrails {
+        let warnings = check_guardrails(plan, config);
+        if !warnings.is_empty() {
+            confirm_flagged_plan(warnings, config).await?;
+        }
+    }
+
+    console::log_1(
+        &format!(
+            "Agent {} ({:?}): LLM returned {} commands. Executing...",
+            selected_agent.id,
+            selected_agent.role,
+            command_array.len()
+        )
+        .into(),
+    );
+
+    let commands: Vec<Result<(DomCommand, Option<String>), String>> =
+        command_array.iter().enumerate().map(|(index, cmd_json_obj)| parse_llm_command(index, cmd_json_obj)).collect();
+    // Outcomes of the rest of the current batch of consecutive read-only commands.
+    let mut batched: VecDeque<Result<String, AgentError>> = VecDeque::new();
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            Ok((dom_command, reason)) => {
+                let value = if config.redact_values_in_results && entered_value(dom_command).is_some() {
+                    dom_command.value.as_ref().map(|_| REDACTED_VALUE.to_string())
+                } else {
+                    dom_command.value.clone()
+                };
+                let mut cmd_representation = format!(
+                    "Action: {:?}, Selector: '{}', Value: {:?}, AttrName: {:?}",
+                    dom_command.action,
+                    dom_command.selector,
+                    value,
+                    dom_command.attribute_name
+                );
+                if let Some(reason) = reason {
+                    cmd_representation.push_str(&format!(", Reason: {:?}", reason));
+                }
+
+                if batched.is_empty() && runs_in_batch(dom_command, config) {
+                    let batch = commands[index..].iter().map_while(|command| match command {
+                        Ok((dom_command, _)) if runs_in_batch(dom_command, config) => Some(dom_command),
+                        _ => None,
+                    });
+                    batched = run_read_only_batch(batch, config, journal);
+                }
+                let outcome = match batched.pop_front() {
+                    Some(outcome) => outcome,
+                    None => execute_picking_match(dom_command, task, llm, config, journal).await,
+                };
+                // A command beyond the caps of the run aborts the whole plan.
+                if let Err(AgentError::RunLimitExceeded(exceeded)) = outcome {
+                    dispatch_llm_command_event(index, dom_command, reason.as_deref(), false);
+                    return Err(AgentError::RunLimitExceeded(exceeded));
+                }
+                let selector_failed = outcome.as_ref().is_err_and(is_selector_failure);
+                let cmd_result_str: Result<String, String> = outcome
+                    .map(|message| match reason {
+                        Some(reason) => format!("{} (reason: {})", message, reason),
+                        None => message,
+                    })
+                    .map_err(|e| {
+                        format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
+                    });
+                if let (true, Err(message)) = (selector_failed, &cmd_result_str) {
+                    plan_failure.get_or_insert_with(|| message.clone());
+                }
+                dispatch_llm_command_event(index, dom_command, reason.as_deref(), cmd_result_str.is_ok());
+                results.push(cmd_result_str);
+            }
+            Err(err_msg) => {
+                console::warn_1(&err_msg.clone().into());
+                plan_failure.get_or_insert_with(|| err_msg.clone());
+                results.push(Err(err_msg.clone()));
+            }
+        }
+    }
+    let code = if results.iter().all(Result::is_ok) { result_code::LLM_PLAN_OK } else { result_code::LLM_PLAN_PARTIAL };
+    let results = serde_json::to_string(&results)
+        .map_err(|e| AgentError::SerializationError(format!("Error serializing LLM command results: {}", e)))?;
+    Ok(PlanOutcome { results, plan_failure, code })
+}
+
+// Private helper function for LLM interaction and response processing
+#[cfg(not(feature = "dom-only"))]
+async fn handle_llm_task(
+    selected_agent: &Agent,
+    task: &str,
+    llm: LlmEndpoint<'_>,
+    failures: &[String],
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<PlanOutcome, AgentError> {
+    console::log_1(
+        &format!(
+            "Agent {} ({:?}): No direct DOM command parsed. Defaulting to LLM for task: {}",
+            selected_agent.id, selected_agent.role, task
+        )
+        .into(),
+    );
+
+    // Task text may embed page content (e.g. READ results through {{PREVIOUS_RESULT}}), so it is
+    // redacted here, right before it leaves the browser.
+    let mut redactions = RedactionMap::default();
+    let task = if config.redaction.is_enabled() {
+        config.redaction.redact(task, &mut redactions)?
+    } else {
+        task.to_string()
+    };
+
+    // Tasks similar to this one that succeeded earlier are shown to the LLM with the given examples.
+    let embedding = config.memory.embed(&task).await;
+    let recalled = embedding.as_deref().map(|embedding| config.memory.recall(embedding)).unwrap_or_default();
+    let prompt_for_llm = generate_structured_llm_prompt(
+        selected_agent.id,
+        &selected_agent.role,
+        &task,
+        &AVAILABLE_DOM_COMMANDS,
+        &format!(
+            "{}{}{}{}",
+            config.apis.prompt_section(),
+            config.functions.prompt_section(),
+            config.prompt_examples.prompt_section(&task, &recalled),
+            escalation::failure_section(failures)
+        ),
+    );
+
+    config.run_limits.charge(RunAction::LlmCall).map_err(AgentError::RunLimitExceeded)?;
+    // Every RustAgent on the page draws from the same budget, if one is set.
+    budget::acquire(prompt_for_llm.len()).await.map_err(AgentError::BudgetExceeded)?;
+
+    match audit::complete("plan", prompt_for_llm, llm, config).await {
+        Ok(llm_response) => {
+            budget::record_response(llm_response.len());
+            // Commands are remembered as the LLM proposed them, with placeholders for redacted values.
+            let proposed = serde_json::from_str::<serde_json::Value>(&llm_response).ok();
+            let llm_response = if redactions.is_empty() {
+                llm_response
+            } else {
+                match serde_json::from_str::<serde_json::Value>(&llm_response) {
+                    Ok(mut json_value) => {
+                        redactions.restore_json(&mut json_value);
+                        json_value.to_string()
+                    }
+                    Err(_) => redactions.restore(&llm_response),
+                }
+            };
+            match serde_json::from_str::<serde_json::Value>(&llm_response) {
+                Ok(json_value) => {
+                    if json_value.is_array() {
+                        let command_array = json_value.as_array().ok_or_else(|| {
+                            AgentError::InvalidLlmResponse(
+                                "LLM response is JSON but not an array.".to_string(),
+                            )
+                        })?;
+
+                        if command_array.is_empty() {
+                            console::log_1(
+                                &format!(
+                                    "Agent {} ({:?}): LLM returned an empty command array. Treating as natural language response: {}",
+                                    selected_agent.id, selected_agent.role, llm_response
+                                )
+                                .into(),
+                            );
+                            return Ok(PlanOutcome::answer(format!(
+                                "Agent {} ({:?}) completed task via LLM: {}",
+                                selected_agent.id, selected_agent.role, llm_response
+                            )));
+                        }
+                        let outcome = execute_llm_commands(selected_agent, command_array, &task, llm, config, journal).await?;
+                        if let (Some(embedding), Some(proposed)) = (embedding, proposed) {
+                            let succeeded = serde_json::from_str::<Vec<Result<String, String>>>(&outcome.results)
+                                .is_ok_and(|results| results.iter().all(Result::is_ok));
+                            if succeeded {
+                                config.memory.remember(&task, &proposed, embedding);
+                            }
+                        }
+                        Ok(outcome)
+                    } else {
+                        console::log_1(
+                            &format!(
+                                "Agent {} ({:?}): LLM response was valid JSON but not an array. Treating as natural language: {}",
+                                selected_agent.id, selected_agent.role, llm_response
+                            )
+                            .into(),
+                        );
+                        Ok(PlanOutcome::answer(format!(
+                            "Agent {} ({:?}) completed task via LLM: {}",
+                            selected_agent.id, selected_agent.role, llm_response
+                        )))
+                    }
+                }
+                Err(e) => {
+                    let trimmed_response = llm_response.trim();
+                    if trimmed_response.starts_with('{') || trimmed_response.starts_with('[') {
+                        Err(AgentError::InvalidLlmResponse(format!(
+                            "LLM response started like JSON but failed to parse: {}. Error: {}",
+                            llm_response, e
+                        )))
+                    } else {
+                        console::log_1(
+                            &format!(
+                                "Agent {} ({:?}): LLM response was not JSON (Error: {}). Treating as natural language: {}",
+                                selected_agent.id, selected_agent.role, e, llm_response
+                            )
+                            .into(),
+                        );
+                        Ok(PlanOutcome::answer(format!(
+                            "Agent {} ({:?}) completed task via LLM: {}",
+                            selected_agent.id, selected_agent.role, llm_response
+                        )))
+                    }
+                }
+            }
+        }
+        Err(llm_error) => Err(AgentError::from(llm_error)),
+    }
+}
+
+// Private helper that plans and carries out an LLM task. Under an escalation policy, a task whose
+// plan failed is planned again, with the reasons of the failures in the prompt: by the model in
+// use until `after_failures` plans failed, then once by the stronger model of the policy's profile.
+// The escalation is recorded for the run and dispatched as `escalation::ESCALATION_EVENT`.
+// Returns the results with their code (see `result_code`).
+#[cfg(not(feature = "dom-only"))]
+async fn plan_llm_task(
+    selected_agent: &Agent,
+    task: &str,
+    llm: LlmEndpoint<'_>,
+    config: &ExecutionConfig,
+    journal: &Journal,
+) -> Result<(String, &'static str), AgentError> {
+    let Some(policy) = &config.escalation.policy else {
+        return handle_llm_task(selected_agent, task, llm, &[], config, journal)
+            .await
+            .map(|outcome| (outcome.results, outcome.code));
+    };
+    let profile = config.llm_profiles.get(&policy.profile).ok_or_else(|| {
+        AgentError::CommandParseError(format!("Unknown LLM profile '{}' to escalate to. Add it with add_llm_profile first.", policy.profile))
+    })?;
+    let stronger = LlmEndpoint {
+        api_key: profile.api_key.as_deref().unwrap_or(llm.api_key),
+        api_url: profile.api_url.as_deref().unwrap_or(llm.api_url),
+        model_name: &profile.model,
+    };
+    let mut failures: Vec<String> = Vec::new();
+    loop {
+        let escalating = failures.len() >= policy.after_failures.max(1) as usize;
+        let endpoint = if escalating { stronger } else { llm };
+        let outcome = handle_llm_task(selected_agent, task, endpoint, &failures, config, journal).await;
+        let failure = match &outcome {
+            Ok(outcome) => outcome.plan_failure.clone(),
+            Err(e @ (AgentError::InvalidLlmResponse(_) | AgentError::PlanValidationFailed(_))) => Some(e.to_string()),
+            Err(_) => None,
+        };
+        if escalating {
+            let escalation = Escalation {
+                task: task.to_string(),
+                from_model: llm.model_name.to_string(),
+                to_model: stronger.model_name.to_string(),
+                profile: policy.profile.clone(),
+                failures,
+                success: outcome.is_ok() && failure.is_none(),
+            };
+            console::warn_1(&format!("Agent {} ({:?}): Task planned again with model '{}' after {} failed plans", selected_agent.id, selected_agent.role, stronger.model_name, escalation.failures.len()).into());
+            escalation::dispatch(&escalation);
+            config.escalation.record(escalation);
+            return outcome.map(|outcome| (outcome.results, outcome.code));
+        }
+        match failure {
+            Some(failure) => {
+                console::warn_1(&format!("Agent {} ({:?}): Plan with model '{}' failed: {}", selected_agent.id, selected_agent.role, llm.model_name, failure).into());
+                failures.push(failure);
+            }
+            None => return outcome.map(|outcome| (outcome.results, outcome.code)),
+        }
+    }
+}
+
+
+// Builds without the LLM (the `dom-only` feature) only run direct DOM commands.
+#[cfg(feature = "dom-only")]
+async fn plan_llm_task(
+    selected_agent: &Agent,
+    task: &str,
+    _llm: LlmEndpoint<'_>,
+    _config: &ExecutionConfig,
+    _journal: &Journal,
+) -> Result<(String, &'static str), AgentError> {
+    Err(AgentError::CommandParseError(format!(
+        "Agent {} ({:?}): '{}' is not a DOM command, and this build has no LLM to plan it (the `dom-only` feature)",
+        selected_agent.id, selected_agent.role, task
+    )))
+}
+
+impl AgentSystem {
+    /// Creates a new `AgentSystem` and initializes a predefined set of agents
+    /// with different roles (Navigator, FormFiller, Generic).
+    pub fn new() -> Self {
+        let agents = vec![
+            Agent {
+                id: 1,
+                role: AgentRole::Navigator,
+                keywords: vec!["navigate".to_string(), "go to".to_string(), "url".to_string(), "open".to_string()],
+                priority: 10,
+            },
+            Agent {
+                id: 2,
+                role: AgentRole::FormFiller,
+                keywords: vec!["fill".to_string(), "type".to_string(), "input".to_string(), "form".to_string(), "enter".to_string(), "select".to_string()],
+                priority: 10,
+            },
+            Agent {
+                id: 3,
+                role: AgentRole::Generic,
+                keywords: vec![], // Generic agent has no specific keywords by default
+                priority: 0,     // Lowest priority
+            },
+        ];
+        AgentSystem { agents, config: ExecutionConfig::default(), journal: Journal::default() }
+    }
+
+    /// Creates an `AgentSystem` with the default agents and the given execution policy.
+    /// Used to give scheduled runs their own copy of the policy in effect when they were scheduled.
+    pub fn with_config(config: ExecutionConfig) -> Self {
+        AgentSystem { config, ..AgentSystem::new() }
+    }
+
+    /// Returns the execution policy.
+    pub fn config(&self) -> &ExecutionConfig {
+        &self.config
+    }
+
+    /// Returns a mutable reference to the execution policy, for use by configuration setters.
+    pub fn config_mut(&mut self) -> &mut ExecutionConfig {
+        &mut self.config
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    /// Adds an example to the prompt of similar tasks sent to the LLM (see `prompt_examples`).
+    ///
+    /// # Returns
+    /// `Ok(())`, or `Err(AgentError::CommandParseError)` if `commands_json` is not a non-empty JSON
+    /// array of valid commands, as the LLM would propose them, or `task` is empty.
+    pub fn add_prompt_example(&mut self, task: &str, commands_json: &str) -> Result<(), AgentError> {
+        let commands: serde_json::Value = serde_json::from_str(commands_json)
+            .map_err(|e| AgentError::CommandParseError(format!("The commands of the example are not valid JSON: {}", e)))?;
+        for (index, command) in commands.as_array().into_iter().flatten().enumerate() {
+            llm_request_to_dom_command(index, command).map_err(AgentError::CommandParseError)?;
+        }
+        self.config.prompt_examples.add(task, &commands).map_err(AgentError::CommandParseError)
+    }
+
+    /// Marks the start of the run with id `run_id`: changes recorded during the previous run are
+    /// forgotten, so that `rollback_last_run` only undoes what the new run changes, and tasks
+    /// remembered from now on belong to the new run (see `memory::ExperienceMemory::apply_feedback`).
+    #[cfg_attr(feature = "dom-only", allow(unused_variables))]
+    pub fn begin_run(&self, run_id: &str) {
+        self.journal.clear();
+        #[cfg(not(feature = "dom-only"))]
+        self.config.memory.begin_run(run_id);
+        self.config.run_limits.begin_run();
+        self.config.soft_assertions.begin_run();
+        #[cfg(not(feature = "dom-only"))]
+        self.config.escalation.begin_run();
+        #[cfg(not(feature = "dom-only"))]
+        self.config.llm_audit.begin_run(run_id);
+    }
+
+    /// Restores the original attribute and form values changed during the last run, if
+    /// `transactional` execution was enabled while it ran.
+    ///
+    /// # Returns
+    /// The number of changes that were restored.
+    pub fn rollback_last_run(&self) -> usize {
+        self.journal.rollback()
+    }
+
+    /// Runs the commands recorded by a preview (see `sandbox::SandboxScope::performed`) on the page,
+    /// with this system's execution policy, stopping at the first failure.
+    ///
+    /// # Returns
+    /// The result of each command that ran, the last one failed if a command failed.
+    pub async fn apply_commands(&self, commands: &[serde_json::Value]) -> Vec<TaskResult> {
+        let mut results = Vec::with_capacity(commands.len());
+        for command in commands {
+            let result = match serde_json::from_value::<DomCommand>(command.clone()) {
+                Ok(dom_command) => match execute_dom_command(&dom_command, &self.config, &self.journal).await {
+                    Ok(message) => TaskResult::ok(message, result_code::command_ok(dom_command.action.name())),
+                    Err(e) => TaskResult::err(LibError::from(e)),
+                },
+                Err(e) => TaskResult::err(LibError::CommandParse { message: format!("Invalid previewed DOM command: {}", e) }),
+            };
+            let failed = !result.is_ok();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        results
+    }
+
+    /// Performs a DOM command received from a worker through the DOM proxy (see `worker::serve`),
+    /// with this system's execution policy and undo journal. Pacing, secrets and result limits
+    /// were already applied by the worker.
+    pub async fn perform_proxied_command(&self, command: serde_json::Value) -> Result<String, LibError> {
+        let dom_command: DomCommand = serde_json::from_value(command)
+            .map_err(|e| LibError::CommandParse { message: format!("Invalid proxied DOM command: {}", e) })?;
+        perform_dom_command(&dom_command, &self.config, &self.journal).await.map_err(LibError::from)
+    }
+
+    /// Runs a given task, either by parsing it as a direct DOM command or by
+    /// sending it to an LLM for interpretation into DOM commands or a natural language response.
+    /// `LLM_DECIDE` and `LLM_ASSERT` tasks are yes/no questions answered by the LLM (see `judge::judge`).
+    /// A task starting with a `profile:<name>` hint runs with that LLM profile instead, its API URL
+    /// and key defaulting to the ones given. Conditions at the end of a task (`[pre: ...]` and
+    /// `[post: ...]`, see `conditions::split`) are waited for before and after it, and noted at the
+    /// end of its result.
+    pub async fn run_task(
+        &self,
+        task: &str,
+        api_key: &str,
+        api_url: &str,
+        model_name: &str,
+    ) -> Result<String, AgentError> {
+        self.run_coded_task(task, api_key, api_url, model_name).await.map(|(result, _code)| result)
+    }
+
+    /// Runs a task like `run_task`, also returning the code of its success (see `result_code`):
+    /// `<COMMAND>_OK` for a direct command, `LLM_DECIDE_OK` or `LLM_ASSERT_OK` for a question,
+    /// `LLM_NATURAL_RESPONSE`, `LLM_PLAN_OK` or `LLM_PLAN_PARTIAL` for a task planned by the LLM, and
+    /// `SOFT_ASSERTION_FAILED` instead if an assertion of the task did not hold in soft-assert mode.
+    pub async fn run_coded_task(
+        &self,
+        task: &str,
+        api_key: &str,
+        api_url: &str,
+        model_name: &str,
+    ) -> Result<(String, String), AgentError> {
+        let failed_assertions = self.config.soft_assertions.failed();
+        let (task, conditions) = conditions::split(task).map_err(AgentError::CommandParseError)?;
+        let mut notes = Vec::new();
+        for condition in conditions.iter().filter(|condition| condition.time == ConditionTime::Pre) {
+            notes.push(check_condition(condition, None, &self.config, &self.journal).await?);
+        }
+        let (result, code) = self.run_task_without_conditions(task, api_key, api_url, model_name).await?;
+        for condition in conditions.iter().filter(|condition| condition.time == ConditionTime::Post) {
+            notes.push(check_condition(condition, Some(&result), &self.config, &self.journal).await?);
+        }
+        let code = if self.config.soft_assertions.failed() > failed_assertions {
+            result_code::SOFT_ASSERTION_FAILED.to_string()
+        } else {
+            code
+        };
+        if notes.is_empty() {
+            return Ok((result, code));
+        }
+        Ok((format!("{} {}", result, notes.join(" ")), code))
+    }
+
+    // Runs a task whose conditions were split off, returning its result and code.
+    async fn run_task_without_conditions(
+        &self,
+        task: &str,
+        api_key: &str,
+        api_url: &str,
+        model_name: &str,
+    ) -> Result<(String, String), AgentError> {
+        #[cfg(not(feature = "dom-only"))]
+        let (task, api_key, api_url, model_name) = match llm_profiles::parse_hint(task) {
+            None => (task, api_key, api_url, model_name),
+            Some(hint) => {
+                let (name, task) = hint.map_err(AgentError::CommandParseError)?;
+                let profile = self.config.llm_profiles.get(name).ok_or_else(|| {
+                    AgentError::CommandParseError(format!("Unknown LLM profile '{}'. Add it with add_llm_profile first.", name))
+                })?;
+                let api_key = profile.api_key.as_deref().unwrap_or(api_key);
+                let api_url = profile.api_url.as_deref().unwrap_or(api_url);
+                (task, api_key, api_url, profile.model.as_str())
+            }
+        };
+        let task_lowercase = task.to_lowercase();
+        let mut matching_agents: Vec<&Agent> = self
+            .agents
+            .iter()
+            .filter(|agent| {
+                agent.keywords.iter().any(|keyword| task_lowercase.contains(keyword))
+            })
+            .collect();
+
+        let selected_agent: &Agent;
+
+        if matching_agents.is_empty() {
+            // Default to Generic agent if no keywords match
+            selected_agent = self.agents.iter()
+                .find(|a| a.role == AgentRole::Generic)
+                .unwrap_or_else(|| {
+                    console::warn_1(&"Generic agent not found, defaulting to first agent in list.".into());
+                    &self.agents[0] // Should always find Generic, but as a robust fallback
+                });
+        } else {
+            // Sort matching agents by priority (descending)
+            matching_agents.sort_by(|a, b| b.priority.cmp(&a.priority));
+            
+            let highest_priority = matching_agents[0].priority;
+            let top_priority_agents: Vec<&&Agent> = matching_agents
+                .iter()
+                .filter(|a| a.priority == highest_priority)
+                .collect();
+
+            if top_priority_agents.len() == 1 {
+                selected_agent = top_priority_agents[0];
+            } else {
+                // Tie-breaking: if Generic is not among the tied, prefer the first specialized one.
+                // If Generic is among the tied, and there's another specialized one, prefer specialized.
+                // If all tied are specialized, or all tied are Generic (or only Generic is tied), pick the first one encountered.
+                if let Some(non_generic_tied_agent) = top_priority_agents.iter().find(|a| a.role != AgentRole::Generic) {
+                    selected_agent = non_generic_tied_agent;
+                } else {
+                    // All tied agents are Generic, or only Generic was tied.
+                    // Or, all tied agents are specialized (non-Generic) - pick the first from sorted list.
+                    selected_agent = top_priority_agents[0];
+                }
+            }
+        }
+
+        console::log_1(
+            &format!(
+                "Task received: '{}'. Selected Agent ID: {}, Role: {:?}, Priority: {}",
+                task, selected_agent.id, selected_agent.role, selected_agent.priority
+            )
+            .into(),
+        );
+
+        #[cfg(not(feature = "dom-only"))]
+        if let Some(judgment) = judge::parse_command(task) {
+            let judgment = judgment.map_err(AgentError::CommandParseError)?;
+            let outcome = judge::judge(&judgment, api_key, api_url, model_name, &self.config).await;
+            let (message, code) = match judgment.mode {
+                judge::JudgeMode::Assert => (
+                    soften_assertion(&format!("LLM_ASSERT {} {}", judgment.selector, judgment.question), outcome, &self.config)?,
+                    result_code::LLM_ASSERT_OK,
+                ),
+                judge::JudgeMode::Decide => (outcome?, result_code::LLM_DECIDE_OK),
+            };
+            return Ok((format!("Agent {} ({:?}): {}", selected_agent.id, selected_agent.role, message), code.to_string()));
+        }
+        let llm = LlmEndpoint { api_key, api_url, model_name };
+        if let Some(dom_command) = parse_dom_command(task) {
+            let result = execute_direct_dom_command(selected_agent, &dom_command, task, llm, &self.config, &self.journal).await?;
+            Ok((result, result_code::command_ok(dom_command.action.name())))
+        } else {
+            let (results, code) = plan_llm_task(selected_agent, task, llm, &self.config, &self.journal).await?;
+            Ok((results, code.to_string()))
+        }
+    }
+}
+
+// #[cfg(test)] attribute will be applied to the entire module below
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*; // For async tests in WASM
+    use crate::dom_utils::DomError; // Make sure DomError is in scope for tests
+    #[cfg(not(feature = "dom-only"))]
+    use crate::memory::EmbeddingEndpoint;
+    #[cfg(feature = "mock-llm")]
+    use crate::fixtures;
+    #[cfg(feature = "mock-llm")]
+    use crate::llm::add_mock_fixtures;
+    wasm_bindgen_test_configure!(run_in_browser); // Allows tests to run in a browser-like environment
+
+    // Helper to assert AgentError equality, focusing on variants and key parts of messages
+    fn assert_agent_error_variant(result: Result<String, AgentError>, expected_variant: AgentError) {
+        match result {
+            Ok(s) => panic!("Expected AgentError {:?}, but got Ok({})", expected_variant, s),
+            Err(e) => match (e, expected_variant) {
+                (AgentError::DomOperationFailed(actual_dom_err), AgentError::DomOperationFailed(expected_dom_err)) => {
+                    // For DomError, we might only check the variant or a substring of the message
+                    // due to potential complexities in full DomError equality (e.g. JsValue inside)
+                    assert_eq!(std::mem::discriminant(&actual_dom_err), std::mem::discriminant(&expected_dom_err));
+                    // Example: if expected_dom_err has a selector, check if actual_dom_err's string form contains it
+                    // This is a basic check; more specific checks might be needed depending on DomError structure
+                    if let DomError::ElementNotFound { selector: expected_selector, .. } = expected_dom_err {
+                         assert!(actual_dom_err.to_string().contains(&expected_selector));
+                    }
+                    // Add more specific checks for other DomError variants if necessary
+                }
+                (AgentError::LlmCallFailed(actual_msg), AgentError::LlmCallFailed(expected_msg)) => {
+                    assert!(actual_msg.contains(&expected_msg), "LlmCallFailed message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
+                }
+                (AgentError::LlmNetworkError(actual_msg), AgentError::LlmNetworkError(expected_msg)) => {
+                    assert!(actual_msg.contains(&expected_msg), "LlmNetworkError message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
+                }
+                (AgentError::InvalidLlmResponse(actual_msg), AgentError::InvalidLlmResponse(expected_msg)) => {
+                    assert!(actual_msg.contains(&expected_msg), "InvalidLlmResponse message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
+                }
+                (AgentError::CommandParseError(actual_msg), AgentError::CommandParseError(expected_msg)) => {
+                    assert!(actual_msg.contains(&expected_msg), "CommandParseError message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
+                }
+                (AgentError::SerializationError(actual_msg), AgentError::SerializationError(expected_msg)) => {
+                    assert!(actual_msg.contains(&expected_msg), "SerializationError message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
+                }
+                (actual, expected) => panic!("AgentError variant mismatch. Actual: {:?}, Expected: {:?}", actual, expected),
+            },
+        }
+    }
+
+
+    #[test]
+    fn test_parse_dom_command_get_url() {
+        let cmd = parse_dom_command("GET_URL").expect("GET_URL should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetUrl);
+        assert_eq!(cmd.selector, ""); // Selector is not used
+
+        // With unexpected args (should be ignored by parser, logged by GET_URL itself if needed)
+        let cmd_with_args = parse_dom_command("GET_URL some_arg").expect("GET_URL with args should parse");
+        assert_eq!(cmd_with_args.action, DomCommandAction::GetUrl);
+        assert_eq!(cmd_with_args.selector, ""); // Selector is not used
+    }
+
+    #[test]
+    fn test_parse_dom_command_element_exists() {
+        let cmd = parse_dom_command("ELEMENT_EXISTS css:#myId").expect("ELEMENT_EXISTS should parse");
+        assert_eq!(cmd.action, DomCommandAction::ElementExists);
+        assert_eq!(cmd.selector, "css:#myId");
+
+        assert!(parse_dom_command("ELEMENT_EXISTS").is_none(), "ELEMENT_EXISTS should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_wait_for_element() {
+        let cmd_no_timeout = parse_dom_command("WAIT_FOR_ELEMENT css:#myId").expect("WAIT_FOR_ELEMENT no timeout should parse");
+        assert_eq!(cmd_no_timeout.action, DomCommandAction::WaitForElement);
+        assert_eq!(cmd_no_timeout.selector, "css:#myId");
+        assert_eq!(cmd_no_timeout.value, None);
+
+        let cmd_with_timeout = parse_dom_command("WAIT_FOR_ELEMENT xpath://div 1000").expect("WAIT_FOR_ELEMENT with timeout should parse");
+        assert_eq!(cmd_with_timeout.action, DomCommandAction::WaitForElement);
+        assert_eq!(cmd_with_timeout.selector, "xpath://div");
+        assert_eq!(cmd_with_timeout.value, Some("1000".to_string()));
+
+        assert!(parse_dom_command("WAIT_FOR_ELEMENT").is_none(), "WAIT_FOR_ELEMENT should require a selector");
+
+        let cmd_invalid_timeout = parse_dom_command("WAIT_FOR_ELEMENT css:#myId abc").expect("WAIT_FOR_ELEMENT invalid timeout should parse");
+        assert_eq!(cmd_invalid_timeout.action, DomCommandAction::WaitForElement);
+        assert_eq!(cmd_invalid_timeout.selector, "css:#myId");
+        assert_eq!(cmd_invalid_timeout.value, None); // Invalid timeout 'abc' results in None
+    }
+
+    #[test]
+    fn test_parse_dom_command_is_visible() {
+        let cmd = parse_dom_command("IS_VISIBLE css:#myId").expect("IS_VISIBLE should parse");
+        assert_eq!(cmd.action, DomCommandAction::IsVisible);
+        assert_eq!(cmd.selector, "css:#myId");
+        assert!(parse_dom_command("IS_VISIBLE").is_none(), "IS_VISIBLE should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_scroll_to() {
+        let cmd = parse_dom_command("SCROLL_TO css:#myId").expect("SCROLL_TO should parse");
+        assert_eq!(cmd.action, DomCommandAction::ScrollTo);
+        assert_eq!(cmd.selector, "css:#myId");
+        assert!(parse_dom_command("SCROLL_TO").is_none(), "SCROLL_TO should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_tour() {
+        let cmd = parse_dom_command("TOUR_STEP css:#search Search for anything here").expect("TOUR_STEP should parse");
+        assert_eq!(cmd.action, DomCommandAction::TourStep);
+        assert_eq!(cmd.selector, "css:#search");
+        assert_eq!(cmd.value, Some("Search for anything here".to_string()));
+
+        let cmd = parse_dom_command("tour_show css:#profile Your account").expect("TOUR_SHOW should parse");
+        assert_eq!(cmd.action, DomCommandAction::TourShow);
+        assert_eq!(cmd.value, Some("Your account".to_string()));
+
+        let cmd = parse_dom_command("TOUR_END").expect("TOUR_END should parse");
+        assert_eq!(cmd.action, DomCommandAction::TourEnd);
+        assert_eq!(cmd.selector, "");
+
+        assert!(parse_dom_command("TOUR_STEP css:#search").is_none(), "TOUR_STEP should require text");
+        assert!(parse_dom_command("TOUR_SHOW").is_none(), "TOUR_SHOW should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_eval_js() {
+        let cmd = parse_dom_command("EVAL_JS return document.title;").expect("EVAL_JS should parse");
+        assert_eq!(cmd.action, DomCommandAction::EvalJs);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, Some("return document.title;".to_string()));
+        assert!(parse_dom_command("EVAL_JS").is_none(), "EVAL_JS should require a script");
+    }
+
+    #[test]
+    fn test_parse_dom_command_injected_css() {
+        let cmd = parse_dom_command("INJECT_CSS .sticky-header { display: none !important; }").expect("INJECT_CSS should parse");
+        assert_eq!(cmd.action, DomCommandAction::InjectCss);
+        assert_eq!(cmd.value, Some(".sticky-header { display: none !important; }".to_string()));
+        assert!(parse_dom_command("INJECT_CSS").is_none(), "INJECT_CSS should require CSS text");
+
+        let cmd = parse_dom_command("REMOVE_INJECTED_CSS rustagent-injected-css-1").expect("REMOVE_INJECTED_CSS should parse");
+        assert_eq!(cmd.action, DomCommandAction::RemoveInjectedCss);
+        assert_eq!(cmd.value, Some("rustagent-injected-css-1".to_string()));
+
+        let cmd = parse_dom_command("REMOVE_INJECTED_CSS").expect("REMOVE_INJECTED_CSS without id should parse");
+        assert_eq!(cmd.value, None);
+    }
+
+    #[test]
+    fn test_parse_dom_command_get_element_attributes() {
+        let cmd = parse_dom_command("GET_ELEMENT_ATTRIBUTES css:#myId").expect("GET_ELEMENT_ATTRIBUTES should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetElementAttributes);
+        assert_eq!(cmd.selector, "css:#myId");
+        assert!(parse_dom_command("GET_ELEMENT_ATTRIBUTES").is_none(), "GET_ELEMENT_ATTRIBUTES should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_get_dataset() {
+        let cmd = parse_dom_command("GET_DATASET css:.product-card").expect("GET_DATASET should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetDataset);
+        assert_eq!(cmd.selector, "css:.product-card");
+        assert!(parse_dom_command("GET_DATASET").is_none(), "GET_DATASET should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_aria() {
+        let cmd = parse_dom_command("GET_ROLE css:#menu-button").expect("GET_ROLE should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetRole);
+        assert_eq!(cmd.selector, "css:#menu-button");
+
+        let cmd = parse_dom_command("get_aria_state css:#menu-button").expect("GET_ARIA_STATE should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetAriaState);
+        assert_eq!(cmd.selector, "css:#menu-button");
+
+        assert!(parse_dom_command("GET_ROLE").is_none(), "GET_ROLE should require a selector");
+        assert!(parse_dom_command("GET_ARIA_STATE").is_none(), "GET_ARIA_STATE should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_state_predicates() {
+        for (task, expected_action) in [
+            ("IS_ENABLED css:#submit", DomCommandAction::IsEnabled),
+            ("IS_EDITABLE css:#submit", DomCommandAction::IsEditable),
+            ("is_selected css:#submit", DomCommandAction::IsSelected),
+        ] {
+            let cmd = parse_dom_command(task).expect("State predicate should parse");
+            assert_eq!(cmd.action, expected_action);
+            assert_eq!(cmd.selector, "css:#submit");
+        }
+        assert!(parse_dom_command("IS_ENABLED").is_none(), "IS_ENABLED should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_if_exists() {
+        let cmd = parse_dom_command("CLICK_IF_EXISTS css:.promo-close").expect("CLICK_IF_EXISTS should parse");
+        assert_eq!(cmd.action, DomCommandAction::ClickIfExists);
+        assert_eq!(cmd.selector, "css:.promo-close");
+
+        let cmd = parse_dom_command("TYPE_IF_EXISTS css:#coupon SAVE10").expect("TYPE_IF_EXISTS should parse");
+        assert_eq!(cmd.action, DomCommandAction::TypeIfExists);
+        assert_eq!(cmd.selector, "css:#coupon");
+        assert_eq!(cmd.value, Some("SAVE10".to_string()));
+
+        assert!(parse_dom_command("CLICK_IF_EXISTS").is_none(), "CLICK_IF_EXISTS should require a selector");
+        assert!(parse_dom_command("TYPE_IF_EXISTS css:#coupon").is_none(), "TYPE_IF_EXISTS should require text");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_if_exists_commands_skip_absent_elements() {
+        let agent_system = AgentSystem::new();
+
+        let result = agent_system.run_task("CLICK_IF_EXISTS css:#absent-promo", "key", "url", "model").await
+            .expect("CLICK_IF_EXISTS should not fail for an absent element");
+        assert!(result.contains("Skipped: {\"action\":\"CLICK\",\"reason\":\"element_not_found\",\"selector\":\"css:#absent-promo\",\"status\":\"skipped\"}"), "Unexpected result: {}", result);
+
+        let result = agent_system.run_task("TYPE_IF_EXISTS css:#absent-input hello", "key", "url", "model").await
+            .expect("TYPE_IF_EXISTS should not fail for an absent element");
+        assert!(result.contains("\"status\":\"skipped\""), "Unexpected result: {}", result);
+
+        // Other errors, such as invalid selectors, are still reported.
+        let err = agent_system.run_task("CLICK_IF_EXISTS css:[[[invalid", "key", "url", "model").await
+            .expect_err("Invalid selectors should still fail");
+        assert!(err.to_string().contains("InvalidSelector"), "Unexpected error: {}", err);
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_validate_llm_plan_reports_all_field_errors() {
+        let commands: Vec<serde_json::Value> = serde_json::from_str(r#"[
+            {"action": "GET_URL", "selector": ""},
+            {"action": "TYPE", "selector": "css:#email"},
+            {"action": "FLY", "selector": "css:#plane"},
+            {"action": "GETATTRIBUTE", "selector": "css:a"}
+        ]"#).unwrap();
+
+        let report = validate_llm_plan(&commands);
+        assert!(!report.valid);
+        let flagged: Vec<(usize, &str)> = report.issues.iter().map(|i| (i.index, i.action.as_str())).collect();
+        assert_eq!(flagged, vec![(1, "TYPE"), (2, "FLY"), (3, "GETATTRIBUTE")]);
+        assert!(report.issues.iter().all(|i| i.severity == PlanIssueSeverity::Error));
+        assert!(report.issues[0].message.contains("requires 'value'"), "Unexpected message: {}", report.issues[0].message);
+        assert_eq!(
+            serde_json::to_value(&report.issues[1]).unwrap()["severity"],
+            serde_json::json!("error")
+        );
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_llm_plan_with_eval_js_is_refused() {
+        let commands = vec![serde_json::json!({"action": "EVAL_JS", "selector": "", "value": "return document.cookie;"})];
+        let report = validate_llm_plan(&commands);
+        assert!(!report.valid);
+        assert!(report.issues[0].message.contains("EVAL_JS can only be run as a direct task"), "Unexpected message: {}", report.issues[0].message);
+        assert!(!AVAILABLE_DOM_COMMANDS.iter().any(|command| command.starts_with("EVAL_JS")));
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen_test]
+    fn test_validate_llm_plan_checks_selectors() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let button = document.create_element("button").unwrap();
+        button.set_id("plan-open-dialog");
+        document.body().unwrap().append_child(&button).unwrap();
+
+        let commands: Vec<serde_json::Value> = serde_json::from_str(r#"[
+            {"action": "READ", "selector": "css:#plan-missing"},
+            {"action": "WAIT_FOR_ELEMENT", "selector": "css:#plan-spinner-done"},
+            {"action": "READ", "selector": "css:#plan-spinner-done"},
+            {"action": "CLICK", "selector": "css:#plan-open-dialog"},
+            {"action": "TYPE", "selector": "css:#plan-dialog-input", "value": "hi"},
+            {"action": "ELEMENT_EXISTS", "selector": "css:#plan-anything"}
+        ]"#).unwrap();
+
+        let report = validate_llm_plan(&commands);
+        let flagged: Vec<(usize, PlanIssueSeverity)> = report.issues.iter().map(|i| (i.index, i.severity)).collect();
+        assert_eq!(flagged, vec![(0, PlanIssueSeverity::Error), (4, PlanIssueSeverity::Warning)]);
+        assert!(!report.valid);
+
+        // Warnings alone do not invalidate the plan.
+        let report = validate_llm_plan(&commands[3..]);
+        assert!(report.valid);
+        assert_eq!(report.issues.len(), 1);
+
+        button.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_commands_run_through_dom_proxy() {
+        use std::rc::Rc;
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let heading = document.create_element("h2").unwrap();
+        heading.set_id("proxied-heading");
+        heading.set_text_content(Some("Proxied"));
+        document.body().unwrap().append_child(&heading).unwrap();
+
+        // A message channel stands in for the worker boundary.
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let page_agents = Rc::new(AgentSystem::new());
+        let handler: crate::worker::DomRequestHandler = Rc::new(move |command| {
+            let page_agents = page_agents.clone();
+            Box::pin(async move { page_agents.perform_proxied_command(command).await })
+        });
+        let _host = crate::worker::serve(channel.port2().into(), handler).unwrap();
+        let mut worker_agents = AgentSystem::new();
+        worker_agents.config_mut().dom_proxy = Some(DomProxy::connect(channel.port1().into(), Some(1000)).unwrap());
+
+        let result = worker_agents.run_task("READ css:#proxied-heading", "key", "url", "model").await.unwrap();
+        assert!(result.contains("Proxied"), "Unexpected result: {}", result);
+        let err = worker_agents.run_task("READ css:#missing-proxied", "key", "url", "model").await.unwrap_err();
+        assert!(
+            matches!(&err, AgentError::ProxiedCommandFailed(LibError::DomOperation { kind, .. }) if kind == "ElementNotFound"),
+            "Unexpected error: {}", err
+        );
+        heading.remove();
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen_test]
+    fn test_read_only_batch_runs_in_one_pass() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let total = document.create_element("span").unwrap();
+        total.set_id("batched-total");
+        total.set_text_content(Some("42.00"));
+        document.body().unwrap().append_child(&total).unwrap();
+
+        let mut config = ExecutionConfig { pacing: Pacing::new(300, 300, None), ..ExecutionConfig::default() };
+        let commands = [
+            parse_dom_command("READ css:#batched-total").unwrap(),
+            parse_dom_command("IS_VISIBLE css:#batched-total").unwrap(),
+            parse_dom_command("CLICK css:#batched-total").unwrap(),
+        ];
+        assert!(commands[..2].iter().all(|command| runs_in_batch(command, &config)));
+        assert!(!runs_in_batch(&commands[2], &config), "Commands acting on the page are not batched");
+        // Not an async call: the reads complete without returning to the event loop.
+        let outcomes = run_read_only_batch(commands[..2].iter(), &config, &Journal::default());
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].as_ref().unwrap().contains("42.00"), "Unexpected outcome: {:?}", outcomes[0]);
+        assert!(outcomes[1].is_ok());
+
+        config.disambiguate_matches = true;
+        assert!(!runs_in_batch(&commands[0], &config), "Picking a match may ask the LLM");
+        total.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_read_only_commands_are_not_paced() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let heading = document.create_element("h2").unwrap();
+        heading.set_id("paced-heading");
+        heading.set_text_content(Some("Orders"));
+        document.body().unwrap().append_child(&heading).unwrap();
+
+        let mut agent_system = AgentSystem::new();
+        agent_system.config_mut().pacing = Pacing::new(300, 300, None);
+        let started = js_sys::Date::now();
+        for _ in 0..3 {
+            agent_system.run_task("READ css:#paced-heading", "key", "url", "model").await.unwrap();
         }
+        assert!(js_sys::Date::now() - started < 300.0, "Consecutive reads should run without delays");
+
+        agent_system.run_task("SETATTRIBUTE css:#paced-heading class done", "key", "url", "model").await.unwrap();
+        let started = js_sys::Date::now();
+        agent_system.run_task("SETATTRIBUTE css:#paced-heading class checked", "key", "url", "model").await.unwrap();
+        assert!(js_sys::Date::now() - started >= 299.0, "Commands changing the page are still paced");
+        heading.remove();
     }
-}
 
-// Private helper function for executing a list of LLM-derived commands
-async fn execute_llm_commands(
-    selected_agent: &Agent,
-    command_array: &[serde_json::Value],
-) -> Result<String, AgentError> {
-    let mut results: Vec<Result<String, String>> = Vec::new();
+    #[wasm_bindgen_test]
+    async fn test_auto_settle_waits_after_interactions() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let button = document.create_element("button").unwrap();
+        button.set_id("settle-load-more");
+        button.set_attribute("onclick", "setTimeout(() => { const p = document.createElement('p'); p.id = 'settle-loaded'; p.textContent = 'Loaded'; document.body.appendChild(p); }, 60)").unwrap();
+        document.body().unwrap().append_child(&button).unwrap();
 
-    console::log_1(
-        &format!(
-            "Agent {} ({:?}): LLM returned {} commands. Executing...",
-            selected_agent.id,
-            selected_agent.role,
-            command_array.len()
-        )
-        .into(),
-    );
+        let mut agent_system = AgentSystem::new();
+        agent_system.run_task("CLICK css:#settle-load-more", "key", "url", "model").await.unwrap();
+        assert!(agent_system.run_task("READ css:#settle-loaded", "key", "url", "model").await.is_err(), "Without auto-settle the next command runs at once");
+        gloo_timers::future::TimeoutFuture::new(100).await;
+        document.get_element_by_id("settle-loaded").unwrap().remove();
 
-    for (index, cmd_json_obj) in command_array.iter().enumerate() {
-        match serde_json::from_value::<LlmDomCommandRequest>(cmd_json_obj.clone()) {
-            Ok(llm_cmd_req) => {
-                let action_upper = llm_cmd_req.action.to_uppercase();
-                let dom_action = match action_upper.as_str() {
-                    "CLICK" => DomCommandAction::Click,
-                    "TYPE" => DomCommandAction::Type,
-                    "READ" => DomCommandAction::Read,
-                    "GETVALUE" => DomCommandAction::GetValue,
-                    "GETATTRIBUTE" => DomCommandAction::GetAttribute,
-                    "SETATTRIBUTE" => DomCommandAction::SetAttribute,
-                    "SELECTOPTION" => DomCommandAction::SelectOption,
-                    "GET_ALL_ATTRIBUTES" => DomCommandAction::GetAllAttributes,
-                    "GET_URL" => DomCommandAction::GetUrl,
-                    "ELEMENT_EXISTS" => DomCommandAction::ElementExists,
-                    "WAIT_FOR_ELEMENT" => DomCommandAction::WaitForElement,
-                    "IS_VISIBLE" => DomCommandAction::IsVisible,
-                    "SCROLL_TO" => DomCommandAction::ScrollTo,
-                    "HOVER" => DomCommandAction::Hover,
-                    "GET_ALL_TEXT" => DomCommandAction::GetAllText,
-                    _ => {
-                        let err_msg = format!(
-                            "Invalid action '{}' from LLM at index {}.",
-                            llm_cmd_req.action, index
-                        );
-                        console::warn_1(&err_msg.clone().into());
-                        results.push(Err(err_msg));
-                        continue;
-                    }
-                };
+        agent_system.config_mut().auto_settle = Some(SettleConfig { quiet_ms: 100, timeout_ms: 2000 });
+        let result = agent_system.run_task("CLICK css:#settle-load-more", "key", "url", "model").await.unwrap();
+        assert!(!result.contains("still changing"), "Unexpected result: {}", result);
+        let read = agent_system.run_task("READ css:#settle-loaded", "key", "url", "model").await.unwrap();
+        assert!(read.contains("Loaded"), "Unexpected result: {}", read);
+        document.get_element_by_id("settle-loaded").unwrap().remove();
+        button.remove();
+    }
 
-                let validation_error: Option<String> = match dom_action {
-                    DomCommandAction::Type
-                    | DomCommandAction::SetAttribute
-                    | DomCommandAction::SelectOption => {
-                        if llm_cmd_req.value.is_none() {
-                            Some(format!(
-                                "Action {:?} requires 'value'. Command index: {}. Request: {:?}",
-                                dom_action, index, llm_cmd_req
-                            ))
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                };
-                if let Some(err_msg) = validation_error {
-                    console::warn_1(&err_msg.clone().into());
-                    results.push(Err(err_msg));
-                    continue;
-                }
+    #[wasm_bindgen_test]
+    async fn test_soft_assertions_collect_failures() {
+        let mut agent_system = AgentSystem::new();
+        let failed = agent_system.run_task(r#"ASSERT_JSON {"total": 12} {"total": 10}"#, "key", "url", "model").await;
+        assert!(matches!(failed, Err(AgentError::AssertionFailed(_))), "Assertions fail their task by default");
 
-                let validation_error_attr: Option<String> = match dom_action {
-                    DomCommandAction::GetAttribute
-                    | DomCommandAction::SetAttribute
-                    | DomCommandAction::GetAllAttributes => {
-                        if llm_cmd_req.attribute_name.is_none() {
-                            Some(format!("Action {:?} requires 'attribute_name'. Command index: {}. Request: {:?}", dom_action, index, llm_cmd_req))
-                        } else {
-                            None
-                        }
-                    }
-                    _ => None,
-                };
-                if let Some(err_msg) = validation_error_attr {
-                    console::warn_1(&err_msg.clone().into());
-                    results.push(Err(err_msg));
-                    continue;
-                }
+        agent_system.config_mut().soft_assertions.enabled = true;
+        agent_system.begin_run("run-soft");
+        let result = agent_system.run_task(r#"ASSERT_JSON {"total": 12} {"total": 10} $.total"#, "key", "url", "model").await.unwrap();
+        assert!(result.contains("Soft assertion failed: JSON at $.total does not match"), "Unexpected result: {}", result);
+        agent_system.run_task(r#"ASSERT_JSON [1, 2] [1, 2]"#, "key", "url", "model").await.unwrap();
+        let not_found = agent_system.run_task("ASSERT_VISUAL_MATCH css:#soft-missing missing 1%", "key", "url", "model").await;
+        assert!(not_found.is_err(), "Errors other than failed assertions still fail the task");
 
-                let dom_command = DomCommand {
-                    action: dom_action,
-                    selector: llm_cmd_req.selector,
-                    value: llm_cmd_req.value,
-                    attribute_name: llm_cmd_req.attribute_name,
-                };
+        let report = agent_system.config().soft_assertions.report().unwrap();
+        assert_eq!((report.checked, report.failed), (2, 1));
+        assert_eq!(report.failures[0].assertion, "ASSERT_JSON $.total");
+        assert!(report.failures[0].message.contains("$.total: expected 10, got 12"), "Unexpected failure: {:?}", report.failures[0]);
+    }
 
-                let cmd_representation = format!(
-                    "Action: {:?}, Selector: '{}', Value: {:?}, AttrName: {:?}",
-                    dom_command.action,
-                    dom_command.selector,
-                    dom_command.value,
-                    dom_command.attribute_name
-                );
+    #[wasm_bindgen_test]
+    async fn test_task_conditions_are_waited_for() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let button = document.create_element("button").unwrap();
+        button.set_id("conditions-save");
+        button.set_attribute("onclick", "setTimeout(() => { const p = document.createElement('p'); p.id = 'conditions-toast'; document.body.appendChild(p); }, 80)").unwrap();
+        document.body().unwrap().append_child(&button).unwrap();
 
-                let cmd_result_str: Result<String, String> = match &dom_command.action {
-                    DomCommandAction::Click => dom_utils::click_element(&dom_command.selector)
-                        .map(|_| {
-                            format!(
-                                "Successfully clicked element with selector: '{}'",
-                                dom_command.selector
-                            )
-                        })
-                        .map_err(|e| {
-                            format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                        }),
-                    DomCommandAction::Type => {
-                        let text_to_type = dom_command.value.as_deref().unwrap_or_default();
-                        dom_utils::type_in_element(&dom_command.selector, text_to_type)
-                            .map(|_| {
-                                format!(
-                                    "Successfully typed '{}' in element with selector: '{}'",
-                                    text_to_type, dom_command.selector
-                                )
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            })
-                    }
-                    DomCommandAction::Read => dom_utils::get_element_text(&dom_command.selector)
-                        .map(|text| format!("Text from element '{}': {}", dom_command.selector, text))
-                        .map_err(|e| {
-                            format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                        }),
-                    DomCommandAction::GetValue => {
-                        dom_utils::get_element_value(&dom_command.selector)
-                            .map(|value| {
-                                format!("Value from element '{}': {}", dom_command.selector, value)
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            })
-                    }
-                    DomCommandAction::GetAttribute => {
-                        let attribute_name =
-                            dom_command.attribute_name.as_deref().unwrap_or_default();
-                        dom_utils::get_element_attribute(&dom_command.selector, attribute_name)
-                            .map(|value| {
-                                format!(
-                                    "Attribute '{}' from element '{}': {}",
-                                    attribute_name, dom_command.selector, value
-                                )
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            })
-                    }
-                    DomCommandAction::SetAttribute => {
-                        let attribute_name =
-                            dom_command.attribute_name.as_deref().unwrap_or_default();
-                        let attribute_value = dom_command.value.as_deref().unwrap_or_default();
-                        dom_utils::set_element_attribute(
-                            &dom_command.selector,
-                            attribute_name,
-                            attribute_value,
-                        )
-                        .map(|_| {
-                            format!(
-                                "Successfully set attribute '{}' to '{}' for element '{}'",
-                                attribute_name, attribute_value, dom_command.selector
-                            )
-                        })
-                        .map_err(|e| {
-                            format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                        })
-                    }
-                    DomCommandAction::SelectOption => {
-                        let value = dom_command.value.as_deref().unwrap_or_default();
-                        dom_utils::select_dropdown_option(&dom_command.selector, value)
-                            .map(|_| {
-                                format!(
-                                    "Successfully selected option '{}' for dropdown '{}'",
-                                    value, dom_command.selector
-                                )
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            })
-                    }
-                    DomCommandAction::GetAllAttributes => {
-                        let attribute_name =
-                            dom_command.attribute_name.as_deref().unwrap_or_default();
-                        dom_utils::get_all_elements_attributes(
-                            &dom_command.selector,
-                            attribute_name,
-                        )
-                        .map(|json_string| {
-                            format!(
-                                "Successfully retrieved attributes '{}' for elements matching selector '{}': {}",
-                                attribute_name, dom_command.selector, json_string
-                            )
-                        })
-                        .map_err(|e| {
-                            format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                        })
-                    }
-                    DomCommandAction::GetUrl => dom_utils::get_current_url()
-                        .map(|url| format!("Current URL is: {}", url))
-                        .map_err(|e| {
-                            format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                        }),
-                    DomCommandAction::ElementExists => {
-                        dom_utils::element_exists(&dom_command.selector)
-                            .map(|exists| {
-                                format!("Element '{}' exists: {}", dom_command.selector, exists)
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            })
-                    }
-                    DomCommandAction::WaitForElement => {
-                        let timeout_ms =
-                            dom_command.value.as_ref().and_then(|s| s.parse::<u32>().ok());
-                        match dom_utils::wait_for_element(&dom_command.selector, timeout_ms).await
-                        {
-                            Ok(()) => Ok(format!("Element '{}' appeared.", dom_command.selector)),
-                            Err(e) => Err(format!(
-                                "Command {} ('{}') failed: {}",
-                                index, cmd_representation, e
-                            )),
-                        }
-                    }
-                    DomCommandAction::IsVisible => {
-                        dom_utils::is_visible(&dom_command.selector)
-                            .map(|visible| {
-                                format!("Element '{}' is visible: {}", dom_command.selector, visible)
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            })
-                    }
-                    DomCommandAction::ScrollTo => dom_utils::scroll_to(&dom_command.selector)
-                        .map(|_| {
-                            format!(
-                                "Successfully scrolled to element '{}'",
-                                dom_command.selector
-                            )
-                        })
-                        .map_err(|e| {
-                            format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                        }),
-                        DomCommandAction::Hover => dom_utils::hover_element(&dom_command.selector)
-                            .map(|_| {
-                                format!(
-                                    "Successfully hovered over element '{}'",
-                                    dom_command.selector
-                                )
-                            })
-                            .map_err(|e| {
-                                format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                            }),
-                        DomCommandAction::GetAllText => {
-                            let separator = dom_command.value.as_deref().unwrap_or("\n");
-                            dom_utils::get_all_text_from_elements(&dom_command.selector, separator)
-                                .map(|text_content| {
-                                    format!(
-                                        "Retrieved text from elements matching '{}' (separated by '{}'): \"{}\"",
-                                        dom_command.selector, separator.replace("\n", "\\n"), text_content
-                                    )
-                                })
-                                .map_err(|e| {
-                                    format!("Command {} ('{}') failed: {}", index, cmd_representation, e)
-                                })
-                        }
-                };
-                results.push(cmd_result_str);
-            }
-            Err(e) => {
-                let err_msg = format!(
-                    "Command at index {} was malformed and could not be parsed: {}. Object: {}",
-                    index, e, cmd_json_obj
-                );
-                console::warn_1(&err_msg.clone().into());
-                results.push(Err(err_msg));
-            }
-        }
+        let mut agent_system = AgentSystem::new();
+        agent_system.config_mut().wait = WaitConfig { timeout_ms: 1000, interval_ms: 20 };
+        let result = agent_system
+            .run_task("CLICK css:#conditions-save [pre: IS_ENABLED css:#conditions-save] [post: ELEMENT_EXISTS css:#conditions-toast]", "key", "url", "model")
+            .await
+            .unwrap();
+        assert!(result.contains("Successfully clicked"), "Unexpected result: {}", result);
+        assert!(result.contains(" [pre: IS_ENABLED css:#conditions-save held after "), "Unexpected result: {}", result);
+        assert!(result.contains(" [post: ELEMENT_EXISTS css:#conditions-toast held after "), "Unexpected result: {}", result);
+
+        let failed = agent_system.run_task("READ css:#conditions-save [post 100: NOT ELEMENT_EXISTS css:#conditions-toast]", "key", "url", "model").await;
+        assert!(
+            matches!(&failed, Err(AgentError::AssertionFailed(message)) if message.contains("within 100ms") && message.contains("the task succeeded with")),
+            "Unexpected result: {:?}", failed
+        );
+        document.get_element_by_id("conditions-toast").unwrap().remove();
+        button.set_attribute("disabled", "").unwrap();
+        let failed = agent_system.run_task("CLICK css:#conditions-save [pre 100: IS_ENABLED css:#conditions-save]", "key", "url", "model").await;
+        assert!(
+            matches!(&failed, Err(AgentError::AssertionFailed(message)) if message.contains("the task did not run")),
+            "Unexpected result: {:?}", failed
+        );
+        assert!(matches!(
+            agent_system.run_task("CLICK css:#conditions-save [post: LLM_DECIDE css:body saved?]", "key", "url", "model").await,
+            Err(AgentError::CommandParseError(_))
+        ));
+        button.remove();
     }
-    serde_json::to_string(&results)
-        .map_err(|e| AgentError::SerializationError(format!("Error serializing LLM command results: {}", e)))
-}
 
-// Private helper function for LLM interaction and response processing
-async fn handle_llm_task(
-    selected_agent: &Agent,
-    task: &str,
-    api_key: &str,
-    api_url: &str,
-    model_name: &str,
-) -> Result<String, AgentError> {
-    console::log_1(
-        &format!(
-            "Agent {} ({:?}): No direct DOM command parsed. Defaulting to LLM for task: {}",
-            selected_agent.id, selected_agent.role, task
-        )
-        .into(),
-    );
+    #[wasm_bindgen_test]
+    async fn test_transactional_run_can_be_rolled_back() {
+        use wasm_bindgen::JsCast;
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let input = document.create_element("input").unwrap();
+        input.set_id("txn-input");
+        input.set_attribute("class", "original").unwrap();
+        document.body().unwrap().append_child(&input).unwrap();
 
-    let prompt_for_llm = generate_structured_llm_prompt(
-        selected_agent.id,
-        &selected_agent.role,
-        task,
-        &AVAILABLE_DOM_COMMANDS,
-    );
+        let mut agent_system = AgentSystem::new();
+        agent_system.run_task("SETATTRIBUTE css:#txn-input class untracked", "key", "url", "model").await.unwrap();
+        assert_eq!(agent_system.rollback_last_run(), 0, "Nothing is recorded unless transactional");
 
-    match call_llm_async(
-        prompt_for_llm,
-        api_key.to_string(),
-        api_url.to_string(),
-        model_name.to_string(),
-    )
-    .await
-    {
-        Ok(llm_response) => {
-            match serde_json::from_str::<serde_json::Value>(&llm_response) {
-                Ok(json_value) => {
-                    if json_value.is_array() {
-                        let command_array = json_value.as_array().ok_or_else(|| {
-                            AgentError::InvalidLlmResponse(
-                                "LLM response is JSON but not an array.".to_string(),
-                            )
-                        })?;
+        agent_system.config_mut().transactional = true;
+        agent_system.begin_run("run-txn");
+        agent_system.run_task("SETATTRIBUTE css:#txn-input class changed", "key", "url", "model").await.unwrap();
+        agent_system.run_task("TYPE css:#txn-input typed", "key", "url", "model").await.unwrap();
+        assert_eq!(agent_system.rollback_last_run(), 2);
+        assert_eq!(input.get_attribute("class"), Some("untracked".to_string()));
+        assert_eq!(input.dyn_ref::<web_sys::HtmlInputElement>().unwrap().value(), "");
 
-                        if command_array.is_empty() {
-                            console::log_1(
-                                &format!(
-                                    "Agent {} ({:?}): LLM returned an empty command array. Treating as natural language response: {}",
-                                    selected_agent.id, selected_agent.role, llm_response
-                                )
-                                .into(),
-                            );
-                            return Ok(format!(
-                                "Agent {} ({:?}) completed task via LLM: {}",
-                                selected_agent.id, selected_agent.role, llm_response
-                            ));
-                        }
-                        execute_llm_commands(selected_agent, command_array).await
-                    } else {
-                        console::log_1(
-                            &format!(
-                                "Agent {} ({:?}): LLM response was valid JSON but not an array. Treating as natural language: {}",
-                                selected_agent.id, selected_agent.role, llm_response
-                            )
-                            .into(),
-                        );
-                        Ok(format!(
-                            "Agent {} ({:?}) completed task via LLM: {}",
-                            selected_agent.id, selected_agent.role, llm_response
-                        ))
-                    }
-                }
-                Err(e) => {
-                    let trimmed_response = llm_response.trim();
-                    if trimmed_response.starts_with('{') || trimmed_response.starts_with('[') {
-                        Err(AgentError::InvalidLlmResponse(format!(
-                            "LLM response started like JSON but failed to parse: {}. Error: {}",
-                            llm_response, e
-                        )))
-                    } else {
-                        console::log_1(
-                            &format!(
-                                "Agent {} ({:?}): LLM response was not JSON (Error: {}). Treating as natural language: {}",
-                                selected_agent.id, selected_agent.role, e, llm_response
-                            )
-                            .into(),
-                        );
-                        Ok(format!(
-                            "Agent {} ({:?}) completed task via LLM: {}",
-                            selected_agent.id, selected_agent.role, llm_response
-                        ))
-                    }
-                }
-            }
-        }
-        Err(js_err) => Err(AgentError::LlmCallFailed(
-            js_err.as_string().unwrap_or_else(|| "Unknown LLM error".to_string()),
-        )),
+        input.remove();
     }
-}
 
+    #[test]
+    fn test_parse_dom_command_click_at_and_center() {
+        let cmd = parse_dom_command("CLICK_AT 120 45.5").expect("CLICK_AT should parse");
+        assert_eq!(cmd.action, DomCommandAction::ClickAt);
+        assert_eq!(cmd.value, Some("120 45.5".to_string()));
+        assert!(parse_dom_command("CLICK_AT 120").is_none(), "CLICK_AT should require two coordinates");
+        assert!(parse_dom_command("CLICK_AT x y").is_none(), "CLICK_AT coordinates should be numeric");
 
-impl AgentSystem {
-    /// Creates a new `AgentSystem` and initializes a predefined set of agents
-    /// with different roles (Navigator, FormFiller, Generic).
-    pub fn new() -> Self {
-        let agents = vec![
-            Agent {
-                id: 1,
-                role: AgentRole::Navigator,
-                keywords: vec!["navigate".to_string(), "go to".to_string(), "url".to_string(), "open".to_string()],
-                priority: 10,
-            },
-            Agent {
-                id: 2,
-                role: AgentRole::FormFiller,
-                keywords: vec!["fill".to_string(), "type".to_string(), "input".to_string(), "form".to_string(), "enter".to_string(), "select".to_string()],
-                priority: 10,
-            },
-            Agent {
-                id: 3,
-                role: AgentRole::Generic,
-                keywords: vec![], // Generic agent has no specific keywords by default
-                priority: 0,     // Lowest priority
-            },
-        ];
-        AgentSystem { agents }
+        let cmd = parse_dom_command("CLICK_CENTER css:#canvas").expect("CLICK_CENTER should parse");
+        assert_eq!(cmd.action, DomCommandAction::ClickCenter);
+        assert_eq!(cmd.selector, "css:#canvas");
+        assert!(parse_dom_command("CLICK_CENTER").is_none(), "CLICK_CENTER should require a selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_mouse_move_and_wheel() {
+        let cmd = parse_dom_command("MOUSE_MOVE css:#menu").expect("MOUSE_MOVE should parse");
+        assert_eq!(cmd.action, DomCommandAction::MouseMove);
+        assert_eq!(cmd.selector, "css:#menu");
+        let cmd = parse_dom_command("MOUSE_MOVE 120,48").expect("MOUSE_MOVE with coordinates should parse");
+        assert_eq!(cmd.selector, "120,48");
+        assert!(parse_dom_command("MOUSE_MOVE").is_none(), "MOUSE_MOVE should require a target");
+
+        let cmd = parse_dom_command("WHEEL css:.feed -300").expect("WHEEL should parse");
+        assert_eq!(cmd.action, DomCommandAction::Wheel);
+        assert_eq!(cmd.selector, "css:.feed");
+        assert_eq!(cmd.value, Some("-300".to_string()));
+        assert!(parse_dom_command("WHEEL css:.feed").is_none(), "WHEEL should require a delta");
+        assert!(parse_dom_command("WHEEL css:.feed down").is_none(), "WHEEL delta should be numeric");
+    }
+
+    #[test]
+    fn test_parse_dom_command_touch_gestures() {
+        let cmd = parse_dom_command("TAP css:#menu-toggle").expect("TAP should parse");
+        assert_eq!(cmd.action, DomCommandAction::Tap);
+        assert_eq!(cmd.selector, "css:#menu-toggle");
+        assert!(parse_dom_command("TAP").is_none(), "TAP should require a selector");
+
+        let cmd = parse_dom_command("SWIPE css:.carousel left 300").expect("SWIPE should parse");
+        assert_eq!(cmd.action, DomCommandAction::Swipe);
+        assert_eq!(cmd.selector, "css:.carousel");
+        assert_eq!(cmd.value, Some("left 300".to_string()));
+        let cmd = parse_dom_command("SWIPE css:.carousel up").expect("SWIPE without distance should parse");
+        assert_eq!(cmd.value, Some("up".to_string()));
+        assert!(parse_dom_command("SWIPE css:.carousel").is_none(), "SWIPE should require a direction");
+        assert!(parse_dom_command("SWIPE css:.carousel diagonal").is_none(), "SWIPE direction should be validated");
+        assert!(parse_dom_command("SWIPE css:.carousel left far").is_none(), "SWIPE distance should be numeric");
+
+        let cmd = parse_dom_command("LONG_PRESS css:.item 1200").expect("LONG_PRESS should parse");
+        assert_eq!(cmd.action, DomCommandAction::LongPress);
+        assert_eq!(cmd.selector, "css:.item");
+        assert_eq!(cmd.value, Some("1200".to_string()));
+        let cmd = parse_dom_command("LONG_PRESS css:.item").expect("LONG_PRESS without duration should parse");
+        assert_eq!(cmd.value, None);
+        assert!(parse_dom_command("LONG_PRESS css:.item soon").is_none(), "LONG_PRESS duration should be numeric");
+    }
+
+    #[test]
+    fn test_parse_dom_command_xpath_eval() {
+        let cmd = parse_dom_command("XPATH_EVAL count(//ul[@class = 'results']/li)").expect("XPATH_EVAL should parse");
+        assert_eq!(cmd.action, DomCommandAction::XpathEval);
+        assert_eq!(cmd.selector, "count(//ul[@class = 'results']/li)");
+        assert_eq!(cmd.value, None);
+        assert!(parse_dom_command("XPATH_EVAL").is_none(), "XPATH_EVAL should require an expression");
+    }
+
+    #[test]
+    fn test_parse_dom_command_get_frames() {
+        let cmd = parse_dom_command("GET_FRAMES").expect("GET_FRAMES should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetFrames);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, None);
+    }
+
+    #[test]
+    fn test_parse_dom_command_navigate() {
+        let cmd = parse_dom_command("NAVIGATE https://example.com/cart?step=2").expect("NAVIGATE should parse");
+        assert_eq!(cmd.action, DomCommandAction::Navigate);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, Some("https://example.com/cart?step=2".to_string()));
+        assert!(parse_dom_command("NAVIGATE").is_none(), "NAVIGATE should require a URL");
+    }
+
+    #[test]
+    fn test_origin_allowed() {
+        let allowed = vec!["https://shop.example".to_string(), "http://localhost:8080/".to_string()];
+        assert!(origin_allowed(&allowed, "https://shop.example"));
+        assert!(origin_allowed(&allowed, "http://localhost:8080"));
+        assert!(!origin_allowed(&allowed, "http://shop.example"));
+        assert!(!origin_allowed(&allowed, "https://shop.example.evil.test"));
+        assert!(!origin_allowed(&[], "https://shop.example"));
+    }
+
+    #[test]
+    fn test_sanitize_result() {
+        let type_command = parse_dom_command("TYPE css:#card 4111111111111111").unwrap();
+        let message = "Successfully typed '4111111111111111' in element with selector: 'css:#card'".to_string();
+        let mut config = ExecutionConfig::default();
+        assert_eq!(sanitize_result(message.clone(), &type_command, &config), message, "Unchanged by default");
+
+        config.redact_values_in_results = true;
+        assert_eq!(
+            sanitize_result(message.clone(), &type_command, &config),
+            "Successfully typed '[redacted]' in element with selector: 'css:#card'"
+        );
+        let read_command = parse_dom_command("READ css:#card").unwrap();
+        assert_eq!(sanitize_result(message.clone(), &read_command, &config), message, "Read results are not entered values");
+
+        config.max_result_length = Some(10);
+        assert_eq!(
+            sanitize_result("Text from élément".to_string(), &read_command, &config),
+            "Text from ... [truncated 7 characters]"
+        );
+        assert_eq!(sanitize_result("Short".to_string(), &read_command, &config), "Short");
+        assert_eq!(
+            sanitize_result("Family: 👩‍👩‍👧 and more".to_string(), &read_command, &config),
+            "Family: 👩‍👩‍👧 ... [truncated 8 characters]",
+            "Emoji sequences are not cut apart"
+        );
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_unicode_values_survive_round_trip() {
+        let value = "José Müller 👩‍👩‍👧 🇯🇵 שלום עולם مرحبا 東京";
+        let cmd = parse_dom_command(&format!("TYPE css:#name {}", value)).expect("TYPE with Unicode text should parse");
+        assert_eq!(cmd.selector, "css:#name");
+        assert_eq!(cmd.value.as_deref(), Some(value));
+        let cmd = parse_dom_command("GET_ALL_TEXT css:.item \" → \"").expect("GET_ALL_TEXT with a multi-byte separator should parse");
+        assert_eq!(cmd.value.as_deref(), Some(" → "));
+
+        // LLM responses may escape non-ASCII text, emoji as surrogate pairs.
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"action": "TYPE", "selector": "css:#name", "value": "Jos\u00e9 \ud83d\udc4d \u05e9\u05dc\u05d5\u05dd"}"#
+        ).unwrap();
+        let cmd = llm_request_to_dom_command(0, &json).unwrap();
+        assert_eq!(cmd.value.as_deref(), Some("José 👍 שלום"));
+
+        // Commands sent to the main thread by a worker are serialized to JSON and back.
+        let cmd = parse_dom_command(&format!("TYPE css:#name {}", value)).unwrap();
+        let round_tripped: DomCommand = serde_json::from_value(serde_json::to_value(&cmd).unwrap()).unwrap();
+        assert_eq!(round_tripped.value.as_deref(), Some(value));
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_parse_llm_command_reason() {
+        let (cmd, reason) = parse_llm_command(0, &serde_json::json!({"action": "CLICK", "selector": "css:#login", "reason": "  opens the login form "})).unwrap();
+        assert_eq!(cmd.selector, "css:#login");
+        assert_eq!(reason.as_deref(), Some("opens the login form"));
+        let (_, reason) = parse_llm_command(0, &serde_json::json!({"action": "CLICK", "selector": "css:#login", "reason": " "})).unwrap();
+        assert_eq!(reason, None);
+        let (_, reason) = parse_llm_command(0, &serde_json::json!({"action": "CLICK", "selector": "css:#login", "reason": "é".repeat(300)})).unwrap();
+        assert_eq!(reason.unwrap().chars().count(), MAX_REASON_CHARS);
+        let (_, reason) = parse_llm_command(0, &serde_json::json!({"action": "CLICK", "selector": "css:#login"})).unwrap();
+        assert_eq!(reason, None);
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_prompt_quotes_task_as_json_string() {
+        let task = "Type \"Zoë 👋\" into the name field\nthen submit";
+        let prompt = generate_structured_llm_prompt(1, &AgentRole::Generic, task, &AVAILABLE_DOM_COMMANDS, "");
+        assert!(prompt.contains(r#"The user wants to perform the following task: "Type \"Zoë 👋\" into the name field\nthen submit""#));
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_prompt_includes_similar_examples() {
+        let mut agent_system = AgentSystem::new();
+        agent_system
+            .add_prompt_example("Add the margherita to the cart", r#"[{"action": "CLICK", "selector": "css:[data-pizza=margherita] .add"}]"#)
+            .unwrap();
+        let unknown_action = agent_system.add_prompt_example("Open the menu", r#"[{"action": "FLY", "selector": "css:#menu"}]"#);
+        assert!(matches!(unknown_action, Err(AgentError::CommandParseError(_))), "Unexpected result: {:?}", unknown_action);
+        assert!(matches!(agent_system.add_prompt_example("Open the menu", "CLICK css:#menu"), Err(AgentError::CommandParseError(_))));
+
+        let examples = &agent_system.config().prompt_examples;
+        let task = "Add the pepperoni to the cart";
+        let prompt = generate_structured_llm_prompt(1, &AgentRole::Generic, task, &AVAILABLE_DOM_COMMANDS, &examples.prompt_section(task, &[]));
+        assert!(prompt.contains("Task: \"Add the margherita to the cart\"\nResponse: [{\"action\":\"CLICK\""), "Missing example: {}", prompt);
+        assert_eq!(examples.prompt_section("What time is it?", &[]), "");
+    }
+
+    #[test]
+    fn test_parse_dom_command_type_and_select() {
+        let cmd = parse_dom_command("TYPE_AND_SELECT css:#city css:#suggestions>li New York | #2").expect("TYPE_AND_SELECT should parse");
+        assert_eq!(cmd.action, DomCommandAction::TypeAndSelect);
+        assert_eq!(cmd.selector, "css:#city");
+        assert_eq!(
+            parse_type_and_select_value(cmd.value.as_deref().unwrap()),
+            Some(("css:#suggestions>li", "New York", SuggestionPick::Position(2)))
+        );
+        let cmd = parse_dom_command("TYPE_AND_SELECT css:#user css:.result ann | Ann Smith").unwrap();
+        assert_eq!(
+            parse_type_and_select_value(cmd.value.as_deref().unwrap()),
+            Some(("css:.result", "ann", SuggestionPick::Text("Ann Smith".to_string())))
+        );
+        assert_eq!(parse_type_and_select_value("css:.result ann").unwrap().2, SuggestionPick::Position(1), "The first suggestion is picked by default");
+        assert!(parse_dom_command("TYPE_AND_SELECT css:#city css:#suggestions>li").is_none(), "TYPE_AND_SELECT should require a query");
+        assert!(parse_dom_command("TYPE_AND_SELECT css:#city css:#suggestions>li Paris | #0").is_none(), "Positions start at 1");
+    }
+
+    #[test]
+    fn test_parse_dom_command_search_and_open() {
+        let cmd = parse_dom_command("SEARCH_AND_OPEN css:#q css:.results>a rust book | The Rust Programming Language").expect("SEARCH_AND_OPEN should parse");
+        assert_eq!(cmd.action, DomCommandAction::SearchAndOpen);
+        assert_eq!(cmd.selector, "css:#q");
+        assert_eq!(
+            parse_search_and_open_value(cmd.value.as_deref().unwrap()),
+            Some(("css:.results>a", "rust book", "The Rust Programming Language"))
+        );
+        assert_eq!(parse_search_and_open_value("css:.results>a rust book"), Some(("css:.results>a", "rust book", "rust book")), "Results are scored against the terms by default");
+        assert!(parse_dom_command("SEARCH_AND_OPEN css:#q css:.results>a").is_none(), "SEARCH_AND_OPEN should require search terms");
+        assert!(parse_dom_command("SEARCH_AND_OPEN css:#q css:.results>a rust |").is_none(), "The wanted text cannot be empty");
+    }
+
+    #[test]
+    fn test_parse_dom_command_login() {
+        let cmd = parse_dom_command("LOGIN css:#user css:#pass css:#submit css:.avatar css:.error | ann {{secret.password}}").expect("LOGIN should parse");
+        assert_eq!(cmd.action, DomCommandAction::Login);
+        assert_eq!(cmd.selector, "css:#user");
+        let (form, username, password) = parse_login_value(&cmd.selector, cmd.value.as_deref().unwrap()).unwrap();
+        assert_eq!(form, LoginForm {
+            username_selector: "css:#user",
+            password_selector: "css:#pass",
+            submit_selector: "css:#submit",
+            success_selector: "css:.avatar",
+            failure_selector: Some("css:.error"),
+        });
+        assert_eq!((username, password), ("ann", "{{secret.password}}"));
+
+        let (form, _, password) = parse_login_value("css:#user", "css:#pass css:#submit css:.avatar | ann pass phrase").unwrap();
+        assert_eq!(form.failure_selector, None);
+        assert_eq!(password, "pass phrase", "The password is everything after the username");
+        assert!(parse_dom_command("LOGIN css:#user css:#pass css:#submit css:.avatar").is_none(), "LOGIN should require credentials");
+        assert!(parse_dom_command("LOGIN css:#user css:#pass css:#submit | ann {{secret.password}}").is_none(), "LOGIN should require a success selector");
+    }
+
+    #[test]
+    fn test_parse_dom_command_request_user_input() {
+        let cmd = parse_dom_command("REQUEST_USER_INPUT otp Enter the code we texted you").expect("REQUEST_USER_INPUT should parse");
+        assert_eq!(cmd.action, DomCommandAction::RequestUserInput);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(
+            parse_user_input_value(cmd.value.as_deref().unwrap()),
+            Some(("otp", "Enter the code we texted you".to_string()))
+        );
+        assert_eq!(parse_user_input_value("otp"), Some(("otp", "Please enter otp".to_string())));
+        assert!(parse_dom_command("REQUEST_USER_INPUT").is_none(), "REQUEST_USER_INPUT should require a variable name");
+        assert!(parse_dom_command("REQUEST_USER_INPUT {{var.otp}} Code").is_none(), "Variable names cannot contain braces");
+    }
+
+    #[test]
+    fn test_parse_dom_command_call_api() {
+        let cmd = parse_dom_command(r#"CALL_API shop.addNote {"orderId": 7, "body": {"text": "Leave at the door"}}"#).expect("CALL_API should parse");
+        assert_eq!(cmd.action, DomCommandAction::CallApi);
+        assert_eq!(cmd.selector, "shop.addNote");
+        assert_eq!(cmd.value.as_deref(), Some(r#"{"orderId": 7, "body": {"text": "Leave at the door"}}"#));
+        assert_eq!(parse_dom_command("CALL_API shop.listOrders").unwrap().value, None, "Arguments are optional");
+        assert!(parse_dom_command("CALL_API listOrders").is_none(), "The operation should be named as <api>.<operation_id>");
+    }
+
+    #[test]
+    fn test_parse_dom_command_graphql() {
+        let cmd = parse_dom_command(r#"GRAPHQL /graphql query($id: ID!) { order(id: $id) { status } } | {"id": "42"}"#).expect("GRAPHQL should parse");
+        assert_eq!(cmd.action, DomCommandAction::GraphQl);
+        assert_eq!(cmd.selector, "/graphql");
+        assert_eq!(cmd.value.as_deref(), Some(r#"query($id: ID!) { order(id: $id) { status } } | {"id": "42"}"#));
+        assert!(parse_dom_command("GRAPHQL /graphql").is_none(), "GRAPHQL should require a query");
+    }
+
+    #[test]
+    fn test_parse_dom_command_fetch() {
+        let cmd = parse_dom_command(r#"FETCH post https://api.example.com/items {"name": "a b"}"#).expect("FETCH should parse");
+        assert_eq!(cmd.action, DomCommandAction::Fetch);
+        assert_eq!(cmd.selector, "https://api.example.com/items");
+        assert_eq!(parse_fetch_value(cmd.value.as_deref().unwrap()), Some(("POST".to_string(), Some(r#"{"name": "a b"}"#))));
+        assert_eq!(parse_dom_command("FETCH GET /config.json").unwrap().value.as_deref(), Some("GET"));
+        assert!(parse_dom_command("FETCH GET /config.json body").is_none(), "GET requests cannot have a body");
+        assert!(parse_dom_command("FETCH FOO /config.json").is_none(), "Unknown methods are rejected");
+        assert!(parse_dom_command("FETCH GET").is_none(), "FETCH should require a URL");
+    }
+
+    #[test]
+    fn test_parse_dom_command_call_function() {
+        let cmd = parse_dom_command(r#"CALL_FUNCTION add_to_cart {"sku": "A-1", "quantity": 2}"#).expect("CALL_FUNCTION should parse");
+        assert_eq!(cmd.action, DomCommandAction::CallFunction);
+        assert_eq!(cmd.selector, "add_to_cart");
+        assert_eq!(cmd.value.as_deref(), Some(r#"{"sku": "A-1", "quantity": 2}"#));
+        assert_eq!(parse_dom_command("CALL_FUNCTION open_chat").unwrap().value, None, "Arguments are optional");
+        assert!(parse_dom_command("CALL_FUNCTION").is_none(), "CALL_FUNCTION should require a function name");
     }
 
-    /// Runs a given task, either by parsing it as a direct DOM command or by
-    /// sending it to an LLM for interpretation into DOM commands or a natural language response.
-    pub async fn run_task(
-        &self,
-        task: &str,
-        api_key: &str,
-        api_url: &str,
-        model_name: &str,
-    ) -> Result<String, AgentError> {
-        let task_lowercase = task.to_lowercase();
-        let mut matching_agents: Vec<&Agent> = self
-            .agents
-            .iter()
-            .filter(|agent| {
-                agent.keywords.iter().any(|keyword| task_lowercase.contains(keyword))
-            })
-            .collect();
+    #[test]
+    fn test_parse_dom_command_listen() {
+        let cmd = parse_dom_command("LISTEN css:#search keydown,input 3000").expect("LISTEN should parse");
+        assert_eq!(cmd.action, DomCommandAction::Listen);
+        assert_eq!(cmd.selector, "css:#search");
+        assert_eq!(cmd.value.as_deref(), Some("keydown,input 3000"));
+        assert!(parse_dom_command("LISTEN css:#search click").is_none(), "LISTEN should require a duration");
+        assert!(parse_dom_command("LISTEN css:#search click 120000").is_none(), "Durations are capped");
+        assert!(parse_dom_command("LISTEN").is_none());
+    }
 
-        let selected_agent: &Agent;
+    #[test]
+    fn test_parse_dom_command_watch() {
+        let cmd = parse_dom_command("WATCH css:#cart 2000").expect("WATCH should parse");
+        assert_eq!(cmd.action, DomCommandAction::Watch);
+        assert_eq!(cmd.selector, "css:#cart");
+        assert_eq!(cmd.value.as_deref(), Some("2000"));
+        assert!(parse_dom_command("WATCH css:#cart").is_none(), "WATCH should require a duration");
+        assert!(parse_dom_command("WATCH css:#cart soon").is_none());
+    }
 
-        if matching_agents.is_empty() {
-            // Default to Generic agent if no keywords match
-            selected_agent = self.agents.iter()
-                .find(|a| a.role == AgentRole::Generic)
-                .unwrap_or_else(|| {
-                    console::warn_1(&"Generic agent not found, defaulting to first agent in list.".into());
-                    &self.agents[0] // Should always find Generic, but as a robust fallback
-                });
-        } else {
-            // Sort matching agents by priority (descending)
-            matching_agents.sort_by(|a, b| b.priority.cmp(&a.priority));
-            
-            let highest_priority = matching_agents[0].priority;
-            let top_priority_agents: Vec<&&Agent> = matching_agents
-                .iter()
-                .filter(|a| a.priority == highest_priority)
-                .collect();
+    #[test]
+    fn test_parse_dom_command_assert_json() {
+        let cmd = parse_dom_command(r#"ASSERT_JSON Structured data: [{"@type": "Product"}] ["Product"] $[0]["@type"]"#).expect("ASSERT_JSON should parse");
+        assert_eq!(cmd.action, DomCommandAction::AssertJson);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value.as_deref(), Some(r#"Structured data: [{"@type": "Product"}] ["Product"] $[0]["@type"]"#));
+        let cmd = parse_dom_command(r#"ASSERT_JSON ["a", "b"] ["a", "b"]"#).expect("JSON arrays are not selector chains");
+        assert_eq!(cmd.value.as_deref(), Some(r#"["a", "b"] ["a", "b"]"#));
+        assert!(parse_dom_command("ASSERT_JSON {{var.order}} {\"id\": 7} $.id").is_some(), "Variables are substituted when the command runs");
+        assert!(parse_dom_command("ASSERT_JSON").is_none());
+    }
 
-            if top_priority_agents.len() == 1 {
-                selected_agent = top_priority_agents[0];
-            } else {
-                // Tie-breaking: if Generic is not among the tied, prefer the first specialized one.
-                // If Generic is among the tied, and there's another specialized one, prefer specialized.
-                // If all tied are specialized, or all tied are Generic (or only Generic is tied), pick the first one encountered.
-                if let Some(non_generic_tied_agent) = top_priority_agents.iter().find(|a| a.role != AgentRole::Generic) {
-                    selected_agent = non_generic_tied_agent;
-                } else {
-                    // All tied agents are Generic, or only Generic was tied.
-                    // Or, all tied agents are specialized (non-Generic) - pick the first from sorted list.
-                    selected_agent = top_priority_agents[0];
-                }
-            }
-        }
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_parse_dom_command_check_bot_signals() {
+        let cmd = parse_dom_command("CHECK_BOT_SIGNALS").expect("CHECK_BOT_SIGNALS should parse");
+        assert_eq!(cmd.action, DomCommandAction::CheckBotSignals);
+        assert_eq!(cmd.selector, "");
+        assert!(!requires_existing_element(&cmd));
+        assert!(is_read_only(&cmd.action));
+    }
 
-        console::log_1(
-            &format!(
-                "Task received: '{}'. Selected Agent ID: {}, Role: {:?}, Priority: {}",
-                task, selected_agent.id, selected_agent.role, selected_agent.priority
-            )
-            .into(),
-        );
+    #[test]
+    fn test_parse_dom_command_selector_chain() {
+        let cmd = parse_dom_command("CLICK [css:#buy, text:\"Buy now\", xpath://button[1]]").expect("CLICK with a chain should parse");
+        assert_eq!(cmd.action, DomCommandAction::Click);
+        assert_eq!(cmd.selector, "[css:#buy, text:\"Buy now\", xpath://button[1]]");
 
-        if let Some(dom_command) = parse_dom_command(task) {
-            execute_direct_dom_command(selected_agent, &dom_command).await
-        } else {
-            handle_llm_task(selected_agent, task, api_key, api_url, model_name).await
-        }
+        let cmd = parse_dom_command("TYPE [css:#q, text:\"Search\"] rust wasm").expect("TYPE with a chain should parse");
+        assert_eq!(cmd.selector, "[css:#q, text:\"Search\"]");
+        assert_eq!(cmd.value.as_deref(), Some("rust wasm"));
+
+        let cmd = parse_dom_command("CLICK [data-test=buy]").expect("CLICK with an attribute selector should parse");
+        assert_eq!(cmd.selector, "[data-test=buy]", "An attribute selector is not a chain");
+        assert!(parse_dom_command("TYPE [css:#q, css:#search]").is_none(), "TYPE still requires text");
     }
-}
 
-// #[cfg(test)] attribute will be applied to the entire module below
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use wasm_bindgen_test::*; // For async tests in WASM
-    use crate::dom_utils::DomError; // Make sure DomError is in scope for tests
-    wasm_bindgen_test_configure!(run_in_browser); // Allows tests to run in a browser-like environment
+    #[test]
+    fn test_parse_dom_command_set_range() {
+        let cmd = parse_dom_command("SET_RANGE css:#volume 42.5").expect("SET_RANGE should parse");
+        assert_eq!(cmd.action, DomCommandAction::SetRange);
+        assert_eq!(cmd.selector, "css:#volume");
+        assert_eq!(cmd.value, Some("42.5".to_string()));
+        assert!(parse_dom_command("SET_RANGE css:#volume").is_none(), "SET_RANGE should require a value");
+        assert!(parse_dom_command("SET_RANGE css:#volume loud").is_none(), "SET_RANGE value should be numeric");
+    }
 
-    // Helper to assert AgentError equality, focusing on variants and key parts of messages
-    fn assert_agent_error_variant(result: Result<String, AgentError>, expected_variant: AgentError) {
-        match result {
-            Ok(s) => panic!("Expected AgentError {:?}, but got Ok({})", expected_variant, s),
-            Err(e) => match (e, expected_variant) {
-                (AgentError::DomOperationFailed(actual_dom_err), AgentError::DomOperationFailed(expected_dom_err)) => {
-                    // For DomError, we might only check the variant or a substring of the message
-                    // due to potential complexities in full DomError equality (e.g. JsValue inside)
-                    assert_eq!(std::mem::discriminant(&actual_dom_err), std::mem::discriminant(&expected_dom_err));
-                    // Example: if expected_dom_err has a selector, check if actual_dom_err's string form contains it
-                    // This is a basic check; more specific checks might be needed depending on DomError structure
-                    if let DomError::ElementNotFound { selector: expected_selector, .. } = expected_dom_err {
-                         assert!(actual_dom_err.to_string().contains(&expected_selector));
-                    }
-                    // Add more specific checks for other DomError variants if necessary
-                }
-                (AgentError::LlmCallFailed(actual_msg), AgentError::LlmCallFailed(expected_msg)) => {
-                    assert!(actual_msg.contains(&expected_msg), "LlmCallFailed message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
-                }
-                (AgentError::InvalidLlmResponse(actual_msg), AgentError::InvalidLlmResponse(expected_msg)) => {
-                    assert!(actual_msg.contains(&expected_msg), "InvalidLlmResponse message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
-                }
-                (AgentError::CommandParseError(actual_msg), AgentError::CommandParseError(expected_msg)) => {
-                    assert!(actual_msg.contains(&expected_msg), "CommandParseError message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
-                }
-                (AgentError::SerializationError(actual_msg), AgentError::SerializationError(expected_msg)) => {
-                    assert!(actual_msg.contains(&expected_msg), "SerializationError message mismatch. Actual: '{}', Expected to contain: '{}'", actual_msg, expected_msg);
-                }
-                (actual, expected) => panic!("AgentError variant mismatch. Actual: {:?}, Expected: {:?}", actual, expected),
-            },
-        }
+    #[test]
+    fn test_parse_dom_command_websocket_messages() {
+        let cmd = parse_dom_command("GET_WS_MESSAGES /chat").expect("GET_WS_MESSAGES should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetWsMessages);
+        assert_eq!(cmd.value, Some("/chat".to_string()));
+        let cmd = parse_dom_command("GET_WS_MESSAGES").expect("GET_WS_MESSAGES without filter should parse");
+        assert_eq!(cmd.value, None);
+
+        let cmd = parse_dom_command("WAIT_FOR_WS_MESSAGE \"status\": \"done\"").expect("WAIT_FOR_WS_MESSAGE should parse");
+        assert_eq!(cmd.action, DomCommandAction::WaitForWsMessage);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, Some("\"status\": \"done\"".to_string()));
+        assert!(parse_dom_command("WAIT_FOR_WS_MESSAGE").is_none(), "WAIT_FOR_WS_MESSAGE should require a pattern");
     }
 
+    #[test]
+    fn test_parse_dom_command_get_perf_metrics() {
+        let cmd = parse_dom_command("get_perf_metrics").expect("GET_PERF_METRICS should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetPerfMetrics);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, None);
+    }
 
     #[test]
-    fn test_parse_dom_command_get_url() {
-        let cmd = parse_dom_command("GET_URL").expect("GET_URL should parse");
-        assert_eq!(cmd.action, DomCommandAction::GetUrl);
-        assert_eq!(cmd.selector, ""); // Selector is not used
+    fn test_parse_dom_command_get_console_errors() {
+        let cmd = parse_dom_command("GET_CONSOLE_ERRORS").expect("GET_CONSOLE_ERRORS should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetConsoleErrors);
+        assert_eq!(cmd.value, None);
+        let cmd = parse_dom_command("GET_CONSOLE_ERRORS fail").expect("GET_CONSOLE_ERRORS FAIL should parse");
+        assert_eq!(cmd.value, Some("FAIL".to_string()));
+        assert!(parse_dom_command("GET_CONSOLE_ERRORS loudly").is_none(), "Only FAIL is accepted");
+    }
 
-        // With unexpected args (should be ignored by parser, logged by GET_URL itself if needed)
-        let cmd_with_args = parse_dom_command("GET_URL some_arg").expect("GET_URL with args should parse");
-        assert_eq!(cmd_with_args.action, DomCommandAction::GetUrl);
-        assert_eq!(cmd_with_args.selector, ""); // Selector is not used
+    #[test]
+    fn test_parse_dom_command_set_geolocation() {
+        let cmd = parse_dom_command("SET_GEOLOCATION 52.3676 4.9041").expect("SET_GEOLOCATION should parse");
+        assert_eq!(cmd.action, DomCommandAction::SetGeolocation);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, Some("52.3676 4.9041".to_string()));
+        assert_eq!(parse_geolocation_value("52.3676 4.9041"), Some((52.3676, 4.9041, emulation::DEFAULT_GEOLOCATION_ACCURACY_M)));
+        assert_eq!(parse_geolocation_value("-33.86 151.2 50"), Some((-33.86, 151.2, 50.0)));
+        assert!(parse_dom_command("SET_GEOLOCATION 52.3676").is_none(), "A longitude is required");
+        assert!(parse_dom_command("SET_GEOLOCATION 95 4.9").is_none(), "Latitude must be within [-90, 90]");
+        assert!(parse_dom_command("SET_GEOLOCATION north east").is_none());
     }
 
     #[test]
-    fn test_parse_dom_command_element_exists() {
-        let cmd = parse_dom_command("ELEMENT_EXISTS css:#myId").expect("ELEMENT_EXISTS should parse");
-        assert_eq!(cmd.action, DomCommandAction::ElementExists);
-        assert_eq!(cmd.selector, "css:#myId");
+    fn test_parse_dom_command_media_queries() {
+        let cmd = parse_dom_command("GET_MEDIA_QUERY (min-width: 768px) and (orientation: landscape)").expect("GET_MEDIA_QUERY should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetMediaQuery);
+        assert_eq!(cmd.value, Some("(min-width: 768px) and (orientation: landscape)".to_string()));
+        assert!(parse_dom_command("GET_MEDIA_QUERY").is_none(), "GET_MEDIA_QUERY should require a query");
 
-        assert!(parse_dom_command("ELEMENT_EXISTS").is_none(), "ELEMENT_EXISTS should require a selector");
+        let cmd = parse_dom_command("IS_DARK_MODE").expect("IS_DARK_MODE should parse");
+        assert_eq!(cmd.action, DomCommandAction::IsDarkMode);
+        assert_eq!(cmd.selector, "");
     }
 
     #[test]
-    fn test_parse_dom_command_wait_for_element() {
-        let cmd_no_timeout = parse_dom_command("WAIT_FOR_ELEMENT css:#myId").expect("WAIT_FOR_ELEMENT no timeout should parse");
-        assert_eq!(cmd_no_timeout.action, DomCommandAction::WaitForElement);
-        assert_eq!(cmd_no_timeout.selector, "css:#myId");
-        assert_eq!(cmd_no_timeout.value, None);
+    fn test_parse_dom_command_capture_element() {
+        let cmd = parse_dom_command("CAPTURE_ELEMENT css:#cart .summary").expect("CAPTURE_ELEMENT should parse");
+        assert_eq!(cmd.action, DomCommandAction::CaptureElement);
+        assert_eq!(cmd.selector, "css:#cart .summary");
+        assert!(parse_dom_command("CAPTURE_ELEMENT").is_none(), "CAPTURE_ELEMENT should require a selector");
+    }
 
-        let cmd_with_timeout = parse_dom_command("WAIT_FOR_ELEMENT xpath://div 1000").expect("WAIT_FOR_ELEMENT with timeout should parse");
-        assert_eq!(cmd_with_timeout.action, DomCommandAction::WaitForElement);
-        assert_eq!(cmd_with_timeout.selector, "xpath://div");
-        assert_eq!(cmd_with_timeout.value, Some("1000".to_string()));
+    #[test]
+    fn test_parse_dom_command_assert_visual_match() {
+        let cmd = parse_dom_command("ASSERT_VISUAL_MATCH css:#cart .summary cart-summary 1.5%").expect("ASSERT_VISUAL_MATCH should parse");
+        assert_eq!(cmd.action, DomCommandAction::AssertVisualMatch);
+        assert_eq!(cmd.selector, "css:#cart .summary");
+        assert_eq!(cmd.value.as_deref(), Some("cart-summary 1.5%"));
+        assert_eq!(parse_visual_match_value("cart-summary 1.5%"), Some(("cart-summary", 0.015)));
+        assert_eq!(parse_visual_match_value("header 0.02"), Some(("header", 0.02)));
 
-        assert!(parse_dom_command("WAIT_FOR_ELEMENT").is_none(), "WAIT_FOR_ELEMENT should require a selector");
+        assert!(parse_dom_command("ASSERT_VISUAL_MATCH css:#header header").is_none(), "A threshold is required");
+        assert!(parse_dom_command("ASSERT_VISUAL_MATCH css:#header header lots").is_none(), "The threshold must be numeric");
+        assert!(parse_dom_command("ASSERT_VISUAL_MATCH css:#header header 150%").is_none(), "The threshold must be at most 100%");
+    }
 
-        let cmd_invalid_timeout = parse_dom_command("WAIT_FOR_ELEMENT css:#myId abc").expect("WAIT_FOR_ELEMENT invalid timeout should parse");
-        assert_eq!(cmd_invalid_timeout.action, DomCommandAction::WaitForElement);
-        assert_eq!(cmd_invalid_timeout.selector, "css:#myId");
-        assert_eq!(cmd_invalid_timeout.value, None); // Invalid timeout 'abc' results in None
+    #[test]
+    fn test_parse_dom_command_get_dom_diff() {
+        let cmd = parse_dom_command("GET_DOM_DIFF").expect("GET_DOM_DIFF should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetDomDiff);
+        assert_eq!(cmd.selector, "");
+        assert!(cmd.value.is_none());
     }
 
+    #[cfg(not(feature = "dom-only"))]
     #[test]
-    fn test_parse_dom_command_is_visible() {
-        let cmd = parse_dom_command("IS_VISIBLE css:#myId").expect("IS_VISIBLE should parse");
-        assert_eq!(cmd.action, DomCommandAction::IsVisible);
-        assert_eq!(cmd.selector, "css:#myId");
-        assert!(parse_dom_command("IS_VISIBLE").is_none(), "IS_VISIBLE should require a selector");
+    fn test_parse_dom_command_get_lang() {
+        let cmd = parse_dom_command("get_lang").expect("GET_LANG should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetLang);
+        assert_eq!(cmd.selector, "");
+        assert!(cmd.value.is_none());
+        let cmd = llm_request_to_dom_command(0, &serde_json::json!({"action": "GET_LANG", "selector": ""})).unwrap();
+        assert_eq!(cmd.action, DomCommandAction::GetLang);
     }
 
+    #[cfg(not(feature = "dom-only"))]
     #[test]
-    fn test_parse_dom_command_scroll_to() {
-        let cmd = parse_dom_command("SCROLL_TO css:#myId").expect("SCROLL_TO should parse");
-        assert_eq!(cmd.action, DomCommandAction::ScrollTo);
-        assert_eq!(cmd.selector, "css:#myId");
-        assert!(parse_dom_command("SCROLL_TO").is_none(), "SCROLL_TO should require a selector");
+    fn test_parse_dom_command_keyboard_navigation() {
+        let cmd = parse_dom_command("TAB_TO css:form .submit").expect("TAB_TO should parse");
+        assert_eq!(cmd.action, DomCommandAction::TabTo);
+        assert_eq!(cmd.selector, "css:form .submit");
+        assert!(cmd.value.is_none());
+        assert!(requires_existing_element(&cmd));
+
+        let cmd = parse_dom_command("TAB_TO css:#email 5").expect("TAB_TO with a maximum should parse");
+        assert_eq!(cmd.selector, "css:#email");
+        assert_eq!(cmd.value.as_deref(), Some("5"));
+
+        let cmd = parse_dom_command("TAB_TO 3").expect("TAB_TO with a count should parse");
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value.as_deref(), Some("3"));
+        assert!(!requires_existing_element(&cmd));
+        assert!(parse_dom_command("TAB_TO").is_none(), "TAB_TO should require a selector or a count");
+
+        let cmd = parse_dom_command("GET_FOCUSED").expect("GET_FOCUSED should parse");
+        assert_eq!(cmd.action, DomCommandAction::GetFocused);
+        assert!(is_read_only(&cmd.action));
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_parse_dom_command_seo_and_structured_data() {
+        let cmd = parse_dom_command("AUDIT_SEO").expect("AUDIT_SEO should parse");
+        assert_eq!(cmd.action, DomCommandAction::AuditSeo);
+        assert_eq!(cmd.selector, "");
+        assert!(!requires_existing_element(&cmd));
+
+        let cmd = parse_dom_command("extract_structured_data").expect("EXTRACT_STRUCTURED_DATA should parse");
+        assert_eq!(cmd.action, DomCommandAction::ExtractStructuredData);
+        assert!(is_read_only(&cmd.action));
+    }
+
+    #[test]
+    fn test_parse_dom_command_dismiss_overlays() {
+        let cmd = parse_dom_command("DISMISS_OVERLAYS").expect("DISMISS_OVERLAYS should parse");
+        assert_eq!(cmd.action, DomCommandAction::DismissOverlays);
+        assert_eq!(cmd.selector, "");
+        assert_eq!(cmd.value, None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_eval_js_policy() {
+        let mut agent_system = AgentSystem::new();
+        let task = "EVAL_JS return 40 + 2;";
+
+        // Disabled by default.
+        let err = agent_system.run_task(task, "key", "url", "model").await.expect_err("EVAL_JS should be refused by default");
+        assert!(matches!(err, AgentError::PolicyViolation(_)), "Unexpected error: {}", err);
+
+        // Enabled, but the current origin is not on the allowlist.
+        agent_system.config_mut().allow_eval = true;
+        agent_system.config_mut().eval_allowed_origins = vec!["https://example.invalid".to_string()];
+        let err = agent_system.run_task(task, "key", "url", "model").await.expect_err("EVAL_JS should be refused on other origins");
+        assert!(err.to_string().contains("is not allowed on origin"), "Unexpected error: {}", err);
+
+        // Enabled and allowed on the current origin.
+        let origin = web_sys::window().unwrap().location().origin().unwrap();
+        agent_system.config_mut().eval_allowed_origins = vec![format!("{}/", origin)];
+        let result = agent_system.run_task(task, "key", "url", "model").await.expect("EVAL_JS should run");
+        assert!(result.ends_with("EVAL_JS result: 42"), "Unexpected result: {}", result);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_origin_allowlist_policy() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let link = document.create_element("a").unwrap();
+        link.set_id("external-link");
+        link.set_attribute("href", "https://elsewhere.invalid/offer").unwrap();
+        link.set_inner_html("<span id='external-link-label'>Offer</span>");
+        document.body().unwrap().append_child(&link).unwrap();
+        let origin = web_sys::window().unwrap().location().origin().unwrap();
+
+        let mut agent_system = AgentSystem::new();
+        agent_system.config_mut().allowed_origins = vec!["https://example.invalid".to_string()];
+        let err = agent_system.run_task("GET_URL", "key", "url", "model").await.expect_err("Commands should be refused on other origins");
+        assert!(err.to_string().contains("are not allowed on origin"), "Unexpected error: {}", err);
+
+        agent_system.config_mut().allowed_origins = vec![origin];
+        assert!(agent_system.run_task("GET_URL", "key", "url", "model").await.is_ok());
+        let err = agent_system.run_task("NAVIGATE https://elsewhere.invalid/", "key", "url", "model").await.expect_err("NAVIGATE should be refused");
+        assert!(matches!(err, AgentError::PolicyViolation(_)), "Unexpected error: {}", err);
+        let err = agent_system.run_task("CLICK css:#external-link-label", "key", "url", "model").await.expect_err("Clicking an external link should be refused");
+        assert!(err.to_string().contains("elsewhere.invalid"), "Unexpected error: {}", err);
+
+        // Same-origin navigation (here, to a fragment) is allowed.
+        let result = agent_system.run_task("NAVIGATE #allowlist-test", "key", "url", "model").await.expect("NAVIGATE should run");
+        assert!(result.contains("#allowlist-test"), "Unexpected result: {}", result);
+        assert_eq!(web_sys::window().unwrap().location().hash().unwrap(), "#allowlist-test");
+
+        let err = agent_system.run_task("NAVIGATE javascript:alert(1)", "key", "url", "model").await.expect_err("javascript: URLs should be refused");
+        assert!(matches!(err, AgentError::PolicyViolation(_)), "Unexpected error: {}", err);
+
+        web_sys::window().unwrap().location().set_hash("").unwrap();
+        link.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_secrets_are_typed_but_not_echoed() {
+        use wasm_bindgen::JsCast;
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let input = document.create_element("input").unwrap();
+        input.set_id("secret-password");
+        document.body().unwrap().append_child(&input).unwrap();
+
+        let mut agent_system = AgentSystem::new();
+        agent_system.config_mut().secrets.set("password", "hunter2");
+        let result = agent_system.run_task("TYPE css:#secret-password {{secret.password}}", "key", "url", "model").await.unwrap();
+        assert_eq!(input.dyn_ref::<web_sys::HtmlInputElement>().unwrap().value(), "hunter2");
+        assert!(!result.contains("hunter2"), "The secret should be masked: {}", result);
+        assert!(result.contains("{{secret.password}}"), "Unexpected result: {}", result);
+
+        let result = agent_system.run_task("GETVALUE css:#secret-password", "key", "url", "model").await.unwrap();
+        assert!(!result.contains("hunter2"), "Values read back should be masked too: {}", result);
+
+        let err = agent_system.run_task("TYPE css:#secret-password {{secret.pin}}", "key", "url", "model").await.unwrap_err();
+        assert!(err.to_string().contains("Unknown secret 'pin'"), "Unexpected error: {}", err);
+        let err = agent_system.run_task("SETATTRIBUTE css:#secret-password title {{secret.password}}", "key", "url", "model").await.unwrap_err();
+        assert!(matches!(err, AgentError::PolicyViolation(_)), "Unexpected error: {}", err);
+        let err = agent_system.run_task("LOGIN css:#secret-password css:#secret-password css:body css:body | ann hunter2", "key", "url", "model").await.unwrap_err();
+        assert!(matches!(err, AgentError::PolicyViolation(_)), "LOGIN passwords must be secrets: {}", err);
+        input.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_request_user_input_stores_reply_for_later_commands() {
+        use wasm_bindgen::JsCast;
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let input = document.create_element("input").unwrap();
+        input.set_id("otp-code");
+        document.body().unwrap().append_child(&input).unwrap();
+
+        let mut agent_system = AgentSystem::new();
+        let err = agent_system.run_task("REQUEST_USER_INPUT otp Code", "key", "url", "model").await.unwrap_err();
+        assert!(matches!(err, AgentError::UserInputUnavailable(_)), "A handler is required: {}", err);
+
+        // Answers asynchronously, as a host showing a dialog would; an empty reply cancels.
+        agent_system.config_mut().user_input_handler = Some(js_sys::Function::new_with_args(
+            "prompt, name",
+            "return name === 'otp' ? Promise.resolve('123456') : '';",
+        ));
+        let result = agent_system.run_task("REQUEST_USER_INPUT otp Enter the code we texted you", "key", "url", "model").await.unwrap();
+        assert!(!result.contains("123456"), "The reply should not be echoed: {}", result);
+        agent_system.run_task("TYPE css:#otp-code {{var.otp}}", "key", "url", "model").await.unwrap();
+        assert_eq!(input.dyn_ref::<web_sys::HtmlInputElement>().unwrap().value(), "123456");
+
+        let err = agent_system.run_task("REQUEST_USER_INPUT pin", "key", "url", "model").await.unwrap_err();
+        assert!(matches!(err, AgentError::UserInputUnavailable(_)), "Unexpected error: {}", err);
+        let err = agent_system.run_task("TYPE css:#otp-code {{var.pin}}", "key", "url", "model").await.unwrap_err();
+        assert!(err.to_string().contains("Unknown variable 'pin'"), "Unexpected error: {}", err);
+        input.remove();
     }
 
     // Use wasm_bindgen_test for async tests
@@ -1258,9 +5388,10 @@ mod tests {
         assert_eq!(agent_system.agents[2].role, AgentRole::Generic, "Agent 3 should be Generic.");
     }
 
+    #[cfg(not(feature = "dom-only"))]
     #[test]
     fn test_generate_structured_llm_prompt_includes_new_commands() {
-        let prompt = generate_structured_llm_prompt(1, &AgentRole::Generic, "test task", &AVAILABLE_DOM_COMMANDS);
+        let prompt = generate_structured_llm_prompt(1, &AgentRole::Generic, "test task", &AVAILABLE_DOM_COMMANDS, "");
 
         // Check for GET_URL
         assert!(prompt.contains("\"action\": \"GET_URL\""));
@@ -1281,6 +5412,9 @@ mod tests {
         // Check for SCROLL_TO
         assert!(prompt.contains("\"action\": \"SCROLL_TO\""));
         assert!(prompt.contains("- Scroll To: {{\"action\": \"SCROLL_TO\", \"selector\": \"<selector>\"}} (scrolls the page to make the element visible)"));
+
+        // Check for GET_ELEMENT_ATTRIBUTES
+        assert!(prompt.contains("\"action\": \"GET_ELEMENT_ATTRIBUTES\""));
     }
 
     #[wasm_bindgen_test]
@@ -1324,8 +5458,27 @@ mod tests {
     }
 
 
+    #[cfg(feature = "dom-only")]
+    #[wasm_bindgen_test]
+    async fn test_dom_only_build_runs_commands_without_llm() {
+        let agent_system = AgentSystem::new();
+        let result = agent_system.run_task("GET_URL", "", "", "").await.expect("DOM commands run without the LLM");
+        assert!(result.contains("Agent 1 (Navigator)") && result.contains("Current URL is"), "{}", result);
+
+        let result = agent_system.run_task("summarize this page", "", "", "").await;
+        match result {
+            Err(AgentError::CommandParseError(message)) => {
+                assert!(message.contains("Agent 3 (Generic)") && message.contains("dom-only"), "{}", message)
+            }
+            other => panic!("Expected a CommandParseError, got {:?}", other),
+        }
+    }
+
+    #[cfg(not(feature = "dom-only"))]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_fallback_agent_selection() {
+        #[cfg(feature = "mock-llm")]
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let dummy_api_key = "test_api_key_llm_will_fail_network";
         let dummy_api_url = "http://localhost:12345/nonexistent_endpoint"; // Ensure network call fails
@@ -1377,6 +5530,7 @@ mod tests {
         }
     }
 
+    #[cfg(not(feature = "dom-only"))]
     #[wasm_bindgen_test]
     async fn test_new_agent_selection_logic() {
         let agent_system = AgentSystem::new();
@@ -1390,7 +5544,7 @@ mod tests {
         #[cfg(feature = "mock-llm")] {
             assert!(result_nav.unwrap().contains("Agent 1 (Navigator) completed task via LLM"));
         } #[cfg(not(feature = "mock-llm"))] {
-            assert_agent_error_variant(result_nav, AgentError::LlmCallFailed("NetworkError".to_string()));
+            assert_agent_error_variant(result_nav, AgentError::LlmNetworkError("NetworkError".to_string()));
         }
 
 
@@ -1400,7 +5554,7 @@ mod tests {
          #[cfg(feature = "mock-llm")] {
             assert!(result_form.unwrap().contains("Agent 2 (FormFiller) completed task via LLM"));
         } #[cfg(not(feature = "mock-llm"))] {
-            assert_agent_error_variant(result_form, AgentError::LlmCallFailed("NetworkError".to_string()));
+            assert_agent_error_variant(result_form, AgentError::LlmNetworkError("NetworkError".to_string()));
         }
 
         // Scenario 3: Generic task (no keywords)
@@ -1409,7 +5563,7 @@ mod tests {
         #[cfg(feature = "mock-llm")] {
             assert!(result_generic.unwrap().contains("Agent 3 (Generic) completed task via LLM"));
         } #[cfg(not(feature = "mock-llm"))] {
-            assert_agent_error_variant(result_generic, AgentError::LlmCallFailed("NetworkError".to_string()));
+            assert_agent_error_variant(result_generic, AgentError::LlmNetworkError("NetworkError".to_string()));
         }
 
         // Scenario 4: Keyword Tie (Navigator & FormFiller, same priority)
@@ -1419,7 +5573,7 @@ mod tests {
         #[cfg(feature = "mock-llm")] {
             assert!(result_tie.unwrap().contains("Agent 1 (Navigator) completed task via LLM"));
         } #[cfg(not(feature = "mock-llm"))] {
-            assert_agent_error_variant(result_tie, AgentError::LlmCallFailed("NetworkError".to_string()));
+            assert_agent_error_variant(result_tie, AgentError::LlmNetworkError("NetworkError".to_string()));
         }
 
         // Scenario 5: Direct DOM command with specific agent keywords
@@ -1446,10 +5600,45 @@ mod tests {
     }
 
 
+    #[cfg(feature = "mock-llm")]
+    #[wasm_bindgen_test]
+    async fn test_run_task_remembers_successful_commands() {
+        let mut agent_system = AgentSystem::new();
+        let endpoint = EmbeddingEndpoint { api_url: "dummy".to_string(), api_key: String::new(), model: "dummy".to_string() };
+        agent_system.config_mut().memory.set_endpoint(Some(endpoint.clone()));
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+
+        // CLICK css:#memory-orders, or "Recalled an example." once the prompt has examples.
+        crate::provider::set_loader(Some(js_sys::Function::new_no_args(
+            "return Promise.resolve((request) => request.prompt.includes('Examples of tasks on this site')
+                 ? 'Recalled an example.'
+                 : JSON.stringify([{ action: 'CLICK', selector: 'css:#memory-orders' }]));",
+        )));
+        let failed = agent_system.run_task("integ-memory open the orders page", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(failed.contains("failed"), "Unexpected result: {}", failed);
+        assert_eq!(agent_system.config().memory.count(), 0, "Failed commands are not remembered");
+
+        let el = dom_utils::setup_element(&document, "memory-orders", "button", None);
+        agent_system.run_task("integ-memory open the orders page", "dummy", "dummy", "dummy").await.unwrap();
+        assert_eq!(agent_system.config().memory.count(), 1);
+        let recalled = agent_system.run_task("integ-memory open the orders page please", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(recalled.ends_with("Recalled an example."), "Unexpected result: {}", recalled);
+
+        // Without an embedding (the mock fails without a model), the task runs without recalled examples.
+        agent_system.config_mut().memory.set_endpoint(Some(EmbeddingEndpoint { model: String::new(), ..endpoint }));
+        let unembedded = agent_system.run_task("integ-memory open the orders page please", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(!unembedded.contains("Recalled"), "Unexpected result: {}", unembedded);
+        crate::provider::set_loader(None);
+        agent_system.config_mut().memory.set_endpoint(None);
+        assert_eq!(agent_system.config().memory.count(), 0);
+        dom_utils::cleanup_element(el);
+    }
+
     // New tests for LLM JSON response handling (ensure 'mock-llm' feature is active for these)
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_single_valid_command() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "click the submit button"; // Triggers mock: [{"action": "CLICK", "selector": "css:#submitBtn"}]
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1473,6 +5662,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_multiple_valid_commands() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "login with testuser and click login"; // Triggers mock: [{"action": "TYPE", "selector": "css:#username", "value": "testuser"}, {"action": "CLICK", "selector": "css:#loginBtn"}]
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1497,6 +5687,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_invalid_json_string() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "task expected to return invalid json"; // Triggers mock: "This is not JSON."
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1508,6 +5699,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_malformed_json_string() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "task expected to return malformed json"; // Triggers mock: "{ \"action\": \"CLICK\", \"selector\": " // Malformed
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1518,6 +5710,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_object_not_array() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "task expected to return json object not array"; // Triggers mock: {"message": "This is a JSON object, not an array."}
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1529,6 +5722,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_array_malformed_command_object() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         // Triggers mock: [{"foo": "bar"}] - valid JSON array, but object inside is not LlmDomCommandRequest
         let task = "task expected to return json array of non-commands";
@@ -1550,6 +5744,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_array_mixed_valid_invalid_commands() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         // Triggers mock: [{"action": "CLICK", "selector": "css:#ok"}, {"action": "INVALID_ACTION", "selector": "css:#bad"}, {"action": "TYPE", "selector": "css:#missingValue"}] (missing value for TYPE)
         let task = "task with mixed valid and invalid commands"; 
@@ -1577,6 +5772,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_empty_array() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "task expected to return empty command array"; // Triggers mock: []
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1588,6 +5784,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_get_url() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "llm_get_url_task"; // Mock in llm.rs returns: [{"action": "GET_URL"}]
         let result = agent_system.run_task(task, "dummy_key", "dummy_url", "dummy_model").await;
@@ -1602,6 +5799,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_element_exists() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let (_window, document) = dom_utils::get_window_document().unwrap();
         let el = dom_utils::setup_element(&document, "llm-exists", "div", None);
@@ -1621,9 +5819,148 @@ mod tests {
         assert_eq!(results_false[0].as_ref().unwrap(), "Element 'css:#llm-nonexistent' exists: false");
     }
 
+    #[cfg(feature = "mock-llm")]
+    #[wasm_bindgen_test]
+    async fn test_run_task_llm_decide_and_assert() {
+        let agent_system = AgentSystem::new();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let el = dom_utils::setup_element(&document, "judge-status", "div", None);
+        el.set_text_content(Some("Order #12 is confirmed"));
+
+        // "Yes." to questions about the confirmed order, "no" to those about the cancelled one.
+        fixtures::add("integ-judge order confirmed", "Yes.").unwrap();
+        fixtures::add("integ-judge order cancelled", "no").unwrap();
+        let decided = agent_system.run_task("LLM_DECIDE css:#judge-status Is the integ-judge order confirmed?", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(decided.ends_with("LLM decision on 'Is the integ-judge order confirmed?': true"), "Unexpected result: {}", decided);
+        let decided = agent_system.run_task("llm_decide css:#judge-status Is the integ-judge order cancelled?", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(decided.ends_with(": false"), "Unexpected result: {}", decided);
+
+        assert!(agent_system.run_task("LLM_ASSERT css:#judge-status Is the integ-judge order confirmed?", "dummy", "dummy", "dummy").await.is_ok());
+        let failed = agent_system.run_task("LLM_ASSERT css:#judge-status Is the integ-judge order cancelled?", "dummy", "dummy", "dummy").await;
+        assert!(matches!(failed, Err(AgentError::AssertionFailed(_))), "Unexpected result: {:?}", failed);
+        let unclear = agent_system.run_task("LLM_DECIDE css:#judge-status Is the shop open?", "dummy", "dummy", "dummy").await;
+        assert!(matches!(unclear, Err(AgentError::InvalidLlmResponse(_))), "Unexpected result: {:?}", unclear);
+        let missing = agent_system.run_task("LLM_DECIDE css:#judge-missing Is the integ-judge order confirmed?", "dummy", "dummy", "dummy").await;
+        assert!(matches!(missing, Err(AgentError::DomOperationFailed(_))));
+        dom_utils::cleanup_element(el);
+    }
+
+    #[cfg(feature = "mock-llm")]
+    #[wasm_bindgen_test]
+    async fn test_run_task_disambiguates_matches() {
+        let mut agent_system = AgentSystem::new();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let on_click = "this.setAttribute('data-chosen', 'yes')";
+        let plans: Vec<_> = ["Basic", "Pro", "Team"]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let el = dom_utils::setup_element(&document, &format!("pick-plan-{}", index), "button", Some(vec![("class", "pick-plan"), ("onclick", on_click)]));
+                el.set_text_content(Some(name));
+                el
+            })
+            .collect();
+
+        // Disabled: the first match is clicked.
+        agent_system.run_task("CLICK css:.pick-plan", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(plans[0].has_attribute("data-chosen"));
+        plans[0].remove_attribute("data-chosen").unwrap();
+
+        // The LLM answers the number of the match whose text is "Pro", and nothing useful once none is.
+        fixtures::add("integ-pick unclear", "Whichever you like.").unwrap();
+        fixtures::add("1: button#pick-plan-1.pick-plan \"Pro\"", "1").unwrap();
+        agent_system.config_mut().disambiguate_matches = true;
+        let clicked = agent_system.run_task("CLICK css:.pick-plan", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(clicked.ends_with("(picked match 2 of 3 for 'css:.pick-plan': button#pick-plan-1.pick-plan \"Pro\")"), "Unexpected result: {}", clicked);
+        assert!(!clicked.contains("data-rustagent-picked"), "The pinned selector leaked: {}", clicked);
+        assert_eq!(plans.iter().map(|el| el.has_attribute("data-chosen")).collect::<Vec<_>>(), [false, true, false]);
+        assert_eq!(document.query_selector("[data-rustagent-picked]").unwrap(), None, "The mark is removed");
+
+        // A single match is acted on without asking.
+        let read = agent_system.run_task("READ css:#pick-plan-2", "dummy", "dummy", "dummy").await.unwrap();
+        assert!(!read.contains("picked match"), "Unexpected result: {}", read);
+
+        plans[1].set_text_content(Some("integ-pick unclear"));
+        let unclear = agent_system.run_task("CLICK css:.pick-plan", "dummy", "dummy", "dummy").await;
+        assert!(matches!(unclear, Err(AgentError::InvalidLlmResponse(_))), "Unexpected result: {:?}", unclear);
+        for el in plans {
+            dom_utils::cleanup_element(el);
+        }
+    }
+
+    #[cfg(feature = "mock-llm")]
+    #[wasm_bindgen_test]
+    async fn test_guardrails_require_confirmation() {
+        let mut agent_system = AgentSystem::new();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let input = dom_utils::setup_element(&document, "guard-email", "input", None);
+        let value = |input: &web_sys::Element| input.clone().dyn_into::<web_sys::HtmlInputElement>().unwrap().value();
+
+        // TYPE "<your email>" into #guard-email, then click #guard-missing, which the TYPE may create.
+        let plan = r#"[{"action": "TYPE", "selector": "css:#guard-email", "value": "<your email>"}, {"action": "CLICK", "selector": "css:#guard-missing"}]"#;
+        fixtures::add("integ-guardrail", plan).unwrap();
+        agent_system.config_mut().guardrails = true;
+        let rejected = agent_system.run_task("Fill in the integ-guardrail form", "dummy", "dummy", "dummy").await;
+        let Err(AgentError::PlanValidationFailed(report)) = rejected else { panic!("Unexpected result: {:?}", rejected) };
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["confirmed"], false);
+        assert_eq!(report["warnings"].as_array().unwrap().len(), 1, "Unexpected report: {}", report);
+        assert_eq!(report["warnings"][0]["kind"], "placeholder_value");
+        assert_eq!(report["warnings"][0]["index"], 0);
+        assert_eq!(value(&input), "", "Nothing runs before the plan is confirmed");
+
+        let declines = js_sys::Function::new_with_args("report", "return Promise.resolve(false);");
+        agent_system.config_mut().confirmation_handler = Some(declines);
+        assert!(matches!(agent_system.run_task("Fill in the integ-guardrail form", "dummy", "dummy", "dummy").await, Err(AgentError::PlanValidationFailed(_))));
+
+        let confirms = js_sys::Function::new_with_args("report", "return JSON.parse(report).warnings[0].kind === 'placeholder_value';");
+        agent_system.config_mut().confirmation_handler = Some(confirms);
+        agent_system.run_task("Fill in the integ-guardrail form", "dummy", "dummy", "dummy").await.unwrap();
+        assert_eq!(value(&input), "<your email>");
+        dom_utils::cleanup_element(input);
+    }
+
+    #[cfg(feature = "mock-llm")]
+    #[wasm_bindgen_test]
+    async fn test_command_reasons_in_results_and_events() {
+        let agent_system = AgentSystem::new();
+        let (window, document) = dom_utils::get_window_document().unwrap();
+        let button = dom_utils::setup_element(&document, "reason-open", "button", None);
+        let events = js_sys::Array::new();
+        let listener = wasm_bindgen::closure::Closure::wrap(Box::new({
+            let events = events.clone();
+            move |event: web_sys::CustomEvent| {
+                events.push(&event.detail());
+            }
+        }) as Box<dyn FnMut(web_sys::CustomEvent)>);
+        window.add_event_listener_with_callback(LLM_COMMAND_EVENT, listener.as_ref().unchecked_ref()).unwrap();
+
+        // Click #reason-open and #reason-missing with reasons, then #reason-open without one.
+        let plan = r#"[{"action": "CLICK", "selector": "css:#reason-open", "reason": " opens the login form "}, {"action": "CLICK", "selector": "css:#reason-missing", "reason": "closes the cookie banner"}, {"action": "CLICK", "selector": "css:#reason-open"}]"#;
+        fixtures::add("integ-reason", plan).unwrap();
+        let result = agent_system.run_task("Log in integ-reason", "dummy", "dummy", "dummy").await.unwrap();
+        window.remove_event_listener_with_callback(LLM_COMMAND_EVENT, listener.as_ref().unchecked_ref()).unwrap();
+        let results: Vec<Result<String, String>> = serde_json::from_str(&result).unwrap();
+        assert!(results[0].as_ref().unwrap().ends_with(" (reason: opens the login form)"), "{:?}", results[0]);
+        assert!(results[1].as_ref().unwrap_err().contains("Reason: \"closes the cookie banner\""), "{:?}", results[1]);
+        assert!(!results[2].as_ref().unwrap().contains("reason"), "{:?}", results[2]);
+
+        let events: Vec<serde_json::Value> = events
+            .iter()
+            .map(|detail| serde_json::from_str(&js_sys::JSON::stringify(&detail).unwrap().as_string().unwrap()).unwrap())
+            .collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], serde_json::json!({"index": 0, "action": "Click", "selector": "css:#reason-open", "reason": "opens the login form", "success": true}));
+        assert_eq!(events[1]["reason"], "closes the cookie banner");
+        assert_eq!(events[1]["success"], false);
+        assert_eq!(events[2]["reason"], serde_json::Value::Null);
+        dom_utils::cleanup_element(button);
+    }
+
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_wait_for_element() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let (_window, document) = dom_utils::get_window_document().unwrap();
         
@@ -1646,6 +5983,7 @@ mod tests {
     #[cfg(feature = "mock-llm")]
     #[wasm_bindgen_test]
     async fn test_run_task_llm_json_mixed_validity_commands() {
+        add_mock_fixtures();
         let agent_system = AgentSystem::new();
         let task = "task with mixed valid and malformed json commands";
         // Mock response: [{"action": "CLICK", "selector": "css:#valid"}, {"invalid_field": "some_value", "action": "EXTRA_INVALID_FIELD"}, {"action": "TYPE", "selector": "css:#anotherValid", "value": "test"}]
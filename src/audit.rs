@@ -0,0 +1,269 @@
+use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use crate::agent::{ExecutionConfig, LlmEndpoint};
+use crate::benchmark::CHARS_PER_TOKEN;
+use crate::dom_utils::DomError;
+use crate::llm;
+use crate::llm_error::LlmError;
+use crate::redact::{RedactionConfig, RedactionMap};
+use crate::storage::Storage;
+
+/// Number of LLM calls kept in the audit log until `set_llm_audit_limit` is called.
+pub const DEFAULT_AUDIT_LIMIT: usize = 200;
+
+/// Storage key the audit log is persisted under, as a JSON array of calls, oldest first.
+pub const AUDIT_KEY: &str = "llm_audit";
+
+/// Logged instead of a prompt or response that could not be redacted.
+const UNREDACTABLE: &str = "[not logged: redaction failed]";
+
+/// An LLM call, as listed by `get_llm_audit_log`. The prompt and response are redacted with the
+/// agent's PII redaction settings; API keys are never logged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LlmExchange {
+    /// The id of the run the call was made in, as in the run history; `None` outside runs.
+    pub run_id: Option<String>,
+    /// Why the call was made: `plan`, `disambiguation` or `judgment`.
+    pub purpose: String,
+    pub model: String,
+    pub api_url: String,
+    pub prompt: String,
+    /// The response, unless the call failed.
+    pub response: Option<String>,
+    /// Why the call failed, if it did.
+    pub error: Option<String>,
+    pub started_at_ms: f64,
+    pub finished_at_ms: f64,
+    /// Estimated from the characters sent, `CHARS_PER_TOKEN` per token, as in benchmarks.
+    pub prompt_tokens: u64,
+    /// Estimated from the characters received; 0 if the call failed.
+    pub response_tokens: u64,
+}
+
+/// Tokens estimated for `text`.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.len() as u64).div_ceil(CHARS_PER_TOKEN)
+}
+
+#[derive(Debug)]
+struct AuditState {
+    // Oldest first.
+    exchanges: VecDeque<LlmExchange>,
+    limit: usize,
+    // Set once persistence is enabled; the log is written to it after every call.
+    storage: Option<Storage>,
+}
+
+/// The bounded log of the prompts and responses of an agent's LLM calls. Clones share the same log,
+/// so that calls of scheduled and triggered runs are logged alongside the caller's own.
+#[derive(Debug, Clone)]
+pub struct LlmAuditLog {
+    state: Rc<RefCell<AuditState>>,
+    // The run being executed with this copy of the log; not shared by clones, so that scheduled
+    // runs tag their own calls.
+    run_id: RefCell<Option<String>>,
+}
+
+impl Default for LlmAuditLog {
+    fn default() -> Self {
+        LlmAuditLog {
+            state: Rc::new(RefCell::new(AuditState {
+                exchanges: VecDeque::new(),
+                limit: DEFAULT_AUDIT_LIMIT,
+                storage: None,
+            })),
+            run_id: RefCell::new(None),
+        }
+    }
+}
+
+impl LlmAuditLog {
+    /// Marks the start of the run with id `run_id`: calls logged from now on belong to it.
+    pub fn begin_run(&self, run_id: &str) {
+        *self.run_id.borrow_mut() = Some(run_id.to_string());
+    }
+
+    /// The id of the run calls are logged for, if any.
+    pub fn run_id(&self) -> Option<String> {
+        self.run_id.borrow().clone()
+    }
+
+    /// Adds a call, dropping the oldest calls beyond the limit.
+    pub fn record(&self, exchange: LlmExchange) {
+        let mut state = self.state.borrow_mut();
+        state.exchanges.push_back(exchange);
+        trim(&mut state);
+        persist(&state);
+    }
+
+    /// The logged calls, oldest first, only those of the run with id `run_id` if given.
+    pub fn exchanges(&self, run_id: Option<&str>) -> Vec<LlmExchange> {
+        let state = self.state.borrow();
+        state
+            .exchanges
+            .iter()
+            .filter(|exchange| run_id.is_none_or(|run_id| exchange.run_id.as_deref() == Some(run_id)))
+            .cloned()
+            .collect()
+    }
+
+    /// Forgets every call, including persisted ones.
+    pub fn clear(&self) {
+        let mut state = self.state.borrow_mut();
+        state.exchanges.clear();
+        persist(&state);
+    }
+
+    /// Sets how many calls are kept (0 stops logging), dropping the oldest calls beyond it.
+    pub fn set_limit(&self, limit: usize) {
+        let mut state = self.state.borrow_mut();
+        state.limit = limit;
+        if trim(&mut state) {
+            persist(&state);
+        }
+    }
+
+    /// Keeps the log in `storage`, alongside the run history. Calls persisted by earlier sessions
+    /// are loaded and come before the calls of this session.
+    pub async fn enable_persistence(&self, storage: Storage) -> Result<(), DomError> {
+        let stored: Vec<LlmExchange> = match storage.get(AUDIT_KEY).await? {
+            Some(json) => serde_json::from_str(&json).map_err(|e| DomError::SerializationError {
+                message: format!("Stored LLM audit log is malformed: {}", e),
+            })?,
+            None => Vec::new(),
+        };
+
+        let mut state = self.state.borrow_mut();
+        // Calls already in memory, e.g. loaded when persistence was enabled before, are not repeated.
+        let stored: Vec<LlmExchange> = stored.into_iter().filter(|exchange| !state.exchanges.contains(exchange)).collect();
+        for exchange in stored.into_iter().rev() {
+            state.exchanges.push_front(exchange);
+        }
+        trim(&mut state);
+        state.storage = Some(storage);
+        persist(&state);
+        Ok(())
+    }
+}
+
+// Drops the oldest calls beyond the limit; returns whether any were dropped.
+fn trim(state: &mut AuditState) -> bool {
+    let excess = state.exchanges.len().saturating_sub(state.limit);
+    state.exchanges.drain(..excess);
+    excess > 0
+}
+
+// Writes the log to the storage, if persistence is enabled. Writes are queued in order and
+// failures are logged.
+fn persist(state: &AuditState) {
+    let Some(storage) = state.storage.clone() else {
+        return;
+    };
+    let json = match serde_json::to_string(&state.exchanges) {
+        Ok(json) => json,
+        Err(e) => return web_sys::console::warn_1(&format!("Failed to serialize the LLM audit log: {}", e).into()),
+    };
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(e) = storage.set(AUDIT_KEY, &json).await {
+            web_sys::console::warn_1(&format!("Failed to persist the LLM audit log: {}", e).into());
+        }
+    });
+}
+
+// Redacts `text` for the log. Prompts are redacted before they are sent already, if redaction is
+// enabled; this also covers responses, which may repeat page content.
+fn redact(redaction: &RedactionConfig, text: &str, map: &mut RedactionMap) -> String {
+    if !redaction.is_enabled() {
+        return text.to_string();
+    }
+    redaction.redact(text, map).unwrap_or_else(|e| {
+        web_sys::console::warn_1(&format!("Failed to redact an LLM call for the audit log: {}", e).into());
+        UNREDACTABLE.to_string()
+    })
+}
+
+/// Calls the LLM like `llm::complete` and logs the call in the agent's audit log, made for
+/// `purpose` (see `LlmExchange::purpose`).
+pub async fn complete(purpose: &str, prompt: String, llm: LlmEndpoint<'_>, config: &ExecutionConfig) -> Result<String, LlmError> {
+    let started_at_ms = js_sys::Date::now();
+    let mut redactions = RedactionMap::default();
+    let logged_prompt = redact(&config.redaction, &prompt, &mut redactions);
+    let prompt_tokens = estimate_tokens(&prompt);
+    let result = llm::complete(prompt, llm.api_key.to_string(), llm.api_url.to_string(), llm.model_name.to_string()).await;
+    let (response, error) = match &result {
+        Ok(response) => (Some(redact(&config.redaction, response, &mut redactions)), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    config.llm_audit.record(LlmExchange {
+        run_id: config.llm_audit.run_id(),
+        purpose: purpose.to_string(),
+        model: llm.model_name.to_string(),
+        api_url: llm.api_url.to_string(),
+        prompt: logged_prompt,
+        response_tokens: result.as_deref().map(estimate_tokens).unwrap_or(0),
+        response,
+        error,
+        started_at_ms,
+        finished_at_ms: js_sys::Date::now(),
+        prompt_tokens,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exchange(run_id: Option<&str>, prompt: &str) -> LlmExchange {
+        LlmExchange {
+            run_id: run_id.map(str::to_string),
+            purpose: "plan".to_string(),
+            model: "gpt-4o-mini".to_string(),
+            api_url: "https://llm.example".to_string(),
+            prompt: prompt.to_string(),
+            response: Some("[]".to_string()),
+            error: None,
+            started_at_ms: 1.0,
+            finished_at_ms: 2.0,
+            prompt_tokens: estimate_tokens(prompt),
+            response_tokens: 1,
+        }
+    }
+
+    #[test]
+    fn test_audit_log_is_bounded_and_filtered_by_run() {
+        let log = LlmAuditLog::default();
+        log.set_limit(2);
+        log.record(exchange(Some("run-1"), "first"));
+        log.record(exchange(Some("run-2"), "second"));
+        log.record(exchange(None, "third"));
+
+        let prompts = |exchanges: Vec<LlmExchange>| exchanges.into_iter().map(|exchange| exchange.prompt).collect::<Vec<_>>();
+        assert_eq!(prompts(log.exchanges(None)), vec!["second", "third"]);
+        assert_eq!(prompts(log.exchanges(Some("run-2"))), vec!["second"]);
+        assert!(log.exchanges(Some("run-1")).is_empty());
+
+        let scheduled = log.clone();
+        scheduled.begin_run("run-3");
+        assert_eq!((scheduled.run_id().as_deref(), log.run_id()), (Some("run-3"), None), "Clones log for their own runs");
+        scheduled.record(exchange(Some("run-3"), "fourth"));
+        assert_eq!(prompts(log.exchanges(None)), vec!["third", "fourth"], "Clones share the log");
+
+        log.set_limit(0);
+        log.record(exchange(None, "fifth"));
+        assert!(log.exchanges(None).is_empty());
+        log.set_limit(1);
+        log.record(exchange(None, "sixth"));
+        log.clear();
+        assert!(log.exchanges(None).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+}
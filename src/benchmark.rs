@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use crate::budget::BudgetUsage;
+use crate::result_code::TaskResult;
+
+/// Characters per token assumed when estimating token usage from the characters sent and received.
+pub const CHARS_PER_TOKEN: u64 = 4;
+
+/// A model to benchmark, e.g. `{"name": "small", "model": "gpt-4o-mini"}`. The API URL and key
+/// default to those set with `set_llm_config`, so that models of another provider can be compared
+/// by giving both.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchmarkModel {
+    /// The name of the model in the report; the model name if not given.
+    #[serde(default)]
+    pub name: Option<String>,
+    pub model: String,
+    #[serde(default)]
+    pub api_url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+impl BenchmarkModel {
+    /// The name of the model in the report.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.model)
+    }
+}
+
+/// How one model did on the benchmarked tasks.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ModelReport {
+    pub name: String,
+    pub model: String,
+    /// The id of the model's run in the run history.
+    pub run_id: String,
+    pub tasks: usize,
+    pub succeeded: usize,
+    /// `succeeded / tasks`, from 0 to 1.
+    pub success_rate: f64,
+    pub total_ms: f64,
+    pub mean_task_ms: f64,
+    pub llm_calls: u32,
+    /// Characters sent to and received from the LLM.
+    pub llm_chars: u64,
+    /// `llm_chars / CHARS_PER_TOKEN`, rounded up.
+    pub estimated_tokens: u64,
+    pub results: Vec<TaskResult>,
+}
+
+/// Parses the models given to `RustAgent::benchmark`: a non-empty JSON array of `BenchmarkModel`s
+/// with distinct names.
+pub fn parse_models(models_json: &str) -> Result<Vec<BenchmarkModel>, String> {
+    let models: Vec<BenchmarkModel> = serde_json::from_str(models_json)
+        .map_err(|e| format!("Invalid models JSON: {}. Expected an array of {{\"name\", \"model\", \"api_url\", \"api_key\"}} objects", e))?;
+    if models.is_empty() {
+        return Err("No models to benchmark".to_string());
+    }
+    for (index, model) in models.iter().enumerate() {
+        if model.model.trim().is_empty() {
+            return Err(format!("Model {} has no model name", index));
+        }
+        if models[..index].iter().any(|earlier| earlier.label() == model.label()) {
+            return Err(format!("Two models are named '{}'", model.label()));
+        }
+    }
+    Ok(models)
+}
+
+/// Summarizes the run of `model` with id `run_id`: its `results`, how long it took and the LLM
+/// usage between the readings of `budget::total_usage` taken before and after it.
+pub fn summarize(
+    model: &BenchmarkModel,
+    run_id: &str,
+    results: Vec<TaskResult>,
+    total_ms: f64,
+    usage_before: &BudgetUsage,
+    usage_after: &BudgetUsage,
+) -> ModelReport {
+    let tasks = results.len();
+    let succeeded = results.iter().filter(|result| result.is_ok()).count();
+    let llm_chars = usage_after.chars.saturating_sub(usage_before.chars);
+    ModelReport {
+        name: model.label().to_string(),
+        model: model.model.clone(),
+        run_id: run_id.to_string(),
+        tasks,
+        succeeded,
+        success_rate: if tasks == 0 { 0.0 } else { succeeded as f64 / tasks as f64 },
+        total_ms,
+        mean_task_ms: if tasks == 0 { 0.0 } else { total_ms / tasks as f64 },
+        llm_calls: usage_after.calls.saturating_sub(usage_before.calls),
+        llm_chars,
+        estimated_tokens: llm_chars.div_ceil(CHARS_PER_TOKEN),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_models() {
+        let models = parse_models(r#"[{"model": "gpt-4o-mini"}, {"name": "local", "model": "llama3", "api_url": "http://localhost:11434/v1/chat/completions"}]"#).unwrap();
+        assert_eq!(models[0].label(), "gpt-4o-mini");
+        assert_eq!(models[0].api_url, None);
+        assert_eq!(models[1].label(), "local");
+        assert_eq!(models[1].api_key, None);
+
+        assert!(parse_models("[]").is_err());
+        assert!(parse_models(r#"[{"name": "no model"}]"#).is_err());
+        assert!(parse_models(r#"[{"model": " "}]"#).is_err());
+        let duplicate = parse_models(r#"[{"model": "gpt-4o"}, {"name": "gpt-4o", "model": "gpt-4o-2024-08-06"}]"#).unwrap_err();
+        assert!(duplicate.contains("'gpt-4o'"), "{}", duplicate);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let model = BenchmarkModel { name: None, model: "gpt-4o-mini".to_string(), api_url: None, api_key: None };
+        let results = vec![
+            TaskResult::ok("Clicked".to_string(), "CLICK_OK".to_string()),
+            TaskResult::err(crate::LibError::Workflow { message: "no element".to_string() }),
+            TaskResult::ok("Typed".to_string(), "TYPE_OK".to_string()),
+            TaskResult::ok("Read".to_string(), "READ_OK".to_string()),
+        ];
+        let before = BudgetUsage { calls: 3, chars: 1000 };
+        let after = BudgetUsage { calls: 7, chars: 1801 };
+        let report = summarize(&model, "run-1-0", results, 2000.0, &before, &after);
+        assert_eq!(report.name, "gpt-4o-mini");
+        assert_eq!((report.tasks, report.succeeded), (4, 3));
+        assert_eq!(report.success_rate, 0.75);
+        assert_eq!(report.mean_task_ms, 500.0);
+        assert_eq!((report.llm_calls, report.llm_chars, report.estimated_tokens), (4, 801, 201));
+
+        let empty = summarize(&model, "run-1-1", Vec::new(), 0.0, &after, &after);
+        assert_eq!((empty.success_rate, empty.mean_task_ms, empty.estimated_tokens), (0.0, 0.0, 0));
+    }
+}
@@ -0,0 +1,226 @@
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+use crate::dom_utils::{self, DomError};
+use crate::pacing::Pacing;
+
+/// Typing delays below this, in milliseconds per character, are faster than people type.
+const MIN_HUMAN_TYPING_DELAY_MS: u32 = 30;
+/// Command caps above this are faster than people click through pages.
+const MAX_HUMAN_ACTIONS_PER_MINUTE: u32 = 60;
+
+/// One signal bot-detection scripts commonly look at, and whether this page shows it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BotSignal {
+    /// A short identifier, e.g. `webdriver`.
+    pub name: String,
+    /// `true` if the signal points toward automation.
+    pub detected: bool,
+    /// What was observed, e.g. "navigator.webdriver is true".
+    pub detail: String,
+    /// What can be done about it, if anything.
+    pub advice: String,
+}
+
+/// The result of `check_bot_signals`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BotSignalReport {
+    /// The names of the detected signals.
+    pub detected: Vec<String>,
+    pub signals: Vec<BotSignal>,
+}
+
+/// What the browser exposes about itself, read from `navigator` and `window`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrowserProfile {
+    pub webdriver: bool,
+    pub user_agent: String,
+    pub plugin_count: u32,
+    pub language_count: u32,
+    pub outer_width: f64,
+    pub outer_height: f64,
+}
+
+fn signal(name: &str, detected: bool, detail: String, advice: &str) -> BotSignal {
+    BotSignal { name: name.to_string(), detected, detail, advice: advice.to_string() }
+}
+
+// Reads `object[key]` as a number, or `0` if it is missing or not a number.
+fn number_property(object: &JsValue, key: &str) -> f64 {
+    js_sys::Reflect::get(object, &JsValue::from_str(key)).ok().and_then(|value| value.as_f64()).unwrap_or(0.0)
+}
+
+/// Reads the browser's profile from the current page.
+///
+/// # Returns
+/// * `Ok(BrowserProfile)` with the values found; missing properties read as `false`, `""` or `0`.
+/// * `Err(DomError)` if there is no window.
+pub fn browser_profile() -> Result<BrowserProfile, DomError> {
+    let (window, _document) = dom_utils::get_window_document()?;
+    let navigator = js_sys::Reflect::get(&window, &JsValue::from_str("navigator"))?;
+    let property = |key: &str| js_sys::Reflect::get(&navigator, &JsValue::from_str(key)).unwrap_or(JsValue::UNDEFINED);
+    Ok(BrowserProfile {
+        webdriver: property("webdriver").is_truthy(),
+        user_agent: property("userAgent").as_string().unwrap_or_default(),
+        plugin_count: number_property(&property("plugins"), "length") as u32,
+        language_count: number_property(&property("languages"), "length") as u32,
+        outer_width: number_property(&window, "outerWidth"),
+        outer_height: number_property(&window, "outerHeight"),
+    })
+}
+
+/// The signals the browser itself gives away. These come from how the browser was started, so
+/// they cannot be changed from the page.
+pub fn browser_signals(profile: &BrowserProfile) -> Vec<BotSignal> {
+    let headless = profile.user_agent.contains("Headless");
+    vec![
+        signal(
+            "webdriver",
+            profile.webdriver,
+            format!("navigator.webdriver is {}", profile.webdriver),
+            "The browser is controlled through WebDriver or a similar protocol. Run the agent in a browser started normally.",
+        ),
+        signal(
+            "headless_user_agent",
+            headless,
+            if headless { format!("The user agent names a headless browser: {}", profile.user_agent) } else { "The user agent does not name a headless browser".to_string() },
+            "Run the browser with a window, or with a user agent that does not say it is headless.",
+        ),
+        signal(
+            "no_plugins",
+            profile.plugin_count == 0,
+            format!("navigator.plugins lists {} plugins", profile.plugin_count),
+            "Desktop browsers usually list their built-in PDF viewer; an empty list is typical of headless browsers.",
+        ),
+        signal(
+            "no_languages",
+            profile.language_count == 0,
+            format!("navigator.languages lists {} languages", profile.language_count),
+            "Configure the browser with at least one preferred language.",
+        ),
+        signal(
+            "no_window_size",
+            profile.outer_width == 0.0 || profile.outer_height == 0.0,
+            format!("The window's outer size is {}x{}", profile.outer_width, profile.outer_height),
+            "A window without a size is typical of headless browsers. Run the browser with a visible window.",
+        ),
+        signal(
+            "synthetic_events",
+            true,
+            "Clicks and key presses are dispatched by the page, so their events have isTrusted set to false".to_string(),
+            "This cannot be changed from inside the page. Sites that only accept trusted events need input from the user or the browser itself.",
+        ),
+    ]
+}
+
+/// The signals the agent's own timing gives away: commands run back to back, at a perfectly
+/// regular rate or faster than a person could, and text entered without per-character key events.
+///
+/// # Arguments
+/// * `pacing`: The pacing of commands (see `RustAgent::set_pacing`).
+/// * `typing_delay_ms`: The delay between typed characters, or `None` if values are set at once
+///   (see `RustAgent::set_human_typing`).
+pub fn timing_signals(pacing: &Pacing, typing_delay_ms: Option<u32>) -> Vec<BotSignal> {
+    let (delay_detected, delay_detail) = if pacing.max_delay_ms == 0 {
+        (true, "Commands run back to back, with no delay between them".to_string())
+    } else if pacing.min_delay_ms == pacing.max_delay_ms {
+        (true, format!("Commands are always {}ms apart, a perfectly regular rhythm", pacing.min_delay_ms))
+    } else {
+        (false, format!("Commands are {} to {}ms apart", pacing.min_delay_ms, pacing.max_delay_ms))
+    };
+    let (rate_detected, rate_detail) = match pacing.max_actions_per_minute {
+        None => (true, "The number of commands per minute is not capped".to_string()),
+        Some(cap) if cap > MAX_HUMAN_ACTIONS_PER_MINUTE => (true, format!("Up to {} commands run per minute", cap)),
+        Some(cap) => (false, format!("At most {} commands run per minute", cap)),
+    };
+    let (typing_detected, typing_detail) = match typing_delay_ms {
+        None => (true, "Values are set at once, without key events for each character".to_string()),
+        Some(delay_ms) if delay_ms < MIN_HUMAN_TYPING_DELAY_MS => (true, format!("Characters are typed {}ms apart, faster than people type", delay_ms)),
+        Some(delay_ms) => (false, format!("Characters are typed {}ms apart", delay_ms)),
+    };
+    vec![
+        signal(
+            "command_delay",
+            delay_detected,
+            delay_detail,
+            "Call set_pacing with different minimum and maximum delays, e.g. 300 and 1500, so commands are spaced irregularly.",
+        ),
+        signal(
+            "command_rate",
+            rate_detected,
+            rate_detail,
+            "Pass set_pacing a cap on commands per minute, e.g. 30.",
+        ),
+        signal(
+            "typing",
+            typing_detected,
+            typing_detail,
+            "Call set_human_typing with a delay of 80ms or more, so text is typed one character at a time.",
+        ),
+    ]
+}
+
+/// Reports the signals that may make a site take the agent for a bot: what the browser gives
+/// away about itself, and what the agent's pacing and typing give away. The report is advisory;
+/// sites combine many more signals, and no check can tell whether a given site will block the agent.
+///
+/// # Arguments
+/// * `pacing`: The pacing of commands.
+/// * `typing_delay_ms`: The delay between typed characters, or `None` if values are set at once.
+///
+/// # Returns
+/// * `Ok(BotSignalReport)` with every signal checked and the names of those detected.
+/// * `Err(DomError)` if there is no window.
+pub fn check_bot_signals(pacing: &Pacing, typing_delay_ms: Option<u32>) -> Result<BotSignalReport, DomError> {
+    let mut signals = browser_signals(&browser_profile()?);
+    signals.extend(timing_signals(pacing, typing_delay_ms));
+    let detected = signals.iter().filter(|signal| signal.detected).map(|signal| signal.name.clone()).collect();
+    Ok(BotSignalReport { detected, signals })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detected(signals: &[BotSignal]) -> Vec<&str> {
+        signals.iter().filter(|signal| signal.detected).map(|signal| signal.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_browser_signals() {
+        let profile = BrowserProfile {
+            webdriver: true,
+            user_agent: "Mozilla/5.0 (X11; Linux x86_64) HeadlessChrome/120.0.0.0 Safari/537.36".to_string(),
+            plugin_count: 0,
+            language_count: 1,
+            outer_width: 0.0,
+            outer_height: 0.0,
+        };
+        assert_eq!(
+            detected(&browser_signals(&profile)),
+            ["webdriver", "headless_user_agent", "no_plugins", "no_window_size", "synthetic_events"]
+        );
+
+        let profile = BrowserProfile {
+            webdriver: false,
+            user_agent: "Mozilla/5.0 (X11; Linux x86_64) Chrome/120.0.0.0 Safari/537.36".to_string(),
+            plugin_count: 5,
+            outer_width: 1280.0,
+            outer_height: 800.0,
+            ..profile
+        };
+        assert_eq!(detected(&browser_signals(&profile)), ["synthetic_events"]);
+    }
+
+    #[test]
+    fn test_timing_signals() {
+        let signals = timing_signals(&Pacing::default(), None);
+        assert_eq!(detected(&signals), ["command_delay", "command_rate", "typing"]);
+        assert!(signals[0].detail.contains("back to back"));
+
+        let signals = timing_signals(&Pacing::new(500, 500, Some(120)), Some(10));
+        assert_eq!(detected(&signals), ["command_delay", "command_rate", "typing"]);
+        assert!(signals[0].detail.contains("always 500ms apart"), "Unexpected detail: {}", signals[0].detail);
+
+        assert!(detected(&timing_signals(&Pacing::new(300, 1500, Some(30)), Some(80))).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use gloo_timers::future::TimeoutFuture;
+use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+/// Length of the sliding window `max_calls_per_minute` is counted over, in milliseconds.
+pub const RATE_WINDOW_MS: f64 = 60_000.0;
+
+thread_local! {
+    // The budget shared by every `RustAgent` on the page. Wasm runs the page's agents on a single
+    // thread, so this is global to the page (each worker has its own); calls take and release the
+    // borrow without awaiting in between, so concurrent runs cannot both take the last call.
+    static SHARED_BUDGET: RefCell<Option<SharedBudget>> = const { RefCell::new(None) };
+    // Every LLM call of the page's agents, whether or not a budget is set.
+    static TOTAL_USAGE: RefCell<BudgetUsage> = const { RefCell::new(BudgetUsage { calls: 0, chars: 0 }) };
+}
+
+/// Limits on the LLM calls of every `RustAgent` on the page, set with `RustAgent.set_shared_budget`.
+/// Every limit is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BudgetLimits {
+    /// Calls started in any 60-second window; further calls wait until one leaves the window.
+    pub max_calls_per_minute: Option<u32>,
+    /// Calls in total; further calls fail.
+    pub max_calls: Option<u32>,
+    /// Characters sent and received in total (prompts plus responses), a stand-in for tokens and
+    /// therefore cost; a call whose prompt would exceed it fails.
+    pub max_chars: Option<u64>,
+}
+
+/// What has been spent from the shared budget.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct BudgetUsage {
+    pub calls: u32,
+    pub chars: u64,
+}
+
+#[derive(Debug, Default)]
+struct SharedBudget {
+    limits: BudgetLimits,
+    usage: BudgetUsage,
+    // Start times of the calls in the current rate window, oldest first.
+    recent_calls: VecDeque<f64>,
+}
+
+impl SharedBudget {
+    // Takes a call sending `prompt_chars` from the budget, or returns how long to wait, in
+    // milliseconds, before a call fits in the rate window. Fails if the budget is spent.
+    fn try_acquire(&mut self, prompt_chars: u64, now_ms: f64) -> Result<Option<f64>, String> {
+        if let Some(max_calls) = self.limits.max_calls {
+            if self.usage.calls >= max_calls {
+                return Err(format!("Shared LLM budget exhausted: all {} calls used", max_calls));
+            }
+        }
+        if let Some(max_chars) = self.limits.max_chars {
+            if self.usage.chars + prompt_chars > max_chars {
+                return Err(format!(
+                    "Shared LLM budget exhausted: {} of {} characters used, the prompt needs {}",
+                    self.usage.chars, max_chars, prompt_chars
+                ));
+            }
+        }
+        while self.recent_calls.front().is_some_and(|started_at_ms| now_ms - started_at_ms >= RATE_WINDOW_MS) {
+            self.recent_calls.pop_front();
+        }
+        if let Some(max_calls_per_minute) = self.limits.max_calls_per_minute {
+            if self.recent_calls.len() >= max_calls_per_minute as usize {
+                let oldest_ms = self.recent_calls.front().copied().unwrap_or(now_ms);
+                return Ok(Some(oldest_ms + RATE_WINDOW_MS - now_ms));
+            }
+        }
+        self.usage.calls += 1;
+        self.usage.chars += prompt_chars;
+        self.recent_calls.push_back(now_ms);
+        Ok(None)
+    }
+}
+
+/// Sets the limits of the shared budget, or removes it (`None`). Usage so far is kept when the
+/// limits change, and forgotten when the budget is removed.
+pub fn set_limits(limits: Option<BudgetLimits>) {
+    SHARED_BUDGET.with(|budget| {
+        let mut budget = budget.borrow_mut();
+        match limits {
+            Some(limits) => budget.get_or_insert_with(SharedBudget::default).limits = limits,
+            None => *budget = None,
+        }
+    });
+}
+
+/// Usage of the shared budget, or `None` if there is none.
+pub fn usage() -> Option<BudgetUsage> {
+    SHARED_BUDGET.with(|budget| budget.borrow().as_ref().map(|budget| budget.usage.clone()))
+}
+
+/// Every LLM call made by the page's agents so far, whether or not a budget is set. Never reset,
+/// so that the calls of a period are the difference between two readings.
+pub fn total_usage() -> BudgetUsage {
+    TOTAL_USAGE.with(|usage| usage.borrow().clone())
+}
+
+/// Forgets the usage of the shared budget, e.g. at the start of a billing period.
+pub fn reset_usage() {
+    SHARED_BUDGET.with(|budget| {
+        if let Some(budget) = budget.borrow_mut().as_mut() {
+            budget.usage = BudgetUsage::default();
+            budget.recent_calls.clear();
+        }
+    });
+}
+
+/// Takes an LLM call sending `prompt_chars` characters from the shared budget, waiting for the
+/// rate limit if needed. Succeeds at once when no budget is set.
+pub async fn acquire(prompt_chars: usize) -> Result<(), String> {
+    loop {
+        let wait_ms = SHARED_BUDGET.with(|budget| match budget.borrow_mut().as_mut() {
+            Some(budget) => budget.try_acquire(prompt_chars as u64, js_sys::Date::now()),
+            None => Ok(None),
+        })?;
+        match wait_ms {
+            None => {
+                TOTAL_USAGE.with(|usage| {
+                    let mut usage = usage.borrow_mut();
+                    usage.calls += 1;
+                    usage.chars += prompt_chars as u64;
+                });
+                return Ok(());
+            }
+            // Another agent may take the freed slot first, so the budget is checked again.
+            Some(wait_ms) => TimeoutFuture::new(wait_ms.ceil() as u32).await,
+        }
+    }
+}
+
+/// Charges the characters of an LLM response to the shared budget and the total usage.
+pub fn record_response(response_chars: usize) {
+    TOTAL_USAGE.with(|usage| usage.borrow_mut().chars += response_chars as u64);
+    SHARED_BUDGET.with(|budget| {
+        if let Some(budget) = budget.borrow_mut().as_mut() {
+            budget.usage.chars += response_chars as u64;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_acquire_enforces_limits() {
+        let mut budget = SharedBudget {
+            limits: BudgetLimits { max_calls_per_minute: Some(2), max_calls: Some(3), max_chars: Some(100) },
+            ..Default::default()
+        };
+        assert_eq!(budget.try_acquire(10, 0.0), Ok(None));
+        assert_eq!(budget.try_acquire(10, 1_000.0), Ok(None));
+        assert_eq!(budget.try_acquire(10, 30_000.0), Ok(Some(30_000.0)), "Waits for the first call to leave the window");
+        assert_eq!(budget.usage, BudgetUsage { calls: 2, chars: 20 });
+
+        assert!(budget.try_acquire(90, 60_000.0).unwrap_err().contains("characters"));
+        assert_eq!(budget.try_acquire(10, 60_000.0), Ok(None));
+        assert!(budget.try_acquire(10, 200_000.0).unwrap_err().contains("all 3 calls used"));
+    }
+}
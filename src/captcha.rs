@@ -0,0 +1,182 @@
+use gloo_timers::future::TimeoutFuture;
+use serde::Serialize;
+use std::cell::Cell;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{console, Document, Element};
+use crate::dom_utils::{self, DomError};
+
+/// Name of the `CustomEvent` dispatched on `window` when a run pauses for a captcha. Its `detail`
+/// is the `Captcha` that was found.
+pub const CAPTCHA_DETECTED_EVENT: &str = "captcha_detected";
+/// Default time a run waits for a captcha to be solved.
+pub const DEFAULT_CAPTCHA_TIMEOUT_MS: u32 = 300_000;
+/// Polling interval used while waiting for a captcha to be solved.
+const POLL_INTERVAL_MS: u32 = 250;
+
+/// The captcha widgets looked for: the provider, the selector of its visible widgets (iframes
+/// and the containers pages render them into; invisible variants are left out, since they
+/// never ask the user anything) and the selector of the field that receives the token once
+/// the captcha is solved.
+const PROVIDERS: [(&str, &str, &str); 3] = [
+    (
+        "reCAPTCHA",
+        "iframe[src*='/recaptcha/']:not([src*='size=invisible']), .g-recaptcha:not([data-size='invisible'])",
+        "[name='g-recaptcha-response']",
+    ),
+    ("hCaptcha", "iframe[src*='hcaptcha.com']:not([src*='size=invisible']), .h-captcha:not([data-size='invisible'])", "[name='h-captcha-response']"),
+    ("Turnstile", "iframe[src*='challenges.cloudflare.com'], .cf-turnstile", "[name='cf-turnstile-response']"),
+];
+
+thread_local! {
+    // Set by `mark_solved` when the host reports that the user solved the captcha.
+    static SOLVED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A captcha shown on the page.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Captcha {
+    /// `reCAPTCHA`, `hCaptcha` or `Turnstile`.
+    pub provider: String,
+    /// A short description of the widget (tag, id and first class), e.g. `div.h-captcha`.
+    pub element: String,
+}
+
+/// How `wait_while_captcha_shown` ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CaptchaWait {
+    /// No captcha was shown.
+    NoCaptcha,
+    /// A captcha was shown and has been solved, or the host reported it solved.
+    Solved(Captcha),
+    /// A captcha was shown and was still unsolved when the wait ended.
+    TimedOut(Captcha),
+}
+
+// Whether a field matching `response_selector` holds a token, i.e. its captcha was solved.
+fn has_token(document: &Document, response_selector: &str) -> Result<bool, DomError> {
+    let fields = document.query_selector_all(response_selector)?;
+    Ok((0..fields.length()).filter_map(|index| fields.get(index)).any(|field| {
+        js_sys::Reflect::get(&field, &JsValue::from_str("value"))
+            .ok()
+            .and_then(|value| value.as_string())
+            .is_some_and(|value| !value.is_empty())
+    }))
+}
+
+/// Looks for a visible, unsolved captcha widget on the page.
+///
+/// # Returns
+/// * `Ok(Some(Captcha))` with the first captcha found.
+/// * `Ok(None)` if no captcha is shown, or every one shown has been solved.
+/// * `Err(DomError)` if the page cannot be queried.
+pub fn detect_captcha() -> Result<Option<Captcha>, DomError> {
+    let (window, document) = dom_utils::get_window_document()?;
+    for (provider, widget_selector, response_selector) in PROVIDERS {
+        if has_token(&document, response_selector)? {
+            continue;
+        }
+        let widgets = document.query_selector_all(widget_selector)?;
+        let widget = (0..widgets.length())
+            .filter_map(|index| widgets.get(index))
+            .filter_map(|node| node.dyn_into::<Element>().ok())
+            .find(|element| dom_utils::is_rendered(&window, element));
+        if let Some(widget) = widget {
+            return Ok(Some(Captcha { provider: provider.to_string(), element: dom_utils::describe_element(&widget) }));
+        }
+    }
+    Ok(None)
+}
+
+/// Records that the user solved the captcha a run is waiting for (see `wait_while_captcha_shown`).
+pub fn mark_solved() {
+    SOLVED.with(|solved| solved.set(true));
+}
+
+// Dispatches `CAPTCHA_DETECTED_EVENT` on `window`, with the captcha as `detail`.
+fn dispatch_detected_event(captcha: &Captcha) -> Result<(), DomError> {
+    let (window, _document) = dom_utils::get_window_document()?;
+    let json = serde_json::to_string(captcha).map_err(|e| DomError::SerializationError { message: e.to_string() })?;
+    let event_init = web_sys::CustomEventInit::new();
+    event_init.set_detail(&js_sys::JSON::parse(&json)?);
+    let event = web_sys::CustomEvent::new_with_event_init_dict(CAPTCHA_DETECTED_EVENT, &event_init)?;
+    window.dispatch_event(&event)?;
+    Ok(())
+}
+
+/// Pauses while a captcha is shown, so that a person can solve it.
+///
+/// If `detect_captcha` finds a captcha, `CAPTCHA_DETECTED_EVENT` is dispatched on `window` and the
+/// page is polled until the captcha is solved (its token field is filled or the widget goes
+/// away) or the host calls `mark_solved`, for at most `timeout_ms`.
+///
+/// # Returns
+/// * `Ok(CaptchaWait)` telling whether a captcha was shown and whether it was solved in time.
+/// * `Err(DomError)` if the page cannot be queried or the event cannot be dispatched.
+pub async fn wait_while_captcha_shown(timeout_ms: u32) -> Result<CaptchaWait, DomError> {
+    let Some(captcha) = detect_captcha()? else { return Ok(CaptchaWait::NoCaptcha) };
+    SOLVED.with(|solved| solved.set(false));
+    console::warn_1(&format!("{} captcha detected ({}); pausing until it is solved", captcha.provider, captcha.element).into());
+    dispatch_detected_event(&captcha)?;
+
+    let mut waited = 0;
+    while waited < timeout_ms {
+        TimeoutFuture::new(POLL_INTERVAL_MS).await;
+        waited += POLL_INTERVAL_MS;
+        if SOLVED.with(Cell::get) || detect_captcha()?.is_none() {
+            console::log_1(&format!("{} captcha solved; resuming", captcha.provider).into());
+            return Ok(CaptchaWait::Solved(captcha));
+        }
+    }
+    Ok(CaptchaWait::TimedOut(captcha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // Renders an hCaptcha container with its (empty) token field.
+    fn setup_hcaptcha(document: &Document) -> Element {
+        let widget = document.create_element("div").unwrap();
+        widget.set_class_name("h-captcha");
+        widget.set_attribute("style", "width: 300px; height: 80px").unwrap();
+        widget.set_inner_html("<textarea name='h-captcha-response' style='display: none'></textarea>");
+        document.body().unwrap().append_child(&widget).unwrap();
+        widget
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_wait_while_captcha_shown() {
+        let (window, document) = dom_utils::get_window_document().unwrap();
+        assert_eq!(wait_while_captcha_shown(1000).await.unwrap(), CaptchaWait::NoCaptcha);
+
+        // The "user" solves the captcha once the event is received, which fills the token field.
+        let widget = setup_hcaptcha(&document);
+        let listener = js_sys::Function::new_with_args(
+            "event",
+            "window.captchaProvider = event.detail.provider; \
+             setTimeout(() => { document.querySelector('[name=h-captcha-response]').value = 'token'; }, 100);",
+        );
+        window.add_event_listener_with_callback(CAPTCHA_DETECTED_EVENT, &listener).unwrap();
+        let captcha = Captcha { provider: "hCaptcha".to_string(), element: "div.h-captcha".to_string() };
+        assert_eq!(wait_while_captcha_shown(5000).await.unwrap(), CaptchaWait::Solved(captcha.clone()));
+        let provider = js_sys::Reflect::get(&window, &JsValue::from_str("captchaProvider")).unwrap();
+        assert_eq!(provider.as_string().as_deref(), Some("hCaptcha"));
+        window.remove_event_listener_with_callback(CAPTCHA_DETECTED_EVENT, &listener).unwrap();
+        widget.remove();
+
+        // Without a token, the host reports the captcha solved, or the wait times out.
+        let widget = setup_hcaptcha(&document);
+        let (outcome, _) = join(wait_while_captcha_shown(5000), async {
+            TimeoutFuture::new(100).await;
+            mark_solved();
+        })
+        .await;
+        assert_eq!(outcome.unwrap(), CaptchaWait::Solved(captcha.clone()));
+        assert_eq!(wait_while_captcha_shown(500).await.unwrap(), CaptchaWait::TimedOut(captcha));
+        widget.remove();
+    }
+}
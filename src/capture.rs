@@ -0,0 +1,240 @@
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{console, Element, HtmlCanvasElement, HtmlImageElement, CanvasRenderingContext2d};
+use crate::dom_utils::{self, DomError};
+use crate::storage::Storage;
+
+/// Largest width or height, in CSS pixels, `capture_element` renders.
+pub const MAX_CAPTURE_SIZE: f64 = 4096.0;
+/// Largest difference in any color channel for two pixels to still count as equal, which absorbs
+/// anti-aliasing and color rounding differences between renderings.
+pub const PIXEL_TOLERANCE: u8 = 32;
+
+// Copies the computed style of `original` onto `clone` as an inline style, so the clone renders
+// the same without the page's stylesheets.
+fn inline_computed_style(window: &web_sys::Window, original: &Element, clone: &Element) -> Result<(), DomError> {
+    let Some(style) = window.get_computed_style(original)? else { return Ok(()) };
+    let mut css_text = String::new();
+    for i in 0..style.length() {
+        let name = style.item(i);
+        let value = style.get_property_value(&name)?;
+        css_text.push_str(&format!("{}:{};", name, value));
+    }
+    clone.set_attribute("style", &css_text)?;
+    // Form controls show their current value, which the clone only keeps as an attribute.
+    if let Some(input) = original.dyn_ref::<web_sys::HtmlInputElement>() {
+        clone.set_attribute("value", &input.value())?;
+    }
+    Ok(())
+}
+
+// Loads `src` into a new image and waits until it is decoded.
+async fn load_image(src: &str) -> Result<HtmlImageElement, DomError> {
+    let image = HtmlImageElement::new()?;
+    let loading_image = image.clone();
+    let loaded = js_sys::Promise::new(&mut |resolve, reject| {
+        loading_image.set_onload(Some(&resolve));
+        loading_image.set_onerror(Some(&reject));
+    });
+    image.set_src(src);
+    JsFuture::from(loaded).await.map_err(|_| DomError::JsError {
+        message: "The browser could not render the element snapshot".to_string(),
+    })?;
+    Ok(image)
+}
+
+/// Takes a screenshot of an element and returns it as a PNG data URL (`data:image/png;base64,...`).
+///
+/// The element is cloned with its computed styles inlined, rendered through an SVG
+/// `<foreignObject>` onto a canvas at the device pixel ratio, and exported with `toDataURL`.
+/// No external library or browser extension API is involved, so the result is an approximation:
+/// external images, fonts not yet loaded, `<canvas>` content and cross-origin iframes are not rendered.
+///
+/// # Returns
+/// * `Ok(String)` with the PNG data URL.
+/// * `Err(DomError::ElementNotFound)` / `Err(DomError::InvalidSelector)` if the selector does not resolve.
+/// * `Err(DomError::JsError)` if the element has no size, is larger than `MAX_CAPTURE_SIZE`, or the
+///   browser refuses to export the canvas (e.g. Safari taints canvases drawn from SVG images).
+pub async fn capture_element(selector: &str) -> Result<String, DomError> {
+    let (window, document) = dom_utils::get_window_document()?;
+    let element = dom_utils::get_element(&document, selector)?;
+    let rect = element.get_bounding_client_rect();
+    let (width, height) = (rect.width().ceil(), rect.height().ceil());
+    if width <= 0.0 || height <= 0.0 {
+        return Err(DomError::JsError { message: format!("Element '{}' has no size to capture", selector) });
+    }
+    if width > MAX_CAPTURE_SIZE || height > MAX_CAPTURE_SIZE {
+        return Err(DomError::JsError {
+            message: format!("Element '{}' is {}x{}px, larger than the {}px capture limit", selector, width, height, MAX_CAPTURE_SIZE),
+        });
+    }
+
+    let clone: Element = element.clone_node_with_deep(true)?.unchecked_into();
+    inline_computed_style(&window, &element, &clone)?;
+    let (originals, clones) = (element.query_selector_all("*")?, clone.query_selector_all("*")?);
+    for i in 0..originals.length() {
+        if let (Some(original), Some(copy)) = (originals.item(i), clones.item(i)) {
+            inline_computed_style(&window, original.unchecked_ref(), copy.unchecked_ref())?;
+        }
+    }
+    // The snapshot starts at the element's border box.
+    let root_style = clone.get_attribute("style").unwrap_or_default();
+    clone.set_attribute("style", &format!("{}margin:0;", root_style))?;
+
+    let xhtml = web_sys::XmlSerializer::new()?.serialize_to_string(&clone)?;
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}"><foreignObject x="0" y="0" width="{w}" height="{h}">{xhtml}</foreignObject></svg>"#,
+        w = width,
+        h = height,
+        xhtml = xhtml
+    );
+    let svg_url = format!("data:image/svg+xml;charset=utf-8,{}", String::from(js_sys::encode_uri_component(&svg)));
+    let image = load_image(&svg_url).await?;
+
+    let scale = window.device_pixel_ratio().max(1.0);
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?.unchecked_into();
+    canvas.set_width((width * scale) as u32);
+    canvas.set_height((height * scale) as u32);
+    let context: CanvasRenderingContext2d = canvas
+        .get_context("2d")?
+        .ok_or_else(|| DomError::JsError { message: "Canvas 2D context is not available".to_string() })?
+        .unchecked_into();
+    context.scale(scale, scale)?;
+    context.draw_image_with_html_image_element(&image, 0.0, 0.0)?;
+    let data_url = canvas.to_data_url().map_err(|e| DomError::JsError {
+        message: format!("The browser refused to export the snapshot: {:?}", e.as_string()),
+    })?;
+
+    console::log_1(&format!("Captured element '{}' ({}x{}px, {} characters)", selector, width, height, data_url.len()).into());
+    Ok(data_url)
+}
+
+/// Fraction (between `0.0` and `1.0`) of the pixels that differ between two RGBA buffers of the
+/// same image size. A pixel differs if any channel differs by more than `PIXEL_TOLERANCE`.
+pub fn pixel_diff_ratio(a: &[u8], b: &[u8]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 1.0;
+    }
+    let differing = a
+        .chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .filter(|(pixel_a, pixel_b)| pixel_a.iter().zip(pixel_b.iter()).any(|(x, y)| x.abs_diff(*y) > PIXEL_TOLERANCE))
+        .count();
+    differing as f64 / (a.len() / 4) as f64
+}
+
+// Decodes a PNG data URL and returns its size and RGBA pixels.
+async fn decode_pixels(data_url: &str) -> Result<(u32, u32, Vec<u8>), DomError> {
+    let image = load_image(data_url).await?;
+    let (width, height) = (image.natural_width(), image.natural_height());
+    let (_window, document) = dom_utils::get_window_document()?;
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?.unchecked_into();
+    canvas.set_width(width);
+    canvas.set_height(height);
+    let context: CanvasRenderingContext2d = canvas
+        .get_context("2d")?
+        .ok_or_else(|| DomError::JsError { message: "Canvas 2D context is not available".to_string() })?
+        .unchecked_into();
+    context.draw_image_with_html_image_element(&image, 0.0, 0.0)?;
+    let pixels = context.get_image_data(0.0, 0.0, width as f64, height as f64)?.data().0;
+    Ok((width, height, pixels))
+}
+
+/// Captures an element and compares it with the baseline stored under `key`.
+///
+/// If there is no baseline yet, the capture is saved as the baseline.
+///
+/// # Returns
+/// * `Ok(None)` if the capture was recorded as the new baseline.
+/// * `Ok(Some(ratio))` with the fraction of differing pixels (see `pixel_diff_ratio`); `1.0` if
+///   the capture and the baseline differ in size.
+/// * `Err(DomError)` if the element cannot be captured or the storage fails.
+pub async fn compare_with_baseline(selector: &str, key: &str, storage: &Storage) -> Result<Option<f64>, DomError> {
+    let current = capture_element(selector).await?;
+    let Some(baseline) = storage.get(key).await? else {
+        storage.set(key, &current).await?;
+        console::log_1(&format!("Recorded baseline '{}' for element '{}'", key, selector).into());
+        return Ok(None);
+    };
+    let (current_width, current_height, current_pixels) = decode_pixels(&current).await?;
+    let (baseline_width, baseline_height, baseline_pixels) = decode_pixels(&baseline).await?;
+    if (current_width, current_height) != (baseline_width, baseline_height) {
+        console::log_1(&format!(
+            "Element '{}' is {}x{}px, baseline '{}' is {}x{}px",
+            selector, current_width, current_height, key, baseline_width, baseline_height
+        ).into());
+        return Ok(Some(1.0));
+    }
+    Ok(Some(pixel_diff_ratio(&current_pixels, &baseline_pixels)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_pixel_diff_ratio() {
+        let red = [200, 30, 30, 255];
+        let almost_red = [210, 25, 30, 255];
+        let blue = [30, 30, 200, 255];
+        let image: Vec<u8> = [red, red, red, red].concat();
+        assert_eq!(pixel_diff_ratio(&image, &image), 0.0);
+        assert_eq!(pixel_diff_ratio(&image, &[red, almost_red, red, red].concat()), 0.0, "Small differences are tolerated");
+        assert_eq!(pixel_diff_ratio(&image, &[red, blue, red, red].concat()), 0.25);
+        assert_eq!(pixel_diff_ratio(&image, &[red, red].concat()), 1.0, "Different sizes never match");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_compare_with_baseline_records_then_compares() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let panel = document.create_element("div").unwrap();
+        panel.set_id("baseline-panel");
+        panel.set_attribute("style", "width:60px;height:30px;background:rgb(30,30,200)").unwrap();
+        document.body().unwrap().append_child(&panel).unwrap();
+
+        // An in-memory store standing in for the host's storage.
+        let store = js_sys::Map::new();
+        let load_store = store.clone();
+        let load = Closure::wrap(Box::new(move |key: JsValue| load_store.get(&key)) as Box<dyn FnMut(JsValue) -> JsValue>);
+        let save_store = store.clone();
+        let save = Closure::wrap(Box::new(move |key: JsValue, value: JsValue| {
+            save_store.set(&key, &value);
+        }) as Box<dyn FnMut(JsValue, JsValue)>);
+        let storage = Storage::Custom { load: load.as_ref().clone().unchecked_into(), save: save.as_ref().clone().unchecked_into() };
+
+        assert_eq!(compare_with_baseline("css:#baseline-panel", "panel", &storage).await.unwrap(), None, "The first run records the baseline");
+        assert_eq!(store.size(), 1);
+        assert_eq!(compare_with_baseline("css:#baseline-panel", "panel", &storage).await.unwrap(), Some(0.0));
+
+        panel.set_attribute("style", "width:60px;height:30px;background:rgb(30,30,200);border-left:30px solid rgb(200,30,30)").unwrap();
+        let ratio = compare_with_baseline("css:#baseline-panel", "panel", &storage).await.unwrap().unwrap();
+        assert_eq!(ratio, 1.0, "A wider element does not match");
+        panel.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_capture_element_returns_png() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let badge = document.create_element("div").unwrap();
+        badge.set_id("capture-badge");
+        badge.set_attribute("style", "width:120px;height:40px;background:rgb(200,30,30);color:white").unwrap();
+        badge.set_text_content(Some("Sale"));
+        document.body().unwrap().append_child(&badge).unwrap();
+
+        let data_url = capture_element("css:#capture-badge").await.unwrap();
+        assert!(data_url.starts_with("data:image/png;base64,"), "Unexpected data URL: {}", &data_url[..40.min(data_url.len())]);
+
+        let image = load_image(&data_url).await.unwrap();
+        let scale = web_sys::window().unwrap().device_pixel_ratio().max(1.0);
+        assert_eq!(image.natural_width(), (120.0 * scale) as u32);
+
+        badge.set_attribute("style", "display:none").unwrap();
+        assert!(matches!(capture_element("css:#capture-badge").await, Err(DomError::JsError { .. })), "Hidden elements have no size");
+        assert!(matches!(capture_element("css:#no-such-element").await, Err(DomError::ElementNotFound { .. })));
+        badge.remove();
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+/// A prompt sent to the LLM and the response it gave.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Interaction {
+    /// The model that answered, for reference; replay does not depend on it.
+    #[serde(default)]
+    pub model: String,
+    pub prompt: String,
+    pub response: String,
+}
+
+/// Recorded LLM interactions, exported with `RustAgent::export_llm_cassette` and served again
+/// with `RustAgent::replay_llm_cassette`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Off,
+    Record,
+    Replay,
+}
+
+#[derive(Debug)]
+struct State {
+    mode: Mode,
+    cassette: Cassette,
+    // Whether each interaction was already served during the replay.
+    served: Vec<bool>,
+}
+
+thread_local! {
+    // Like the shared budget, the cassette applies to every agent of the page (each worker has its own).
+    static STATE: RefCell<State> = const { RefCell::new(State { mode: Mode::Off, cassette: Cassette { interactions: Vec::new() }, served: Vec::new() }) };
+}
+
+/// Starts recording the responses of the LLM into a new, empty cassette.
+pub fn start_recording() {
+    STATE.with(|state| {
+        *state.borrow_mut() = State { mode: Mode::Record, cassette: Cassette::default(), served: Vec::new() };
+    });
+}
+
+/// Starts serving the responses of `cassette` instead of calling the LLM.
+pub fn start_replay(cassette: Cassette) {
+    STATE.with(|state| {
+        let served = vec![false; cassette.interactions.len()];
+        *state.borrow_mut() = State { mode: Mode::Replay, cassette, served };
+    });
+}
+
+/// Stops recording or replaying. The cassette is kept, so a recording can still be exported.
+pub fn stop() {
+    STATE.with(|state| state.borrow_mut().mode = Mode::Off);
+}
+
+/// `"record"`, `"replay"` or `"off"`.
+pub fn mode() -> &'static str {
+    STATE.with(|state| match state.borrow().mode {
+        Mode::Off => "off",
+        Mode::Record => "record",
+        Mode::Replay => "replay",
+    })
+}
+
+/// The cassette being recorded, or the one last recorded or replayed.
+pub fn cassette() -> Cassette {
+    STATE.with(|state| state.borrow().cassette.clone())
+}
+
+/// Adds an interaction to the cassette while recording.
+pub fn record(model: &str, prompt: &str, response: &str) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if state.mode == Mode::Record {
+            state.cassette.interactions.push(Interaction { model: model.to_string(), prompt: prompt.to_string(), response: response.to_string() });
+        }
+    });
+}
+
+/// While replaying, the recorded response to `prompt`, or an error if none was recorded; `None`
+/// when not replaying.
+///
+/// Interactions with the same prompt are served in the order they were recorded, the last one
+/// again once they have all been served, so that repeated questions (e.g. of a `WHILE` loop) get
+/// the same sequence of answers. A prompt that was not recorded as such (because the page content
+/// it includes changed) is answered by the first interaction for the same task, `task_of` giving
+/// the task of a prompt.
+pub fn replay(prompt: &str, task_of: impl Fn(&str) -> Option<String>) -> Option<Result<String, String>> {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if state.mode != Mode::Replay {
+            return None;
+        }
+        let State { cassette, served, .. } = &mut *state;
+        let task = task_of(prompt);
+        let exact: Vec<usize> = (0..cassette.interactions.len()).filter(|&index| cassette.interactions[index].prompt == prompt).collect();
+        let candidates = if exact.is_empty() && task.is_some() {
+            (0..cassette.interactions.len()).filter(|&index| task_of(&cassette.interactions[index].prompt) == task).collect()
+        } else {
+            exact
+        };
+        let chosen = candidates.iter().copied().find(|&index| !served[index]).or_else(|| candidates.last().copied());
+        Some(match chosen {
+            Some(index) => {
+                served[index] = true;
+                Ok(cassette.interactions[index].response.clone())
+            }
+            None => Err(format!(
+                "No recorded LLM response for {} in the replayed cassette",
+                task.map(|task| format!("task '{}'", task)).unwrap_or_else(|| "this prompt".to_string())
+            )),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Prompts of these tests are "<task>|<page content>".
+    fn task_of(prompt: &str) -> Option<String> {
+        prompt.split_once('|').map(|(task, _)| task.to_string())
+    }
+
+    #[test]
+    fn test_record_only_while_recording() {
+        stop();
+        record("gpt-4o", "ignored|", "[]");
+        start_recording();
+        assert_eq!(mode(), "record");
+        record("gpt-4o", "open the cart|<main>", r#"[{"action":"CLICK","selector":"css:#cart"}]"#);
+        stop();
+        record("gpt-4o", "ignored|", "[]");
+        let cassette = cassette();
+        assert_eq!(cassette.interactions.len(), 1);
+        assert_eq!(cassette.interactions[0].prompt, "open the cart|<main>");
+
+        let exported = serde_json::to_string(&cassette).unwrap();
+        assert_eq!(serde_json::from_str::<Cassette>(&exported).unwrap(), cassette);
+    }
+
+    #[test]
+    fn test_replay_serves_recorded_responses() {
+        let interaction = |prompt: &str, response: &str| Interaction { model: String::new(), prompt: prompt.to_string(), response: response.to_string() };
+        start_replay(Cassette {
+            interactions: vec![
+                interaction("are there more items|page 1", "yes"),
+                interaction("are there more items|page 1", "no"),
+                interaction("open the cart|<main>", "[]"),
+            ],
+        });
+        assert_eq!(mode(), "replay");
+        assert_eq!(replay("are there more items|page 1", task_of), Some(Ok("yes".to_string())));
+        assert_eq!(replay("are there more items|page 1", task_of), Some(Ok("no".to_string())));
+        assert_eq!(replay("are there more items|page 1", task_of), Some(Ok("no".to_string())), "The last response is served again");
+        assert_eq!(replay("open the cart|<main class=\"changed\">", task_of), Some(Ok("[]".to_string())), "Matched by task");
+        let missing = replay("open the menu|<main>", task_of).unwrap().unwrap_err();
+        assert!(missing.contains("'open the menu'"), "{}", missing);
+
+        stop();
+        assert_eq!(replay("open the cart|<main>", task_of), None);
+    }
+}
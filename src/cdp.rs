@@ -0,0 +1,844 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::agent::{self, AgentError, DomCommand, DomCommandAction};
+use crate::dom_utils::{self, DomError, WaitConfig};
+use crate::workflow::{self, WorkflowDefinition, WorkflowRegistry};
+use crate::{goto, try_catch, while_loop, LibError};
+
+/// How long Chrome may take to start and report its DevTools endpoint.
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a DevTools call may go unanswered before the connection is considered lost.
+const PROTOCOL_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long `NAVIGATE` waits for the new page to load, unless the wait timeout is longer.
+const NAVIGATION_TIMEOUT_MS: u32 = 30_000;
+/// The line Chrome writes to stderr once its DevTools endpoint is ready, followed by the URL.
+const DEVTOOLS_LISTENING_PREFIX: &str = "DevTools listening on ";
+
+// Numbers the profile directories of the browsers launched by this process.
+static LAUNCHED_BROWSERS: AtomicU32 = AtomicU32::new(0);
+
+// Helpers shared by every script run in the page: resolving `spec` (built by `selector_spec`) like
+// `dom_utils::get_element` does in the page, and the errors `dom_error` turns into `DomError`s.
+// `text:` selectors match the folded text, `aria-label` or `value` of the innermost element, exactly
+// or failing that as a substring; unlike in the page, the locale dictionary's translations are not tried.
+const PRELUDE: &str = r#"
+const fail = (kind, fields) => { throw Object.assign({ kind, selector: selectorText }, fields); };
+const fold = (text) => String(text).normalize('NFC').toLowerCase().replace(/\s+/g, ' ').trim();
+const byText = (root, text) => {
+  const wanted = fold(text);
+  const elements = Array.from((root || document.body || document.documentElement).querySelectorAll('*'))
+    .filter((el) => !['SCRIPT', 'STYLE', 'NOSCRIPT', 'TEMPLATE'].includes(el.tagName));
+  const labels = (el) => [el.innerText, el.getAttribute('aria-label'), el.value]
+    .filter((label) => typeof label === 'string').map(fold);
+  let matches = elements.filter((el) => labels(el).includes(wanted));
+  if (!matches.length) matches = elements.filter((el) => labels(el).some((label) => label.includes(wanted)));
+  return matches.filter((el) => !matches.some((other) => other !== el && el.contains(other)));
+};
+const queryAll = (root, spec) => {
+  if (spec.scope) {
+    const region = queryAll(root, spec.scope[0])[0];
+    return region ? queryAll(region, spec.scope[1]) : [];
+  }
+  if (spec.chain) {
+    let firstError = null;
+    for (const candidate of spec.chain) {
+      try {
+        const found = queryAll(root, candidate);
+        if (found.length) return found;
+      } catch (e) {
+        firstError = firstError || e;
+      }
+    }
+    if (firstError) throw firstError;
+    return [];
+  }
+  if (spec.text !== undefined) return byText(root, spec.text);
+  if (spec.xpath !== undefined) {
+    const path = root && spec.xpath.startsWith('/') ? '.' + spec.xpath : spec.xpath;
+    let result;
+    try {
+      result = document.evaluate(path, root || document, null, XPathResult.ORDERED_NODE_SNAPSHOT_TYPE, null);
+    } catch (e) {
+      fail('invalid_selector', { error: String(e.message) });
+    }
+    const found = [];
+    for (let i = 0; i < result.snapshotLength; i++) found.push(result.snapshotItem(i));
+    return found.filter((node) => node instanceof Element);
+  }
+  try {
+    return Array.from((root || document).querySelectorAll(spec.css));
+  } catch (e) {
+    fail('invalid_selector', { error: String(e.message) });
+  }
+};
+const queryOne = () => queryAll(null, spec)[0] || fail('not_found', {});
+const expect = (el, type, name) => (el instanceof window[type] ? el : fail('element_type', { expected_type: name }));
+const describeError = (e) => (e && e.kind ? e : { kind: 'js', name: e && e.name, message: String(e && e.message !== undefined ? e.message : e) });
+"#;
+
+// Script bodies of the commands, run with `spec`, `selectorText`, `value` and `attribute` in scope
+// (see `page_script`). Each mirrors the `dom_utils` function the in-page command calls.
+const CLICK: &str = "expect(queryOne(), 'HTMLElement', 'HtmlElement').click(); return null;";
+const TYPE: &str = "expect(queryOne(), 'HTMLInputElement', 'HtmlInputElement').value = value.normalize('NFC'); return null;";
+const READ: &str = "return expect(queryOne(), 'HTMLElement', 'HtmlElement').innerText;";
+const GET_VALUE: &str = "return expect(queryOne(), 'HTMLInputElement', 'HtmlInputElement').value;";
+const GET_ATTRIBUTE: &str = "const found = queryOne().getAttribute(attribute); \
+    return found === null ? fail('attribute_not_found', { attribute_name: attribute }) : found;";
+const SET_ATTRIBUTE: &str = "queryOne().setAttribute(attribute, value); return null;";
+const SELECT_OPTION: &str = "expect(queryOne(), 'HTMLSelectElement', 'HtmlSelectElement').value = value; return null;";
+const GET_ALL_ATTRIBUTES: &str = "return JSON.stringify(queryAll(null, spec).map((el) => el.getAttribute(attribute)));";
+const GET_URL: &str = "return location.href;";
+const RESOLVE_URL: &str = "return new URL(value, location.href).href;";
+const READY_STATE: &str = "return document.readyState;";
+const ELEMENT_EXISTS: &str = "return queryAll(null, spec).length > 0;";
+const IS_VISIBLE: &str = "const el = queryOne(); const style = getComputedStyle(el); \
+    if (style.display === 'none' || style.visibility === 'hidden') return false; \
+    const rect = el.getBoundingClientRect(); return rect.width > 0 && rect.height > 0;";
+const SCROLL_TO: &str = "queryOne().scrollIntoView(); return null;";
+// Scrolls the element into view and returns the viewport coordinates of its center.
+const ELEMENT_CENTER: &str = "const el = expect(queryOne(), 'HTMLElement', 'HtmlElement'); \
+    el.scrollIntoView({ block: 'center', inline: 'center' }); const rect = el.getBoundingClientRect(); \
+    return { x: rect.left + rect.width / 2, y: rect.top + rect.height / 2 };";
+// Scrolls the element into view and returns its box in page coordinates, as screenshot clips use.
+const ELEMENT_BOX: &str = "const el = queryOne(); el.scrollIntoView({ block: 'center', inline: 'center' }); \
+    const rect = el.getBoundingClientRect(); \
+    return { x: rect.left + scrollX, y: rect.top + scrollY, width: rect.width, height: rect.height };";
+const GET_ALL_TEXT: &str = "return queryAll(null, spec).filter((el) => el instanceof HTMLElement) \
+    .map((el) => el.innerText).filter((text) => text).join(value);";
+const EVAL_JS: &str = "const json = JSON.stringify(new Function(value).call(null)); return json === undefined ? 'null' : json;";
+const GET_ELEMENT_ATTRIBUTES: &str = "const el = queryOne(); \
+    return JSON.stringify(Object.fromEntries(el.getAttributeNames().sort().map((name) => [name, el.getAttribute(name)])));";
+const GET_DATASET: &str = "const el = expect(queryOne(), 'HTMLElement', 'HtmlElement'); \
+    return JSON.stringify(Object.fromEntries(Object.keys(el.dataset).sort().map((key) => [key, el.dataset[key]])));";
+const IS_ENABLED: &str = "const el = queryOne(); return !(el.matches(':disabled') || el.closest(\"[aria-disabled='true']\"));";
+const IS_SELECTED: &str = "const el = queryOne(); return el.matches(':checked') \
+    || el.getAttribute('aria-selected') === 'true' || el.getAttribute('aria-checked') === 'true';";
+const MEDIA_QUERY_MATCHES: &str = "return matchMedia(value).matches;";
+
+/// Errors of the headless Chrome backend.
+#[derive(Debug)]
+pub enum CdpError {
+    /// Chrome could not be started, or did not report its DevTools endpoint in time.
+    Launch(String),
+    /// The DevTools connection failed, or Chrome rejected a protocol call.
+    Protocol(String),
+    /// A task failed, as it would have in the page (e.g. `ElementNotFound`).
+    Task(LibError),
+}
+
+impl fmt::Display for CdpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CdpError::Launch(s) => write!(f, "Browser Launch Failed: {}", s),
+            CdpError::Protocol(s) => write!(f, "DevTools Protocol Error: {}", s),
+            CdpError::Task(e) => write!(f, "Task Failed: {}", serde_json::to_string(e).unwrap_or_else(|_| format!("{:?}", e))),
+        }
+    }
+}
+
+impl Error for CdpError {}
+
+impl From<LibError> for CdpError {
+    fn from(err: LibError) -> Self {
+        CdpError::Task(err)
+    }
+}
+
+impl From<AgentError> for CdpError {
+    fn from(err: AgentError) -> Self {
+        CdpError::Task(LibError::from(err))
+    }
+}
+
+impl From<DomError> for CdpError {
+    fn from(err: DomError) -> Self {
+        CdpError::from(AgentError::from(err))
+    }
+}
+
+// A Chrome started by `Browser::launch`. Dropping it kills the browser and deletes its profile.
+struct ChromeProcess {
+    child: Child,
+    profile_dir: PathBuf,
+}
+
+impl Drop for ChromeProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.profile_dir);
+    }
+}
+
+/// Runs tasks and workflows written for the in-page agent in a page of headless Chrome, driven
+/// over the Chrome DevTools Protocol, so they can serve as standalone automation scripts.
+///
+/// Tasks must be direct DOM commands: there is no LLM to plan other tasks. The commands that read
+/// and act on elements (`CLICK`, `TYPE`, `READ`, `WAIT_FOR_ELEMENT`, ...), `NAVIGATE`, `GET_URL`,
+/// `HOVER`, `CAPTURE_ELEMENT` and `EVAL_JS` are supported, with the results they have in the page;
+/// the others fail with a `CommandParse` error. Task lists may use `{{PREVIOUS_RESULT}}`, but not
+/// the flow steps (`TRY`, `LABEL` / `GOTO`, `WHILE`).
+pub struct Browser {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    // The session attached to the page commands act on (calls without one go to the browser).
+    session_id: Option<String>,
+    next_call_id: u64,
+    // Set when the browser was started by `launch`.
+    process: Option<ChromeProcess>,
+    wait: WaitConfig,
+    allow_eval: bool,
+    workflows: HashMap<String, WorkflowDefinition>,
+}
+
+impl Browser {
+    /// Starts headless Chrome (or Chromium) with a fresh, temporary profile and attaches to its page.
+    /// The browser is closed when the returned `Browser` is dropped.
+    ///
+    /// # Arguments
+    /// * `chrome_path`: The Chrome executable, e.g. `/usr/bin/google-chrome`.
+    pub fn launch(chrome_path: impl AsRef<Path>) -> Result<Self, CdpError> {
+        let chrome_path = chrome_path.as_ref();
+        let profile_dir = std::env::temp_dir().join(format!(
+            "rustagent-cdp-{}-{}",
+            std::process::id(),
+            LAUNCHED_BROWSERS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut child = Command::new(chrome_path)
+            .args(["--headless=new", "--remote-debugging-port=0", "--no-first-run", "--no-default-browser-check"])
+            .arg(format!("--user-data-dir={}", profile_dir.display()))
+            .arg("about:blank")
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| CdpError::Launch(format!("Could not start '{}': {}", chrome_path.display(), e)))?;
+        let stderr = child.stderr.take();
+        let process = ChromeProcess { child, profile_dir };
+        let stderr = stderr.ok_or_else(|| CdpError::Launch("Chrome's stderr is not available".to_string()))?;
+
+        // Chrome keeps writing to stderr after reporting its endpoint; the rest is drained so that
+        // it never blocks on a full pipe.
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut sender = Some(sender);
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if let Some(url) = devtools_url(&line) {
+                    if let Some(sender) = sender.take() {
+                        let _ = sender.send(url.to_string());
+                    }
+                }
+            }
+        });
+        let ws_url = receiver.recv_timeout(LAUNCH_TIMEOUT).map_err(|_| {
+            CdpError::Launch(format!(
+                "'{}' did not report a DevTools endpoint within {}s",
+                chrome_path.display(),
+                LAUNCH_TIMEOUT.as_secs()
+            ))
+        })?;
+        let mut browser = Browser::connect(&ws_url)?;
+        browser.process = Some(process);
+        Ok(browser)
+    }
+
+    /// Attaches to the first page of a running browser (creating one if it has none), e.g. one
+    /// started with `--remote-debugging-port`.
+    ///
+    /// # Arguments
+    /// * `ws_url`: The browser's DevTools WebSocket URL (`webSocketDebuggerUrl` of `/json/version`).
+    pub fn connect(ws_url: &str) -> Result<Self, CdpError> {
+        let (socket, _response) = tungstenite::connect(ws_url)
+            .map_err(|e| CdpError::Protocol(format!("Could not connect to '{}': {}", ws_url, e)))?;
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream
+                .set_read_timeout(Some(PROTOCOL_TIMEOUT))
+                .map_err(|e| CdpError::Protocol(format!("Could not set the read timeout: {}", e)))?;
+        }
+        let mut browser = Browser {
+            socket,
+            session_id: None,
+            next_call_id: 0,
+            process: None,
+            wait: WaitConfig::default(),
+            allow_eval: false,
+            workflows: HashMap::new(),
+        };
+
+        let targets = browser.call("Target.getTargets", json!({}))?;
+        let page = targets["targetInfos"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|target| target["type"] == "page")
+            .and_then(|target| target["targetId"].as_str())
+            .map(str::to_string);
+        let target_id = match page {
+            Some(target_id) => target_id,
+            None => string_field(&browser.call("Target.createTarget", json!({ "url": "about:blank" }))?, "targetId")?,
+        };
+        let attached = browser.call("Target.attachToTarget", json!({ "targetId": target_id, "flatten": true }))?;
+        browser.session_id = Some(string_field(&attached, "sessionId")?);
+        Ok(browser)
+    }
+
+    /// Allows or refuses `EVAL_JS` commands (refused by default), like `allow_eval` in the page.
+    pub fn allow_eval(&mut self, allow: bool) {
+        self.allow_eval = allow;
+    }
+
+    /// Sets how long `WAIT_FOR_ELEMENT` waits when the command gives no timeout.
+    pub fn set_wait_timeout(&mut self, timeout_ms: u32) {
+        self.wait.timeout_ms = timeout_ms;
+    }
+
+    /// Registers a workflow (JSON or YAML, as for `register_workflow` in the page), replacing any
+    /// workflow registered under the same name.
+    pub fn register_workflow(&mut self, definition: &str) -> Result<(), CdpError> {
+        let definition = WorkflowDefinition::parse(definition).map_err(LibError::from)?;
+        self.workflows.insert(definition.name.clone(), definition);
+        Ok(())
+    }
+
+    /// Runs a workflow registered with `register_workflow`.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the workflow.
+    /// * `params_json`: A JSON object of parameter values, as for `run_workflow` in the page.
+    ///
+    /// # Returns
+    /// The results of the workflow's rendered steps, as `run_tasks` returns them, or a `Workflow`
+    /// error if the workflow is unknown or the parameters don't match its declaration.
+    pub fn run_workflow(&mut self, name: &str, params_json: &str) -> Result<Vec<Result<String, CdpError>>, CdpError> {
+        let params: serde_json::Map<String, Value> = serde_json::from_str(params_json).map_err(|e| LibError::Workflow {
+            message: format!("Invalid JSON parameters: expected an object ({})", e),
+        })?;
+        let mut registry = WorkflowRegistry::default();
+        for definition in self.workflows.values() {
+            registry.register(definition.clone());
+        }
+        let tasks = registry.render(name, &workflow::params_from_json(&params)).map_err(LibError::from)?;
+        self.run_tasks(&tasks)
+    }
+
+    /// Runs tasks in order, like `automate` in the page: `{{PREVIOUS_RESULT}}` is replaced with the
+    /// output of the previous task (an empty string after a failure), and a failed task does not
+    /// stop the tasks after it.
+    ///
+    /// # Returns
+    /// The result of each task, or a `Workflow` error, before any task runs, if the list contains
+    /// flow steps.
+    pub fn run_tasks(&mut self, tasks: &[String]) -> Result<Vec<Result<String, CdpError>>, CdpError> {
+        if let Some(step) = tasks.iter().find(|task| is_flow_step(task)) {
+            return Err(LibError::Workflow {
+                message: format!("'{}' is a flow step (TRY, LABEL, GOTO or WHILE), which the CDP backend does not run", step.trim()),
+            }
+            .into());
+        }
+        let mut previous_result: Option<String> = None;
+        let mut results = Vec::with_capacity(tasks.len());
+        for template in tasks {
+            let task = template.replace("{{PREVIOUS_RESULT}}", previous_result.as_deref().unwrap_or(""));
+            let result = self.run_task(&task);
+            previous_result = result.as_ref().ok().cloned();
+            results.push(result);
+        }
+        Ok(results)
+    }
+
+    /// Runs a single DOM command, e.g. `CLICK css:#submit`, and returns its result as in the page.
+    pub fn run_task(&mut self, task: &str) -> Result<String, CdpError> {
+        let dom_command = agent::parse_dom_command(task).ok_or_else(|| {
+            AgentError::CommandParseError(format!("'{}' is not a DOM command, and the CDP backend has no LLM to plan it", task))
+        })?;
+        // As in the page, a selector fallback chain is replaced by its first candidate matching an element.
+        match self.matching_candidate(&dom_command.selector)? {
+            Some(candidate) => {
+                let with_candidate = DomCommand { selector: candidate.clone(), ..dom_command.clone() };
+                let message = self.perform(&with_candidate)?;
+                Ok(format!("{} (matched selector '{}' of {})", message, candidate, dom_command.selector))
+            }
+            None => self.perform(&dom_command),
+        }
+    }
+
+    // Performs a `DomCommand` on the selector it names.
+    fn perform(&mut self, dom_command: &DomCommand) -> Result<String, CdpError> {
+        let selector = dom_command.selector.as_str();
+        let value = dom_command.value.as_deref();
+        let attribute_name = dom_command.attribute_name.as_deref();
+        match dom_command.action {
+            DomCommandAction::Click => {
+                self.run_script(selector, None, None, CLICK)?;
+                Ok(format!("Successfully clicked element with selector: '{}'", selector))
+            }
+            DomCommandAction::Type => {
+                let text = required(value, "TYPE command requires text value")?;
+                self.run_script(selector, Some(text), None, TYPE)?;
+                Ok(format!("Successfully typed '{}' in element with selector: '{}'", text, selector))
+            }
+            DomCommandAction::ClickIfExists => {
+                if !self.element_exists(selector)? {
+                    return Ok(agent::skipped_outcome("CLICK", selector));
+                }
+                self.run_script(selector, None, None, CLICK)?;
+                Ok(format!("Successfully clicked element with selector: '{}'", selector))
+            }
+            DomCommandAction::TypeIfExists => {
+                let text = required(value, "TYPE_IF_EXISTS command requires text value")?;
+                if !self.element_exists(selector)? {
+                    return Ok(agent::skipped_outcome("TYPE", selector));
+                }
+                self.run_script(selector, Some(text), None, TYPE)?;
+                Ok(format!("Successfully typed '{}' in element with selector: '{}'", text, selector))
+            }
+            DomCommandAction::Read => {
+                let text = self.run_script(selector, None, None, READ)?;
+                Ok(format!("Text from element '{}': {}", selector, text_of(&text)))
+            }
+            DomCommandAction::GetValue => {
+                let found = self.run_script(selector, None, None, GET_VALUE)?;
+                Ok(format!("Value from element '{}': {}", selector, text_of(&found)))
+            }
+            DomCommandAction::GetAttribute => {
+                let attribute_name = required(attribute_name, "GETATTRIBUTE command requires attribute name")?;
+                let found = self.run_script(selector, None, Some(attribute_name), GET_ATTRIBUTE)?;
+                Ok(format!("Attribute '{}' from element '{}': {}", attribute_name, selector, text_of(&found)))
+            }
+            DomCommandAction::SetAttribute => {
+                let attribute_name = required(attribute_name, "SETATTRIBUTE command requires attribute name")?;
+                let attribute_value = required(value, "SETATTRIBUTE command requires attribute value")?;
+                self.run_script(selector, Some(attribute_value), Some(attribute_name), SET_ATTRIBUTE)?;
+                Ok(format!(
+                    "Successfully set attribute '{}' to '{}' for element '{}'",
+                    attribute_name, attribute_value, selector
+                ))
+            }
+            DomCommandAction::SelectOption => {
+                let option = required(value, "SELECTOPTION command requires option value")?;
+                self.run_script(selector, Some(option), None, SELECT_OPTION)?;
+                Ok(format!("Successfully selected option '{}' for dropdown '{}'", option, selector))
+            }
+            DomCommandAction::GetAllAttributes => {
+                let attribute_name = required(attribute_name, "GET_ALL_ATTRIBUTES command requires attribute name")?;
+                let json_string = self.run_script(selector, None, Some(attribute_name), GET_ALL_ATTRIBUTES)?;
+                Ok(format!(
+                    "Successfully retrieved attributes '{}' for elements matching selector '{}': {}",
+                    attribute_name, selector, text_of(&json_string)
+                ))
+            }
+            DomCommandAction::GetUrl => {
+                let url = self.run_script(selector, None, None, GET_URL)?;
+                Ok(format!("Current URL is: {}", text_of(&url)))
+            }
+            DomCommandAction::Navigate => {
+                let url = required(value, "NAVIGATE command requires a URL")?;
+                let target = self.navigate(url)?;
+                Ok(format!("Navigating to: {}", target))
+            }
+            DomCommandAction::ElementExists => {
+                let exists = self.element_exists(selector)?;
+                Ok(format!("Element '{}' exists: {}", selector, exists))
+            }
+            DomCommandAction::WaitForElement => {
+                let timeout_ms = value.and_then(|s| s.parse::<u32>().ok()).unwrap_or(self.wait.timeout_ms);
+                self.wait_for_element(selector, timeout_ms)?;
+                Ok(format!("Element '{}' appeared.", selector))
+            }
+            DomCommandAction::IsVisible => {
+                let visible = self.run_script(selector, None, None, IS_VISIBLE)?;
+                Ok(format!("Element '{}' is visible: {}", selector, visible))
+            }
+            DomCommandAction::ScrollTo => {
+                self.run_script(selector, None, None, SCROLL_TO)?;
+                Ok(format!("Successfully scrolled to element '{}'", selector))
+            }
+            DomCommandAction::Hover => {
+                // Unlike in the page, the pointer really moves, so CSS `:hover` rules apply too.
+                let center = self.run_script(selector, None, None, ELEMENT_CENTER)?;
+                self.call("Input.dispatchMouseEvent", json!({ "type": "mouseMoved", "x": center["x"], "y": center["y"] }))?;
+                Ok(format!("Successfully hovered over element '{}'", selector))
+            }
+            DomCommandAction::GetAllText => {
+                let separator = value.unwrap_or("\n"); // Default to newline if not provided
+                let text_content = self.run_script(selector, Some(separator), None, GET_ALL_TEXT)?;
+                Ok(format!(
+                    "Retrieved text from elements matching '{}' (separated by '{}'): \"{}\"",
+                    selector,
+                    separator.replace("\n", "\\n"),
+                    text_of(&text_content)
+                ))
+            }
+            DomCommandAction::EvalJs => {
+                if !self.allow_eval {
+                    return Err(AgentError::PolicyViolation("EVAL_JS is disabled. Enable it with allow_eval(true).".to_string()).into());
+                }
+                let script = required(value, "EVAL_JS command requires a script")?;
+                let json_result = self.run_script(selector, Some(script), None, EVAL_JS)?;
+                Ok(format!("EVAL_JS result: {}", text_of(&json_result)))
+            }
+            DomCommandAction::GetElementAttributes => {
+                let json_string = self.run_script(selector, None, None, GET_ELEMENT_ATTRIBUTES)?;
+                Ok(format!("Attributes of element '{}': {}", selector, text_of(&json_string)))
+            }
+            DomCommandAction::GetDataset => {
+                let json_string = self.run_script(selector, None, None, GET_DATASET)?;
+                Ok(format!("Dataset of element '{}': {}", selector, text_of(&json_string)))
+            }
+            DomCommandAction::IsEnabled => {
+                let enabled = self.run_script(selector, None, None, IS_ENABLED)?;
+                Ok(format!("Element '{}' is enabled: {}", selector, enabled))
+            }
+            DomCommandAction::IsSelected => {
+                let selected = self.run_script(selector, None, None, IS_SELECTED)?;
+                Ok(format!("Element '{}' is selected: {}", selector, selected))
+            }
+            DomCommandAction::GetMediaQuery => {
+                let query = required(value, "GET_MEDIA_QUERY command requires a media query")?;
+                let matches = self.run_script(selector, Some(query), None, MEDIA_QUERY_MATCHES)?;
+                Ok(format!("Media query '{}' matches: {}", query, matches))
+            }
+            DomCommandAction::IsDarkMode => {
+                let dark = self.run_script(selector, Some("(prefers-color-scheme: dark)"), None, MEDIA_QUERY_MATCHES)?;
+                Ok(format!("Dark mode: {}", dark))
+            }
+            DomCommandAction::CaptureElement => {
+                let data_url = self.capture_element(selector)?;
+                Ok(format!("Screenshot of element '{}': {}", selector, data_url))
+            }
+            ref action => Err(AgentError::CommandParseError(format!("{:?} is not available in the CDP backend", action)).into()),
+        }
+    }
+
+    // Whether an element matches `selector`, like `dom_utils::element_exists`.
+    fn element_exists(&mut self, selector: &str) -> Result<bool, CdpError> {
+        Ok(self.run_script(selector, None, None, ELEMENT_EXISTS)?.as_bool().unwrap_or(false))
+    }
+
+    // The first candidate of a selector fallback chain that matches an element, like
+    // `dom_utils::matching_candidate`.
+    fn matching_candidate(&mut self, selector: &str) -> Result<Option<String>, CdpError> {
+        let (region, chain) = match dom_utils::split_scope(selector) {
+            Some((region, chain)) => (Some(region), chain),
+            None => (None, selector),
+        };
+        let Some(candidates) = dom_utils::selector_candidates(chain) else { return Ok(None) };
+        let mut first_error = None;
+        for candidate in candidates {
+            let scoped = match region {
+                Some(region) => format!("{}{}{}", region, dom_utils::SCOPE_SEPARATOR, candidate),
+                None => candidate,
+            };
+            match self.element_exists(&scoped) {
+                Ok(true) => return Ok(Some(scoped)),
+                Ok(false) => {}
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(None), Err)
+    }
+
+    // Polls the page until an element matches `selector`, like `dom_utils::wait_for_element_with`.
+    fn wait_for_element(&mut self, selector: &str, timeout_ms: u32) -> Result<(), CdpError> {
+        let started = Instant::now();
+        loop {
+            if self.element_exists(selector)? {
+                return Ok(());
+            }
+            if started.elapsed() >= Duration::from_millis(timeout_ms.into()) {
+                return Err(DomError::ElementNotFound {
+                    selector: selector.to_string(),
+                    message: Some(format!("Element '{}' not found after {}ms timeout", selector, timeout_ms)),
+                }
+                .into());
+            }
+            thread::sleep(Duration::from_millis(self.wait.interval_ms.into()));
+        }
+    }
+
+    // Navigates the page to `url` (absolute or relative to the current page) and waits for the new
+    // page to load, so that the next task finds it. Returns the absolute URL.
+    fn navigate(&mut self, url: &str) -> Result<String, CdpError> {
+        let target = self.run_script("", Some(url), None, RESOLVE_URL)?;
+        let target = text_of(&target).to_string();
+        let navigation = self.call("Page.navigate", json!({ "url": target }))?;
+        if let Some(error_text) = navigation["errorText"].as_str().filter(|text| !text.is_empty()) {
+            return Err(DomError::JsError { message: format!("Navigation to '{}' failed: {}", target, error_text) }.into());
+        }
+        let timeout_ms = self.wait.timeout(None, NAVIGATION_TIMEOUT_MS);
+        let started = Instant::now();
+        // Scripts fail while the old page unloads; the page is checked again until it has loaded.
+        while self.run_script("", None, None, READY_STATE).ok().as_ref().and_then(Value::as_str) != Some("complete") {
+            if started.elapsed() >= Duration::from_millis(timeout_ms.into()) {
+                return Err(DomError::JsError {
+                    message: format!("Page '{}' did not finish loading within {}ms", target, timeout_ms),
+                }
+                .into());
+            }
+            thread::sleep(Duration::from_millis(self.wait.interval_ms.into()));
+        }
+        Ok(target)
+    }
+
+    // Takes a PNG screenshot of the element matching `selector`, as a data URL.
+    fn capture_element(&mut self, selector: &str) -> Result<String, CdpError> {
+        let element_box = self.run_script(selector, None, None, ELEMENT_BOX)?;
+        let (width, height) = (element_box["width"].as_f64().unwrap_or(0.0), element_box["height"].as_f64().unwrap_or(0.0));
+        if width <= 0.0 || height <= 0.0 {
+            return Err(DomError::JsError { message: format!("Element '{}' has no size to capture", selector) }.into());
+        }
+        let screenshot = self.call(
+            "Page.captureScreenshot",
+            json!({
+                "format": "png",
+                "captureBeyondViewport": true,
+                "clip": { "x": element_box["x"], "y": element_box["y"], "width": width, "height": height, "scale": 1 },
+            }),
+        )?;
+        Ok(format!("data:image/png;base64,{}", string_field(&screenshot, "data")?))
+    }
+
+    // Evaluates a command's script (see `page_script`) in the page and returns its value.
+    fn run_script(&mut self, selector: &str, value: Option<&str>, attribute_name: Option<&str>, body: &str) -> Result<Value, CdpError> {
+        let reply = self.call(
+            "Runtime.evaluate",
+            json!({ "expression": page_script(selector, value, attribute_name, body), "returnByValue": true }),
+        )?;
+        if let Some(details) = reply.get("exceptionDetails") {
+            return Err(CdpError::Protocol(format!("Command script failed: {}", details["text"].as_str().unwrap_or("unknown error"))));
+        }
+        script_outcome(&reply["result"]["value"])
+    }
+
+    // Sends a DevTools call to the attached page (or the browser, before one is attached) and waits
+    // for its reply. Events and replies to other calls received meanwhile are skipped.
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, CdpError> {
+        self.next_call_id += 1;
+        let id = self.next_call_id;
+        let mut message = json!({ "id": id, "method": method, "params": params });
+        if let Some(session_id) = &self.session_id {
+            message["sessionId"] = json!(session_id);
+        }
+        self.socket
+            .send(Message::Text(message.to_string()))
+            .map_err(|e| CdpError::Protocol(format!("Could not send {}: {}", method, e)))?;
+        loop {
+            let text = match self.socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => return Err(CdpError::Protocol("Chrome closed the DevTools connection".to_string())),
+                Ok(_) => continue,
+                Err(e) => return Err(CdpError::Protocol(format!("No reply to {}: {}", method, e))),
+            };
+            let reply: Value = serde_json::from_str(&text)
+                .map_err(|e| CdpError::Protocol(format!("Invalid DevTools message: {}", e)))?;
+            if reply["id"].as_u64() == Some(id) {
+                return call_result(method, reply);
+            }
+        }
+    }
+}
+
+// Fails with a `CommandParseError` if a command lacks a required argument.
+fn required<'a>(argument: Option<&'a str>, message: &str) -> Result<&'a str, CdpError> {
+    argument.ok_or_else(|| AgentError::CommandParseError(message.to_string()).into())
+}
+
+// A script's value as text: strings as they are, other values as JSON.
+fn text_of(value: &Value) -> std::borrow::Cow<'_, str> {
+    match value.as_str() {
+        Some(text) => text.into(),
+        None => value.to_string().into(),
+    }
+}
+
+// Whether `task` is a flow step of task lists, which the CDP backend does not run.
+fn is_flow_step(task: &str) -> bool {
+    try_catch::marker(task).is_some() || goto::parse_step(task).is_some() || while_loop::parse_step(task).is_some()
+}
+
+// The DevTools WebSocket URL of a line Chrome writes to stderr on startup, if it is that line.
+fn devtools_url(line: &str) -> Option<&str> {
+    line.trim().strip_prefix(DEVTOOLS_LISTENING_PREFIX).filter(|url| url.starts_with("ws://"))
+}
+
+// A string field of a DevTools reply.
+fn string_field(reply: &Value, name: &str) -> Result<String, CdpError> {
+    reply[name]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| CdpError::Protocol(format!("DevTools reply has no '{}': {}", name, reply)))
+}
+
+// The `result` of a reply to `method`, or the error Chrome answered with.
+fn call_result(method: &str, mut reply: Value) -> Result<Value, CdpError> {
+    match reply.get("error") {
+        Some(error) => Err(CdpError::Protocol(format!(
+            "{} failed: {} ({})",
+            method,
+            error["message"].as_str().unwrap_or("unknown error"),
+            error["code"]
+        ))),
+        None => Ok(reply["result"].take()),
+    }
+}
+
+// The selector spec `PRELUDE` resolves, built with the same rules as `dom_utils::get_element`:
+// scoped selectors first, then fallback chains, then `xpath:`, `text:` and CSS selectors.
+fn selector_spec(selector: &str) -> Value {
+    if let Some((region, inner)) = dom_utils::split_scope(selector) {
+        return json!({ "scope": [selector_spec(region), selector_spec(inner)] });
+    }
+    if let Some(candidates) = dom_utils::selector_candidates(selector) {
+        return json!({ "chain": candidates.iter().map(|candidate| selector_spec(candidate)).collect::<Vec<_>>() });
+    }
+    if let Some(xpath) = selector.strip_prefix("xpath:") {
+        json!({ "xpath": xpath })
+    } else if let Some(text) = selector.strip_prefix("text:") {
+        json!({ "text": text })
+    } else {
+        json!({ "css": selector.strip_prefix("css:").unwrap_or(selector) })
+    }
+}
+
+// Wraps a command's script `body` into an expression evaluating to `{ok: <returned value>}`, or to
+// `{error: ...}` if it throws. The body runs with the helpers of `PRELUDE` and with `selectorText`,
+// `spec` (see `selector_spec`), `value` and `attribute` (`null` when absent) in scope.
+fn page_script(selector: &str, value: Option<&str>, attribute_name: Option<&str>, body: &str) -> String {
+    format!(
+        "(() => {{\nconst selectorText = {};\nconst spec = {};\nconst value = {};\nconst attribute = {};\n{}\n\
+         try {{ return {{ ok: (() => {{ {} }})() }}; }} catch (e) {{ return {{ error: describeError(e) }}; }}\n}})()",
+        json!(selector),
+        selector_spec(selector),
+        json!(value),
+        json!(attribute_name),
+        PRELUDE,
+        body
+    )
+}
+
+// The value a command's script returned, or the `DomError` it failed with.
+fn script_outcome(outcome: &Value) -> Result<Value, CdpError> {
+    match outcome.get("error") {
+        Some(error) => Err(dom_error(error).into()),
+        None => Ok(outcome.get("ok").cloned().unwrap_or(Value::Null)),
+    }
+}
+
+// Converts an error thrown by a command's script (see `PRELUDE`) into the `DomError` the command
+// fails with in the page.
+fn dom_error(error: &Value) -> DomError {
+    let field = |name: &str| error[name].as_str().unwrap_or_default().to_string();
+    match error["kind"].as_str() {
+        Some("not_found") => DomError::ElementNotFound { selector: field("selector"), message: None },
+        Some("invalid_selector") => DomError::InvalidSelector { selector: field("selector"), error: field("error") },
+        Some("element_type") => DomError::ElementTypeError { selector: field("selector"), expected_type: field("expected_type") },
+        Some("attribute_not_found") => {
+            DomError::AttributeNotFound { selector: field("selector"), attribute_name: field("attribute_name") }
+        }
+        _ => match error["name"].as_str() {
+            Some("TypeError") => DomError::JsTypeError { message: field("message") },
+            Some("SyntaxError") => DomError::JsSyntaxError { message: field("message") },
+            Some("ReferenceError") => DomError::JsReferenceError { message: field("message") },
+            _ => DomError::JsError { message: field("message") },
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_devtools_url_is_read_from_chrome_stderr() {
+        assert_eq!(
+            devtools_url("DevTools listening on ws://127.0.0.1:40123/devtools/browser/5f1c\n"),
+            Some("ws://127.0.0.1:40123/devtools/browser/5f1c")
+        );
+        assert_eq!(devtools_url("[0101/000000.000:ERROR:gpu_init.cc] Passthrough is not supported"), None);
+        assert_eq!(devtools_url("DevTools listening on "), None);
+    }
+
+    #[test]
+    fn test_selector_spec_follows_the_in_page_rules() {
+        assert_eq!(selector_spec("#buy"), json!({ "css": "#buy" }));
+        assert_eq!(selector_spec("css:[data-test=buy]"), json!({ "css": "[data-test=buy]" }));
+        assert_eq!(selector_spec("xpath://li[1]"), json!({ "xpath": "//li[1]" }));
+        assert_eq!(
+            selector_spec("[css:#buy, text:\"Buy now\"]"),
+            json!({ "chain": [{ "css": "#buy" }, { "text": "Buy now" }] })
+        );
+        assert_eq!(
+            selector_spec("css:#chat >> text:Send"),
+            json!({ "scope": [{ "css": "#chat" }, { "text": "Send" }] })
+        );
+    }
+
+    #[test]
+    fn test_page_script_embeds_arguments_as_literals() {
+        let script = page_script("css:#q", Some("it's \"quoted\"\n</script>"), None, TYPE);
+        assert!(script.contains(r##"const selectorText = "css:#q";"##));
+        assert!(script.contains(r##"const spec = {"css":"#q"};"##));
+        assert!(script.contains(r#"const value = "it's \"quoted\"\n</script>";"#));
+        assert!(script.contains("const attribute = null;"));
+        assert!(script.contains(TYPE));
+    }
+
+    #[test]
+    fn test_script_errors_map_to_dom_errors() {
+        let not_found = script_outcome(&json!({ "error": { "kind": "not_found", "selector": "#missing" } }));
+        assert!(matches!(
+            not_found,
+            Err(CdpError::Task(LibError::DomOperation { ref kind, .. })) if kind == "ElementNotFound"
+        ));
+        assert_eq!(
+            dom_error(&json!({ "kind": "attribute_not_found", "selector": "#a", "attribute_name": "href" })),
+            DomError::AttributeNotFound { selector: "#a".to_string(), attribute_name: "href".to_string() }
+        );
+        assert_eq!(
+            dom_error(&json!({ "kind": "element_type", "selector": "#a", "expected_type": "HtmlInputElement" })),
+            DomError::ElementTypeError { selector: "#a".to_string(), expected_type: "HtmlInputElement".to_string() }
+        );
+        assert_eq!(
+            dom_error(&json!({ "kind": "js", "name": "SyntaxError", "message": "Unexpected token" })),
+            DomError::JsSyntaxError { message: "Unexpected token".to_string() }
+        );
+        assert_eq!(script_outcome(&json!({ "ok": true })).unwrap(), json!(true));
+        assert_eq!(script_outcome(&json!({})).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_call_result_reports_protocol_errors() {
+        let reply = json!({ "id": 3, "error": { "code": -32000, "message": "No target with given id found" } });
+        match call_result("Target.attachToTarget", reply) {
+            Err(CdpError::Protocol(message)) => {
+                assert_eq!(message, "Target.attachToTarget failed: No target with given id found (-32000)")
+            }
+            other => panic!("expected a protocol error, got {:?}", other),
+        }
+        assert_eq!(call_result("Page.navigate", json!({ "id": 4, "result": { "frameId": "F" } })).unwrap(), json!({ "frameId": "F" }));
+    }
+
+    #[test]
+    fn test_flow_steps_are_recognized() {
+        assert!(is_flow_step("TRY"));
+        assert!(is_flow_step("LABEL retry"));
+        assert!(is_flow_step("GOTO retry 3"));
+        assert!(!is_flow_step("CLICK css:#submit"));
+        assert!(!is_flow_step("Label the photos"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use crate::while_loop;
+
+/// Commands a condition may check. They read the page without changing it, so conditions can be
+/// checked repeatedly while waiting for them to hold.
+const CONDITION_COMMANDS: [&str; 5] = ["ELEMENT_EXISTS", "IS_VISIBLE", "IS_ENABLED", "IS_EDITABLE", "IS_SELECTED"];
+
+/// When a condition is checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConditionTime {
+    /// `[pre: ...]`: before the task, which does not run unless the condition holds.
+    Pre,
+    /// `[post: ...]`: after the task succeeded, which fails unless the condition holds.
+    Post,
+}
+
+/// A condition attached to a task, e.g. `[post: ELEMENT_EXISTS css:#toast-success]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskCondition<'a> {
+    pub time: ConditionTime,
+    /// The predicate command, e.g. `ELEMENT_EXISTS css:#toast-success`.
+    pub predicate: &'a str,
+    /// `true` if the condition holds when the predicate does not, as in `[post: NOT IS_VISIBLE css:.spinner]`.
+    pub negated: bool,
+    /// How long the condition is waited for, as in `[post 10000: ...]`. The wait defaults apply
+    /// while `None`.
+    pub timeout_ms: Option<u32>,
+}
+
+impl TaskCondition<'_> {
+    /// Whether the condition holds, given the result of its predicate command.
+    ///
+    /// # Returns
+    /// `Some(bool)`, or `None` if the result says neither true nor false.
+    pub fn holds(&self, result: &str) -> Option<bool> {
+        while_loop::truth(result).map(|value| value != self.negated)
+    }
+}
+
+impl fmt::Display for TaskCondition<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = match self.time {
+            ConditionTime::Pre => "pre",
+            ConditionTime::Post => "post",
+        };
+        write!(f, "{}: {}{}", time, if self.negated { "NOT " } else { "" }, self.predicate)
+    }
+}
+
+/// Splits the conditions off the end of a task, e.g. `CLICK css:#save [pre: IS_ENABLED css:#save]
+/// [post: ELEMENT_EXISTS css:#toast-success]`. A condition is `[pre: <predicate>]` or
+/// `[post: <predicate>]`, optionally with a timeout in milliseconds before the colon (`[post 10000: ...]`),
+/// where the predicate is `ELEMENT_EXISTS`, `IS_VISIBLE`, `IS_ENABLED`, `IS_EDITABLE` or
+/// `IS_SELECTED`, optionally preceded by `NOT`. Keywords are matched case-insensitively.
+///
+/// # Returns
+/// The task without its conditions and the conditions in the order they were written (none for
+/// most tasks), or an error if a condition is malformed.
+pub fn split(task: &str) -> Result<(&str, Vec<TaskCondition<'_>>), String> {
+    let mut rest = task.trim_end();
+    let mut conditions = Vec::new();
+    while let Some((start, condition)) = last_condition(rest) {
+        conditions.push(condition?);
+        rest = rest[..start].trim_end();
+    }
+    if !conditions.is_empty() && rest.is_empty() {
+        return Err(format!("'{}' has conditions but no command", task.trim()));
+    }
+    conditions.reverse();
+    Ok((rest, conditions))
+}
+
+// Finds the condition `task` ends with, returning where it starts and the parsed condition, or
+// `None` if the task does not end with one. Predicates may contain brackets themselves (e.g.
+// selector fallback chains), so the condition starts at the last `[pre` or `[post` keyword.
+fn last_condition(task: &str) -> Option<(usize, Result<TaskCondition<'_>, String>)> {
+    let inner = task.strip_suffix(']')?;
+    let lowercase = inner.to_ascii_lowercase();
+    let (start, time, keyword_len) = [("[pre", ConditionTime::Pre), ("[post", ConditionTime::Post)]
+        .into_iter()
+        .flat_map(|(keyword, time)| lowercase.match_indices(keyword).map(move |(index, _)| (index, time, keyword.len())))
+        .filter(|&(index, _, keyword_len)| {
+            let preceded = index == 0 || inner[..index].ends_with(char::is_whitespace);
+            let header = inner[index + keyword_len..].split(':').next().unwrap_or("");
+            preceded && inner[index + keyword_len..].contains(':') && header.trim().chars().all(|c| c.is_ascii_digit())
+        })
+        .max_by_key(|&(index, _, _)| index)?;
+    let (header, predicate) = inner[start + keyword_len..].split_once(':')?;
+    let header = header.trim();
+    let timeout_ms = if header.is_empty() { None } else { Some(header.parse::<u32>().ok()?) };
+    Some((start, parse_predicate(time, predicate.trim(), timeout_ms)))
+}
+
+// Parses the predicate of a condition, `[NOT] <command> <arguments>`.
+fn parse_predicate(time: ConditionTime, predicate: &str, timeout_ms: Option<u32>) -> Result<TaskCondition<'_>, String> {
+    let (negated, predicate) = match predicate.split_once(char::is_whitespace) {
+        Some((not, rest)) if not.eq_ignore_ascii_case("NOT") => (true, rest.trim()),
+        _ => (false, predicate),
+    };
+    let command = predicate.split_whitespace().next().unwrap_or("").to_uppercase();
+    if !CONDITION_COMMANDS.contains(&command.as_str()) {
+        return Err(format!("Condition '{}' must be one of {}, optionally preceded by NOT", predicate, CONDITION_COMMANDS.join(", ")));
+    }
+    Ok(TaskCondition { time, predicate, negated, timeout_ms })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_conditions() {
+        let (task, conditions) = split("CLICK css:#save [pre: IS_ENABLED css:#save] [POST 10000: element_exists css:#toast-success]").unwrap();
+        assert_eq!(task, "CLICK css:#save");
+        assert_eq!(conditions.len(), 2);
+        assert_eq!(conditions[0], TaskCondition { time: ConditionTime::Pre, predicate: "IS_ENABLED css:#save", negated: false, timeout_ms: None });
+        assert_eq!(conditions[1].time, ConditionTime::Post);
+        assert_eq!(conditions[1].predicate, "element_exists css:#toast-success");
+        assert_eq!(conditions[1].timeout_ms, Some(10000));
+
+        let (task, conditions) = split(r#"CLICK [text:"Buy", css:.buy] [post: NOT IS_VISIBLE [text:"Buy", css:.buy]]"#).unwrap();
+        assert_eq!(task, r#"CLICK [text:"Buy", css:.buy]"#);
+        assert!(conditions[0].negated);
+        assert_eq!(conditions[0].predicate, r#"IS_VISIBLE [text:"Buy", css:.buy]"#);
+        assert_eq!(conditions[0].to_string(), r#"post: NOT IS_VISIBLE [text:"Buy", css:.buy]"#);
+    }
+
+    #[test]
+    fn test_split_leaves_other_tasks_alone() {
+        assert_eq!(split("CLICK [text:\"Save\", css:#save]").unwrap(), ("CLICK [text:\"Save\", css:#save]", vec![]));
+        assert_eq!(split("TYPE css:#note [postponed]").unwrap(), ("TYPE css:#note [postponed]", vec![]));
+        assert_eq!(split("TYPE css:#note a[post: b]").unwrap().1, vec![], "Conditions start a word");
+        assert_eq!(split("Summarize the page").unwrap(), ("Summarize the page", vec![]));
+
+        assert!(split("CLICK css:#save [post: LLM_DECIDE css:body saved?]").unwrap_err().contains("must be one of"));
+        assert!(split("[post: ELEMENT_EXISTS css:#toast]").unwrap_err().contains("no command"));
+    }
+
+    #[test]
+    fn test_condition_holds() {
+        let (_, conditions) = split("CLICK css:#close [post: NOT IS_VISIBLE css:#dialog]").unwrap();
+        assert_eq!(conditions[0].holds("Element 'css:#dialog' is visible: false"), Some(true));
+        assert_eq!(conditions[0].holds("true"), Some(false));
+        assert_eq!(conditions[0].holds("unknown"), None);
+    }
+}
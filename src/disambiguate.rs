@@ -0,0 +1,92 @@
+use web_sys::console;
+use crate::agent::{AgentError, ExecutionConfig, LlmEndpoint};
+use crate::audit;
+use crate::budget; // LLM call budget shared by every RustAgent on the page
+use crate::redact::RedactionMap;
+use crate::run_limits::RunAction;
+
+/// Builds the prompt asking the LLM which of the elements matched by `command` (described in
+/// document order by `dom_utils::describe_matches`) is the one meant by `task`.
+pub fn disambiguation_prompt(task: &str, command: &str, descriptions: &[String]) -> String {
+    let candidates: Vec<String> = descriptions
+        .iter()
+        .enumerate()
+        .map(|(index, description)| format!("{}: {}", index, description))
+        .collect();
+    format!(
+        "A command on a web page matches several elements, and only one of them is intended.\n\
+        Task: {}\n\
+        Command: {}\n\
+        Matching elements:\n{}\n\
+        Answer with only the number of the intended element.",
+        task,
+        command,
+        candidates.join("\n")
+    )
+}
+
+/// Reads the LLM's answer as the index of one of `count` candidates: the first number in it.
+pub fn parse_choice(answer: &str, count: usize) -> Option<usize> {
+    answer
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|digits| !digits.is_empty())?
+        .parse::<usize>()
+        .ok()
+        .filter(|index| *index < count)
+}
+
+/// Asks the LLM which of the matches described by `descriptions` `command` should act on, given
+/// the `task` it is part of. The prompt is redacted if redaction is enabled, and the call draws
+/// from the shared LLM budget and is logged in the audit log.
+///
+/// # Returns
+/// * `Ok(usize)` with the index of the chosen match.
+/// * `Err(AgentError)` if the LLM call fails or the answer is not the number of a match.
+pub async fn pick(
+    task: &str,
+    command: &str,
+    descriptions: &[String],
+    llm: LlmEndpoint<'_>,
+    config: &ExecutionConfig,
+) -> Result<usize, AgentError> {
+    let prompt = disambiguation_prompt(task, command, descriptions);
+    let prompt = if config.redaction.is_enabled() {
+        config.redaction.redact(&prompt, &mut RedactionMap::default())?
+    } else {
+        prompt
+    };
+    config.run_limits.charge(RunAction::LlmCall).map_err(AgentError::RunLimitExceeded)?;
+    budget::acquire(prompt.len()).await.map_err(AgentError::BudgetExceeded)?;
+    let answer = audit::complete("disambiguation", prompt, llm, config).await.map_err(AgentError::from)?;
+    budget::record_response(answer.len());
+    console::log_1(&format!("LLM pick among {} matches for '{}': {}", descriptions.len(), command, answer).into());
+    parse_choice(&answer, descriptions.len()).ok_or_else(|| {
+        AgentError::InvalidLlmResponse(format!(
+            "The answer was not the number of one of the {} elements matching '{}': {}",
+            descriptions.len(), command, answer
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_choice() {
+        assert_eq!(parse_choice("2", 3), Some(2));
+        assert_eq!(parse_choice("Element 1.", 3), Some(1));
+        assert_eq!(parse_choice("  \"0\"", 3), Some(0));
+        assert_eq!(parse_choice("3", 3), None, "Out of range");
+        assert_eq!(parse_choice("The second one", 3), None);
+        assert_eq!(parse_choice("", 3), None);
+    }
+
+    #[test]
+    fn test_disambiguation_prompt() {
+        let descriptions = vec!["button.plan \"Basic\"".to_string(), "button.plan \"Pro\"".to_string()];
+        let prompt = disambiguation_prompt("Choose the Pro plan", "CLICK css:button.plan", &descriptions);
+        assert!(prompt.contains("Task: Choose the Pro plan\nCommand: CLICK css:button.plan\n"));
+        assert!(prompt.contains("0: button.plan \"Basic\"\n1: button.plan \"Pro\"\n"));
+    }
+}
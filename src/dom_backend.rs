@@ -0,0 +1,104 @@
+use web_sys::Window;
+#[cfg(feature = "node")]
+use std::cell::RefCell;
+#[cfg(feature = "node")]
+use wasm_bindgen::{JsCast, JsValue};
+
+#[cfg(feature = "node")]
+thread_local! {
+    // The window of a DOM implementation registered by the host, e.g. that of jsdom under Node.
+    // Like the LLM provider, it applies to every agent of the page (or Node process).
+    static WINDOW: RefCell<Option<Window>> = const { RefCell::new(None) };
+}
+
+/// The window commands act on: the one registered with `set_window` if any (the `node` feature),
+/// otherwise the browser's. `None` where there is neither, e.g. under Node or in a worker.
+pub fn window() -> Option<Window> {
+    #[cfg(feature = "node")]
+    if let Some(window) = WINDOW.with(|registered| registered.borrow().clone()) {
+        return Some(window);
+    }
+    web_sys::window()
+}
+
+/// Registers the window of a DOM implementation such as jsdom or linkedom, or removes it (`None`),
+/// so that commands act on its document instead of the browser's.
+///
+/// The command engine checks the types of elements and creates events with the global DOM
+/// constructors (`HTMLInputElement`, `MouseEvent`, ...), which Node lacks: the window's constructors,
+/// `window` and `document` are copied to the global object where it has none of its own, as
+/// `global-jsdom` does. They are left in place when the window is removed.
+#[cfg(feature = "node")]
+pub fn set_window(window: Option<JsValue>) {
+    if let Some(window) = &window {
+        install_globals(window);
+    }
+    WINDOW.with(|registered| *registered.borrow_mut() = window.map(JsCast::unchecked_into));
+}
+
+/// Whether a window was registered with `set_window`.
+#[cfg(feature = "node")]
+pub fn is_registered() -> bool {
+    WINDOW.with(|registered| registered.borrow().is_some())
+}
+
+// Copies the constructors of `window` (its properties named like `HTMLElement`), `window` and
+// `document` to the global object, skipping names it already has.
+#[cfg(feature = "node")]
+fn install_globals(window: &JsValue) {
+    let global = js_sys::global();
+    let mut names: Vec<String> = vec!["window".to_string(), "document".to_string()];
+    // jsdom defines most constructors on the window itself, linkedom on its prototype chain.
+    let mut object = window.clone();
+    while let Some(current) = object.dyn_ref::<js_sys::Object>() {
+        names.extend(
+            js_sys::Object::get_own_property_names(current)
+                .iter()
+                .filter_map(|name| name.as_string())
+                .filter(|name| name.starts_with(|c: char| c.is_ascii_uppercase())),
+        );
+        object = js_sys::Object::get_prototype_of(current).into();
+    }
+    for name in names {
+        let key = JsValue::from_str(&name);
+        if js_sys::Reflect::has(&global, &key).unwrap_or(true) {
+            continue;
+        }
+        let value = if name == "window" { Ok(window.clone()) } else { js_sys::Reflect::get(window, &key) };
+        // Properties that cannot be read or set (e.g. getters throwing outside a browser) are skipped.
+        if let Ok(value) = value {
+            if !value.is_undefined() {
+                let _ = js_sys::Reflect::set(&global, &key, &value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "node")]
+mod tests {
+    use super::*;
+    use crate::dom_utils;
+    use wasm_bindgen_test::*;
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_commands_act_on_the_registered_window() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let frame: web_sys::HtmlIFrameElement = document.create_element("iframe").unwrap().unchecked_into();
+        document.body().unwrap().append_child(&frame).unwrap();
+        let frame_window = frame.content_window().unwrap();
+        frame_window.document().unwrap().body().unwrap().set_inner_html("<p id='backend-only'>From the other window</p>");
+
+        assert!(!dom_utils::element_exists("css:#backend-only").unwrap());
+        set_window(Some(frame_window.clone().into()));
+        assert!(is_registered());
+        // Only commands that do not check element types: the frame has constructors of its own.
+        assert!(dom_utils::element_exists("css:#backend-only").unwrap());
+        set_window(None);
+        assert!(!dom_utils::element_exists("css:#backend-only").unwrap());
+        assert!(!is_registered());
+        assert!(js_sys::Object::is(&window().unwrap(), &web_sys::window().unwrap()));
+        frame.remove();
+    }
+}
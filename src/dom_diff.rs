@@ -0,0 +1,228 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, Node};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use crate::dom_utils::{self, DomError};
+
+/// Maximum number of elements fingerprinted; elements after this many, in document order, are
+/// ignored, so that tracking stays cheap on very large pages.
+pub const MAX_FINGERPRINT_NODES: usize = 5000;
+
+thread_local! {
+    // The diff of the last command run while DOM change tracking was enabled.
+    static LAST_DOM_DIFF: RefCell<Option<DomDiff>> = const { RefCell::new(None) };
+}
+
+/// A snapshot of the page's elements: a path (e.g. `body > div#cart > ul > li[2]`) and a hash of
+/// the attributes, own text and form state of every element, in document order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DomFingerprint {
+    nodes: Vec<(String, u64)>,
+}
+
+/// What changed between two fingerprints. Paths are listed in document order; when a whole subtree
+/// was added or removed, only its root is listed.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct DomDiff {
+    /// Elements that did not exist before.
+    pub added: Vec<String>,
+    /// Elements that no longer exist.
+    pub removed: Vec<String>,
+    /// Elements whose attributes, own text or form state changed.
+    pub changed: Vec<String>,
+}
+
+impl DomDiff {
+    /// One-line summary appended to command results, e.g. `changed_nodes: 2 added, 0 removed, 1 changed`.
+    pub fn summary(&self) -> String {
+        format!(
+            "changed_nodes: {} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        )
+    }
+}
+
+// The path segment of an element: its tag name and id, or its position among the siblings with
+// the same tag name (from 1) when it has no id and is not the first of them.
+fn path_segment(element: &Element, position: usize) -> String {
+    let tag = element.tag_name().to_lowercase();
+    let id = element.id();
+    if !id.is_empty() {
+        format!("{}#{}", tag, id)
+    } else if position > 1 {
+        format!("{}[{}]", tag, position)
+    } else {
+        tag
+    }
+}
+
+// Hashes what a command can change on an element itself: its attributes, the text of its own text
+// nodes (not its descendants'), and the `value` and `checked` state of form fields, which typing
+// and selecting change without touching attributes.
+fn element_hash(element: &Element) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for name in element.get_attribute_names().iter().filter_map(|name| name.as_string()) {
+        name.hash(&mut hasher);
+        element.get_attribute(&name).hash(&mut hasher);
+    }
+    let mut child = element.first_child();
+    while let Some(node) = child {
+        if node.node_type() == Node::TEXT_NODE {
+            node.node_value().hash(&mut hasher);
+        }
+        child = node.next_sibling();
+    }
+    if matches!(element.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT") {
+        for property in ["value", "checked"] {
+            let state = js_sys::Reflect::get(element, &JsValue::from_str(property)).unwrap_or(JsValue::UNDEFINED);
+            format!("{:?}", state).hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Fingerprints the elements of the page's `<body>`, at most `MAX_FINGERPRINT_NODES` of them.
+pub fn fingerprint() -> Result<DomFingerprint, DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    let body: Element = document
+        .body()
+        .ok_or_else(|| DomError::JsError { message: "Document has no body".to_string() })?
+        .into();
+    Ok(fingerprint_subtree(&body, "body"))
+}
+
+/// Fingerprints `root` and its descendants, at most `MAX_FINGERPRINT_NODES` elements, with paths
+/// starting with `root_path`.
+pub fn fingerprint_subtree(root: &Element, root_path: &str) -> DomFingerprint {
+    let mut nodes = Vec::new();
+    // Depth-first, so that nodes are in document order.
+    let mut stack = vec![(root.clone(), root_path.to_string())];
+    while let Some((element, path)) = stack.pop() {
+        if nodes.len() == MAX_FINGERPRINT_NODES {
+            break;
+        }
+        nodes.push((path.clone(), element_hash(&element)));
+
+        let mut children = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        let mut child = element.first_element_child();
+        while let Some(child_element) = child {
+            let position = positions.entry(child_element.tag_name()).or_insert(0);
+            *position += 1;
+            let child_path = format!("{} > {}", path, path_segment(&child_element, *position));
+            child = child_element.next_element_sibling();
+            children.push((child_element, child_path));
+        }
+        stack.extend(children.into_iter().rev());
+    }
+    DomFingerprint { nodes }
+}
+
+// Drops the paths that are inside another listed path, keeping the roots of added or removed subtrees.
+fn subtree_roots(paths: Vec<String>) -> Vec<String> {
+    let mut roots: Vec<String> = Vec::new();
+    for path in paths {
+        let inside_last_root = roots.last().is_some_and(|root| path.starts_with(&format!("{} > ", root)));
+        if !inside_last_root {
+            roots.push(path);
+        }
+    }
+    roots
+}
+
+/// Compares two fingerprints of the same page.
+pub fn diff(before: &DomFingerprint, after: &DomFingerprint) -> DomDiff {
+    let before_hashes: HashMap<&str, u64> = before.nodes.iter().map(|(path, hash)| (path.as_str(), *hash)).collect();
+    let after_hashes: HashMap<&str, u64> = after.nodes.iter().map(|(path, hash)| (path.as_str(), *hash)).collect();
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for (path, hash) in &after.nodes {
+        match before_hashes.get(path.as_str()) {
+            None => added.push(path.clone()),
+            Some(before_hash) if before_hash != hash => changed.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    let removed = before
+        .nodes
+        .iter()
+        .filter(|(path, _)| !after_hashes.contains_key(path.as_str()))
+        .map(|(path, _)| path.clone())
+        .collect();
+    DomDiff { added: subtree_roots(added), removed: subtree_roots(removed), changed }
+}
+
+/// Stores the diff of the command that just ran, for `GET_DOM_DIFF`.
+pub fn record_last_diff(diff: DomDiff) {
+    LAST_DOM_DIFF.with(|last| *last.borrow_mut() = Some(diff));
+}
+
+/// Returns the diff of the last command run while DOM change tracking was enabled as JSON, e.g.
+/// `{"added":["body > div#toast"],"removed":[],"changed":["body > form > input#email"]}`.
+pub fn get_last_diff() -> Result<String, DomError> {
+    let diff = LAST_DOM_DIFF.with(|last| last.borrow().clone()).ok_or_else(|| DomError::JsError {
+        message: "No DOM diff recorded. Call set_track_dom_changes(true) and run a command first.".to_string(),
+    })?;
+    serde_json::to_string(&diff).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn fingerprint_of(nodes: &[(&str, u64)]) -> DomFingerprint {
+        DomFingerprint { nodes: nodes.iter().map(|(path, hash)| (path.to_string(), *hash)).collect() }
+    }
+
+    #[test]
+    fn test_diff_lists_subtree_roots() {
+        let before = fingerprint_of(&[
+            ("body", 1),
+            ("body > ul", 2),
+            ("body > ul > li", 3),
+            ("body > ul > li[2]", 4),
+            ("body > ul > li[2] > span", 5),
+        ]);
+        let after = fingerprint_of(&[
+            ("body", 1),
+            ("body > ul", 2),
+            ("body > ul > li", 30),
+            ("body > div#toast", 6),
+            ("body > div#toast > p", 7),
+        ]);
+        let diff = diff(&before, &after);
+        assert_eq!(diff.added, vec!["body > div#toast"]);
+        assert_eq!(diff.removed, vec!["body > ul > li[2]"]);
+        assert_eq!(diff.changed, vec!["body > ul > li"]);
+        assert_eq!(diff.summary(), "changed_nodes: 1 added, 1 removed, 1 changed");
+        assert_eq!(super::diff(&after, &after), DomDiff::default());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_fingerprint_detects_page_changes() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let form = document.create_element("form").unwrap();
+        form.set_id("diff-form");
+        form.set_inner_html("<input id='diff-email'><p>Hint</p><p>Second</p>");
+        document.body().unwrap().append_child(&form).unwrap();
+        let before = fingerprint().unwrap();
+
+        let input: web_sys::HtmlInputElement = document.get_element_by_id("diff-email").unwrap().dyn_into().unwrap();
+        input.set_value("user@example.com");
+        form.last_element_child().unwrap().remove();
+        form.append_child(&document.create_element("output").unwrap()).unwrap();
+        let diff = diff(&before, &fingerprint().unwrap());
+        assert_eq!(diff.added, vec!["body > form#diff-form > output"]);
+        assert_eq!(diff.removed, vec!["body > form#diff-form > p[2]"]);
+        assert_eq!(diff.changed, vec!["body > form#diff-form > input#diff-email"]);
+        form.remove();
+    }
+}
@@ -1,11 +1,18 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{console, Window, Document, Element, HtmlElement, HtmlInputElement, XPathResult, NodeList}; // Removed Node
+use web_sys::{console, Window, Document, Element, HtmlElement, HtmlInputElement, XPathResult, NodeList, Url, MutationObserver, MutationObserverInit}; // Removed Node
 use serde_json; // Added for JSON serialization
 use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering}; // For generated ids of injected stylesheets
+use std::cell::{Cell, RefCell}; // For the simulated pointer position and the element cache
+use std::collections::HashMap;
+use crate::dom_backend; // The window commands act on
+use crate::locale; // Unicode-aware text matching and translated terms
+use crate::sandbox; // Copies of containers that previews run in
 use gloo_timers::future::{TimeoutFuture, IntervalStream};
 use futures_util::stream::StreamExt; // For IntervalStream.next()
 use futures::future::{select, Either}; // For select pattern
+use unicode_segmentation::UnicodeSegmentation; // For typing one grapheme cluster at a time
 
 /// Represents errors that can occur during DOM operations.
 #[derive(Debug, PartialEq)]
@@ -33,6 +40,15 @@ pub enum DomError {
     JsSyntaxError { message: String },
     /// A JavaScript `ReferenceError` occurred (e.g., accessing an undefined variable).
     JsReferenceError { message: String },
+    /// Indicates that an element was detached from the page while a command was using it (e.g.
+    /// re-rendered by a framework) and could not be found again by its selector.
+    StaleElement { selector: String },
+    /// Indicates that the server of another origin answered a request but its CORS headers do not
+    /// allow the page's `origin` to read the response.
+    CorsBlocked { url: String, origin: String },
+    /// Indicates that a request got no response (unreachable server, offline, mixed content, or
+    /// blocked by the browser).
+    NetworkError { url: String, message: String },
 }
 
 impl fmt::Display for DomError {
@@ -53,6 +69,9 @@ impl fmt::Display for DomError {
             DomError::JsTypeError { message } => write!(f, "JsTypeError: {}", message),
             DomError::JsSyntaxError { message } => write!(f, "JsSyntaxError: {}", message),
             DomError::JsReferenceError { message } => write!(f, "JsReferenceError: {}", message),
+            DomError::StaleElement { selector } => write!(f, "StaleElement: Element for selector '{}' was detached from the page and could not be found again", selector),
+            DomError::CorsBlocked { url, origin } => write!(f, "CorsBlocked: The server of '{}' answered, but its CORS headers do not allow '{}' to read the response", url, origin),
+            DomError::NetworkError { url, message } => write!(f, "NetworkError: No response from '{}': {}", url, message),
         }
     }
 }
@@ -107,16 +126,26 @@ impl Into<JsValue> for DomError {
 }
 
 // Helper function to get window and document
-fn get_window_document() -> Result<(Window, Document), DomError> {
-    let window = web_sys::window().ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?;
+pub(crate) fn get_window_document() -> Result<(Window, Document), DomError> {
+    let window = dom_backend::window().ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?;
     let document = window.document().ok_or_else(|| DomError::JsError { message: "Failed to get document object".to_string() })?;
     Ok((window, document))
 }
 
+// Evaluates an XPath selector against the document or, within a root (the sandbox of a preview or
+// the region of a scoped selector), against the root, where `//` paths are made relative to it;
+// absolute `/html/...` paths then match nothing.
+fn evaluate_xpath(document: &Document, root: Option<&Element>, xpath: &str) -> Result<XPathResult, JsValue> {
+    match root {
+        Some(root) if xpath.starts_with('/') => document.evaluate(&format!(".{}", xpath), root),
+        Some(root) => document.evaluate(xpath, root),
+        None => document.evaluate(xpath, document),
+    }
+}
+
 // Helper function to get an element using XPath
-fn get_element_by_xpath_logic(document: &Document, xpath: &str, original_selector: &str) -> Result<Element, DomError> {
-    let result = document
-        .evaluate(xpath, &document) // Corrected as per compiler suggestion
+fn get_element_by_xpath_logic(document: &Document, root: Option<&Element>, xpath: &str, original_selector: &str) -> Result<Element, DomError> {
+    let result = evaluate_xpath(document, root, xpath)
         .map_err(|e| DomError::InvalidSelector {
             selector: original_selector.to_string(),
             error: e.as_string().unwrap_or_else(|| "Unknown XPath error".to_string()),
@@ -137,12 +166,320 @@ fn get_element_by_xpath_logic(document: &Document, xpath: &str, original_selecto
     }
 }
 
-// Unified helper function to get an element by CSS selector or XPath
-fn get_element(document: &Document, original_selector: &str) -> Result<Element, DomError> {
+// CSS pseudo-classes that depend on state a `MutationObserver` does not report (focus, pointer,
+// form state); selectors using them are never cached.
+const UNCACHEABLE_PSEUDO_CLASSES: [&str; 8] =
+    [":focus", ":hover", ":active", ":checked", ":valid", ":invalid", ":placeholder-shown", ":target"];
+
+struct ElementCache {
+    document: Document,
+    elements: HashMap<String, Element>,
+    observer: MutationObserver,
+    // Clears `elements` when the observer reports mutations.
+    _on_mutation: Closure<dyn FnMut()>,
+}
+
+thread_local! {
+    // The elements resolved during the current run, while an `ElementCacheScope` is alive.
+    static ELEMENT_CACHE: RefCell<Option<ElementCache>> = const { RefCell::new(None) };
+}
+
+/// Caches the elements `get_element` resolves, by selector, while it is alive. A run keeps one, so
+/// that consecutive commands on the same selectors (e.g. many `READ`s of a table) query the page once.
+///
+/// Any change to the document (nodes, attributes or text) empties the cache; pending changes are
+/// checked on every lookup, so a cached element is never one the selector no longer matches. Only
+/// the first scope started owns the cache; scopes started while it exists (e.g. a triggered run
+/// during `automate`) share it.
+pub(crate) struct ElementCacheScope {
+    owner: bool,
+}
+
+impl ElementCacheScope {
+    /// Starts caching elements of the window's document.
+    pub(crate) fn start() -> Result<Self, DomError> {
+        if ELEMENT_CACHE.with(|cache| cache.borrow().is_some()) {
+            return Ok(ElementCacheScope { owner: false });
+        }
+        let (_window, document) = get_window_document()?;
+        let on_mutation = Closure::wrap(Box::new(|| {
+            ELEMENT_CACHE.with(|cache| {
+                if let Some(cache) = cache.borrow_mut().as_mut() {
+                    cache.elements.clear();
+                }
+            });
+        }) as Box<dyn FnMut()>);
+        let observer = MutationObserver::new(on_mutation.as_ref().unchecked_ref())?;
+        let options = MutationObserverInit::new();
+        options.set_child_list(true);
+        options.set_subtree(true);
+        options.set_attributes(true);
+        options.set_character_data(true);
+        observer.observe_with_options(&document, &options)?;
+        ELEMENT_CACHE.with(|cache| {
+            *cache.borrow_mut() = Some(ElementCache { document, elements: HashMap::new(), observer, _on_mutation: on_mutation });
+        });
+        Ok(ElementCacheScope { owner: true })
+    }
+}
+
+impl Drop for ElementCacheScope {
+    fn drop(&mut self) {
+        if !self.owner {
+            return;
+        }
+        if let Some(cache) = ELEMENT_CACHE.with(|cache| cache.borrow_mut().take()) {
+            cache.observer.disconnect();
+        }
+    }
+}
+
+// Returns the cached element for `selector` in `document`. The cache is emptied first if the page
+// changed since the last lookup.
+fn cached_element(document: &Document, selector: &str) -> Option<Element> {
+    ELEMENT_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let cache = cache.as_mut().filter(|cache| &cache.document == document)?;
+        if cache.observer.take_records().length() > 0 {
+            cache.elements.clear();
+        }
+        cache.elements.get(selector).filter(|element| element.is_connected()).cloned()
+    })
+}
+
+// Stores a resolved element, unless no cache is active or the selector depends on unobserved state.
+fn cache_element(document: &Document, selector: &str, element: &Element) {
+    if UNCACHEABLE_PSEUDO_CLASSES.iter().any(|pseudo_class| selector.contains(pseudo_class)) {
+        return;
+    }
+    ELEMENT_CACHE.with(|cache| {
+        if let Some(cache) = cache.borrow_mut().as_mut().filter(|cache| &cache.document == document) {
+            cache.elements.insert(selector.to_string(), element.clone());
+        }
+    });
+}
+
+// Unified helper function to get an element by CSS selector, XPath or text, from the element cache
+// while one is active. While a preview runs, elements are looked up in its sandbox, uncached.
+pub(crate) fn get_element(document: &Document, original_selector: &str) -> Result<Element, DomError> {
+    if sandbox::is_active() {
+        return query_element(document, original_selector);
+    }
+    if let Some(element) = cached_element(document, original_selector) {
+        return Ok(element);
+    }
+    let element = query_element(document, original_selector)?;
+    cache_element(document, original_selector, &element);
+    Ok(element)
+}
+
+// Index of the `]` closing the `[` that starts `text`, skipping brackets inside quotes (as in
+// `css:[title="a]"]`) and nested ones (as in `xpath://li[1]`).
+fn closing_bracket(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Length of the selector fallback chain at the start of `text`, e.g. of
+/// `[css:#buy, text:"Buy now"]` in `[css:#buy, text:"Buy now"] extra`, or `None` if `text` does
+/// not start with one (see `selector_candidates`).
+pub(crate) fn selector_chain_len(text: &str) -> Option<usize> {
+    if !text.starts_with('[') {
+        return None;
+    }
+    let end = closing_bracket(text)? + 1;
+    selector_candidates(&text[..end]).map(|_| end)
+}
+
+/// Splits a selector fallback chain, `[<selector>, <selector>, ...]`, into its candidates, in the
+/// order they are tried. The text of a `text:` candidate may be quoted, e.g. `text:"Buy now"`.
+///
+/// # Returns
+/// The candidates, or `None` if `selector` is not a chain of at least two selectors (a CSS
+/// attribute selector such as `[data-test=buy]` is a single selector, not a chain).
+pub(crate) fn selector_candidates(selector: &str) -> Option<Vec<String>> {
+    let selector = selector.trim();
+    if !selector.starts_with('[') || closing_bracket(selector)? != selector.len() - 1 {
+        return None;
+    }
+    let inner = &selector[1..selector.len() - 1];
+    let mut candidates = Vec::new();
+    let (mut depth, mut quote, mut start) = (0, None, 0);
+    for (index, c) in inner.char_indices() {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                candidates.push(&inner[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    candidates.push(&inner[start..]);
+    let candidates: Vec<String> = candidates
+        .into_iter()
+        .map(str::trim)
+        .map(|candidate| match candidate.strip_prefix("text:") {
+            Some(text) => {
+                let unquoted = ['"', '\''].iter().find_map(|&q| text.strip_prefix(q).and_then(|text| text.strip_suffix(q)));
+                format!("text:{}", unquoted.unwrap_or(text))
+            }
+            None => candidate.to_string(),
+        })
+        .collect();
+    (candidates.len() >= 2 && candidates.iter().all(|candidate| !candidate.is_empty())).then_some(candidates)
+}
+
+/// Separates the region from the selector resolved within it in a scoped selector, e.g.
+/// `css:#chat >> text:Send`.
+pub(crate) const SCOPE_SEPARATOR: &str = " >> ";
+
+/// Splits a scoped selector, `<region> >> <selector>`, at its first separator outside brackets
+/// and quotes. The selector is resolved among the descendants of the first element matching the
+/// region, and may itself be scoped.
+///
+/// # Returns
+/// The region and the selector, or `None` if `selector` is not scoped.
+pub(crate) fn split_scope(selector: &str) -> Option<(&str, &str)> {
+    let (mut depth, mut quote) = (0, None);
+    for (index, c) in selector.char_indices() {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, ' ') if depth == 0 && selector[index..].starts_with(SCOPE_SEPARATOR) => {
+                let (region, inner) = (selector[..index].trim(), selector[index + SCOPE_SEPARATOR.len()..].trim());
+                return (!region.is_empty() && !inner.is_empty()).then_some((region, inner));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// The error of a scoped selector whose region could not be found.
+fn region_error(error: DomError, scoped_selector: &str) -> DomError {
+    match error {
+        DomError::ElementNotFound { selector, .. } => DomError::ElementNotFound {
+            message: Some(format!("ElementNotFound: The region '{}' of selector '{}' is not on the page", selector, scoped_selector)),
+            selector: scoped_selector.to_string(),
+        },
+        e => e,
+    }
+}
+
+// The error of a scoped selector whose selector matched nothing within the region.
+fn scoped_error(error: DomError, scoped_selector: &str, region_selector: &str) -> DomError {
+    match error {
+        DomError::ElementNotFound { selector, message } => DomError::ElementNotFound {
+            message: Some(message.unwrap_or_else(|| {
+                format!("ElementNotFound: No element found for selector '{}' within the region '{}'", selector, region_selector)
+            })),
+            selector: scoped_selector.to_string(),
+        },
+        e => e,
+    }
+}
+
+// Tries the candidates of a selector fallback chain in order and returns the result of the first
+// one `query` finds. Candidates that fail for another reason (e.g. an invalid selector) are
+// skipped, and that error is returned only if no candidate matches.
+fn query_candidates<T>(
+    chain: &str,
+    candidates: &[String],
+    query: impl Fn(&str) -> Result<Option<T>, DomError>,
+) -> Result<Option<(T, usize)>, DomError> {
+    let mut first_error = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+        match query(candidate) {
+            Ok(Some(found)) => return Ok(Some((found, index))),
+            Ok(None) => {}
+            Err(e) => {
+                console::warn_1(&format!("Candidate '{}' of selector chain {} failed: {}", candidate, chain, e).into());
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(None), Err)
+}
+
+/// The first candidate of a selector fallback chain that matches an element on the page.
+///
+/// # Returns
+/// * `Ok(Some(String))` with the candidate, if `selector` is a chain and one of its candidates matches.
+/// * `Ok(None)` if `selector` is not a chain, or none of its candidates matches.
+/// * `Err(DomError)` if no candidate matches and one of them could not be resolved.
+pub(crate) fn matching_candidate(selector: &str) -> Result<Option<String>, DomError> {
+    // The candidates of a chain within a region keep the region.
+    let (region, chain) = match split_scope(selector) {
+        Some((region, chain)) => (Some(region), chain),
+        None => (None, selector),
+    };
+    let Some(candidates) = selector_candidates(chain) else { return Ok(None) };
+    let scoped = |candidate: &str| match region {
+        Some(region) => format!("{}{}{}", region, SCOPE_SEPARATOR, candidate),
+        None => candidate.to_string(),
+    };
+    let (_window, document) = get_window_document()?;
+    let found = query_candidates(selector, &candidates, |candidate| match query_element(&document, &scoped(candidate)) {
+        Ok(_) => Ok(Some(())),
+        Err(DomError::ElementNotFound { .. }) => Ok(None),
+        Err(e) => Err(e),
+    })?;
+    Ok(found.map(|((), index)| scoped(&candidates[index])))
+}
+
+// Resolves a selector against the page (the sandbox while a preview runs), bypassing the element cache.
+fn query_element(document: &Document, original_selector: &str) -> Result<Element, DomError> {
+    query_element_in(document, sandbox::root().as_ref(), original_selector)
+}
+
+// Resolves a selector among the descendants of `root`, or in the whole document while `None`.
+fn query_element_in(document: &Document, root: Option<&Element>, original_selector: &str) -> Result<Element, DomError> {
+    if let Some((region_selector, selector)) = split_scope(original_selector) {
+        let region = query_element_in(document, root, region_selector).map_err(|e| region_error(e, original_selector))?;
+        return query_element_in(document, Some(&region), selector).map_err(|e| scoped_error(e, original_selector, region_selector));
+    }
+    if let Some(candidates) = selector_candidates(original_selector) {
+        let found = query_candidates(original_selector, &candidates, |candidate| match query_element_in(document, root, candidate) {
+            Ok(element) => Ok(Some(element)),
+            Err(DomError::ElementNotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        })?;
+        return found
+            .map(|(element, _)| element)
+            .ok_or_else(|| DomError::ElementNotFound { selector: original_selector.to_string(), message: None });
+    }
     if original_selector.starts_with("xpath:") {
         let xpath = original_selector.strip_prefix("xpath:").unwrap_or(original_selector);
         console::log_1(&format!("Using XPath selector: {}", xpath).into());
-        get_element_by_xpath_logic(document, xpath, original_selector)
+        get_element_by_xpath_logic(document, root, xpath, original_selector)
+    } else if let Some(text) = original_selector.strip_prefix("text:") {
+        console::log_1(&format!("Using text selector: {}", text).into());
+        get_element_by_text(document, root, text)?
+            .ok_or_else(|| DomError::ElementNotFound { selector: original_selector.to_string(), message: None })
     } else {
         let css_selector_to_use;
         if original_selector.starts_with("css:") {
@@ -153,8 +490,11 @@ fn get_element(document: &Document, original_selector: &str) -> Result<Element,
             css_selector_to_use = original_selector;
             console::log_1(&format!("Defaulting to CSS selector: {}", css_selector_to_use).into());
         }
-        document
-            .query_selector(css_selector_to_use)
+        let found = match root {
+            Some(root) => root.query_selector(css_selector_to_use),
+            None => document.query_selector(css_selector_to_use),
+        };
+        found
             .map_err(|e| DomError::InvalidSelector {
                 selector: original_selector.to_string(),
                 error: e.as_string().unwrap_or_else(|| "Unknown querySelector error".to_string()),
@@ -163,10 +503,99 @@ fn get_element(document: &Document, original_selector: &str) -> Result<Element,
     }
 }
 
+// Resolves a `text:` selector: the innermost element whose text, `aria-label` or `value` equals
+// `text` or one of its translations (see `locale::term_variants`), compared with `locale::fold`.
+// Failing that, the innermost element whose text contains one of them.
+fn get_element_by_text(document: &Document, root: Option<&Element>, text: &str) -> Result<Option<Element>, DomError> {
+    let variants = locale::term_variants(text);
+    let body: Element = match root {
+        Some(root) => root.clone(),
+        None => document.body().ok_or_else(|| DomError::JsError { message: "Document has no body".to_string() })?.into(),
+    };
+    let elements = body.query_selector_all("*:not(script):not(style):not(noscript)")?;
+    let (mut exact, mut partial): (Option<Element>, Option<Element>) = (None, None);
+    // Matches are found outermost first; a match inside the current one replaces it.
+    let keep_innermost = |best: &mut Option<Element>, element: &Element| {
+        if best.as_ref().is_none_or(|best| best.contains(Some(element))) {
+            *best = Some(element.clone());
+        }
+    };
+    for i in 0..elements.length() {
+        let Some(element) = elements.item(i).and_then(|node| node.dyn_into::<Element>().ok()) else { continue };
+        let labels = [
+            element.text_content().unwrap_or_default(),
+            element.get_attribute("aria-label").unwrap_or_default(),
+            element.get_attribute("value").unwrap_or_default(),
+        ];
+        for label in labels.iter().map(|label| locale::fold(label)).filter(|label| !label.is_empty()) {
+            if variants.contains(&label) {
+                keep_innermost(&mut exact, &element);
+                break;
+            }
+            if variants.iter().any(|variant| label.contains(variant.as_str())) {
+                keep_innermost(&mut partial, &element);
+            }
+        }
+    }
+    Ok(exact.or(partial))
+}
+
+thread_local! {
+    // Whether `ElementHandle` looks a detached element up again by its selector.
+    static RERESOLVE_STALE_ELEMENTS: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Sets whether elements that are detached from the page while a command uses them (e.g. an input
+/// re-rendered between two typed characters) are looked up again by their selector. Enabled by
+/// default; when disabled, such commands fail with `DomError::StaleElement`.
+pub(crate) fn set_reresolve_stale_elements(enabled: bool) {
+    RERESOLVE_STALE_ELEMENTS.with(|reresolve| reresolve.set(enabled));
+}
+
+/// An element resolved from a selector, for commands that keep using it across awaits.
+///
+/// `element()` checks that the element is still connected to the document and, if it is not,
+/// resolves the selector again, so that a command survives the page replacing the element.
+pub(crate) struct ElementHandle {
+    selector: String,
+    element: Element,
+}
+
+impl ElementHandle {
+    /// Resolves `selector` with `get_element`.
+    pub(crate) fn resolve(document: &Document, selector: &str) -> Result<Self, DomError> {
+        Ok(ElementHandle { selector: selector.to_string(), element: get_element(document, selector)? })
+    }
+
+    /// The element, resolved again if it was detached since the last call.
+    ///
+    /// # Returns
+    /// * `Err(DomError::StaleElement)` if the element is detached and re-resolution is disabled or
+    ///   finds nothing.
+    pub(crate) fn element(&mut self) -> Result<&Element, DomError> {
+        if self.element.is_connected() {
+            return Ok(&self.element);
+        }
+        let stale = || DomError::StaleElement { selector: self.selector.clone() };
+        if !RERESOLVE_STALE_ELEMENTS.with(Cell::get) {
+            return Err(stale());
+        }
+        let (_window, document) = get_window_document()?;
+        match get_element(&document, &self.selector) {
+            Ok(element) => {
+                console::log_1(&format!("Element for selector '{}' was detached; resolved it again", self.selector).into());
+                self.element = element;
+                Ok(&self.element)
+            }
+            Err(DomError::ElementNotFound { .. }) => Err(stale()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
 // Helper function to get multiple elements using XPath
-fn get_elements_by_xpath_logic(document: &Document, xpath: &str, original_selector: &str) -> Result<Vec<Element>, DomError> {
-    let result = document
-        .evaluate(xpath, &document) // Corrected as per compiler suggestion
+fn get_elements_by_xpath_logic(document: &Document, root: Option<&Element>, xpath: &str, original_selector: &str) -> Result<Vec<Element>, DomError> {
+    let result = evaluate_xpath(document, root, xpath)
         .map_err(|e| DomError::InvalidSelector {
             selector: original_selector.to_string(),
             error: e.as_string().unwrap_or_else(|| "Unknown XPath error".to_string()),
@@ -177,18 +606,94 @@ fn get_elements_by_xpath_logic(document: &Document, xpath: &str, original_select
         if let Some(element) = node.dyn_ref::<Element>() {
             elements.push(element.clone());
         } else {
-            console::warn_1(&format!("XPath selector '{}' returned a Node that is not an Element.", original_selector).into());
+            console::warn_1(&format!("XPath selector '{}' returned a Node that is not an Element. Use XPATH_EVAL to read text or attribute nodes.", original_selector).into());
         }
     }
     Ok(elements)
 }
 
-// Unified helper function to get all elements by CSS selector or XPath
+// Converts an XPath number result to JSON, keeping whole numbers (e.g. from `count()`) as integers.
+// `NaN` and infinities, which JSON cannot represent, become `null`.
+fn xpath_number_to_json(number: f64) -> serde_json::Value {
+    if number.is_finite() && number.fract() == 0.0 && number.abs() < 9_007_199_254_740_992.0 {
+        serde_json::Value::from(number as i64)
+    } else {
+        serde_json::Number::from_f64(number).map_or(serde_json::Value::Null, serde_json::Value::Number)
+    }
+}
+
+/// Evaluates an arbitrary XPath expression and returns its result, whatever its type.
+///
+/// Unlike selectors, which must resolve to elements, this accepts expressions producing
+/// numbers (e.g. `count(//li)`), strings (e.g. `string(//h1)`), booleans (e.g. `boolean(//form)`),
+/// or node-sets of any node kind, including text and attribute nodes (e.g. `//a/@href`).
+///
+/// # Arguments
+/// * `expression`: The XPath expression, optionally prefixed with `"xpath:"`.
+///
+/// # Returns
+/// * `Ok(String)`: A JSON object with the result `type` and `value`:
+///   - `{"type":"number","value":3}` (`null` for `NaN`)
+///   - `{"type":"string","value":"Welcome"}`
+///   - `{"type":"boolean","value":true}`
+///   - `{"type":"nodes","value":["first","second"]}` with the text content of each node in document order.
+/// * `Err(DomError)`: `InvalidSelector` if the expression is malformed, or another error if the
+///   result could not be read or serialized.
+#[wasm_bindgen]
+pub fn xpath_eval(expression: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to evaluate XPath expression: {}", expression).into());
+    let xpath = expression.strip_prefix("xpath:").unwrap_or(expression);
+    let (_window, document) = get_window_document()?;
+    let result = evaluate_xpath(&document, sandbox::root().as_ref(), xpath)
+        .map_err(|e| DomError::InvalidSelector {
+            selector: expression.to_string(),
+            error: e.as_string().unwrap_or_else(|| "Unknown XPath error".to_string()),
+        })?;
+
+    let read_error = |e: JsValue| DomError::JsError {
+        message: format!("Error reading result of XPath '{}'. Details: {:?}", expression, e.as_string()),
+    };
+    let (result_type, value) = match result.result_type() {
+        XPathResult::NUMBER_TYPE => ("number", xpath_number_to_json(result.number_value().map_err(read_error)?)),
+        XPathResult::STRING_TYPE => ("string", serde_json::Value::String(result.string_value().map_err(read_error)?)),
+        XPathResult::BOOLEAN_TYPE => ("boolean", serde_json::Value::Bool(result.boolean_value().map_err(read_error)?)),
+        _ => {
+            let mut texts = Vec::new();
+            while let Some(node) = result.iterate_next().map_err(read_error)? {
+                texts.push(serde_json::Value::String(node.text_content().unwrap_or_default()));
+            }
+            ("nodes", serde_json::Value::Array(texts))
+        }
+    };
+
+    let json_string = serde_json::to_string(&serde_json::json!({ "type": result_type, "value": value }))
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize XPath result to JSON. Details: {}", e) })?;
+    console::log_1(&format!("Successfully evaluated XPath expression '{}' to a {} result", expression, result_type).into());
+    Ok(json_string)
+}
+
+// Unified helper function to get all elements by CSS selector or XPath. For a selector fallback
+// chain, the elements of the first candidate matching any.
 fn get_all_elements(document: &Document, original_selector: &str) -> Result<Vec<Element>, DomError> {
+    get_all_elements_in(document, sandbox::root().as_ref(), original_selector)
+}
+
+// `get_all_elements` among the descendants of `root`, or in the whole document while `None`.
+fn get_all_elements_in(document: &Document, root: Option<&Element>, original_selector: &str) -> Result<Vec<Element>, DomError> {
+    if let Some((region_selector, selector)) = split_scope(original_selector) {
+        let region = query_element_in(document, root, region_selector).map_err(|e| region_error(e, original_selector))?;
+        return get_all_elements_in(document, Some(&region), selector);
+    }
+    if let Some(candidates) = selector_candidates(original_selector) {
+        let found = query_candidates(original_selector, &candidates, |candidate| {
+            get_all_elements_in(document, root, candidate).map(|elements| Some(elements).filter(|elements| !elements.is_empty()))
+        })?;
+        return Ok(found.map(|(elements, _)| elements).unwrap_or_default());
+    }
     if original_selector.starts_with("xpath:") {
         let xpath = original_selector.strip_prefix("xpath:").unwrap_or(original_selector);
         console::log_1(&format!("Using XPath selector for all elements: {}", xpath).into());
-        get_elements_by_xpath_logic(document, xpath, original_selector)
+        get_elements_by_xpath_logic(document, root, xpath, original_selector)
     } else {
         let css_selector_to_use;
         if original_selector.starts_with("css:") {
@@ -198,8 +703,11 @@ fn get_all_elements(document: &Document, original_selector: &str) -> Result<Vec<
             css_selector_to_use = original_selector;
             console::log_1(&format!("Defaulting to CSS selector for all elements: {}", css_selector_to_use).into());
         }
-        let node_list: NodeList = document
-            .query_selector_all(css_selector_to_use)
+        let node_list = match root {
+            Some(root) => root.query_selector_all(css_selector_to_use),
+            None => document.query_selector_all(css_selector_to_use),
+        };
+        let node_list: NodeList = node_list
             .map_err(|e| DomError::InvalidSelector {
                 selector: original_selector.to_string(),
                 error: e.as_string().unwrap_or_else(|| "Unknown querySelectorAll error".to_string()),
@@ -260,7 +768,8 @@ pub fn click_element(selector: &str) -> Result<(), DomError> {
 /// * `Err(DomError)` if the element is not found, not an `HTMLInputElement`, or another error occurs.
 #[wasm_bindgen]
 pub fn type_in_element(selector: &str, text: &str) -> Result<(), DomError> {
-    console::log_1(&format!("Attempting to type '{}' in element with selector: {}", text, selector).into());
+    // The text itself is not logged, since it may be a secret (see `RustAgent::set_secret`).
+    console::log_1(&format!("Attempting to type {} characters in element with selector: {}", text.chars().count(), selector).into());
     let (_window, document) = get_window_document()?;
 
     let element = get_element(&document, selector)?;
@@ -272,12 +781,464 @@ pub fn type_in_element(selector: &str, text: &str) -> Result<(), DomError> {
             expected_type: "HtmlInputElement".to_string(),
         })?;
 
-    input_element.set_value(text);
+    // Typed text is normalized to NFC, the form keyboards produce and servers usually expect.
+    input_element.set_value(&locale::nfc(text));
     
-    console::log_1(&format!("Successfully typed '{}' in element with selector: {}", text, selector).into());
+    console::log_1(&format!("Successfully typed {} characters in element with selector: {}", text.chars().count(), selector).into());
+    Ok(())
+}
+
+// Dispatches a bubbling, cancelable keyboard event for `key` on `target`.
+// Returns `false` if a handler called `preventDefault`.
+fn dispatch_key_event(target: &Element, event_type: &str, key: &str) -> Result<bool, DomError> {
+    let event_init = web_sys::KeyboardEventInit::new();
+    event_init.set_bubbles(true);
+    event_init.set_cancelable(true);
+    event_init.set_key(key);
+    let event = web_sys::KeyboardEvent::new_with_keyboard_event_init_dict(event_type, &event_init)
+        .map_err(|e| DomError::JsError { message: format!("Failed to create {} event: {:?}", event_type, e.as_string()) })?;
+    target.dispatch_event(&event)
+        .map_err(|e| DomError::JsError { message: format!("Failed to dispatch {} event: {:?}", event_type, e.as_string()) })
+}
+
+// Dispatches an `input` event announcing that `data` was inserted (or, with `None`, that the
+// content was deleted), as browsers do after each keystroke.
+fn dispatch_input_event(target: &Element, data: Option<&str>) -> Result<(), DomError> {
+    let event_init = web_sys::InputEventInit::new();
+    event_init.set_bubbles(true);
+    event_init.set_data(data);
+    event_init.set_input_type(if data.is_some() { "insertText" } else { "deleteContentBackward" });
+    let event = web_sys::InputEvent::new_with_event_init_dict("input", &event_init)
+        .map_err(|e| DomError::JsError { message: format!("Failed to create input event: {:?}", e.as_string()) })?;
+    target.dispatch_event(&event)
+        .map_err(|e| DomError::JsError { message: format!("Failed to dispatch input event: {:?}", e.as_string()) })?;
+    Ok(())
+}
+
+/// Types the given text into an input element one character at a time, the way a person would.
+/// Like `type_in_element`, it replaces the current value.
+///
+/// The element is focused and cleared, then each character gets `keydown`, `keypress`, an
+/// `input` event after the value is updated, and `keyup`, with `delay_ms` between characters.
+/// A `change` event is dispatched at the end. Characters whose `keydown` or `keypress` is
+/// cancelled by the page are not inserted, as in a browser. Use it for autocomplete widgets and
+/// validators that only react to incremental input.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression for the input element.
+/// * `text`: The text to type.
+/// * `delay_ms`: The delay between characters, in milliseconds.
+///
+/// # Returns
+/// * `Ok(())` if typing was successful.
+/// * `Err(DomError)` if the element is not found, not an `HTMLInputElement`, or the events could not be dispatched.
+#[wasm_bindgen]
+pub async fn type_like_human(selector: &str, text: &str, delay_ms: u32) -> Result<(), DomError> {
+    // The text itself is not logged, since it may be a secret (see `RustAgent::set_secret`).
+    console::log_1(&format!("Attempting to type {} characters one by one in element with selector: {}", text.graphemes(true).count(), selector).into());
+    // Normalized to NFC first, so that an accented letter is typed as one key, not as a base
+    // letter followed by a combining mark.
+    let text = locale::nfc(text);
+    let (_window, document) = get_window_document()?;
+    let mut handle = ElementHandle::resolve(&document, selector)?;
+    // The input is looked up through the handle before every character, since frameworks may
+    // replace it while typing; a replacement input is focused before typing continues.
+    let as_input = |element: &Element| {
+        element.clone().dyn_into::<HtmlInputElement>().map_err(|_| DomError::ElementTypeError {
+            selector: selector.to_string(),
+            expected_type: "HtmlInputElement".to_string(),
+        })
+    };
+
+    let mut input_element = as_input(handle.element()?)?;
+    input_element.focus().map_err(DomError::from)?;
+    if !input_element.value().is_empty() {
+        input_element.set_value("");
+        dispatch_input_event(&input_element, None)?;
+    }
+    // One keystroke per grapheme cluster rather than per `char`, so that an emoji sequence or a
+    // letter with combining marks is inserted whole, as an IME would, never half-typed.
+    for (index, key) in text.graphemes(true).enumerate() {
+        if index > 0 && delay_ms > 0 {
+            TimeoutFuture::new(delay_ms).await;
+            let current = as_input(handle.element()?)?;
+            if current != input_element {
+                current.focus().map_err(DomError::from)?;
+                input_element = current;
+            }
+        }
+        let allowed = dispatch_key_event(&input_element, "keydown", key)? && dispatch_key_event(&input_element, "keypress", key)?;
+        if allowed {
+            input_element.set_value(&format!("{}{}", input_element.value(), key));
+            dispatch_input_event(&input_element, Some(key))?;
+        }
+        dispatch_key_event(&input_element, "keyup", key)?;
+    }
+    dispatch_simple_event(&input_element, "change")?;
+
+    console::log_1(&format!("Successfully typed {} characters one by one in element with selector: {}", text.graphemes(true).count(), selector).into());
+    Ok(())
+}
+
+/// How `type_and_select` chooses among the suggestions it waits for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SuggestionPick {
+    /// The suggestion at this 1-based position, written `#2` in commands.
+    Position(usize),
+    /// The first suggestion whose text equals this text or, failing that, contains it (compared with
+    /// `locale::fold`, so case and Unicode normalization do not matter).
+    Text(String),
+}
+
+impl SuggestionPick {
+    /// Parses `#<position>` (from 1) or a suggestion text. Returns `None` for `#0` or an empty text.
+    pub fn parse(pick: &str) -> Option<SuggestionPick> {
+        let pick = pick.trim();
+        match pick.strip_prefix('#').map(str::parse::<usize>) {
+            Some(Ok(0)) => None,
+            Some(Ok(position)) => Some(SuggestionPick::Position(position)),
+            _ if pick.is_empty() => None,
+            _ => Some(SuggestionPick::Text(pick.to_string())),
+        }
+    }
+
+    /// Returns the index, in `texts`, of the suggestion to pick, if it is there.
+    pub fn find(&self, texts: &[String]) -> Option<usize> {
+        match self {
+            SuggestionPick::Position(position) => Some(position - 1).filter(|&index| index < texts.len()),
+            SuggestionPick::Text(wanted) => {
+                let wanted = locale::fold(wanted);
+                let texts: Vec<String> = texts.iter().map(|text| locale::fold(text)).collect();
+                texts.iter().position(|text| *text == wanted).or_else(|| texts.iter().position(|text| text.contains(&wanted)))
+            }
+        }
+    }
+}
+
+/// Default time `type_and_select` waits for the wanted suggestion to appear.
+pub const DEFAULT_SUGGESTION_TIMEOUT_MS: u32 = 5000;
+
+/// Types a query into an autocomplete field and picks one of the suggestions it brings up.
+///
+/// The query is typed one character at a time (see `type_like_human`), so the widget sees the
+/// keystrokes it listens to. The elements matching `suggestion_selector` are then polled until
+/// the wanted suggestion is there, and it receives `mousedown`, `mouseup` and `click` events
+/// (many widgets select on `mousedown`, before the field loses focus).
+///
+/// # Arguments
+/// * `input_selector`: The selector of the input to type into.
+/// * `query`: The text to type.
+/// * `suggestion_selector`: The selector matching every suggestion, e.g. `css:#results>li`.
+/// * `pick`: Which suggestion to pick.
+/// * `delay_ms`: The delay between typed characters, in milliseconds.
+/// * `timeout_ms`: How long to wait for the suggestion. Defaults to `DEFAULT_SUGGESTION_TIMEOUT_MS`,
+///   or the timeout of `wait` if it is longer.
+/// * `wait`: The default timeout and the polling interval (see `WaitConfig`).
+///
+/// # Returns
+/// * `Ok(String)` with the text of the picked suggestion.
+/// * `Err(DomError::ElementNotFound)` if the wanted suggestion did not appear in time.
+/// * `Err(DomError)` if the input is not found or not an input, or a selector is invalid.
+pub async fn type_and_select(
+    input_selector: &str,
+    query: &str,
+    suggestion_selector: &str,
+    pick: &SuggestionPick,
+    delay_ms: u32,
+    timeout_ms: Option<u32>,
+    wait: &WaitConfig,
+) -> Result<String, DomError> {
+    type_like_human(input_selector, query, delay_ms).await?;
+
+    let (window, document) = get_window_document()?;
+    let timeout = wait.timeout(timeout_ms, DEFAULT_SUGGESTION_TIMEOUT_MS);
+    let mut waited = 0;
+    loop {
+        let suggestions = get_all_elements(&document, suggestion_selector)?;
+        let texts: Vec<String> = suggestions.iter().map(|element| element.text_content().unwrap_or_default()).collect();
+        if let Some(index) = pick.find(&texts) {
+            let suggestion = &suggestions[index];
+            suggestion.scroll_into_view();
+            let rect = suggestion.get_bounding_client_rect();
+            dispatch_mouse_events_at(&window, suggestion, rect.left() + rect.width() / 2.0, rect.top() + rect.height() / 2.0, &["mousedown", "mouseup", "click"])?;
+            let text = texts[index].trim().to_string();
+            console::log_1(&format!("Selected suggestion '{}' matching selector: {}", text, suggestion_selector).into());
+            return Ok(text);
+        }
+        if waited >= timeout {
+            return Err(DomError::ElementNotFound {
+                selector: suggestion_selector.to_string(),
+                message: Some(format!("No suggestion matching {:?} appeared within {}ms ({} suggestions found)", pick, timeout, texts.len())),
+            });
+        }
+        TimeoutFuture::new(wait.interval_ms).await;
+        waited += wait.interval_ms;
+    }
+}
+
+/// Default time `search_and_open` waits for a matching search result to appear.
+pub const DEFAULT_SEARCH_RESULTS_TIMEOUT_MS: u32 = 10000;
+
+/// Lowest `locale::similarity` a search result must reach for `search_and_open` to open it.
+pub const MIN_SEARCH_SCORE: f64 = 0.3;
+
+/// Searches with a search box and opens the result whose text best matches what is wanted.
+///
+/// The search terms are typed one character at a time (see `type_like_human`) and Enter is
+/// pressed. Unless the page cancels the Enter key, a `submit` event is also dispatched on the
+/// input's form, for pages that search from a submit handler; being synthetic, it never makes
+/// the browser load a new page, so the results must be shown on the same page (live or
+/// single-page-app search). The elements matching `result_selector` are then polled until one
+/// scores at least `MIN_SEARCH_SCORE` against `wanted` (see `locale::similarity`), and the best
+/// one is clicked.
+///
+/// # Arguments
+/// * `input_selector`: The selector of the search box.
+/// * `terms`: The text to search for.
+/// * `result_selector`: The selector matching every result link, e.g. `css:#results a`.
+/// * `wanted`: The text of the result to open, scored against each result's text.
+/// * `delay_ms`: The delay between typed characters, in milliseconds.
+/// * `timeout_ms`: How long to wait for a matching result. Defaults to `DEFAULT_SEARCH_RESULTS_TIMEOUT_MS`,
+///   or the timeout of `wait` if it is longer.
+/// * `wait`: The default timeout and the polling interval (see `WaitConfig`).
+///
+/// # Returns
+/// * `Ok((String, f64))` with the text of the opened result and its score.
+/// * `Err(DomError::ElementNotFound)` if no result scored high enough in time.
+/// * `Err(DomError)` if the search box is not found or not an input, or a selector is invalid.
+pub async fn search_and_open(
+    input_selector: &str,
+    terms: &str,
+    result_selector: &str,
+    wanted: &str,
+    delay_ms: u32,
+    timeout_ms: Option<u32>,
+    wait: &WaitConfig,
+) -> Result<(String, f64), DomError> {
+    type_like_human(input_selector, terms, delay_ms).await?;
+
+    let (_window, document) = get_window_document()?;
+    let input = get_element(&document, input_selector)?;
+    let submit = dispatch_key_event(&input, "keydown", "Enter")? && dispatch_key_event(&input, "keypress", "Enter")?;
+    dispatch_key_event(&input, "keyup", "Enter")?;
+    if submit {
+        if let Some(form) = input.closest("form").map_err(DomError::from)? {
+            let event_init = web_sys::EventInit::new();
+            event_init.set_bubbles(true);
+            event_init.set_cancelable(true);
+            let event = web_sys::Event::new_with_event_init_dict("submit", &event_init)
+                .map_err(|e| DomError::JsError { message: format!("Failed to create submit event: {:?}", e.as_string()) })?;
+            form.dispatch_event(&event)
+                .map_err(|e| DomError::JsError { message: format!("Failed to dispatch submit event: {:?}", e.as_string()) })?;
+        }
+    }
+
+    let timeout = wait.timeout(timeout_ms, DEFAULT_SEARCH_RESULTS_TIMEOUT_MS);
+    let mut waited = 0;
+    loop {
+        let results = get_all_elements(&document, result_selector)?;
+        let best = results
+            .iter()
+            .map(|element| {
+                let text = element.text_content().unwrap_or_default().trim().to_string();
+                let score = locale::similarity(wanted, &text);
+                (element, text, score)
+            })
+            .filter(|(_, _, score)| *score >= MIN_SEARCH_SCORE)
+            .max_by(|(_, _, a), (_, _, b)| a.total_cmp(b));
+        if let Some((element, text, score)) = best {
+            let link = element.dyn_ref::<HtmlElement>().ok_or_else(|| DomError::ElementTypeError {
+                selector: result_selector.to_string(),
+                expected_type: "HtmlElement".to_string(),
+            })?;
+            link.scroll_into_view();
+            link.click();
+            console::log_1(&format!("Opened search result '{}' (score {:.2}) matching selector: {}", text, score, result_selector).into());
+            return Ok((text, score));
+        }
+        if waited >= timeout {
+            return Err(DomError::ElementNotFound {
+                selector: result_selector.to_string(),
+                message: Some(format!(
+                    "No search result resembling {:?} appeared within {}ms ({} results found)",
+                    wanted, timeout, results.len()
+                )),
+            });
+        }
+        TimeoutFuture::new(wait.interval_ms).await;
+        waited += wait.interval_ms;
+    }
+}
+
+// Dispatches a bubbling event of `event_type` (e.g. `input`, `change`) on `target`.
+fn dispatch_simple_event(target: &Element, event_type: &str) -> Result<(), DomError> {
+    let event_init = web_sys::EventInit::new();
+    event_init.set_bubbles(true);
+    let event = web_sys::Event::new_with_event_init_dict(event_type, &event_init)
+        .map_err(|e| DomError::JsError { message: format!("Failed to create {} event: {:?}", event_type, e.as_string()) })?;
+    target.dispatch_event(&event)
+        .map_err(|e| DomError::JsError { message: format!("Failed to dispatch {} event: {:?}", event_type, e.as_string()) })?;
     Ok(())
 }
 
+/// Maximum number of arrow-key presses `set_range` sends to an ARIA slider.
+const SLIDER_MAX_KEY_PRESSES: u32 = 1000;
+
+// Reads the numeric `aria-valuenow` of an ARIA slider.
+fn slider_value(element: &Element) -> Option<f64> {
+    element.get_attribute("aria-valuenow").and_then(|value| value.trim().parse::<f64>().ok())
+}
+
+/// Sets the value of a range control: an `<input type="range">` or an ARIA slider (`role="slider"`).
+///
+/// For a range input, the value is set (the browser clamps it to `min`/`max` and snaps it to
+/// `step`) and `input` and `change` events are dispatched. An ARIA slider is focused and sent
+/// `ArrowRight`/`ArrowLeft` key presses until its `aria-valuenow` reaches the value, or until it
+/// would move past it or stops moving (e.g. at its bounds), since such widgets only change
+/// through their own key handlers.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+/// * `value`: The value to set.
+///
+/// # Returns
+/// * `Ok(String)` with the value the control ended up with.
+/// * `Err(DomError::ElementTypeError)` if the element is neither a range input nor an ARIA slider
+///   with a numeric `aria-valuenow`.
+/// * `Err(DomError)` if the element is not found or the events could not be dispatched.
+#[wasm_bindgen]
+pub fn set_range(selector: &str, value: f64) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to set range {} to {}", selector, value).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+
+    if let Some(input) = element.dyn_ref::<HtmlInputElement>().filter(|input| input.type_() == "range") {
+        input.set_value(&value.to_string());
+        dispatch_simple_event(&element, "input")?;
+        dispatch_simple_event(&element, "change")?;
+        console::log_1(&format!("Successfully set range {} to {}", selector, input.value()).into());
+        return Ok(input.value());
+    }
+
+    let is_slider = element.get_attribute("role").is_some_and(|role| role.split_whitespace().any(|r| r == "slider"));
+    let mut current = slider_value(&element).filter(|_| is_slider).ok_or_else(|| DomError::ElementTypeError {
+        selector: selector.to_string(),
+        expected_type: "input[type=range] or ARIA slider with aria-valuenow".to_string(),
+    })?;
+    if let Some(html_element) = element.dyn_ref::<HtmlElement>() {
+        html_element.focus().map_err(DomError::from)?;
+    }
+    for _ in 0..SLIDER_MAX_KEY_PRESSES {
+        if current == value {
+            break;
+        }
+        let increasing = value > current;
+        let key = if increasing { "ArrowRight" } else { "ArrowLeft" };
+        dispatch_key_event(&element, "keydown", key)?;
+        dispatch_key_event(&element, "keyup", key)?;
+        let next = slider_value(&element).unwrap_or(current);
+        if next == current {
+            break; // The slider does not move any further
+        }
+        if (increasing && next > value) || (!increasing && next < value) {
+            // One more step would pass the value: keep whichever of the two is closer.
+            if (next - value).abs() > (current - value).abs() {
+                let back = if increasing { "ArrowLeft" } else { "ArrowRight" };
+                dispatch_key_event(&element, "keydown", back)?;
+                dispatch_key_event(&element, "keyup", back)?;
+                current = slider_value(&element).unwrap_or(next);
+            } else {
+                current = next;
+            }
+            break;
+        }
+        current = next;
+    }
+    console::log_1(&format!("Slider {} is now at {}", selector, current).into());
+    Ok(current.to_string())
+}
+
+/// Selector of the editable root searched for inside an element given to `rich_text_type` that is
+/// not editable itself (e.g. Quill's `.ql-editor` inside its container).
+const CONTENT_EDITABLE_SELECTOR: &str = "[contenteditable]:not([contenteditable='false'])";
+
+// Returns the editable root for `element`: the element itself if it is editable, else its first
+// editable descendant.
+fn editable_root(element: &Element) -> Result<Option<HtmlElement>, DomError> {
+    if let Some(html_element) = element.dyn_ref::<HtmlElement>().filter(|e| e.is_content_editable()) {
+        return Ok(Some(html_element.clone()));
+    }
+    let descendant = element.query_selector(CONTENT_EDITABLE_SELECTOR).map_err(DomError::from)?;
+    Ok(descendant.and_then(|d| d.dyn_into::<HtmlElement>().ok()))
+}
+
+/// Types text into a rich text editor: a `contenteditable` element, or an editor such as
+/// ProseMirror or Quill whose container holds one.
+///
+/// Such editors keep their own document model and ignore direct DOM changes, so the text is
+/// inserted the way the browser inserts typed text: the editor is focused, the caret is moved to
+/// the end of its content, and `document.execCommand('insertText')` is used. Where that command is
+/// unavailable, a cancelable `beforeinput` event is dispatched; if the editor does not handle it
+/// (by cancelling it), the text is inserted at the caret and an `input` event is dispatched.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression for the editor.
+/// * `text`: The text to insert after the existing content.
+///
+/// # Returns
+/// * `Ok(String)` with the text content of the editor after typing.
+/// * `Err(DomError::ElementTypeError)` if neither the element nor a descendant is editable.
+/// * `Err(DomError)` if the element is not found or the text could not be inserted.
+#[wasm_bindgen]
+pub fn rich_text_type(selector: &str, text: &str) -> Result<String, DomError> {
+    // The text itself is not logged, since it may be a secret (see `RustAgent::set_secret`).
+    console::log_1(&format!("Attempting to type {} characters in rich text editor with selector: {}", text.chars().count(), selector).into());
+    let normalized = locale::nfc(text);
+    let text = normalized.as_str();
+    let (window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    let editor = editable_root(&element)?.ok_or_else(|| DomError::ElementTypeError {
+        selector: selector.to_string(),
+        expected_type: "contenteditable element".to_string(),
+    })?;
+
+    editor.focus().map_err(DomError::from)?;
+    let selection = window
+        .get_selection()
+        .map_err(DomError::from)?
+        .ok_or_else(|| DomError::JsError { message: "Failed to get the document selection".to_string() })?;
+    let range = document.create_range().map_err(DomError::from)?;
+    range.select_node_contents(&editor).map_err(DomError::from)?;
+    range.collapse_with_to_start(false);
+    selection.remove_all_ranges().map_err(DomError::from)?;
+    selection.add_range(&range).map_err(DomError::from)?;
+
+    let inserted = match document.dyn_ref::<web_sys::HtmlDocument>() {
+        Some(html_document) => html_document.exec_command_with_show_ui_and_value("insertText", false, text).unwrap_or(false),
+        None => false,
+    };
+    if !inserted {
+        let event_init = web_sys::InputEventInit::new();
+        event_init.set_bubbles(true);
+        event_init.set_cancelable(true);
+        event_init.set_data(Some(text));
+        event_init.set_input_type("insertText");
+        let before_input = web_sys::InputEvent::new_with_event_init_dict("beforeinput", &event_init)
+            .map_err(|e| DomError::JsError { message: format!("Failed to create beforeinput event: {:?}", e.as_string()) })?;
+        let unhandled = editor.dispatch_event(&before_input)
+            .map_err(|e| DomError::JsError { message: format!("Failed to dispatch beforeinput event: {:?}", e.as_string()) })?;
+        if unhandled {
+            range.insert_node(&document.create_text_node(text)).map_err(DomError::from)?;
+            range.collapse_with_to_start(false);
+            dispatch_input_event(&editor, Some(text))?;
+        }
+    }
+
+    let content = editor.text_content().unwrap_or_default();
+    console::log_1(&format!("Successfully typed in rich text editor with selector: {}", selector).into());
+    Ok(content)
+}
+
 /// Retrieves the inner text content of an element identified by the selector.
 /// The element should be an `HtmlElement` or subclass.
 ///
@@ -363,15 +1324,46 @@ pub fn get_element_attribute(selector: &str, attribute_name: &str) -> Result<Str
     }
 }
 
-/// Waits for an element matching the selector to exist in the DOM within a specified timeout.
-///
-/// Polls the DOM at regular intervals (currently 100ms) until the element is found
+/// Default time `wait_for_element` waits for an element to appear.
+pub const DEFAULT_WAIT_TIMEOUT_MS: u32 = 5000;
+/// Default interval between two checks of the page while waiting.
+pub const DEFAULT_WAIT_INTERVAL_MS: u32 = 100;
+
+/// The default timeout and the polling interval of the commands that wait for something to happen
+/// (`WAIT_FOR_ELEMENT`, `TYPE_AND_SELECT`, `SEARCH_AND_OPEN`, `LOGIN`, `WAIT_FOR_WS_MESSAGE`) and of
+/// the conditions of tasks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaitConfig {
+    /// How long a wait lasts when the command gives no timeout. Commands with a longer default of
+    /// their own (`SEARCH_AND_OPEN`, `LOGIN`) keep it.
+    pub timeout_ms: u32,
+    /// How often the page is checked while waiting.
+    pub interval_ms: u32,
+}
+
+impl Default for WaitConfig {
+    fn default() -> Self {
+        WaitConfig { timeout_ms: DEFAULT_WAIT_TIMEOUT_MS, interval_ms: DEFAULT_WAIT_INTERVAL_MS }
+    }
+}
+
+impl WaitConfig {
+    /// The timeout of a wait: `timeout_ms` if the command gives one, otherwise the longer of the
+    /// configured timeout and `command_default_ms`, the command's own default.
+    pub fn timeout(&self, timeout_ms: Option<u32>, command_default_ms: u32) -> u32 {
+        timeout_ms.unwrap_or(self.timeout_ms.max(command_default_ms))
+    }
+}
+
+/// Waits for an element matching the selector to exist in the DOM within a specified timeout.
+///
+/// Polls the DOM at regular intervals (`DEFAULT_WAIT_INTERVAL_MS`) until the element is found
 /// or the timeout is reached.
 ///
 /// # Arguments
 /// * `selector`: A string representing a CSS selector or an XPath expression.
 ///   If no prefix is provided, it defaults to a CSS selector.
-/// * `timeout_ms`: An optional timeout in milliseconds. If `None`, a default timeout (5000ms) is used.
+/// * `timeout_ms`: An optional timeout in milliseconds. If `None`, `DEFAULT_WAIT_TIMEOUT_MS` is used.
 ///
 /// # Returns
 /// * `Ok(())` if the element appears within the timeout.
@@ -380,12 +1372,15 @@ pub fn get_element_attribute(selector: &str, attribute_name: &str) -> Result<Str
 /// * `Err(DomError)` for other errors, such as an invalid selector.
 #[wasm_bindgen]
 pub async fn wait_for_element(selector: &str, timeout_ms: Option<u32>) -> Result<(), DomError> {
-    const DEFAULT_TIMEOUT_MS: u32 = 5000; // Default timeout: 5 seconds
-    const INTERVAL_MS: u32 = 100; // Polling interval: 100 milliseconds
-    let timeout_duration = timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS);
+    wait_for_element_with(selector, timeout_ms, &WaitConfig::default()).await
+}
+
+/// Like `wait_for_element`, with the default timeout and the polling interval taken from `wait`.
+pub async fn wait_for_element_with(selector: &str, timeout_ms: Option<u32>, wait: &WaitConfig) -> Result<(), DomError> {
+    let timeout_duration = timeout_ms.unwrap_or(wait.timeout_ms);
 
     let main_future = async {
-        let mut interval = IntervalStream::new(INTERVAL_MS);
+        let mut interval = IntervalStream::new(wait.interval_ms);
         loop {
             match element_exists(selector) {
                 Ok(true) => return Ok(()),
@@ -502,6 +1497,243 @@ pub fn get_all_elements_attributes(selector: &str, attribute_name: &str) -> Resu
     Ok(json_string)
 }
 
+/// Retrieves every attribute of the single element matching the selector as a JSON object.
+///
+/// Complements `get_all_elements_attributes` (one attribute across many elements) by letting
+/// callers inspect an unknown element in one step.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(String)` containing a JSON object mapping attribute names to their values
+///   (e.g., `{"class":"btn primary","id":"submit","type":"submit"}`). Returns `{}` if the
+///   element has no attributes.
+/// * `Err(DomError)` if the element is not found or an error occurs during JSON serialization.
+#[wasm_bindgen]
+pub fn get_element_attributes(selector: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to get all attributes of element with selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+
+    let mut attributes = serde_json::Map::new();
+    for name in element.get_attribute_names().iter().filter_map(|name| name.as_string()) {
+        let value = element.get_attribute(&name).unwrap_or_default();
+        attributes.insert(name, serde_json::Value::String(value));
+    }
+
+    let json_string = serde_json::to_string(&attributes)
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize attributes to JSON. Details: {}", e) })?;
+
+    console::log_1(&format!("Successfully retrieved {} attributes for selector '{}'", attributes.len(), selector).into());
+    Ok(json_string)
+}
+
+/// Retrieves the `dataset` (all `data-*` attributes) of the element matching the selector as a JSON object.
+///
+/// Keys use the browser's `dataset` naming, i.e. `data-user-id` becomes `userId`.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(String)` containing a JSON object of the element's dataset (e.g., `{"userId":"42","state":"open"}`).
+///   Returns `{}` if the element has no `data-*` attributes.
+/// * `Err(DomError)` if the element is not found, is not an `HtmlElement`, or an error occurs during JSON serialization.
+#[wasm_bindgen]
+pub fn get_element_dataset(selector: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to get dataset of element with selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+
+    let html_element = element
+        .dyn_into::<HtmlElement>()
+        .map_err(|_| DomError::ElementTypeError {
+            selector: selector.to_string(),
+            expected_type: "HtmlElement".to_string(),
+        })?;
+    let dataset = html_element.dataset();
+
+    let mut entries = serde_json::Map::new();
+    for key in js_sys::Object::keys(dataset.unchecked_ref::<js_sys::Object>()).iter().filter_map(|key| key.as_string()) {
+        if let Some(value) = dataset.get(&key) {
+            entries.insert(key, serde_json::Value::String(value));
+        }
+    }
+
+    let json_string = serde_json::to_string(&entries)
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize dataset to JSON. Details: {}", e) })?;
+
+    console::log_1(&format!("Successfully retrieved {} dataset entries for selector '{}'", entries.len(), selector).into());
+    Ok(json_string)
+}
+
+// Computes the implicit ARIA role of an element from its tag (and type, for inputs),
+// following the common cases of the HTML-AAM mapping. Returns `None` for generic elements.
+fn implicit_aria_role(element: &Element) -> Option<&'static str> {
+    let tag = element.tag_name().to_lowercase();
+    let role = match tag.as_str() {
+        "a" | "area" if element.has_attribute("href") => "link",
+        "button" => "button",
+        "input" => {
+            let input_type = element.get_attribute("type").unwrap_or_default().to_lowercase();
+            match input_type.as_str() {
+                "checkbox" => "checkbox",
+                "radio" => "radio",
+                "range" => "slider",
+                "number" => "spinbutton",
+                "button" | "submit" | "reset" | "image" => "button",
+                "search" => "searchbox",
+                "hidden" => return None,
+                _ if element.has_attribute("list") => "combobox",
+                _ => "textbox",
+            }
+        }
+        "textarea" => "textbox",
+        "select" => {
+            let size = element.get_attribute("size").and_then(|s| s.parse::<u32>().ok()).unwrap_or(0);
+            if element.has_attribute("multiple") || size > 1 { "listbox" } else { "combobox" }
+        }
+        "option" => "option",
+        "img" if element.get_attribute("alt").is_none_or(|alt| !alt.is_empty()) => "img",
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => "heading",
+        "ul" | "ol" | "menu" => "list",
+        "li" => "listitem",
+        "nav" => "navigation",
+        "main" => "main",
+        "aside" => "complementary",
+        "form" => "form",
+        "dialog" => "dialog",
+        "table" => "table",
+        "tr" => "row",
+        "td" => "cell",
+        "th" => "columnheader",
+        "progress" => "progressbar",
+        "details" => "group",
+        "summary" => "button",
+        "hr" => "separator",
+        _ => return None,
+    };
+    Some(role)
+}
+
+// Reads an ARIA state attribute as JSON: "true"/"false" become booleans, a missing attribute
+// becomes `null`, and any other token (e.g. "mixed") is returned as a string.
+fn aria_state_value(element: &Element, attribute_name: &str) -> Option<serde_json::Value> {
+    element.get_attribute(attribute_name).map(|value| match value.trim().to_lowercase().as_str() {
+        "true" => serde_json::Value::Bool(true),
+        "false" => serde_json::Value::Bool(false),
+        other => serde_json::Value::String(other.to_string()),
+    })
+}
+
+// Resolves the role of an element: the first token of an explicit `role` attribute, otherwise
+// its implicit role, otherwise "generic".
+fn element_role(element: &Element) -> String {
+    element
+        .get_attribute("role")
+        .and_then(|role| role.split_whitespace().next().map(|r| r.to_lowercase()))
+        .or_else(|| implicit_aria_role(element).map(|r| r.to_string()))
+        .unwrap_or_else(|| "generic".to_string())
+}
+
+/// Retrieves the ARIA role of the element matching the selector.
+///
+/// The explicit `role` attribute takes precedence (its first token, as browsers do); otherwise
+/// the implicit role of the element is derived from its tag, e.g. `<button>` is `button`,
+/// `<a href>` is `link` and `<input type="checkbox">` is `checkbox`.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(String)` containing the role, or `"generic"` if the element has no explicit or implicit role.
+/// * `Err(DomError)` if the element is not found or another error occurs.
+#[wasm_bindgen]
+pub fn get_element_role(selector: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to get role of element with selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+
+    let role = element_role(&element);
+
+    console::log_1(&format!("Successfully retrieved role '{}' for selector '{}'", role, selector).into());
+    Ok(role)
+}
+
+/// Retrieves the role and common ARIA widget states of the element matching the selector as a JSON object.
+///
+/// Explicit `aria-*` attributes take precedence. When they are absent, native state is used
+/// where HTML defines it: `checked` for checkboxes/radios (`"mixed"` when indeterminate),
+/// `selected` for `<option>`, `expanded` for `<details>`, and `disabled` via the `:disabled`
+/// pseudo-class (which accounts for disabled `<fieldset>` ancestors). States that do not
+/// apply to the element are `null`.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(String)` containing a JSON object, e.g.
+///   `{"role":"button","expanded":false,"checked":null,"selected":null,"pressed":null,"disabled":false,"hidden":false}`.
+/// * `Err(DomError)` if the element is not found or an error occurs during JSON serialization.
+#[wasm_bindgen]
+pub fn get_aria_state(selector: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to get ARIA state of element with selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    let role = element_role(&element);
+    let tag = element.tag_name().to_lowercase();
+
+    let checked = aria_state_value(&element, "aria-checked").or_else(|| {
+        element.dyn_ref::<HtmlInputElement>()
+            .filter(|input| input.type_() == "checkbox" || input.type_() == "radio")
+            .map(|input| {
+                if input.indeterminate() {
+                    serde_json::Value::String("mixed".to_string())
+                } else {
+                    serde_json::Value::Bool(input.checked())
+                }
+            })
+    });
+    let selected = aria_state_value(&element, "aria-selected").or_else(|| {
+        if tag == "option" {
+            element.matches(":checked").ok().map(serde_json::Value::Bool)
+        } else {
+            None
+        }
+    });
+    let expanded = aria_state_value(&element, "aria-expanded").or_else(|| {
+        if tag == "details" {
+            Some(serde_json::Value::Bool(element.has_attribute("open")))
+        } else {
+            None
+        }
+    });
+    let disabled = aria_state_value(&element, "aria-disabled")
+        .unwrap_or_else(|| serde_json::Value::Bool(element.matches(":disabled").unwrap_or(false)));
+    let hidden = aria_state_value(&element, "aria-hidden")
+        .unwrap_or(serde_json::Value::Bool(false));
+
+    let state = serde_json::json!({
+        "role": role,
+        "expanded": expanded,
+        "checked": checked,
+        "selected": selected,
+        "pressed": aria_state_value(&element, "aria-pressed"),
+        "disabled": disabled,
+        "hidden": hidden,
+    });
+    let json_string = serde_json::to_string(&state)
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize ARIA state to JSON. Details: {}", e) })?;
+
+    console::log_1(&format!("Successfully retrieved ARIA state for selector '{}': {}", selector, json_string).into());
+    Ok(json_string)
+}
+
 /// Retrieves the current URL of the page.
 ///
 /// # Returns
@@ -518,6 +1750,149 @@ pub fn get_current_url() -> Result<String, DomError> {
     }
 }
 
+/// Evaluates a CSS media query against the page, as `window.matchMedia(query).matches`.
+///
+/// # Arguments
+/// * `query`: The media query, e.g. `(max-width: 600px)` or `(prefers-reduced-motion: reduce)`.
+///
+/// # Returns
+/// * `Ok(bool)` with whether the query currently matches.
+/// * `Err(DomError::JsSyntaxError)` if the browser cannot parse the query.
+#[wasm_bindgen]
+pub fn media_query_matches(query: &str) -> Result<bool, DomError> {
+    let (window, _) = get_window_document()?;
+    let list = window.match_media(query)?.ok_or_else(|| DomError::JsError {
+        message: "window.matchMedia is not available".to_string(),
+    })?;
+    // Unparsable queries are not an error for `matchMedia`; they become `not all`.
+    if list.media() == "not all" && query.trim() != "not all" {
+        return Err(DomError::JsSyntaxError { message: format!("Invalid media query '{}'", query) });
+    }
+    Ok(list.matches())
+}
+
+/// Tells whether the page is shown with a dark color scheme, i.e. whether
+/// `(prefers-color-scheme: dark)` matches.
+#[wasm_bindgen]
+pub fn is_dark_mode() -> Result<bool, DomError> {
+    media_query_matches("(prefers-color-scheme: dark)")
+}
+
+/// Number of characters of visible text `get_page_language` reads, from the start of the page.
+const LANGUAGE_SAMPLE_CHARS: usize = 10_000;
+
+/// Reports the language the page declares (`<html lang>`) and the one its visible text is written
+/// in, as guessed by `locale::detect_language`, so that a workflow can branch on the language
+/// actually shown, e.g. `{"lang":"en","detected":"de","confidence":0.87}`. `lang` is `null` if the
+/// page declares none, `detected` and `confidence` if there is too little text to tell.
+#[wasm_bindgen]
+pub fn get_page_language() -> Result<String, DomError> {
+    let (_window, document) = get_window_document()?;
+    let declared = document
+        .document_element()
+        .and_then(|root| root.get_attribute("lang"))
+        .map(|lang| lang.trim().to_string())
+        .filter(|lang| !lang.is_empty());
+    // `innerText` leaves out hidden elements, scripts and styles.
+    let text = document.body().map(|body| body.inner_text()).unwrap_or_default();
+    let sample: String = text.chars().take(LANGUAGE_SAMPLE_CHARS).collect();
+    let guess = locale::detect_language(&sample);
+
+    let language = serde_json::json!({
+        "lang": declared,
+        "detected": guess.as_ref().map(|guess| guess.language.as_str()),
+        "confidence": guess.as_ref().map(|guess| (guess.confidence * 100.0).round() / 100.0),
+    });
+    let json_string = serde_json::to_string(&language)
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize page language to JSON. Details: {}", e) })?;
+    console::log_1(&format!("Page language: {}", json_string).into());
+    Ok(json_string)
+}
+
+/// Resolves `url`, absolute or relative to the current page (e.g. `/cart` or `#reviews`), to an absolute URL.
+///
+/// # Returns
+/// * `Ok(Url)` with the resolved URL.
+/// * `Err(DomError::JsTypeError)` if `url` cannot be parsed.
+pub fn resolve_url(url: &str) -> Result<Url, DomError> {
+    let base = get_current_url()?;
+    Ok(Url::new_with_base(url, &base)?)
+}
+
+/// Navigates the current page to `url` (absolute or relative to the current page).
+///
+/// Unless only the fragment changes, the page unloads, so tasks queued after this one in
+/// the same run do not execute.
+///
+/// # Returns
+/// * `Ok(String)` with the absolute URL being navigated to.
+/// * `Err(DomError)` if `url` cannot be parsed or the navigation is refused by the browser.
+pub fn navigate(url: &str) -> Result<String, DomError> {
+    let (window, _) = get_window_document()?;
+    let target = resolve_url(url)?.href();
+    console::log_1(&format!("Navigating to: {}", target).into());
+    window.location().set_href(&target)?;
+    Ok(target)
+}
+
+/// Returns the absolute URL a click on the element would follow: the `href` of the element
+/// itself or of its closest link (`<a href>` / `<area href>`) ancestor.
+///
+/// # Returns
+/// * `Ok(Some(String))` with the link target.
+/// * `Ok(None)` if the element is not inside a link, or does not exist.
+/// * `Err(DomError)` if the selector is invalid or the `href` cannot be parsed.
+pub fn link_target(selector: &str) -> Result<Option<String>, DomError> {
+    let (_window, document) = get_window_document()?;
+    let element = match get_element(&document, selector) {
+        Ok(element) => element,
+        Err(DomError::ElementNotFound { .. }) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let href = element
+        .closest("a[href], area[href]")?
+        .and_then(|link| link.get_attribute("href"));
+    match href {
+        Some(href) => Ok(Some(resolve_url(&href)?.href())),
+        None => Ok(None),
+    }
+}
+
+/// Lists the iframes of the current document, so the frame to target can be discovered.
+///
+/// Frames are reported in document order. A frame is considered same-origin when its
+/// `contentDocument` is accessible from this page; cross-origin frames can be listed but
+/// their content cannot be automated from here.
+///
+/// # Returns
+/// * `Ok(String)`: A JSON array with one object per iframe, e.g.
+///   `[{"index":0,"name":"checkout","same_origin":true,"src":"https://example.com/pay"}]`.
+///   `name` and `src` are empty strings when the iframe has no such attribute.
+/// * `Err(DomError)`: If the frames could not be queried or the result could not be serialized.
+#[wasm_bindgen]
+pub fn get_frames() -> Result<String, DomError> {
+    console::log_1(&"Attempting to list iframes of the current document".into());
+    let (_window, document) = get_window_document()?;
+    let node_list = document.query_selector_all("iframe")?;
+
+    let mut frames = Vec::new();
+    for i in 0..node_list.length() {
+        if let Some(frame) = node_list.item(i).and_then(|node| node.dyn_into::<web_sys::HtmlIFrameElement>().ok()) {
+            frames.push(serde_json::json!({
+                "index": frames.len(),
+                "name": frame.name(),
+                "src": frame.src(),
+                "same_origin": frame.content_document().is_some(),
+            }));
+        }
+    }
+
+    let json_string = serde_json::to_string(&frames)
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize frames to JSON. Details: {}", e) })?;
+    console::log_1(&format!("Successfully listed {} iframes", frames.len()).into());
+    Ok(json_string)
+}
+
 /// Checks if an element identified by the selector is currently visible on the page.
 ///
 /// An element is considered visible if it meets all the following conditions:
@@ -589,6 +1964,318 @@ pub fn is_visible(selector: &str) -> Result<bool, DomError> {
     Ok(true)
 }
 
+// Evaluates a CSS selector-matching predicate (e.g. ":disabled") against an element.
+fn element_matches(element: &Element, css_selector: &str) -> Result<bool, DomError> {
+    element.matches(css_selector).map_err(|e| DomError::JsError {
+        message: format!("Failed to evaluate '{}' on element: {:?}", css_selector, e.as_string()),
+    })
+}
+
+// Whether the element is disabled, natively (including via a disabled `<fieldset>` ancestor)
+// or through `aria-disabled="true"` on itself or an ancestor.
+fn element_is_disabled(element: &Element) -> Result<bool, DomError> {
+    // `:disabled` implements the HTML rules, including inheritance from a disabled `<fieldset>`
+    // (except for controls inside its first `<legend>`).
+    if element_matches(element, ":disabled")? {
+        return Ok(true);
+    }
+    Ok(element.closest("[aria-disabled='true']").map_err(DomError::from)?.is_some())
+}
+
+/// Checks if the element identified by the selector is enabled (i.e., not disabled).
+///
+/// An element is disabled if it has the `disabled` attribute, is a form control inside a
+/// disabled `<fieldset>` (outside that fieldset's first `<legend>`), or it or one of its
+/// ancestors has `aria-disabled="true"`.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(true)` if the element is enabled.
+/// * `Ok(false)` if the element is disabled.
+/// * `Err(DomError)` if the element is not found or another error occurs.
+#[wasm_bindgen]
+pub fn is_enabled(selector: &str) -> Result<bool, DomError> {
+    console::log_1(&format!("Checking if element is enabled for selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    let enabled = !element_is_disabled(&element)?;
+    console::log_1(&format!("Element {} is enabled: {}", selector, enabled).into());
+    Ok(enabled)
+}
+
+/// Checks if the element identified by the selector accepts text input from the user.
+///
+/// This is true for enabled, non-`readonly` text-entry controls (`<input>` of a text-like
+/// type, `<textarea>`) and for `contenteditable` elements, unless `aria-readonly="true"` is set.
+/// Checkboxes, buttons and other non-text controls are never editable.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(true)` if the element is editable.
+/// * `Ok(false)` if it is not.
+/// * `Err(DomError)` if the element is not found or another error occurs.
+#[wasm_bindgen]
+pub fn is_editable(selector: &str) -> Result<bool, DomError> {
+    console::log_1(&format!("Checking if element is editable for selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    // `:read-write` excludes readonly and disabled controls (including fieldset-disabled ones)
+    // and includes contenteditable elements.
+    let editable = element_matches(&element, ":read-write")?
+        && !element_is_disabled(&element)?
+        && element.get_attribute("aria-readonly").as_deref() != Some("true");
+    console::log_1(&format!("Element {} is editable: {}", selector, editable).into());
+    Ok(editable)
+}
+
+/// Checks if the element identified by the selector is selected.
+///
+/// This covers checked checkboxes and radio buttons, selected `<option>`s, and custom
+/// widgets using `aria-selected="true"` or `aria-checked="true"`.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(true)` if the element is selected.
+/// * `Ok(false)` if it is not.
+/// * `Err(DomError)` if the element is not found or another error occurs.
+#[wasm_bindgen]
+pub fn is_selected(selector: &str) -> Result<bool, DomError> {
+    console::log_1(&format!("Checking if element is selected for selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    let selected = element_matches(&element, ":checked")?
+        || element.get_attribute("aria-selected").as_deref() == Some("true")
+        || element.get_attribute("aria-checked").as_deref() == Some("true");
+    console::log_1(&format!("Element {} is selected: {}", selector, selected).into());
+    Ok(selected)
+}
+
+/// Elements that may be in the tab order; `tab_order` keeps those that are focusable now.
+const TABBABLE_SELECTOR: &str = "a[href], area[href], button, input, select, textarea, iframe, summary, [tabindex], [contenteditable]";
+
+// Whether an element is shown: it has a box (elements inside a `display: none` ancestor have
+// none) and is not `visibility: hidden`.
+pub(crate) fn is_rendered(window: &Window, element: &Element) -> bool {
+    let rect = element.get_bounding_client_rect();
+    if rect.width() <= 0.0 && rect.height() <= 0.0 {
+        return false;
+    }
+    window
+        .get_computed_style(element)
+        .ok()
+        .flatten()
+        .and_then(|style| style.get_property_value("visibility").ok())
+        .is_none_or(|visibility| visibility != "hidden")
+}
+
+// The elements Tab moves focus through, in order: positive `tabindex` values first, ascending,
+// then the others in document order. Left out are `tabindex="-1"`, disabled, hidden and inert
+// elements, and the radio buttons of a group other than its checked one (or its first, if none is).
+fn tab_order(window: &Window, document: &Document) -> Result<Vec<HtmlElement>, DomError> {
+    let candidates = document
+        .query_selector_all(TABBABLE_SELECTOR)
+        .map_err(|e| DomError::JsError { message: format!("Failed to query focusable elements: {:?}", e.as_string()) })?;
+    let candidates: Vec<HtmlElement> = (0..candidates.length())
+        .filter_map(|index| candidates.item(index).and_then(|node| node.dyn_into::<HtmlElement>().ok()))
+        .collect();
+    let is_radio = |element: &HtmlElement| {
+        element.tag_name().eq_ignore_ascii_case("input")
+            && element.get_attribute("type").is_some_and(|input_type| input_type.eq_ignore_ascii_case("radio"))
+    };
+    let radio_group = |element: &HtmlElement| element.get_attribute("name").filter(|name| !name.is_empty());
+    let checked_groups: Vec<String> = candidates
+        .iter()
+        .filter(|element| is_radio(element) && element.matches(":checked").unwrap_or(false))
+        .filter_map(radio_group)
+        .collect();
+
+    let mut radio_groups_seen: Vec<String> = Vec::new();
+    let mut order: Vec<(i32, HtmlElement)> = Vec::new();
+    for element in candidates {
+        // The `tabIndex` property is -1 for elements not focusable by default, contenteditable
+        // ones included, unless the attribute says otherwise.
+        let tab_index = match element.get_attribute("tabindex").and_then(|value| value.trim().parse::<i32>().ok()) {
+            Some(tab_index) => tab_index,
+            None if element.is_content_editable() => 0,
+            None => element.tab_index(),
+        };
+        if tab_index < 0
+            || element_matches(&element, ":disabled")?
+            || element.closest("[inert]").map_err(DomError::from)?.is_some()
+            || !is_rendered(window, &element)
+        {
+            continue;
+        }
+        if is_radio(&element) {
+            if let Some(group) = radio_group(&element) {
+                let in_order = if checked_groups.contains(&group) {
+                    element_matches(&element, ":checked")?
+                } else {
+                    !radio_groups_seen.contains(&group)
+                };
+                radio_groups_seen.push(group);
+                if !in_order {
+                    continue;
+                }
+            }
+        }
+        order.push((tab_index, element));
+    }
+    // The sort is stable, so elements with the same `tabindex` stay in document order.
+    order.sort_by_key(|(tab_index, _)| if *tab_index > 0 { *tab_index } else { i32::MAX });
+    Ok(order.into_iter().map(|(_, element)| element).collect())
+}
+
+// Presses Tab once: dispatches `keydown` to the focused element and, unless the page cancels it
+// (e.g. a focus trap moving focus itself), focuses the next element of the tab order, wrapping
+// around at its end; then dispatches `keyup` to the element focused now.
+fn press_tab(window: &Window, document: &Document) -> Result<(), DomError> {
+    let active = document.active_element();
+    let key_target = active
+        .clone()
+        .or_else(|| document.document_element())
+        .ok_or_else(|| DomError::JsError { message: "The document has no element to send Tab to".to_string() })?;
+    if dispatch_key_event(&key_target, "keydown", "Tab")? {
+        let order = tab_order(window, document)?;
+        let position = active.as_ref().and_then(|active| order.iter().position(|element| element.unchecked_ref::<Element>() == active));
+        let next = match (position, active.as_ref()) {
+            (Some(index), _) => order.get(index + 1).or(order.first()),
+            // Focus outside the tab order, e.g. on the body, moves to the first element after it.
+            (None, Some(active)) => order
+                .iter()
+                .find(|element| active.compare_document_position(element) & web_sys::Node::DOCUMENT_POSITION_FOLLOWING != 0)
+                .or(order.first()),
+            (None, None) => order.first(),
+        };
+        if let Some(next) = next {
+            next.focus().map_err(DomError::from)?;
+        }
+    }
+    let focused = document.active_element().unwrap_or(key_target);
+    dispatch_key_event(&focused, "keyup", "Tab")?;
+    Ok(())
+}
+
+/// Moves focus with Tab presses, as a keyboard user would, until the element identified by the
+/// selector, or an element inside it, is focused.
+///
+/// Each press dispatches `keydown` and `keyup` events for Tab; unless the page cancels the
+/// `keydown` (focus traps in dialogs do, and move focus themselves), focus moves to the next
+/// element of the tab order: positive `tabindex` values first, then document order, skipping
+/// disabled, hidden and inert elements and the unselected radio buttons of a group.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+/// * `max_tabs`: The most Tab presses to try. By default, one more than the number of elements
+///   in the tab order, i.e. a full cycle.
+///
+/// # Returns
+/// * `Ok(Some(tabs))` with the number of presses it took (0 if the element already had focus).
+/// * `Ok(None)` if the element was not focused after `max_tabs` presses.
+/// * `Err(DomError)` if the element is not found or another error occurs.
+pub fn tab_to(selector: &str, max_tabs: Option<u32>) -> Result<Option<u32>, DomError> {
+    console::log_1(&format!("Attempting to tab to element with selector: {}", selector).into());
+    let (window, document) = get_window_document()?;
+    let target = get_element(&document, selector)?;
+    let max_tabs = match max_tabs {
+        Some(max_tabs) => max_tabs,
+        None => tab_order(&window, &document)?.len() as u32 + 1,
+    };
+    for tabs in 0..=max_tabs {
+        if document.active_element().is_some_and(|active| target.contains(Some(&active))) {
+            console::log_1(&format!("Element {} focused after {} Tab presses", selector, tabs).into());
+            return Ok(Some(tabs));
+        }
+        if tabs < max_tabs {
+            press_tab(&window, &document)?;
+        }
+    }
+    Ok(None)
+}
+
+/// Presses Tab `count` times (see `tab_to`), regardless of where focus ends up.
+pub fn press_tabs(count: u32) -> Result<(), DomError> {
+    let (window, document) = get_window_document()?;
+    for _ in 0..count {
+        press_tab(&window, &document)?;
+    }
+    Ok(())
+}
+
+// Approximates the accessible name of an element: the text of its `aria-labelledby` elements,
+// `aria-label`, the text of its `<label>`, its own text, then `alt`, `value` (for buttons),
+// `title` and `placeholder`, whichever comes first.
+fn accessible_name(document: &Document, element: &Element) -> String {
+    let text_of = |element: &Element| element.text_content().unwrap_or_default();
+    let labelled_by = element.get_attribute("aria-labelledby").map(|ids| {
+        ids.split_whitespace()
+            .filter_map(|id| document.get_element_by_id(id))
+            .map(|label| text_of(&label))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    let label = || {
+        let id = element.id();
+        let labels = document.query_selector_all("label").ok()?;
+        let label_for = (0..labels.length())
+            .filter_map(|index| labels.item(index).and_then(|node| node.dyn_into::<Element>().ok()))
+            .find(|label| !id.is_empty() && label.get_attribute("for").as_deref() == Some(id.as_str()));
+        label_for.or_else(|| element.closest("label").ok().flatten()).map(|label| text_of(&label))
+    };
+    let is_button_input = element.tag_name().eq_ignore_ascii_case("input")
+        && matches!(element.get_attribute("type").unwrap_or_default().to_lowercase().as_str(), "button" | "submit" | "reset");
+    [
+        labelled_by,
+        element.get_attribute("aria-label"),
+        label(),
+        Some(text_of(element)),
+        element.get_attribute("alt"),
+        if is_button_input { element.get_attribute("value") } else { None },
+        element.get_attribute("title"),
+        element.get_attribute("placeholder"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|name| name.split_whitespace().collect::<Vec<_>>().join(" "))
+    .find(|name| !name.is_empty())
+    .unwrap_or_default()
+}
+
+/// Describes the focused element (`document.activeElement`) as JSON, e.g.
+/// `{"element":"button#save.primary","role":"button","name":"Save","tab_index":0}`, where
+/// `name` approximates its accessible name. Returns `null` if no element has focus, i.e. focus is
+/// on the page itself.
+#[wasm_bindgen]
+pub fn get_focused() -> Result<String, DomError> {
+    let (_window, document) = get_window_document()?;
+    let focused = document.active_element().filter(|element| {
+        Some(element) != document.body().as_ref().map(|body| body.unchecked_ref::<Element>()) && Some(element) != document.document_element().as_ref()
+    });
+    let description = match focused {
+        Some(element) => serde_json::json!({
+            "element": describe_element(&element),
+            "role": element_role(&element),
+            "name": accessible_name(&document, &element),
+            "tab_index": element.dyn_ref::<HtmlElement>().map(|element| element.tab_index()),
+        }),
+        None => serde_json::Value::Null,
+    };
+    let json_string = serde_json::to_string(&description)
+        .map_err(|e| DomError::SerializationError { message: format!("Failed to serialize focused element to JSON. Details: {}", e) })?;
+    console::log_1(&format!("Focused element: {}", json_string).into());
+    Ok(json_string)
+}
+
 /// Scrolls the page to make the element identified by the selector visible in the viewport.
 ///
 /// Uses the standard `element.scroll_into_view()` method.
@@ -671,6 +2358,422 @@ pub fn hover_element(selector: &str) -> Result<(), DomError> {
     Ok(())
 }
 
+// Dispatches a sequence of mouse events (e.g. mousedown, mouseup, click) on `target`, with the
+// client coordinates set to (`x`, `y`) so handlers that read the event position see the point.
+fn dispatch_mouse_events_at(window: &Window, target: &Element, x: f64, y: f64, event_types: &[&str]) -> Result<(), DomError> {
+    let event_init = web_sys::MouseEventInit::new();
+    event_init.set_bubbles(true);
+    event_init.set_cancelable(true);
+    event_init.set_view(Some(window));
+    event_init.set_client_x(x.round() as i32);
+    event_init.set_client_y(y.round() as i32);
+    event_init.set_button(0);
+
+    for event_type in event_types {
+        let event = web_sys::MouseEvent::new_with_mouse_event_init_dict(event_type, &event_init)
+            .map_err(|e| DomError::JsError { message: format!("Failed to create {} event: {:?}", event_type, e.as_string()) })?;
+        target.dispatch_event(&event)
+            .map_err(|e| DomError::JsError { message: format!("Failed to dispatch {} event: {:?}", event_type, e.as_string()) })?;
+    }
+    Ok(())
+}
+
+// Finds the topmost element at the given viewport coordinates.
+fn element_at_point(document: &Document, x: f64, y: f64) -> Result<Element, DomError> {
+    document.element_from_point(x as f32, y as f32).ok_or_else(|| DomError::ElementNotFound {
+        selector: format!("({}, {})", x, y),
+        message: Some(format!("No element found at viewport coordinates ({}, {})", x, y)),
+    })
+}
+
+/// Clicks whatever element is rendered at the given viewport coordinates.
+///
+/// The topmost element at the point (as reported by `document.elementFromPoint`) receives
+/// `mousedown`, `mouseup` and `click` events carrying the coordinates as `clientX`/`clientY`.
+/// This is useful for canvas-based UIs, where the click position matters, and for targets
+/// that cannot be selected directly (e.g. pseudo-element overlays).
+///
+/// # Arguments
+/// * `x`: The horizontal viewport coordinate, in CSS pixels.
+/// * `y`: The vertical viewport coordinate, in CSS pixels.
+///
+/// # Returns
+/// * `Ok(String)` describing the element that received the click (e.g., `"canvas#chart"`).
+/// * `Err(DomError::ElementNotFound)` if no element is rendered at the point (e.g., it is outside the viewport).
+/// * `Err(DomError)` if the events could not be created or dispatched.
+#[wasm_bindgen]
+pub fn click_at(x: f64, y: f64) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to click at viewport coordinates ({}, {})", x, y).into());
+    let (window, document) = get_window_document()?;
+    let target = element_at_point(&document, x, y)?;
+    dispatch_mouse_events_at(&window, &target, x, y, &["mousedown", "mouseup", "click"])?;
+    LAST_POINTER_POSITION.with(|position| position.set(Some((x, y))));
+
+    let description = describe_element(&target);
+    console::log_1(&format!("Successfully clicked {} at ({}, {})", description, x, y).into());
+    Ok(description)
+}
+
+/// Clicks the center of the bounding box of the element identified by the selector.
+///
+/// The element is scrolled into view first. The click is then performed with `click_at`, so it
+/// lands on whatever is rendered topmost at that point, exactly like a real user click would.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(String)` describing the element that received the click.
+/// * `Err(DomError)` if the element is not found, has no size, or the click could not be dispatched.
+#[wasm_bindgen]
+pub fn click_center(selector: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to click center of element with selector: {}", selector).into());
+    let (_window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    element.scroll_into_view();
+
+    let rect = element.get_bounding_client_rect();
+    if rect.width() <= 0.0 || rect.height() <= 0.0 {
+        return Err(DomError::ElementTypeError {
+            selector: selector.to_string(),
+            expected_type: "element with a non-empty bounding box".to_string(),
+        });
+    }
+    click_at(rect.left() + rect.width() / 2.0, rect.top() + rect.height() / 2.0)
+}
+
+/// Number of intermediate points generated by `mouse_move` between the previous and the new
+/// pointer position, so handlers that track the pointer path see a continuous movement.
+const MOUSE_MOVE_STEPS: u32 = 5;
+
+thread_local! {
+    // Last viewport position of the simulated pointer, updated by `click_at` and `mouse_move`.
+    static LAST_POINTER_POSITION: Cell<Option<(f64, f64)>> = const { Cell::new(None) };
+}
+
+// Dispatches pointer events of the given types and pointer type ("mouse" or "touch") on `target` at (`x`, `y`).
+fn dispatch_pointer_events_at(window: &Window, target: &Element, x: f64, y: f64, pointer_type: &str, event_types: &[&str]) -> Result<(), DomError> {
+    let event_init = web_sys::PointerEventInit::new();
+    event_init.set_bubbles(true);
+    event_init.set_cancelable(true);
+    event_init.set_view(Some(window));
+    event_init.set_client_x(x.round() as i32);
+    event_init.set_client_y(y.round() as i32);
+    event_init.set_pointer_id(1);
+    event_init.set_pointer_type(pointer_type);
+    event_init.set_is_primary(true);
+
+    for event_type in event_types {
+        let event = web_sys::PointerEvent::new_with_event_init_dict(event_type, &event_init)
+            .map_err(|e| DomError::JsError { message: format!("Failed to create {} event: {:?}", event_type, e.as_string()) })?;
+        target.dispatch_event(&event)
+            .map_err(|e| DomError::JsError { message: format!("Failed to dispatch {} event: {:?}", event_type, e.as_string()) })?;
+    }
+    Ok(())
+}
+
+// Resolves a pointer target given either as viewport coordinates ("x,y") or as a selector,
+// in which case the center of the element (after scrolling it into view) is used.
+fn resolve_pointer_target(document: &Document, target: &str) -> Result<(f64, f64), DomError> {
+    let coordinates: Vec<f64> = target.split(',').filter_map(|c| c.trim().parse::<f64>().ok()).collect();
+    if coordinates.len() == 2 && target.split(',').count() == 2 {
+        return Ok((coordinates[0], coordinates[1]));
+    }
+    let element = get_element(document, target)?;
+    element.scroll_into_view();
+    let rect = element.get_bounding_client_rect();
+    Ok((rect.left() + rect.width() / 2.0, rect.top() + rect.height() / 2.0))
+}
+
+/// Moves the simulated mouse pointer to an element or to viewport coordinates.
+///
+/// If the pointer has a known previous position (from an earlier `mouse_move` or `click_at`),
+/// the movement is interpolated over several intermediate points, so menus that track the
+/// pointer path (e.g. diagonal moves into a submenu) behave as they would for a user.
+/// At each point, `pointermove`/`mousemove` are dispatched on the element under the pointer,
+/// and `pointerout`/`mouseout` + `pointerover`/`mouseover` when that element changes.
+///
+/// # Arguments
+/// * `target`: Either viewport coordinates written as `"x,y"` (e.g., `"120,48"`), or a CSS
+///   selector / XPath expression whose element center is the destination.
+///
+/// # Returns
+/// * `Ok(String)` describing the element under the pointer at the destination.
+/// * `Err(DomError)` if the target element is not found, no element is rendered at the
+///   destination, or the events could not be dispatched.
+#[wasm_bindgen]
+pub fn mouse_move(target: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to move mouse to: {}", target).into());
+    let (window, document) = get_window_document()?;
+    let (x, y) = resolve_pointer_target(&document, target)?;
+
+    let path: Vec<(f64, f64)> = match LAST_POINTER_POSITION.with(|position| position.get()) {
+        Some((start_x, start_y)) => (1..=MOUSE_MOVE_STEPS)
+            .map(|step| {
+                let t = step as f64 / MOUSE_MOVE_STEPS as f64;
+                (start_x + (x - start_x) * t, start_y + (y - start_y) * t)
+            })
+            .collect(),
+        None => vec![(x, y)],
+    };
+
+    let mut previous_element: Option<Element> = None;
+    for (step_x, step_y) in path {
+        let element = match document.element_from_point(step_x as f32, step_y as f32) {
+            Some(element) => element,
+            None => continue, // Intermediate points may lie outside the viewport.
+        };
+        if previous_element.as_ref() != Some(&element) {
+            if let Some(previous) = previous_element.as_ref() {
+                dispatch_pointer_events_at(&window, previous, step_x, step_y, "mouse", &["pointerout"])?;
+                dispatch_mouse_events_at(&window, previous, step_x, step_y, &["mouseout"])?;
+            }
+            dispatch_pointer_events_at(&window, &element, step_x, step_y, "mouse", &["pointerover"])?;
+            dispatch_mouse_events_at(&window, &element, step_x, step_y, &["mouseover"])?;
+        }
+        dispatch_pointer_events_at(&window, &element, step_x, step_y, "mouse", &["pointermove"])?;
+        dispatch_mouse_events_at(&window, &element, step_x, step_y, &["mousemove"])?;
+        previous_element = Some(element);
+    }
+    LAST_POINTER_POSITION.with(|position| position.set(Some((x, y))));
+
+    let destination = element_at_point(&document, x, y)?;
+    let description = describe_element(&destination);
+    console::log_1(&format!("Successfully moved mouse to ({}, {}) over {}", x, y, description).into());
+    Ok(description)
+}
+
+/// Simulates a mouse wheel scroll over the element identified by the selector.
+///
+/// A `wheel` event with the given `deltaY` is dispatched at the element's center. Since
+/// synthetic wheel events do not scroll by themselves, if no handler cancels the event the
+/// nearest scrollable ancestor (or the page) is then scrolled by `delta_y` pixels, matching
+/// what the browser would do for a real wheel gesture.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+/// * `delta_y`: The vertical scroll amount in pixels (positive scrolls down).
+///
+/// # Returns
+/// * `Ok(true)` if the default scrolling was applied.
+/// * `Ok(false)` if a page handler canceled the wheel event (e.g., a custom scroll container or zoomable map).
+/// * `Err(DomError)` if the element is not found or the event could not be dispatched.
+#[wasm_bindgen]
+pub fn wheel(selector: &str, delta_y: f64) -> Result<bool, DomError> {
+    console::log_1(&format!("Attempting to dispatch wheel (deltaY {}) on element with selector: {}", delta_y, selector).into());
+    let (window, document) = get_window_document()?;
+    let element = get_element(&document, selector)?;
+    let rect = element.get_bounding_client_rect();
+
+    let event_init = web_sys::WheelEventInit::new();
+    event_init.set_bubbles(true);
+    event_init.set_cancelable(true);
+    event_init.set_view(Some(&window));
+    event_init.set_client_x((rect.left() + rect.width() / 2.0).round() as i32);
+    event_init.set_client_y((rect.top() + rect.height() / 2.0).round() as i32);
+    event_init.set_delta_y(delta_y);
+    event_init.set_delta_mode(0); // DOM_DELTA_PIXEL
+    let wheel_event = web_sys::WheelEvent::new_with_event_init_dict("wheel", &event_init)
+        .map_err(|e| DomError::JsError { message: format!("Failed to create wheel event: {:?}", e.as_string()) })?;
+    let not_canceled = element.dispatch_event(&wheel_event)
+        .map_err(|e| DomError::JsError { message: format!("Failed to dispatch wheel event: {:?}", e.as_string()) })?;
+
+    if !not_canceled {
+        console::log_1(&format!("Wheel event on {} was canceled by a page handler; not scrolling", selector).into());
+        return Ok(false);
+    }
+
+    // Find the nearest ancestor (including the element itself) that can scroll vertically.
+    let mut current = Some(element);
+    while let Some(candidate) = current {
+        if candidate.scroll_height() > candidate.client_height() {
+            if let Some(style) = window.get_computed_style(&candidate)? {
+                let overflow_y = style.get_property_value("overflow-y")?;
+                if overflow_y == "auto" || overflow_y == "scroll" || overflow_y == "overlay" {
+                    candidate.scroll_by_with_x_and_y(0.0, delta_y);
+                    console::log_1(&format!("Successfully scrolled {} by {}px", describe_element(&candidate), delta_y).into());
+                    return Ok(true);
+                }
+            }
+        }
+        current = candidate.parent_element();
+    }
+    window.scroll_by_with_x_and_y(0.0, delta_y);
+    console::log_1(&format!("Successfully scrolled page by {}px", delta_y).into());
+    Ok(true)
+}
+
+/// Default hold time of `long_press`, in milliseconds (about what mobile browsers use for a context press).
+pub const DEFAULT_LONG_PRESS_MS: u32 = 800;
+/// Default travel distance of `swipe`, in pixels.
+pub const DEFAULT_SWIPE_DISTANCE_PX: f64 = 200.0;
+// Number of intermediate `touchmove` points dispatched during a swipe.
+const SWIPE_STEPS: u32 = 8;
+// Delay between swipe points, roughly one animation frame, so gesture handlers see a realistic velocity.
+const SWIPE_STEP_INTERVAL_MS: u32 = 16;
+
+// Dispatches a single-finger touch event of the given type on `target` at (`x`, `y`).
+// Returns `Ok(None)` if the browser cannot construct `Touch`/`TouchEvent` (most desktop
+// browsers), otherwise `Ok(Some(not_canceled))`.
+fn dispatch_touch_event_at(window: &Window, target: &Element, x: f64, y: f64, event_type: &str) -> Result<Option<bool>, DomError> {
+    let touch_init = web_sys::TouchInit::new(0, target);
+    touch_init.set_client_x(x.round() as i32);
+    touch_init.set_client_y(y.round() as i32);
+    touch_init.set_page_x((x + window.scroll_x().unwrap_or(0.0)).round() as i32);
+    touch_init.set_page_y((y + window.scroll_y().unwrap_or(0.0)).round() as i32);
+    let touch = match web_sys::Touch::new(&touch_init) {
+        Ok(touch) => touch,
+        Err(_) => return Ok(None),
+    };
+
+    let changed_touches = js_sys::Array::of1(&touch);
+    // A lifted finger is no longer part of `touches`/`targetTouches`.
+    let active_touches = if event_type == "touchend" || event_type == "touchcancel" {
+        js_sys::Array::new()
+    } else {
+        changed_touches.clone()
+    };
+    let event_init = web_sys::TouchEventInit::new();
+    event_init.set_bubbles(true);
+    event_init.set_cancelable(true);
+    event_init.set_view(Some(window));
+    event_init.set_touches(&active_touches);
+    event_init.set_target_touches(&active_touches);
+    event_init.set_changed_touches(&changed_touches);
+    let event = match web_sys::TouchEvent::new_with_event_init_dict(event_type, &event_init) {
+        Ok(event) => event,
+        Err(_) => return Ok(None),
+    };
+    target.dispatch_event(&event)
+        .map(Some)
+        .map_err(|e| DomError::JsError { message: format!("Failed to dispatch {} event: {:?}", event_type, e.as_string()) })
+}
+
+// Dispatches the pointer event (pointerType "touch") followed by the touch event for one phase
+// of a gesture, in the order browsers use. Returns the same value as `dispatch_touch_event_at`.
+fn dispatch_touch_phase_at(window: &Window, target: &Element, x: f64, y: f64, pointer_event: &str, touch_event: &str) -> Result<Option<bool>, DomError> {
+    dispatch_pointer_events_at(window, target, x, y, "touch", &[pointer_event])?;
+    dispatch_touch_event_at(window, target, x, y, touch_event)
+}
+
+// Scrolls the element into view and returns it along with the viewport coordinates of its center.
+fn touch_target(document: &Document, selector: &str) -> Result<(Element, f64, f64), DomError> {
+    let element = get_element(document, selector)?;
+    element.scroll_into_view();
+    let rect = element.get_bounding_client_rect();
+    Ok((element, rect.left() + rect.width() / 2.0, rect.top() + rect.height() / 2.0))
+}
+
+/// Simulates a finger tap on the element identified by the selector.
+///
+/// Dispatches `pointerdown`/`touchstart` and `pointerup`/`touchend` (pointerType `"touch"`) at
+/// the element's center, followed by the compatibility mouse events and `click` that browsers
+/// fire after a tap, unless a `touchstart`/`touchend` handler canceled them.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+///
+/// # Returns
+/// * `Ok(true)` if touch events were dispatched.
+/// * `Ok(false)` if the browser does not support constructing touch events, so only pointer
+///   and mouse events were dispatched.
+/// * `Err(DomError)` if the element is not found or the events could not be dispatched.
+#[wasm_bindgen]
+pub fn tap(selector: &str) -> Result<bool, DomError> {
+    console::log_1(&format!("Attempting to tap element with selector: {}", selector).into());
+    let (window, document) = get_window_document()?;
+    let (element, x, y) = touch_target(&document, selector)?;
+
+    let start = dispatch_touch_phase_at(&window, &element, x, y, "pointerdown", "touchstart")?;
+    let end = dispatch_touch_phase_at(&window, &element, x, y, "pointerup", "touchend")?;
+    // Canceling either touch event suppresses the synthesized mouse events and click.
+    if start != Some(false) && end != Some(false) {
+        dispatch_mouse_events_at(&window, &element, x, y, &["mouseover", "mousemove", "mousedown", "mouseup", "click"])?;
+    }
+    console::log_1(&format!("Successfully tapped element with selector: {}", selector).into());
+    Ok(start.is_some())
+}
+
+/// Simulates a single-finger swipe starting at the center of the element identified by the selector.
+///
+/// Dispatches `pointerdown`/`touchstart`, a series of `pointermove`/`touchmove` events spaced about
+/// one frame apart, and `pointerup`/`touchend`, all targeted at the element where the touch
+/// started (as browsers do). No compatibility mouse events or `click` are fired.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+/// * `direction`: The direction the finger travels: `"left"`, `"right"`, `"up"`, or `"down"`.
+/// * `distance_px`: How far the finger travels, in pixels. Defaults to `DEFAULT_SWIPE_DISTANCE_PX`.
+///
+/// # Returns
+/// * `Ok(true)` if touch events were dispatched.
+/// * `Ok(false)` if the browser does not support constructing touch events, so only pointer
+///   events were dispatched.
+/// * `Err(DomError)` if the element is not found, the direction is invalid, or the events could not be dispatched.
+#[wasm_bindgen]
+pub async fn swipe(selector: &str, direction: &str, distance_px: Option<f64>) -> Result<bool, DomError> {
+    console::log_1(&format!("Attempting to swipe {} on element with selector: {}", direction, selector).into());
+    let distance = distance_px.unwrap_or(DEFAULT_SWIPE_DISTANCE_PX);
+    let (dx, dy) = match direction.to_lowercase().as_str() {
+        "left" => (-distance, 0.0),
+        "right" => (distance, 0.0),
+        "up" => (0.0, -distance),
+        "down" => (0.0, distance),
+        _ => return Err(DomError::JsError {
+            message: format!("Invalid swipe direction '{}': expected left, right, up, or down", direction),
+        }),
+    };
+    let (window, document) = get_window_document()?;
+    let (element, start_x, start_y) = touch_target(&document, selector)?;
+    // The gesture goes on on the element's replacement if the page re-renders it meanwhile.
+    let mut handle = ElementHandle { selector: selector.to_string(), element };
+
+    let touch_supported = dispatch_touch_phase_at(&window, handle.element()?, start_x, start_y, "pointerdown", "touchstart")?.is_some();
+    for step in 1..=SWIPE_STEPS {
+        TimeoutFuture::new(SWIPE_STEP_INTERVAL_MS).await;
+        let t = step as f64 / SWIPE_STEPS as f64;
+        dispatch_touch_phase_at(&window, handle.element()?, start_x + dx * t, start_y + dy * t, "pointermove", "touchmove")?;
+    }
+    dispatch_touch_phase_at(&window, handle.element()?, start_x + dx, start_y + dy, "pointerup", "touchend")?;
+    console::log_1(&format!("Successfully swiped {} by {}px on element with selector: {}", direction, distance, selector).into());
+    Ok(touch_supported)
+}
+
+/// Simulates pressing and holding a finger on the element identified by the selector.
+///
+/// Dispatches `pointerdown`/`touchstart` at the element's center, waits for the given duration,
+/// then dispatches `pointerup`/`touchend`. No `click` is fired, as browsers do not treat a
+/// long press as a tap.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+/// * `duration_ms`: How long to hold, in milliseconds. Defaults to `DEFAULT_LONG_PRESS_MS`.
+///
+/// # Returns
+/// * `Ok(true)` if touch events were dispatched.
+/// * `Ok(false)` if the browser does not support constructing touch events, so only pointer
+///   events were dispatched.
+/// * `Err(DomError)` if the element is not found or the events could not be dispatched.
+#[wasm_bindgen]
+pub async fn long_press(selector: &str, duration_ms: Option<u32>) -> Result<bool, DomError> {
+    let duration = duration_ms.unwrap_or(DEFAULT_LONG_PRESS_MS);
+    console::log_1(&format!("Attempting to long-press element with selector: {} for {}ms", selector, duration).into());
+    let (window, document) = get_window_document()?;
+    let (element, x, y) = touch_target(&document, selector)?;
+    let mut handle = ElementHandle { selector: selector.to_string(), element };
+
+    let touch_supported = dispatch_touch_phase_at(&window, handle.element()?, x, y, "pointerdown", "touchstart")?.is_some();
+    TimeoutFuture::new(duration).await;
+    dispatch_touch_phase_at(&window, handle.element()?, x, y, "pointerup", "touchend")?;
+    console::log_1(&format!("Successfully long-pressed element with selector: {}", selector).into());
+    Ok(touch_supported)
+}
+
 /// Retrieves and concatenates the inner text content from all elements matching the given selector.
 ///
 /// This function finds all DOM elements that match the provided `selector`. For each
@@ -680,49 +2783,417 @@ pub fn hover_element(selector: &str) -> Result<(), DomError> {
 /// between each piece of text.
 ///
 /// # Arguments
-/// * `selector`: A `&str` representing a CSS selector (e.g., ".myClass", "div > p")
-///   or an XPath expression (prefixed with "xpath:", e.g., "xpath://ul/li") used to
-///   identify the target elements.
-/// * `separator`: A `&str` that will be used to join the `inner_text` from each
-///   matching element. For example, a newline character `"\n"`, a comma and space `", "`,
-///   or any other custom string.
+/// * `selector`: A `&str` representing a CSS selector (e.g., ".myClass", "div > p")
+///   or an XPath expression (prefixed with "xpath:", e.g., "xpath://ul/li") used to
+///   identify the target elements.
+/// * `separator`: A `&str` that will be used to join the `inner_text` from each
+///   matching element. For example, a newline character `"\n"`, a comma and space `", "`,
+///   or any other custom string.
+///
+/// # Returns
+/// * `Ok(String)`:
+///     - If elements are found and contain text, this is the concatenated string of their
+///       `inner_text` values, joined by the `separator`.
+///     - If no elements are found matching the `selector`, an empty string is returned.
+///     - If elements are found but none of them contain any non-empty text content (e.g.,
+///       they are empty elements or contain only other elements without text), an empty
+///       string is returned.
+/// * `Err(DomError)`: If an error occurs during element retrieval, such as an
+///   `InvalidSelector` if the provided selector string is malformed.
+#[wasm_bindgen]
+pub fn get_all_text_from_elements(selector: &str, separator: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to get all text from elements matching selector: {} with separator: '{}'", selector, separator).into());
+    let (_window, document) = get_window_document()?;
+    let elements = get_all_elements(&document, selector)?;
+
+    if elements.is_empty() {
+        console::log_1(&format!("No elements found for selector '{}'. Returning empty string.", selector).into());
+        return Ok("".to_string());
+    }
+
+    let texts: Vec<String> = elements
+        .into_iter()
+        .filter_map(|el| {
+            el.dyn_into::<HtmlElement>().ok().map(|html_el| html_el.inner_text())
+        })
+        .filter(|text| !text.is_empty()) // Optionally filter out empty strings
+        .collect();
+
+    if texts.is_empty() {
+        console::log_1(&format!("Elements found for selector '{}', but they contained no text. Returning empty string.", selector).into());
+        return Ok("".to_string());
+    }
+
+    console::log_1(&format!("Successfully retrieved {} text segments for selector '{}'.", texts.len(), selector).into());
+    Ok(texts.join(separator))
+}
+
+/// At most this many of the elements matching a selector are described by `describe_matches`.
+#[cfg(not(feature = "dom-only"))]
+const MAX_DESCRIBED_MATCHES: usize = 20;
+/// Characters of an element's text included in its description by `describe_matches`.
+#[cfg(not(feature = "dom-only"))]
+const MATCH_TEXT_CHARS: usize = 80;
+/// Attribute marking the element picked among the matches of a selector (see `pin_match`).
+#[cfg(not(feature = "dom-only"))]
+const PICKED_ATTRIBUTE: &str = "data-rustagent-picked";
+
+/// Describes the elements matching `selector`, in document order, so that one of them can be
+/// picked: tag, id and first class, `aria-label`, the start of the text, and whether it is hidden,
+/// e.g. `button.plan aria-label="Choose Pro" "Pro – $20/month"`. Only the first
+/// `MAX_DESCRIBED_MATCHES` matches are described.
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with one description per match (empty if nothing matches).
+/// * `Err(DomError)` if the selector is invalid.
+#[cfg(not(feature = "dom-only"))]
+pub(crate) fn describe_matches(selector: &str) -> Result<Vec<String>, DomError> {
+    let (window, document) = get_window_document()?;
+    let elements = get_all_elements(&document, selector)?;
+    Ok(elements
+        .iter()
+        .take(MAX_DESCRIBED_MATCHES)
+        .map(|element| {
+            let mut description = describe_element(element);
+            if let Some(label) = element.get_attribute("aria-label").filter(|label| !label.trim().is_empty()) {
+                description.push_str(&format!(" aria-label=\"{}\"", label.trim()));
+            }
+            let text = element.text_content().unwrap_or_default().split_whitespace().collect::<Vec<_>>().join(" ");
+            if !text.is_empty() {
+                let shown: String = text.chars().take(MATCH_TEXT_CHARS).collect();
+                let ellipsis = if shown.len() < text.len() { "…" } else { "" };
+                description.push_str(&format!(" \"{}{}\"", shown, ellipsis));
+            }
+            if !is_rendered(&window, element) {
+                description.push_str(" (hidden)");
+            }
+            description
+        })
+        .collect())
+}
+
+/// Marks the element at `index` among those matching `selector`, so that it alone can be targeted
+/// until `unpin_matches` is called.
+///
+/// # Returns
+/// * `Ok(String)` with a selector matching only the marked element.
+/// * `Err(DomError)` if the selector is invalid or matches fewer than `index + 1` elements.
+#[cfg(not(feature = "dom-only"))]
+pub(crate) fn pin_match(selector: &str, index: usize) -> Result<String, DomError> {
+    let (_window, document) = get_window_document()?;
+    unpin_matches();
+    let element = get_all_elements(&document, selector)?.into_iter().nth(index).ok_or_else(|| DomError::ElementNotFound {
+        selector: selector.to_string(),
+        message: Some(format!("No match at index {}", index)),
+    })?;
+    element.set_attribute(PICKED_ATTRIBUTE, "")?;
+    Ok(format!("css:[{}]", PICKED_ATTRIBUTE))
+}
+
+/// Removes the mark set by `pin_match`, if any.
+#[cfg(not(feature = "dom-only"))]
+pub(crate) fn unpin_matches() {
+    if let Ok((_window, document)) = get_window_document() {
+        if let Ok(marked) = document.query_selector_all(&format!("[{}]", PICKED_ATTRIBUTE)) {
+            for element in (0..marked.length()).filter_map(|index| marked.item(index)).filter_map(|node| node.dyn_into::<Element>().ok()) {
+                let _ = element.remove_attribute(PICKED_ATTRIBUTE);
+            }
+        }
+    }
+}
+
+/// Minimum `z-index` for a fixed-position element to be considered an overlay by `dismiss_overlays`.
+const OVERLAY_MIN_Z_INDEX: i32 = 10;
+/// Fraction of the viewport width an element must span to be treated as a banner (e.g. cookie bars).
+const OVERLAY_MIN_WIDTH_RATIO: f64 = 0.9;
+/// Fraction of the viewport area an element must cover to be treated as a modal/overlay.
+const OVERLAY_MIN_AREA_RATIO: f64 = 0.25;
+/// Texts (matched as whole words, with `locale::fold`, in English or any translation `locale` knows)
+/// that identify a control which dismisses an overlay.
+/// Ordered by preference: accepting a consent banner is more likely to make it go away for good.
+const OVERLAY_DISMISS_KEYWORDS: [&str; 10] = [
+    "accept", "agree", "allow", "got it", "close", "dismiss", "no thanks", "continue", "reject", "decline",
+];
+/// Keywords that only identify a dismiss control when they are its entire label, since they also
+/// start labels like "Allow notifications" or "Continue to checkout".
+const OVERLAY_DISMISS_WHOLE_LABEL_KEYWORDS: [&str; 2] = ["allow", "continue"];
+/// Texts that identify a dismiss control only when they are the control's entire label (e.g. close icons).
+const OVERLAY_DISMISS_EXACT_LABELS: [&str; 5] = ["x", "×", "✕", "✖", "ok"];
+
+// Builds a short human-readable description of an element (tag, id and first class) for reports.
+pub(crate) fn describe_element(element: &Element) -> String {
+    let mut description = element.tag_name().to_lowercase();
+    let id = element.id();
+    if !id.is_empty() {
+        description.push('#');
+        description.push_str(&id);
+    }
+    if let Some(class) = element.class_name().split_whitespace().next() {
+        description.push('.');
+        description.push_str(class);
+    }
+    description
+}
+
+// Finds the best control inside `overlay` that looks like it dismisses it, returning it with its label.
+fn find_dismiss_control(overlay: &Element) -> Result<Option<(HtmlElement, String)>, DomError> {
+    let candidates = overlay
+        .query_selector_all("button, a, [role='button'], input[type='button'], input[type='submit']")
+        .map_err(|e| DomError::JsError { message: format!("Failed to query overlay controls: {:?}", e.as_string()) })?;
+
+    // The keywords and their translations (see `locale`), in order of preference.
+    let keywords: Vec<Vec<String>> = OVERLAY_DISMISS_KEYWORDS.iter().map(|keyword| locale::term_variants(keyword)).collect();
+    let mut best: Option<(usize, HtmlElement, String)> = None;
+    for i in 0..candidates.length() {
+        let control = match candidates.item(i).and_then(|node| node.dyn_into::<HtmlElement>().ok()) {
+            Some(control) => control,
+            None => continue,
+        };
+        // Links leading elsewhere would navigate away instead of closing the overlay.
+        let leaves_page = control.tag_name().eq_ignore_ascii_case("a")
+            && control.get_attribute("role").as_deref() != Some("button")
+            && control.get_attribute("href").is_some_and(|href| !href.trim().starts_with('#'));
+        if leaves_page {
+            continue;
+        }
+        // Consider the visible text as well as accessible labels, since close buttons are often icons.
+        let labels = [
+            control.inner_text(),
+            control.get_attribute("aria-label").unwrap_or_default(),
+            control.get_attribute("title").unwrap_or_default(),
+            control.get_attribute("value").unwrap_or_default(),
+        ];
+        for label in labels.iter() {
+            let normalized = locale::fold(label);
+            if normalized.is_empty() {
+                continue;
+            }
+            let rank = keywords
+                .iter()
+                .zip(OVERLAY_DISMISS_KEYWORDS)
+                .position(|(variants, keyword)| {
+                    let whole_label = OVERLAY_DISMISS_WHOLE_LABEL_KEYWORDS.contains(&keyword);
+                    variants.iter().any(|variant| {
+                        if whole_label { words(&normalized) == words(variant) } else { contains_words(&normalized, variant) }
+                    })
+                })
+                .or_else(|| {
+                    OVERLAY_DISMISS_EXACT_LABELS
+                        .iter()
+                        .position(|exact| normalized == *exact)
+                        .map(|pos| OVERLAY_DISMISS_KEYWORDS.len() + pos)
+                });
+            if let Some(rank) = rank {
+                if best.as_ref().is_none_or(|(best_rank, _, _)| rank < *best_rank) {
+                    best = Some((rank, control.clone(), label.trim().to_string()));
+                }
+                break;
+            }
+        }
+    }
+    Ok(best.map(|(_, control, label)| (control, label)))
+}
+
+// The words of `text`, without punctuation.
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect()
+}
+
+// Whether the words of `phrase` appear in `text` in a row, e.g. "accept" in "accept all" but not
+// in "acceptable terms".
+fn contains_words(text: &str, phrase: &str) -> bool {
+    let (text, phrase) = (words(text), words(phrase));
+    !phrase.is_empty() && text.windows(phrase.len()).any(|window| window == phrase.as_slice())
+}
+
+/// Finds overlays covering the page (cookie banners, modals, newsletter pop-ups) and dismisses them.
+///
+/// An element is treated as an overlay if it is visible, has `position: fixed`, a `z-index` of at
+/// least 10, and either spans (nearly) the full viewport width or covers a quarter of the viewport.
+/// Nested overlays are only handled once, through their outermost ancestor.
+///
+/// For each overlay, a control that looks like it dismisses it (e.g. "Accept", "Close", "×") is
+/// clicked. If no such control is found, the overlay is hidden with `display: none !important`.
+///
+/// # Returns
+/// * `Ok(String)`: A JSON array describing each handled overlay, e.g.
+///   `[{"overlay": "div#cookie-banner", "action": "clicked", "control": "Accept all"},
+///     {"overlay": "div.modal", "action": "hidden", "control": null}]`.
+///   An empty array (`[]`) means no overlays were found.
+/// * `Err(DomError)` if the page could not be inspected or the report could not be serialized.
+#[wasm_bindgen]
+pub fn dismiss_overlays() -> Result<String, DomError> {
+    console::log_1(&"Attempting to dismiss overlays".into());
+    let (window, document) = get_window_document()?;
+    let viewport_width = window.inner_width()?.as_f64().unwrap_or(0.0);
+    let viewport_height = window.inner_height()?.as_f64().unwrap_or(0.0);
+
+    let mut overlays: Vec<Element> = Vec::new();
+    for element in get_all_elements(&document, "css:body *")? {
+        if overlays.iter().any(|overlay| overlay.contains(Some(&element))) {
+            continue; // Already handled through an ancestor.
+        }
+        let style = match window.get_computed_style(&element)? {
+            Some(style) => style,
+            None => continue,
+        };
+        if style.get_property_value("position")? != "fixed"
+            || style.get_property_value("display")? == "none"
+            || style.get_property_value("visibility")? == "hidden"
+        {
+            continue;
+        }
+        let z_index = style.get_property_value("z-index")?.parse::<i32>().unwrap_or(0);
+        if z_index < OVERLAY_MIN_Z_INDEX {
+            continue;
+        }
+        let rect = element.get_bounding_client_rect();
+        let is_banner = rect.width() >= viewport_width * OVERLAY_MIN_WIDTH_RATIO && rect.height() > 0.0;
+        let is_modal = rect.width() * rect.height() >= viewport_width * viewport_height * OVERLAY_MIN_AREA_RATIO;
+        if is_banner || is_modal {
+            overlays.push(element);
+        }
+    }
+
+    let mut report = Vec::new();
+    for overlay in overlays {
+        let description = describe_element(&overlay);
+        match find_dismiss_control(&overlay)? {
+            Some((control, label)) => {
+                control.click();
+                console::log_1(&format!("Clicked '{}' to dismiss overlay {}", label, description).into());
+                report.push(serde_json::json!({ "overlay": description, "action": "clicked", "control": label }));
+            }
+            None => {
+                let html_overlay = overlay.dyn_into::<HtmlElement>().map_err(|_| DomError::ElementTypeError {
+                    selector: description.clone(),
+                    expected_type: "HtmlElement".to_string(),
+                })?;
+                html_overlay.style().set_property_with_priority("display", "none", "important")?;
+                console::log_1(&format!("Hid overlay {} (no dismiss control found)", description).into());
+                report.push(serde_json::json!({ "overlay": description, "action": "hidden", "control": null }));
+            }
+        }
+    }
+
+    console::log_1(&format!("Successfully handled {} overlay(s)", report.len()).into());
+    serde_json::to_string(&report).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+/// Attribute set on every `<style>` element created by `inject_css`, so injected styles can be
+/// told apart from the page's own styles and removed together.
+const INJECTED_CSS_MARKER_ATTRIBUTE: &str = "data-rustagent-injected-css";
+
+// Counter used to generate unique ids for injected `<style>` elements.
+static INJECTED_CSS_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Injects a stylesheet into the page, e.g. to hide sticky headers or overlays that block clicks.
+///
+/// The CSS is added as a new `<style>` element in the document head, with a generated id
+/// (`rustagent-injected-css-<n>`) that can later be passed to `remove_injected_css`.
+///
+/// # Arguments
+/// * `css_text`: The CSS rules to inject (e.g., `".cookie-banner { display: none !important; }"`).
+///
+/// # Returns
+/// * `Ok(String)`: The generated id of the injected `<style>` element.
+/// * `Err(DomError)` if the style element could not be created or inserted.
+#[wasm_bindgen]
+pub fn inject_css(css_text: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to inject CSS ({} chars)", css_text.len()).into());
+    let (_window, document) = get_window_document()?;
+
+    let style_id = format!("rustagent-injected-css-{}", INJECTED_CSS_COUNTER.fetch_add(1, Ordering::Relaxed) + 1);
+    let style_element = document.create_element("style")?;
+    style_element.set_id(&style_id);
+    style_element.set_attribute(INJECTED_CSS_MARKER_ATTRIBUTE, "")?;
+    style_element.set_text_content(Some(css_text));
+
+    let parent = document
+        .query_selector("head")?
+        .or_else(|| document.document_element())
+        .ok_or_else(|| DomError::JsError { message: "Failed to find document head or root element".to_string() })?;
+    parent.append_child(&style_element)?;
+
+    console::log_1(&format!("Successfully injected CSS with id: {}", style_id).into());
+    Ok(style_id)
+}
+
+/// Removes stylesheets previously added with `inject_css`.
+///
+/// # Arguments
+/// * `style_id`: The id returned by `inject_css`. If `None`, every injected stylesheet is removed.
+///   Elements that were not created by `inject_css` are never removed.
 ///
 /// # Returns
-/// * `Ok(String)`:
-///     - If elements are found and contain text, this is the concatenated string of their
-///       `inner_text` values, joined by the `separator`.
-///     - If no elements are found matching the `selector`, an empty string is returned.
-///     - If elements are found but none of them contain any non-empty text content (e.g.,
-///       they are empty elements or contain only other elements without text), an empty
-///       string is returned.
-/// * `Err(DomError)`: If an error occurs during element retrieval, such as an
-///   `InvalidSelector` if the provided selector string is malformed.
+/// * `Ok(usize)`: The number of stylesheets removed.
+/// * `Err(DomError::ElementNotFound)` if `style_id` is given but no injected stylesheet has that id.
+/// * `Err(DomError)` for other errors.
 #[wasm_bindgen]
-pub fn get_all_text_from_elements(selector: &str, separator: &str) -> Result<String, DomError> {
-    console::log_1(&format!("Attempting to get all text from elements matching selector: {} with separator: '{}'", selector, separator).into());
+pub fn remove_injected_css(style_id: Option<String>) -> Result<usize, DomError> {
+    console::log_1(&format!("Attempting to remove injected CSS: {}", style_id.as_deref().unwrap_or("<all>")).into());
     let (_window, document) = get_window_document()?;
-    let elements = get_all_elements(&document, selector)?;
-
-    if elements.is_empty() {
-        console::log_1(&format!("No elements found for selector '{}'. Returning empty string.", selector).into());
-        return Ok("".to_string());
-    }
 
-    let texts: Vec<String> = elements
-        .into_iter()
-        .filter_map(|el| {
-            el.dyn_into::<HtmlElement>().ok().map(|html_el| html_el.inner_text())
-        })
-        .filter(|text| !text.is_empty()) // Optionally filter out empty strings
-        .collect();
+    let removed = match style_id {
+        Some(id) => {
+            let style_element = document
+                .get_element_by_id(&id)
+                .filter(|el| el.has_attribute(INJECTED_CSS_MARKER_ATTRIBUTE))
+                .ok_or_else(|| DomError::ElementNotFound {
+                    selector: id.clone(),
+                    message: Some(format!("No injected stylesheet found with id '{}'", id)),
+                })?;
+            style_element.remove();
+            1
+        }
+        None => {
+            let injected = get_all_elements(&document, &format!("css:style[{}]", INJECTED_CSS_MARKER_ATTRIBUTE))?;
+            let count = injected.len();
+            for style_element in injected {
+                style_element.remove();
+            }
+            count
+        }
+    };
 
-    if texts.is_empty() {
-        console::log_1(&format!("Elements found for selector '{}', but they contained no text. Returning empty string.", selector).into());
-        return Ok("".to_string());
-    }
+    console::log_1(&format!("Successfully removed {} injected stylesheet(s)", removed).into());
+    Ok(removed)
+}
 
-    console::log_1(&format!("Successfully retrieved {} text segments for selector '{}'.", texts.len(), selector).into());
-    Ok(texts.join(separator))
+/// Runs a JavaScript snippet and returns its result serialized as JSON.
+///
+/// The snippet is used as the body of a new function (via `js_sys::Function`), so it must
+/// `return` the value it wants to hand back, e.g. `return document.title;`.
+///
+/// This function is intentionally not exported to JavaScript and performs no policy checks
+/// itself; callers are responsible for enforcing the eval policy (see `ExecutionConfig`).
+///
+/// # Arguments
+/// * `script`: The JavaScript function body to execute.
+///
+/// # Returns
+/// * `Ok(String)`: The JSON serialization of the returned value (`"null"` if the snippet
+///   returns `undefined` or a value that JSON cannot represent, such as a function).
+/// * `Err(DomError)`: If the snippet fails to compile or throws (mapped to `JsSyntaxError`,
+///   `JsTypeError`, `JsReferenceError` or `JsError`), or the result cannot be serialized.
+pub fn eval_js(script: &str) -> Result<String, DomError> {
+    console::log_1(&format!("Attempting to evaluate JS snippet ({} chars)", script.len()).into());
+    // `Function::new_no_args` cannot report syntax errors, so the `Function` constructor is
+    // invoked through `Reflect::construct`, which surfaces them as a catchable error.
+    let function_constructor = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("Function"))?
+        .dyn_into::<js_sys::Function>()
+        .map_err(|_| DomError::JsError { message: "Failed to get Function constructor".to_string() })?;
+    let function = js_sys::Reflect::construct(&function_constructor, &js_sys::Array::of1(&JsValue::from_str(script)))?
+        .dyn_into::<js_sys::Function>()
+        .map_err(|_| DomError::JsError { message: "Failed to build function from EVAL_JS snippet".to_string() })?;
+    let value = function.call0(&JsValue::NULL)?;
+    let json = js_sys::JSON::stringify(&value).map_err(|e| DomError::SerializationError {
+        message: format!("Failed to serialize EVAL_JS result: {}", e.as_string().unwrap_or_else(|| "Unknown serialization error".to_string())),
+    })?;
+    // JSON.stringify returns undefined (not a string) for undefined/function results.
+    let json_string = JsValue::from(json).as_string().unwrap_or_else(|| "null".to_string());
+    console::log_1(&"Successfully evaluated JS snippet".into());
+    Ok(json_string)
 }
 
 
@@ -1071,6 +3542,68 @@ mod tests {
         assert!(url.contains("http") || url.contains("file:"), "URL should be a valid http or file URL, got: {}", url);
     }
 
+    #[wasm_bindgen_test]
+    fn test_get_page_language() {
+        let (_window, document) = get_window_document().unwrap();
+        let root = document.document_element().unwrap();
+        let previous_lang = root.get_attribute("lang");
+        root.set_attribute("lang", "en").unwrap();
+        let notice = setup_element(&document, "language-notice", "p", None);
+        notice.set_text_content(Some("Wir verwenden Cookies, damit die Seite für Sie funktioniert und wir sie verbessern können. Bitte stimmen Sie der Nutzung zu, bevor Sie mit dem Einkauf fortfahren und die Angebote sehen."));
+
+        let language: serde_json::Value = serde_json::from_str(&get_page_language().unwrap()).unwrap();
+        assert_eq!(language["lang"], "en");
+        assert_eq!(language["detected"], "de", "The visible text is German despite the declared language");
+        assert!(language["confidence"].as_f64().unwrap() > 0.5);
+
+        root.remove_attribute("lang").unwrap();
+        let language: serde_json::Value = serde_json::from_str(&get_page_language().unwrap()).unwrap();
+        assert!(language["lang"].is_null());
+
+        cleanup_element(notice);
+        if let Some(lang) = previous_lang {
+            root.set_attribute("lang", &lang).unwrap();
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_media_query_matches() {
+        assert!(media_query_matches("(min-width: 0px)").unwrap());
+        assert!(!media_query_matches("(max-width: 0px)").unwrap());
+        assert_eq!(is_dark_mode().unwrap(), media_query_matches("(prefers-color-scheme: dark)").unwrap());
+        assert!(matches!(media_query_matches("((("), Err(DomError::JsSyntaxError { .. })));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_get_frames_lists_iframes() {
+        let (_window, document) = get_window_document().unwrap();
+        let frame = setup_element(&document, "frames-test", "iframe", Some(vec![("name", "embedded")]));
+
+        let frames: Vec<serde_json::Value> = serde_json::from_str(&get_frames().unwrap()).unwrap();
+        let entry = frames.iter().find(|f| f["name"] == "embedded").expect("iframe should be listed");
+        assert_eq!(entry["same_origin"], true, "An iframe without src is same-origin (about:blank)");
+        assert_eq!(entry["src"], "");
+        assert!(entry["index"].is_u64());
+
+        cleanup_element(frame);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_xpath_eval_result_types() {
+        let (_window, document) = get_window_document().unwrap();
+        let list = setup_element(&document, "xpath-eval-list", "ul", None);
+        list.set_inner_html("<li data-n='1'>one</li><li data-n='2'>two</li><li data-n='3'>three</li>");
+
+        assert_eq!(xpath_eval("count(//ul[@id='xpath-eval-list']/li)"), Ok(r#"{"type":"number","value":3}"#.to_string()));
+        assert_eq!(xpath_eval("xpath:string(//ul[@id='xpath-eval-list']/li[2])"), Ok(r#"{"type":"string","value":"two"}"#.to_string()));
+        assert_eq!(xpath_eval("boolean(//ul[@id='xpath-eval-list']/li[4])"), Ok(r#"{"type":"boolean","value":false}"#.to_string()));
+        assert_eq!(xpath_eval("//ul[@id='xpath-eval-list']/li/@data-n"), Ok(r#"{"type":"nodes","value":["1","2","3"]}"#.to_string()));
+        assert_eq!(xpath_eval("//ul[@id='xpath-eval-list']/li[1]/text()"), Ok(r#"{"type":"nodes","value":["one"]}"#.to_string()));
+        assert!(matches!(xpath_eval("count(//li"), Err(DomError::InvalidSelector { .. })));
+
+        cleanup_element(list);
+    }
+
     // Tests for element_exists
     #[wasm_bindgen_test]
     fn test_element_exists_css_true() {
@@ -1165,7 +3698,7 @@ mod tests {
         // This test will take around 5 seconds if the element doesn't exist
         // To make it practical, we can test that it *would* succeed if element was there
         // or test the timeout with a very short, specific timeout for "non-existent"
-        // The timeout_ms: None should use DEFAULT_TIMEOUT_MS (5000ms)
+        // The timeout_ms: None should use DEFAULT_WAIT_TIMEOUT_MS (5000ms)
         let (_window, document) = get_window_document().unwrap();
         let el = setup_element(&document, "wait-default-timeout", "div", None);
         let result = wait_for_element("css:#wait-default-timeout", None).await; // Uses default timeout
@@ -1173,6 +3706,103 @@ mod tests {
         cleanup_element(el);
     }
 
+    #[wasm_bindgen_test]
+    async fn test_wait_for_element_with_configured_timeout() {
+        let wait = WaitConfig { timeout_ms: 150, interval_ms: 20 };
+        let result = wait_for_element_with("css:#wait-configured-nonexistent", None, &wait).await;
+        match result {
+            Err(DomError::ElementNotFound { message: Some(message), .. }) => {
+                assert!(message.contains("after 150ms timeout"), "Unexpected message: {}", message);
+            }
+            _ => panic!("Expected ElementNotFound, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_wait_config_timeout() {
+        let wait = WaitConfig { timeout_ms: 20000, interval_ms: 250 };
+        assert_eq!(wait.timeout(None, DEFAULT_SUGGESTION_TIMEOUT_MS), 20000);
+        assert_eq!(wait.timeout(Some(300), DEFAULT_SUGGESTION_TIMEOUT_MS), 300, "A command's own timeout takes precedence");
+        assert_eq!(WaitConfig::default().timeout(None, DEFAULT_SEARCH_RESULTS_TIMEOUT_MS), 10000, "Longer command defaults are kept");
+    }
+
+    #[test]
+    fn test_selector_candidates() {
+        assert_eq!(
+            selector_candidates("[css:#buy, text:\"Buy now\", xpath://button[1]]").unwrap(),
+            ["css:#buy", "text:Buy now", "xpath://button[1]"]
+        );
+        assert_eq!(
+            selector_candidates("[css:[title='a, b'], css:li:is(.a, .b)]").unwrap(),
+            ["css:[title='a, b']", "css:li:is(.a, .b)"],
+            "Commas inside quotes, brackets and parentheses do not separate candidates"
+        );
+        assert_eq!(selector_candidates("[data-test=buy]"), None, "A CSS attribute selector is not a chain");
+        assert_eq!(selector_candidates("[name=q], [name=search]"), None, "Nor is a CSS selector list");
+        assert_eq!(selector_candidates("[css:#buy, ]"), None);
+        assert_eq!(selector_chain_len("[css:#q, text:\"Search\"] hello world"), Some(23));
+        assert_eq!(selector_chain_len("css:#q hello"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_selector_chain_uses_first_matching_candidate() {
+        let (_window, document) = get_window_document().unwrap();
+        let first = setup_element(&document, "chain-first", "button", None);
+        let second = setup_element(&document, "chain-second", "button", None);
+        let chain = "[css:#chain-missing, css:##bad, css:#chain-second, css:#chain-first]";
+
+        assert!(element_exists(chain).unwrap());
+        assert_eq!(matching_candidate(chain).unwrap().as_deref(), Some("css:#chain-second"));
+        assert_eq!(get_element(&document, chain).unwrap(), second, "Candidates are tried in order");
+        assert_eq!(matching_candidate("css:#chain-first").unwrap(), None, "A single selector is not a chain");
+
+        let result = get_element(&document, "[css:#chain-missing, css:##bad]");
+        assert!(matches!(result, Err(DomError::InvalidSelector { .. })), "Unexpected result: {:?}", result);
+        assert!(!element_exists("[css:#chain-missing, xpath://nothing]").unwrap());
+        cleanup_element(first);
+        cleanup_element(second);
+    }
+
+    #[test]
+    fn test_split_scope() {
+        assert_eq!(split_scope("css:#chat >> text:Send"), Some(("css:#chat", "text:Send")));
+        assert_eq!(split_scope("css:#a >> css:#b >> css:button"), Some(("css:#a", "css:#b >> css:button")), "Split at the first separator");
+        assert_eq!(split_scope("[css:#chat, text:Chat] >> css:button"), Some(("[css:#chat, text:Chat]", "css:button")));
+        assert_eq!(split_scope("css:#chat >> [css:#send, text:\"a >> b\"]"), Some(("css:#chat", "[css:#send, text:\"a >> b\"]")));
+        assert_eq!(split_scope("text:\"a >> b\""), None, "Separators inside quotes do not scope");
+        assert_eq!(split_scope("css:ul > li"), None);
+        assert_eq!(split_scope(" >> css:button"), None);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_scoped_selectors_resolve_within_the_region() {
+        let (_window, document) = get_window_document().unwrap();
+        let first = setup_element(&document, "scope-first", "div", None);
+        let second = setup_element(&document, "scope-second", "div", None);
+        for region in [&first, &second] {
+            region.set_inner_html("<button class=\"scope-save\">Save</button><button class=\"scope-save\">Save as</button>");
+        }
+        let in_second = second.query_selector("button").unwrap().unwrap();
+
+        for selector in ["css:#scope-second >> css:.scope-save", "css:#scope-second >> xpath://button", "css:#scope-second >> text:Save"] {
+            assert_eq!(get_element(&document, selector).unwrap(), in_second, "{}", selector);
+        }
+        assert_eq!(get_all_elements(&document, "css:#scope-second >> css:.scope-save").unwrap().len(), 2);
+        assert_eq!(
+            matching_candidate("css:#scope-second >> [css:#scope-first, css:.scope-save]").unwrap().as_deref(),
+            Some("css:#scope-second >> css:.scope-save"),
+            "Candidates outside the region do not match"
+        );
+
+        let outside = get_element(&document, "css:#scope-second >> css:#scope-first").unwrap_err();
+        assert!(outside.to_string().contains("within the region 'css:#scope-second'"), "{}", outside);
+        let missing = get_element(&document, "css:#scope-missing >> css:.scope-save").unwrap_err();
+        assert!(matches!(&missing, DomError::ElementNotFound { selector, .. } if selector == "css:#scope-missing >> css:.scope-save"));
+        assert!(missing.to_string().contains("region 'css:#scope-missing'"), "{}", missing);
+        cleanup_element(first);
+        cleanup_element(second);
+    }
+
     // Tests for is_visible
     #[wasm_bindgen_test]
     fn test_is_visible_standard_element() {
@@ -1435,4 +4065,660 @@ mod tests {
             other => panic!("Expected InvalidSelector, got {:?}", other),
         }
     }
+
+    #[wasm_bindgen_test]
+    fn test_eval_js_returns_json() {
+        assert_eq!(eval_js("return 1 + 2;").unwrap(), "3");
+        assert_eq!(eval_js("return {a: [1, 'x']};").unwrap(), "{\"a\":[1,\"x\"]}");
+        assert_eq!(eval_js("document.title;").unwrap(), "null"); // No return => undefined
+    }
+
+    #[wasm_bindgen_test]
+    fn test_eval_js_errors() {
+        match eval_js("return (;") {
+            Err(DomError::JsSyntaxError { .. }) => {}
+            other => panic!("Expected JsSyntaxError, got {:?}", other),
+        }
+        match eval_js("return undefinedVariableForEvalTest;") {
+            Err(DomError::JsReferenceError { .. }) => {}
+            other => panic!("Expected JsReferenceError, got {:?}", other),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_inject_and_remove_css() {
+        let (window, document) = get_window_document().unwrap();
+        let el = setup_element(&document, "css-inject-target", "div", None);
+
+        let style_id = inject_css("#css-inject-target { display: none !important; }").unwrap();
+        assert!(style_id.starts_with("rustagent-injected-css-"));
+        let style = window.get_computed_style(&el).unwrap().unwrap();
+        assert_eq!(style.get_property_value("display").unwrap(), "none");
+
+        assert_eq!(remove_injected_css(Some(style_id.clone())), Ok(1));
+        assert!(document.get_element_by_id(&style_id).is_none());
+        let style = window.get_computed_style(&el).unwrap().unwrap();
+        assert_ne!(style.get_property_value("display").unwrap(), "none");
+
+        cleanup_element(el);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_remove_injected_css_all_and_unknown_id() {
+        let (_window, document) = get_window_document().unwrap();
+        // A page-owned element must never be removed by remove_injected_css.
+        let page_style = setup_element(&document, "page-owned-style", "style", None);
+
+        inject_css("body { outline: 1px solid red; }").unwrap();
+        inject_css("body { outline: 2px solid blue; }").unwrap();
+        assert!(remove_injected_css(None).unwrap() >= 2);
+        assert_eq!(remove_injected_css(None), Ok(0));
+
+        match remove_injected_css(Some("page-owned-style".to_string())) {
+            Err(DomError::ElementNotFound { selector, .. }) => assert_eq!(selector, "page-owned-style"),
+            other => panic!("Expected ElementNotFound, got {:?}", other),
+        }
+        assert!(document.get_element_by_id("page-owned-style").is_some());
+
+        cleanup_element(page_style);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_dismiss_overlays_clicks_accept_button() {
+        let (_window, document) = get_window_document().unwrap();
+        let banner = setup_element(&document, "test-cookie-banner", "div", Some(vec![
+            ("style", "position: fixed; bottom: 0; left: 0; width: 100%; height: 80px; z-index: 9999;"),
+        ]));
+        let accept = document.create_element("button").unwrap();
+        accept.set_text_content(Some("Accept all cookies"));
+        // Mark the banner when the accept button is clicked so the click can be observed.
+        accept.set_attribute("onclick", "this.parentElement.setAttribute('data-accepted', 'true')").unwrap();
+        banner.append_child(&accept).unwrap();
+
+        let report: serde_json::Value = serde_json::from_str(&dismiss_overlays().unwrap()).unwrap();
+        let entry = report.as_array().unwrap().iter()
+            .find(|entry| entry["overlay"] == "div#test-cookie-banner")
+            .expect("Banner should be reported");
+        assert_eq!(entry["action"], "clicked");
+        assert_eq!(entry["control"], "Accept all cookies");
+        assert_eq!(banner.get_attribute("data-accepted").as_deref(), Some("true"));
+
+        cleanup_element(banner);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_dismiss_overlays_skips_navigating_links() {
+        let (_window, document) = get_window_document().unwrap();
+        let modal = setup_element(&document, "test-checkout-modal", "div", Some(vec![
+            ("style", "position: fixed; top: 0; left: 0; width: 100vw; height: 100vh; z-index: 1000;"),
+        ]));
+        let checkout = document.create_element("a").unwrap();
+        checkout.set_attribute("href", "/checkout").unwrap();
+        checkout.set_text_content(Some("Close and go to checkout"));
+        let notifications = document.create_element("button").unwrap();
+        notifications.set_text_content(Some("Allow notifications"));
+        let dismiss = document.create_element("a").unwrap();
+        dismiss.set_attribute("href", "#").unwrap();
+        dismiss.set_text_content(Some("No thanks"));
+        for control in [&checkout, &notifications, &dismiss] {
+            modal.append_child(control).unwrap();
+        }
+
+        let report: serde_json::Value = serde_json::from_str(&dismiss_overlays().unwrap()).unwrap();
+        let entry = report.as_array().unwrap().iter()
+            .find(|entry| entry["overlay"] == "div#test-checkout-modal")
+            .expect("Modal should be reported");
+        assert_eq!(entry["control"], "No thanks");
+        assert!(contains_words("accept all", "accept"));
+        assert!(!contains_words("acceptable terms", "accept"));
+
+        cleanup_element(modal);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_dismiss_overlays_hides_overlay_without_controls() {
+        let (_window, document) = get_window_document().unwrap();
+        let modal = setup_element(&document, "test-modal-overlay", "div", Some(vec![
+            ("style", "position: fixed; top: 0; left: 0; width: 100vw; height: 100vh; z-index: 1000;"),
+        ]));
+        let low_banner = setup_element(&document, "test-low-z-banner", "div", Some(vec![
+            ("style", "position: fixed; top: 0; left: 0; width: 100%; height: 40px; z-index: 1;"),
+        ]));
+
+        let report: serde_json::Value = serde_json::from_str(&dismiss_overlays().unwrap()).unwrap();
+        let entries = report.as_array().unwrap();
+        let entry = entries.iter()
+            .find(|entry| entry["overlay"] == "div#test-modal-overlay")
+            .expect("Modal should be reported");
+        assert_eq!(entry["action"], "hidden");
+        assert!(entries.iter().all(|entry| entry["overlay"] != "div#test-low-z-banner"), "Low z-index element is not an overlay");
+        assert_eq!(is_visible("css:#test-modal-overlay"), Ok(false));
+
+        cleanup_element(modal);
+        cleanup_element(low_banner);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_get_element_attributes() {
+        let (_window, document) = get_window_document().unwrap();
+        let el = setup_element(&document, "all-attrs-el", "input", Some(vec![
+            ("type", "email"),
+            ("data-user-id", "42"),
+            ("disabled", ""),
+        ]));
+
+        let result: serde_json::Value = serde_json::from_str(&get_element_attributes("css:#all-attrs-el").unwrap()).unwrap();
+        assert_eq!(result, serde_json::json!({
+            "id": "all-attrs-el",
+            "type": "email",
+            "data-user-id": "42",
+            "disabled": ""
+        }));
+
+        cleanup_element(el);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_get_element_attributes_not_found() {
+        let result = get_element_attributes("css:#no-such-attrs-el");
+        assert_dom_error_eq(result, DomError::ElementNotFound { selector: "css:#no-such-attrs-el".to_string(), message: None });
+    }
+
+    #[wasm_bindgen_test]
+    fn test_get_element_dataset() {
+        let (_window, document) = get_window_document().unwrap();
+        let el = setup_element(&document, "dataset-el", "div", Some(vec![
+            ("data-user-id", "42"),
+            ("data-state", "open"),
+            ("class", "not-data"),
+        ]));
+
+        let result: serde_json::Value = serde_json::from_str(&get_element_dataset("css:#dataset-el").unwrap()).unwrap();
+        assert_eq!(result, serde_json::json!({ "userId": "42", "state": "open" }));
+
+        let empty = setup_element(&document, "dataset-empty-el", "div", None);
+        assert_eq!(get_element_dataset("css:#dataset-empty-el"), Ok("{}".to_string()));
+
+        cleanup_element(el);
+        cleanup_element(empty);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_get_element_role_explicit_and_implicit() {
+        let (_window, document) = get_window_document().unwrap();
+        let explicit = setup_element(&document, "role-explicit", "div", Some(vec![("role", "tab presentation")]));
+        let link = setup_element(&document, "role-link", "a", Some(vec![("href", "#top")]));
+        let checkbox = setup_element(&document, "role-checkbox", "input", Some(vec![("type", "checkbox")]));
+        let plain = setup_element(&document, "role-plain", "div", None);
+
+        assert_eq!(get_element_role("css:#role-explicit"), Ok("tab".to_string()));
+        assert_eq!(get_element_role("css:#role-link"), Ok("link".to_string()));
+        assert_eq!(get_element_role("css:#role-checkbox"), Ok("checkbox".to_string()));
+        assert_eq!(get_element_role("css:#role-plain"), Ok("generic".to_string()));
+
+        cleanup_element(explicit);
+        cleanup_element(link);
+        cleanup_element(checkbox);
+        cleanup_element(plain);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_get_aria_state() {
+        let (_window, document) = get_window_document().unwrap();
+        let toggle = setup_element(&document, "aria-toggle", "button", Some(vec![("aria-expanded", "false"), ("aria-pressed", "mixed")]));
+        let state: serde_json::Value = serde_json::from_str(&get_aria_state("css:#aria-toggle").unwrap()).unwrap();
+        assert_eq!(state["role"], "button");
+        assert_eq!(state["expanded"], false);
+        assert_eq!(state["pressed"], "mixed");
+        assert_eq!(state["checked"], serde_json::Value::Null);
+        assert_eq!(state["disabled"], false);
+
+        // Native checkbox state inside a disabled fieldset.
+        let fieldset = setup_element(&document, "aria-fieldset", "fieldset", Some(vec![("disabled", "")]));
+        let checkbox = document.create_element("input").unwrap();
+        checkbox.set_id("aria-native-checkbox");
+        checkbox.set_attribute("type", "checkbox").unwrap();
+        fieldset.append_child(&checkbox).unwrap();
+        checkbox.dyn_ref::<HtmlInputElement>().unwrap().set_checked(true);
+
+        let state: serde_json::Value = serde_json::from_str(&get_aria_state("css:#aria-native-checkbox").unwrap()).unwrap();
+        assert_eq!(state["role"], "checkbox");
+        assert_eq!(state["checked"], true);
+        assert_eq!(state["disabled"], true);
+
+        cleanup_element(toggle);
+        cleanup_element(fieldset);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_is_enabled_with_fieldset_inheritance() {
+        let (_window, document) = get_window_document().unwrap();
+        let enabled = setup_element(&document, "state-enabled", "button", None);
+        let disabled = setup_element(&document, "state-disabled", "button", Some(vec![("disabled", "")]));
+        let aria_disabled = setup_element(&document, "state-aria-disabled", "div", Some(vec![("aria-disabled", "true")]));
+        let fieldset = setup_element(&document, "state-fieldset", "fieldset", Some(vec![("disabled", "")]));
+        let legend = document.create_element("legend").unwrap();
+        let legend_input = document.create_element("input").unwrap();
+        legend_input.set_id("state-legend-input");
+        legend.append_child(&legend_input).unwrap();
+        fieldset.append_child(&legend).unwrap();
+        let inner_input = document.create_element("input").unwrap();
+        inner_input.set_id("state-fieldset-input");
+        fieldset.append_child(&inner_input).unwrap();
+
+        assert_eq!(is_enabled("css:#state-enabled"), Ok(true));
+        assert_eq!(is_enabled("css:#state-disabled"), Ok(false));
+        assert_eq!(is_enabled("css:#state-aria-disabled"), Ok(false));
+        assert_eq!(is_enabled("css:#state-fieldset-input"), Ok(false));
+        assert_eq!(is_enabled("css:#state-legend-input"), Ok(true), "Controls in the first legend are not disabled by the fieldset");
+
+        cleanup_element(enabled);
+        cleanup_element(disabled);
+        cleanup_element(aria_disabled);
+        cleanup_element(fieldset);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_is_editable() {
+        let (_window, document) = get_window_document().unwrap();
+        let text = setup_element(&document, "edit-text", "input", Some(vec![("type", "text")]));
+        let readonly = setup_element(&document, "edit-readonly", "input", Some(vec![("readonly", "")]));
+        let checkbox = setup_element(&document, "edit-checkbox", "input", Some(vec![("type", "checkbox")]));
+        let rich = setup_element(&document, "edit-rich", "div", Some(vec![("contenteditable", "true")]));
+
+        assert_eq!(is_editable("css:#edit-text"), Ok(true));
+        assert_eq!(is_editable("css:#edit-readonly"), Ok(false));
+        assert_eq!(is_editable("css:#edit-checkbox"), Ok(false));
+        assert_eq!(is_editable("css:#edit-rich"), Ok(true));
+
+        cleanup_element(text);
+        cleanup_element(readonly);
+        cleanup_element(checkbox);
+        cleanup_element(rich);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_is_selected() {
+        let (_window, document) = get_window_document().unwrap();
+        let checked = setup_element(&document, "sel-checked", "input", Some(vec![("type", "checkbox"), ("checked", "")]));
+        let unchecked = setup_element(&document, "sel-unchecked", "input", Some(vec![("type", "radio")]));
+        let tab = setup_element(&document, "sel-tab", "div", Some(vec![("role", "tab"), ("aria-selected", "true")]));
+
+        assert_eq!(is_selected("css:#sel-checked"), Ok(true));
+        assert_eq!(is_selected("css:#sel-unchecked"), Ok(false));
+        assert_eq!(is_selected("css:#sel-tab"), Ok(true));
+
+        cleanup_element(checked);
+        cleanup_element(unchecked);
+        cleanup_element(tab);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_tab_to_follows_tab_order() {
+        let (_window, document) = get_window_document().unwrap();
+        let elements = vec![
+            setup_element(&document, "tab-save", "button", None),
+            setup_element(&document, "tab-disabled", "input", Some(vec![("disabled", "")])),
+            setup_element(&document, "tab-hidden", "input", Some(vec![("style", "display: none")])),
+            setup_element(&document, "tab-radio-1", "input", Some(vec![("type", "radio"), ("name", "tab-plan")])),
+            setup_element(&document, "tab-radio-2", "input", Some(vec![("type", "radio"), ("name", "tab-plan"), ("checked", "")])),
+            setup_element(&document, "tab-skipped", "div", Some(vec![("tabindex", "-1")])),
+            setup_element(&document, "tab-email-label", "label", Some(vec![("for", "tab-email")])),
+            setup_element(&document, "tab-email", "input", None),
+        ];
+        elements[0].set_text_content(Some("Save"));
+        elements[6].set_text_content(Some("Email address"));
+        let save = elements[0].dyn_ref::<HtmlElement>().unwrap();
+
+        save.focus().unwrap();
+        let focused: serde_json::Value = serde_json::from_str(&get_focused().unwrap()).unwrap();
+        assert_eq!(focused["element"], "button#tab-save");
+        assert_eq!(focused["role"], "button");
+        assert_eq!(focused["name"], "Save");
+
+        assert_eq!(tab_to("css:#tab-radio-2", None), Ok(Some(1)), "Disabled, hidden and unselected radio buttons are skipped");
+        assert_eq!(tab_to("css:#tab-email", None), Ok(Some(1)), "Elements with tabindex -1 are skipped");
+        assert_eq!(tab_to("css:#tab-email", None), Ok(Some(0)), "Already focused");
+        let focused: serde_json::Value = serde_json::from_str(&get_focused().unwrap()).unwrap();
+        assert_eq!(focused["name"], "Email address");
+        assert_eq!(tab_to("css:#tab-skipped", Some(3)), Ok(None));
+
+        // A focus trap cancels Tab and moves focus itself.
+        elements[7].set_attribute("onkeydown", "if (event.key === 'Tab') { event.preventDefault(); document.getElementById('tab-save').focus(); }").unwrap();
+        elements[7].dyn_ref::<HtmlElement>().unwrap().focus().unwrap();
+        press_tabs(1).unwrap();
+        assert_eq!(document.active_element().as_ref(), Some(&elements[0]));
+
+        save.blur().unwrap();
+        assert_eq!(get_focused().unwrap(), "null");
+        for element in elements {
+            cleanup_element(element);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_click_at_and_click_center() {
+        let (_window, document) = get_window_document().unwrap();
+        let target = setup_element(&document, "click-at-target", "div", Some(vec![
+            ("style", "position: fixed; top: 10px; left: 10px; width: 100px; height: 50px; z-index: 100000;"),
+            ("onclick", "this.setAttribute('data-click', event.clientX + ',' + event.clientY)"),
+        ]));
+
+        assert_eq!(click_at(20.0, 30.0), Ok("div#click-at-target".to_string()));
+        assert_eq!(target.get_attribute("data-click").as_deref(), Some("20,30"));
+
+        assert_eq!(click_center("css:#click-at-target"), Ok("div#click-at-target".to_string()));
+        assert_eq!(target.get_attribute("data-click").as_deref(), Some("60,35"));
+
+        cleanup_element(target);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_click_at_outside_viewport() {
+        match click_at(-100.0, -100.0) {
+            Err(DomError::ElementNotFound { .. }) => {}
+            other => panic!("Expected ElementNotFound, got {:?}", other),
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_mouse_move_dispatches_over_and_move() {
+        let (_window, document) = get_window_document().unwrap();
+        let target = setup_element(&document, "mouse-move-target", "div", Some(vec![
+            ("style", "position: fixed; top: 200px; left: 200px; width: 80px; height: 40px; z-index: 100000;"),
+            ("onmouseover", "this.setAttribute('data-over', 'true')"),
+            ("onpointermove", "this.setAttribute('data-pointer-type', event.pointerType)"),
+        ]));
+
+        assert!(mouse_move("5,5").is_ok());
+        assert_eq!(mouse_move("css:#mouse-move-target"), Ok("div#mouse-move-target".to_string()));
+        assert_eq!(target.get_attribute("data-over").as_deref(), Some("true"));
+        assert_eq!(target.get_attribute("data-pointer-type").as_deref(), Some("mouse"));
+
+        cleanup_element(target);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_wheel_scrolls_container_unless_canceled() {
+        let (_window, document) = get_window_document().unwrap();
+        let container = setup_element(&document, "wheel-container", "div", Some(vec![
+            ("style", "height: 50px; overflow-y: auto;"),
+        ]));
+        let content = document.create_element("div").unwrap();
+        content.set_attribute("style", "height: 500px;").unwrap();
+        container.append_child(&content).unwrap();
+
+        assert_eq!(wheel("css:#wheel-container", 100.0), Ok(true));
+        assert_eq!(container.scroll_top(), 100);
+
+        container.set_attribute("onwheel", "event.preventDefault()").unwrap();
+        assert_eq!(wheel("css:#wheel-container", 100.0), Ok(false));
+        assert_eq!(container.scroll_top(), 100);
+
+        cleanup_element(container);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_tap_dispatches_touch_sequence_and_click() {
+        let (_window, document) = get_window_document().unwrap();
+        let button = setup_element(&document, "tap-target", "button", Some(vec![
+            ("style", "width: 80px; height: 40px;"),
+            ("onpointerdown", "this.dataset.events = (this.dataset.events || '') + event.pointerType + '-down,'"),
+            ("onclick", "this.dataset.events = (this.dataset.events || '') + 'click,'"),
+        ]));
+
+        assert!(tap("css:#tap-target").is_ok());
+        assert_eq!(button.get_attribute("data-events"), Some("touch-down,click,".to_string()));
+
+        // A canceled touchstart suppresses the click, as in a real browser.
+        button.set_attribute("data-events", "").unwrap();
+        button.set_attribute("ontouchstart", "event.preventDefault()").unwrap();
+        let touch_supported = tap("css:#tap-target").unwrap();
+        let expected = if touch_supported { "touch-down," } else { "touch-down,click," };
+        assert_eq!(button.get_attribute("data-events"), Some(expected.to_string()));
+
+        cleanup_element(button);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_swipe_and_long_press() {
+        let (_window, document) = get_window_document().unwrap();
+        let panel = setup_element(&document, "swipe-target", "div", Some(vec![
+            ("style", "width: 100px; height: 100px;"),
+            ("onpointerdown", "this.dataset.startX = event.clientX"),
+            ("onpointerup", "this.dataset.endX = event.clientX"),
+            ("onclick", "this.dataset.clicked = 'yes'"),
+        ]));
+
+        assert!(swipe("css:#swipe-target", "left", Some(60.0)).await.is_ok());
+        let start_x: i32 = panel.get_attribute("data-start-x").unwrap().parse().unwrap();
+        let end_x: i32 = panel.get_attribute("data-end-x").unwrap().parse().unwrap();
+        assert_eq!(start_x - end_x, 60);
+        assert!(swipe("css:#swipe-target", "sideways", None).await.is_err());
+
+        assert!(long_press("css:#swipe-target", Some(50)).await.is_ok());
+        assert_eq!(panel.get_attribute("data-clicked"), None, "Gestures other than tap should not click");
+        assert!(matches!(long_press("css:#missing-long-press", Some(10)).await, Err(DomError::ElementNotFound { .. })));
+
+        cleanup_element(panel);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_type_like_human_sends_incremental_input() {
+        let (_window, document) = get_window_document().unwrap();
+        let input = setup_element(&document, "human-typing", "input", Some(vec![
+            ("value", "old"),
+            ("oninput", "this.dataset.inputs = (this.dataset.inputs || '') + '|' + this.value"),
+            ("onkeydown", "if (event.key === 'x') event.preventDefault()"),
+            ("onchange", "this.dataset.changed = 'yes'"),
+        ]));
+
+        type_like_human("css:#human-typing", "abxc", 5).await.unwrap();
+        let input_element = input.dyn_ref::<HtmlInputElement>().unwrap();
+        assert_eq!(input_element.value(), "abc", "Cancelled keystrokes should not be inserted");
+        assert_eq!(input.get_attribute("data-inputs").unwrap(), "||a|ab|abc", "Expected one input event per character after clearing");
+        assert_eq!(input.get_attribute("data-changed").as_deref(), Some("yes"));
+        assert!(matches!(type_like_human("css:#missing-human-typing", "a", 0).await, Err(DomError::ElementNotFound { .. })));
+
+        cleanup_element(input);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_type_like_human_keeps_emoji_and_rtl_text_whole() {
+        let (_window, document) = get_window_document().unwrap();
+        let input = setup_element(&document, "human-typing-unicode", "input", Some(vec![
+            ("oninput", "this.dataset.inputs = (this.dataset.inputs || '') + '|' + event.data"),
+        ]));
+
+        let text = "Zoë 👩‍👩‍👧 🇯🇵 שָׁלוֹם";
+        type_like_human("css:#human-typing-unicode", text, 0).await.unwrap();
+        let input_element = input.dyn_ref::<HtmlInputElement>().unwrap();
+        assert_eq!(input_element.value(), text);
+        let inputs = input.get_attribute("data-inputs").unwrap();
+        assert!(inputs.starts_with("|Z|o|ë| |👩‍👩‍👧| |🇯🇵| |שָׁ|"), "Expected one input event per grapheme cluster, got {}", inputs);
+
+        cleanup_element(input);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_element_cache_invalidated_by_mutations() {
+        let (_window, document) = get_window_document().unwrap();
+        let row = setup_element(&document, "cached-row", "div", Some(vec![("class", "row selected")]));
+        let scope = ElementCacheScope::start().unwrap();
+        let nested_scope = ElementCacheScope::start().unwrap();
+        assert_eq!(get_element(&document, "css:.row.selected").unwrap(), row);
+        assert_eq!(cached_element(&document, "css:.row.selected"), Some(row.clone()));
+        drop(nested_scope);
+        assert_eq!(cached_element(&document, "css:.row.selected"), Some(row.clone()), "Only the first scope owns the cache");
+
+        // A synchronous change is noticed before the observer callback runs.
+        row.set_attribute("class", "row").unwrap();
+        assert_eq!(cached_element(&document, "css:.row.selected"), None);
+        assert!(matches!(get_element(&document, "css:.row.selected"), Err(DomError::ElementNotFound { .. })));
+
+        assert_eq!(get_element(&document, "css:#cached-row").unwrap(), row);
+        row.set_text_content(Some("Updated"));
+        TimeoutFuture::new(0).await; // Lets the observer callback empty the cache
+        assert_eq!(cached_element(&document, "css:#cached-row"), None);
+
+        drop(scope);
+        get_element(&document, "css:#cached-row").unwrap();
+        assert_eq!(cached_element(&document, "css:#cached-row"), None, "Nothing is cached without a scope");
+        cleanup_element(row);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_element_handle_reresolves_detached_element() {
+        let (_window, document) = get_window_document().unwrap();
+        let original = setup_element(&document, "stale-target", "button", None);
+        let mut handle = ElementHandle::resolve(&document, "css:#stale-target").unwrap();
+        assert_eq!(handle.element().unwrap(), &original);
+
+        // A framework re-render replaces the element with an equivalent one.
+        cleanup_element(original);
+        let replacement = setup_element(&document, "stale-target", "button", None);
+        assert_eq!(handle.element().unwrap(), &replacement, "The detached element should be resolved again");
+
+        set_reresolve_stale_elements(false);
+        cleanup_element(replacement);
+        let second_replacement = setup_element(&document, "stale-target", "button", None);
+        let result = handle.element().map(|element| element.clone());
+        set_reresolve_stale_elements(true);
+        assert_eq!(result, Err(DomError::StaleElement { selector: "css:#stale-target".to_string() }));
+
+        cleanup_element(second_replacement);
+        assert!(matches!(handle.element(), Err(DomError::StaleElement { .. })), "Nothing left to resolve");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_text_selector_matches_folded_and_translated_text() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let banner = setup_element(&document, "text-banner", "div", None);
+        banner.set_inner_html("<p>Wir verwenden Cookies</p><span><button id='text-accept'>ALLE  Akzeptieren</button></span><button id='text-close' aria-label='Schließen'>×</button>");
+
+        let accept = document.get_element_by_id("text-accept").unwrap();
+        assert_eq!(get_element(&document, "text:alle akzeptieren").unwrap(), accept, "Exact matches ignore case and spacing");
+        assert_eq!(get_element(&document, "text:accept").unwrap(), accept, "Translations of English terms match");
+        assert_eq!(get_element(&document, "text:close").unwrap().id(), "text-close");
+        assert_eq!(get_element(&document, "text:cookies").unwrap().tag_name(), "P", "The innermost element containing the text");
+        assert!(matches!(get_element(&document, "text:Newsletter"), Err(DomError::ElementNotFound { .. })));
+        cleanup_element(banner);
+    }
+
+    #[test]
+    fn test_suggestion_pick() {
+        assert_eq!(SuggestionPick::parse("#2"), Some(SuggestionPick::Position(2)));
+        assert_eq!(SuggestionPick::parse("#0"), None);
+        assert_eq!(SuggestionPick::parse(" Amsterdam "), Some(SuggestionPick::Text("Amsterdam".to_string())));
+        assert_eq!(SuggestionPick::parse(""), None);
+
+        let texts: Vec<String> = ["Amsterdam Zuid", " Amsterdam ", "Rotterdam"].iter().map(|t| t.to_string()).collect();
+        assert_eq!(SuggestionPick::Text("amsterdam".to_string()).find(&texts), Some(1), "Exact matches come first");
+        assert_eq!(SuggestionPick::Text("zuid".to_string()).find(&texts), Some(0));
+        assert_eq!(SuggestionPick::Text("Utrecht".to_string()).find(&texts), None);
+        assert_eq!(SuggestionPick::Position(3).find(&texts), Some(2));
+        assert_eq!(SuggestionPick::Position(4).find(&texts), None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_type_and_select_picks_suggestion() {
+        let (_window, document) = get_window_document().unwrap();
+        let list = setup_element(&document, "city-suggestions", "ul", None);
+        let input = setup_element(&document, "city-input", "input", Some(vec![
+            // Suggestions appear asynchronously once at least three characters are typed.
+            ("oninput", "const list = document.getElementById('city-suggestions'); \
+                if (this.value.length >= 3) setTimeout(() => { list.innerHTML = '<li>Amsterdam</li><li>Amstelveen</li>'; }, 50);"),
+        ]));
+        list.set_attribute("onmousedown", "document.getElementById('city-input').value = event.target.textContent").unwrap();
+
+        let picked = type_and_select("css:#city-input", "Ams", "css:#city-suggestions>li", &SuggestionPick::Text("amstelveen".to_string()), 0, None, &WaitConfig::default()).await.unwrap();
+        assert_eq!(picked, "Amstelveen");
+        assert_eq!(input.dyn_ref::<HtmlInputElement>().unwrap().value(), "Amstelveen");
+
+        let result = type_and_select("css:#city-input", "Ams", "css:#city-suggestions>li", &SuggestionPick::Position(5), 0, Some(200), &WaitConfig::default()).await;
+        assert!(matches!(result, Err(DomError::ElementNotFound { .. })), "Unexpected result: {:?}", result);
+
+        cleanup_element(input);
+        cleanup_element(list);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_search_and_open_clicks_best_match() {
+        let (_window, document) = get_window_document().unwrap();
+        let results = setup_element(&document, "book-results", "div", None);
+        results.set_attribute("onclick", "event.preventDefault(); this.dataset.opened = event.target.textContent").unwrap();
+        let form = setup_element(&document, "book-search", "form", Some(vec![
+            // Results are rendered on submit, as single-page apps do.
+            ("onsubmit", "event.preventDefault(); setTimeout(() => { document.getElementById('book-results').innerHTML = \
+                '<a href=\"#1\">Rust cookbook</a><a href=\"#2\">The Rust Programming Language</a><a href=\"#3\">Gardening</a>'; }, 50);"),
+        ]));
+        let input = document.create_element("input").unwrap();
+        input.set_id("book-query");
+        form.append_child(&input).unwrap();
+
+        let (opened, score) = search_and_open("css:#book-query", "rust", "css:#book-results a", "rust programming language", 0, None, &WaitConfig::default()).await.unwrap();
+        assert_eq!(opened, "The Rust Programming Language");
+        assert!(score >= MIN_SEARCH_SCORE);
+        assert_eq!(results.get_attribute("data-opened").as_deref(), Some("The Rust Programming Language"));
+
+        let result = search_and_open("css:#book-query", "rust", "css:#book-results a", "cooking for beginners", 0, Some(200), &WaitConfig::default()).await;
+        assert!(matches!(result, Err(DomError::ElementNotFound { .. })), "Unexpected result: {:?}", result);
+
+        cleanup_element(form);
+        cleanup_element(results);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_range_input_and_aria_slider() {
+        let (_window, document) = get_window_document().unwrap();
+        let range = setup_element(&document, "volume-range", "input", Some(vec![
+            ("type", "range"), ("min", "0"), ("max", "100"), ("step", "5"),
+            ("onchange", "this.dataset.changed = this.value"),
+        ]));
+        assert_eq!(set_range("css:#volume-range", 42.0).unwrap(), "40", "The browser snaps the value to the step");
+        assert_eq!(range.get_attribute("data-changed").as_deref(), Some("40"));
+        assert_eq!(set_range("css:#volume-range", 500.0).unwrap(), "100");
+
+        // A slider that moves by 10 on arrow keys, between 0 and 50.
+        let slider = setup_element(&document, "volume-slider", "div", Some(vec![
+            ("role", "slider"), ("tabindex", "0"), ("aria-valuenow", "20"),
+            ("onkeydown", "const step = { ArrowRight: 10, ArrowLeft: -10 }[event.key] || 0; \
+                this.setAttribute('aria-valuenow', Math.min(50, Math.max(0, Number(this.getAttribute('aria-valuenow')) + step)))"),
+        ]));
+        assert_eq!(set_range("css:#volume-slider", 40.0).unwrap(), "40");
+        assert_eq!(set_range("css:#volume-slider", 12.0).unwrap(), "10", "Stops at the closest reachable value");
+        assert_eq!(set_range("css:#volume-slider", 90.0).unwrap(), "50", "Stops when the slider stops moving");
+
+        let div = setup_element(&document, "not-a-slider", "div", None);
+        assert!(matches!(set_range("css:#not-a-slider", 1.0), Err(DomError::ElementTypeError { .. })));
+
+        cleanup_element(range);
+        cleanup_element(slider);
+        cleanup_element(div);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_rich_text_type_in_contenteditable() {
+        let (_window, document) = get_window_document().unwrap();
+        let editor = setup_element(&document, "rich-editor", "div", Some(vec![
+            ("contenteditable", "true"),
+            ("oninput", "this.dataset.inputs = (Number(this.dataset.inputs) || 0) + 1"),
+        ]));
+        editor.set_inner_html("<p>Hi </p>");
+        // Browsers may turn the space before the caret into a non-breaking one.
+        assert_eq!(rich_text_type("css:#rich-editor", "there").unwrap().replace('\u{a0}', " "), "Hi there");
+        assert!(editor.get_attribute("data-inputs").is_some(), "The editor should be notified through an input event");
+
+        // Quill-like container holding the editable root.
+        let container = setup_element(&document, "quill-container", "div", None);
+        container.set_inner_html("<div class='toolbar'></div><div class='ql-editor' contenteditable='true'></div>");
+        assert_eq!(rich_text_type("css:#quill-container", "Hello").unwrap(), "Hello");
+
+        let plain = setup_element(&document, "plain-div", "div", None);
+        assert!(matches!(rich_text_type("css:#plain-div", "x"), Err(DomError::ElementTypeError { .. })));
+
+        cleanup_element(editor);
+        cleanup_element(container);
+        cleanup_element(plain);
+    }
 }
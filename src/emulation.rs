@@ -0,0 +1,232 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::console;
+use std::cell::{Cell, RefCell};
+use crate::dom_backend;
+use crate::dom_utils::DomError;
+
+/// Accuracy, in meters, reported by `SET_GEOLOCATION` when none is given.
+pub const DEFAULT_GEOLOCATION_ACCURACY_M: f64 = 10.0;
+
+type GeolocationShim = Closure<dyn FnMut(JsValue, JsValue, JsValue) -> i32>;
+
+// The functions installed on `navigator.geolocation`, kept alive once installed.
+struct GeolocationShims {
+    _get_current_position: GeolocationShim,
+    _watch_position: GeolocationShim,
+    _clear_watch: Closure<dyn FnMut(JsValue)>,
+}
+type ConstructTrap = Closure<dyn FnMut(JsValue, js_sys::Array, JsValue) -> Result<JsValue, JsValue>>;
+type ApplyTrap = Closure<dyn FnMut(JsValue, JsValue, js_sys::Array) -> Result<JsValue, JsValue>>;
+
+thread_local! {
+    // Position reported by the geolocation shims: latitude, longitude and accuracy.
+    static POSITION: Cell<Option<(f64, f64, f64)>> = const { Cell::new(None) };
+    static GEOLOCATION_SHIMS: RefCell<Option<GeolocationShims>> = const { RefCell::new(None) };
+    static NEXT_WATCH_ID: Cell<i32> = const { Cell::new(1) };
+    // Time zone `Intl.DateTimeFormat` defaults to, or `None` to use the browser's.
+    static TIMEZONE: RefCell<Option<String>> = const { RefCell::new(None) };
+    // `construct` and `apply` traps of the `Intl.DateTimeFormat` proxy, kept alive once installed.
+    static TIMEZONE_TRAPS: RefCell<Option<(ConstructTrap, ApplyTrap)>> = const { RefCell::new(None) };
+}
+
+// Builds a `GeolocationPosition`-like object for the overridden position.
+fn position_object(latitude: f64, longitude: f64, accuracy: f64) -> Result<JsValue, JsValue> {
+    let coords = js_sys::Object::new();
+    for (name, value) in [("latitude", latitude), ("longitude", longitude), ("accuracy", accuracy)] {
+        js_sys::Reflect::set(&coords, &JsValue::from_str(name), &JsValue::from_f64(value))?;
+    }
+    for name in ["altitude", "altitudeAccuracy", "heading", "speed"] {
+        js_sys::Reflect::set(&coords, &JsValue::from_str(name), &JsValue::NULL)?;
+    }
+    let position = js_sys::Object::new();
+    js_sys::Reflect::set(&position, &JsValue::from_str("coords"), &coords)?;
+    js_sys::Reflect::set(&position, &JsValue::from_str("timestamp"), &JsValue::from_f64(js_sys::Date::now()))?;
+    Ok(position.into())
+}
+
+// Calls a geolocation success callback with the overridden position, asynchronously like the
+// real API does.
+fn report_position(success: JsValue) {
+    let Some(success) = success.dyn_into::<js_sys::Function>().ok() else { return };
+    wasm_bindgen_futures::spawn_local(async move {
+        let Some((latitude, longitude, accuracy)) = POSITION.with(Cell::get) else { return };
+        let result = position_object(latitude, longitude, accuracy).and_then(|position| success.call1(&JsValue::NULL, &position));
+        if let Err(e) = result {
+            console::warn_1(&format!("Geolocation callback threw: {:?}", e.as_string()).into());
+        }
+    });
+}
+
+/// Makes `navigator.geolocation` report the given position to the page.
+///
+/// `getCurrentPosition` and `watchPosition` are replaced on the page's `Geolocation` object the
+/// first time, and report the latest position set; no permission prompt is shown. A watch only
+/// reports the position once, when it starts.
+///
+/// # Arguments
+/// * `latitude`, `longitude`: The position, in decimal degrees.
+/// * `accuracy`: The accuracy reported, in meters.
+pub fn set_geolocation(latitude: f64, longitude: f64, accuracy: f64) -> Result<(), DomError> {
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(DomError::JsError { message: format!("Invalid coordinates: {}, {}", latitude, longitude) });
+    }
+    POSITION.with(|position| position.set(Some((latitude, longitude, accuracy))));
+    if GEOLOCATION_SHIMS.with(|shims| shims.borrow().is_some()) {
+        return Ok(());
+    }
+
+    let window = dom_backend::window().ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?;
+    let navigator = js_sys::Reflect::get(&window, &JsValue::from_str("navigator"))?;
+    let geolocation = js_sys::Reflect::get(&navigator, &JsValue::from_str("geolocation"))?;
+    if geolocation.is_undefined() || geolocation.is_null() {
+        return Err(DomError::JsError { message: "navigator.geolocation is not available on this page".to_string() });
+    }
+    let get_current_position = Closure::wrap(Box::new(|success: JsValue, _error: JsValue, _options: JsValue| {
+        report_position(success);
+        0
+    }) as Box<dyn FnMut(JsValue, JsValue, JsValue) -> i32>);
+    let watch_position = Closure::wrap(Box::new(|success: JsValue, _error: JsValue, _options: JsValue| {
+        report_position(success);
+        NEXT_WATCH_ID.with(|next| next.replace(next.get() + 1))
+    }) as Box<dyn FnMut(JsValue, JsValue, JsValue) -> i32>);
+    let clear_watch = Closure::wrap(Box::new(|_watch_id: JsValue| {}) as Box<dyn FnMut(JsValue)>);
+
+    for (name, shim) in [
+        ("getCurrentPosition", get_current_position.as_ref()),
+        ("watchPosition", watch_position.as_ref()),
+        ("clearWatch", clear_watch.as_ref()),
+    ] {
+        if !js_sys::Reflect::set(&geolocation, &JsValue::from_str(name), shim)? {
+            return Err(DomError::JsError { message: format!("navigator.geolocation.{} cannot be overridden", name) });
+        }
+    }
+    GEOLOCATION_SHIMS.with(|shims| *shims.borrow_mut() = Some(GeolocationShims {
+        _get_current_position: get_current_position,
+        _watch_position: watch_position,
+        _clear_watch: clear_watch,
+    }));
+    console::log_1(&format!("Geolocation overridden to {}, {}", latitude, longitude).into());
+    Ok(())
+}
+
+// Returns `arguments` with the overridden time zone added to the options (the second argument)
+// of an `Intl.DateTimeFormat` call, unless the caller chose a time zone.
+fn with_timezone(arguments: &js_sys::Array) -> Result<js_sys::Array, JsValue> {
+    let Some(timezone) = TIMEZONE.with(|timezone| timezone.borrow().clone()) else { return Ok(arguments.clone()) };
+    let options = arguments.get(1);
+    let has_timezone = !options.is_undefined()
+        && !options.is_null()
+        && !js_sys::Reflect::get(&options, &JsValue::from_str("timeZone"))?.is_undefined();
+    if has_timezone {
+        return Ok(arguments.clone());
+    }
+    let overridden = js_sys::Object::new();
+    if options.is_object() {
+        js_sys::Object::assign(&overridden, options.unchecked_ref());
+    }
+    js_sys::Reflect::set(&overridden, &JsValue::from_str("timeZone"), &JsValue::from_str(&timezone))?;
+    Ok(js_sys::Array::of2(&arguments.get(0), &overridden))
+}
+
+/// Makes `Intl.DateTimeFormat` default to `timezone`, so that
+/// `Intl.DateTimeFormat().resolvedOptions().timeZone` and the dates formatted with it follow the
+/// override. An empty string restores the browser's time zone.
+///
+/// `Intl.DateTimeFormat` is replaced by a proxy the first time. `Date` methods such as
+/// `getTimezoneOffset` and `toString` cannot be patched reliably and keep the browser's zone.
+///
+/// # Arguments
+/// * `timezone`: An IANA time zone name, e.g. `Europe/Paris`, or `""`.
+pub fn set_timezone(timezone: &str) -> Result<(), DomError> {
+    let intl = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("Intl"))?;
+    let date_time_format = js_sys::Reflect::get(&intl, &JsValue::from_str("DateTimeFormat"))?
+        .dyn_into::<js_sys::Function>()
+        .map_err(|_| DomError::JsError { message: "Intl.DateTimeFormat is not available on this page".to_string() })?;
+
+    if !timezone.is_empty() {
+        // Invalid zones make the constructor throw a RangeError.
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &JsValue::from_str("timeZone"), &JsValue::from_str(timezone))?;
+        js_sys::Reflect::construct(&date_time_format, &js_sys::Array::of2(&JsValue::UNDEFINED, &options))
+            .map_err(|_| DomError::JsError { message: format!("Unknown time zone '{}'", timezone) })?;
+    }
+    TIMEZONE.with(|current| *current.borrow_mut() = Some(timezone.to_string()).filter(|timezone| !timezone.is_empty()));
+    if TIMEZONE_TRAPS.with(|traps| traps.borrow().is_some()) {
+        return Ok(());
+    }
+
+    // `new Intl.DateTimeFormat(...)` and `Intl.DateTimeFormat(...)` are both valid.
+    let construct = Closure::wrap(Box::new(|target: JsValue, arguments: js_sys::Array, new_target: JsValue| {
+        js_sys::Reflect::construct_with_new_target(target.unchecked_ref(), &with_timezone(&arguments)?, new_target.unchecked_ref())
+    }) as Box<dyn FnMut(JsValue, js_sys::Array, JsValue) -> Result<JsValue, JsValue>>);
+    let apply = Closure::wrap(Box::new(|target: JsValue, this: JsValue, arguments: js_sys::Array| {
+        js_sys::Reflect::apply(target.unchecked_ref(), &this, &with_timezone(&arguments)?)
+    }) as Box<dyn FnMut(JsValue, JsValue, js_sys::Array) -> Result<JsValue, JsValue>>);
+    let handler = js_sys::Object::new();
+    js_sys::Reflect::set(&handler, &JsValue::from_str("construct"), construct.as_ref())?;
+    js_sys::Reflect::set(&handler, &JsValue::from_str("apply"), apply.as_ref())?;
+    let proxy = js_sys::Proxy::new(&date_time_format, &handler);
+    if !js_sys::Reflect::set(&intl, &JsValue::from_str("DateTimeFormat"), &proxy)? {
+        return Err(DomError::JsError { message: "Intl.DateTimeFormat cannot be overridden".to_string() });
+    }
+
+    TIMEZONE_TRAPS.with(|traps| *traps.borrow_mut() = Some((construct, apply)));
+    console::log_1(&format!("Intl.DateTimeFormat time zone overridden to '{}'", timezone).into());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gloo_timers::future::TimeoutFuture;
+    use std::rc::Rc;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    async fn test_set_geolocation_overrides_current_position() {
+        set_geolocation(48.8584, 2.2945, 25.0).unwrap();
+        assert!(set_geolocation(91.0, 0.0, 10.0).is_err(), "Latitude out of range");
+
+        let reported: Rc<RefCell<Option<JsValue>>> = Rc::default();
+        let reported_clone = reported.clone();
+        let success = Closure::wrap(Box::new(move |position: JsValue| {
+            *reported_clone.borrow_mut() = Some(position);
+        }) as Box<dyn FnMut(JsValue)>);
+        let navigator = js_sys::Reflect::get(&web_sys::window().unwrap(), &"navigator".into()).unwrap();
+        let geolocation = js_sys::Reflect::get(&navigator, &"geolocation".into()).unwrap();
+        let get_current_position: js_sys::Function =
+            js_sys::Reflect::get(&geolocation, &"getCurrentPosition".into()).unwrap().unchecked_into();
+        get_current_position.call1(&geolocation, success.as_ref()).unwrap();
+        TimeoutFuture::new(20).await;
+
+        let position = reported.borrow().clone().expect("The success callback was not called");
+        let coords = js_sys::Reflect::get(&position, &"coords".into()).unwrap();
+        assert_eq!(js_sys::Reflect::get(&coords, &"latitude".into()).unwrap().as_f64(), Some(48.8584));
+        assert_eq!(js_sys::Reflect::get(&coords, &"accuracy".into()).unwrap().as_f64(), Some(25.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_set_timezone_overrides_intl_default() {
+        // Reads `Intl.DateTimeFormat().resolvedOptions().timeZone` through the (possibly proxied) global.
+        fn resolved_timezone(options: &JsValue) -> String {
+            let intl = js_sys::Reflect::get(&js_sys::global(), &"Intl".into()).unwrap();
+            let constructor: js_sys::Function = js_sys::Reflect::get(&intl, &"DateTimeFormat".into()).unwrap().unchecked_into();
+            let format = js_sys::Reflect::construct(&constructor, &js_sys::Array::of2(&"en-US".into(), options)).unwrap();
+            let resolved: js_sys::Function = js_sys::Reflect::get(&format, &"resolvedOptions".into()).unwrap().unchecked_into();
+            let resolved = resolved.call0(&format).unwrap();
+            js_sys::Reflect::get(&resolved, &"timeZone".into()).unwrap().as_string().unwrap()
+        }
+
+        set_timezone("Asia/Tokyo").unwrap();
+        assert_eq!(resolved_timezone(&JsValue::UNDEFINED), "Asia/Tokyo");
+        let explicit = js_sys::JSON::parse(r#"{"timeZone":"UTC"}"#).unwrap();
+        assert_eq!(resolved_timezone(&explicit), "UTC", "An explicit time zone wins");
+        assert!(set_timezone("Mars/Olympus_Mons").is_err());
+
+        set_timezone("").unwrap();
+        assert_ne!(resolved_timezone(&JsValue::UNDEFINED), "", "The browser's time zone is back");
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use web_sys::console;
+use crate::dom_backend;
+
+/// Name of the `CustomEvent` dispatched on `window` when a task is planned again with a stronger
+/// model; its `detail` is the `Escalation`.
+pub const ESCALATION_EVENT: &str = "llm_escalation";
+
+/// When the planning step of an LLM task is retried with a stronger model, set with
+/// `RustAgent.set_model_escalation`, e.g. `{"profile": "strong", "after_failures": 2}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EscalationPolicy {
+    /// The LLM profile of the stronger model (see `llm_profiles`).
+    pub profile: String,
+    /// How many plans of the model in use must fail before the task is planned with the stronger
+    /// one; until then, the model in use plans again. 1 by default.
+    #[serde(default = "default_after_failures")]
+    pub after_failures: u32,
+}
+
+fn default_after_failures() -> u32 {
+    1
+}
+
+/// A task that was planned again with a stronger model.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Escalation {
+    pub task: String,
+    pub from_model: String,
+    pub to_model: String,
+    pub profile: String,
+    /// Why each plan of the model in use failed, in order.
+    pub failures: Vec<String>,
+    /// Whether the stronger model's plan succeeded.
+    pub success: bool,
+}
+
+/// The escalation policy of an agent and the escalations of the current run. Like the usage of
+/// `RunGuard`, the escalations are not shared by clones.
+#[derive(Debug, Clone, Default)]
+pub struct ModelEscalation {
+    pub policy: Option<EscalationPolicy>,
+    escalations: RefCell<Vec<Escalation>>,
+}
+
+impl ModelEscalation {
+    /// Marks the start of a run: nothing was escalated yet.
+    pub fn begin_run(&self) {
+        self.escalations.borrow_mut().clear();
+    }
+
+    /// Records an escalation of the current run.
+    pub fn record(&self, escalation: Escalation) {
+        self.escalations.borrow_mut().push(escalation);
+    }
+
+    /// The escalations of the current run, in order.
+    pub fn escalations(&self) -> Vec<Escalation> {
+        self.escalations.borrow().clone()
+    }
+}
+
+/// Dispatches `ESCALATION_EVENT` on `window`. Nothing is dispatched where there is no window (in a
+/// worker); failures are only logged.
+pub fn dispatch(escalation: &Escalation) {
+    let Some(window) = dom_backend::window() else { return };
+    let Ok(detail) = serde_json::to_string(escalation) else { return };
+    let dispatched = js_sys::JSON::parse(&detail).and_then(|detail| {
+        let event_init = web_sys::CustomEventInit::new();
+        event_init.set_detail(&detail);
+        let event = web_sys::CustomEvent::new_with_event_init_dict(ESCALATION_EVENT, &event_init)?;
+        window.dispatch_event(&event)
+    });
+    if let Err(e) = dispatched {
+        console::warn_1(&format!("Could not dispatch the {} event: {:?}", ESCALATION_EVENT, e.as_string()).into());
+    }
+}
+
+/// The part of the prompt telling the LLM why the earlier plans for the task failed, so that the
+/// next plan avoids the same mistakes. Empty for the first plan.
+pub fn failure_section(failures: &[String]) -> String {
+    if failures.is_empty() {
+        return String::new();
+    }
+    let listed: Vec<String> = failures.iter().map(|failure| format!("- {}", failure)).collect();
+    format!(
+        "Earlier plans for this task failed:\n{}\nPropose commands that avoid these failures, e.g. other selectors for elements that were not found.\n\n",
+        listed.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_defaults() {
+        let policy: EscalationPolicy = serde_json::from_str(r#"{"profile": "strong"}"#).unwrap();
+        assert_eq!(policy, EscalationPolicy { profile: "strong".to_string(), after_failures: 1 });
+        assert!(serde_json::from_str::<EscalationPolicy>(r#"{"after_failures": 2}"#).is_err());
+    }
+
+    #[test]
+    fn test_failure_section() {
+        assert_eq!(failure_section(&[]), "");
+        let section = failure_section(&["Selector 'css:#buy' matched nothing".to_string(), "Malformed JSON".to_string()]);
+        assert!(section.starts_with("Earlier plans for this task failed:\n- Selector 'css:#buy' matched nothing\n- Malformed JSON\n"), "{}", section);
+    }
+
+    #[test]
+    fn test_escalations_are_per_run() {
+        let escalation = ModelEscalation::default();
+        escalation.record(Escalation {
+            task: "Buy it".to_string(),
+            from_model: "small".to_string(),
+            to_model: "large".to_string(),
+            profile: "strong".to_string(),
+            failures: vec!["Malformed JSON".to_string()],
+            success: true,
+        });
+        let scheduled = escalation.clone();
+        escalation.begin_run();
+        assert!(escalation.escalations().is_empty());
+        assert_eq!(scheduled.escalations().len(), 1, "Clones keep their own escalations");
+    }
+}
@@ -0,0 +1,576 @@
+use crate::agent::{self, DomCommand, DomCommandAction};
+use crate::conditions;
+use crate::dom_utils;
+use crate::try_catch::{self, BlockMarker};
+
+/// The placeholder of task lists standing for the output of the previous task.
+const PREVIOUS_RESULT_PLACEHOLDER: &str = "{{PREVIOUS_RESULT}}";
+
+/// The test frameworks runs and workflows can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptFormat {
+    /// A Playwright Test spec, in TypeScript.
+    Playwright,
+    /// A pytest test driving Selenium WebDriver, in Python.
+    Selenium,
+}
+
+impl ScriptFormat {
+    /// Parses a format name, `playwright` or `selenium`, case-insensitively.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "playwright" => Some(ScriptFormat::Playwright),
+            "selenium" => Some(ScriptFormat::Selenium),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ScriptFormat::Playwright => "Playwright",
+            ScriptFormat::Selenium => "Selenium",
+        }
+    }
+
+    fn indent(self) -> &'static str {
+        match self {
+            ScriptFormat::Playwright => "  ",
+            ScriptFormat::Selenium => "    ",
+        }
+    }
+
+    fn comment(self, text: &str) -> String {
+        // Task text may span lines; a comment may not.
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        match self {
+            ScriptFormat::Playwright => format!("// {}", text),
+            ScriptFormat::Selenium => format!("# {}", text),
+        }
+    }
+
+    // The variable holding the value read by the latest step that reads one.
+    fn result_variable(self) -> &'static str {
+        match self {
+            ScriptFormat::Playwright => "previousResult",
+            ScriptFormat::Selenium => "previous_result",
+        }
+    }
+
+    // The text of the error handled by a CATCH part.
+    fn error_text(self) -> &'static str {
+        match self {
+            ScriptFormat::Playwright => "String(error)",
+            ScriptFormat::Selenium => "str(error)",
+        }
+    }
+}
+
+/// Converts a task list, such as the tasks of a recorded run or the rendered steps of a workflow,
+/// into an equivalent script for `format`, so that a prototype automated in the browser can join
+/// an existing test suite.
+///
+/// Direct DOM commands become the framework's calls, selector fallback chains and scopes included;
+/// `TRY` / `CATCH` blocks become `try` / `catch` blocks. `{{PREVIOUS_RESULT}}` stands for the value
+/// read by the latest step that reads one (e.g. the text of a `READ`), rather than for the whole
+/// result message, and `{{error}}` for the error being handled. Tasks planned by the LLM, other
+/// flow steps (`LABEL` / `GOTO`, `WHILE`) and commands without an equivalent are left as `TODO`
+/// comments. Unlike a run, the script stops at the first failure outside a `TRY` part.
+///
+/// # Arguments
+/// * `title`: The name of the generated test, e.g. `workflow:checkout`.
+/// * `tasks`: The tasks, before `{{PREVIOUS_RESULT}}` substitution.
+/// * `format`: The framework to export to.
+pub fn export_script(title: &str, tasks: &[String], format: ScriptFormat) -> String {
+    let mut script = Script { format, lines: Vec::new(), depth: 1, blocks: Vec::new(), block_empty: false, reads_result: false };
+    for task in tasks {
+        script.push_task(task);
+    }
+    script.finish(title)
+}
+
+// A script being generated, task by task.
+struct Script {
+    format: ScriptFormat,
+    // The lines of the test body, indented.
+    lines: Vec<String>,
+    // The indentation level of the next line.
+    depth: usize,
+    // The open TRY blocks: whether each has reached its CATCH.
+    blocks: Vec<bool>,
+    // Whether the innermost block was opened and has no line yet (Python requires one).
+    block_empty: bool,
+    // Whether a step stores a value in the result variable.
+    reads_result: bool,
+}
+
+impl Script {
+    fn push_line(&mut self, line: &str) {
+        self.lines.push(format!("{}{}", self.format.indent().repeat(self.depth), line));
+        self.block_empty = false;
+    }
+
+    // Pushes statements, which may span lines indented relative to their first line.
+    fn push_statements(&mut self, statements: &str) {
+        for line in statements.lines() {
+            self.push_line(line);
+        }
+    }
+
+    fn open_block(&mut self, line: &str) {
+        self.push_line(line);
+        self.depth += 1;
+        self.block_empty = true;
+    }
+
+    fn close_block(&mut self) {
+        if self.block_empty && self.format == ScriptFormat::Selenium {
+            self.push_line("pass");
+        }
+        self.depth -= 1;
+    }
+
+    fn push_task(&mut self, task: &str) {
+        if let Some(marker) = try_catch::marker(task) {
+            self.push_marker(marker, task);
+            return;
+        }
+        if crate::goto::parse_step(task).is_some() || crate::while_loop::parse_step(task).is_some() {
+            let comment = self.format.comment(&format!("TODO: flow step not exported: {}", task));
+            self.push_line(&comment);
+            return;
+        }
+        // The command is exported without its conditions.
+        let (task, conditions) = conditions::split(task).unwrap_or((task, Vec::new()));
+        for condition in &conditions {
+            let comment = self.format.comment(&format!("TODO: condition not exported: [{}]", condition));
+            self.push_line(&comment);
+        }
+        let statements = match agent::parse_dom_command(task) {
+            Some(dom_command) => match self.command_statements(&dom_command) {
+                Some(statements) => statements,
+                None => self.format.comment(&format!("TODO: {} has no {} equivalent: {}", action_name(task), self.format.name(), task)),
+            },
+            None => self.format.comment(&format!("TODO: planned by the LLM in the page; write its steps: {}", task)),
+        };
+        self.push_statements(&statements);
+    }
+
+    fn push_marker(&mut self, marker: BlockMarker, task: &str) {
+        let (format, open) = (self.format, self.blocks.last().copied());
+        match (marker, open) {
+            (BlockMarker::Try, _) => {
+                self.blocks.push(false);
+                self.open_block(match format {
+                    ScriptFormat::Playwright => "try {",
+                    ScriptFormat::Selenium => "try:",
+                });
+            }
+            (BlockMarker::Catch, Some(false)) => {
+                self.close_block();
+                *self.blocks.last_mut().expect("a TRY block is open") = true;
+                self.open_block(match format {
+                    ScriptFormat::Playwright => "} catch (error) {",
+                    ScriptFormat::Selenium => "except Exception as error:",
+                });
+            }
+            (BlockMarker::EndTry, Some(caught)) => {
+                self.close_block();
+                self.blocks.pop();
+                match (format, caught) {
+                    (ScriptFormat::Playwright, true) => self.push_line("}"),
+                    // Without a CATCH part, failures of the TRY part are ignored.
+                    (ScriptFormat::Playwright, false) => self.push_line("} catch {}"),
+                    (ScriptFormat::Selenium, true) => {}
+                    (ScriptFormat::Selenium, false) => self.push_statements("except Exception:\n    pass"),
+                }
+            }
+            _ => {
+                let comment = format.comment(&format!("TODO: misplaced block marker not exported: {}", task));
+                self.push_line(&comment);
+            }
+        }
+    }
+
+    // The statements performing `dom_command`, or `None` if the format has no equivalent.
+    fn command_statements(&mut self, dom_command: &DomCommand) -> Option<String> {
+        let statements = match self.format {
+            ScriptFormat::Playwright => self.playwright_statements(dom_command)?,
+            ScriptFormat::Selenium => self.selenium_statements(dom_command)?,
+        };
+        self.reads_result |= statements.contains(&format!("{} =", self.format.result_variable()));
+        Some(statements)
+    }
+
+    // An expression evaluating to `text`, with the task list's placeholders replaced by the
+    // values they stand for.
+    fn literal(&self, text: &str) -> String {
+        let mut parts = Vec::new();
+        for (index, piece) in text.split(PREVIOUS_RESULT_PLACEHOLDER).enumerate() {
+            if index > 0 {
+                parts.push(self.format.result_variable().to_string());
+            }
+            for (index, piece) in piece.split(try_catch::ERROR_PLACEHOLDER).enumerate() {
+                if index > 0 {
+                    parts.push(self.format.error_text().to_string());
+                }
+                if !piece.is_empty() {
+                    parts.push(string_literal(piece));
+                }
+            }
+        }
+        match parts.len() {
+            0 => string_literal(""),
+            _ => parts.join(" + "),
+        }
+    }
+
+    fn playwright_statements(&self, dom_command: &DomCommand) -> Option<String> {
+        let element = format!("{}.first()", self.playwright_locator("page", &dom_command.selector));
+        let elements = self.playwright_locator("page", &dom_command.selector);
+        let value = dom_command.value.as_deref().map(|value| self.literal(value));
+        let attribute_name = dom_command.attribute_name.as_deref().map(|name| self.literal(name));
+        let statements = match dom_command.action {
+            DomCommandAction::Click => format!("await {}.click();", element),
+            DomCommandAction::ClickIfExists => format!("if (await {0}.count() > 0) await {0}.click();", element),
+            DomCommandAction::Type => format!("await {}.fill({});", element, value?),
+            DomCommandAction::TypeIfExists => format!("if (await {0}.count() > 0) await {0}.fill({1});", element, value?),
+            DomCommandAction::Read => format!("previousResult = await {}.innerText();", element),
+            DomCommandAction::GetValue => format!("previousResult = await {}.inputValue();", element),
+            DomCommandAction::GetAttribute => format!("previousResult = (await {}.getAttribute({})) ?? '';", element, attribute_name?),
+            DomCommandAction::SetAttribute => format!(
+                "await {}.evaluate((el, [name, value]) => el.setAttribute(name, value), [{}, {}]);",
+                element, attribute_name?, value?
+            ),
+            DomCommandAction::SelectOption => format!("await {}.selectOption({});", element, value?),
+            DomCommandAction::GetAllAttributes => format!(
+                "previousResult = JSON.stringify(await {}.evaluateAll((els, name) => els.map((el) => el.getAttribute(name)), {}));",
+                elements, attribute_name?
+            ),
+            DomCommandAction::GetUrl => "previousResult = page.url();".to_string(),
+            DomCommandAction::Navigate => match dom_command.value.as_deref()?.contains("://") {
+                true => format!("await page.goto({});", value?),
+                // Relative URLs are resolved against the current page, as in the page.
+                false => format!("await page.goto(new URL({}, page.url()).href);", value?),
+            },
+            DomCommandAction::ElementExists => format!("previousResult = String(await {}.count() > 0);", elements),
+            DomCommandAction::WaitForElement => match dom_command.value.as_deref().and_then(|ms| ms.parse::<u32>().ok()) {
+                Some(timeout_ms) => format!("await {}.waitFor({{ state: 'attached', timeout: {} }});", element, timeout_ms),
+                None => format!("await {}.waitFor({{ state: 'attached' }});", element),
+            },
+            DomCommandAction::IsVisible => format!("previousResult = String(await {}.isVisible());", element),
+            DomCommandAction::ScrollTo => format!("await {}.scrollIntoViewIfNeeded();", element),
+            DomCommandAction::Hover => format!("await {}.hover();", element),
+            DomCommandAction::GetAllText => format!(
+                "previousResult = (await {}.allInnerTexts()).filter((text) => text).join({});",
+                elements,
+                value.unwrap_or_else(|| string_literal("\n"))
+            ),
+            DomCommandAction::EvalJs => format!(
+                "previousResult = JSON.stringify(await page.evaluate({})) ?? 'null';",
+                self.literal(&format!("(() => {{ {} }})()", dom_command.value.as_deref()?))
+            ),
+            DomCommandAction::IsEnabled => format!("previousResult = String(await {}.isEnabled());", element),
+            DomCommandAction::IsSelected => format!("previousResult = String(await {}.isChecked());", element),
+            _ => return None,
+        };
+        Some(statements)
+    }
+
+    // A Playwright locator for `selector` within `base`, resolved like `dom_utils::get_element`:
+    // scoped selectors first, then fallback chains (`or`), then `xpath:`, `text:` and CSS selectors.
+    fn playwright_locator(&self, base: &str, selector: &str) -> String {
+        if let Some((region, inner)) = dom_utils::split_scope(selector) {
+            let region = format!("{}.first()", self.playwright_locator(base, region));
+            return self.playwright_locator(&region, inner);
+        }
+        if let Some(candidates) = dom_utils::selector_candidates(selector) {
+            let mut locators = candidates.iter().map(|candidate| self.playwright_locator(base, candidate));
+            let first = locators.next().unwrap_or_default();
+            return locators.fold(first, |chain, locator| format!("{}.or({})", chain, locator));
+        }
+        if let Some(xpath) = selector.strip_prefix("xpath:") {
+            format!("{}.locator({})", base, self.literal(&format!("xpath={}", xpath)))
+        } else if let Some(text) = selector.strip_prefix("text:") {
+            format!("{}.getByText({})", base, self.literal(text))
+        } else {
+            format!("{}.locator({})", base, self.literal(&format!("css={}", selector.strip_prefix("css:").unwrap_or(selector))))
+        }
+    }
+
+    fn selenium_statements(&self, dom_command: &DomCommand) -> Option<String> {
+        let element = self.selenium_lookup("driver", &dom_command.selector, "find", false);
+        let elements = self.selenium_lookup("driver", &dom_command.selector, "find_all", false);
+        let value = dom_command.value.as_deref().map(|value| self.literal(value));
+        let attribute_name = dom_command.attribute_name.as_deref().map(|name| self.literal(name));
+        let statements = match dom_command.action {
+            DomCommandAction::Click => format!("{}.click()", element),
+            DomCommandAction::ClickIfExists => format!("for element in {}[:1]:\n    element.click()", elements),
+            DomCommandAction::Type => format!("element = {}\nelement.clear()\nelement.send_keys({})", element, value?),
+            DomCommandAction::TypeIfExists => {
+                format!("for element in {}[:1]:\n    element.clear()\n    element.send_keys({})", elements, value?)
+            }
+            DomCommandAction::Read => format!("previous_result = {}.text", element),
+            DomCommandAction::GetValue => format!("previous_result = {}.get_property(\"value\")", element),
+            DomCommandAction::GetAttribute => format!("previous_result = {}.get_dom_attribute({}) or \"\"", element, attribute_name?),
+            DomCommandAction::SetAttribute => format!(
+                "driver.execute_script(\"arguments[0].setAttribute(arguments[1], arguments[2])\", {}, {}, {})",
+                element, attribute_name?, value?
+            ),
+            DomCommandAction::SelectOption => format!("Select({}).select_by_value({})", element, value?),
+            DomCommandAction::GetAllAttributes => format!(
+                "previous_result = json.dumps([element.get_dom_attribute({}) for element in {}], separators=(\",\", \":\"))",
+                attribute_name?, elements
+            ),
+            DomCommandAction::GetUrl => "previous_result = driver.current_url".to_string(),
+            DomCommandAction::Navigate => match dom_command.value.as_deref()?.contains("://") {
+                true => format!("driver.get({})", value?),
+                // Relative URLs are resolved against the current page, as in the page.
+                false => format!("driver.get(urljoin(driver.current_url, {}))", value?),
+            },
+            DomCommandAction::ElementExists => format!("previous_result = str(bool({})).lower()", elements),
+            DomCommandAction::WaitForElement => {
+                let timeout_ms = dom_command.value.as_deref().and_then(|ms| ms.parse::<u32>().ok());
+                let timeout_ms = timeout_ms.unwrap_or(dom_utils::DEFAULT_WAIT_TIMEOUT_MS);
+                let lookup = self.selenium_lookup("driver", &dom_command.selector, "find_all", false);
+                format!("WebDriverWait(driver, {}).until(lambda _: {})", f64::from(timeout_ms) / 1000.0, lookup)
+            }
+            DomCommandAction::IsVisible => format!("previous_result = str({}.is_displayed()).lower()", element),
+            DomCommandAction::ScrollTo => format!("driver.execute_script(\"arguments[0].scrollIntoView()\", {})", element),
+            DomCommandAction::Hover => format!("ActionChains(driver).move_to_element({}).perform()", element),
+            DomCommandAction::GetAllText => format!(
+                "previous_result = {}.join(text for text in (element.text for element in {}) if text)",
+                value.unwrap_or_else(|| string_literal("\n")),
+                elements
+            ),
+            DomCommandAction::EvalJs => {
+                format!("previous_result = json.dumps(driver.execute_script({}), separators=(\",\", \":\"))", value?)
+            }
+            DomCommandAction::IsEnabled => format!("previous_result = str({}.is_enabled()).lower()", element),
+            DomCommandAction::IsSelected => format!("previous_result = str({}.is_selected()).lower()", element),
+            _ => return None,
+        };
+        Some(statements)
+    }
+
+    // A call of the script's `find` / `find_all` helper (`function`) looking `selector` up within
+    // `context`, resolved like `dom_utils::get_element`; `scoped` is set within a region, where
+    // XPath expressions are made relative to it.
+    fn selenium_lookup(&self, context: &str, selector: &str, function: &str, scoped: bool) -> String {
+        if let Some((region, inner)) = dom_utils::split_scope(selector) {
+            let region = self.selenium_lookup(context, region, "find", scoped);
+            return self.selenium_lookup(&region, inner, function, true);
+        }
+        let candidates = dom_utils::selector_candidates(selector).unwrap_or_else(|| vec![selector.to_string()]);
+        let locators: Vec<String> = candidates.iter().map(|candidate| self.selenium_locator(candidate, scoped)).collect();
+        format!("{}({}, {})", function, context, locators.join(", "))
+    }
+
+    // A Selenium `(By, value)` locator for a selector that is neither scoped nor a chain.
+    fn selenium_locator(&self, selector: &str, scoped: bool) -> String {
+        let relative = |xpath: &str| match scoped && xpath.starts_with('/') {
+            true => format!(".{}", xpath),
+            false => xpath.to_string(),
+        };
+        if let Some(xpath) = selector.strip_prefix("xpath:") {
+            format!("(By.XPATH, {})", self.literal(&relative(xpath)))
+        } else if let Some(text) = selector.strip_prefix("text:") {
+            // The innermost element containing the text, like the page's fallback for `text:` selectors.
+            let text = xpath_literal(text);
+            let xpath = format!("//*[contains(normalize-space(.), {0})][not(.//*[contains(normalize-space(.), {0})])]", text);
+            format!("(By.XPATH, {})", self.literal(&relative(&xpath)))
+        } else {
+            format!("(By.CSS_SELECTOR, {})", self.literal(selector.strip_prefix("css:").unwrap_or(selector)))
+        }
+    }
+
+    // The whole script: the test, named after `title`, wrapped around the body.
+    fn finish(mut self, title: &str) -> String {
+        while !self.blocks.is_empty() {
+            // An unclosed TRY block is closed at the end of the tasks.
+            self.push_marker(BlockMarker::EndTry, "END_TRY");
+        }
+        let variable = match (self.reads_result, self.format) {
+            (false, _) => None,
+            (true, ScriptFormat::Playwright) => Some("let previousResult = '';"),
+            (true, ScriptFormat::Selenium) => Some("previous_result = \"\""),
+        };
+        let mut body: Vec<String> = variable.map(|line| format!("{}{}", self.format.indent(), line)).into_iter().collect();
+        body.extend(self.lines);
+        if body.is_empty() && self.format == ScriptFormat::Selenium {
+            body.push(format!("{}pass", self.format.indent()));
+        }
+        let body = body.join("\n");
+        match self.format {
+            ScriptFormat::Playwright => format!(
+                "// Exported from RustAgent.\nimport {{ test }} from '@playwright/test';\n\ntest({}, async ({{ page }}) => {{\n{}\n}});\n",
+                string_literal(title),
+                body
+            ),
+            ScriptFormat::Selenium => format!("{}\n\ndef test_{}(driver):\n{}\n", SELENIUM_PRELUDE, python_name(title), body),
+        }
+    }
+}
+
+// The imports, fixture and lookup helpers of exported Selenium scripts. `find_all` tries each
+// locator of a selector fallback chain in turn, as the page does.
+const SELENIUM_PRELUDE: &str = r#"# Exported from RustAgent.
+import json
+from urllib.parse import urljoin
+
+import pytest
+from selenium import webdriver
+from selenium.common.exceptions import NoSuchElementException
+from selenium.webdriver.common.action_chains import ActionChains
+from selenium.webdriver.common.by import By
+from selenium.webdriver.support.select import Select
+from selenium.webdriver.support.wait import WebDriverWait
+
+
+@pytest.fixture
+def driver():
+    driver = webdriver.Chrome()
+    yield driver
+    driver.quit()
+
+
+def find_all(context, *locators):
+    for locator in locators:
+        elements = context.find_elements(*locator)
+        if elements:
+            return elements
+    return []
+
+
+def find(context, *locators):
+    elements = find_all(context, *locators)
+    if not elements:
+        raise NoSuchElementException(f"No element matches {locators}")
+    return elements[0]
+"#;
+
+// The command keyword of a task, for comments.
+fn action_name(task: &str) -> String {
+    task.split_whitespace().next().unwrap_or_default().to_uppercase()
+}
+
+// A string literal valid in both TypeScript and Python.
+fn string_literal(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+// An XPath string literal for `text`, which may contain both kinds of quotes.
+fn xpath_literal(text: &str) -> String {
+    if !text.contains('\'') {
+        format!("'{}'", text)
+    } else if !text.contains('"') {
+        format!("\"{}\"", text)
+    } else {
+        let parts: Vec<String> = text.split('\'').map(|part| format!("'{}'", part)).collect();
+        format!("concat({})", parts.join(", \"'\", "))
+    }
+}
+
+// A Python identifier made of the letters and digits of `title`, e.g. `workflow_checkout`.
+fn python_name(title: &str) -> String {
+    let name = title
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if name.is_empty() { "exported_run".to_string() } else { name }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks(tasks: &[&str]) -> Vec<String> {
+        tasks.iter().map(|task| task.to_string()).collect()
+    }
+
+    #[test]
+    fn test_format_names() {
+        assert_eq!(ScriptFormat::parse("Playwright"), Some(ScriptFormat::Playwright));
+        assert_eq!(ScriptFormat::parse(" selenium "), Some(ScriptFormat::Selenium));
+        assert_eq!(ScriptFormat::parse("cypress"), None);
+    }
+
+    #[test]
+    fn test_playwright_export() {
+        let script = export_script(
+            "workflow:login",
+            &tasks(&[
+                "NAVIGATE https://example.com/login",
+                "TYPE css:#email user@example.com",
+                "CLICK [css:#submit, text:\"Sign in\"]",
+                "READ css:#chat >> xpath://p[1]",
+                "TYPE css:#search {{PREVIOUS_RESULT}} again",
+                "Add the cheapest plan to the cart",
+                "TOUR_END",
+                "CLICK css:#save [post 3000: ELEMENT_EXISTS css:#toast]",
+            ]),
+            ScriptFormat::Playwright,
+        );
+        assert!(script.contains("test(\"workflow:login\", async ({ page }) => {\n  let previousResult = '';\n"));
+        assert!(script.contains("  await page.goto(\"https://example.com/login\");\n"));
+        assert!(script.contains("  await page.locator(\"css=#email\").first().fill(\"user@example.com\");\n"));
+        assert!(script.contains("  await page.locator(\"css=#submit\").or(page.getByText(\"Sign in\")).first().click();\n"));
+        assert!(script.contains("  previousResult = await page.locator(\"css=#chat\").first().locator(\"xpath=//p[1]\").first().innerText();\n"));
+        assert!(script.contains(".fill(previousResult + \" again\");\n"));
+        assert!(script.contains("  // TODO: planned by the LLM in the page; write its steps: Add the cheapest plan to the cart\n"));
+        assert!(script.contains("  // TODO: TOUR_END has no Playwright equivalent: TOUR_END\n"));
+        assert!(script.contains("  // TODO: condition not exported: [post: ELEMENT_EXISTS css:#toast]\n  await page.locator(\"css=#save\").first().click();\n"));
+        assert!(script.ends_with("});\n"));
+    }
+
+    #[test]
+    fn test_selenium_export() {
+        let script = export_script(
+            "automate run-1-1",
+            &tasks(&[
+                "NAVIGATE /pricing",
+                "WAIT_FOR_ELEMENT css:#plans 2500",
+                "CLICK_IF_EXISTS text:It's \"free\"",
+                "GET_ALL_TEXT css:.plan ,",
+            ]),
+            ScriptFormat::Selenium,
+        );
+        assert!(script.starts_with(SELENIUM_PRELUDE));
+        assert!(script.contains("\ndef test_automate_run_1_1(driver):\n    previous_result = \"\"\n"));
+        assert!(script.contains("    driver.get(urljoin(driver.current_url, \"/pricing\"))\n"));
+        assert!(script.contains("    WebDriverWait(driver, 2.5).until(lambda _: find_all(driver, (By.CSS_SELECTOR, \"#plans\")))\n"));
+        assert!(script.contains(
+            "    for element in find_all(driver, (By.XPATH, \"//*[contains(normalize-space(.), concat('It', \\\"'\\\", 's \\\"free\\\"'))]"
+        ));
+        assert!(script.contains("\n        element.click()\n"));
+        assert!(script.contains("previous_result = \",\".join(text for text in (element.text for element in find_all(driver, (By.CSS_SELECTOR, \".plan\"))) if text)"));
+    }
+
+    #[test]
+    fn test_try_blocks_are_exported() {
+        let steps = tasks(&["TRY", "CLICK css:#accept", "CATCH", "TYPE css:#notes Failed: {{error}}", "END_TRY", "TRY", "END_TRY", "GOTO retry"]);
+        let script = export_script("blocks", &steps, ScriptFormat::Playwright);
+        assert!(script.contains("  try {\n    await page.locator(\"css=#accept\").first().click();\n  } catch (error) {\n"));
+        assert!(script.contains("    await page.locator(\"css=#notes\").first().fill(\"Failed: \" + String(error));\n  }\n"));
+        assert!(script.contains("  try {\n  } catch {}\n"));
+        assert!(script.contains("  // TODO: flow step not exported: GOTO retry\n"));
+
+        let script = export_script("blocks", &steps, ScriptFormat::Selenium);
+        assert!(script.contains("    try:\n        find(driver, (By.CSS_SELECTOR, \"#accept\")).click()\n    except Exception as error:\n"));
+        assert!(script.contains("    try:\n        pass\n    except Exception:\n        pass\n"));
+        let unclosed = export_script("unclosed", &tasks(&["TRY", "CLICK css:#a"]), ScriptFormat::Selenium);
+        assert!(unclosed.ends_with("    try:\n        find(driver, (By.CSS_SELECTOR, \"#a\")).click()\n    except Exception:\n        pass\n"));
+    }
+
+    #[test]
+    fn test_placeholders_become_expressions() {
+        let script = Script { format: ScriptFormat::Selenium, lines: Vec::new(), depth: 1, blocks: Vec::new(), block_empty: false, reads_result: false };
+        assert_eq!(script.literal("{{PREVIOUS_RESULT}}"), "previous_result");
+        assert_eq!(script.literal("Failed: {{error}}!"), "\"Failed: \" + str(error) + \"!\"");
+        assert_eq!(script.literal(""), "\"\"");
+        assert_eq!(xpath_literal("plain"), "'plain'");
+        assert_eq!(xpath_literal("it's"), "\"it's\"");
+        assert_eq!(python_name("!!"), "exported_run");
+    }
+}
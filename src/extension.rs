@@ -0,0 +1,211 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::console;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::RustAgent;
+
+/// Value of the `target` field a message must have to be handled by the adapter.
+/// Messages for other listeners are ignored, so the adapter can share `onMessage` with them.
+pub const MESSAGE_TARGET: &str = "rustagent";
+
+/// A request sent to the content script, e.g.
+/// `{"target": "rustagent", "type": "automate", "tasks": ["CLICK css:#buy"]}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExtensionRequest {
+    /// Checks that the adapter is listening; answered with `"pong"`.
+    Ping,
+    /// Same as `RustAgent::set_llm_config`; not available in `dom-only` builds.
+    #[cfg(not(feature = "dom-only"))]
+    SetLlmConfig { api_url: String, model_name: String, api_key: String },
+    /// Same as `RustAgent::automate`; `tasks` is the task list itself, not a JSON string.
+    Automate { tasks: Vec<String> },
+    /// Same as `RustAgent::register_workflow`; `workflow` is the definition object.
+    RegisterWorkflow { workflow: Value },
+    /// Same as `RustAgent::run_workflow`.
+    RunWorkflow {
+        name: String,
+        #[serde(default)]
+        params: serde_json::Map<String, Value>,
+    },
+    /// Same as `RustAgent::rollback_last_run`.
+    RollbackLastRun,
+}
+
+/// Parses a message received by the adapter.
+///
+/// # Returns
+/// * `None` if the message is not addressed to the adapter (no `"target": "rustagent"`).
+/// * `Some(Err(error))` if it is addressed to the adapter but malformed.
+pub fn parse_message(message: &Value) -> Option<Result<ExtensionRequest, String>> {
+    if message.get("target").and_then(Value::as_str) != Some(MESSAGE_TARGET) {
+        return None;
+    }
+    Some(serde_json::from_value(message.clone()).map_err(|e| format!("Invalid RustAgent message: {}", e)))
+}
+
+/// Builds the response sent back for a request: `{"ok": true, "result": ...}` or `{"ok": false, "error": ...}`.
+///
+/// Errors that are serialized `LibError`s are sent as objects (with their `error_type`);
+/// other errors are sent as strings.
+pub fn response(outcome: Result<Value, JsValue>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "ok": true, "result": result }),
+        Err(error) => {
+            let error = error.as_string().unwrap_or_else(|| format!("{:?}", error));
+            let error = serde_json::from_str::<Value>(&error)
+                .ok()
+                .filter(Value::is_object)
+                .unwrap_or(Value::String(error));
+            json!({ "ok": false, "error": error })
+        }
+    }
+}
+
+// Parses the JSON string returned by `automate` and `run_workflow` so the sender gets the
+// output as an object rather than as a string to parse again.
+fn parse_results(results: JsValue) -> Value {
+    let text = results.as_string().unwrap_or_default();
+    serde_json::from_str(&text).unwrap_or(Value::String(text))
+}
+
+// Runs a request against the agent. Requests that change the agent's configuration are refused
+// while a run is in progress, instead of waiting for it.
+// Holding the shared borrow across `.await` is intended: runs may overlap each other, and the
+// mutating requests below use `try_borrow_mut` rather than panicking while a run holds it.
+#[allow(clippy::await_holding_refcell_ref)]
+async fn handle_request(agent: &RefCell<RustAgent>, request: ExtensionRequest) -> Result<Value, JsValue> {
+    let busy = || JsValue::from_str("The agent is busy running tasks; retry once the run has finished.");
+    match request {
+        ExtensionRequest::Ping => Ok(json!("pong")),
+        #[cfg(not(feature = "dom-only"))]
+        ExtensionRequest::SetLlmConfig { api_url, model_name, api_key } => {
+            agent.try_borrow_mut().map_err(|_| busy())?.set_llm_config(api_url, model_name, api_key);
+            Ok(Value::Null)
+        }
+        ExtensionRequest::Automate { tasks } => {
+            let tasks_json = serde_json::to_string(&tasks).map_err(|e| JsValue::from_str(&e.to_string()))?;
+            let agent = agent.borrow();
+            agent.automate(tasks_json).await.map(parse_results)
+        }
+        ExtensionRequest::RegisterWorkflow { workflow } => {
+            agent.try_borrow_mut().map_err(|_| busy())?.register_workflow(workflow.to_string())?;
+            Ok(Value::Null)
+        }
+        ExtensionRequest::RunWorkflow { name, params } => {
+            let agent = agent.borrow();
+            agent.run_workflow(name, Value::Object(params).to_string()).await.map(parse_results)
+        }
+        ExtensionRequest::RollbackLastRun => Ok(json!(agent.borrow().rollback_last_run())),
+    }
+}
+
+// Looks up `chrome.runtime.onMessage` (or `browser.runtime.onMessage` in Firefox).
+fn on_message_event() -> Result<JsValue, JsValue> {
+    let global = js_sys::global();
+    for namespace in ["chrome", "browser"] {
+        let runtime = js_sys::Reflect::get(&global, &JsValue::from_str(namespace))
+            .and_then(|api| if api.is_object() { js_sys::Reflect::get(&api, &JsValue::from_str("runtime")) } else { Ok(JsValue::UNDEFINED) })?;
+        if runtime.is_object() {
+            let event = js_sys::Reflect::get(&runtime, &JsValue::from_str("onMessage"))?;
+            if event.is_object() {
+                return Ok(event);
+            }
+        }
+    }
+    Err(JsValue::from_str("chrome.runtime.onMessage is not available; the extension adapter must run in an extension context."))
+}
+
+// Calls `method` (`addListener` / `removeListener`) of the `onMessage` event with `listener`.
+fn call_on_message(event: &JsValue, method: &str, listener: &js_sys::Function) -> Result<(), JsValue> {
+    js_sys::Reflect::get(event, &JsValue::from_str(method))?
+        .dyn_into::<js_sys::Function>()?
+        .call1(event, listener)?;
+    Ok(())
+}
+
+/// Listens for `chrome.runtime` messages addressed to a `RustAgent` and answers them, so the
+/// crate can run in an extension content script and be driven from the background service worker.
+///
+/// See `ExtensionRequest` for the message schema. Every handled message is answered through
+/// `sendResponse` with `{"ok": true, "result": ...}` or `{"ok": false, "error": ...}`.
+#[wasm_bindgen]
+pub struct ExtensionAdapter {
+    on_message: JsValue,
+    listener: Closure<dyn FnMut(JsValue, JsValue, js_sys::Function) -> JsValue>,
+}
+
+#[wasm_bindgen]
+impl ExtensionAdapter {
+    /// Starts answering messages with `agent`, which the adapter takes ownership of.
+    ///
+    /// # Returns
+    /// The adapter, or `Err(JsValue)` if `chrome.runtime.onMessage` is not available.
+    #[wasm_bindgen]
+    pub fn listen(agent: RustAgent) -> Result<ExtensionAdapter, JsValue> {
+        let on_message = on_message_event()?;
+        let agent = Rc::new(RefCell::new(agent));
+        let listener = Closure::wrap(Box::new(move |message: JsValue, _sender: JsValue, send_response: js_sys::Function| {
+            let message = js_sys::JSON::stringify(&message)
+                .ok()
+                .and_then(|text| text.as_string())
+                .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+                .unwrap_or(Value::Null);
+            let request = match parse_message(&message) {
+                None => return JsValue::FALSE, // Not for us: leave it to other listeners
+                Some(request) => request,
+            };
+            let agent = agent.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let outcome = match request {
+                    Ok(request) => handle_request(&agent, request).await,
+                    Err(e) => Err(JsValue::from_str(&e)),
+                };
+                let reply = js_sys::JSON::parse(&response(outcome).to_string()).unwrap_or(JsValue::NULL);
+                if let Err(e) = send_response.call1(&JsValue::NULL, &reply) {
+                    console::warn_1(&format!("Failed to send the response to a RustAgent message: {:?}", e.as_string()).into());
+                }
+            });
+            JsValue::TRUE // Keeps `sendResponse` valid until the asynchronous reply
+        }) as Box<dyn FnMut(JsValue, JsValue, js_sys::Function) -> JsValue>);
+        call_on_message(&on_message, "addListener", listener.as_ref().unchecked_ref())?;
+        console::log_1(&"RustAgent extension adapter listening for runtime messages".into());
+        Ok(ExtensionAdapter { on_message, listener })
+    }
+
+    /// Stops answering messages.
+    #[wasm_bindgen]
+    pub fn stop(&self) -> Result<(), JsValue> {
+        call_on_message(&self.on_message, "removeListener", self.listener.as_ref().unchecked_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_message_schema() {
+        assert_eq!(parse_message(&json!({"type": "ping"})), None, "Messages without the target are ignored");
+        assert_eq!(parse_message(&json!({"target": "other", "type": "ping"})), None);
+        assert_eq!(parse_message(&json!({"target": "rustagent", "type": "ping"})), Some(Ok(ExtensionRequest::Ping)));
+        assert_eq!(
+            parse_message(&json!({"target": "rustagent", "type": "automate", "tasks": ["CLICK css:#buy"]})),
+            Some(Ok(ExtensionRequest::Automate { tasks: vec!["CLICK css:#buy".to_string()] }))
+        );
+        assert_eq!(
+            parse_message(&json!({"target": "rustagent", "type": "run_workflow", "name": "login"})),
+            Some(Ok(ExtensionRequest::RunWorkflow { name: "login".to_string(), params: serde_json::Map::new() }))
+        );
+        assert!(matches!(parse_message(&json!({"target": "rustagent", "type": "explode"})), Some(Err(_))));
+        assert!(matches!(parse_message(&json!({"target": "rustagent", "type": "automate"})), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_response_shape() {
+        assert_eq!(response(Ok(json!([{"Ok": "done"}]))), json!({"ok": true, "result": [{"Ok": "done"}]}));
+    }
+}
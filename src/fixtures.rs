@@ -0,0 +1,109 @@
+use std::cell::RefCell;
+
+use crate::llm::prompt_subject;
+use crate::llm_error::LlmError;
+
+/// A response returned instead of calling the LLM for prompts whose subject contains `matcher`.
+#[derive(Debug, Clone, PartialEq)]
+struct LlmFixture {
+    matcher: String,
+    response: Result<String, LlmError>,
+}
+
+thread_local! {
+    // Like the shared budget, fixtures apply to every agent of the page (each worker has its own).
+    static FIXTURES: RefCell<Vec<LlmFixture>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registers `response` as the answer to every prompt whose subject (see `llm::prompt_subject`)
+/// contains `matcher`, replacing the response of an earlier fixture with the same matcher.
+/// Fixtures are checked in the order they were added.
+///
+/// # Returns
+/// `Ok(())`, or an error if `matcher` is empty, since it would match every prompt.
+pub fn add(matcher: &str, response: &str) -> Result<(), String> {
+    insert(matcher, Ok(response.to_string()))
+}
+
+/// Registers `error` as the failure of every prompt whose subject contains `matcher`, like `add`.
+#[cfg(test)]
+pub fn add_failure(matcher: &str, error: LlmError) -> Result<(), String> {
+    insert(matcher, Err(error))
+}
+
+fn insert(matcher: &str, response: Result<String, LlmError>) -> Result<(), String> {
+    if matcher.is_empty() {
+        return Err("An LLM fixture needs a non-empty matcher.".to_string());
+    }
+    FIXTURES.with(|fixtures| {
+        let mut fixtures = fixtures.borrow_mut();
+        let fixture = LlmFixture { matcher: matcher.to_string(), response };
+        match fixtures.iter_mut().find(|existing| existing.matcher == fixture.matcher) {
+            Some(existing) => *existing = fixture,
+            None => fixtures.push(fixture),
+        }
+    });
+    Ok(())
+}
+
+/// Removes every fixture.
+pub fn clear() {
+    FIXTURES.with(|fixtures| fixtures.borrow_mut().clear());
+}
+
+/// The number of registered fixtures.
+pub fn count() -> usize {
+    FIXTURES.with(|fixtures| fixtures.borrow().len())
+}
+
+/// The response of the first fixture whose matcher the subject of `prompt` contains, if any. The
+/// subject is the task of a planning prompt, so that the command catalogue and page content sent
+/// with it do not match, or the question of an `LLM_DECIDE` / `LLM_ASSERT` prompt.
+pub fn respond(prompt: &str) -> Option<Result<String, LlmError>> {
+    let subject = prompt_subject(prompt);
+    FIXTURES.with(|fixtures| {
+        fixtures
+            .borrow()
+            .iter()
+            .find(|fixture| subject.contains(&fixture.matcher))
+            .map(|fixture| fixture.response.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planning_prompt(task: &str) -> String {
+        format!(
+            "You can use CLICK <selector> and READ <selector>.\nThe user wants to perform the following task: {}\nThe page shows: cart, menu.",
+            serde_json::to_string(task).unwrap()
+        )
+    }
+
+    #[test]
+    fn test_fixtures_answer_matching_prompts() {
+        clear();
+        assert!(add("", "[]").is_err());
+        add("open the cart", r#"[{"action": "CLICK", "selector": "css:#cart"}]"#).unwrap();
+        add("cart", "The cart is empty.").unwrap();
+        assert_eq!(count(), 2);
+
+        assert_eq!(respond(&planning_prompt("open the cart")), Some(Ok(r#"[{"action": "CLICK", "selector": "css:#cart"}]"#.to_string())), "The first fixture added wins");
+        assert_eq!(respond(&planning_prompt("empty the cart")), Some(Ok("The cart is empty.".to_string())));
+        assert_eq!(respond(&planning_prompt("open the menu")), None, "The page content is not matched");
+
+        add("cart", "Your cart has 2 items.").unwrap();
+        assert_eq!(count(), 2, "A fixture with the same matcher is replaced");
+        assert_eq!(respond("empty the cart"), Some(Ok("Your cart has 2 items.".to_string())), "Unstructured prompts are matched whole");
+
+        add("CLICK", "[]").unwrap();
+        assert_eq!(respond(&planning_prompt("open the menu")), None, "The command catalogue is not matched");
+        add_failure("Is the shop open", LlmError::RateLimited { retry_after_ms: None, message: "Slow down".to_string() }).unwrap();
+        let question = "The page shows:\n---\nQuestion: a cart\n---\nQuestion: Is the shop open?\nAnswer with only \"yes\" or \"no\".";
+        assert!(matches!(respond(question), Some(Err(LlmError::RateLimited { .. }))), "Questions are matched on the last question");
+
+        clear();
+        assert_eq!(respond("open the cart"), None);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use crate::try_catch::{self, BlockMarker};
+
+/// Number of times a `GOTO` jumps before failing, unless it gives its own limit.
+pub const DEFAULT_MAX_JUMPS: u32 = 10;
+
+/// A step of a task list that moves through it instead of running anything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FlowStep<'a> {
+    /// `LABEL <name>`: a place `GOTO` can jump to.
+    Label(&'a str),
+    /// `GOTO <label> [max_jumps]`: continues with the tasks after the label, at most `max_jumps` times.
+    Goto { label: &'a str, max_jumps: u32 },
+}
+
+// `true` if `name` can name a label: letters, digits, `_` and `-` only.
+fn is_valid_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Parses a `LABEL <name>` or `GOTO <label> [max_jumps]` step. Keywords are matched
+/// case-insensitively. Tasks that merely start with these words (e.g. "Label the photos") are
+/// left to the other commands and the LLM.
+///
+/// # Returns
+/// * `None` if `task` is not a `LABEL` or `GOTO` step.
+/// * `Some(Ok(FlowStep))` with the parsed step.
+/// * `Some(Err(String))` if the jump limit is zero.
+pub fn parse_step(task: &str) -> Option<Result<FlowStep<'_>, String>> {
+    let mut parts = task.split_whitespace();
+    let keyword = parts.next()?.to_uppercase();
+    let args: Vec<&str> = parts.collect();
+    let step = match (keyword.as_str(), args.as_slice()) {
+        ("LABEL", [name]) if is_valid_label(name) => Ok(FlowStep::Label(name)),
+        ("GOTO", [label]) if is_valid_label(label) => Ok(FlowStep::Goto { label, max_jumps: DEFAULT_MAX_JUMPS }),
+        ("GOTO", [label, max_jumps]) if is_valid_label(label) => match max_jumps.parse::<u32>().ok()? {
+            0 => Err(format!("The jump limit of '{}' must be at least 1", task.trim())),
+            max_jumps => Ok(FlowStep::Goto { label, max_jumps }),
+        },
+        _ => return None,
+    };
+    Some(step)
+}
+
+/// Checks the labels and jumps of a task list: every step parses, labels are unique and outside
+/// `CATCH` parts, and every `GOTO` names a label of the list.
+///
+/// # Returns
+/// `Ok(())`, or an error naming the (1-based) task at fault.
+pub fn check_labels(tasks: &[String]) -> Result<(), String> {
+    let mut labels = HashMap::new();
+    let mut targets = Vec::new();
+    // Whether each open TRY block has reached its CATCH (see `try_catch::check_blocks`).
+    let mut open: Vec<bool> = Vec::new();
+    for (index, task) in tasks.iter().enumerate() {
+        match try_catch::marker(task) {
+            Some(BlockMarker::Try) => open.push(false),
+            Some(BlockMarker::Catch) => {
+                if let Some(in_catch) = open.last_mut() {
+                    *in_catch = true;
+                }
+            }
+            Some(BlockMarker::EndTry) => {
+                open.pop();
+            }
+            None => {}
+        }
+        match parse_step(task) {
+            Some(Err(e)) => return Err(format!("Task {}: {}", index + 1, e)),
+            Some(Ok(FlowStep::Label(name))) => {
+                if open.iter().any(|&in_catch| in_catch) {
+                    return Err(format!("Task {}: label '{}' is inside a CATCH part", index + 1, name));
+                }
+                if labels.insert(name, index).is_some() {
+                    return Err(format!("Task {}: label '{}' is defined twice", index + 1, name));
+                }
+            }
+            Some(Ok(FlowStep::Goto { label, .. })) => targets.push((index, label)),
+            None => {}
+        }
+    }
+    match targets.into_iter().find(|(_, label)| !labels.contains_key(label)) {
+        Some((index, label)) => Err(format!("Task {}: GOTO names unknown label '{}'", index + 1, label)),
+        None => Ok(()),
+    }
+}
+
+/// Index of the `LABEL <name>` step in `tasks`.
+pub fn label_index(tasks: &[String], name: &str) -> Option<usize> {
+    tasks.iter().position(|task| matches!(parse_step(task), Some(Ok(FlowStep::Label(label))) if label == name))
+}
+
+/// Counts the jumps each `GOTO` of a run has made, to stop loops that never end.
+#[derive(Debug, Default)]
+pub struct JumpCounter {
+    jumps: HashMap<usize, u32>,
+}
+
+impl JumpCounter {
+    /// Records a jump of the `GOTO` at `index` in the task list.
+    ///
+    /// # Returns
+    /// `Ok(())` if the jump may be made, or an error once that `GOTO` has jumped `max_jumps` times.
+    pub fn jump(&mut self, index: usize, label: &str, max_jumps: u32) -> Result<(), String> {
+        let jumps = self.jumps.entry(index).or_insert(0);
+        if *jumps >= max_jumps {
+            return Err(format!("GOTO {} (task {}) already jumped {} times; stopping the loop", label, index + 1, max_jumps));
+        }
+        *jumps += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks(list: &[&str]) -> Vec<String> {
+        list.iter().map(|task| task.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_step() {
+        assert_eq!(parse_step("LABEL retry"), Some(Ok(FlowStep::Label("retry"))));
+        assert_eq!(parse_step("goto retry"), Some(Ok(FlowStep::Goto { label: "retry", max_jumps: DEFAULT_MAX_JUMPS })));
+        assert_eq!(parse_step("GOTO retry 3"), Some(Ok(FlowStep::Goto { label: "retry", max_jumps: 3 })));
+        assert!(matches!(parse_step("GOTO retry 0"), Some(Err(_))));
+        assert_eq!(parse_step("Label the photos"), None, "Other tasks starting with a keyword are left alone");
+        assert_eq!(parse_step("goto the checkout page"), None);
+        assert_eq!(parse_step("CLICK css:#label"), None);
+        assert_eq!(parse_step("LABELS"), None);
+    }
+
+    #[test]
+    fn test_check_labels() {
+        let list = tasks(&["LABEL retry", "TRY", "CLICK css:#refresh", "CATCH", "GOTO retry 5", "END_TRY"]);
+        assert!(check_labels(&list).is_ok());
+        assert_eq!(label_index(&list, "retry"), Some(0));
+
+        assert!(check_labels(&tasks(&["GOTO missing"])).unwrap_err().contains("unknown label 'missing'"));
+        assert!(check_labels(&tasks(&["LABEL a", "LABEL a"])).unwrap_err().contains("defined twice"));
+        assert!(check_labels(&tasks(&["TRY", "CATCH", "LABEL a", "END_TRY"])).unwrap_err().contains("inside a CATCH"));
+        assert!(check_labels(&tasks(&["TRY", "LABEL a", "CATCH", "END_TRY", "GOTO a"])).is_ok(), "Labels may be inside a TRY part");
+    }
+
+    #[test]
+    fn test_jump_counter() {
+        let mut counter = JumpCounter::default();
+        assert!(counter.jump(4, "retry", 2).is_ok());
+        assert!(counter.jump(4, "retry", 2).is_ok());
+        assert!(counter.jump(4, "retry", 2).unwrap_err().contains("already jumped 2 times"));
+        assert!(counter.jump(7, "retry", 2).is_ok(), "Each GOTO has its own count");
+    }
+}
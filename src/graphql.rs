@@ -0,0 +1,112 @@
+use crate::agent::AgentError;
+use crate::dom_utils;
+use crate::openapi::{self, ApiRequest};
+use serde_json::{json, Map, Value};
+
+/// Splits the value of `GRAPHQL`, `"<query> [| <variables_json>]"`, into the query and its
+/// variables. The variables start at the first `|` followed by a JSON object, so the query may
+/// contain `|` (e.g. in a string argument).
+///
+/// # Returns
+/// The query and the variables, or `None` if the query is empty.
+pub fn parse_value(value: &str) -> Option<(&str, Option<Map<String, Value>>)> {
+    let value = value.trim();
+    let split = value.match_indices('|').find_map(|(index, _)| {
+        let variables = serde_json::from_str::<Map<String, Value>>(value[index + 1..].trim()).ok()?;
+        Some((value[..index].trim(), Some(variables)))
+    });
+    let (query, variables) = split.unwrap_or((value, None));
+    if query.is_empty() {
+        return None;
+    }
+    Some((query, variables))
+}
+
+/// Sends `query` with its `variables` to the GraphQL endpoint at `endpoint` (absolute or relative
+/// to the current page), as a JSON `POST` made from the page with the user's cookies, even if the
+/// endpoint is on another origin that allows it.
+///
+/// # Returns
+/// The JSON response (`data`, and `errors` if some fields could not be resolved), or
+/// `AgentError::ApiCallFailed` if the request failed, the endpoint answered with an error status or
+/// something other than JSON, or no data came back.
+pub async fn query(endpoint: &str, query: &str, variables: Option<Map<String, Value>>) -> Result<String, AgentError> {
+    let url = dom_utils::resolve_url(endpoint)?.href();
+    let request = ApiRequest {
+        method: "POST".to_string(),
+        url: url.clone(),
+        headers: vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Accept".to_string(), "application/graphql-response+json, application/json".to_string()),
+        ],
+        body: Some(request_body(query, variables)),
+    };
+    let (status, text) = openapi::fetch(&request, "include").await.map_err(|message| AgentError::ApiCallFailed {
+        message: format!("GraphQL request to {} failed: {}", url, message),
+        status: None,
+    })?;
+    read_response(&url, status, &text)
+}
+
+// The JSON body of a GraphQL request; `variables` is left out if there are none.
+fn request_body(query: &str, variables: Option<Map<String, Value>>) -> String {
+    match variables {
+        Some(variables) => json!({ "query": query, "variables": variables }),
+        None => json!({ "query": query }),
+    }
+    .to_string()
+}
+
+// Reads the response of a GraphQL endpoint: a JSON object with `data` unless the whole request
+// failed, in which case its `errors` say why.
+fn read_response(url: &str, status: u16, text: &str) -> Result<String, AgentError> {
+    let failed = |reason: String| AgentError::ApiCallFailed {
+        message: format!("GraphQL endpoint {} {}", url, reason),
+        status: if (200..300).contains(&status) { None } else { Some(status) },
+    };
+    let response: Value = serde_json::from_str(text).map_err(|_| failed(format!("answered {} with no JSON: {}", status, text)))?;
+    let has_data = response.get("data").is_some_and(|data| !data.is_null());
+    if !(200..300).contains(&status) || !has_data {
+        let errors = response.get("errors").map(Value::to_string).unwrap_or_else(|| response.to_string());
+        return Err(failed(format!("answered {} without data: {}", status, errors)));
+    }
+    Ok(format!("GraphQL response: {}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value() {
+        let (query, variables) = parse_value(r#"query($id: ID!) { order(id: $id) { status } } | {"id": "42"}"#).unwrap();
+        assert_eq!(query, "query($id: ID!) { order(id: $id) { status } }");
+        assert_eq!(variables.unwrap()["id"], "42");
+
+        let (query, variables) = parse_value(r#"{ search(text: "a | b") { id } } | {"limit": "x|y"}"#).unwrap();
+        assert_eq!(query, r#"{ search(text: "a | b") { id } }"#);
+        assert_eq!(variables.unwrap()["limit"], "x|y");
+        assert_eq!(parse_value("{ viewer { login } }"), Some(("{ viewer { login } }", None)));
+        assert_eq!(parse_value(r#" | {"id": 1}"#), None);
+        assert_eq!(parse_value(""), None);
+    }
+
+    #[test]
+    fn test_request_body() {
+        assert_eq!(request_body("{ viewer { login } }", None), r#"{"query":"{ viewer { login } }"}"#);
+        let variables = serde_json::from_str(r#"{"id": 1}"#).unwrap();
+        assert_eq!(request_body("query($id: ID!) { node(id: $id) { id } }", Some(variables)), r#"{"query":"query($id: ID!) { node(id: $id) { id } }","variables":{"id":1}}"#);
+    }
+
+    #[test]
+    fn test_read_response() {
+        let url = "https://example.com/graphql";
+        let partial = r#"{"data":{"order":null},"errors":[{"message":"Not found","path":["order"]}]}"#;
+        assert_eq!(read_response(url, 200, partial).unwrap(), format!("GraphQL response: {}", partial));
+
+        let no_data = read_response(url, 200, r#"{"errors":[{"message":"Syntax Error"}]}"#);
+        assert!(matches!(&no_data, Err(AgentError::ApiCallFailed { message, status: None }) if message.contains("Syntax Error")), "{:?}", no_data);
+        assert!(matches!(read_response(url, 401, r#"{"data":null}"#), Err(AgentError::ApiCallFailed { status: Some(401), .. })));
+        assert!(matches!(read_response(url, 502, "<html>Bad gateway</html>"), Err(AgentError::ApiCallFailed { status: Some(502), .. })));
+    }
+}
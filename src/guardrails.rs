@@ -0,0 +1,112 @@
+use serde::Serialize;
+
+/// What a guardrail found suspicious about an LLM-proposed command.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailKind {
+    /// The command's element is not on the page, and no earlier command waits for it or may create it.
+    MissingElement,
+    /// The command leads to an origin outside the allowlist (see `ExecutionConfig::allowed_origins`).
+    DisallowedNavigation,
+    /// No allowlist is set and the command leads to another origin than the current page's.
+    OffsiteNavigation,
+    /// The command enters a value that looks like a placeholder the LLM made up, e.g. `<your email>`.
+    PlaceholderValue,
+}
+
+/// A command of an LLM plan that a guardrail flagged, to be confirmed before the plan runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuardrailWarning {
+    /// Index of the command in the array.
+    pub index: usize,
+    pub action: String,
+    pub selector: String,
+    pub kind: GuardrailKind,
+    pub message: String,
+}
+
+/// What is passed to the confirmation handler, and the message of the `PlanValidation` error of a
+/// plan that was not confirmed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuardrailReport {
+    pub confirmed: bool,
+    pub warnings: Vec<GuardrailWarning>,
+}
+
+// Words that, in brackets, name the field a made-up value stands for, e.g. `[email]`.
+const FIELD_WORDS: &[&str] = &[
+    "email", "e-mail", "name", "username", "password", "phone", "address", "city", "zip", "postcode",
+    "card", "number", "date", "code", "company", "value", "text", "query", "placeholder",
+];
+// How made-up values often start, e.g. `<your email>` or `[insert name]`.
+const PLACEHOLDER_PREFIXES: &[&str] = &["your ", "your_", "my ", "insert ", "enter ", "the user's ", "user's "];
+// Made-up values written without brackets, lowercased.
+const PLACEHOLDER_PHRASES: &[&str] = &["your email here", "your name here", "lorem ipsum", "@example.com", "@example.org", "xxx-xxx", "todo:"];
+
+/// The part of `value` that looks like a placeholder the LLM made up instead of a real value, if
+/// any: bracketed field names (`<your email>`, `[Full Name]`, `{password}`), `YOUR_...` tokens,
+/// and stock phrases such as `lorem ipsum` or `@example.com` addresses. `{{var.name}}` and
+/// `{{secret.name}}` placeholders are substituted when the command runs and are not reported.
+pub fn made_up_placeholder(value: &str) -> Option<String> {
+    for (open, close) in [('<', '>'), ('[', ']'), ('{', '}')] {
+        let mut rest = value;
+        while let Some(start) = rest.find(open) {
+            let after = &rest[start + open.len_utf8()..];
+            // `{{...}}` placeholders are substituted at execution time.
+            if open == '{' && (after.starts_with('{') || rest[..start].ends_with('{')) {
+                rest = after.trim_start_matches('{');
+                continue;
+            }
+            let Some(end) = after.find(close) else { break };
+            let inner = &after[..end];
+            if looks_like_field(inner) {
+                return Some(format!("{}{}{}", open, inner, close));
+            }
+            rest = &after[end..];
+        }
+    }
+    if let Some(token) = value.split(|c: char| !(c.is_alphanumeric() || c == '_')).find(|token| token.starts_with("YOUR_")) {
+        return Some(token.to_string());
+    }
+    let lowercase = value.to_lowercase();
+    PLACEHOLDER_PHRASES.iter().find(|phrase| lowercase.contains(*phrase)).map(|phrase| phrase.to_string())
+}
+
+// Whether bracketed text reads like the name of a field rather than content, e.g. `your email` but
+// not `b` (an HTML tag) or `1` (a footnote).
+fn looks_like_field(inner: &str) -> bool {
+    let inner = inner.trim();
+    if inner.is_empty() || inner.chars().count() > 40 || !inner.chars().all(|c| c.is_alphabetic() || matches!(c, ' ' | '_' | '-' | '\'')) {
+        return false;
+    }
+    let lowercase = inner.to_lowercase();
+    PLACEHOLDER_PREFIXES.iter().any(|prefix| lowercase.starts_with(prefix))
+        || lowercase.split([' ', '_', '-']).any(|word| FIELD_WORDS.contains(&word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_made_up_placeholders() {
+        assert_eq!(made_up_placeholder("<your email>").as_deref(), Some("<your email>"));
+        assert_eq!(made_up_placeholder("Dear [Full Name],").as_deref(), Some("[Full Name]"));
+        assert_eq!(made_up_placeholder("{password}").as_deref(), Some("{password}"));
+        assert_eq!(made_up_placeholder("<insert street>").as_deref(), Some("<insert street>"));
+        assert_eq!(made_up_placeholder("YOUR_API_KEY").as_deref(), Some("YOUR_API_KEY"));
+        assert_eq!(made_up_placeholder("john.doe@example.com").as_deref(), Some("@example.com"));
+        assert_eq!(made_up_placeholder("Lorem ipsum dolor").as_deref(), Some("lorem ipsum"));
+    }
+
+    #[test]
+    fn test_real_values_pass() {
+        assert_eq!(made_up_placeholder("jane@acme.io"), None);
+        assert_eq!(made_up_placeholder("{{var.otp}}"), None);
+        assert_eq!(made_up_placeholder("{{secret.password}}"), None);
+        assert_eq!(made_up_placeholder("<b>Great</b> pizza [1]"), None);
+        assert_eq!(made_up_placeholder("a < b and c > d"), None);
+        assert_eq!(made_up_placeholder("Margherita, large"), None);
+        assert_eq!(made_up_placeholder("Your order"), None);
+    }
+}
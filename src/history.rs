@@ -0,0 +1,317 @@
+use serde::{Serialize, Deserialize};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use crate::dom_utils::DomError;
+use crate::result_code::TaskResult;
+use crate::soft_assert::AssertionReport;
+use crate::storage::Storage;
+
+/// Number of runs kept in the history until `set_history_limit` is called.
+pub const DEFAULT_HISTORY_LIMIT: usize = 50;
+
+/// Storage key the history is persisted under, as a JSON array of runs, oldest first.
+pub const HISTORY_KEY: &str = "history";
+
+thread_local! {
+    // Runs started in this session, for run ids.
+    static RUNS_STARTED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A new id for a run started at `started_at_ms`, e.g. `run-1718000000000-3`. Ids are unique
+/// across sessions, so that feedback can be reported on persisted runs.
+pub fn new_run_id(started_at_ms: f64) -> String {
+    let number = RUNS_STARTED.with(|runs| {
+        runs.set(runs.get() + 1);
+        runs.get()
+    });
+    format!("run-{}-{}", started_at_ms as u64, number)
+}
+
+/// What the host reported about the outcome of a run with `report_feedback`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunFeedback {
+    /// Whether the run achieved what it was meant to, whatever its results say.
+    pub success: bool,
+    pub note: Option<String>,
+    pub reported_at_ms: f64,
+}
+
+/// A finished run, as listed by `get_history`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    /// The id of the run, as in its result envelope. Empty for runs persisted before runs had ids.
+    #[serde(default)]
+    pub id: String,
+    /// What was run, as in result envelopes: `automate`, `workflow:<name>`, `schedule`, ...
+    pub run: String,
+    /// The tasks of the run, before `{{PREVIOUS_RESULT}}` substitution.
+    pub tasks: Vec<String>,
+    /// `true` if every task that ran succeeded.
+    pub success: bool,
+    pub started_at_ms: f64,
+    pub finished_at_ms: f64,
+    /// The result of each task that ran, in the same shape as the result of `automate`.
+    pub results: Vec<TaskResult>,
+    /// What the assertions of the run found, if it ran in soft-assert mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assertion_report: Option<AssertionReport>,
+    /// The feedback last reported on the run, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<RunFeedback>,
+}
+
+/// Which runs `get_history` returns, e.g. `{"run": "workflow:", "success": false, "limit": 10}`.
+/// Every field is optional.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct HistoryFilter {
+    /// Only runs whose label starts with this, e.g. `workflow:` for every workflow run.
+    pub run: Option<String>,
+    /// Only successful (`true`) or failed (`false`) runs.
+    pub success: Option<bool>,
+    /// Only runs started at or after this time, in milliseconds since the epoch.
+    pub since_ms: Option<f64>,
+    /// Only runs with (`true`) or without (`false`) feedback.
+    pub feedback: Option<bool>,
+    /// At most this many runs, the most recent ones.
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, record: &RunRecord) -> bool {
+        self.run.as_deref().is_none_or(|prefix| record.run.starts_with(prefix))
+            && self.success.is_none_or(|success| record.success == success)
+            && self.since_ms.is_none_or(|since_ms| record.started_at_ms >= since_ms)
+            && self.feedback.is_none_or(|feedback| record.feedback.is_some() == feedback)
+    }
+}
+
+#[derive(Debug)]
+struct HistoryState {
+    // Oldest first.
+    records: VecDeque<RunRecord>,
+    limit: usize,
+    // Set once persistence is enabled; the history is written to it after every change.
+    storage: Option<Storage>,
+}
+
+/// The bounded history of finished runs. Clones share the same history, so runs started by
+/// schedules and triggers are recorded alongside the caller's own.
+#[derive(Debug, Clone)]
+pub struct RunHistory {
+    state: Rc<RefCell<HistoryState>>,
+}
+
+impl Default for RunHistory {
+    fn default() -> Self {
+        RunHistory {
+            state: Rc::new(RefCell::new(HistoryState {
+                records: VecDeque::new(),
+                limit: DEFAULT_HISTORY_LIMIT,
+                storage: None,
+            })),
+        }
+    }
+}
+
+impl RunHistory {
+    /// Adds a finished run, dropping the oldest runs beyond the limit.
+    pub fn record(&self, record: RunRecord) {
+        let mut state = self.state.borrow_mut();
+        state.records.push_back(record);
+        trim(&mut state);
+        persist(&state);
+    }
+
+    /// The runs matching `filter`, most recent first.
+    pub fn query(&self, filter: &HistoryFilter) -> Vec<RunRecord> {
+        let state = self.state.borrow();
+        state
+            .records
+            .iter()
+            .rev()
+            .filter(|record| filter.matches(record))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    /// The run with id `id`, if it is still in the history.
+    pub fn get(&self, id: &str) -> Option<RunRecord> {
+        let state = self.state.borrow();
+        state.records.iter().find(|record| !id.is_empty() && record.id == id).cloned()
+    }
+
+    /// Attaches `feedback` to the run with id `id`, replacing earlier feedback.
+    ///
+    /// # Returns
+    /// `false` if no run in the history has this id.
+    pub fn set_feedback(&self, id: &str, feedback: RunFeedback) -> bool {
+        let mut state = self.state.borrow_mut();
+        let Some(record) = state.records.iter_mut().find(|record| !id.is_empty() && record.id == id) else {
+            return false;
+        };
+        record.feedback = Some(feedback);
+        persist(&state);
+        true
+    }
+
+    /// Forgets every run, including persisted ones.
+    pub fn clear(&self) {
+        let mut state = self.state.borrow_mut();
+        state.records.clear();
+        persist(&state);
+    }
+
+    /// Sets how many runs are kept (0 stops recording), dropping the oldest runs beyond it.
+    pub fn set_limit(&self, limit: usize) {
+        let mut state = self.state.borrow_mut();
+        state.limit = limit;
+        if trim(&mut state) {
+            persist(&state);
+        }
+    }
+
+    /// Keeps the history in `storage`, so that it survives reloads. Runs persisted by earlier
+    /// sessions are loaded and come before the runs of this session.
+    pub async fn enable_persistence(&self, storage: Storage) -> Result<(), DomError> {
+        let stored: Vec<RunRecord> = match storage.get(HISTORY_KEY).await? {
+            Some(json) => serde_json::from_str(&json).map_err(|e| DomError::SerializationError {
+                message: format!("Stored history is malformed: {}", e),
+            })?,
+            None => Vec::new(),
+        };
+
+        let mut state = self.state.borrow_mut();
+        // Runs already in memory, e.g. loaded when persistence was enabled before, are not repeated.
+        let stored: Vec<RunRecord> = stored.into_iter().filter(|record| !state.records.contains(record)).collect();
+        for record in stored.into_iter().rev() {
+            state.records.push_front(record);
+        }
+        trim(&mut state);
+        state.storage = Some(storage);
+        persist(&state);
+        Ok(())
+    }
+}
+
+// Drops the oldest runs beyond the limit; returns whether any were dropped.
+fn trim(state: &mut HistoryState) -> bool {
+    let excess = state.records.len().saturating_sub(state.limit);
+    state.records.drain(..excess);
+    excess > 0
+}
+
+// Writes the history to the storage, if persistence is enabled. Writes are queued in order and
+// failures are logged.
+fn persist(state: &HistoryState) {
+    let Some(storage) = state.storage.clone() else {
+        return;
+    };
+    let json = match serde_json::to_string(&state.records) {
+        Ok(json) => json,
+        Err(e) => return web_sys::console::warn_1(&format!("Failed to serialize the run history: {}", e).into()),
+    };
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(e) = storage.set(HISTORY_KEY, &json).await {
+            web_sys::console::warn_1(&format!("Failed to persist the run history: {}", e).into());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn run(label: &str, success: bool, started_at_ms: f64) -> RunRecord {
+        RunRecord {
+            id: format!("run-{}", started_at_ms),
+            run: label.to_string(),
+            tasks: vec!["CLICK css:#go".to_string()],
+            success,
+            started_at_ms,
+            finished_at_ms: started_at_ms + 1.0,
+            results: if success {
+                vec![TaskResult::ok("Clicked".to_string(), "CLICK_OK".to_string())]
+            } else {
+                vec![TaskResult::err(crate::LibError::LlmCall { message: "timeout".to_string() })]
+            },
+            assertion_report: None,
+            feedback: None,
+        }
+    }
+
+    fn labels(records: &[RunRecord]) -> Vec<&str> {
+        records.iter().map(|record| record.run.as_str()).collect()
+    }
+
+    #[test]
+    fn test_history_is_bounded_and_filtered() {
+        let history = RunHistory::default();
+        history.set_limit(3);
+        history.record(run("automate", true, 1.0));
+        history.record(run("workflow:login", false, 2.0));
+        history.record(run("workflow:checkout", true, 3.0));
+        history.record(run("schedule", true, 4.0));
+
+        let all = history.query(&HistoryFilter::default());
+        assert_eq!(labels(&all), vec!["schedule", "workflow:checkout", "workflow:login"]);
+        let filter = HistoryFilter { run: Some("workflow:".to_string()), ..Default::default() };
+        assert_eq!(labels(&history.query(&filter)), vec!["workflow:checkout", "workflow:login"]);
+        let filter = HistoryFilter { success: Some(false), ..Default::default() };
+        assert_eq!(labels(&history.query(&filter)), vec!["workflow:login"]);
+        let filter = HistoryFilter { since_ms: Some(3.0), limit: Some(1), ..Default::default() };
+        assert_eq!(labels(&history.query(&filter)), vec!["schedule"]);
+
+        let feedback = RunFeedback { success: false, note: Some("Ordered the wrong size".to_string()), reported_at_ms: 5.0 };
+        assert!(history.set_feedback("run-3", feedback.clone()));
+        assert!(!history.set_feedback("run-1", feedback.clone()), "Dropped runs are unknown");
+        let filter = HistoryFilter { feedback: Some(true), ..Default::default() };
+        let with_feedback = history.query(&filter);
+        assert_eq!(labels(&with_feedback), vec!["workflow:checkout"]);
+        assert_eq!(with_feedback[0].feedback, Some(feedback));
+        assert_eq!(history.get("run-3").map(|record| record.run), Some("workflow:checkout".to_string()));
+        assert_eq!(history.get("run-1"), None);
+        assert_eq!(history.get(""), None);
+
+        history.set_limit(1);
+        assert_eq!(labels(&history.query(&HistoryFilter::default())), vec!["schedule"]);
+        history.clear();
+        assert!(history.query(&HistoryFilter::default()).is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_history_persists_to_storage() {
+        let storage = Storage::memory();
+        let history = RunHistory::default();
+        history.record(run("automate", true, 1.0));
+        history.enable_persistence(storage.clone()).await.unwrap();
+        history.record(run("schedule", false, 2.0));
+        gloo_timers::future::TimeoutFuture::new(0).await; // Lets the queued writes run
+
+        let reloaded = RunHistory::default();
+        reloaded.record(run("trigger:watch", true, 3.0));
+        reloaded.enable_persistence(storage.clone()).await.unwrap();
+        reloaded.enable_persistence(storage).await.unwrap();
+        let records = reloaded.query(&HistoryFilter::default());
+        assert_eq!(labels(&records), vec!["trigger:watch", "schedule", "automate"]);
+        assert_eq!(records[1], run("schedule", false, 2.0));
+    }
+
+    #[test]
+    fn test_run_ids() {
+        let (first, second) = (new_run_id(1718000000000.0), new_run_id(1718000000000.0));
+        assert!(first.starts_with("run-1718000000000-"));
+        assert_ne!(first, second);
+
+        // Runs persisted before runs had ids still load, and feedback is left out until reported.
+        let json = r#"{"run":"automate","tasks":[],"success":true,"started_at_ms":1.0,"finished_at_ms":2.0,"results":[]}"#;
+        let record: RunRecord = serde_json::from_str(json).unwrap();
+        assert_eq!((record.id.as_str(), &record.feedback), ("", &None));
+        assert!(!serde_json::to_string(&record).unwrap().contains("feedback"));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::agent::AgentError;
+use crate::openapi;
+use crate::secrets::SecretStore;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use wasm_bindgen::{JsCast, JsValue};
+
+/// The definition of a host function, as given to `RustAgent::register_function`, e.g.
+/// `{"description": "Adds a product to the cart", "parameters": {"type": "object",
+/// "properties": {"sku": {"type": "string"}, "quantity": {"type": "integer"}}, "required": ["sku"]}}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FunctionSignature {
+    /// What the function does, shown to the LLM.
+    pub description: String,
+    /// The JSON schema of the arguments: an object schema whose `properties` are the arguments.
+    /// No arguments by default.
+    #[serde(default = "no_parameters")]
+    pub parameters: Value,
+}
+
+fn no_parameters() -> Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+impl FunctionSignature {
+    /// Parses and checks a function definition.
+    ///
+    /// # Returns
+    /// The signature, or an error if the definition is malformed, has no description, or its
+    /// parameters are not an object schema.
+    pub fn parse(definition: &str) -> Result<Self, String> {
+        let signature: FunctionSignature = serde_json::from_str(definition).map_err(|e| format!("Invalid function definition: {}", e))?;
+        if signature.description.trim().is_empty() {
+            return Err("The function definition needs a description, which tells the LLM when to call it".to_string());
+        }
+        if signature.parameters.get("type").and_then(Value::as_str) != Some("object") {
+            return Err("The parameters of a function must be a JSON schema of type 'object'".to_string());
+        }
+        if signature.parameters.get("properties").is_some_and(|properties| !properties.is_object()) {
+            return Err("The 'properties' of the parameters must be an object".to_string());
+        }
+        Ok(signature)
+    }
+
+    /// Parses the arguments of a call, a JSON object, and checks them against the parameters:
+    /// unknown arguments, missing required ones and values not matching their schema are refused.
+    pub fn check_arguments(&self, arguments: Option<&str>) -> Result<Map<String, Value>, String> {
+        let arguments = arguments.map(str::trim).filter(|arguments| !arguments.is_empty()).unwrap_or("{}");
+        let arguments: Map<String, Value> = serde_json::from_str(arguments).map_err(|e| format!("The arguments must be a JSON object: {}", e))?;
+        let properties = self.parameters.get("properties").and_then(Value::as_object);
+        for name in arguments.keys() {
+            if !properties.is_some_and(|properties| properties.contains_key(name)) {
+                let names: Vec<&str> = properties.into_iter().flat_map(|properties| properties.keys().map(String::as_str)).collect();
+                return Err(format!("Unknown argument '{}'; the arguments are: {}", name, names.join(", ")));
+            }
+        }
+        openapi::check_schema(&self.parameters, &Value::Object(arguments.clone()), "arguments")?;
+        Ok(arguments)
+    }
+}
+
+/// A function registered by the host, which `CALL_FUNCTION` calls.
+#[derive(Debug, Clone)]
+pub struct HostFunction {
+    pub signature: FunctionSignature,
+    /// Called as `handler(arguments)` with the checked arguments as an object; returns the result,
+    /// or a `Promise` of it.
+    pub handler: js_sys::Function,
+}
+
+/// The functions registered with `RustAgent::register_function`, by name. None by default.
+#[derive(Debug, Clone, Default)]
+pub struct HostFunctions {
+    functions: BTreeMap<String, HostFunction>,
+}
+
+impl HostFunctions {
+    /// Registers `function` under `name` (a letter, then letters, digits and `_`), replacing any
+    /// function of that name.
+    pub fn register(&mut self, name: &str, function: HostFunction) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(format!("Invalid function name '{}': use a letter, then letters, digits and '_'", name));
+        }
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    /// Forgets the function registered under `name`. Returns `false` if there was none.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.functions.remove(name).is_some()
+    }
+
+    /// The part of the prompt describing `CALL_FUNCTION` and the registered functions, or an
+    /// empty string if none is registered.
+    #[cfg(not(feature = "dom-only"))]
+    pub fn prompt_section(&self) -> String {
+        if self.functions.is_empty() {
+            return String::new();
+        }
+        let tools: Vec<String> = self
+            .functions
+            .iter()
+            .map(|(name, function)| format!("- {}: {} Arguments: {}", name, function.signature.description.trim(), function.signature.parameters))
+            .collect();
+        format!(
+            "The application provides these functions, which can be called like DOM actions with \
+            {{\"action\": \"CALL_FUNCTION\", \"selector\": \"<function_name>\", \"value\": \"<JSON object of the arguments>\"}} \
+            (returns the function's result); the arguments must match the JSON schema given:\n{}\n\n",
+            tools.join("\n")
+        )
+    }
+}
+
+// Whether `name` can name a host function.
+fn is_valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|first| first.is_ascii_alphabetic()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Calls the host function `name` with `arguments`, a JSON object, once they are checked against
+/// its parameters.
+///
+/// # Returns
+/// A message with the result (text as it is, other values as JSON), or
+/// `AgentError::HostFunctionFailed` if the function is unknown, the arguments do not match its
+/// parameters (the handler is then not called), or the handler threw or its `Promise` rejected.
+pub async fn call(functions: &HostFunctions, name: &str, arguments: Option<&str>) -> Result<String, AgentError> {
+    let function = functions.functions.get(name).ok_or_else(|| {
+        let names: Vec<&str> = functions.functions.keys().map(String::as_str).collect();
+        AgentError::HostFunctionFailed(format!("Unknown function '{}'; registered functions: {}", name, names.join(", ")))
+    })?;
+    // Functions receive secrets only if the host gives them, never through their arguments.
+    if arguments.is_some_and(SecretStore::has_placeholder) {
+        return Err(AgentError::PolicyViolation("Secrets cannot be used in CALL_FUNCTION arguments.".to_string()));
+    }
+    let arguments = function.signature.check_arguments(arguments).map_err(|e| AgentError::HostFunctionFailed(format!("{}: {}", name, e)))?;
+
+    let failed = |e: JsValue| {
+        let message = match e.dyn_ref::<js_sys::Error>() {
+            Some(error) => String::from(error.message()),
+            None => e.as_string().unwrap_or_else(|| format!("{:?}", e)),
+        };
+        AgentError::HostFunctionFailed(format!("{} failed: {}", name, message))
+    };
+    let arguments = js_sys::JSON::parse(&Value::Object(arguments).to_string()).map_err(failed)?;
+    let result = function.handler.call1(&JsValue::NULL, &arguments).map_err(failed)?;
+    let result = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&result)).await.map_err(failed)?;
+    let result = match result.as_string() {
+        Some(text) => text,
+        None if result.is_undefined() => String::new(),
+        None => js_sys::JSON::stringify(&result).map(String::from).unwrap_or_else(|_| format!("{:?}", result)),
+    };
+    Ok(format!("Function {} returned: {}", name, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    const ADD_TO_CART: &str = r#"{
+        "description": "Adds a product to the cart.",
+        "parameters": {
+            "type": "object",
+            "properties": {
+                "sku": {"type": "string"},
+                "quantity": {"type": "integer"},
+                "gift": {"type": "object", "properties": {"message": {"type": "string"}}, "required": ["message"]}
+            },
+            "required": ["sku"]
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_signature() {
+        let signature = FunctionSignature::parse(ADD_TO_CART).unwrap();
+        assert_eq!(signature.parameters["required"], serde_json::json!(["sku"]));
+        let no_arguments = FunctionSignature::parse(r#"{"description": "Opens the chat widget."}"#).unwrap();
+        assert_eq!(no_arguments.check_arguments(None), Ok(Map::new()));
+
+        assert!(FunctionSignature::parse(r#"{"description": " "}"#).unwrap_err().contains("needs a description"));
+        assert!(FunctionSignature::parse(r#"{"description": "x", "parameters": {"type": "string"}}"#).is_err());
+        assert!(FunctionSignature::parse(r#"{"description": "x", "params": {}}"#).is_err(), "Unknown fields are refused");
+        assert!(is_valid_name("add_to_cart") && !is_valid_name("2fa") && !is_valid_name("add-to-cart") && !is_valid_name(""));
+    }
+
+    #[test]
+    fn test_check_arguments() {
+        let signature = FunctionSignature::parse(ADD_TO_CART).unwrap();
+        let arguments = signature.check_arguments(Some(r#"{"sku": "A-1", "quantity": 2}"#)).unwrap();
+        assert_eq!(arguments["quantity"], 2);
+
+        assert_eq!(signature.check_arguments(Some(r#"{"quantity": 2}"#)), Err("'arguments' requires property 'sku'".to_string()));
+        assert_eq!(signature.check_arguments(Some(r#"{"sku": "A-1", "quantity": "two"}"#)), Err("'arguments.quantity' must be of type integer, got \"two\"".to_string()));
+        assert_eq!(signature.check_arguments(Some(r#"{"sku": "A-1", "gift": {}}"#)), Err("'arguments.gift' requires property 'message'".to_string()));
+        assert_eq!(signature.check_arguments(Some(r#"{"sku": "A-1", "qty": 2}"#)), Err("Unknown argument 'qty'; the arguments are: gift, quantity, sku".to_string()));
+        assert!(signature.check_arguments(Some("[1]")).unwrap_err().starts_with("The arguments must be a JSON object"));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_call_validates_before_calling_handler() {
+        let handler = js_sys::Function::new_with_args(
+            "args",
+            "globalThis.__cartCalls = (globalThis.__cartCalls || 0) + 1;
+             if (args.sku === 'OUT') throw new Error('Out of stock');
+             return Promise.resolve({ added: args.sku, quantity: args.quantity || 1 });",
+        );
+        let mut functions = HostFunctions::default();
+        let function = HostFunction { signature: FunctionSignature::parse(ADD_TO_CART).unwrap(), handler };
+        assert!(functions.register("add-to-cart", function.clone()).is_err());
+        functions.register("add_to_cart", function).unwrap();
+
+        let result = call(&functions, "add_to_cart", Some(r#"{"sku": "A-1", "quantity": 2}"#)).await.unwrap();
+        assert_eq!(result, r#"Function add_to_cart returned: {"added":"A-1","quantity":2}"#);
+        let calls = || js_sys::Reflect::get(&js_sys::global(), &"__cartCalls".into()).unwrap().as_f64();
+        assert_eq!(calls(), Some(1.0));
+
+        assert!(matches!(call(&functions, "add_to_cart", Some(r#"{"sku": 1}"#)).await, Err(AgentError::HostFunctionFailed(_))));
+        assert!(matches!(call(&functions, "add_to_cart", Some(r#"{"sku": "{{secret.card}}"}"#)).await, Err(AgentError::PolicyViolation(_))));
+        assert!(matches!(call(&functions, "checkout", None).await, Err(AgentError::HostFunctionFailed(_))));
+        assert_eq!(calls(), Some(1.0), "Invalid calls do not reach the handler");
+        match call(&functions, "add_to_cart", Some(r#"{"sku": "OUT"}"#)).await {
+            Err(AgentError::HostFunctionFailed(message)) => assert_eq!(message, "add_to_cart failed: Out of stock"),
+            other => panic!("Expected HostFunctionFailed, got {:?}", other),
+        }
+
+        #[cfg(not(feature = "dom-only"))]
+        assert!(functions.prompt_section().contains(r#"- add_to_cart: Adds a product to the cart. Arguments: {"properties":"#));
+        assert!(functions.unregister("add_to_cart"));
+        assert!(!functions.unregister("add_to_cart"));
+    }
+}
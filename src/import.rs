@@ -0,0 +1,564 @@
+use crate::agent;
+use crate::dom_utils::SCOPE_SEPARATOR;
+use serde::Serialize;
+
+/// The task list imported from a Puppeteer or Playwright script by `import_script`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ImportedScript {
+    /// The tasks, in the order of the calls they were imported from.
+    pub tasks: Vec<String>,
+    /// The calls on the page that could not be imported, in the order they appear.
+    pub skipped: Vec<SkippedCall>,
+}
+
+/// A call on the page of an imported script with no equivalent task.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkippedCall {
+    /// The line of the script the call starts on, from 1.
+    pub line: usize,
+    /// The source of the call, e.g. `page.keyboard.press('Enter')`.
+    pub code: String,
+    /// Why no task was imported from it.
+    pub reason: String,
+}
+
+/// Imports the page interactions of a Puppeteer or Playwright script into a task list, on a
+/// best-effort basis, to help migrate existing automations into the agent.
+///
+/// Calls on the page (`page`, or a variable assigned from `newPage()`) are imported, whether they
+/// take a selector (`page.click('#buy')`, `page.type('#q', 'shoes')`, `page.waitForSelector(...)`)
+/// or act on a locator (`page.locator('#buy').click()`, `page.getByText('Buy').click()`).
+/// Playwright and Puppeteer selectors are converted to the agent's (`text=` / `::-p-text()` to
+/// `text:`, `xpath=` / `::-p-xpath()` / `//...` to `xpath:`, Playwright `>>` chains to scopes).
+///
+/// The script is not run: calls whose arguments are not literals (variables, template strings
+/// with substitutions), locators kept in variables, assertions and other statements are not
+/// followed. Calls on the page that cannot be imported are reported in `skipped`.
+pub fn import_script(script: &str) -> ImportedScript {
+    let tokens = tokenize(script);
+    let pages = page_variables(&tokens);
+    let mut imported = ImportedScript::default();
+    let mut index = 0;
+    while index < tokens.len() {
+        let is_page = matches!(&tokens[index].token, Token::Ident(name) if pages.contains(name))
+            && !(index > 0 && tokens[index - 1].token == Token::Punct('.'))
+            && tokens.get(index + 1).is_some_and(|next| next.token == Token::Punct('.'));
+        if !is_page {
+            index += 1;
+            continue;
+        }
+        let (calls, end) = parse_chain(&tokens, index + 1);
+        let (start, stop) = (tokens[index].start, tokens[end - 1].end);
+        match chain_task(&calls) {
+            Ok(task) => imported.tasks.push(task),
+            Err(reason) => imported.skipped.push(SkippedCall {
+                line: script[..start].matches('\n').count() + 1,
+                code: script[start..stop].split_whitespace().collect::<Vec<_>>().join(" "),
+                reason,
+            }),
+        }
+        index = end;
+    }
+    imported
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    // A string literal, or `None` for a template literal with substitutions.
+    Str(Option<String>),
+    Num(f64),
+    Punct(char),
+}
+
+// A token and the byte range of its source.
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    start: usize,
+    end: usize,
+}
+
+// Splits JavaScript (or TypeScript) source into tokens, skipping comments. Operators are split
+// into single characters and regular expression literals are not recognized, which is enough to
+// find calls and their literal arguments.
+fn tokenize(source: &str) -> Vec<Spanned> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let offset = |index: usize| chars.get(index).map_or(source.len(), |&(offset, _)| offset);
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let (start, c) = chars[index];
+        let next = chars.get(index + 1).map(|&(_, c)| c);
+        if c.is_whitespace() {
+            index += 1;
+            continue;
+        }
+        if c == '/' && next == Some('/') {
+            while index < chars.len() && chars[index].1 != '\n' {
+                index += 1;
+            }
+            continue;
+        }
+        if c == '/' && next == Some('*') {
+            index += 2;
+            while index < chars.len() && !(chars[index].1 == '*' && chars.get(index + 1).map(|&(_, c)| c) == Some('/')) {
+                index += 1;
+            }
+            index += 2;
+            continue;
+        }
+        let token = if matches!(c, '\'' | '"' | '`') {
+            let (text, end) = string_literal(&chars, index);
+            index = end;
+            Token::Str(text)
+        } else if c.is_ascii_digit() {
+            let end = (index..chars.len()).find(|&i| !(chars[i].1.is_ascii_alphanumeric() || matches!(chars[i].1, '.' | '_'))).unwrap_or(chars.len());
+            let number: String = chars[index..end].iter().map(|&(_, c)| c).filter(|&c| c != '_').collect();
+            index = end;
+            match number.parse() {
+                Ok(number) => Token::Num(number),
+                Err(_) => Token::Ident(number),
+            }
+        } else if c.is_alphabetic() || matches!(c, '_' | '$') {
+            let end = (index..chars.len()).find(|&i| !(chars[i].1.is_alphanumeric() || matches!(chars[i].1, '_' | '$'))).unwrap_or(chars.len());
+            let name: String = chars[index..end].iter().map(|&(_, c)| c).collect();
+            index = end;
+            Token::Ident(name)
+        } else {
+            index += 1;
+            Token::Punct(c)
+        };
+        tokens.push(Spanned { token, start, end: offset(index) });
+    }
+    tokens
+}
+
+// Reads the string or template literal starting at `chars[start]`. Returns its value, `None` for
+// a template literal with substitutions, and the index past its closing quote.
+fn string_literal(chars: &[(usize, char)], start: usize) -> (Option<String>, usize) {
+    let quote = chars[start].1;
+    let (mut text, mut literal) = (String::new(), true);
+    let mut index = start + 1;
+    while index < chars.len() {
+        let c = chars[index].1;
+        index += 1;
+        match c {
+            _ if c == quote => return (literal.then_some(text), index),
+            '\\' if index < chars.len() => {
+                let escaped = chars[index].1;
+                index += 1;
+                match escaped {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'r' => text.push('\r'),
+                    '\n' => {} // A line continuation
+                    'u' => {
+                        let hex: String = chars[index..].iter().take(4).map(|&(_, c)| c).collect();
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) if hex.len() == 4 => {
+                                text.push(c);
+                                index += 4;
+                            }
+                            _ => literal = false,
+                        }
+                    }
+                    _ => text.push(escaped),
+                }
+            }
+            '$' if quote == '`' && chars.get(index).map(|&(_, c)| c) == Some('{') => {
+                literal = false;
+                // Skips the substitution, which may contain braces of its own.
+                let mut depth = 0;
+                while index < chars.len() {
+                    match chars[index].1 {
+                        '{' => depth += 1,
+                        '}' if depth == 1 => break,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    index += 1;
+                }
+                index += 1;
+            }
+            _ => text.push(c),
+        }
+    }
+    (None, index)
+}
+
+// The names of the variables holding a page: `page`, and those assigned from `newPage()`, as in
+// `const tab = await browser.newPage();`.
+fn page_variables(tokens: &[Spanned]) -> Vec<String> {
+    let mut pages = vec!["page".to_string()];
+    for (index, spanned) in tokens.iter().enumerate() {
+        let Token::Ident(name) = &spanned.token else { continue };
+        let mut rest = tokens[index + 1..].iter().map(|spanned| &spanned.token);
+        if rest.next() != Some(&Token::Punct('=')) {
+            continue;
+        }
+        let mut next = rest.next();
+        if next == Some(&Token::Ident("await".to_string())) {
+            next = rest.next();
+        }
+        if matches!(next, Some(Token::Ident(_)))
+            && rest.next() == Some(&Token::Punct('.'))
+            && rest.next() == Some(&Token::Ident("newPage".to_string()))
+        {
+            pages.push(name.clone());
+        }
+    }
+    pages
+}
+
+// A method call or property access in a chain on the page, e.g. `click('#buy')` or `keyboard`.
+#[derive(Debug)]
+struct Call {
+    name: String,
+    // The arguments, each a list of tokens; `None` for a property access.
+    args: Option<Vec<Vec<Token>>>,
+}
+
+impl Call {
+    fn arg(&self, index: usize) -> Option<&[Token]> {
+        self.args.as_ref()?.get(index).map(Vec::as_slice)
+    }
+
+    fn arg_count(&self) -> usize {
+        self.args.as_ref().map_or(0, Vec::len)
+    }
+
+    // The string literal passed as argument `index`.
+    fn string(&self, index: usize) -> Result<String, String> {
+        match self.arg(index) {
+            Some([Token::Str(Some(text))]) => Ok(text.clone()),
+            Some(_) => Err(format!("argument {} of {}() is not a string literal", index + 1, self.name)),
+            None => Err(format!("{}() is missing argument {}", self.name, index + 1)),
+        }
+    }
+
+    // The number given for `key` in the options object passed as argument `index`, e.g. the
+    // `timeout` of `{ timeout: 5000 }`.
+    fn option(&self, index: usize, key: &str) -> Option<&Token> {
+        let tokens = self.arg(index)?;
+        let position = tokens.windows(2).position(|pair| pair[0] == Token::Ident(key.to_string()) && pair[1] == Token::Punct(':'))?;
+        tokens.get(position + 2)
+    }
+}
+
+// Parses the calls and property accesses of the chain starting with the `.` at `tokens[start]`,
+// up to the first token that does not continue it. Returns them and the index of that token.
+fn parse_chain(tokens: &[Spanned], start: usize) -> (Vec<Call>, usize) {
+    let mut calls = Vec::new();
+    let mut index = start;
+    while let (Some(Token::Punct('.')), Some(Token::Ident(name))) =
+        (tokens.get(index).map(|spanned| &spanned.token), tokens.get(index + 1).map(|spanned| &spanned.token))
+    {
+        index += 2;
+        let mut call = Call { name: name.clone(), args: None };
+        if tokens.get(index).is_some_and(|spanned| spanned.token == Token::Punct('(')) {
+            let (args, end) = parse_args(tokens, index + 1);
+            call.args = Some(args);
+            index = end;
+        }
+        calls.push(call);
+    }
+    (calls, index)
+}
+
+// Splits the arguments of a call at top-level commas, from `tokens[start]` up to the closing
+// parenthesis. Returns them and the index past the parenthesis.
+fn parse_args(tokens: &[Spanned], start: usize) -> (Vec<Vec<Token>>, usize) {
+    let (mut args, mut current, mut depth) = (Vec::new(), Vec::new(), 0);
+    let mut index = start;
+    while let Some(spanned) = tokens.get(index) {
+        index += 1;
+        match spanned.token {
+            Token::Punct(')' | ']' | '}') if depth == 0 => break,
+            Token::Punct(',') if depth == 0 => {
+                args.push(std::mem::take(&mut current));
+                continue;
+            }
+            Token::Punct('(' | '[' | '{') => depth += 1,
+            Token::Punct(')' | ']' | '}') => depth -= 1,
+            _ => {}
+        }
+        current.push(spanned.token.clone());
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    (args, index)
+}
+
+// The task equivalent to a chain of calls on the page, or why there is none.
+fn chain_task(calls: &[Call]) -> Result<String, String> {
+    // Locator builders narrow down the element the final call acts on.
+    let mut scope: Vec<String> = Vec::new();
+    let mut calls = calls.iter();
+    let action = loop {
+        let Some(call) = calls.next() else {
+            return Err("locator without an action (e.g. in an assertion)".to_string());
+        };
+        match call.name.as_str() {
+            "locator" => scope.push(convert_selector(&call.string(0)?)?),
+            "getByText" => scope.push(format!("text:{}", call.string(0)?)),
+            "getByTestId" => scope.push(format!("css:[data-testid={}]", css_string(&call.string(0)?))),
+            "getByPlaceholder" => scope.push(format!("css:[placeholder={}]", css_string(&call.string(0)?))),
+            // Commands act on the first match.
+            "first" => {}
+            _ if call.args.is_none() => return Err(format!("page.{} is not imported", call.name)),
+            _ => break call,
+        }
+    };
+    let on_locator = !scope.is_empty();
+    let command = match action.name.as_str() {
+        "goto" if !on_locator => "NAVIGATE",
+        "url" if !on_locator => "GET_URL",
+        "click" => "CLICK",
+        "fill" | "type" | "pressSequentially" => "TYPE",
+        "hover" => "HOVER",
+        "waitForSelector" | "waitFor" => "WAIT_FOR_ELEMENT",
+        "waitForXPath" if !on_locator => "WAIT_FOR_ELEMENT",
+        "select" | "selectOption" => "SELECTOPTION",
+        "textContent" | "innerText" => "READ",
+        "inputValue" => "GETVALUE",
+        "getAttribute" => "GETATTRIBUTE",
+        "isVisible" => "IS_VISIBLE",
+        "isEnabled" => "IS_ENABLED",
+        "isChecked" => "IS_SELECTED",
+        "scrollIntoViewIfNeeded" => "SCROLL_TO",
+        name if on_locator => return Err(format!("{}() on a locator has no equivalent task", name)),
+        name => return Err(format!("page.{}() has no equivalent task", name)),
+    };
+    if calls.next().is_some() {
+        return Err(format!("the result of {}() is used in an expression", action.name));
+    }
+
+    // Page-level calls take the selector as their first argument, locator calls act on the locator.
+    let first_arg = usize::from(!on_locator);
+    let selector = match (command, action.name.as_str()) {
+        ("NAVIGATE" | "GET_URL", _) => String::new(),
+        (_, "waitForXPath") => format!("xpath:{}", action.string(0)?.trim()),
+        _ if on_locator => scope.join(SCOPE_SEPARATOR),
+        _ => convert_selector(&action.string(0)?)?,
+    };
+    let value = match command {
+        "NAVIGATE" => Some(action.string(0)?),
+        "TYPE" => {
+            let text = action.string(first_arg)?;
+            if text.is_empty() {
+                return Err(format!("{}() with empty text has no equivalent task", action.name));
+            }
+            Some(text)
+        }
+        "SELECTOPTION" if action.arg_count() > first_arg + 1 => {
+            return Err(format!("{}() with several values has no equivalent task", action.name));
+        }
+        "SELECTOPTION" | "GETATTRIBUTE" => Some(action.string(first_arg)?),
+        "WAIT_FOR_ELEMENT" => {
+            let hidden = match action.option(first_arg, "state") {
+                Some(Token::Str(Some(state))) => state == "hidden" || state == "detached",
+                _ => action.option(first_arg, "hidden") == Some(&Token::Ident("true".to_string())),
+            };
+            if hidden {
+                return Err("waiting for an element to disappear has no equivalent task".to_string());
+            }
+            match action.option(first_arg, "timeout") {
+                Some(&Token::Num(timeout_ms)) if timeout_ms >= 0.0 && timeout_ms.fract() == 0.0 => Some(timeout_ms.to_string()),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    let task = [command, &selector, value.as_deref().unwrap_or_default()]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // Selectors with spaces are only accepted by commands that take nothing after them.
+    match agent::parse_dom_command(&task) {
+        Some(dom_command) if dom_command.selector == selector => Ok(task),
+        _ if selector.contains(char::is_whitespace) => {
+            Err(format!("the selector has spaces, which {} (from {}()) does not accept before its value", command, action.name))
+        }
+        _ => Err(format!("{}() has no equivalent task with these arguments", action.name)),
+    }
+}
+
+// Converts a Playwright or Puppeteer selector to the agent's syntax, or tells why it can't.
+fn convert_selector(selector: &str) -> Result<String, String> {
+    let selector = selector.trim();
+    if selector.contains(">>>") {
+        return Err(format!("shadow DOM piercing selector '{}' is not supported", selector));
+    }
+    // Playwright chains search each selector within the match of the previous one, like scopes.
+    if selector.contains(">>") {
+        let parts = selector.split(">>").map(convert_selector).collect::<Result<Vec<_>, _>>()?;
+        return Ok(parts.join(SCOPE_SEPARATOR));
+    }
+    let unquote = |text: &str| {
+        ['"', '\''].iter().find_map(|&quote| text.strip_prefix(quote).and_then(|text| text.strip_suffix(quote))).unwrap_or(text).to_string()
+    };
+    let pseudo = |name: &str| selector.strip_prefix(name).and_then(|rest| rest.strip_suffix(')')).map(unquote);
+    if let Some(xpath) = selector.strip_prefix("xpath=").or_else(|| selector.strip_prefix("xpath/")) {
+        return Ok(format!("xpath:{}", xpath));
+    }
+    if let Some(xpath) = pseudo("::-p-xpath(") {
+        return Ok(format!("xpath:{}", xpath));
+    }
+    if selector.starts_with("//") || selector.starts_with("(//") || selector.starts_with("..") {
+        return Ok(format!("xpath:{}", selector));
+    }
+    if let Some(text) = selector.strip_prefix("text=").or_else(|| selector.strip_prefix("text/")) {
+        return Ok(format!("text:{}", unquote(text)));
+    }
+    if let Some(text) = pseudo("::-p-text(") {
+        return Ok(format!("text:{}", text));
+    }
+    if selector.starts_with('"') || selector.starts_with('\'') {
+        return Ok(format!("text:{}", unquote(selector)));
+    }
+    if let Some(css) = selector.strip_prefix("css=") {
+        return Ok(format!("css:{}", css));
+    }
+    if let Some(id) = selector.strip_prefix("id=") {
+        return Ok(format!("css:[id={}]", css_string(&unquote(id))));
+    }
+    for attribute in ["data-testid", "data-test-id", "data-test"] {
+        if let Some(value) = selector.strip_prefix(attribute).and_then(|rest| rest.strip_prefix('=')) {
+            return Ok(format!("css:[{}={}]", attribute, css_string(&unquote(value))));
+        }
+    }
+    // Other selector engines (`role=`, `aria/`, `::-p-aria()`, ...) and Playwright's CSS extensions.
+    let engine = selector.split_once(['=', '/']).map(|(engine, _)| engine).filter(|engine| {
+        !engine.is_empty() && engine.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':'))
+    });
+    let extensions = [":has-text(", ":text(", ":text-is(", ":text-matches(", ":visible", ":nth-match(", "::-p-"];
+    if engine.is_some() || extensions.iter().any(|extension| selector.contains(extension)) {
+        return Err(format!("selector '{}' uses a selector engine or CSS extension that is not supported", selector));
+    }
+    Ok(format!("css:{}", selector))
+}
+
+// A CSS string, e.g. for an attribute selector.
+fn css_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_puppeteer_script() {
+        let imported = import_script(
+            r#"const puppeteer = require('puppeteer');
+(async () => {
+  const browser = await puppeteer.launch();
+  const tab = await browser.newPage();
+  await tab.goto('https://example.com/login'); // Sign in
+  await tab.type('#email', "user@example.com");
+  await tab.click('::-p-text(Sign in)');
+  /* await tab.click('#ignored'); */
+  await tab.waitForSelector('::-p-xpath(//div[@id="welcome"])', { timeout: 3000 });
+  await tab.select('select#plan', 'pro');
+  await tab.keyboard.press('Enter');
+  await tab.type('#name', `${process.env.NAME}`);
+  await browser.close();
+})();"#,
+        );
+        assert_eq!(
+            imported.tasks,
+            vec![
+                "NAVIGATE https://example.com/login",
+                "TYPE css:#email user@example.com",
+                "CLICK text:Sign in",
+                "WAIT_FOR_ELEMENT xpath://div[@id=\"welcome\"] 3000",
+                "SELECTOPTION css:select#plan pro",
+            ]
+        );
+        assert_eq!(imported.skipped.len(), 2);
+        assert_eq!(imported.skipped[0], SkippedCall {
+            line: 11,
+            code: "tab.keyboard.press('Enter')".to_string(),
+            reason: "page.keyboard is not imported".to_string(),
+        });
+        assert_eq!(imported.skipped[1].line, 12);
+        assert_eq!(imported.skipped[1].reason, "argument 2 of type() is not a string literal");
+    }
+
+    #[test]
+    fn test_import_playwright_script() {
+        let imported = import_script(
+            r#"import { test, expect } from '@playwright/test';
+
+test('checkout', async ({ page }) => {
+  await page.goto('https://shop.example.com');
+  await page.getByPlaceholder('Search').fill('shoes');
+  await page.locator('.results >> text="Running shoes"').first().click();
+  await page.fill('form input[name=qty]', '2');
+  await page.getByTestId('add-to-cart').click();
+  await page.locator('#cart').waitFor({ state: 'visible', timeout: 2000 });
+  const total = await page.textContent('#total');
+  await expect(page.locator('#cart')).toBeVisible();
+  await page.getByRole('button', { name: 'Pay' }).click();
+});"#,
+        );
+        assert_eq!(
+            imported.tasks,
+            vec![
+                "NAVIGATE https://shop.example.com",
+                "TYPE css:[placeholder=\"Search\"] shoes",
+                "CLICK css:.results >> text:Running shoes",
+                "CLICK css:[data-testid=\"add-to-cart\"]",
+                "WAIT_FOR_ELEMENT css:#cart 2000",
+                "READ css:#total",
+            ]
+        );
+        let reasons: Vec<&str> = imported.skipped.iter().map(|skipped| skipped.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "the selector has spaces, which TYPE (from fill()) does not accept before its value",
+                "locator without an action (e.g. in an assertion)",
+                "page.getByRole() has no equivalent task",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert_selector() {
+        assert_eq!(convert_selector("#buy").unwrap(), "css:#buy");
+        assert_eq!(convert_selector("css=.cart button").unwrap(), "css:.cart button");
+        assert_eq!(convert_selector("xpath=//a[1]").unwrap(), "xpath://a[1]");
+        assert_eq!(convert_selector("(//a)[2]").unwrap(), "xpath:(//a)[2]");
+        assert_eq!(convert_selector("text='Buy now'").unwrap(), "text:Buy now");
+        assert_eq!(convert_selector("::-p-xpath(//button)").unwrap(), "xpath://button");
+        assert_eq!(convert_selector("id=main").unwrap(), "css:[id=\"main\"]");
+        assert_eq!(convert_selector("#list >> li").unwrap(), "css:#list >> css:li");
+        assert_eq!(convert_selector("a[href=\"/x\"]").unwrap(), "css:a[href=\"/x\"]");
+        assert!(convert_selector("role=button").is_err());
+        assert!(convert_selector("aria/Submit").is_err());
+        assert!(convert_selector("button:has-text('Buy')").is_err());
+        assert!(convert_selector("my-app >>> button").is_err());
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let tokens = tokenize(r#"'it\'s' "a\nb" `plain` `x${y}z` 'é'"#);
+        let strings: Vec<Token> = tokens.into_iter().map(|spanned| spanned.token).collect();
+        assert_eq!(
+            strings,
+            vec![
+                Token::Str(Some("it's".to_string())),
+                Token::Str(Some("a\nb".to_string())),
+                Token::Str(Some("plain".to_string())),
+                Token::Str(None),
+                Token::Str(Some("é".to_string())),
+            ]
+        );
+    }
+}
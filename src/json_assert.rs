@@ -0,0 +1,260 @@
+use serde_json::Value;
+
+/// Differences listed in the error of a failed `ASSERT_JSON`; the rest are only counted.
+pub const MAX_LISTED_DIFFERENCES: usize = 20;
+/// Longest JSON text shown for a value in a difference, in characters.
+const MAX_SHOWN_VALUE_CHARS: usize = 80;
+
+/// The arguments of `ASSERT_JSON`: the value checked, the value expected and the part of the
+/// checked value compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonAssertion {
+    pub actual: Value,
+    pub expected: Value,
+    /// A JSON path such as `$.items[0].price`; `$`, the whole value, by default.
+    pub path: String,
+}
+
+/// A step of a JSON path: an object member or an array item.
+#[derive(Debug, Clone, PartialEq)]
+enum PathStep {
+    Member(String),
+    Item(usize),
+}
+
+/// Parses the value of `ASSERT_JSON`, `"<actual_json> <expected_json> [json_path]"`, once its
+/// placeholders have been substituted. The actual value may follow a label ending with `: `, as in
+/// the results of commands such as `EXTRACT_STRUCTURED_DATA` (`Structured data: {...}`), so that
+/// `{{PREVIOUS_RESULT}}` can stand for it. The path may use `.name`, `["name"]` and `[index]`
+/// steps after `$`.
+///
+/// # Returns
+/// The assertion, or an error saying which argument is malformed.
+pub fn parse_value(value: &str) -> Result<JsonAssertion, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("ASSERT_JSON requires the actual JSON, the expected JSON and optionally a JSON path".to_string());
+    }
+    // Where the actual value may start: at the beginning, or after a label.
+    let starts = std::iter::once(0).chain(value.match_indices(": ").map(|(index, separator)| index + separator.len()));
+    let mut first_error = None;
+    for start in starts {
+        let Some((actual, rest)) = leading_json(&value[start..]) else {
+            continue;
+        };
+        let result = leading_json(rest)
+            .ok_or_else(|| format!("ASSERT_JSON expected value is not valid JSON: '{}'", brief_text(rest.trim())))
+            .and_then(|(expected, rest)| {
+                let path = if rest.trim().is_empty() { "$" } else { rest.trim() };
+                parse_path(path)?;
+                Ok(JsonAssertion { actual, expected, path: path.to_string() })
+            });
+        match result {
+            Ok(assertion) => return Ok(assertion),
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| format!("ASSERT_JSON actual value is not valid JSON: '{}'", brief_text(value))))
+}
+
+impl JsonAssertion {
+    /// Compares the value at the path with the expected one. Objects match if they have the same
+    /// members, in any order, and arrays if they have the same items in the same order; numbers
+    /// are compared by value, so `1` matches `1.0`.
+    ///
+    /// # Returns
+    /// The differences, each as `<path>: <difference>`, or none if the values match.
+    pub fn differences(&self) -> Vec<String> {
+        let steps = parse_path(&self.path).unwrap_or_default();
+        let mut actual = &self.actual;
+        let mut path = "$".to_string();
+        for step in &steps {
+            let next = match step {
+                PathStep::Member(name) => actual.get(name.as_str()),
+                PathStep::Item(index) => actual.get(*index),
+            };
+            path.push_str(&step_text(step));
+            match next {
+                Some(next) => actual = next,
+                None => return vec![format!("{}: missing, expected {}", path, brief(&self.expected))],
+            }
+        }
+        let mut differences = Vec::new();
+        compare(&self.expected, actual, &path, &mut differences);
+        differences
+    }
+}
+
+/// Describes the differences found by `JsonAssertion::differences` for the error of `ASSERT_JSON`,
+/// one per line, listing at most `MAX_LISTED_DIFFERENCES`.
+pub fn describe_differences(path: &str, differences: &[String]) -> String {
+    let mut description = format!(
+        "JSON at {} does not match the expected value ({} difference{}):",
+        path,
+        differences.len(),
+        if differences.len() == 1 { "" } else { "s" }
+    );
+    for difference in differences.iter().take(MAX_LISTED_DIFFERENCES) {
+        description.push_str("\n  ");
+        description.push_str(difference);
+    }
+    if differences.len() > MAX_LISTED_DIFFERENCES {
+        description.push_str(&format!("\n  ... and {} more", differences.len() - MAX_LISTED_DIFFERENCES));
+    }
+    description
+}
+
+// Parses the JSON value `text` starts with, returning it and the text after it.
+fn leading_json(text: &str) -> Option<(Value, &str)> {
+    let mut values = serde_json::Deserializer::from_str(text).into_iter::<Value>();
+    let value = values.next()?.ok()?;
+    Some((value, &text[values.byte_offset()..]))
+}
+
+// Parses a JSON path, `$` followed by `.name`, `["name"]` and `[index]` steps.
+fn parse_path(path: &str) -> Result<Vec<PathStep>, String> {
+    let invalid = || format!("ASSERT_JSON path '{}' is not a JSON path such as $.items[0].name", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            if end == 0 {
+                return Err(invalid());
+            }
+            steps.push(PathStep::Member(after_dot[..end].to_string()));
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            if after_bracket.starts_with('"') {
+                let (name, after_name) = leading_json(after_bracket).ok_or_else(invalid)?;
+                let name = name.as_str().ok_or_else(invalid)?.to_string();
+                rest = after_name.strip_prefix(']').ok_or_else(invalid)?;
+                steps.push(PathStep::Member(name));
+            } else {
+                let (index, after_index) = after_bracket.split_once(']').ok_or_else(invalid)?;
+                steps.push(PathStep::Item(index.trim().parse().map_err(|_| invalid())?));
+                rest = after_index;
+            }
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(steps)
+}
+
+// The text of a path step, as written after the path of its parent.
+fn step_text(step: &PathStep) -> String {
+    match step {
+        PathStep::Member(name) if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') => format!(".{}", name),
+        PathStep::Member(name) => format!("[{}]", Value::String(name.clone())),
+        PathStep::Item(index) => format!("[{}]", index),
+    }
+}
+
+// Adds the differences between `expected` and `actual`, the values at `path`, to `differences`.
+fn compare(expected: &Value, actual: &Value, path: &str, differences: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (name, expected_member) in expected {
+                let member_path = format!("{}{}", path, step_text(&PathStep::Member(name.clone())));
+                match actual.get(name) {
+                    Some(actual_member) => compare(expected_member, actual_member, &member_path, differences),
+                    None => differences.push(format!("{}: missing, expected {}", member_path, brief(expected_member))),
+                }
+            }
+            for (name, actual_member) in actual.iter().filter(|(name, _)| !expected.contains_key(*name)) {
+                differences.push(format!("{}{}: unexpected {}", path, step_text(&PathStep::Member(name.clone())), brief(actual_member)));
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) => {
+            for (index, (expected_item, actual_item)) in expected.iter().zip(actual).enumerate() {
+                compare(expected_item, actual_item, &format!("{}[{}]", path, index), differences);
+            }
+            if expected.len() != actual.len() {
+                differences.push(format!("{}: expected {} items, got {}", path, expected.len(), actual.len()));
+            }
+            for (index, item) in expected.iter().enumerate().skip(actual.len()) {
+                differences.push(format!("{}[{}]: missing, expected {}", path, index, brief(item)));
+            }
+            for (index, item) in actual.iter().enumerate().skip(expected.len()) {
+                differences.push(format!("{}[{}]: unexpected {}", path, index, brief(item)));
+            }
+        }
+        (Value::Number(expected), Value::Number(actual)) if expected.as_f64() == actual.as_f64() => {}
+        (expected, actual) if expected == actual => {}
+        (expected, actual) => differences.push(format!("{}: expected {}, got {}", path, brief(expected), brief(actual))),
+    }
+}
+
+// The JSON text of `value`, cut to `MAX_SHOWN_VALUE_CHARS`.
+fn brief(value: &Value) -> String {
+    brief_text(&value.to_string())
+}
+
+// `text`, cut to `MAX_SHOWN_VALUE_CHARS`.
+fn brief_text(text: &str) -> String {
+    match text.char_indices().nth(MAX_SHOWN_VALUE_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_value() {
+        let assertion = parse_value(r#"{"name": "Lamp", "price": 20} {"name": "Lamp"} $.name"#).unwrap();
+        assert_eq!(assertion.actual, json!({"name": "Lamp", "price": 20}));
+        assert_eq!(assertion.expected, json!({"name": "Lamp"}));
+        assert_eq!(assertion.path, "$.name");
+        assert_eq!(parse_value("[1, 2] [1, 2]").unwrap().path, "$");
+
+        let labelled = parse_value(r#"Structured data: {"items": [{"@type": "Product"}]} "Product" $.items[0]["@type"]"#).unwrap();
+        assert_eq!(labelled.actual, json!({"items": [{"@type": "Product"}]}));
+        assert!(labelled.differences().is_empty());
+
+        assert!(parse_value("").is_err());
+        assert!(parse_value("Nothing found").unwrap_err().contains("actual value is not valid JSON"));
+        assert!(parse_value(r#"{"a": 1} {a: 1}"#).unwrap_err().contains("expected value is not valid JSON"));
+        assert!(parse_value(r#"{"a": 1} {"a": 1} a.b"#).unwrap_err().contains("is not a JSON path"));
+        assert!(parse_value(r#"{"a": 1} {"a": 1} $..a"#).is_err());
+    }
+
+    #[test]
+    fn test_differences() {
+        let assertion = JsonAssertion {
+            actual: json!({"id": 7, "total": 12.0, "items": [{"sku": "A", "qty": 2}], "coupon": null}),
+            expected: json!({"id": 7, "total": 12, "items": [{"sku": "A", "qty": 1}, {"sku": "B", "qty": 1}], "status": "paid"}),
+            path: "$".to_string(),
+        };
+        assert_eq!(
+            assertion.differences(),
+            vec![
+                "$.items[0].qty: expected 1, got 2",
+                "$.items: expected 2 items, got 1",
+                r#"$.items[1]: missing, expected {"qty":1,"sku":"B"}"#,
+                r#"$.status: missing, expected "paid""#,
+                "$.coupon: unexpected null",
+            ]
+        );
+
+        let at_path = JsonAssertion { path: "$.items[0].sku".to_string(), expected: json!("B"), ..assertion.clone() };
+        assert_eq!(at_path.differences(), vec![r#"$.items[0].sku: expected "B", got "A""#]);
+        let missing = JsonAssertion { path: r#"$["order id"]"#.to_string(), expected: json!(7), ..assertion };
+        assert_eq!(missing.differences(), vec![r#"$["order id"]: missing, expected 7"#]);
+    }
+
+    #[test]
+    fn test_describe_differences() {
+        let differences: Vec<String> = (0..25).map(|index| format!("$[{}]: expected 0, got 1", index)).collect();
+        let description = describe_differences("$", &differences);
+        assert!(description.starts_with("JSON at $ does not match the expected value (25 differences):\n  $[0]: expected 0, got 1"));
+        assert!(description.ends_with("\n  ... and 5 more"));
+        assert_eq!(description.lines().count(), 2 + MAX_LISTED_DIFFERENCES);
+    }
+}
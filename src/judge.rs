@@ -0,0 +1,159 @@
+use web_sys::console;
+use crate::agent::{AgentError, ExecutionConfig, LlmEndpoint};
+use crate::audit;
+use crate::budget; // LLM call budget shared by every RustAgent on the page
+use crate::dom_utils;
+use crate::redact::RedactionMap;
+use crate::run_limits::RunAction;
+
+/// Page content sent with a question is cut to this many characters.
+const MAX_CONTENT_CHARS: usize = 8000;
+
+/// What a judgment does with the LLM's answer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JudgeMode {
+    /// `LLM_DECIDE`: reports the answer as `true` or `false`.
+    Decide,
+    /// `LLM_ASSERT`: fails unless the answer is yes.
+    Assert,
+}
+
+/// An `LLM_DECIDE <selector> <question>` or `LLM_ASSERT <selector> <question>` task: a yes/no
+/// question about the text of an element, answered by the LLM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Judgment<'a> {
+    pub mode: JudgeMode,
+    /// The element whose text is sent with the question, e.g. `css:#order-status` or `css:body`.
+    pub selector: &'a str,
+    pub question: &'a str,
+}
+
+/// Parses an `LLM_DECIDE` or `LLM_ASSERT` task. Keywords are matched case-insensitively.
+///
+/// # Returns
+/// * `None` if `task` is neither.
+/// * `Some(Ok(Judgment))` with the parsed task.
+/// * `Some(Err(String))` if the selector or the question is missing.
+pub fn parse_command(task: &str) -> Option<Result<Judgment<'_>, String>> {
+    let task = task.trim();
+    let (keyword, args) = task.split_once(char::is_whitespace).unwrap_or((task, ""));
+    let mode = match keyword.to_uppercase().as_str() {
+        "LLM_DECIDE" => JudgeMode::Decide,
+        "LLM_ASSERT" => JudgeMode::Assert,
+        _ => return None,
+    };
+    let (selector, question) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+    let question = question.trim();
+    if selector.is_empty() || question.is_empty() {
+        return Some(Err(format!("{} requires a selector and a question, e.g. {} css:#status Is the order confirmed?", keyword, keyword)));
+    }
+    Some(Ok(Judgment { mode, selector, question }))
+}
+
+/// Builds the prompt asking the LLM `question` about `content`.
+pub fn judgment_prompt(question: &str, content: &str) -> String {
+    format!(
+        "You are checking a condition on a web page. Here is the relevant text of the page:\n\
+        ---\n{}\n---\n\
+        Question: {}\n\
+        Answer with only \"yes\" or \"no\".",
+        content, question
+    )
+}
+
+/// Reads the LLM's answer as a boolean: its first word is `yes` / `true` or `no` / `false`,
+/// ignoring case, quotes and punctuation.
+pub fn coerce_answer(answer: &str) -> Option<bool> {
+    let first_word = answer.split(|c: char| !c.is_alphanumeric()).find(|word| !word.is_empty())?;
+    match first_word.to_lowercase().as_str() {
+        "yes" | "true" => Some(true),
+        "no" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+// Cuts `text` to `MAX_CONTENT_CHARS` characters.
+fn truncate_content(text: &str) -> &str {
+    match text.char_indices().nth(MAX_CONTENT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Asks the LLM the question of `judgment` about the text of its element. The text is redacted
+/// if redaction is enabled, and the call draws from the shared LLM budget and is logged in the
+/// audit log.
+///
+/// # Returns
+/// * `Ok(String)` ending with `true` or `false` for `LLM_DECIDE`, and with `true` for a held `LLM_ASSERT`.
+/// * `Err(AgentError::AssertionFailed)` if the answer to an `LLM_ASSERT` is no.
+/// * `Err(AgentError)` if the element cannot be read, the LLM call fails, or the answer is neither yes nor no.
+pub async fn judge(
+    judgment: &Judgment<'_>,
+    api_key: &str,
+    api_url: &str,
+    model_name: &str,
+    config: &ExecutionConfig,
+) -> Result<String, AgentError> {
+    let text = dom_utils::get_element_text(judgment.selector)?;
+    let mut redactions = RedactionMap::default();
+    let content = if config.redaction.is_enabled() {
+        config.redaction.redact(truncate_content(&text), &mut redactions)?
+    } else {
+        truncate_content(&text).to_string()
+    };
+    let prompt = judgment_prompt(judgment.question, &content);
+    config.run_limits.charge(RunAction::LlmCall).map_err(AgentError::RunLimitExceeded)?;
+    budget::acquire(prompt.len()).await.map_err(AgentError::BudgetExceeded)?;
+    let llm = LlmEndpoint { api_key, api_url, model_name };
+    let answer = audit::complete("judgment", prompt, llm, config).await.map_err(AgentError::from)?;
+    budget::record_response(answer.len());
+    console::log_1(&format!("LLM answer to '{}': {}", judgment.question, answer).into());
+
+    let holds = coerce_answer(&answer).ok_or_else(|| {
+        AgentError::InvalidLlmResponse(format!("The answer to '{}' was neither yes nor no: {}", judgment.question, answer))
+    })?;
+    match judgment.mode {
+        JudgeMode::Decide => Ok(format!("LLM decision on '{}': {}", judgment.question, holds)),
+        JudgeMode::Assert if holds => Ok(format!("LLM assertion '{}' holds: true", judgment.question)),
+        JudgeMode::Assert => Err(AgentError::AssertionFailed(format!(
+            "The LLM judged '{}' false for element '{}': {}",
+            judgment.question, judgment.selector, answer.trim()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command("LLM_DECIDE css:#status Is the order confirmed?"),
+            Some(Ok(Judgment { mode: JudgeMode::Decide, selector: "css:#status", question: "Is the order confirmed?" }))
+        );
+        assert!(matches!(parse_command("llm_assert css:body Is this the cart?"), Some(Ok(Judgment { mode: JudgeMode::Assert, .. }))));
+        assert!(matches!(parse_command("LLM_DECIDE css:#status"), Some(Err(_))));
+        assert!(matches!(parse_command("LLM_ASSERT"), Some(Err(_))));
+        assert_eq!(parse_command("READ css:#status"), None);
+    }
+
+    #[test]
+    fn test_coerce_answer() {
+        assert_eq!(coerce_answer("Yes."), Some(true));
+        assert_eq!(coerce_answer("\"no\""), Some(false));
+        assert_eq!(coerce_answer("  TRUE, the order is confirmed"), Some(true));
+        assert_eq!(coerce_answer("false"), Some(false));
+        assert_eq!(coerce_answer("Not sure"), None);
+        assert_eq!(coerce_answer(""), None);
+    }
+
+    #[test]
+    fn test_judgment_prompt_truncates_content() {
+        let long_text = "é".repeat(MAX_CONTENT_CHARS + 10);
+        assert_eq!(truncate_content(&long_text).chars().count(), MAX_CONTENT_CHARS);
+        let prompt = judgment_prompt("Is the order confirmed?", "Order #12 confirmed");
+        assert!(prompt.contains("Order #12 confirmed") && prompt.contains("Question: Is the order confirmed?"));
+    }
+}
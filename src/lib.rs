@@ -1,25 +1,148 @@
 use wasm_bindgen::prelude::*;
 use crate::agent::{AgentSystem, AgentError}; // Import AgentError
-use crate::dom_utils::DomError; // Import DomError for From<AgentError>
+use crate::dom_utils::{DomError, ElementCacheScope}; // Import DomError for From<AgentError>
+#[cfg(not(feature = "dom-only"))]
+use crate::escalation::EscalationPolicy;
+use crate::export::ScriptFormat;
+use crate::history::{HistoryFilter, RunFeedback, RunRecord};
+use crate::host_functions::{FunctionSignature, HostFunction};
+#[cfg(not(feature = "dom-only"))]
+use crate::llm_profiles::LlmProfile;
+#[cfg(not(feature = "dom-only"))]
+use crate::memory::EmbeddingEndpoint;
+use crate::openapi::{ApiOptions, ApiSpec};
+use crate::pacing::Pacing;
+use crate::page_errors::PageErrorMonitor;
+#[cfg(not(feature = "dom-only"))]
+use crate::redact::RedactionConfig;
+use crate::report::ResultReporting;
+use crate::result_code::TaskResult;
+use crate::result_schema::ResultSchema;
+use crate::run_limits::RunLimits;
+use crate::run_summary::RunSummary;
+use crate::sandbox::{PendingPreview, PreviewReport, SandboxScope};
+use crate::schedule::ScheduledRun;
+use crate::storage::Storage;
+use crate::trigger::{TriggerDefinition, TriggerHandle};
+use crate::try_catch::TryStack;
+use crate::while_loop::{Condition, LoopStep};
+use crate::worker::{DomProxy, DomRequestHandler, WorkerHost};
+use crate::workflow::{WorkflowDefinition, WorkflowError, WorkflowRegistry};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
 use web_sys; // Ensure web_sys is imported for console logging
 #[cfg(debug_assertions)]
 use console_error_panic_hook; // For better panic messages
 use serde::{Serialize, Deserialize}; // For LibError
 
 mod agent;
+#[cfg(not(feature = "dom-only"))]
 mod llm;
+mod llm_error; // Kinds of LLM API failures
+#[cfg(not(feature = "dom-only"))]
+mod llm_profiles; // Named LLM configurations switched between runs and tasks
+#[cfg(not(feature = "dom-only"))]
+mod escalation; // Planning retried with a stronger model after failed plans
+#[cfg(not(feature = "dom-only"))]
+mod benchmark; // The same tasks run with several models, compared
+mod bot_signals; // Advisory bot-detection signal reports
+#[cfg(not(feature = "dom-only"))]
+mod budget; // LLM call budget shared by every RustAgent on the page
+mod dom_backend; // The window commands act on, the browser's or a registered one (jsdom, linkedom)
 mod dom_utils; // Declare dom_utils module
+mod captcha; // Captcha detection and handoff
+mod capture; // Element screenshots
+#[cfg(all(feature = "cdp", not(target_arch = "wasm32")))]
+pub mod cdp; // Native backend running tasks in headless Chrome over the DevTools Protocol
+#[cfg(not(feature = "dom-only"))]
+mod cassette; // Recorded LLM responses, replayed in tests
+mod conditions; // Pre- and postconditions checked around tasks
+mod dom_diff; // DOM fingerprints and diffs between commands
+#[cfg(not(feature = "dom-only"))]
+mod disambiguate; // LLM picks among elements matching a selector
+mod emulation; // Geolocation and time zone overrides
+mod export; // Playwright and Selenium scripts generated from runs and workflows
+#[cfg(not(feature = "dom-only"))]
+mod fixtures; // LLM responses registered at runtime
+mod goto; // LABEL and bounded GOTO steps of task lists
+mod graphql; // GraphQL queries sent from the page with the user's cookies
+#[cfg(not(feature = "dom-only"))]
+mod guardrails; // Checks of LLM plans confirmed by the host
+mod history; // Bounded history of finished runs
+mod host_functions; // Functions registered by the host, with typed arguments, called by CALL_FUNCTION
+mod import; // Task lists imported from Puppeteer and Playwright scripts
+mod json_assert; // Deep comparison of JSON values by ASSERT_JSON
+#[cfg(not(feature = "dom-only"))]
+mod audit; // Redacted log of the prompts and responses of LLM calls
+#[cfg(not(feature = "dom-only"))]
+mod judge; // LLM_DECIDE / LLM_ASSERT questions about page content
+mod listen; // Events logged by LISTEN
+mod locale; // Unicode-aware text matching and translated terms
+mod login; // Login forms and outcome detection
+#[cfg(not(feature = "dom-only"))]
+mod memory; // Commands that succeeded, recalled for similar tasks
+#[cfg(feature = "extension")]
+mod extension; // chrome.runtime messaging adapter
+mod network; // WebSocket capture, performance timing and FETCH requests
+mod openapi; // OpenAPI specs registered by the host, whose operations CALL_API calls
+mod pacing; // Human-like pacing of commands
+mod page_errors; // Console and page error capture
+#[cfg(not(feature = "dom-only"))]
+mod prompt_examples; // Few-shot examples of the structured prompt
+#[cfg(not(feature = "dom-only"))]
+mod provider; // LLM provider registered by the host, loaded on first use
+#[cfg(not(feature = "dom-only"))]
+mod redact; // PII redaction of LLM prompts
+mod report; // Result envelopes sent to webhooks / parent frames
+mod result_code; // Stable codes of task results, e.g. CLICK_OK or ELEMENT_NOT_FOUND
+mod result_schema; // Versions of the shape of task results
+mod run_limits; // Caps on what a single run may do
+mod run_summary; // Counts, duration and first error of finished runs
+mod sandbox; // Copies of containers that previews run in
+mod schedule; // Delayed and recurring runs
+mod secrets; // Secrets substituted at execution time
+mod seo; // Search engine metadata audits and structured data extraction
+mod settle; // Waits for the page to settle after interactions
+mod soft_assert; // Assertion failures collected instead of failing tasks
+mod storage; // Memory, localStorage and IndexedDB key-value storage
+mod tour; // Guided tour / walkthrough overlays
+mod trigger; // Workflows started by page changes
+mod transaction; // Undo journal for transactional execution
+mod try_catch; // TRY / CATCH blocks of task lists
+mod variables; // Variables substituted at execution time
+mod watch; // DOM mutations observed by WATCH
+mod while_loop; // WHILE loops of task lists
+mod worker; // DOM proxying between a worker and the main thread
+mod workflow; // Named, parameterized workflows
 
 // Define LibError for serialization
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "error_type")] // This will add an "error_type" field to the JSON
 pub enum LibError {
     DomOperation { kind: String, details: String },
-    LlmCall { message: String },
+    LlmCall { message: String }, // LLM failures that are none of the kinds below
+    RateLimited { message: String, retry_after_ms: Option<u64> }, // LLM calls refused for too many requests; wait `retry_after_ms` if given
+    ContextLengthExceeded { message: String }, // Prompts longer than the model accepts
+    AuthFailed { message: String }, // LLM calls with a missing, invalid or unauthorized API key
+    ContentFiltered { message: String }, // Prompts or responses blocked by the provider's content filter
+    Network { message: String }, // LLM requests that got no response
     InvalidLlmResponse { message: String },
     CommandParse { message: String },
     Serialization { message: String },
     InternalAgent { message: String }, // Fallback for other AgentErrors
+    PolicyViolation { message: String }, // Command refused by the execution policy (e.g. EVAL_JS disabled)
+    PlanValidation { message: String }, // LLM command array rejected before execution; `message` is the JSON report
+    Workflow { message: String }, // Workflow registration or parameter errors, and GOTO / WHILE loops that hit their limit
+    AssertionFailed { message: String }, // A check such as ASSERT_VISUAL_MATCH ran but did not hold
+    BudgetExceeded { message: String }, // LLM call refused because the shared budget is spent
+    UserInput { message: String }, // REQUEST_USER_INPUT without a handler or not answered, or a captcha left unsolved
+    RunLimitExceeded { limit: String, max: u32, message: String }, // The run reached the cap named `limit` (e.g. `max_llm_calls`) and was aborted
+    Export { message: String }, // Script exports of unknown runs or in unknown formats
+    Api { message: String, status: Option<u16> }, // Invalid OpenAPI specs, CALL_API requests refused, not made or answered with the error `status`, and failed GRAPHQL queries
+    HostFunction { message: String }, // Invalid function definitions, and CALL_FUNCTION calls of unknown functions, with invalid arguments, or that failed
 }
 
 impl From<AgentError> for LibError {
@@ -36,6 +159,9 @@ impl From<AgentError> for LibError {
                     DomError::JsTypeError { .. } => "JsTypeError".to_string(),
                     DomError::JsSyntaxError { .. } => "JsSyntaxError".to_string(),
                     DomError::JsReferenceError { .. } => "JsReferenceError".to_string(),
+                    DomError::StaleElement { .. } => "StaleElement".to_string(),
+                    DomError::CorsBlocked { .. } => "CorsBlocked".to_string(),
+                    DomError::NetworkError { .. } => "NetworkError".to_string(),
                 };
                 LibError::DomOperation {
                     kind,
@@ -43,9 +169,27 @@ impl From<AgentError> for LibError {
                 }
             }
             AgentError::LlmCallFailed(message) => LibError::LlmCall { message },
+            AgentError::LlmRateLimited { message, retry_after_ms } => LibError::RateLimited { message, retry_after_ms },
+            AgentError::LlmContextLengthExceeded(message) => LibError::ContextLengthExceeded { message },
+            AgentError::LlmAuthFailed(message) => LibError::AuthFailed { message },
+            AgentError::LlmContentFiltered(message) => LibError::ContentFiltered { message },
+            AgentError::LlmNetworkError(message) => LibError::Network { message },
             AgentError::InvalidLlmResponse(message) => LibError::InvalidLlmResponse { message },
             AgentError::CommandParseError(message) => LibError::CommandParse { message },
             AgentError::SerializationError(message) => LibError::Serialization { message },
+            AgentError::PolicyViolation(message) => LibError::PolicyViolation { message },
+            AgentError::PlanValidationFailed(message) => LibError::PlanValidation { message },
+            AgentError::AssertionFailed(message) => LibError::AssertionFailed { message },
+            AgentError::BudgetExceeded(message) => LibError::BudgetExceeded { message },
+            AgentError::UserInputUnavailable(message) => LibError::UserInput { message },
+            AgentError::RunLimitExceeded(exceeded) => LibError::RunLimitExceeded {
+                limit: exceeded.action.limit_name().to_string(),
+                max: exceeded.max,
+                message: exceeded.to_string(),
+            },
+            AgentError::ApiCallFailed { message, status } => LibError::Api { message, status },
+            AgentError::HostFunctionFailed(message) => LibError::HostFunction { message },
+            AgentError::ProxiedCommandFailed(lib_error) => lib_error,
             // If AgentError grows more variants, they can be mapped here or fall into a generic category.
             // For now, let's assume any other AgentError is an InternalAgent error.
             // To make this more robust, one might want to ensure all AgentError variants are explicitly handled.
@@ -55,6 +199,12 @@ impl From<AgentError> for LibError {
 }
 
 
+impl From<WorkflowError> for LibError {
+    fn from(workflow_error: WorkflowError) -> Self {
+        LibError::Workflow { message: workflow_error.to_string() }
+    }
+}
+
 // Expose RustAgent to JavaScript
 /// `RustAgent` is the main entry point for JavaScript to interact with the Rust-based agent system.
 /// It encapsulates an `AgentSystem` and handles configuration for LLM (Large Language Model) interactions.
@@ -62,136 +212,2170 @@ impl From<AgentError> for LibError {
 pub struct RustAgent {
     /// The core agent system that manages and runs agents.
     agents: AgentSystem,
+    #[cfg(not(feature = "dom-only"))]
     /// Optional URL for the LLM API endpoint.
     api_url: Option<String>,
+    #[cfg(not(feature = "dom-only"))]
     /// Optional name of the LLM model to be used.
     model_name: Option<String>,
+    #[cfg(not(feature = "dom-only"))]
     /// Optional API key for authenticating with the LLM service.
     api_key: Option<String>,
+    /// Workflows registered with `register_workflow`, by name.
+    workflows: WorkflowRegistry,
+    /// Where the result envelope of each finished run is reported.
+    reporting: ResultReporting,
+    /// The commands of the last preview, until they are applied or discarded.
+    pending_preview: RefCell<Option<PendingPreview>>,
 }
 
-#[wasm_bindgen]
-impl RustAgent {
-    /// Creates a new instance of `RustAgent`.
-    /// Initializes the underlying `AgentSystem` with a default set of agents.
-    /// LLM configuration is initially unset.
-    #[wasm_bindgen(constructor)]
-    pub fn new() -> RustAgent {
-        RustAgent {
-            agents: AgentSystem::new(),
-            api_url: None,
-            model_name: None,
-            api_key: None,
+#[wasm_bindgen]
+impl RustAgent {
+    /// Creates a new instance of `RustAgent`.
+    /// Initializes the underlying `AgentSystem` with a default set of agents.
+    /// LLM configuration is initially unset.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> RustAgent {
+        RustAgent {
+            agents: AgentSystem::new(),
+            #[cfg(not(feature = "dom-only"))]
+            api_url: None,
+            #[cfg(not(feature = "dom-only"))]
+            model_name: None,
+            #[cfg(not(feature = "dom-only"))]
+            api_key: None,
+            workflows: WorkflowRegistry::default(),
+            reporting: ResultReporting::default(),
+            pending_preview: RefCell::new(None),
+        }
+    }
+
+    /// Sets the configuration for the Large Language Model (LLM) to be used by the agents.
+    /// All parameters are required to enable LLM-based task processing.
+    ///
+    /// # Arguments
+    /// * `api_url`: The URL of the LLM API endpoint.
+    /// * `model_name`: The specific model name to use (e.g., "gpt-3.5-turbo").
+    /// * `api_key`: The API key for authentication with the LLM service.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_llm_config(&mut self, api_url: String, model_name: String, api_key: String) {
+        self.api_url = Some(api_url);
+        self.model_name = Some(model_name);
+        self.api_key = Some(api_key);
+    }
+
+    /// Adds a named LLM configuration, or replaces the one with the same name, so that a run can
+    /// use several models, e.g. a cheap one to summarize and a stronger one to plan.
+    ///
+    /// A task starting with `profile:<name>`, e.g. `"profile:cheap Summarize the reviews"`, runs
+    /// with that profile, including its `LLM_DECIDE` / `LLM_ASSERT` questions and disambiguation;
+    /// other tasks use the profile selected with `use_profile`, or the `set_llm_config`
+    /// configuration. A task naming an unknown profile fails with a `CommandParse` error.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the profile, without whitespace.
+    /// * `config_json`: A JSON object with the `model` and optional `api_url` and `api_key`, e.g.
+    ///   `{"model": "gpt-4o-mini"}`. `api_url` and `api_key` default to those of the configuration
+    ///   in use when the profile is.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` with an error message if the name or the configuration is invalid.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn add_llm_profile(&mut self, name: String, config_json: String) -> Result<(), JsValue> {
+        let profile = LlmProfile::parse(&config_json).map_err(|e| JsValue::from_str(&format!("{}.", e)))?;
+        self.agents.config_mut().llm_profiles.add(name.trim(), profile).map_err(|e| JsValue::from_str(&format!("{}.", e)))
+    }
+
+    /// Uses the LLM profile named `name` for the following runs instead of the `set_llm_config`
+    /// configuration, or that configuration again if `name` is `None` or empty. Tasks with a
+    /// `profile:` hint keep the profile they name. Runs already started are not affected.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` with an error message if no profile has that name.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn use_profile(&mut self, name: Option<String>) -> Result<(), JsValue> {
+        let name = name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty());
+        self.agents.config_mut().llm_profiles.select(name.as_deref()).map_err(|e| JsValue::from_str(&format!("{}.", e)))
+    }
+
+    /// Plans LLM tasks again when their plan fails, with a stronger model once enough plans failed.
+    /// Plans are not retried by default.
+    ///
+    /// A plan fails when the LLM's response starts like JSON but cannot be read, when plan
+    /// validation rejects it, or when one of its commands cannot be read or has a selector that is
+    /// invalid or matches nothing. The task is then planned again with the reasons of the failures
+    /// in the prompt: by the model in use until `after_failures` plans failed, then once by the
+    /// model of the profile. Commands of the failed plans that ran are not undone, and every plan
+    /// is one more LLM call. Each escalation is dispatched as an `llm_escalation` event on `window`
+    /// and listed by `last_run_escalations`.
+    ///
+    /// # Arguments
+    /// * `policy_json`: A JSON object with the `profile` of the stronger model (see
+    ///   `add_llm_profile`) and the optional `after_failures` (1 by default), e.g.
+    ///   `{"profile": "strong", "after_failures": 2}`. `""` stops retrying.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` with an error message if the policy is malformed or names an unknown profile.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_model_escalation(&mut self, policy_json: String) -> Result<(), JsValue> {
+        let policy = if policy_json.trim().is_empty() {
+            None
+        } else {
+            let policy: EscalationPolicy = serde_json::from_str(&policy_json)
+                .map_err(|e| JsValue::from_str(&format!("Invalid escalation policy: {}", e)))?;
+            if self.agents.config().llm_profiles.get(&policy.profile).is_none() {
+                return Err(JsValue::from_str(&format!("Unknown LLM profile '{}'. Add it with add_llm_profile first.", policy.profile)));
+            }
+            Some(policy)
+        };
+        self.agents.config_mut().escalation.policy = policy;
+        Ok(())
+    }
+
+    /// Returns the escalations of the last run as a JSON array, e.g. `[{"task":"...","from_model":
+    /// "gpt-4o-mini","to_model":"gpt-4o","profile":"strong","failures":["..."],"success":true}]`.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn last_run_escalations(&self) -> String {
+        serde_json::to_string(&self.agents.config().escalation.escalations()).unwrap_or_default()
+    }
+
+    /// Returns the LLM profiles and the one in use as JSON, without their API keys, e.g.
+    /// `{"active":"strong","profiles":[{"name":"cheap","model":"gpt-4o-mini","api_url":null}]}`.
+    /// `active` is `null` while the `set_llm_config` configuration is in use.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn llm_profiles(&self) -> String {
+        self.agents.config().llm_profiles.summary().to_string()
+    }
+
+    /// Sets a budget for the LLM calls of every `RustAgent` on the page (`RustAgent.set_shared_budget(...)`
+    /// in JavaScript), so that embedding several agents does not multiply LLM spend. Calls beyond
+    /// the per-minute rate wait for a slot; calls beyond the total limits fail with a
+    /// `BudgetExceeded` error. Usage so far is kept when the limits change.
+    ///
+    /// # Arguments
+    /// * `limits_json`: A JSON object whose fields are all optional, e.g.
+    ///   `{"max_calls_per_minute": 10, "max_calls": 500, "max_chars": 2000000}`, where `max_chars`
+    ///   counts the characters of prompts and responses. `""` removes the budget and its usage.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` (`error_type` `Serialization`) if the
+    /// limits are malformed.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_shared_budget(limits_json: String) -> Result<(), JsValue> {
+        if limits_json.trim().is_empty() {
+            budget::set_limits(None);
+            return Ok(());
+        }
+        let limits = serde_json::from_str(&limits_json).map_err(|e| {
+            lib_error_to_js(LibError::Serialization { message: format!("Invalid budget limits: {}", e) })
+        })?;
+        budget::set_limits(Some(limits));
+        Ok(())
+    }
+
+    /// Returns what has been spent from the shared budget as JSON, e.g. `{"calls":12,"chars":48210}`,
+    /// or `undefined` if no budget is set.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn shared_budget_usage() -> Option<String> {
+        budget::usage().and_then(|usage| serde_json::to_string(&usage).ok())
+    }
+
+    /// Forgets the usage of the shared budget, e.g. at the start of a new billing period.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn reset_shared_budget_usage() {
+        budget::reset_usage();
+    }
+
+    /// Registers a canned LLM response, returned instead of calling the LLM (or the mock of the
+    /// `mock-llm` feature) for every prompt whose task contains `matcher`. Like the shared
+    /// budget, fixtures apply to every `RustAgent` on the page (a static method in JavaScript), so
+    /// tests of host applications and downstream crates can script the LLM deterministically.
+    ///
+    /// The command catalogue and page content sent with the task are not matched. Prompts of
+    /// `LLM_DECIDE` / `LLM_ASSERT` are matched on their question, and prompts without a task, like
+    /// the one picking among elements matching a selector, as a whole. Fixtures are checked in the
+    /// order they were added; registering a matcher again replaces its response. The response is
+    /// used as the LLM's answer: a JSON command array, a natural language answer, or, for questions
+    /// and ambiguous selectors, `yes`/`no` or a number.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` if `matcher` is empty.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn add_llm_fixture(matcher: String, response: String) -> Result<(), JsValue> {
+        fixtures::add(&matcher, &response).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Registers an LLM provider implemented in JavaScript, called for every LLM call instead of
+    /// the built-in HTTP client (or the mock of the `mock-llm` feature). Like fixtures,
+    /// the provider applies to every `RustAgent` on the page (a static method in JavaScript).
+    ///
+    /// Builds without the `http-llm` feature leave the HTTP client out of the module, which makes it
+    /// much smaller; they need a provider to reach an LLM. The provider is loaded on first use: the
+    /// loader is called by the first LLM call, not now, so provider code can be fetched with a
+    /// dynamic `import()` only when a task needs the LLM. A failed load is retried by the next call.
+    ///
+    /// # Arguments
+    /// * `loader`: Returns (or resolves to) the provider, a function called with
+    ///   `{prompt, model, api_url, api_key}` that returns (or resolves to) the LLM's answer as a
+    ///   string, e.g. `() => import("./openai.js").then((module) => module.complete)`. A rejection
+    ///   with a numeric `status` (and optionally `body` and `retry_after`) is classified like an
+    ///   error response, e.g. `{status: 429, body, retry_after: "20"}` as `RateLimited`.
+    ///   `null` removes the provider.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_llm_provider(loader: Option<js_sys::Function>) {
+        provider::set_loader(loader);
+    }
+
+    /// Whether an LLM provider is registered with `set_llm_provider`.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn has_llm_provider() -> bool {
+        provider::is_registered()
+    }
+
+    /// Makes commands act on the document of a DOM implementation instead of the browser's, so that
+    /// tasks and workflows can run under Node (built with `--target nodejs` and the `node` feature),
+    /// e.g. in unit tests. Like the LLM provider, the window applies to every `RustAgent` (a static
+    /// method in JavaScript).
+    ///
+    /// The window's DOM constructors (`HTMLElement`, `MouseEvent`, ...), `window` and `document` are
+    /// also made global where Node has none, since commands check element types and create events
+    /// with them. Commands relying on layout (visibility, coordinates, screenshots) behave as the
+    /// implementation does; jsdom, for one, lays nothing out.
+    ///
+    /// # Arguments
+    /// * `window`: The window of the implementation, e.g. `new JSDOM(html).window` or
+    ///   `parseHTML(html).window` of linkedom. `null` makes commands act on the browser's again.
+    #[cfg(feature = "node")]
+    #[wasm_bindgen]
+    pub fn set_dom_backend(window: Option<js_sys::Object>) {
+        dom_backend::set_window(window.map(JsValue::from));
+    }
+
+    /// Whether a window was registered with `set_dom_backend`.
+    #[cfg(feature = "node")]
+    #[wasm_bindgen]
+    pub fn has_dom_backend() -> bool {
+        dom_backend::is_registered()
+    }
+
+    /// Removes every fixture registered with `add_llm_fixture`.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn clear_llm_fixtures() {
+        fixtures::clear();
+    }
+
+    /// The number of fixtures registered with `add_llm_fixture`.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn llm_fixture_count() -> usize {
+        fixtures::count()
+    }
+
+    /// Starts recording the prompts sent to the LLM and its responses into a new cassette,
+    /// replacing any earlier one, so that a run against the real API can be replayed later
+    /// without it (see `replay_llm_cassette`). Applies to every `RustAgent` on the page.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn start_llm_recording() {
+        cassette::start_recording();
+    }
+
+    /// Returns the cassette being recorded, or the one last recorded or replayed, as JSON:
+    /// `{"interactions":[{"model":"gpt-4o","prompt":"...","response":"..."}]}`. Prompts include the
+    /// (redacted) task and page content, so treat cassettes like the pages they were recorded on.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn export_llm_cassette() -> Result<String, JsValue> {
+        serde_json::to_string(&cassette::cassette()).map_err(|e| {
+            lib_error_to_js(LibError::Serialization { message: format!("Failed to serialize the LLM cassette: {}", e) })
+        })
+    }
+
+    /// Serves the responses of a cassette exported with `export_llm_cassette` instead of calling
+    /// the LLM, until `stop_llm_cassette` is called. A prompt is answered by the interaction
+    /// recorded for the same prompt or, if the page content in it changed, for the same task;
+    /// repeated prompts get the recorded responses in order. Prompts the cassette has no
+    /// response for fail with an `LlmCall` error instead of reaching the API. Fixtures registered
+    /// with `add_llm_fixture` still take precedence.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` (`error_type` `Serialization`) if the
+    /// cassette is malformed.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn replay_llm_cassette(cassette_json: String) -> Result<(), JsValue> {
+        let recorded = serde_json::from_str(&cassette_json).map_err(|e| {
+            lib_error_to_js(LibError::Serialization { message: format!("Invalid LLM cassette: {}", e) })
+        })?;
+        cassette::start_replay(recorded);
+        Ok(())
+    }
+
+    /// Stops recording or replaying. A recorded cassette can still be exported.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn stop_llm_cassette() {
+        cassette::stop();
+    }
+
+    /// `"record"`, `"replay"` or `"off"`.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn llm_cassette_mode() -> String {
+        cassette::mode().to_string()
+    }
+
+    /// Enables or disables the `EVAL_JS` command, which runs arbitrary JavaScript snippets.
+    /// Disabled by default. Even when enabled, `EVAL_JS` only runs on origins configured
+    /// with `set_eval_allowed_origins`, and only as a direct task: LLM plans cannot contain it.
+    ///
+    /// # Arguments
+    /// * `allow`: `true` to allow `EVAL_JS`, `false` to refuse it with a `PolicyViolation` error.
+    #[wasm_bindgen]
+    pub fn allow_eval(&mut self, allow: bool) {
+        self.agents.config_mut().allow_eval = allow;
+    }
+
+    /// Enables or disables validation of LLM command arrays before execution.
+    /// Disabled by default.
+    ///
+    /// When enabled, every command of an LLM plan is checked (known action, required fields,
+    /// selectors resolving on the current page) before the first one runs. A plan with errors
+    /// is rejected as a whole with a `PlanValidation` error whose message is a JSON report:
+    /// `{"valid":false,"issues":[{"index":1,"action":"CLICK","selector":"#buy","severity":"error","message":"..."}]}`.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to validate plans before execution, `false` to run commands one by one as before.
+    #[wasm_bindgen]
+    pub fn validate_plans(&mut self, enabled: bool) {
+        self.agents.config_mut().validate_plans = enabled;
+    }
+
+    /// Enables or disables guardrails on LLM command arrays. Disabled by default.
+    ///
+    /// When enabled, before the first command of an LLM plan runs, the plan is checked for
+    /// elements that are not on the page (and that no earlier command waits for or may create),
+    /// navigation leading outside the origin allowlist or, without an allowlist, off the current
+    /// site, and entered values that look like placeholders the LLM made up (`<your email>`,
+    /// `[Full Name]`, `YOUR_API_KEY`, `...@example.com`). A flagged plan runs only if the handler
+    /// set with `set_confirmation_handler` confirms it; otherwise it fails with a `PlanValidation`
+    /// error whose message is the report:
+    /// `{"confirmed":false,"warnings":[{"index":0,"action":"Type","selector":"css:#email","kind":"placeholder_value","message":"..."}]}`.
+    /// `kind` is `missing_element`, `disallowed_navigation`, `offsite_navigation` or `placeholder_value`.
+    /// Confirmed plans are still subject to the origin allowlist when their commands run.
+    #[wasm_bindgen]
+    pub fn enable_guardrails(&mut self, enabled: bool) {
+        self.agents.config_mut().guardrails = enabled;
+    }
+
+    /// Sets the function asked to confirm LLM plans flagged by the guardrails (see `enable_guardrails`).
+    ///
+    /// # Arguments
+    /// * `handler`: Called as `handler(report_json)`; returns `true` to run the plan, or a `Promise`
+    ///   of it. Any other reply rejects the plan. Pass `null` to remove the handler, which rejects
+    ///   every flagged plan.
+    #[wasm_bindgen]
+    pub fn set_confirmation_handler(&mut self, handler: Option<js_sys::Function>) {
+        self.agents.config_mut().confirmation_handler = handler;
+    }
+
+    /// Caps what a single run (an `automate` call, workflow, or scheduled or triggered run) may do,
+    /// so that an autonomous loop can be left unattended. No caps are set by default.
+    ///
+    /// Before a command acts on the page or the LLM is called, the run's count is checked against
+    /// its cap. Once a cap is reached, that action is refused and the run is aborted, including
+    /// the rest of an LLM plan and every remaining task. `CATCH` steps are skipped. The task fails
+    /// with a `RunLimitExceeded` error naming the cap, e.g.
+    /// `{"error_type":"RunLimitExceeded","limit":"max_navigations","max":3,"message":"..."}`.
+    ///
+    /// # Arguments
+    /// * `limits_json`: A JSON object whose fields are all optional, e.g.
+    ///   `{"max_dom_mutations": 50, "max_navigations": 3, "max_llm_calls": 10}`.
+    ///   `max_dom_mutations` counts the commands that act on the page, `CALL_API`, `GRAPHQL`, `FETCH` and `CALL_FUNCTION`. Reads, waits and `NAVIGATE`
+    ///   are not counted; `max_navigations` counts `NAVIGATE`. `""` removes every cap.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` (`error_type` `Serialization`) if the
+    /// limits are malformed.
+    #[wasm_bindgen]
+    pub fn set_run_limits(&mut self, limits_json: String) -> Result<(), JsValue> {
+        let limits = if limits_json.trim().is_empty() {
+            RunLimits::default()
+        } else {
+            serde_json::from_str(&limits_json).map_err(|e| {
+                lib_error_to_js(LibError::Serialization { message: format!("Invalid run limits: {}", e) })
+            })?
+        };
+        self.agents.config_mut().run_limits.limits = limits;
+        Ok(())
+    }
+
+    /// Binds the agent to a part of the page, so that several agents embedded in one page (e.g. a
+    /// chat widget's and a form assistant's) each act on their own widget only. No region is set
+    /// by default.
+    ///
+    /// While a region is set, the selector of every command, direct or LLM-proposed, is resolved
+    /// among the descendants of the first element matching the region, as if written
+    /// `<region> >> <selector>`: an element outside the region is not found, and the command fails
+    /// with `ElementNotFound`, as it does when the region itself is not on the page. Commands acting
+    /// on the whole page (`NAVIGATE`, `EVAL_JS`, `INJECT_CSS`, coordinates, `TAB_TO`, tours...) fail
+    /// with a `PolicyViolation`. Commands reading the page as a whole, such as `GET_URL` or
+    /// `XPATH_EVAL`, are not restricted. The selectors inside the values of `TYPE_AND_SELECT`,
+    /// `SEARCH_AND_OPEN` and `LOGIN` are resolved on the whole page, since suggestion lists and
+    /// results are often rendered outside the widget.
+    ///
+    /// # Arguments
+    /// * `root_selector`: The selector of the region, e.g. `"css:#support-chat"`. `None` or `""`
+    ///   unbinds the agent.
+    #[wasm_bindgen]
+    pub fn set_region(&mut self, root_selector: Option<String>) {
+        self.agents.config_mut().region = root_selector.map(|selector| selector.trim().to_string()).filter(|selector| !selector.is_empty());
+    }
+
+    /// Enables or disables soft-assert mode. Disabled by default.
+    ///
+    /// While enabled, the assertions `ASSERT_VISUAL_MATCH`, `ASSERT_JSON` and `LLM_ASSERT` (direct or
+    /// LLM-proposed) do not fail when they do not hold: the task succeeds with a result starting
+    /// with `Soft assertion failed:`, so the rest of its LLM plan runs and `CATCH` steps do not.
+    /// Every assertion of the run is counted in the `assertion_report` of its result envelope and
+    /// history record, e.g. `{"checked":3,"failed":1,"failures":[{"assertion":"ASSERT_JSON $.total","message":"..."}]}`,
+    /// and a run with a failed assertion is not a `success`. Other errors, such as an element that
+    /// was not found, and the conditions of tasks still fail their task.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to collect assertion failures, `false` to fail on the first one.
+    #[wasm_bindgen]
+    pub fn set_soft_assertions(&mut self, enabled: bool) {
+        self.agents.config_mut().soft_assertions.enabled = enabled;
+    }
+
+    /// Returns what the last run counted against its caps as JSON, e.g.
+    /// `{"dom_mutations":12,"navigations":1,"llm_calls":3}`. Actions are counted whether or not caps are set.
+    #[wasm_bindgen]
+    pub fn last_run_usage(&self) -> String {
+        serde_json::to_string(&self.agents.config().run_limits.usage()).unwrap_or_default()
+    }
+
+    /// Enables or disables transactional execution. Disabled by default.
+    ///
+    /// While enabled, the original values changed by `SETATTRIBUTE`, `TYPE` and `SELECTOPTION`
+    /// commands (direct or LLM-proposed) are recorded, so that the changes made by the last
+    /// `automate` call can be undone with `rollback_last_run`.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to record changes, `false` to stop recording.
+    #[wasm_bindgen]
+    pub fn enable_transactions(&mut self, enabled: bool) {
+        self.agents.config_mut().transactional = enabled;
+    }
+
+    /// Restores the attribute and form values changed by the last `automate` call, newest
+    /// change first. Only changes made while transactional execution was enabled are recorded.
+    ///
+    /// # Returns
+    /// The number of changes that were restored (`0` if nothing was recorded, or if the last
+    /// run was already rolled back).
+    #[wasm_bindgen]
+    pub fn rollback_last_run(&self) -> usize {
+        self.agents.rollback_last_run()
+    }
+
+    /// Sets the origins on which `EVAL_JS` may run, replacing any previously configured list.
+    ///
+    /// # Arguments
+    /// * `origins_json`: A JSON string representing a list of origins.
+    ///   Example: `["https://example.com", "http://localhost:8080"]`
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` with an error message if `origins_json` is not a JSON array of strings.
+    #[wasm_bindgen]
+    pub fn set_eval_allowed_origins(&mut self, origins_json: String) -> Result<(), JsValue> {
+        let origins: Vec<String> = serde_json::from_str(&origins_json)
+            .map_err(|_| JsValue::from_str("Invalid JSON origin list. Expected an array of strings."))?;
+        self.agents.config_mut().eval_allowed_origins = origins;
+        Ok(())
+    }
+
+    /// Restricts the agent to the given origins, replacing any previously configured list.
+    /// Unrestricted by default.
+    ///
+    /// While the list is not empty, every command (direct or LLM-proposed) is refused with a
+    /// `PolicyViolation` error on pages of other origins, as are `NAVIGATE` commands and clicks on
+    /// links leading to them. `EVAL_JS` additionally requires `set_eval_allowed_origins`.
+    ///
+    /// # Arguments
+    /// * `origins_json`: A JSON string representing a list of origins, or `[]` to lift the restriction.
+    ///   Example: `["https://shop.example", "https://accounts.shop.example"]`
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` with an error message if `origins_json` is not a JSON array of strings.
+    #[wasm_bindgen]
+    pub fn set_allowed_origins(&mut self, origins_json: String) -> Result<(), JsValue> {
+        let origins: Vec<String> = serde_json::from_str(&origins_json)
+            .map_err(|_| JsValue::from_str("Invalid JSON origin list. Expected an array of strings."))?;
+        self.agents.config_mut().allowed_origins = origins;
+        Ok(())
+    }
+
+    /// Configures which values are replaced by placeholders (e.g. `[EMAIL_1]`) in task text before
+    /// it is sent to the LLM, including page content inserted with `{{PREVIOUS_RESULT}}`.
+    /// Placeholders in the LLM's response are replaced by the original values locally, before the
+    /// proposed commands run. Nothing is redacted by default; direct DOM commands never reach the LLM.
+    ///
+    /// # Arguments
+    /// * `config_json`: A JSON object with optional `emails` and `credit_cards` switches and custom
+    ///   `patterns` (JavaScript regular expressions by placeholder label), or `{}` to disable redaction.
+    ///   Example: `{"emails": true, "credit_cards": true, "patterns": {"PHONE": "\\+?\\d[\\d ]{8,}\\d"}}`
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` with an error message if `config_json` is malformed
+    /// or a pattern is not a valid regular expression.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_pii_redaction(&mut self, config_json: String) -> Result<(), JsValue> {
+        let config: RedactionConfig = serde_json::from_str(&config_json)
+            .map_err(|e| JsValue::from_str(&format!("Invalid redaction config: {}", e)))?;
+        config.validate().map_err(|e| JsValue::from_str(&e))?;
+        self.agents.config_mut().redaction = config;
+        Ok(())
+    }
+
+    /// Paces DOM commands like a human would, both to avoid tripping bot detection and to let people
+    /// follow along. Commands run as fast as possible by default.
+    ///
+    /// Before each command (direct or LLM-proposed), the agent waits a random delay between
+    /// `min_delay_ms` and `max_delay_ms` after the end of the previous one, and, if
+    /// `max_actions_per_minute` is set, until fewer than that many commands started in the last minute.
+    ///
+    /// # Arguments
+    /// * `min_delay_ms`: The minimum delay between commands, e.g. `100`.
+    /// * `max_delay_ms`: The maximum delay between commands, e.g. `400`. `0` disables the delay.
+    /// * `max_actions_per_minute`: The cap on commands per minute, or `0` for no cap.
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` if `min_delay_ms` is greater than `max_delay_ms`.
+    #[wasm_bindgen]
+    pub fn set_pacing(&mut self, min_delay_ms: u32, max_delay_ms: u32, max_actions_per_minute: u32) -> Result<(), JsValue> {
+        if min_delay_ms > max_delay_ms {
+            return Err(JsValue::from_str("min_delay_ms must not be greater than max_delay_ms."));
+        }
+        let cap = Some(max_actions_per_minute).filter(|&cap| cap > 0);
+        self.agents.config_mut().pacing = Pacing::new(min_delay_ms, max_delay_ms, cap);
+        Ok(())
+    }
+
+    /// Enables or disables human-like typing. Disabled by default.
+    ///
+    /// While enabled, `TYPE` and `TYPE_IF_EXISTS` (direct or LLM-proposed) type one character at a
+    /// time, with `keydown`, `keypress`, `input` and `keyup` events for each character and `delay_ms`
+    /// between characters, instead of setting the value at once. Many autocomplete widgets and
+    /// validators only react to such incremental input.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to type character by character, `false` to set values at once.
+    /// * `delay_ms`: The delay between characters, in milliseconds, e.g. `80`.
+    #[wasm_bindgen]
+    pub fn set_human_typing(&mut self, enabled: bool, delay_ms: u32) {
+        self.agents.config_mut().typing_delay_ms = Some(delay_ms).filter(|_| enabled);
+    }
+
+    /// Sets how long commands wait for something to happen when they give no timeout, and how often
+    /// they check the page meanwhile. Defaults to 5000ms and 100ms.
+    ///
+    /// Applies to `WAIT_FOR_ELEMENT`, `TYPE_AND_SELECT`, `SEARCH_AND_OPEN`, `LOGIN` and
+    /// `WAIT_FOR_WS_MESSAGE`, direct or LLM-proposed, and to the `[pre: ...]` and `[post: ...]`
+    /// conditions of tasks. `SEARCH_AND_OPEN` and `LOGIN` wait 10 seconds
+    /// by default, and keep doing so unless `timeout_ms` is longer. A timeout given by a command
+    /// (e.g. `WAIT_FOR_ELEMENT css:#report 30000`) still takes precedence.
+    ///
+    /// # Arguments
+    /// * `timeout_ms`: The default timeout, in milliseconds, e.g. `20000` for a slow app. `0` restores the default.
+    /// * `interval_ms`: The polling interval, in milliseconds. `0` restores the default.
+    #[wasm_bindgen]
+    pub fn set_wait_defaults(&mut self, timeout_ms: u32, interval_ms: u32) {
+        let timeout_ms = if timeout_ms == 0 { dom_utils::DEFAULT_WAIT_TIMEOUT_MS } else { timeout_ms };
+        let interval_ms = if interval_ms == 0 { dom_utils::DEFAULT_WAIT_INTERVAL_MS } else { interval_ms };
+        self.agents.config_mut().wait = dom_utils::WaitConfig { timeout_ms, interval_ms };
+    }
+
+    /// Limits the length of every command result, direct or LLM-proposed. Longer results (e.g. large
+    /// `GET_ALL_TEXT` or `EVAL_JS` payloads) are cut and end with `... [truncated N characters]`.
+    ///
+    /// # Arguments
+    /// * `max_length`: The maximum number of characters kept, or `0` for no limit (the default).
+    #[wasm_bindgen]
+    pub fn set_max_result_length(&mut self, max_length: u32) {
+        self.agents.config_mut().max_result_length = Some(max_length as usize).filter(|&length| length > 0);
+    }
+
+    /// Enables or disables the redaction of entered values in results. Disabled by default.
+    ///
+    /// While enabled, the values entered by `TYPE`, `TYPE_IF_EXISTS`, `RICH_TEXT_TYPE`,
+    /// `TYPE_AND_SELECT`, `SETATTRIBUTE` and `SELECTOPTION` (direct or LLM-proposed) appear as `[redacted]` in results, e.g.
+    /// `Successfully typed '[redacted]' in element with selector: 'css:#card'`.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to redact entered values, `false` to show them.
+    #[wasm_bindgen]
+    pub fn set_redact_values_in_results(&mut self, enabled: bool) {
+        self.agents.config_mut().redact_values_in_results = enabled;
+    }
+
+    /// Enables or disables DOM change tracking. Disabled by default.
+    ///
+    /// While enabled, the page is fingerprinted before and after every command (direct or
+    /// LLM-proposed), each result ends with a summary such as `[changed_nodes: 1 added, 0 removed, 2 changed]`,
+    /// and `GET_DOM_DIFF` lists the elements the previous command changed.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to track changes, `false` to stop.
+    #[wasm_bindgen]
+    pub fn set_track_dom_changes(&mut self, enabled: bool) {
+        self.agents.config_mut().track_dom_changes = enabled;
+    }
+
+    /// Enables or disables auto-settle. Disabled by default.
+    ///
+    /// While enabled, the interactions `CLICK`, `CLICK_IF_EXISTS`, `CLICK_AT`, `CLICK_CENTER`, `TAP`,
+    /// `TYPE`, `TYPE_IF_EXISTS`, `RICH_TEXT_TYPE`, `SELECTOPTION` and `SET_RANGE` (direct or
+    /// LLM-proposed) are followed by a wait until the page has gone `quiet_ms` without DOM
+    /// mutations, so that the next command finds what the interaction rendered without a
+    /// `WAIT_FOR_ELEMENT`. The wait ends after `timeout_ms` in any case, and the result then ends
+    /// with `[page still changing after <timeout_ms>ms]`. Not done by an agent in a worker.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to wait after interactions, `false` to stop.
+    /// * `quiet_ms`: How long the page must go without mutations, in milliseconds. `0` uses the default, 100ms.
+    /// * `timeout_ms`: How long the wait lasts at most, in milliseconds. `0` uses the default, 2000ms.
+    #[wasm_bindgen]
+    pub fn set_auto_settle(&mut self, enabled: bool, quiet_ms: u32, timeout_ms: u32) {
+        let quiet_ms = if quiet_ms == 0 { settle::DEFAULT_SETTLE_QUIET_MS } else { quiet_ms };
+        let timeout_ms = if timeout_ms == 0 { settle::DEFAULT_SETTLE_TIMEOUT_MS } else { timeout_ms };
+        self.agents.config_mut().auto_settle = enabled.then_some(settle::SettleConfig { quiet_ms, timeout_ms });
+    }
+
+    /// Enables or disables asking the LLM which element a command means when its selector matches
+    /// several. Disabled by default.
+    ///
+    /// While enabled, a command acting on one element (direct or LLM-proposed) whose selector
+    /// matches several sends a short description of each match, with the task, to the LLM, acts
+    /// on the element whose number it answers, and ends its result with e.g.
+    /// `(picked match 2 of 3 for 'css:button.plan': button.plan "Pro")`. Commands otherwise act on
+    /// the first match. Ignored in a worker and while transactional mode is enabled, since rollback
+    /// finds elements by selector.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to let the LLM pick, `false` to act on the first match.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_llm_disambiguation(&mut self, enabled: bool) {
+        self.agents.config_mut().disambiguate_matches = enabled;
+    }
+
+    /// Adds an example of a task and the commands that carry it out on this site. Prompts sent
+    /// to the LLM for similar tasks include it, which helps with UIs the LLM gets wrong. Adding an
+    /// example for the same task again replaces it.
+    ///
+    /// Examples are ranked by similarity to the task being run, and the most similar ones are
+    /// included while they fit in the budget set with `set_prompt_example_budget`. Examples with
+    /// little in common with the task are never included.
+    ///
+    /// # Arguments
+    /// * `task`: The task as a user would write it, e.g. `"Add the margherita to the cart"`.
+    /// * `expected_commands_json`: The JSON command array the LLM should answer, e.g.
+    ///   `[{"action": "CLICK", "selector": "css:[data-pizza=margherita] .add"}]`.
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` containing a serialized `LibError` (`error_type`
+    /// `CommandParse`) if `task` is empty or `expected_commands_json` is not a non-empty array of valid commands.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn add_prompt_example(&mut self, task: String, expected_commands_json: String) -> Result<(), JsValue> {
+        self.agents
+            .add_prompt_example(&task, &expected_commands_json)
+            .map_err(|e| lib_error_to_js(LibError::from(e)))
+    }
+
+    /// Removes every example added with `add_prompt_example`.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn clear_prompt_examples(&mut self) {
+        self.agents.config_mut().prompt_examples.clear();
+    }
+
+    /// Sets how many characters of examples a prompt may include, 2000 by default. Characters stand
+    /// in for tokens, as in the shared LLM budget.
+    ///
+    /// # Arguments
+    /// * `max_chars`: The budget, or `0` to leave examples out of prompts.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_prompt_example_budget(&mut self, max_chars: usize) {
+        self.agents.config_mut().prompt_examples.set_max_chars(max_chars);
+    }
+
+    /// Lets the agent learn from experience within the session: tasks whose LLM-proposed commands
+    /// all succeeded are remembered with their embeddings, and the commands of the (at most 3) most
+    /// similar remembered tasks are shown to the LLM as examples for a new task. Nothing is
+    /// remembered by default.
+    ///
+    /// Every task sent to the LLM then costs one request to the embeddings API. Tasks are
+    /// remembered as sent to the LLM, i.e. redacted if redaction is enabled. A failed embeddings
+    /// request only leaves the task without recalled examples.
+    ///
+    /// # Arguments
+    /// * `endpoint_json`: A JSON object with the `api_url`, `model` and optional `api_key` of an
+    ///   embeddings API, or an empty string to stop remembering and forget every task.
+    ///   Example: `{"api_url": "https://api.openai.com/v1/embeddings", "api_key": "sk-...", "model": "text-embedding-3-small"}`
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` with an error message if `endpoint_json` is malformed.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_experience_memory(&mut self, endpoint_json: String) -> Result<(), JsValue> {
+        let endpoint = if endpoint_json.trim().is_empty() {
+            None
+        } else {
+            let endpoint: EmbeddingEndpoint = serde_json::from_str(&endpoint_json)
+                .map_err(|e| JsValue::from_str(&format!("Invalid embedding endpoint: {}", e)))?;
+            Some(endpoint)
+        };
+        self.agents.config_mut().memory.set_endpoint(endpoint);
+        Ok(())
+    }
+
+    /// Returns the number of tasks remembered since `set_experience_memory` was called.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn experience_memory_size(&self) -> usize {
+        self.agents.config().memory.count()
+    }
+
+    /// Forgets every remembered task, e.g. after the site changed. Learning continues.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn clear_experience_memory(&self) {
+        self.agents.config().memory.clear();
+    }
+
+    /// Runs this agent's DOM commands on the main thread instead of touching the DOM directly. Call it
+    /// on an agent created inside a Web Worker, so that LLM calls, response parsing and planning never
+    /// block the page; the page serves the commands with `serve_worker`.
+    ///
+    /// Each command is sent as one message and its result comes back as one message. Features that
+    /// observe the page over a whole run (page errors, DOM change tracking, plan validation against
+    /// the page) are not available in a worker.
+    ///
+    /// # Arguments
+    /// * `timeout_ms`: How long a command may take on the main thread before it fails, or `0` for the
+    ///   default of 30 seconds.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if the worker's message channel cannot be used.
+    #[wasm_bindgen]
+    pub fn enable_dom_proxy(&mut self, timeout_ms: u32) -> Result<(), JsValue> {
+        let proxy = DomProxy::connect(js_sys::global().into(), Some(timeout_ms).filter(|&timeout| timeout > 0))
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        self.agents.config_mut().dom_proxy = Some(proxy);
+        Ok(())
+    }
+
+    /// Performs the DOM commands of an agent running in `worker` (see `enable_dom_proxy`) on this page.
+    ///
+    /// Commands run with a copy of this agent's execution policy taken when this is called (origin
+    /// allowlist, eval policy, transactional execution).
+    ///
+    /// # Returns
+    /// A `WorkerHost` handle whose `stop()` stops serving the worker, or `Err(JsValue)` containing a
+    /// serialized `LibError` if the worker cannot be listened to.
+    #[wasm_bindgen]
+    pub fn serve_worker(&self, worker: web_sys::Worker) -> Result<WorkerHost, JsValue> {
+        let mut config = self.agents.config().clone();
+        config.dom_proxy = None;
+        let agents = Rc::new(AgentSystem::with_config(config));
+        let handler: DomRequestHandler = Rc::new(move |command| {
+            let agents = agents.clone();
+            Box::pin(async move { agents.perform_proxied_command(command).await })
+        });
+        worker::serve(worker.into(), handler)
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
+    }
+
+    /// Enables or disables looking up elements again when the page replaces them during a command.
+    /// Enabled by default, for every `RustAgent` on the page.
+    ///
+    /// Commands that keep using an element over time (`TYPE` with human-like typing, `SWIPE`,
+    /// `LONG_PRESS`) check that it is still attached to the page before each step. If a framework
+    /// re-rendered it, the command continues on the element now matching the selector; when
+    /// disabled, or if nothing matches anymore, the command fails with a `StaleElement` error.
+    ///
+    /// # Arguments
+    /// * `enabled`: `true` to look detached elements up again, `false` to fail instead.
+    #[wasm_bindgen]
+    pub fn set_reresolve_stale_elements(&self, enabled: bool) {
+        dom_utils::set_reresolve_stale_elements(enabled);
+    }
+
+    /// Adds translations for non-English sites, for every `RustAgent` on the page. They extend the
+    /// built-in German, French, Spanish, Italian, Portuguese and Dutch ones.
+    ///
+    /// # Arguments
+    /// * `dictionary_json`: A JSON object with optional `terms` and `commands`, e.g.
+    ///   `{"terms": {"accept": ["godkänn alla"], "close": ["stäng"]}, "commands": {"KLICKA": "CLICK"}}`.
+    ///   `terms` maps English words to translations matched by `text:` selectors and `DISMISS_OVERLAYS`
+    ///   (e.g. `text:accept` also finds a "Godkänn alla" button); `commands` maps translated command
+    ///   names to the commands they stand for in direct commands such as `KLICKA css:#buy`.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if the dictionary is malformed.
+    #[wasm_bindgen]
+    pub fn add_locale_dictionary(&self, dictionary_json: String) -> Result<(), JsValue> {
+        locale::add_dictionary(&dictionary_json)
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
+    }
+
+    /// Starts capturing the messages received by the page's WebSockets, for `GET_WS_MESSAGES` and
+    /// `WAIT_FOR_WS_MESSAGE`. Capture stays enabled until the page is unloaded.
+    ///
+    /// The page's `WebSocket` constructor is wrapped, so only sockets opened after this call are
+    /// observed: call it before the page connects (e.g. from a content script at `document_start`).
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if `WebSocket` is not available.
+    #[wasm_bindgen]
+    pub fn enable_websocket_capture(&self) -> Result<(), JsValue> {
+        network::install_websocket_hook()
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
+    }
+
+    /// Makes `navigator.geolocation` report the given position to the page, without a permission
+    /// prompt, for testing location-dependent flows. The `SET_GEOLOCATION` command does the same
+    /// from a task.
+    ///
+    /// # Arguments
+    /// * `latitude`, `longitude`: The position, in decimal degrees.
+    /// * `accuracy_m`: The accuracy reported, in meters.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if the coordinates are out of range or the
+    /// page's geolocation API cannot be overridden.
+    #[wasm_bindgen]
+    pub fn set_geolocation(&self, latitude: f64, longitude: f64, accuracy_m: f64) -> Result<(), JsValue> {
+        emulation::set_geolocation(latitude, longitude, accuracy_m)
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
+    }
+
+    /// Makes `Intl.DateTimeFormat` default to the given time zone, so that
+    /// `Intl.DateTimeFormat().resolvedOptions().timeZone` and dates formatted with `Intl` follow it.
+    /// `Date` methods such as `getTimezoneOffset` keep the browser's time zone.
+    ///
+    /// # Arguments
+    /// * `timezone`: An IANA time zone name, e.g. `"Europe/Paris"`, or `""` to restore the browser's.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if the time zone is unknown.
+    #[wasm_bindgen]
+    pub fn set_timezone(&self, timezone: String) -> Result<(), JsValue> {
+        emulation::set_timezone(&timezone)
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
+    }
+
+    /// Sets where `ASSERT_VISUAL_MATCH` keeps its baseline screenshots, e.g. `localStorage`,
+    /// IndexedDB or a server. Both callbacks may return a `Promise`.
+    ///
+    /// # Arguments
+    /// * `load`: Called as `load(key)`; returns the PNG data URL stored under `key`, or `null` if
+    ///   there is no baseline yet, in which case the current screenshot is saved as the baseline.
+    /// * `save`: Called as `save(key, data_url)` to store a new baseline.
+    #[wasm_bindgen]
+    pub fn set_baseline_storage(&mut self, load: js_sys::Function, save: js_sys::Function) {
+        self.agents.config_mut().baseline_storage = Some(Storage::Custom { load, save });
+    }
+
+    /// Selects where persistent data is kept: the baselines of `ASSERT_VISUAL_MATCH`, the run
+    /// history and the LLM audit log, whose entries from earlier sessions are loaded from it. Replaces any storage set with
+    /// `set_baseline_storage` or `enable_history_persistence`.
+    ///
+    /// # Arguments
+    /// * `kind`: `"memory"` (lost on reload), `"local_storage"` (survives reloads, but limited to a
+    ///   few megabytes, too little for many screenshots; main thread only) or `"indexed_db"`.
+    /// * `name`: The IndexedDB database name, e.g. `"rustagent"`; ignored by the other kinds.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if `kind` is unknown, the storage is
+    /// unavailable, or the stored history or audit log cannot be read.
+    #[wasm_bindgen]
+    pub async fn set_storage(&mut self, kind: String, name: String) -> Result<(), JsValue> {
+        let storage = Storage::open(&kind, &name)
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        self.reporting
+            .history
+            .enable_persistence(storage.clone())
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        #[cfg(not(feature = "dom-only"))]
+        self.agents
+            .config()
+            .llm_audit
+            .enable_persistence(storage.clone())
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        self.agents.config_mut().baseline_storage = Some(storage);
+        Ok(())
+    }
+
+    /// Starts capturing the page's `console.error` and `console.warn` calls, for `GET_CONSOLE_ERRORS`.
+    /// Capture stays enabled until the page is unloaded; messages still reach the console.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if the console cannot be wrapped.
+    #[wasm_bindgen]
+    pub fn enable_console_capture(&self) -> Result<(), JsValue> {
+        page_errors::install_console_hook()
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
+    }
+
+    /// Stores a secret (e.g. a password) that tasks can type with a `{{secret.<name>}}` placeholder,
+    /// as in `TYPE css:#password {{secret.password}}`. An empty value removes the secret.
+    ///
+    /// The value is only substituted when a `TYPE`, `TYPE_IF_EXISTS` or `LOGIN` command runs, so it never
+    /// appears in tasks, logs or LLM prompts; results mention the placeholder instead of the value.
+    /// Using a secret in any other command is refused with a `PolicyViolation` error. Secrets may
+    /// also be sent in the headers of the APIs given to `register_api`.
+    ///
+    /// # Arguments
+    /// * `name`: The name used in placeholders, e.g. `password` for `{{secret.password}}`.
+    /// * `value`: The secret value, or `""` to remove the secret.
+    #[wasm_bindgen]
+    pub fn set_secret(&mut self, name: String, value: String) {
+        self.agents.config_mut().secrets.set(&name, &value);
+    }
+
+    /// Sets the function `REQUEST_USER_INPUT` calls to ask the user for a value, such as the code of a
+    /// two-factor login, pausing the run until it answers.
+    ///
+    /// # Arguments
+    /// * `handler`: Called as `handler(prompt, name)`; returns the user's reply, or a `Promise` of it.
+    ///   Returning `null`, `undefined` or `""` (e.g. when the user cancels) makes the command fail
+    ///   with a `UserInput` error. Pass `null` to remove the handler.
+    #[wasm_bindgen]
+    pub fn set_user_input_handler(&mut self, handler: Option<js_sys::Function>) {
+        self.agents.config_mut().user_input_handler = handler;
+    }
+
+    /// Registers the OpenAPI 3 spec of a backend API, so that tasks and LLM plans can call its
+    /// operations alongside DOM actions, e.g. `CALL_API shop.getOrder {"orderId": 42}`.
+    ///
+    /// Once an API is registered, its operations (method, path, summary, parameters and request
+    /// body schema) are listed in the prompt. `CALL_API` checks its arguments against the spec before
+    /// making the request with `fetch`, and returns the HTTP status and response body; an unknown
+    /// operation or argument, a missing required parameter or a value of the wrong type fails with an
+    /// `Api` error without any request, as does a response whose status is not 2xx (with its `status`).
+    /// `CALL_API` is refused in previews and in an agent's region, and counts against `max_dom_mutations`.
+    ///
+    /// # Arguments
+    /// * `name`: The name operations are called by, e.g. `shop` for `shop.getOrder`. Registering
+    ///   another spec under the same name replaces it.
+    /// * `spec`: The OpenAPI 3 document, as JSON or YAML. Local `$ref`s are followed; operations
+    ///   without an `operationId` are named after their method and path (e.g. `get_orders_id`).
+    /// * `options_json`: Optionally, a JSON object with `base_url` (defaults to the spec's first
+    ///   server), `headers` sent with every call, which may hold `{{secret.<name>}}` placeholders
+    ///   (see `set_secret`), and `read_only` (only `GET` and `HEAD` operations are registered), e.g.
+    ///   `{"base_url": "/api", "headers": {"Authorization": "Bearer {{secret.api_token}}"}, "read_only": true}`.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` (`error_type` `Api`) if the name, spec or
+    /// options are invalid.
+    #[wasm_bindgen]
+    pub fn register_api(&mut self, name: String, spec: String, options_json: Option<String>) -> Result<(), JsValue> {
+        let api_error = |message: String| lib_error_to_js(LibError::Api { message, status: None });
+        let options: ApiOptions = match options_json.filter(|options| !options.trim().is_empty()) {
+            Some(options) => serde_json::from_str(&options).map_err(|e| api_error(format!("Invalid API options: {}", e)))?,
+            None => ApiOptions::default(),
+        };
+        let spec = ApiSpec::parse(&spec, options).map_err(api_error)?;
+        self.agents.config_mut().apis.register(&name, spec).map_err(api_error)
+    }
+
+    /// Removes the API registered under `name` with `register_api`.
+    ///
+    /// # Returns
+    /// `false` if no API was registered under that name.
+    #[wasm_bindgen]
+    pub fn unregister_api(&mut self, name: String) -> bool {
+        self.agents.config_mut().apis.unregister(&name)
+    }
+
+    /// Registers a function of the application that tasks and LLM plans can call with typed
+    /// arguments, e.g. `CALL_FUNCTION add_to_cart {"sku": "A-1", "quantity": 2}`.
+    ///
+    /// Registered functions are described to the LLM as tools, with their description and the JSON
+    /// schema of their arguments. `CALL_FUNCTION` checks the arguments against the schema before
+    /// calling the handler: unknown arguments, missing required ones and values of the wrong type
+    /// fail with a `HostFunction` error without calling it. Secrets cannot be passed as arguments.
+    /// `CALL_FUNCTION` is refused in previews and in an agent's region, and counts against `max_dom_mutations`.
+    ///
+    /// # Arguments
+    /// * `name`: The name the function is called by: a letter, then letters, digits and `_`.
+    ///   Registering another function under the same name replaces it.
+    /// * `definition_json`: A JSON object with the `description` of the function and optionally the
+    ///   JSON schema of its arguments as `parameters`, an object schema, e.g.
+    ///   `{"description": "Adds a product to the cart", "parameters": {"type": "object",
+    ///   "properties": {"sku": {"type": "string"}, "quantity": {"type": "integer"}}, "required": ["sku"]}}`.
+    /// * `handler`: Called as `handler(arguments)` with the arguments as an object; returns the
+    ///   result, or a `Promise` of it. Strings are returned as they are and other values as JSON.
+    ///   Throwing or rejecting fails the command with a `HostFunction` error.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` (`error_type` `HostFunction`) if the name
+    /// or definition is invalid.
+    #[wasm_bindgen]
+    pub fn register_function(&mut self, name: String, definition_json: String, handler: js_sys::Function) -> Result<(), JsValue> {
+        let host_function_error = |message: String| lib_error_to_js(LibError::HostFunction { message });
+        let signature = FunctionSignature::parse(&definition_json).map_err(host_function_error)?;
+        self.agents.config_mut().functions.register(&name, HostFunction { signature, handler }).map_err(host_function_error)
+    }
+
+    /// Removes the function registered under `name` with `register_function`.
+    ///
+    /// # Returns
+    /// `false` if no function was registered under that name.
+    #[wasm_bindgen]
+    pub fn unregister_function(&mut self, name: String) -> bool {
+        self.agents.config_mut().functions.unregister(&name)
+    }
+
+    /// Makes runs pause while a captcha (reCAPTCHA, hCaptcha or Turnstile) is shown on the page,
+    /// so that a person can solve it, instead of failing on the blocked page.
+    ///
+    /// Before each command that acts on the page, the page is checked for a visible, unsolved
+    /// captcha. If there is one, a `captcha_detected` `CustomEvent` is dispatched on `window`, with
+    /// `detail` `{"provider": "hCaptcha", "element": "div.h-captcha"}`, and the run waits until the
+    /// captcha is solved (its token is set or it goes away) or `captcha_solved` is called. A captcha
+    /// still unsolved after `timeout_ms` fails the command with a `UserInput` error. Not available
+    /// to an agent in a worker.
+    ///
+    /// # Arguments
+    /// * `enabled`: Whether to look for captchas. Disabled by default.
+    /// * `timeout_ms`: How long to wait for a captcha to be solved, or 0 for 5 minutes.
+    #[wasm_bindgen]
+    pub fn set_captcha_handoff(&mut self, enabled: bool, timeout_ms: u32) {
+        let timeout_ms = if timeout_ms == 0 { captcha::DEFAULT_CAPTCHA_TIMEOUT_MS } else { timeout_ms };
+        self.agents.config_mut().captcha_timeout_ms = Some(timeout_ms).filter(|_| enabled);
+    }
+
+    /// Tells a run paused for a captcha (see `set_captcha_handoff`) that it was solved, e.g. when
+    /// the user confirms it in the host's UI, so that it resumes.
+    #[wasm_bindgen]
+    pub fn captcha_solved(&self) {
+        captcha::mark_solved();
+    }
+
+    /// Stores a value that commands can use with a `{{var.<name>}}` placeholder, as in
+    /// `TYPE css:#code {{var.otp}}`, substituted when the command runs. `REQUEST_USER_INPUT` stores
+    /// the user's replies the same way. Unlike secrets, values appear in results. An empty value
+    /// removes the variable.
+    #[wasm_bindgen]
+    pub fn set_variable(&self, name: String, value: String) {
+        self.agents.config().variables.set(&name, &value);
+    }
+
+    /// Returns the value of a variable set with `set_variable` or `REQUEST_USER_INPUT`, or `undefined`.
+    #[wasm_bindgen]
+    pub fn get_variable(&self, name: String) -> Option<String> {
+        self.agents.config().variables.get(&name)
+    }
+
+    /// Sets the URL the result envelope of every finished run (`automate`, `run_workflow`,
+    /// scheduled and triggered runs) is POSTed to as JSON. An empty string disables the webhook.
+    ///
+    /// The envelope has the form
+    /// `{"source":"rustagent","schema_version":3,"run":"automate","success":true,"started_at_ms":...,"finished_at_ms":...,"results":[...],"page_errors":[...]}`,
+    /// where `results` has the same shape as the result of `automate` and `page_errors` lists the
+    /// uncaught exceptions and unhandled promise rejections the page raised during the run.
+    /// Webhook failures are logged and do not affect the run.
+    ///
+    /// # Arguments
+    /// * `url`: The webhook URL, or `""` to stop posting results.
+    #[wasm_bindgen]
+    pub fn set_result_webhook(&mut self, url: String) {
+        self.reporting.webhook_url = Some(url).filter(|url| !url.is_empty());
+    }
+
+    /// Makes every finished run send its result envelope (see `set_result_webhook`) to the parent
+    /// frame with `window.postMessage`, so agents embedded in iframes can report to their host.
+    /// When the page is not framed, the message is posted to the page itself.
+    ///
+    /// # Arguments
+    /// * `target_origin`: The origin the parent frame must have for the message to be delivered
+    ///   (e.g. `https://host.example`, or `*` for any origin), or `""` to stop posting results.
+    #[wasm_bindgen]
+    pub fn set_result_post_message(&mut self, target_origin: String) {
+        self.reporting.post_message_origin = Some(target_origin).filter(|origin| !origin.is_empty());
+    }
+
+    /// Pins the version of the shape of task results (see `result_schema`), so that later changes
+    /// of the result format do not break the host. While a version is pinned, `automate`,
+    /// `run_workflow`, `apply_preview` and the callbacks of scheduled and triggered runs resolve
+    /// to what they did in that version, and streamed results and result envelopes keep its
+    /// shape. Version 1 is the bare array of results without `code`, as before results were
+    /// versioned; version 2 is `{"schema_version": 2, "results": [...]}`, and version 3 adds the
+    /// `summary` of the run (but for `apply_preview`), to envelopes too. Result envelopes always
+    /// carry their `schema_version`.
+    ///
+    /// # Arguments
+    /// * `version`: The version, from 1 to the current one (3), or `undefined` for the output of
+    ///   the current version, the default.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` if the version is unknown.
+    #[wasm_bindgen]
+    pub fn set_result_schema_version(&mut self, version: Option<u32>) -> Result<(), JsValue> {
+        self.reporting.schema = result_schema::pin(version).map_err(|message| JsValue::from_str(&message))?;
+        Ok(())
+    }
+
+    /// Returns past runs (`automate`, workflows, streams, schedules and triggers), most recent
+    /// first. The agent keeps the last 50 runs unless `set_history_limit` says otherwise.
+    ///
+    /// # Arguments
+    /// * `filter_json`: Optional JSON filter; every field is optional, e.g.
+    ///   `{"run": "workflow:", "success": false, "since_ms": 1700000000000, "feedback": true, "limit": 10}`.
+    ///   `run` matches run labels by prefix; `feedback` selects the runs with (or without) feedback
+    ///   reported with `report_feedback`, e.g. to export it.
+    ///
+    /// # Returns
+    /// A JSON array of `{"id", "run", "tasks", "success", "started_at_ms", "finished_at_ms", "results"}`
+    /// objects, with a `feedback` object (`{"success", "note", "reported_at_ms"}`) on runs that got
+    /// feedback, where `results` has the same shape as the result of `automate`, or `Err(JsValue)`
+    /// containing a serialized `LibError` (`error_type` `Serialization`) if the filter is malformed.
+    #[wasm_bindgen]
+    pub fn get_history(&self, filter_json: Option<String>) -> Result<String, JsValue> {
+        let filter: HistoryFilter = match filter_json {
+            Some(json) => serde_json::from_str(&json).map_err(|e| {
+                lib_error_to_js(LibError::Serialization { message: format!("Invalid history filter: {}", e) })
+            })?,
+            None => HistoryFilter::default(),
+        };
+        serde_json::to_string(&self.reporting.history.query(&filter))
+            .map_err(|e| lib_error_to_js(LibError::Serialization { message: e.to_string() }))
+    }
+
+    /// Exports a past run as a Playwright (TypeScript) or Selenium (Python, pytest) test, so that a
+    /// prototype that worked in the browser can join an existing test suite.
+    ///
+    /// Direct DOM commands become the framework's calls and `TRY` / `CATCH` blocks become
+    /// `try` / `catch` blocks; tasks the LLM planned are left as `TODO` comments, as are commands and
+    /// flow steps the framework has no equivalent for. `{{PREVIOUS_RESULT}}` becomes a variable
+    /// holding the value read by the latest reading command.
+    ///
+    /// # Arguments
+    /// * `run_id`: The `id` of the run, from its result envelope or `get_history`.
+    /// * `format`: `playwright` or `selenium`.
+    ///
+    /// # Returns
+    /// The script, or `Err(JsValue)` containing a serialized `LibError` (`error_type` `Export`) if
+    /// the run is no longer in the history or the format is unknown.
+    #[wasm_bindgen]
+    pub fn export_run_script(&self, run_id: String, format: String) -> Result<String, JsValue> {
+        let format = script_format(&format)?;
+        let record = self.reporting.history.get(&run_id).ok_or_else(|| {
+            lib_error_to_js(LibError::Export { message: format!("No run with id '{}' in the history", run_id) })
+        })?;
+        Ok(export::export_script(&format!("{} {}", record.run, record.id), &record.tasks, format))
+    }
+
+    /// Forgets every past run, including persisted runs (see `set_storage`).
+    #[wasm_bindgen]
+    pub fn clear_history(&self) {
+        self.reporting.history.clear();
+    }
+
+    /// Sets how many past runs are kept; the oldest runs beyond the limit are dropped, and 0 stops
+    /// recording runs.
+    #[wasm_bindgen]
+    pub fn set_history_limit(&self, limit: u32) {
+        self.reporting.history.set_limit(limit as usize);
+    }
+
+    /// Reports whether a run achieved what it was meant to, e.g. after the user checked the order it
+    /// placed. A run whose commands all succeeded may still have done the wrong thing.
+    ///
+    /// The feedback is attached to the run in the history, where it can be exported with
+    /// `get_history('{"feedback": true}')`, and applied to the tasks the experience memory remembered
+    /// during the run (see `set_experience_memory`): they are recalled before other tasks after
+    /// positive feedback, and forgotten after negative feedback. Reporting again replaces the feedback.
+    ///
+    /// # Arguments
+    /// * `run_id`: The `id` of the run, from its result envelope or `get_history`.
+    /// * `success`: Whether the run did what was meant.
+    /// * `note`: An optional comment kept with the feedback, e.g. `"Picked the wrong size"`.
+    ///
+    /// # Returns
+    /// `true` if the run is in the history or remembered tasks belong to it, `false` if it is unknown.
+    #[wasm_bindgen]
+    pub fn report_feedback(&self, run_id: String, success: bool, note: Option<String>) -> bool {
+        let feedback = RunFeedback { success, note, reported_at_ms: js_sys::Date::now() };
+        let recorded = self.reporting.history.set_feedback(&run_id, feedback);
+        #[cfg(not(feature = "dom-only"))]
+        let recorded = self.agents.config().memory.apply_feedback(&run_id, success) > 0 || recorded;
+        recorded
+    }
+
+    /// Keeps the run history and the LLM audit log in IndexedDB so that they survive page reloads.
+    /// Runs and LLM calls persisted by earlier sessions are loaded, before those of this session.
+    ///
+    /// # Arguments
+    /// * `database_name`: The IndexedDB database to use, e.g. `"rustagent"`.
+    ///
+    /// # Returns
+    /// `Err(JsValue)` containing a serialized `LibError` if IndexedDB is unavailable or the stored
+    /// history or audit log cannot be read.
+    #[wasm_bindgen]
+    pub async fn enable_history_persistence(&self, database_name: String) -> Result<(), JsValue> {
+        let storage = Storage::indexed_db(&database_name)
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        self.reporting
+            .history
+            .enable_persistence(storage.clone())
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        #[cfg(not(feature = "dom-only"))]
+        self.agents
+            .config()
+            .llm_audit
+            .enable_persistence(storage)
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+        Ok(())
+    }
+
+    /// Returns the prompts and responses of the LLM calls made while planning tasks, picking among
+    /// matching elements and answering `LLM_DECIDE` / `LLM_ASSERT` questions, oldest first, e.g. to
+    /// show what data left the browser. The agent keeps the last 200 calls unless
+    /// `set_llm_audit_limit` says otherwise, and persists them with the run history (see `set_storage`).
+    ///
+    /// Prompts and responses are logged after PII redaction (see `set_pii_redaction`); without it,
+    /// they are logged as sent and received. API keys are never logged. Token counts are estimated
+    /// at 4 characters per token, as in `benchmark_models`.
+    ///
+    /// # Arguments
+    /// * `run_id`: Only the calls of this run (the `id` of its result envelope or `get_history`
+    ///   entry), or every call if omitted.
+    ///
+    /// # Returns
+    /// A JSON array of `{"run_id", "purpose", "model", "api_url", "prompt", "response", "error",
+    /// "started_at_ms", "finished_at_ms", "prompt_tokens", "response_tokens"}` objects, where
+    /// `purpose` is `plan`, `disambiguation` or `judgment`, `response` is `null` for failed calls and
+    /// `error` for successful ones.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn get_llm_audit_log(&self, run_id: Option<String>) -> String {
+        let exchanges = self.agents.config().llm_audit.exchanges(run_id.as_deref());
+        serde_json::to_string(&exchanges).unwrap_or_default()
+    }
+
+    /// Forgets every logged LLM call, including persisted ones.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn clear_llm_audit_log(&self) {
+        self.agents.config().llm_audit.clear();
+    }
+
+    /// Sets how many LLM calls the audit log keeps; the oldest calls beyond the limit are dropped,
+    /// and 0 stops logging calls.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub fn set_llm_audit_limit(&self, limit: u32) {
+        self.agents.config().llm_audit.set_limit(limit as usize);
+    }
+
+    /// Automates a list of tasks provided as a JSON string.
+    ///
+    /// Each task in the list is processed sequentially. If a task string contains the
+    /// placeholder `{{PREVIOUS_RESULT}}`, it will be substituted with the successful
+    /// output of the immediately preceding task. If the preceding task failed,
+    /// `{{PREVIOUS_RESULT}}` is replaced with an empty string.
+    ///
+    /// Tasks may be grouped in `TRY` / `CATCH` / `END_TRY` blocks: when a task of the `TRY` part
+    /// fails, the rest of it is skipped and the `CATCH` steps run, with `{{error}}` replaced by the
+    /// error message. Markers and skipped tasks produce no result.
+    ///
+    /// `LABEL <name>` marks a place in the list and `GOTO <name> [max_jumps]` continues with the
+    /// tasks after it, e.g. to retry from a `CATCH` part. Each `GOTO` jumps at most `max_jumps`
+    /// (default 10) times; the jump after that fails with a `Workflow` error instead.
+    ///
+    /// `WHILE [NOT] <condition> [MAX <n>]` ... `END_WHILE` runs the tasks in between as long as the
+    /// condition holds. The condition is a predicate command (`ELEMENT_EXISTS`, `IS_VISIBLE`,
+    /// `IS_ENABLED`, `IS_EDITABLE` or `IS_SELECTED`) or a yes/no question for the LLM. A loop fails
+    /// with a `Workflow` error when its condition still holds after `n` (default 20) iterations.
+    ///
+    /// # Arguments
+    /// * `tasks_json`: A JSON string representing a list of tasks.
+    ///   Example: `["CLICK css:#button", "READ css:#label {{PREVIOUS_RESULT}}"]`
+    ///   A YAML sequence is accepted too (anything not starting with `[`), and items may be
+    ///   named steps, e.g. `- name: Read label` / `  task: READ css:#label`.
+    ///
+    /// # Returns
+    /// A `Result` which, if successful (`Ok`), contains a `JsValue` that is a JSON string of the
+    /// output of the current result schema version (see `result_schema`),
+    /// `{"schema_version": 3, "results": [...], "summary": {...}}`. Each item of `results`
+    /// corresponds to the outcome of a task in the input list, with a stable `code` such as
+    /// `CLICK_OK` or `ELEMENT_NOT_FOUND` (see `result_code`) next to it:
+    ///   - `Ok(String)`: Contains the success message or result string from the task.
+    ///     If the task involved LLM-returned commands, this string itself might be a
+    ///     JSON representation of `Vec<Result<String, LibError>>` for those sub-commands (though currently it's Vec<Result<String,String>> for inner commands).
+    ///   - `Err(LibError)`: Contains the structured error if the task failed.
+    ///
+    /// The `summary` of the run has its `total`, `succeeded`, `failed` and `skipped` tasks,
+    /// `duration_ms` and `first_error` (see `run_summary::RunSummary`). Once a result schema
+    /// version is pinned with `set_result_schema_version`, the JSON string is the output of that
+    /// version instead, e.g. the bare array of results for version 1.
+    ///
+    /// If initial checks fail (e.g., LLM config not set, invalid `tasks_json`),
+    /// it returns `Err(JsValue)` with an error message (this error is a simple string, not LibError).
+    #[wasm_bindgen]
+    pub async fn automate(&self, tasks_json: String) -> Result<JsValue, JsValue> {
+        // 1. LLM Configuration Check: Ensure API key, URL, and model name are set.
+        let (api_key, api_url, model_name) = self.llm_config()?;
+
+        // 2. Parse tasks_json: Deserialize the input JSON string into a vector of task strings.
+        let tasks = parse_tasks(&tasks_json)?;
+
+        task_list_output(&self.reporting.schema, run_task_list(&self.agents, &self.reporting, "automate", tasks, api_key, api_url, model_name).await)
+    }
+
+    /// Runs a list of tasks like `automate`, but yields the result of each task as soon as it has
+    /// run instead of collecting them all, so very long task lists can be consumed incrementally.
+    ///
+    /// The returned object is an async iterable: `for await (const result of agent.automate_stream(tasks))`.
+    /// Each task runs when the next result is requested, and each yielded value is the JSON string of
+    /// one `TaskResult`, the same shape as the items of the `results` `automate` resolves to.
+    /// Leaving the loop early (or calling `return()`) stops the run without running the remaining
+    /// tasks. The result envelope is reported once the run has ended, with the tasks that ran.
+    ///
+    /// The tasks run with a copy of the execution policy in effect when `automate_stream` is called;
+    /// changes made by streamed runs are not recorded for `rollback_last_run`.
+    ///
+    /// # Returns
+    /// The async iterable, or `Err(JsValue)` with the same error messages as `automate` if the tasks
+    /// or LLM configuration are invalid. Requesting a result while the previous task is still
+    /// running rejects.
+    #[wasm_bindgen]
+    pub fn automate_stream(&self, tasks_json: String) -> Result<js_sys::Object, JsValue> {
+        let (api_key, api_url, model_name) = self.llm_config()?;
+        let llm_config = (api_key.to_string(), api_url.to_string(), model_name.to_string());
+        let tasks = parse_tasks(&tasks_json)?;
+
+        let agents = AgentSystem::with_config(self.agents.config().clone());
+        let run = TaskRun::start(&agents, "automate_stream", tasks);
+        task_stream(TaskStreamState { run, agents, reporting: self.reporting.clone(), llm_config })
+    }
+
+    /// Runs a list of tasks once, after a delay, without blocking the caller.
+    ///
+    /// The tasks run with a copy of the LLM configuration and execution policy in effect when
+    /// `schedule` is called. Changes made by scheduled runs are not recorded for `rollback_last_run`.
+    ///
+    /// # Arguments
+    /// * `tasks_json`: The tasks to run, in the same format as for `automate`.
+    /// * `delay_ms`: How long to wait before running the tasks, in milliseconds.
+    /// * `on_result`: Optional callback, called with the same JSON results string `automate`
+    ///   resolves to once the run has finished.
+    ///
+    /// # Returns
+    /// A `ScheduledRun` handle whose `cancel()` prevents the run if it has not started yet, or
+    /// `Err(JsValue)` with the same error messages as `automate` if the tasks or LLM configuration are invalid.
+    #[wasm_bindgen]
+    pub fn schedule(&self, tasks_json: String, delay_ms: u32, on_result: Option<js_sys::Function>) -> Result<ScheduledRun, JsValue> {
+        self.schedule_tasks(&tasks_json, delay_ms, None, on_result)
+    }
+
+    /// Runs a list of tasks every `interval_ms` milliseconds until cancelled, e.g. to check a
+    /// price every 5 minutes and report it. The first run starts after one interval.
+    ///
+    /// The interval is measured from the end of the previous run, so runs never overlap.
+    /// Like `schedule`, runs use a copy of the configuration in effect when this is called.
+    ///
+    /// # Arguments
+    /// * `tasks_json`: The tasks to run, in the same format as for `automate`.
+    /// * `interval_ms`: The time between runs, in milliseconds. Must not be `0`.
+    /// * `on_result`: Optional callback, called with the JSON results string of every run.
+    ///
+    /// # Returns
+    /// A `ScheduledRun` handle whose `cancel()` stops further runs, or `Err(JsValue)` with an error message.
+    #[wasm_bindgen]
+    pub fn schedule_recurring(&self, tasks_json: String, interval_ms: u32, on_result: Option<js_sys::Function>) -> Result<ScheduledRun, JsValue> {
+        if interval_ms == 0 {
+            return Err(JsValue::from_str("The interval of a recurring schedule must be greater than 0ms."));
+        }
+        self.schedule_tasks(&tasks_json, interval_ms, Some(interval_ms), on_result)
+    }
+
+    /// Registers a named, parameterized workflow that can later be run with `run_workflow`.
+    /// Registering a workflow under an existing name replaces it.
+    ///
+    /// # Arguments
+    /// * `workflow_json`: A JSON or YAML string describing the workflow. Steps use the same syntax as
+    ///   `automate` tasks and may reference declared parameters with `{{params.<name>}}`.
+    ///   Example: `{"name": "login", "params": [{"name": "email"}, {"name": "plan", "default": "free"}],
+    ///   "steps": ["TYPE css:#email {{params.email}}", "SELECTOPTION css:#plan {{params.plan}}", "CLICK css:#submit"]}`
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or `Err(JsValue)` containing a serialized `LibError` (`error_type` `Workflow`)
+    /// if the definition is malformed or references undeclared parameters.
+    #[wasm_bindgen]
+    pub fn register_workflow(&mut self, workflow_json: String) -> Result<(), JsValue> {
+        let definition = WorkflowDefinition::parse(&workflow_json).map_err(|e| lib_error_to_js(LibError::from(e)))?;
+        self.workflows.register(definition);
+        Ok(())
+    }
+
+    /// Runs a workflow previously registered with `register_workflow`.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the workflow.
+    /// * `params_json`: A JSON object of parameter values, e.g. `{"email": "user@example.com"}`.
+    ///   Non-string values are converted to their JSON text. Declared parameters with a default may be omitted.
+    ///
+    /// # Returns
+    /// The same result as `automate` for the workflow's rendered steps. If the workflow is unknown
+    /// or the parameters don't match its declaration, returns `Err(JsValue)` containing a serialized
+    /// `LibError` (`error_type` `Workflow`).
+    #[wasm_bindgen]
+    pub async fn run_workflow(&self, name: String, params_json: String) -> Result<JsValue, JsValue> {
+        let (api_key, api_url, model_name) = self.llm_config()?;
+        let params: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&params_json).map_err(|e| {
+            lib_error_to_js(LibError::Workflow { message: format!("Invalid JSON parameters: expected an object ({})", e) })
+        })?;
+        let params = workflow::params_from_json(&params);
+
+        let tasks = self.workflows.render(&name, &params).map_err(|e| lib_error_to_js(LibError::from(e)))?;
+        web_sys::console::log_1(&format!("Running workflow '{}' with {} steps", name, tasks.len()).into());
+        let run = run_task_list(&self.agents, &self.reporting, &format!("workflow:{}", name), tasks, api_key, api_url, model_name).await;
+        task_list_output(&self.reporting.schema, run)
+    }
+
+    /// Imports the page interactions of a Puppeteer or Playwright script into a task list, on a
+    /// best-effort basis, to migrate existing automations to the agent.
+    ///
+    /// Calls such as `page.click(selector)`, `page.type(selector, text)`, `page.fill(...)`,
+    /// `page.waitForSelector(selector, { timeout })` and `page.goto(url)`, and the same actions on
+    /// locators (`page.locator(selector).click()`, `page.getByText(text).click()`), become direct DOM
+    /// commands, with their selectors converted. The script is not run: only calls on the page with
+    /// literal arguments are imported.
+    ///
+    /// # Arguments
+    /// * `script`: The source of the script, in JavaScript or TypeScript.
+    ///
+    /// # Returns
+    /// A JSON object `{"tasks": [...], "skipped": [{"line", "code", "reason"}]}`, where `tasks` can be
+    /// passed to `automate` or used as the steps of a workflow and `skipped` lists the calls on the
+    /// page that could not be imported, or `Err(JsValue)` containing a serialized `LibError`
+    /// (`error_type` `Serialization`) if the result cannot be serialized.
+    #[wasm_bindgen]
+    pub fn import_script(&self, script: String) -> Result<String, JsValue> {
+        serde_json::to_string(&import::import_script(&script))
+            .map_err(|e| lib_error_to_js(LibError::Serialization { message: e.to_string() }))
+    }
+
+    /// Exports a workflow registered with `register_workflow` as a Playwright or Selenium test, like
+    /// `export_run_script`, with its steps rendered with `params_json`.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the workflow.
+    /// * `params_json`: A JSON object of parameter values, as for `run_workflow`.
+    /// * `format`: `playwright` or `selenium`.
+    ///
+    /// # Returns
+    /// The script, or `Err(JsValue)` containing a serialized `LibError`: `error_type` `Workflow` if
+    /// the workflow is unknown or the parameters don't match its declaration, `Export` if the format
+    /// is unknown.
+    #[wasm_bindgen]
+    pub fn export_workflow_script(&self, name: String, params_json: String, format: String) -> Result<String, JsValue> {
+        let format = script_format(&format)?;
+        let params: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&params_json).map_err(|e| {
+            lib_error_to_js(LibError::Workflow { message: format!("Invalid JSON parameters: expected an object ({})", e) })
+        })?;
+        let tasks = self.workflows.render(&name, &workflow::params_from_json(&params)).map_err(|e| lib_error_to_js(LibError::from(e)))?;
+        Ok(export::export_script(&format!("workflow:{}", name), &tasks, format))
+    }
+
+    /// Runs the same tasks with each of several models and reports how each did, to help choose a
+    /// model for the site.
+    ///
+    /// Each model's tasks run like an `automate` call labelled `benchmark:<name>`, with a copy of
+    /// the execution policy in which transactional execution is enabled and experience memory is
+    /// off, so that no model learns from another's run. The attribute and form values a model's
+    /// tasks change are rolled back before the next model runs; navigation and other changes are
+    /// not, so the tasks should leave the page where they found it. With the `mock-llm` feature,
+    /// every model gets the mock responses, which makes benchmarks safe to try out in tests.
+    ///
+    /// # Arguments
+    /// * `tasks_json`: The tasks, as for `automate`.
+    /// * `models_json`: A JSON array of models, e.g. `[{"model": "gpt-4o-mini"}, {"name": "local",
+    ///   "model": "llama3", "api_url": "http://localhost:11434/v1/chat/completions", "api_key": ""}]`.
+    ///   `api_url` and `api_key` default to those set with `set_llm_config`; `name` defaults to `model`.
+    ///
+    /// # Returns
+    /// A JSON array with, for each model in order, `{name, model, run_id, tasks, succeeded,
+    /// success_rate, total_ms, mean_task_ms, llm_calls, llm_chars, estimated_tokens, results}`,
+    /// where `results` are those `automate` would resolve to and `estimated_tokens` assumes four
+    /// characters per token. `Err(JsValue)` if the tasks or models are invalid, or a model has no
+    /// API URL or key and `set_llm_config` was not called.
+    #[cfg(not(feature = "dom-only"))]
+    #[wasm_bindgen]
+    pub async fn benchmark(&self, tasks_json: String, models_json: String) -> Result<String, JsValue> {
+        let tasks = parse_tasks(&tasks_json)?;
+        let models = benchmark::parse_models(&models_json).map_err(|e| JsValue::from_str(&format!("{}.", e)))?;
+        let mut endpoints = Vec::with_capacity(models.len());
+        for model in &models {
+            let api_url = model.api_url.as_deref().or(self.api_url.as_deref());
+            let api_key = model.api_key.as_deref().or(self.api_key.as_deref());
+            match (api_url, api_key) {
+                (Some(api_url), Some(api_key)) => endpoints.push((api_url, api_key)),
+                _ => return Err(JsValue::from_str(&format!(
+                    "Model '{}' has no API URL or key. Please give them or call set_llm_config first.",
+                    model.label()
+                ))),
+            }
+        }
+
+        let mut config = self.agents.config().clone();
+        config.transactional = true;
+        config.memory = Default::default();
+        let agents = AgentSystem::with_config(config);
+        let mut reports = Vec::with_capacity(models.len());
+        for (model, (api_url, api_key)) in models.iter().zip(endpoints) {
+            web_sys::console::log_1(&format!("Benchmarking {} tasks with model '{}'", tasks.len(), model.label()).into());
+            let usage_before = budget::total_usage();
+            let started_at_ms = js_sys::Date::now();
+            let mut run = TaskRun::start(&agents, &format!("benchmark:{}", model.label()), tasks.clone());
+            while run.run_next(&agents, api_key, api_url, &model.model).await.is_some() {}
+            let total_ms = js_sys::Date::now() - started_at_ms;
+            let run_id = run.id.clone();
+            let (results, _summary) = run.finish(&agents, &self.reporting);
+            agents.rollback_last_run();
+            reports.push(benchmark::summarize(model, &run_id, results, total_ms, &usage_before, &budget::total_usage()));
+        }
+        serde_json::to_string(&reports).map_err(|e| {
+            lib_error_to_js(LibError::Serialization { message: format!("Failed to serialize the benchmark report: {}", e) })
+        })
+    }
+
+    /// Runs tasks against a copy of a container instead of the page and reports what they would
+    /// change, so that the changes can be approved before `apply_preview` makes them on the page.
+    ///
+    /// The container (e.g. a form) is deep-copied into a hidden element at the end of the page,
+    /// and the tasks run like an `automate` call labelled `preview`, with every selector (including
+    /// those of LLM-proposed commands) resolved in the copy. Commands acting beyond the container
+    /// (`NAVIGATE`, `EVAL_JS`, `INJECT_CSS`, coordinates, focus order, tours...) fail with a
+    /// `PolicyViolation` error. Links and form submissions in the copy do nothing. The copy keeps
+    /// attributes, form state and inline event handlers, but not the listeners scripts attached,
+    /// so the preview shows what the commands change, not how the site would react. While a
+    /// preview runs, every agent on the page resolves selectors in the copy, so it should not
+    /// overlap other runs. The copy is removed afterwards.
+    ///
+    /// The commands that succeeded are kept until `apply_preview` or `discard_preview` is called,
+    /// or the next preview replaces them. They include those the LLM proposed, so the page gets
+    /// exactly the previewed commands and the LLM is not asked again.
+    ///
+    /// # Arguments
+    /// * `container_selector`: The element to copy, e.g. `css:#checkout`.
+    /// * `tasks_json`: The tasks, as for `automate`.
+    ///
+    /// # Returns
+    /// The JSON report, e.g. `{"container":"css:#checkout","results":[{"Ok":"...","code":"TYPE_OK"}],
+    /// "diff":{"added":[],"removed":[],"changed":["css:#checkout > input#email"]},"commands":1}`,
+    /// where `results` are those `automate` would resolve to and `diff` lists the changed
+    /// elements by path from the container. `Err(JsValue)` if the tasks or LLM configuration are
+    /// invalid, the container cannot be copied, another preview is running, or the agent is in a worker.
+    #[wasm_bindgen]
+    pub async fn preview(&self, container_selector: String, tasks_json: String) -> Result<String, JsValue> {
+        let (api_key, api_url, model_name) = self.llm_config()?;
+        let tasks = parse_tasks(&tasks_json)?;
+        if self.agents.config().dom_proxy.is_some() {
+            return Err(JsValue::from_str("Previews are not available to an agent in a worker."));
+        }
+
+        let mut config = self.agents.config().clone();
+        config.transactional = false;
+        #[cfg(not(feature = "dom-only"))]
+        {
+            config.memory = Default::default();
         }
+        let agents = AgentSystem::with_config(config);
+        let scope = SandboxScope::start(&container_selector).map_err(|e| lib_error_to_js(LibError::from(AgentError::from(e))))?;
+        let mut run = TaskRun::start(&agents, "preview", tasks);
+        while run.run_next(&agents, api_key, api_url, model_name).await.is_some() {}
+        let (results, _summary) = run.finish(&agents, &self.reporting);
+        let diff = scope.diff();
+        let commands = scope.performed();
+        drop(scope);
+
+        let report = PreviewReport { container: container_selector.clone(), results, diff, commands: commands.len() };
+        *self.pending_preview.borrow_mut() = Some(PendingPreview { container_selector, commands });
+        serde_json::to_string(&report).map_err(|e| {
+            lib_error_to_js(LibError::Serialization { message: format!("Failed to serialize the preview report: {}", e) })
+        })
     }
 
-    /// Sets the configuration for the Large Language Model (LLM) to be used by the agents.
-    /// All parameters are required to enable LLM-based task processing.
+    /// Runs the commands of the last `preview` on the page, in order, with the current execution
+    /// policy, stopping at the first failure. The changes can be undone with `rollback_last_run`
+    /// if transactional execution is enabled.
+    ///
+    /// # Returns
+    /// The result of each command that ran, in the shape `automate` resolves to, without a
+    /// `summary`. `Err(JsValue)` if there is no preview to apply or its container is no longer on the page.
+    #[wasm_bindgen]
+    pub async fn apply_preview(&self) -> Result<JsValue, JsValue> {
+        let preview = self
+            .pending_preview
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| JsValue::from_str("No preview to apply. Call preview first."))?;
+        let (_window, document) = dom_utils::get_window_document().map_err(|e| lib_error_to_js(LibError::from(AgentError::from(e))))?;
+        dom_utils::get_element(&document, &preview.container_selector).map_err(|e| lib_error_to_js(LibError::from(AgentError::from(e))))?;
+        self.agents.begin_run(&history::new_run_id(js_sys::Date::now()));
+        let results = self.agents.apply_commands(&preview.commands).await;
+        self.reporting.schema.output_json(&results, None).map(|json| JsValue::from_str(&json)).map_err(|e| {
+            lib_error_to_js(LibError::Serialization { message: format!("Failed to serialize the applied preview results: {}", e) })
+        })
+    }
+
+    /// Forgets the commands of the last `preview` without running them.
+    #[wasm_bindgen]
+    pub fn discard_preview(&self) {
+        self.pending_preview.borrow_mut().take();
+    }
+
+    /// Runs a registered workflow whenever the page changes in a given way, turning a workflow
+    /// into a reactive assistant (e.g. dismissing a cookie banner whenever one appears).
     ///
     /// # Arguments
-    /// * `api_url`: The URL of the LLM API endpoint.
-    /// * `model_name`: The specific model name to use (e.g., "gpt-3.5-turbo").
-    /// * `api_key`: The API key for authentication with the LLM service.
+    /// * `trigger_json`: A JSON object naming the workflow, its parameters and the condition, e.g.
+    ///   `{"workflow": "dismiss_cookies", "when": {"selector_appears": "css:#cookie-banner"}, "once": true}`.
+    ///   `when` is one of `{"selector_appears": <selector>}`, `{"url_matches": <pattern with * wildcards>}`
+    ///   or `{"event": <DOM event name>}`. `params` (an object) and `once` (default `false`) are optional.
+    /// * `on_result`: Optional callback, called with the JSON results string of every run.
+    ///
+    /// The workflow is rendered with its parameters when the trigger is added, so re-registering
+    /// the workflow later does not change an existing trigger. Like scheduled runs, triggered
+    /// runs use a copy of the configuration in effect when the trigger is added.
+    ///
+    /// # Returns
+    /// A `TriggerHandle` whose `cancel()` removes the trigger. Keep a reference to it: the trigger
+    /// is removed when the handle is freed. Returns `Err(JsValue)` containing a serialized `LibError`
+    /// if the trigger is malformed (`CommandParse`), the workflow cannot be rendered (`Workflow`) or
+    /// the selector is invalid (`DomOperation`).
     #[wasm_bindgen]
-    pub fn set_llm_config(&mut self, api_url: String, model_name: String, api_key: String) {
-        self.api_url = Some(api_url);
-        self.model_name = Some(model_name);
-        self.api_key = Some(api_key);
+    pub fn add_trigger(&self, trigger_json: String, on_result: Option<js_sys::Function>) -> Result<TriggerHandle, JsValue> {
+        let definition: TriggerDefinition = serde_json::from_str(&trigger_json).map_err(|e| {
+            lib_error_to_js(LibError::CommandParse { message: format!("Invalid JSON trigger: {}", e) })
+        })?;
+        let tasks = self
+            .workflows
+            .render(&definition.workflow, &workflow::params_from_json(&definition.params))
+            .map_err(|e| lib_error_to_js(LibError::from(e)))?;
+        let run = self.background_run(format!("trigger:{}", definition.workflow), tasks, on_result)?;
+        trigger::start(definition.when, definition.once, Box::new(run))
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))
     }
 
-    /// Automates a list of tasks provided as a JSON string.
+    /// Runs a guided tour over the current page.
     ///
-    /// Each task in the list is processed sequentially. If a task string contains the
-    /// placeholder `{{PREVIOUS_RESULT}}`, it will be substituted with the successful
-    /// output of the immediately preceding task. If the preceding task failed,
-    /// `{{PREVIOUS_RESULT}}` is replaced with an empty string.
+    /// Each step highlights an element and shows explanatory text next to it. Unlike `automate`,
+    /// no LLM configuration is required, since tours are defined entirely by the host page.
     ///
     /// # Arguments
-    /// * `tasks_json`: A JSON string representing a list of tasks.
-    ///   Example: `["CLICK css:#button", "READ css:#label {{PREVIOUS_RESULT}}"]`
+    /// * `steps_json`: A JSON string representing a list of tour steps.
+    ///   Example: `[{"selector": "css:#search", "text": "Search here."}, {"selector": "css:#cart", "text": "Your cart.", "wait_for_next": false, "duration_ms": 2000}]`
+    ///   `wait_for_next` defaults to `true`; `duration_ms` (used when not waiting) defaults to 3000.
     ///
     /// # Returns
-    /// A `Result` which, if successful (`Ok`), contains a `JsValue` that is a JSON string
-    /// representing a `Vec<Result<String, LibError>>`. Each item in this vector corresponds
-    /// to the outcome of a task in the input list:
-    ///   - `Ok(String)`: Contains the success message or result string from the task.
-    ///     If the task involved LLM-returned commands, this string itself might be a
-    ///     JSON representation of `Vec<Result<String, LibError>>` for those sub-commands (though currently it's Vec<Result<String,String>> for inner commands).
-    ///   - `Err(LibError)`: Contains the structured error if the task failed.
+    /// A `Result` which, if successful (`Ok`), contains a `JsValue` JSON string of the form
+    /// `{"steps_total": <n>, "steps_completed": <m>}`. `steps_completed` is lower than `steps_total`
+    /// if the user dismissed the tour early (e.g. via `end_tour`).
     ///
-    /// If initial checks fail (e.g., LLM config not set, invalid `tasks_json`),
-    /// it returns `Err(JsValue)` with an error message (this error is a simple string, not LibError).
+    /// If the steps cannot be parsed or a step fails (e.g. its element is missing), it returns
+    /// `Err(JsValue)` containing a serialized `LibError`.
     #[wasm_bindgen]
-    pub async fn automate(&self, tasks_json: String) -> Result<JsValue, JsValue> {
-        // 1. LLM Configuration Check: Ensure API key, URL, and model name are set.
-        let (api_key, api_url, model_name) = match (&self.api_key, &self.api_url, &self.model_name) {
-            (Some(k), Some(u), Some(m)) => (k, u, m),
-            _ => return Err(JsValue::from_str("LLM configuration not set. Please call set_llm_config first.")),
-        };
+    pub async fn run_tour(&self, steps_json: String) -> Result<JsValue, JsValue> {
+        let steps: Vec<tour::TourStep> = serde_json::from_str(&steps_json).map_err(|e| {
+            lib_error_to_js(LibError::CommandParse { message: format!("Invalid JSON tour step list: {}", e) })
+        })?;
+
+        let steps_completed = tour::run_tour(&steps)
+            .await
+            .map_err(|dom_error| lib_error_to_js(LibError::from(AgentError::DomOperationFailed(dom_error))))?;
+
+        let summary = serde_json::json!({
+            "steps_total": steps.len(),
+            "steps_completed": steps_completed,
+        });
+        Ok(JsValue::from_str(&summary.to_string()))
+    }
+}
 
-        // 2. Parse tasks_json: Deserialize the input JSON string into a vector of task strings.
-        let tasks: Vec<String> = match serde_json::from_str(&tasks_json) {
-            Ok(parsed_tasks) => parsed_tasks,
-            Err(_) => return Err(JsValue::from_str("Invalid JSON task list. Expected an array of strings.")),
+impl RustAgent {
+    // Returns the LLM configuration, that of the profile in use if one was selected with
+    // `use_profile`, or the error `automate` reports when it is not set.
+    #[cfg(not(feature = "dom-only"))]
+    fn llm_config(&self) -> Result<(&str, &str, &str), JsValue> {
+        let (api_key, api_url, model_name) = (self.api_key.as_deref(), self.api_url.as_deref(), self.model_name.as_deref());
+        let (api_key, api_url, model_name) = match self.agents.config().llm_profiles.active() {
+            Some(profile) => (profile.api_key.as_deref().or(api_key), profile.api_url.as_deref().or(api_url), Some(profile.model.as_str())),
+            None => (api_key, api_url, model_name),
         };
-
-        if tasks.is_empty() {
-            return Err(JsValue::from_str("Task list is empty."));
+        match (api_key, api_url, model_name) {
+            (Some(k), Some(u), Some(m)) => Ok((k, u, m)),
+            _ => Err(JsValue::from_str("LLM configuration not set. Please call set_llm_config first.")),
         }
+    }
 
-        // 3. Iterate through tasks and execute
-        let mut results_list: Vec<Result<String, LibError>> = Vec::new();
-        // Stores the successful output of the previous task for placeholder substitution.
-        let mut previous_task_successful_output: Option<String> = None;
-
-        for original_task_template in tasks {
-            web_sys::console::log_1(&format!("Original task template: {}", original_task_template).into());
-
-            let current_task_string: String;
-            // Substitute {{PREVIOUS_RESULT}} placeholder if present.
-            if original_task_template.contains("{{PREVIOUS_RESULT}}") {
-                let replacement_value = previous_task_successful_output.as_deref().unwrap_or("");
-                web_sys::console::log_1(&format!("Placeholder {{PREVIOUS_RESULT}} found. Replacing with: '{}'", replacement_value).into());
-                current_task_string = original_task_template.replace("{{PREVIOUS_RESULT}}", replacement_value);
-            } else {
-                current_task_string = original_task_template.clone();
-            }
-            
-            web_sys::console::log_1(&format!("Executing task (after substitution): {}", current_task_string).into());
-
-            // Run the task using the agent system.
-            match self.agents.run_task(&current_task_string, api_key, api_url, model_name).await {
-                Ok(result_string) => {
-                    // On success, store the output for potential use in the next task
-                    // and add it to the list of results for this task sequence.
-                    web_sys::console::log_1(&format!("Task succeeded. Storing for {{PREVIOUS_RESULT}}: {}", result_string).into());
-                    previous_task_successful_output = Some(result_string.clone());
-                    results_list.push(Ok(result_string));
+    // Without the LLM (the `dom-only` feature) there is no configuration to set: tasks that are not
+    // DOM commands fail when they run.
+    #[cfg(feature = "dom-only")]
+    fn llm_config(&self) -> Result<(&str, &str, &str), JsValue> {
+        Ok(("", "", ""))
+    }
+
+    // Parses and checks the task list for a scheduled run, then starts it.
+    fn schedule_tasks(
+        &self,
+        tasks_json: &str,
+        delay_ms: u32,
+        interval_ms: Option<u32>,
+        on_result: Option<js_sys::Function>,
+    ) -> Result<ScheduledRun, JsValue> {
+        let tasks = parse_tasks(tasks_json)?;
+        web_sys::console::log_1(&format!("Scheduling {} tasks in {}ms (repeat every {:?}ms)", tasks.len(), delay_ms, interval_ms).into());
+        let run = self.background_run("schedule".to_string(), tasks, on_result)?;
+        Ok(schedule::start(delay_ms, interval_ms, run))
+    }
+
+    // Builds the work of a scheduled or triggered run: running `tasks` with a copy of the current
+    // LLM configuration, execution policy and result reporting, then passing the results to `on_result`.
+    fn background_run(
+        &self,
+        run_label: String,
+        tasks: Vec<String>,
+        on_result: Option<js_sys::Function>,
+    ) -> Result<impl Fn() -> Pin<Box<dyn Future<Output = ()>>> + 'static, JsValue> {
+        let (api_key, api_url, model_name) = self.llm_config()?;
+        let (api_key, api_url, model_name) = (api_key.to_string(), api_url.to_string(), model_name.to_string());
+        let agents = Rc::new(AgentSystem::with_config(self.agents.config().clone()));
+        let reporting = self.reporting.clone();
+
+        Ok(move || -> Pin<Box<dyn Future<Output = ()>>> {
+            let (agents, reporting, run_label) = (agents.clone(), reporting.clone(), run_label.clone());
+            let (tasks, on_result) = (tasks.clone(), on_result.clone());
+            let (api_key, api_url, model_name) = (api_key.clone(), api_url.clone(), model_name.clone());
+            Box::pin(async move {
+                let run = run_task_list(&agents, &reporting, &run_label, tasks, &api_key, &api_url, &model_name).await;
+                let result = task_list_output(&reporting.schema, run);
+                if let Some(callback) = on_result {
+                    let value = result.unwrap_or_else(|err| err);
+                    if let Err(e) = callback.call1(&JsValue::NULL, &value) {
+                        web_sys::console::warn_1(&format!("on_result callback threw: {:?}", e.as_string()).into());
+                    }
                 }
-                Err(agent_error) => {
-                    // On failure, clear the stored output
-                    web_sys::console::log_1(&format!("Task failed. Clearing {{PREVIOUS_RESULT}}. Error: {}", agent_error).into());
-                    previous_task_successful_output = None;
-                    results_list.push(Err(LibError::from(agent_error))); // Convert AgentError to LibError
-                    // Optional: Stop execution on first error
-                    // For example: return Err(JsValue::from_str(&format!("Task failed: {}", LibError::from(agent_error))));
+            })
+        })
+    }
+}
+
+// Parses a task list given to `automate` or a scheduling method, rejecting empty lists.
+fn parse_tasks(tasks_json: &str) -> Result<Vec<String>, JsValue> {
+    let tasks = workflow::parse_task_list(tasks_json)
+        .map_err(|e| JsValue::from_str(&format!("{}. Expected an array of task strings.", e)))?;
+    if tasks.is_empty() {
+        return Err(JsValue::from_str("Task list is empty."));
+    }
+    try_catch::check_blocks(&tasks)
+        .and_then(|_| goto::check_labels(&tasks))
+        .and_then(|_| while_loop::check_loops(&tasks))
+        .map_err(|e| JsValue::from_str(&format!("Invalid task list: {}.", e)))?;
+    Ok(tasks)
+}
+
+// Parses the `format` argument of the script export methods.
+fn script_format(format: &str) -> Result<ScriptFormat, JsValue> {
+    ScriptFormat::parse(format).ok_or_else(|| {
+        lib_error_to_js(LibError::Export { message: format!("Unknown script format '{}': expected 'playwright' or 'selenium'", format) })
+    })
+}
+
+// A task list being run one task at a time: the tasks left, the page error monitor and element
+// cache that live as long as the run, and the results so far.
+struct TaskRun {
+    id: String,
+    run_label: String,
+    // Every task of the run, for the run history.
+    task_list: Vec<String>,
+    tasks: VecDeque<String>,
+    started_at_ms: f64,
+    page_error_monitor: Option<PageErrorMonitor>,
+    _element_cache: Option<ElementCacheScope>,
+    results: Vec<TaskResult>,
+    // The index in `task_list` of the step each result is for, and of the step being run.
+    result_steps: Vec<usize>,
+    step: usize,
+    // The successful output of the previous task, for {{PREVIOUS_RESULT}} substitution.
+    previous_task_successful_output: Option<String>,
+    // The TRY blocks the run is inside of, and the error bound to {{error}} in CATCH steps.
+    try_stack: TryStack,
+    // The jumps made by each GOTO of the run.
+    jumps: goto::JumpCounter,
+    // The iterations of each WHILE loop of the run.
+    loops: while_loop::LoopCounter,
+}
+
+impl TaskRun {
+    // Starts a run. Changes recorded for rollback belong to this run only.
+    fn start(agents: &AgentSystem, run_label: &str, tasks: Vec<String>) -> Self {
+        let started_at_ms = js_sys::Date::now();
+        // Uncaught page errors raised while the tasks run are attached to the envelope.
+        let page_error_monitor = PageErrorMonitor::start()
+            .map_err(|e| web_sys::console::warn_1(&format!("Page errors will not be recorded: {}", e).into()))
+            .ok();
+        // Elements resolved by the run's commands are reused until the page changes.
+        let element_cache = ElementCacheScope::start()
+            .map_err(|e| web_sys::console::warn_1(&format!("Elements will not be cached: {}", e).into()))
+            .ok();
+        let id = history::new_run_id(started_at_ms);
+        agents.begin_run(&id);
+        TaskRun {
+            id,
+            run_label: run_label.to_string(),
+            task_list: tasks.clone(),
+            tasks: tasks.into(),
+            started_at_ms,
+            page_error_monitor,
+            _element_cache: element_cache,
+            results: Vec::new(),
+            result_steps: Vec::new(),
+            step: 0,
+            previous_task_successful_output: None,
+            try_stack: TryStack::default(),
+            jumps: goto::JumpCounter::default(),
+            loops: while_loop::LoopCounter::default(),
+        }
+    }
+
+    // Index in `task_list` of the task just taken from the front of the tasks.
+    fn current_index(&self) -> usize {
+        self.task_list.len() - self.tasks.len() - 1
+    }
+
+    // Jumps to the tasks after `LABEL <label>` for the GOTO just taken from the front of the tasks.
+    // Fails once that GOTO has made `max_jumps` jumps.
+    fn jump(&mut self, label: &str, max_jumps: u32) -> Result<(), String> {
+        let goto_index = self.current_index();
+        let label_index = goto::label_index(&self.task_list, label).ok_or_else(|| format!("GOTO names unknown label '{}'", label))?;
+        self.jumps.jump(goto_index, label, max_jumps)?;
+        self.tasks = self.task_list[label_index + 1..].to_vec().into();
+        self.try_stack = TryStack::at(&self.task_list, label_index);
+        Ok(())
+    }
+
+    // Enters the body of the WHILE just taken from the front of the tasks if `result`, the result
+    // of its condition's task, says the condition holds, and skips the loop otherwise. Fails,
+    // skipping the loop, if the result says neither or the loop has run `max_iterations` times.
+    fn enter_loop(&mut self, condition: &Condition<'_>, result: &str) -> Result<(), String> {
+        let while_index = self.current_index();
+        match condition.holds(result) {
+            Some(true) => {
+                let iteration = self.loops.iterate(while_index, condition.max_iterations);
+                if iteration.is_err() {
+                    self.leave_loop(while_index);
                 }
+                iteration
+            }
+            Some(false) => {
+                web_sys::console::log_1(&format!("WHILE condition '{}' no longer holds; leaving the loop", condition.text).into());
+                self.leave_loop(while_index);
+                Ok(())
+            }
+            None => {
+                self.leave_loop(while_index);
+                Err(format!("Could not tell whether WHILE condition '{}' holds from its result: {}", condition.text, result))
             }
         }
+    }
+
+    // Skips the rest of the loop whose WHILE is at `while_index`.
+    fn leave_loop(&mut self, while_index: usize) {
+        self.loops.end(while_index);
+        while_loop::skip_loop(&mut self.tasks);
+    }
+
+    // Goes back to the WHILE of the END_WHILE just taken from the front of the tasks.
+    fn repeat_loop(&mut self) -> Result<(), String> {
+        let end_index = self.current_index();
+        let while_index = while_loop::loop_start(&self.task_list, end_index)
+            .ok_or_else(|| format!("Task {} is an END_WHILE without a WHILE", end_index + 1))?;
+        self.loops.repeat(while_index);
+        self.tasks = self.task_list[while_index..].to_vec().into();
+        Ok(())
+    }
+
+    // Records the result of the step being run.
+    fn record(&mut self, result: TaskResult) {
+        self.results.push(result);
+        self.result_steps.push(self.step);
+    }
+
+    // Records the failure of a task: the stored output is cleared and, inside a TRY part, its CATCH
+    // steps run next. An error of a run limit ends the run instead, even inside a TRY part.
+    fn fail(&mut self, error: LibError, message: &str) {
+        if matches!(error, LibError::RunLimitExceeded { .. }) {
+            web_sys::console::warn_1(&format!("Aborting the run with {} tasks left: {}", self.tasks.len(), message).into());
+            self.tasks.clear();
+            self.record(TaskResult::err(error));
+            return;
+        }
+        web_sys::console::log_1(&format!("Task failed. Clearing {{PREVIOUS_RESULT}}. Error: {}", message).into());
+        self.previous_task_successful_output = None;
+        if self.try_stack.fail(message, &mut self.tasks) {
+            web_sys::console::log_1(&"Failure inside TRY; continuing with its CATCH steps".into());
+        }
+        self.record(TaskResult::err(error));
+    }
 
-        // 4. Serialize results_list and return: Convert the collected results into a JSON string.
-        match serde_json::to_string(&results_list) {
-            Ok(json_results) => Ok(JsValue::from_str(&json_results)),
-            Err(e) => {
-                // This serialization error should ideally be a LibError too, but JsValue is the function signature for this top-level error
-                let lib_err = LibError::Serialization { message: format!("Failed to serialize final results list: {}", e) };
-                let err_json = serde_json::to_string(&lib_err).unwrap_or_else(|_| "{\"error_type\":\"Serialization\",\"message\":\"Failed to serialize error object after failing to serialize results list.\"}".to_string());
-                Err(JsValue::from_str(&err_json))
+    // Runs the next task, substituting {{PREVIOUS_RESULT}} and {{error}}, and returns its result, or
+    // `None` once every task has run. TRY / CATCH markers, labels, GOTO jumps and WHILE loops are
+    // followed on the way and produce no result, except when a GOTO or loop passes its limit or a
+    // loop condition cannot be evaluated, which fails.
+    async fn run_next(
+        &mut self,
+        agents: &AgentSystem,
+        api_key: &str,
+        api_url: &str,
+        model_name: &str,
+    ) -> Option<&TaskResult> {
+        let original_task_template = loop {
+            let task = self.tasks.pop_front()?;
+            self.step = self.current_index();
+            if let Some(marker) = try_catch::marker(&task) {
+                self.try_stack.enter(marker, &mut self.tasks);
+                continue;
+            }
+            let moved = match goto::parse_step(&task) {
+                Some(Ok(goto::FlowStep::Label(_))) => Ok(()),
+                Some(Ok(goto::FlowStep::Goto { label, max_jumps })) => self.jump(label, max_jumps),
+                Some(Err(e)) => Err(e),
+                None => match while_loop::parse_step(&task) {
+                    None => break task,
+                    Some(Ok(LoopStep::While(condition))) => {
+                        // The condition is a predicate command or a question for the LLM, run like any task.
+                        match agents.run_task(&condition.task(), api_key, api_url, model_name).await {
+                            Ok(result) => self.enter_loop(&condition, &result),
+                            Err(agent_error) => {
+                                let message = agent_error.to_string();
+                                self.leave_loop(self.current_index());
+                                self.fail(LibError::from(agent_error), &message);
+                                return self.results.last();
+                            }
+                        }
+                    }
+                    Some(Ok(LoopStep::EndWhile)) => self.repeat_loop(),
+                    Some(Err(e)) => Err(e),
+                },
+            };
+            if let Err(message) = moved {
+                self.fail(LibError::Workflow { message: message.clone() }, &message);
+                return self.results.last();
+            }
+        };
+        web_sys::console::log_1(&format!("Original task template: {}", original_task_template).into());
+
+        let mut current_task_string: String;
+        // Substitute {{PREVIOUS_RESULT}} placeholder if present.
+        if original_task_template.contains("{{PREVIOUS_RESULT}}") {
+            let replacement_value = self.previous_task_successful_output.as_deref().unwrap_or("");
+            web_sys::console::log_1(&format!("Placeholder {{PREVIOUS_RESULT}} found. Replacing with: '{}'", replacement_value).into());
+            current_task_string = original_task_template.replace("{{PREVIOUS_RESULT}}", replacement_value);
+        } else {
+            current_task_string = original_task_template.clone();
+        }
+        // In CATCH steps, {{error}} stands for the error that led to them.
+        if current_task_string.contains(try_catch::ERROR_PLACEHOLDER) {
+            current_task_string = current_task_string.replace(try_catch::ERROR_PLACEHOLDER, self.try_stack.error().unwrap_or(""));
+        }
+        
+        web_sys::console::log_1(&format!("Executing task (after substitution): {}", current_task_string).into());
+
+        // Run the task using the agent system.
+        match agents.run_coded_task(&current_task_string, api_key, api_url, model_name).await {
+            Ok((result_string, code)) => {
+                // On success, store the output for potential use in the next task
+                // and add it to the list of results for this task sequence.
+                web_sys::console::log_1(&format!("Task succeeded. Storing for {{PREVIOUS_RESULT}}: {}", result_string).into());
+                self.previous_task_successful_output = Some(result_string.clone());
+                self.record(TaskResult::ok(result_string, code));
+            }
+            Err(agent_error) => {
+                // On failure, clear the stored output
+                let message = agent_error.to_string();
+                self.fail(LibError::from(agent_error), &message); // Convert AgentError to LibError
+                // Optional: Stop execution on first error
+                // For example: return Err(JsValue::from_str(&format!("Task failed: {}", LibError::from(agent_error))));
             }
         }
+        self.results.last()
+    }
+
+    // Ends the run, reporting the result envelope of the tasks that ran, with the assertion report
+    // in soft-assert mode, and recording the run in the history, and returns their results and summary.
+    fn finish(self, agents: &AgentSystem, reporting: &ResultReporting) -> (Vec<TaskResult>, RunSummary) {
+        let finished_at_ms = js_sys::Date::now();
+        let page_errors = self.page_error_monitor.map(|monitor| monitor.errors()).unwrap_or_default();
+        let assertion_report = agents.config().soft_assertions.report();
+        let summary = run_summary::summarize(&self.task_list, &self.results, &self.result_steps, finished_at_ms - self.started_at_ms);
+        let envelope = report::envelope(
+            &self.id,
+            &self.run_label,
+            self.started_at_ms,
+            &self.results,
+            &summary,
+            &page_errors,
+            assertion_report.as_ref(),
+        );
+        reporting.report(&envelope);
+        reporting.history.record(RunRecord {
+            id: self.id,
+            run: self.run_label,
+            tasks: self.task_list,
+            success: envelope["success"] == true,
+            started_at_ms: self.started_at_ms,
+            finished_at_ms,
+            results: self.results.clone(),
+            assertion_report,
+            feedback: None,
+        });
+        (self.results, summary)
+    }
+}
+
+// Runs tasks in order, substituting {{PREVIOUS_RESULT}}, reports the result envelope (labelled
+// `run_label`) and returns the per-task results with the summary of the run.
+// Shared by `automate`, `run_workflow` and scheduled and triggered runs.
+async fn run_task_list(
+    agents: &AgentSystem,
+    reporting: &ResultReporting,
+    run_label: &str,
+    tasks: Vec<String>,
+    api_key: &str,
+    api_url: &str,
+    model_name: &str,
+) -> (Vec<TaskResult>, RunSummary) {
+    let mut run = TaskRun::start(agents, run_label, tasks);
+    while run.run_next(agents, api_key, api_url, model_name).await.is_some() {}
+    run.finish(agents, reporting)
+}
+
+// Serializes the results of a `run_task_list` run in the shape of `schema`.
+fn task_list_output(schema: &ResultSchema, (results_list, summary): (Vec<TaskResult>, RunSummary)) -> Result<JsValue, JsValue> {
+    // Serialize results_list and return: Convert the collected results into a JSON string.
+    match schema.output_json(&results_list, Some(&summary)) {
+        Ok(json_results) => Ok(JsValue::from_str(&json_results)),
+        Err(e) => {
+            // This serialization error should ideally be a LibError too, but JsValue is the function signature for this top-level error
+            let lib_err = LibError::Serialization { message: format!("Failed to serialize final results list: {}", e) };
+            let err_json = serde_json::to_string(&lib_err).unwrap_or_else(|_| "{\"error_type\":\"Serialization\",\"message\":\"Failed to serialize error object after failing to serialize results list.\"}".to_string());
+            Err(JsValue::from_str(&err_json))
+        }
     }
 }
 
+// What a task stream needs to run its remaining tasks.
+struct TaskStreamState {
+    run: TaskRun,
+    agents: AgentSystem,
+    reporting: ResultReporting,
+    llm_config: (String, String, String),
+}
+
+// Builds the `{ value, done }` object returned by async iterators.
+fn iterator_result(value: &JsValue, done: bool) -> Result<JsValue, JsValue> {
+    let result = js_sys::Object::new();
+    js_sys::Reflect::set(&result, &JsValue::from_str("value"), value)?;
+    js_sys::Reflect::set(&result, &JsValue::from_str("done"), &JsValue::from_bool(done))?;
+    Ok(result.into())
+}
+
+// Builds the async iterator returned by `automate_stream`. Each `next()` runs the next task; the
+// run finishes (and its envelope is reported) when the tasks run out or `return()` is called,
+// e.g. by a `break` out of `for await`.
+fn task_stream(state: TaskStreamState) -> Result<js_sys::Object, JsValue> {
+    // `None` once the run has finished, and while a task is running.
+    let state = Rc::new(RefCell::new(Some(state)));
+    let finished = Rc::new(Cell::new(false));
+
+    let (next_state, next_finished) = (state.clone(), finished.clone());
+    let next = Closure::wrap(Box::new(move || {
+        let (state, finished) = (next_state.clone(), next_finished.clone());
+        wasm_bindgen_futures::future_to_promise(async move {
+            if finished.get() {
+                return iterator_result(&JsValue::UNDEFINED, true);
+            }
+            let mut current = state.borrow_mut().take().ok_or_else(|| {
+                JsValue::from_str("next() was called before the previous task of the stream finished.")
+            })?;
+            let (api_key, api_url, model_name) = current.llm_config.clone();
+            let result = match current.run.run_next(&current.agents, &api_key, &api_url, &model_name).await {
+                Some(result) => current.reporting.schema.result_json(result).map_err(|e| JsValue::from_str(&e.to_string()))?,
+                None => {
+                    finished.set(true);
+                    current.run.finish(&current.agents, &current.reporting);
+                    return iterator_result(&JsValue::UNDEFINED, true);
+                }
+            };
+            *state.borrow_mut() = Some(current);
+            iterator_result(&JsValue::from_str(&result), false)
+        })
+    }) as Box<dyn FnMut() -> js_sys::Promise>);
+
+    let stop = Closure::wrap(Box::new(move || {
+        if !finished.replace(true) {
+            if let Some(current) = state.borrow_mut().take() {
+                current.run.finish(&current.agents, &current.reporting);
+            }
+        }
+        js_sys::Promise::resolve(&iterator_result(&JsValue::UNDEFINED, true).unwrap_or(JsValue::UNDEFINED))
+    }) as Box<dyn FnMut() -> js_sys::Promise>);
+
+    let iterator = js_sys::Object::new();
+    js_sys::Reflect::set(&iterator, &JsValue::from_str("next"), &next.into_js_value())?;
+    js_sys::Reflect::set(&iterator, &JsValue::from_str("return"), &stop.into_js_value())?;
+    // `for await` asks the object for its iterator, which is the object itself.
+    let this_iterator = iterator.clone();
+    let get_iterator = Closure::wrap(Box::new(move || this_iterator.clone()) as Box<dyn FnMut() -> js_sys::Object>);
+    js_sys::Reflect::set(&iterator, &js_sys::Symbol::async_iterator(), &get_iterator.into_js_value())?;
+    Ok(iterator)
+}
+
+// Serializes a `LibError` into the `JsValue` returned to JavaScript by fallible entry points.
+fn lib_error_to_js(lib_err: LibError) -> JsValue {
+    JsValue::from_str(&serde_json::to_string(&lib_err).unwrap_or_else(|_| "{\"error_type\":\"Serialization\",\"message\":\"Failed to serialize error object.\"}".to_string()))
+}
+
 // Note: Serialize, Deserialize were already imported for LibError
 
 /// WASM entry point function, typically called once when the WASM module is initialized.
@@ -208,14 +2392,17 @@ pub fn run() -> Result<(), JsValue> {
 
 #[cfg(test)]
 #[cfg(feature = "mock-llm")] // Ensure mock-llm is active for these tests
+#[cfg(not(feature = "dom-only"))]
 mod tests {
     use super::*;
+    use crate::llm_error::{classify_empty_response, classify_response, LlmError};
     use wasm_bindgen_test::*;
     use serde_json::Value;
 
     wasm_bindgen_test_configure!(run_in_browser);
 
     fn setup_agent() -> RustAgent {
+        llm::add_mock_fixtures();
         let mut agent = RustAgent::new();
         agent.set_llm_config(
             "dummy_url".to_string(),
@@ -225,6 +2412,16 @@ mod tests {
         agent
     }
 
+    // The `results` of the output of an `automate` call.
+    fn output_results(output_json: &str) -> Value {
+        serde_json::from_str::<Value>(output_json).unwrap()["results"].take()
+    }
+
+    // The results of an `automate` call, without their codes.
+    fn task_results(output_json: &str) -> Vec<Result<String, LibError>> {
+        serde_json::from_value::<Vec<TaskResult>>(output_results(output_json)).unwrap().into_iter().map(|result| result.result).collect()
+    }
+
     #[wasm_bindgen_test]
     async fn test_automate_single_task_no_placeholder() {
         let agent = setup_agent();
@@ -232,13 +2429,52 @@ mod tests {
         
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str = result_js.as_string().unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_str).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_str);
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
         assert_eq!(results[0].as_ref().unwrap(), "Agent 3 (Generic) completed task via LLM: Clicked #first_button");
     }
 
+    #[wasm_bindgen_test]
+    async fn test_automate_results_have_codes() {
+        let agent = setup_agent();
+        let tasks = vec!["click #first_button", "GET_URL", "READ css:#no-such-element-for-codes"];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        let codes: Vec<&str> = results.as_array().unwrap().iter().map(|result| result["code"].as_str().unwrap()).collect();
+        assert_eq!(codes, ["LLM_NATURAL_RESPONSE", "GET_URL_OK", "ELEMENT_NOT_FOUND"]);
+        assert_eq!(results[2]["Err"]["kind"], "ElementNotFound");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_pinned_result_schema_version() {
+        let mut agent = setup_agent();
+        let tasks_json = serde_json::to_string(&vec!["GET_URL"]).unwrap();
+        agent.set_result_schema_version(Some(1)).unwrap();
+        let output: Value = serde_json::from_str(&agent.automate(tasks_json.clone()).await.unwrap().as_string().unwrap()).unwrap();
+        assert!(output.is_array(), "Version 1 is the bare array: {}", output);
+        assert!(output[0]["Ok"].is_string());
+        assert!(output[0].get("code").is_none(), "Version 1 results have no code");
+
+        agent.set_result_schema_version(Some(2)).unwrap();
+        let output: Value = serde_json::from_str(&agent.automate(tasks_json.clone()).await.unwrap().as_string().unwrap()).unwrap();
+        assert_eq!(output["schema_version"], 2);
+        assert_eq!(output["results"][0]["code"], "GET_URL_OK");
+        assert!(output.get("summary").is_none(), "Version 2 has no summary");
+
+        agent.set_result_schema_version(Some(3)).unwrap();
+        let output: Value = serde_json::from_str(&agent.automate(tasks_json.clone()).await.unwrap().as_string().unwrap()).unwrap();
+        assert_eq!(output["summary"]["succeeded"], 1);
+        assert!(agent.set_result_schema_version(Some(99)).is_err());
+
+        agent.set_result_schema_version(None).unwrap();
+        let output: Value = serde_json::from_str(&agent.automate(tasks_json).await.unwrap().as_string().unwrap()).unwrap();
+        assert_eq!(output["schema_version"], crate::result_schema::RESULT_SCHEMA_VERSION, "Results come in the current version unless one is pinned");
+        assert_eq!(output["results"][0]["code"], "GET_URL_OK");
+        assert_eq!(output["summary"]["total"], 1, "The current version has the summary of the run");
+    }
+
     #[wasm_bindgen_test]
     async fn test_automate_two_tasks_second_uses_placeholder_successfully() {
         let agent = setup_agent();
@@ -250,7 +2486,7 @@ mod tests {
 
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str = result_js.as_string().unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_str).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_str);
 
         assert_eq!(results.len(), 2);
         assert!(results[0].is_ok());
@@ -280,7 +2516,7 @@ mod tests {
 
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str = result_js.as_string().unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_str).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_str);
 
         assert_eq!(results.len(), 2);
         assert!(results[0].is_err());
@@ -311,7 +2547,7 @@ mod tests {
 
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str = result_js.as_string().unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_str).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_str);
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
@@ -334,7 +2570,7 @@ mod tests {
 
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str = result_js.as_string().unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_str).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_str);
 
         assert_eq!(results.len(), 3);
         assert!(results[0].is_ok());
@@ -345,6 +2581,41 @@ mod tests {
         assert_eq!(results[2].as_ref().unwrap(), "Agent 3 (Generic) completed task via LLM: Final result from C");
     }
 
+    async fn next_stream_item(stream: &js_sys::Object, method: &str) -> (JsValue, bool) {
+        let method: js_sys::Function = js_sys::Reflect::get(stream, &JsValue::from_str(method)).unwrap().into();
+        let promise: js_sys::Promise = method.call0(stream).unwrap().into();
+        let item = wasm_bindgen_futures::JsFuture::from(promise).await.unwrap();
+        let value = js_sys::Reflect::get(&item, &JsValue::from_str("value")).unwrap();
+        let done = js_sys::Reflect::get(&item, &JsValue::from_str("done")).unwrap().as_bool().unwrap();
+        (value, done)
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_automate_stream_yields_each_task_result() {
+        let agent = setup_agent();
+        let tasks = vec![
+            "click #first_button",
+            "process {{PREVIOUS_RESULT}} for task B",
+            "process {{PREVIOUS_RESULT}} for task C",
+        ];
+        let stream = agent.automate_stream(serde_json::to_string(&tasks).unwrap()).unwrap();
+
+        let (value, done) = next_stream_item(&stream, "next").await;
+        assert!(!done);
+        let result = serde_json::from_str::<TaskResult>(&value.as_string().unwrap()).unwrap().result;
+        assert_eq!(result.unwrap(), "Agent 3 (Generic) completed task via LLM: Clicked #first_button");
+        let (value, _) = next_stream_item(&stream, "next").await;
+        let result = serde_json::from_str::<TaskResult>(&value.as_string().unwrap()).unwrap().result;
+        assert_eq!(result.unwrap(), "Agent 3 (Generic) completed task via LLM: Processed Clicked #first_button");
+
+        // Stopping early skips the remaining task.
+        let (_, done) = next_stream_item(&stream, "return").await;
+        assert!(done);
+        let (value, done) = next_stream_item(&stream, "next").await;
+        assert!(done);
+        assert!(value.is_undefined());
+    }
+
     #[wasm_bindgen_test]
     async fn test_automate_placeholder_produces_multicommand_json() {
         let agent = setup_agent();
@@ -358,7 +2629,7 @@ mod tests {
 
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str = result_js.as_string().unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_str).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_str);
 
         assert_eq!(results.len(), 2);
         assert!(results[0].is_ok());
@@ -382,7 +2653,7 @@ mod tests {
         let agent = setup_agent();
         let tasks_json = serde_json::to_string(&vec!["GET_URL"]).unwrap();
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
         assert!(results[0].as_ref().unwrap().contains("Agent 3 (Generic): Current URL is:"));
@@ -397,14 +2668,14 @@ mod tests {
 
         let tasks_true_json = serde_json::to_string(&vec!["ELEMENT_EXISTS css:#integ-exists-direct"]).unwrap();
         let result_true_js = agent.automate(tasks_true_json).await.unwrap();
-        let results_true: Vec<Result<String, LibError>> = serde_json::from_str(&result_true_js.as_string().unwrap()).unwrap();
+        let results_true: Vec<Result<String, LibError>> = task_results(&result_true_js.as_string().unwrap());
         assert_eq!(results_true.len(), 1);
         assert!(results_true[0].is_ok());
         assert_eq!(results_true[0].as_ref().unwrap(), "Agent 3 (Generic): Element 'css:#integ-exists-direct' exists: true");
 
         let tasks_false_json = serde_json::to_string(&vec!["ELEMENT_EXISTS css:#integ-nonexistent-direct"]).unwrap();
         let result_false_js = agent.automate(tasks_false_json).await.unwrap();
-        let results_false: Vec<Result<String, LibError>> = serde_json::from_str(&result_false_js.as_string().unwrap()).unwrap();
+        let results_false: Vec<Result<String, LibError>> = task_results(&result_false_js.as_string().unwrap());
         assert_eq!(results_false.len(), 1);
         assert!(results_false[0].is_ok());
         assert_eq!(results_false[0].as_ref().unwrap(), "Agent 3 (Generic): Element 'css:#integ-nonexistent-direct' exists: false");
@@ -412,6 +2683,488 @@ mod tests {
         dom_utils::cleanup_element(el);
     }
 
+    #[wasm_bindgen_test]
+    async fn test_automate_try_catch() {
+        let agent = setup_agent();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let log = dom_utils::setup_element(&document, "integ-try-log", "input", None);
+
+        let tasks = vec![
+            "TRY",
+            "WAIT_FOR_ELEMENT css:#integ-try-missing 50",
+            "TYPE css:#integ-try-log skipped",
+            "CATCH",
+            "TYPE css:#integ-try-log {{error}}",
+            "END_TRY",
+            "GETVALUE css:#integ-try-log",
+        ];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        assert_eq!(results.len(), 3, "Markers and skipped tasks have no result: {:?}", results);
+        assert!(results[0].is_err());
+        assert!(results[1].is_ok());
+        let value = results[2].as_ref().unwrap();
+        assert!(value.contains("not found after 50ms timeout"), "The CATCH step should have typed the error: {}", value);
+
+        let invalid = serde_json::to_string(&vec!["TRY", "CLICK css:#a"]).unwrap();
+        assert!(agent.automate(invalid).await.unwrap_err().as_string().unwrap().contains("not closed with END_TRY"));
+        dom_utils::cleanup_element(log);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_automate_goto_retries_until_limit() {
+        let agent = setup_agent();
+        let tasks = vec![
+            "LABEL retry",
+            "TRY",
+            "WAIT_FOR_ELEMENT css:#integ-goto-missing 20",
+            "CATCH",
+            "GOTO retry 2",
+            "END_TRY",
+            "READ css:body",
+        ];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        // Three attempts (the first and two retries), then the failed jump, then the task after the block.
+        assert_eq!(results.len(), 5, "Unexpected results: {:?}", results);
+        assert!(results[..3].iter().all(|result| result.is_err()));
+        assert!(matches!(&results[3], Err(LibError::Workflow { message }) if message.contains("already jumped 2 times")));
+        assert!(results[4].is_ok());
+
+        let invalid = serde_json::to_string(&vec!["GOTO nowhere"]).unwrap();
+        assert!(agent.automate(invalid).await.unwrap_err().as_string().unwrap().contains("unknown label 'nowhere'"));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_report_feedback_on_run() {
+        let agent = setup_agent();
+        agent.automate(serde_json::to_string(&vec!["GET_URL"]).unwrap()).await.unwrap();
+        let history: serde_json::Value = serde_json::from_str(&agent.get_history(None).unwrap()).unwrap();
+        let run_id = history[0]["id"].as_str().unwrap().to_string();
+        assert!(run_id.starts_with("run-"), "Unexpected run id: {}", run_id);
+
+        assert!(agent.report_feedback(run_id.clone(), false, Some("Opened the wrong page".to_string())));
+        assert!(!agent.report_feedback("run-unknown".to_string(), true, None));
+        let exported: serde_json::Value = serde_json::from_str(&agent.get_history(Some(r#"{"feedback": true}"#.to_string())).unwrap()).unwrap();
+        assert_eq!(exported.as_array().unwrap().len(), 1);
+        assert_eq!(exported[0]["id"], run_id.as_str());
+        assert_eq!(exported[0]["feedback"]["success"], false);
+        assert_eq!(exported[0]["feedback"]["note"], "Opened the wrong page");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_benchmark_models() {
+        let agent = setup_agent();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let input = dom_utils::setup_element(&document, "integ-bench-input", "input", Some(vec![("value", "before")]));
+        let tasks = serde_json::to_string(&vec!["TYPE css:#integ-bench-input typed", "describe the integ-bench page"]).unwrap();
+        let models = r#"[{"model": "small"}, {"name": "local", "model": "llama3", "api_url": "http://localhost:11434/v1/chat/completions", "api_key": ""}]"#;
+
+        let reports: Value = serde_json::from_str(&agent.benchmark(tasks.clone(), models.to_string()).await.unwrap()).unwrap();
+        let reports = reports.as_array().unwrap();
+        assert_eq!(reports.iter().map(|report| report["name"].as_str().unwrap()).collect::<Vec<_>>(), ["small", "local"]);
+        for report in reports {
+            assert_eq!(report["tasks"], 2);
+            assert_eq!(report["success_rate"], 1.0, "Unexpected report: {}", report);
+            assert_eq!(report["llm_calls"], 1, "Only the natural language task calls the LLM");
+            assert!(report["estimated_tokens"].as_u64().unwrap() > 0);
+            assert!(report["results"][1]["Ok"].as_str().unwrap().contains("integ-bench page"));
+        }
+        assert_ne!(reports[0]["run_id"], reports[1]["run_id"]);
+        let value_js = agent.automate(serde_json::to_string(&vec!["GETVALUE css:#integ-bench-input"]).unwrap()).await.unwrap();
+        let value: Vec<Result<String, LibError>> = task_results(&value_js.as_string().unwrap());
+        assert_eq!(value[0].as_deref(), Ok("before"), "Each model's changes are rolled back");
+
+        assert!(agent.benchmark(tasks.clone(), "[]".to_string()).await.is_err());
+        let unconfigured = RustAgent::new();
+        assert!(unconfigured.benchmark(tasks.clone(), r#"[{"model": "small"}]"#.to_string()).await.is_err());
+        assert!(unconfigured.benchmark(tasks, r#"[{"model": "small", "api_url": "http://localhost/v1", "api_key": ""}]"#.to_string()).await.is_ok());
+        dom_utils::cleanup_element(input);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_llm_fixtures_replace_mock_responses() {
+        let agent = setup_agent();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let button = dom_utils::setup_element(&document, "integ-fixture-button", "button", Some(vec![("onclick", "this.textContent = 'Clicked'")]));
+        let count = RustAgent::llm_fixture_count();
+        RustAgent::add_llm_fixture("press the integ-fixture button".to_string(), r#"[{"action": "CLICK", "selector": "css:#integ-fixture-button"}]"#.to_string()).unwrap();
+        assert!(RustAgent::add_llm_fixture(String::new(), "[]".to_string()).is_err());
+        assert_eq!(RustAgent::llm_fixture_count(), count + 1);
+
+        let tasks = serde_json::to_string(&vec!["press the integ-fixture button", "describe the integ-fixture page"]).unwrap();
+        let result_js = agent.automate(tasks.clone()).await.unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        assert!(results[0].is_ok(), "Unexpected result: {:?}", results[0]);
+        assert_eq!(button.text_content().unwrap_or_default(), "Clicked");
+        assert!(results[1].as_deref().unwrap().starts_with("Mocked LLM response"), "Prompts no fixture matches get the mock's response");
+
+        RustAgent::clear_llm_fixtures();
+        assert_eq!(RustAgent::llm_fixture_count(), 0);
+        dom_utils::cleanup_element(button);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_llm_provider_loaded_on_first_use() {
+        let agent = setup_agent();
+        let (window, document) = dom_utils::get_window_document().unwrap();
+        let button = dom_utils::setup_element(&document, "provider-button", "button", Some(vec![("onclick", "this.textContent = 'Pressed'")]));
+        js_sys::Reflect::set(&window, &"integProviderLoads".into(), &0.into()).unwrap();
+        let loads = || js_sys::Reflect::get(&window, &"integProviderLoads".into()).unwrap().as_f64().unwrap();
+        RustAgent::set_llm_provider(Some(js_sys::Function::new_no_args(
+            "window.integProviderLoads += 1;
+             return Promise.resolve((request) => request.prompt.includes('integ-provider limited')
+                 ? Promise.reject({ status: 429, body: 'Rate limit reached', retry_after: '20' })
+                 : JSON.stringify([{ action: 'CLICK', selector: 'css:#provider-button', reason: 'asked ' + request.model }]));",
+        )));
+        assert!(RustAgent::has_llm_provider());
+        assert_eq!(loads(), 0.0, "The provider is loaded by the first LLM call");
+
+        let tasks = serde_json::to_string(&vec!["Press the integ-provider button", "integ-provider limited"]).unwrap();
+        let results = output_results(&agent.automate(tasks).await.unwrap().as_string().unwrap());
+        let commands: Value = serde_json::from_str(results[0]["Ok"].as_str().unwrap()).unwrap();
+        assert!(commands[0]["Ok"].as_str().unwrap().contains("asked dummy_model"), "{}", commands);
+        assert_eq!(button.text_content().unwrap(), "Pressed");
+        assert_eq!(results[1]["Err"]["error_type"], "RateLimited", "{}", results);
+        assert_eq!(results[1]["Err"]["retry_after_ms"], 20000);
+        assert_eq!(loads(), 1.0, "The provider is loaded once");
+
+        RustAgent::set_llm_provider(None);
+        assert!(!RustAgent::has_llm_provider());
+        dom_utils::cleanup_element(button);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_llm_cassette_record_and_replay() {
+        let agent = setup_agent();
+        let run = |task: &str| agent.automate(serde_json::to_string(&vec![task]).unwrap());
+        let results = |result_js: JsValue| task_results(&result_js.as_string().unwrap());
+
+        RustAgent::start_llm_recording();
+        assert_eq!(RustAgent::llm_cassette_mode(), "record");
+        let recorded = results(run("describe the integ-cassette page").await.unwrap());
+        RustAgent::stop_llm_cassette();
+        let cassette: Value = serde_json::from_str(&RustAgent::export_llm_cassette().unwrap()).unwrap();
+        let interactions = cassette["interactions"].as_array().unwrap();
+        assert_eq!(interactions.len(), 1);
+        assert_eq!(interactions[0]["model"], "dummy_model");
+        assert_eq!(interactions[0]["response"].as_str(), recorded[0].as_deref().ok());
+
+        let mut edited = cassette.clone();
+        edited["interactions"][0]["response"] = Value::from("Replayed description.");
+        RustAgent::replay_llm_cassette(edited.to_string()).unwrap();
+        assert!(RustAgent::replay_llm_cassette("{}".to_string()).is_err());
+        assert_eq!(RustAgent::llm_cassette_mode(), "replay");
+        assert_eq!(results(run("describe the integ-cassette page").await.unwrap())[0].as_deref(), Ok("Replayed description."));
+        let missing = results(run("describe the integ-cassette footer").await.unwrap());
+        assert!(matches!(&missing[0], Err(LibError::LlmCall { message }) if message.contains("integ-cassette footer")), "Unexpected result: {:?}", missing[0]);
+
+        RustAgent::stop_llm_cassette();
+        assert!(results(run("describe the integ-cassette footer").await.unwrap())[0].is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_automate_classifies_llm_errors() {
+        let agent = setup_agent();
+        // Classified like real error responses, so the test sees the same errors hosts would.
+        let failures = [
+            ("integ-llm-error rate limited", classify_response(429, Some("20"), r#"{"error": {"message": "Rate limit reached", "code": "rate_limit_exceeded"}}"#)),
+            ("integ-llm-error context length", classify_response(400, None, r#"{"error": {"message": "This model's maximum context length is 128000 tokens.", "code": "context_length_exceeded"}}"#)),
+            ("integ-llm-error auth", classify_response(401, None, r#"{"error": {"message": "Incorrect API key provided", "code": "invalid_api_key"}}"#)),
+            ("integ-llm-error filtered", classify_empty_response(Some("content_filter"), r#"{"choices": [{"finish_reason": "content_filter"}]}"#)),
+            ("integ-llm-error network", LlmError::Network("Request error: NetworkError when attempting to fetch resource.".to_string())),
+        ];
+        for (matcher, error) in failures {
+            fixtures::add_failure(matcher, error).unwrap();
+        }
+        let tasks = vec![
+            "integ-llm-error rate limited",
+            "integ-llm-error context length",
+            "integ-llm-error auth",
+            "integ-llm-error filtered",
+            "integ-llm-error network",
+            "this task should fail_llm_call please",
+        ];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        let error_types: Vec<&str> = results.as_array().unwrap().iter().map(|result| result["Err"]["error_type"].as_str().unwrap()).collect();
+        assert_eq!(error_types, ["RateLimited", "ContextLengthExceeded", "AuthFailed", "ContentFiltered", "Network", "LlmCall"]);
+        assert_eq!(results[0]["Err"]["retry_after_ms"], 20000);
+        assert!(results[2]["Err"]["message"].as_str().unwrap().contains("Incorrect API key"));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_run_limits_abort_the_run() {
+        let mut agent = setup_agent();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let button = dom_utils::setup_element(&document, "reason-open", "button", None);
+        assert!(agent.set_run_limits("{\"max_dom_mutations\": \"many\"}".to_string()).is_err());
+
+        agent.set_run_limits(r#"{"max_dom_mutations": 1}"#.to_string()).unwrap();
+        let tasks = vec!["CLICK css:#reason-open", "TRY", "CLICK css:#reason-open", "CATCH", "READ css:#reason-open", "END_TRY", "CLICK css:#reason-open"];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert_eq!(results.as_array().unwrap().len(), 2, "Neither CATCH steps nor later tasks run: {}", results);
+        assert!(results[0]["Ok"].is_string());
+        assert_eq!(results[1]["Err"]["error_type"], "RunLimitExceeded");
+        assert_eq!(results[1]["Err"]["limit"], "max_dom_mutations");
+        assert_eq!(results[1]["Err"]["max"], 1);
+        let usage: Value = serde_json::from_str(&agent.last_run_usage()).unwrap();
+        assert_eq!(usage, serde_json::json!({"dom_mutations": 1, "navigations": 0, "llm_calls": 0}));
+
+        // Three clicks; the second is refused and the third never runs.
+        let plan = r#"[{"action": "CLICK", "selector": "css:#reason-open"}, {"action": "CLICK", "selector": "css:#reason-open"}, {"action": "CLICK", "selector": "css:#reason-open"}]"#;
+        RustAgent::add_llm_fixture("integ-limits".to_string(), plan.to_string()).unwrap();
+        let result_js = agent.automate(serde_json::to_string(&vec!["Log in integ-limits"]).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert_eq!(results[0]["Err"]["limit"], "max_dom_mutations", "Each run starts counting anew: {}", results);
+        assert_eq!(serde_json::from_str::<Value>(&agent.last_run_usage()).unwrap()["llm_calls"], 1);
+
+        agent.set_run_limits(r#"{"max_llm_calls": 0}"#.to_string()).unwrap();
+        let result_js = agent.automate(serde_json::to_string(&vec!["Log in integ-limits", "CLICK css:#reason-open"]).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert_eq!(results.as_array().unwrap().len(), 1);
+        assert_eq!(results[0]["Err"]["limit"], "max_llm_calls");
+
+        agent.set_run_limits(String::new()).unwrap();
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert!(results.as_array().unwrap().iter().all(|result| result["Ok"].is_string()), "{}", results);
+        dom_utils::cleanup_element(button);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_preview_then_apply() {
+        let agent = setup_agent();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let form = dom_utils::setup_element(&document, "integ-preview", "form", None);
+        let input = document.create_element("input").unwrap();
+        input.set_id("integ-preview-email");
+        form.append_child(&input).unwrap();
+        let value = || input.clone().dyn_into::<web_sys::HtmlInputElement>().unwrap().value();
+        assert!(agent.apply_preview().await.is_err(), "Nothing to apply yet");
+
+        let tasks = vec!["TYPE css:#integ-preview-email jane@acme.io", "NAVIGATE https://example.com/"];
+        let report = agent.preview("css:#integ-preview".to_string(), serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let report: Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["diff"]["changed"], serde_json::json!(["css:#integ-preview > input#integ-preview-email"]));
+        assert_eq!(report["results"][1]["Err"]["error_type"], "PolicyViolation");
+        assert_eq!(report["commands"], 1);
+        assert_eq!(value(), "", "The page is not changed by the preview");
+        assert!(document.get_element_by_id(sandbox::SANDBOX_HOST_ID).is_none(), "The copy is removed");
+
+        let applied = agent.apply_preview().await.unwrap();
+        let applied = output_results(&applied.as_string().unwrap());
+        assert_eq!(applied.as_array().unwrap().len(), 1);
+        assert!(applied[0]["Ok"].is_string());
+        assert_eq!(value(), "jane@acme.io");
+        assert!(agent.apply_preview().await.is_err(), "A preview is applied once");
+
+        agent.preview("css:#integ-preview".to_string(), serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        agent.discard_preview();
+        assert!(agent.apply_preview().await.is_err());
+        assert!(agent.preview("css:#integ-preview-missing".to_string(), serde_json::to_string(&tasks).unwrap()).await.is_err());
+        dom_utils::cleanup_element(form);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_agents_bound_to_regions() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let chat = dom_utils::setup_element(&document, "integ-region-chat", "div", None);
+        let form = dom_utils::setup_element(&document, "integ-region-form", "div", None);
+        for region in [&chat, &form] {
+            region.set_inner_html("<input class=\"integ-region-input\"><button class=\"integ-region-send\" onclick=\"this.textContent = 'Sent'\">Send</button>");
+        }
+        let text = |region: &web_sys::Element| region.query_selector("button").unwrap().unwrap().text_content().unwrap();
+        let mut chat_agent = setup_agent();
+        chat_agent.set_region(Some("css:#integ-region-chat".to_string()));
+        let mut form_agent = setup_agent();
+        form_agent.set_region(Some(" css:#integ-region-form ".to_string()));
+
+        let tasks = vec!["TYPE css:.integ-region-input hello", "CLICK css:.integ-region-send", "NAVIGATE https://example.com/", "CLICK css:#integ-region-chat", "GET_URL"];
+        let result_js = form_agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert!(results[0]["Ok"].is_string() && results[1]["Ok"].is_string(), "{}", results);
+        assert_eq!(results[2]["Err"]["error_type"], "PolicyViolation");
+        assert_eq!(results[3]["Err"]["kind"], "ElementNotFound", "The chat is outside the form's region: {}", results);
+        assert!(results[4]["Ok"].is_string(), "Page-wide reads are not restricted: {}", results);
+        assert_eq!((text(&chat), text(&form)), ("Send".to_string(), "Sent".to_string()));
+        let input_value = |region: &web_sys::Element| region.query_selector("input").unwrap().unwrap().dyn_into::<web_sys::HtmlInputElement>().unwrap().value();
+        assert_eq!((input_value(&chat), input_value(&form)), (String::new(), "hello".to_string()));
+
+        let result_js = chat_agent.automate(serde_json::to_string(&vec!["CLICK css:.integ-region-send"]).unwrap()).await.unwrap();
+        assert!(result_js.as_string().unwrap().contains("Ok"));
+        assert_eq!(text(&chat), "Sent");
+
+        chat.remove();
+        let result_js = chat_agent.automate(serde_json::to_string(&vec!["CLICK css:.integ-region-send"]).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert_eq!(results[0]["Err"]["kind"], "ElementNotFound", "The region is gone: {}", results);
+        chat_agent.set_region(Some(String::new()));
+        let result_js = chat_agent.automate(serde_json::to_string(&vec!["READ css:#integ-region-form"]).unwrap()).await.unwrap();
+        assert!(result_js.as_string().unwrap().contains("Ok"), "Unbound agents act on the whole page");
+        dom_utils::cleanup_element(form);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_llm_profiles_switch_models() {
+        let mut agent = setup_agent();
+        let models = || -> Vec<String> {
+            let cassette: Value = serde_json::from_str(&RustAgent::export_llm_cassette().unwrap()).unwrap();
+            cassette["interactions"].as_array().unwrap().iter().map(|interaction| interaction["model"].as_str().unwrap().to_string()).collect()
+        };
+        assert!(agent.add_llm_profile("cheap".to_string(), r#"{"api_url": "https://llm.example"}"#.to_string()).is_err());
+        assert!(agent.add_llm_profile("two words".to_string(), r#"{"model": "small"}"#.to_string()).is_err());
+        agent.add_llm_profile("cheap".to_string(), r#"{"model": "small-model"}"#.to_string()).unwrap();
+        agent.add_llm_profile("strong".to_string(), r#"{"model": "large-model", "api_key": "other_key"}"#.to_string()).unwrap();
+        assert!(agent.use_profile(Some("missing".to_string())).is_err());
+
+        RustAgent::start_llm_recording();
+        let tasks = vec!["profile:cheap summarize the integ-profile reviews", "plan the integ-profile checkout", "profile:missing plan it"];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results = output_results(&result_js.as_string().unwrap());
+        assert!(results[0]["Ok"].as_str().unwrap().contains("'summarize the integ-profile reviews'"), "The hint is not sent: {}", results);
+        assert_eq!(results[2]["Err"]["error_type"], "CommandParse");
+        assert_eq!(models(), ["small-model", "dummy_model"]);
+
+        agent.use_profile(Some("strong".to_string())).unwrap();
+        RustAgent::start_llm_recording();
+        agent.automate(serde_json::to_string(&vec!["plan the integ-profile checkout", "profile:cheap summarize the integ-profile reviews"]).unwrap()).await.unwrap();
+        assert_eq!(models(), ["large-model", "small-model"]);
+        let profiles: Value = serde_json::from_str(&agent.llm_profiles()).unwrap();
+        assert_eq!(profiles["active"], "strong");
+        assert!(!agent.llm_profiles().contains("other_key"), "API keys are not listed");
+
+        agent.use_profile(None).unwrap();
+        RustAgent::start_llm_recording();
+        agent.automate(serde_json::to_string(&vec!["plan the integ-profile checkout"]).unwrap()).await.unwrap();
+        assert_eq!(models(), ["dummy_model"]);
+        RustAgent::stop_llm_cassette();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_failed_plans_escalate_to_a_stronger_model() {
+        let mut agent = setup_agent();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let button = dom_utils::setup_element(&document, "escalate-button", "button", Some(vec![("onclick", "this.textContent = 'Pressed'")]));
+        let models = || -> Vec<String> {
+            let cassette: Value = serde_json::from_str(&RustAgent::export_llm_cassette().unwrap()).unwrap();
+            cassette["interactions"].as_array().unwrap().iter().map(|interaction| interaction["model"].as_str().unwrap().to_string()).collect()
+        };
+        let run = |task: &str| agent.automate(serde_json::to_string(&vec![task]).unwrap());
+        assert!(agent.set_model_escalation(r#"{"profile": "strong"}"#.to_string()).is_err(), "Unknown profile");
+        agent.add_llm_profile("strong".to_string(), r#"{"model": "strong-model"}"#.to_string()).unwrap();
+        agent.set_model_escalation(r#"{"profile": "strong", "after_failures": 2}"#.to_string()).unwrap();
+        // "strong-model" plans correctly once told why the earlier plans failed; other models propose
+        // a missing element, or malformed JSON for "malformed" tasks.
+        RustAgent::set_llm_provider(Some(js_sys::Function::new_no_args(
+            "return Promise.resolve((request) => request.model === 'strong-model' && request.prompt.includes('Earlier plans for this task failed')
+                 ? JSON.stringify([{ action: 'CLICK', selector: 'css:#escalate-button' }])
+                 : request.prompt.includes('integ-escalate button, malformed')
+                 ? '[{\"action\": \"CLICK\", \"selector\": '
+                 : JSON.stringify([{ action: 'CLICK', selector: 'css:#escalate-missing' }]));",
+        )));
+
+        RustAgent::start_llm_recording();
+        let results = output_results(&run("Press the integ-escalate button").await.unwrap().as_string().unwrap());
+        let commands: Value = serde_json::from_str(results[0]["Ok"].as_str().unwrap()).unwrap();
+        assert!(commands[0]["Ok"].is_string(), "{}", commands);
+        assert_eq!(button.text_content().unwrap(), "Pressed");
+        assert_eq!(models(), ["dummy_model", "dummy_model", "strong-model"]);
+        let escalations: Value = serde_json::from_str(&agent.last_run_escalations()).unwrap();
+        assert_eq!(escalations.as_array().unwrap().len(), 1);
+        assert_eq!(escalations[0]["from_model"], "dummy_model");
+        assert_eq!(escalations[0]["to_model"], "strong-model");
+        assert_eq!(escalations[0]["success"], true);
+        assert_eq!(escalations[0]["failures"].as_array().unwrap().len(), 2);
+        assert!(escalations[0]["failures"][0].as_str().unwrap().contains("css:#escalate-missing"), "{}", escalations);
+
+        agent.set_model_escalation(r#"{"profile": "strong"}"#.to_string()).unwrap();
+        RustAgent::start_llm_recording();
+        let results = output_results(&run("Press the integ-escalate button, malformed").await.unwrap().as_string().unwrap());
+        assert!(results[0]["Ok"].is_string(), "{}", results);
+        assert_eq!(models(), ["dummy_model", "strong-model"]);
+
+        agent.set_model_escalation(String::new()).unwrap();
+        RustAgent::start_llm_recording();
+        let results = output_results(&run("Press the integ-escalate button").await.unwrap().as_string().unwrap());
+        let commands: Value = serde_json::from_str(results[0]["Ok"].as_str().unwrap()).unwrap();
+        assert!(commands[0]["Err"].is_string(), "Failed plans are not retried by default: {}", commands);
+        assert_eq!(models(), ["dummy_model"]);
+        assert_eq!(agent.last_run_escalations(), "[]");
+        RustAgent::stop_llm_cassette();
+        RustAgent::set_llm_provider(None);
+        dom_utils::cleanup_element(button);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_llm_audit_log_is_redacted() {
+        let mut agent = setup_agent();
+        agent.set_pii_redaction(r#"{"emails": true}"#.to_string()).unwrap();
+        let run = |task: &str| agent.automate(serde_json::to_string(&vec![task]).unwrap());
+        let audit_log = |run_id: Option<&str>| -> Value { serde_json::from_str(&agent.get_llm_audit_log(run_id.map(str::to_string))).unwrap() };
+
+        run("Summarize the integ-audit page for ann@example.com").await.unwrap();
+        let history: Value = serde_json::from_str(&agent.get_history(None).unwrap()).unwrap();
+        let run_id = history[0]["id"].as_str().unwrap();
+        let calls = audit_log(Some(run_id));
+        assert_eq!(calls.as_array().unwrap().len(), 1, "{}", calls);
+        let prompt = calls[0]["prompt"].as_str().unwrap();
+        assert!(prompt.contains("integ-audit page for [EMAIL_1]") && !prompt.contains("ann@example.com"), "{}", prompt);
+        assert!(calls[0]["response"].as_str().unwrap().contains("[EMAIL_1]"), "{}", calls);
+        assert_eq!((calls[0]["purpose"].as_str(), calls[0]["model"].as_str()), (Some("plan"), Some("dummy_model")));
+        assert_eq!(calls[0]["prompt_tokens"], prompt.len().div_ceil(4));
+        assert!(calls[0]["started_at_ms"].as_f64() <= calls[0]["finished_at_ms"].as_f64());
+        assert!(!calls.to_string().contains("dummy_key"), "API keys are never logged");
+
+        run("this task should fail_llm_call please").await.unwrap();
+        let calls = audit_log(None);
+        let failed = calls.as_array().unwrap().last().unwrap();
+        assert!(failed["response"].is_null() && failed["error"].as_str().unwrap().contains("Mocked LLM Error"), "{}", failed);
+        assert_eq!(failed["response_tokens"], 0);
+
+        agent.clear_llm_audit_log();
+        agent.set_llm_audit_limit(0);
+        run("Summarize the integ-audit page").await.unwrap();
+        assert_eq!(agent.get_llm_audit_log(None), "[]");
+        agent.set_llm_audit_limit(200);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_automate_while_loops() {
+        let agent = setup_agent();
+        RustAgent::add_llm_fixture("integ-while items to load".to_string(), "no".to_string()).unwrap();
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let list = dom_utils::setup_element(&document, "integ-while-list", "div", None);
+        // A "Load more" button that goes away once three items are loaded.
+        let on_click = "const list = document.getElementById('integ-while-list'); \
+                        list.appendChild(document.createElement('p')); \
+                        if (list.children.length >= 3) this.remove();";
+        dom_utils::setup_element(&document, "integ-while-more", "button", Some(vec![("onclick", on_click)]));
+
+        let tasks = vec![
+            "WHILE ELEMENT_EXISTS css:#integ-while-more MAX 5",
+            "CLICK css:#integ-while-more",
+            "END_WHILE",
+            "WHILE are there more integ-while items to load", // Judged by the LLM (a fixture): false
+            "CLICK css:#integ-while-missing",
+            "END_WHILE",
+            "WHILE NOT ELEMENT_EXISTS css:#integ-while-never MAX 2",
+            "END_WHILE",
+        ];
+        let result_js = agent.automate(serde_json::to_string(&tasks).unwrap()).await.unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        assert_eq!(list.children().length(), 3);
+        // Three clicks, then the failure of the last loop, which never ends on its own.
+        assert_eq!(results.len(), 4, "Unexpected results: {:?}", results);
+        assert!(results[..3].iter().all(|result| result.is_ok()));
+        assert!(matches!(&results[3], Err(LibError::Workflow { message }) if message.contains("still held after 2 iterations")));
+
+        let invalid = serde_json::to_string(&vec!["WHILE IS_VISIBLE css:#a", "TRY", "END_WHILE", "END_TRY"]).unwrap();
+        assert!(agent.automate(invalid).await.is_err());
+        dom_utils::cleanup_element(list);
+    }
+
     #[wasm_bindgen_test]
     async fn test_automate_wait_for_element_direct_command() {
         let agent = setup_agent();
@@ -420,7 +3173,7 @@ mod tests {
 
         let tasks_success_json = serde_json::to_string(&vec!["WAIT_FOR_ELEMENT css:#integ-wait-direct 100"]).unwrap();
         let result_success_js = agent.automate(tasks_success_json).await.unwrap();
-        let results_success: Vec<Result<String, LibError>> = serde_json::from_str(&result_success_js.as_string().unwrap()).unwrap();
+        let results_success: Vec<Result<String, LibError>> = task_results(&result_success_js.as_string().unwrap());
         assert_eq!(results_success.len(), 1);
         assert!(results_success[0].is_ok());
         assert_eq!(results_success[0].as_ref().unwrap(), "Agent 3 (Generic): Element 'css:#integ-wait-direct' appeared.");
@@ -429,7 +3182,7 @@ mod tests {
 
         let tasks_timeout_json = serde_json::to_string(&vec!["WAIT_FOR_ELEMENT css:#integ-wait-timeout-direct 100"]).unwrap();
         let result_timeout_js = agent.automate(tasks_timeout_json).await.unwrap();
-        let results_timeout: Vec<Result<String, LibError>> = serde_json::from_str(&result_timeout_js.as_string().unwrap()).unwrap();
+        let results_timeout: Vec<Result<String, LibError>> = task_results(&result_timeout_js.as_string().unwrap());
         assert_eq!(results_timeout.len(), 1);
         assert!(results_timeout[0].is_err());
         match results_timeout[0].as_ref().err().unwrap() {
@@ -447,7 +3200,7 @@ mod tests {
         let agent = setup_agent();
         let tasks_json = serde_json::to_string(&vec!["What is the current page URL?"]).unwrap(); // Mock: [{"action": "GET_URL"}]
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
         let inner_result_str = results[0].as_ref().unwrap();
@@ -465,7 +3218,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["Is the button #llm-exists present?"]).unwrap(); // Mock: [{"action": "ELEMENT_EXISTS", "selector": "css:#llm-exists"}]
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
         assert!(results[0].is_ok());
         let inner_results: Vec<Result<String, String>> = serde_json::from_str(results[0].as_ref().unwrap()).unwrap();
         assert_eq!(inner_results.len(), 1);
@@ -483,7 +3236,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["Wait for #llm-wait-immediate for 100ms"]).unwrap(); // Mock: [{"action": "WAIT_FOR_ELEMENT", "selector": "css:#llm-wait-immediate", "value": "100"}]
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
         assert!(results[0].is_ok());
         let inner_results: Vec<Result<String, String>> = serde_json::from_str(results[0].as_ref().unwrap()).unwrap();
         assert_eq!(inner_results.len(), 1);
@@ -502,7 +3255,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["IS_VISIBLE css:#integ-visible-true"]).unwrap();
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
@@ -519,7 +3272,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["IS_VISIBLE css:#integ-visible-false"]).unwrap();
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
@@ -537,7 +3290,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["Is the #mainContent visible?"]).unwrap();
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
@@ -560,7 +3313,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["SCROLL_TO css:#integ-scroll-direct"]).unwrap();
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
@@ -584,7 +3337,7 @@ mod tests {
 
         let tasks_json = serde_json::to_string(&vec!["Scroll to the footer"]).unwrap();
         let result_js = agent.automate(tasks_json).await.unwrap();
-        let results: Vec<Result<String, LibError>> = serde_json::from_str(&result_js.as_string().unwrap()).unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
 
         assert_eq!(results.len(), 1);
         assert!(results[0].is_ok());
@@ -612,7 +3365,7 @@ mod tests {
         let result_js = agent.automate(tasks_json).await.unwrap();
         let result_str_outer = result_js.as_string().unwrap();
 
-        let results_outer: Vec<Result<String, LibError>> = serde_json::from_str(&result_str_outer).unwrap();
+        let results_outer: Vec<Result<String, LibError>> = task_results(&result_str_outer);
         assert_eq!(results_outer.len(), 1, "Expected one top-level task result");
         assert!(results_outer[0].is_ok(), "Expected the LLM command processing itself to be Ok");
 
@@ -660,7 +3413,7 @@ mod tests {
         // Test HOVER on existing element
         let tasks_hover_exists_json = serde_json::to_string(&vec![format!("HOVER css:#{}", element_id)]).unwrap();
         let result_hover_exists_js = agent.automate(tasks_hover_exists_json).await.unwrap();
-        let results_hover_exists: Vec<Result<String, LibError>> = serde_json::from_str(&result_hover_exists_js.as_string().unwrap()).unwrap();
+        let results_hover_exists: Vec<Result<String, LibError>> = task_results(&result_hover_exists_js.as_string().unwrap());
 
         assert_eq!(results_hover_exists.len(), 1);
         assert!(results_hover_exists[0].is_ok(), "HOVER command failed for existing element: {:?}", results_hover_exists[0].as_ref().err());
@@ -671,7 +3424,7 @@ mod tests {
         // Test HOVER on non-existent element
         let tasks_hover_nonexistent_json = serde_json::to_string(&vec!["HOVER css:#nonExistentHoverLib"]).unwrap();
         let result_hover_nonexistent_js = agent.automate(tasks_hover_nonexistent_json).await.unwrap();
-        let results_hover_nonexistent: Vec<Result<String, LibError>> = serde_json::from_str(&result_hover_nonexistent_js.as_string().unwrap()).unwrap();
+        let results_hover_nonexistent: Vec<Result<String, LibError>> = task_results(&result_hover_nonexistent_js.as_string().unwrap());
 
         assert_eq!(results_hover_nonexistent.len(), 1);
         assert!(results_hover_nonexistent[0].is_err());
@@ -706,7 +3459,7 @@ mod tests {
         // Test with default separator (newline)
         let tasks_default_sep_json = serde_json::to_string(&vec![format!("GET_ALL_TEXT css:#{} .{}", parent_id, item_class)]).unwrap();
         let result_default_sep_js = agent.automate(tasks_default_sep_json).await.unwrap();
-        let results_default_sep: Vec<Result<String, LibError>> = serde_json::from_str(&result_default_sep_js.as_string().unwrap()).unwrap();
+        let results_default_sep: Vec<Result<String, LibError>> = task_results(&result_default_sep_js.as_string().unwrap());
         assert_eq!(results_default_sep.len(), 1);
         assert!(results_default_sep[0].is_ok(), "GET_ALL_TEXT (default sep) failed: {:?}", results_default_sep[0].as_ref().err());
         assert!(results_default_sep[0].as_ref().unwrap().contains("Retrieved text from elements matching 'css:#getAllTextParentLib .myTestItemsLib' (separated by '\\n'): \"Text 1\nMore Text 2\""), "Actual: {}", results_default_sep[0].as_ref().unwrap());
@@ -715,7 +3468,7 @@ mod tests {
         // Test with custom separator "---"
         let tasks_custom_sep_json = serde_json::to_string(&vec![format!("GET_ALL_TEXT css:#{} .{} \"---\"", parent_id, item_class)]).unwrap();
         let result_custom_sep_js = agent.automate(tasks_custom_sep_json).await.unwrap();
-        let results_custom_sep: Vec<Result<String, LibError>> = serde_json::from_str(&result_custom_sep_js.as_string().unwrap()).unwrap();
+        let results_custom_sep: Vec<Result<String, LibError>> = task_results(&result_custom_sep_js.as_string().unwrap());
         assert_eq!(results_custom_sep.len(), 1);
         assert!(results_custom_sep[0].is_ok(), "GET_ALL_TEXT (custom sep) failed: {:?}", results_custom_sep[0].as_ref().err());
         assert!(results_custom_sep[0].as_ref().unwrap().contains("Retrieved text from elements matching 'css:#getAllTextParentLib .myTestItemsLib' (separated by '---'): \"Text 1---More Text 2\""));
@@ -723,7 +3476,7 @@ mod tests {
         // Test with custom separator including spaces (quoted)
         let tasks_quoted_sep_json = serde_json::to_string(&vec![format!("GET_ALL_TEXT css:#{} .{} \" | \"", parent_id, item_class)]).unwrap();
         let result_quoted_sep_js = agent.automate(tasks_quoted_sep_json).await.unwrap();
-        let results_quoted_sep: Vec<Result<String, LibError>> = serde_json::from_str(&result_quoted_sep_js.as_string().unwrap()).unwrap();
+        let results_quoted_sep: Vec<Result<String, LibError>> = task_results(&result_quoted_sep_js.as_string().unwrap());
         assert_eq!(results_quoted_sep.len(), 1);
         assert!(results_quoted_sep[0].is_ok(), "GET_ALL_TEXT (quoted sep) failed: {:?}", results_quoted_sep[0].as_ref().err());
         assert!(results_quoted_sep[0].as_ref().unwrap().contains("Retrieved text from elements matching 'css:#getAllTextParentLib .myTestItemsLib' (separated by ' | '): \"Text 1 | More Text 2\""));
@@ -734,7 +3487,7 @@ mod tests {
         // Test no elements found
         let tasks_no_elements_json = serde_json::to_string(&vec!["GET_ALL_TEXT css:.nonExistentItemsLib"]).unwrap();
         let result_no_elements_js = agent.automate(tasks_no_elements_json).await.unwrap();
-        let results_no_elements: Vec<Result<String, LibError>> = serde_json::from_str(&result_no_elements_js.as_string().unwrap()).unwrap();
+        let results_no_elements: Vec<Result<String, LibError>> = task_results(&result_no_elements_js.as_string().unwrap());
         assert_eq!(results_no_elements.len(), 1);
         assert!(results_no_elements[0].is_ok());
         assert!(results_no_elements[0].as_ref().unwrap().contains("Retrieved text from elements matching 'css:.nonExistentItemsLib' (separated by '\\n'): \"\""));
@@ -751,7 +3504,7 @@ mod tests {
 
         let tasks_no_text_json = serde_json::to_string(&vec![format!("GET_ALL_TEXT css:#{} .noTestItemsLib", parent_no_text_id)]).unwrap();
         let result_no_text_js = agent.automate(tasks_no_text_json).await.unwrap();
-        let results_no_text: Vec<Result<String, LibError>> = serde_json::from_str(&result_no_text_js.as_string().unwrap()).unwrap();
+        let results_no_text: Vec<Result<String, LibError>> = task_results(&result_no_text_js.as_string().unwrap());
         assert_eq!(results_no_text.len(), 1);
         assert!(results_no_text[0].is_ok());
         assert!(results_no_text[0].as_ref().unwrap().contains(&format!("Retrieved text from elements matching 'css:#{} .noTestItemsLib' (separated by '\\n'): \"\"", parent_no_text_id)));
@@ -762,7 +3515,7 @@ mod tests {
         // Test invalid selector
         let tasks_invalid_selector_json = serde_json::to_string(&vec!["GET_ALL_TEXT css:[[["]).unwrap();
         let result_invalid_selector_js = agent.automate(tasks_invalid_selector_json).await.unwrap();
-        let results_invalid_selector: Vec<Result<String, LibError>> = serde_json::from_str(&result_invalid_selector_js.as_string().unwrap()).unwrap();
+        let results_invalid_selector: Vec<Result<String, LibError>> = task_results(&result_invalid_selector_js.as_string().unwrap());
         assert_eq!(results_invalid_selector.len(), 1);
         assert!(results_invalid_selector[0].is_err());
         match results_invalid_selector[0].as_ref().err().unwrap() {
@@ -773,4 +3526,173 @@ mod tests {
             _ => panic!("Incorrect error type for GET_ALL_TEXT with invalid selector"),
         }
     }
+
+    #[wasm_bindgen_test]
+    async fn test_register_and_run_workflow() {
+        let mut agent = setup_agent();
+        agent.register_workflow(r#"{"name": "probe", "params": [{"name": "target"}, {"name": "timeout", "default": "50"}],
+            "steps": ["GET_URL", "WAIT_FOR_ELEMENT {{params.target}} {{params.timeout}}"]}"#.to_string()).unwrap();
+
+        let result_js = agent.run_workflow("probe".to_string(), r#"{"target": "css:body", "timeout": 100}"#.to_string()).await.unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_ok()), "Unexpected results: {:?}", results);
+
+        let err = agent.run_workflow("probe".to_string(), "{}".to_string()).await.unwrap_err();
+        let lib_err: LibError = serde_json::from_str(&err.as_string().unwrap()).unwrap();
+        assert_eq!(lib_err, LibError::Workflow { message: "Workflow 'probe' requires parameter 'target'".to_string() });
+
+        let err = agent.register_workflow(r#"{"name": "broken", "steps": ["CLICK {{params.button}}"]}"#.to_string()).unwrap_err();
+        assert!(err.as_string().unwrap().contains("\"error_type\":\"Workflow\""));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_export_workflow_and_run_scripts() {
+        let mut agent = setup_agent();
+        agent.register_workflow(r#"{"name": "probe", "params": [{"name": "target"}], "steps": ["READ {{params.target}}"]}"#.to_string()).unwrap();
+        let script = agent.export_workflow_script("probe".to_string(), r#"{"target": "css:body"}"#.to_string(), "playwright".to_string()).unwrap();
+        assert!(script.contains("test(\"workflow:probe\", async ({ page }) => {"));
+        assert!(script.contains("previousResult = await page.locator(\"css=body\").first().innerText();"));
+
+        let err = agent.export_workflow_script("probe".to_string(), "{}".to_string(), "cypress".to_string()).unwrap_err();
+        let lib_err: LibError = serde_json::from_str(&err.as_string().unwrap()).unwrap();
+        assert!(matches!(lib_err, LibError::Export { .. }));
+
+        agent.automate(r#"["GET_URL"]"#.to_string()).await.unwrap();
+        let history: Vec<RunRecord> = serde_json::from_str(&agent.get_history(None).unwrap()).unwrap();
+        let script = agent.export_run_script(history[0].id.clone(), "selenium".to_string()).unwrap();
+        assert!(script.contains("    previous_result = driver.current_url\n"));
+        assert!(agent.export_run_script("unknown".to_string(), "selenium".to_string()).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_import_script_runs_imported_tasks() {
+        let agent = setup_agent();
+        let imported: serde_json::Value = serde_json::from_str(
+            &agent.import_script("await page.waitForSelector('body');\nawait page.keyboard.press('Enter');".to_string()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(imported["tasks"], serde_json::json!(["WAIT_FOR_ELEMENT css:body"]));
+        assert_eq!(imported["skipped"][0]["line"], 2);
+
+        let result_js = agent.automate(imported["tasks"].to_string()).await.unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        assert!(results[0].is_ok(), "Unexpected results: {:?}", results);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_call_api_fetches_registered_operation() {
+        let mut agent = setup_agent();
+        let spec = r#"{"openapi": "3.0.0", "paths": {"/orders/{id}": {"get": {"operationId": "getOrder",
+            "parameters": [{"name": "id", "in": "path", "required": true, "schema": {"type": "integer"}}]}}}}"#;
+        assert!(agent.register_api("shop".to_string(), "not a spec".to_string(), None).is_err());
+        agent
+            .register_api("shop".to_string(), spec.to_string(), Some(r#"{"base_url": "https://api.test", "headers": {"X-Key": "{{secret.key}}"}}"#.to_string()))
+            .unwrap();
+        agent.set_secret("key".to_string(), "s3cr3t".to_string());
+
+        // The stub answers 404 for order 0 and echoes the key header otherwise.
+        let global = js_sys::global();
+        let original_fetch = js_sys::Reflect::get(&global, &"fetch".into()).unwrap();
+        let stub = js_sys::Function::new_with_args(
+            "url, init",
+            "const status = url.endsWith('/0') ? 404 : 200;
+             return Promise.resolve({ status, text: () => Promise.resolve(JSON.stringify({ url, method: init.method, key: init.headers['X-Key'] })) });",
+        );
+        js_sys::Reflect::set(&global, &"fetch".into(), &stub).unwrap();
+        let result_js = agent
+            .automate(r#"["CALL_API shop.getOrder {\"id\": 42}", "CALL_API shop.getOrder {\"id\": 0}", "CALL_API shop.getOrder {\"id\": \"x\"}"]"#.to_string())
+            .await;
+        js_sys::Reflect::set(&global, &"fetch".into(), &original_fetch).unwrap();
+
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.unwrap().as_string().unwrap());
+        let answer = results[0].as_ref().unwrap();
+        assert!(answer.contains(r#"answered 200: {"url":"https://api.test/orders/42","method":"GET","key":"{{secret.key}}"}"#), "Unexpected answer: {}", answer);
+        assert!(matches!(&results[1], Err(LibError::Api { status: Some(404), .. })), "Unexpected results: {:?}", results);
+        assert!(matches!(&results[2], Err(LibError::Api { status: None, .. })), "Unexpected results: {:?}", results);
+        assert!(agent.unregister_api("shop".to_string()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_call_function_runs_registered_handler() {
+        let mut agent = setup_agent();
+        let definition = r#"{"description": "Converts an amount to another currency.", "parameters": {"type": "object",
+            "properties": {"amount": {"type": "number"}, "to": {"type": "string", "enum": ["EUR", "USD"]}}, "required": ["amount", "to"]}}"#;
+        let handler = js_sys::Function::new_with_args("args", "return Promise.resolve({ amount: args.amount * 2, currency: args.to });");
+        assert!(agent.register_function("convert".to_string(), r#"{"parameters": {}}"#.to_string(), handler.clone()).is_err());
+        agent.register_function("convert".to_string(), definition.to_string(), handler).unwrap();
+
+        let result_js = agent
+            .automate(r#"["CALL_FUNCTION convert {\"amount\": 21, \"to\": \"EUR\"}", "CALL_FUNCTION convert {\"amount\": 21, \"to\": \"GBP\"}"]"#.to_string())
+            .await
+            .unwrap();
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.as_string().unwrap());
+        assert!(results[0].as_ref().unwrap().contains(r#"Function convert returned: {"amount":42,"currency":"EUR"}"#), "Unexpected results: {:?}", results);
+        assert!(matches!(&results[1], Err(LibError::HostFunction { message }) if message.contains("must be one of")), "Unexpected results: {:?}", results);
+        assert!(agent.unregister_function("convert".to_string()));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_graphql_posts_query_with_cookies() {
+        let agent = setup_agent();
+        let global = js_sys::global();
+        let original_fetch = js_sys::Reflect::get(&global, &"fetch".into()).unwrap();
+        let stub = js_sys::Function::new_with_args(
+            "url, init",
+            "const { query, variables } = JSON.parse(init.body);
+             const data = query.includes('broken') ? null : { echo: { url, credentials: init.credentials, variables } };
+             const errors = data ? undefined : [{ message: 'Cannot query field broken' }];
+             return Promise.resolve({ status: 200, text: () => Promise.resolve(JSON.stringify({ data, errors })) });",
+        );
+        js_sys::Reflect::set(&global, &"fetch".into(), &stub).unwrap();
+        let result_js = agent
+            .automate(r#"["GRAPHQL /graphql query($id: ID!) { echo(id: $id) } | {\"id\": 7}", "GRAPHQL /graphql { broken }"]"#.to_string())
+            .await;
+        js_sys::Reflect::set(&global, &"fetch".into(), &original_fetch).unwrap();
+
+        let results: Vec<Result<String, LibError>> = task_results(&result_js.unwrap().as_string().unwrap());
+        let response = results[0].as_ref().unwrap();
+        let origin = web_sys::window().unwrap().location().origin().unwrap();
+        assert!(response.contains(&format!(r#"{{"url":"{}/graphql","credentials":"include","variables":{{"id":7}}}}"#, origin)), "Unexpected response: {}", response);
+        assert!(matches!(&results[1], Err(LibError::Api { message, .. }) if message.contains("Cannot query field broken")), "Unexpected results: {:?}", results);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_schedule_runs_tasks_later() {
+        let agent = setup_agent();
+        let body = web_sys::window().unwrap().document().unwrap().body().unwrap();
+        let handle = agent.schedule(r#"["SETATTRIBUTE css:body data-scheduled yes"]"#.to_string(), 50, None).unwrap();
+        assert!(body.get_attribute("data-scheduled").is_none(), "The tasks should not run before the delay");
+        gloo_timers::future::TimeoutFuture::new(200).await;
+        assert_eq!(body.get_attribute("data-scheduled"), Some("yes".to_string()));
+        assert_eq!(handle.run_count(), 1);
+        body.remove_attribute("data-scheduled").unwrap();
+
+        assert!(agent.schedule_recurring(r#"["GET_URL"]"#.to_string(), 0, None).is_err());
+        assert!(agent.schedule("[]".to_string(), 10, None).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_add_trigger_runs_workflow_on_event() {
+        let mut agent = setup_agent();
+        agent.register_workflow(r#"{"name": "mark", "params": [{"name": "value"}],
+            "steps": ["SETATTRIBUTE css:body data-triggered {{params.value}}"]}"#.to_string()).unwrap();
+        let handle = agent
+            .add_trigger(r#"{"workflow": "mark", "params": {"value": "yes"}, "when": {"event": "rustagent:mark"}}"#.to_string(), None)
+            .unwrap();
+
+        let document = web_sys::window().unwrap().document().unwrap();
+        let body = document.body().unwrap();
+        document.dispatch_event(&web_sys::Event::new("rustagent:mark").unwrap()).unwrap();
+        gloo_timers::future::TimeoutFuture::new(50).await;
+        assert_eq!(body.get_attribute("data-triggered"), Some("yes".to_string()));
+        assert_eq!(handle.fire_count(), 1);
+        handle.cancel();
+        body.remove_attribute("data-triggered").unwrap();
+
+        let err = agent.add_trigger(r#"{"workflow": "missing", "when": {"event": "x"}}"#.to_string(), None).unwrap_err();
+        assert!(err.as_string().unwrap().contains("\"error_type\":\"Workflow\""));
+        let err = agent.add_trigger(r#"{"workflow": "mark", "when": {"on_hover": "css:a"}}"#.to_string(), None).unwrap_err();
+        assert!(err.as_string().unwrap().contains("\"error_type\":\"CommandParse\""));
+    }
 }
\ No newline at end of file
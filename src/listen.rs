@@ -0,0 +1,226 @@
+use crate::dom_utils::{self, DomError};
+use gloo_timers::future::TimeoutFuture;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Longest time `LISTEN` may listen for.
+pub const MAX_LISTEN_DURATION_MS: u32 = 60_000;
+/// Events logged by one `LISTEN`; later ones are only counted.
+pub const MAX_LISTENED_EVENTS: usize = 200;
+
+/// An event received by the element `LISTEN` listened on, or by one of its descendants.
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct ListenedEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The element the event was dispatched to (tag, id and first class), or the node name
+    /// of other targets.
+    pub target: String,
+    /// When the event was dispatched, in milliseconds since listening started.
+    pub time_ms: f64,
+    /// `false` for events dispatched by a script (including the agent's own commands) rather
+    /// than by the browser in response to the user.
+    pub trusted: bool,
+    /// Whether a handler called `preventDefault()`.
+    pub default_prevented: bool,
+    /// Keyboard events: the key value and the physical key code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Mouse and pointer events: the button (0 main, 1 middle, 2 secondary) and viewport position.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub button: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_y: Option<i32>,
+    /// Input events: the kind of edit (e.g. `insertText`) and the inserted text.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// The modifier keys held: `ctrl`, `shift`, `alt` and `meta`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+}
+
+/// What `LISTEN` returns.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ListenReport {
+    pub selector: String,
+    pub event_types: Vec<String>,
+    pub duration_ms: u32,
+    /// Every event received, including those beyond `MAX_LISTENED_EVENTS` that are not logged.
+    pub count: usize,
+    /// The first `MAX_LISTENED_EVENTS` events, in the order they were dispatched.
+    pub events: Vec<ListenedEvent>,
+}
+
+/// Splits the value of `LISTEN`, `"<event>[,<event>...] <duration_ms>"`, into the event types and
+/// the duration.
+///
+/// # Returns
+/// The event types and the duration, or `None` if an event type is empty or has characters other
+/// than letters, digits, `-`, `_`, `:` and `.`, or the duration is not between 1 and
+/// `MAX_LISTEN_DURATION_MS`.
+pub fn parse_value(value: &str) -> Option<(Vec<&str>, u32)> {
+    let (events, duration) = value.trim().rsplit_once(char::is_whitespace)?;
+    let duration = duration.trim().parse::<u32>().ok().filter(|duration| (1..=MAX_LISTEN_DURATION_MS).contains(duration))?;
+    let events: Vec<&str> = events.split(',').map(str::trim).collect();
+    let is_valid = |event: &&str| !event.is_empty() && event.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'));
+    if !events.iter().all(is_valid) {
+        return None;
+    }
+    Some((events, duration))
+}
+
+/// Listens for `event_types` on the element matched by `selector` for `duration_ms`, then returns
+/// a JSON `ListenReport` of the events it and its descendants received, e.g. to check that a
+/// control reacts to the keys and clicks it should. Listeners run in the capture phase, so events
+/// whose propagation a page handler stops are still logged. Events are described once listening
+/// ends, so `default_prevented` reflects every handler.
+pub async fn listen(selector: &str, event_types: &[&str], duration_ms: u32) -> Result<String, DomError> {
+    let (window, document) = dom_utils::get_window_document()?;
+    let element = dom_utils::get_element(&document, selector)?;
+    let started = window.performance().map(|performance| performance.now()).unwrap_or_default();
+
+    let received: Rc<RefCell<Vec<web_sys::Event>>> = Rc::new(RefCell::new(Vec::new()));
+    let count = Rc::new(Cell::new(0usize));
+    let listener = {
+        let received = received.clone();
+        let count = count.clone();
+        Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
+            count.set(count.get() + 1);
+            let mut received = received.borrow_mut();
+            if received.len() < MAX_LISTENED_EVENTS {
+                received.push(event);
+            }
+        })
+    };
+    for event_type in event_types {
+        element.add_event_listener_with_callback_and_bool(event_type, listener.as_ref().unchecked_ref(), true)?;
+    }
+    TimeoutFuture::new(duration_ms).await;
+    for event_type in event_types {
+        element.remove_event_listener_with_callback_and_bool(event_type, listener.as_ref().unchecked_ref(), true)?;
+    }
+
+    let report = ListenReport {
+        selector: selector.to_string(),
+        event_types: event_types.iter().map(|event_type| event_type.to_string()).collect(),
+        duration_ms,
+        count: count.get(),
+        events: received.borrow().iter().map(|event| describe_event(event, started)).collect(),
+    };
+    serde_json::to_string(&report).map_err(|e| DomError::JsError { message: format!("Failed to serialize events: {}", e) })
+}
+
+// Describes `event` for the log, reading the fields of keyboard, mouse and input events where the
+// event has them.
+fn describe_event(event: &web_sys::Event, started: f64) -> ListenedEvent {
+    let target = match event.target() {
+        Some(target) => match target.dyn_ref::<web_sys::Element>() {
+            Some(element) => dom_utils::describe_element(element),
+            None => target.dyn_ref::<web_sys::Node>().map(|node| node.node_name().to_lowercase()).unwrap_or_default(),
+        },
+        None => String::new(),
+    };
+    let text = |name: &str| js_sys::Reflect::get(event, &name.into()).ok().and_then(|value| value.as_string());
+    let mouse = event.dyn_ref::<web_sys::MouseEvent>();
+    let modifiers = [("ctrlKey", "ctrl"), ("shiftKey", "shift"), ("altKey", "alt"), ("metaKey", "meta")]
+        .into_iter()
+        .filter(|(property, _)| js_sys::Reflect::get(event, &(*property).into()).ok().and_then(|value| value.as_bool()) == Some(true))
+        .map(|(_, name)| name.to_string())
+        .collect();
+    ListenedEvent {
+        event_type: event.type_(),
+        target,
+        time_ms: ((event.time_stamp() - started).max(0.0) * 10.0).round() / 10.0,
+        trusted: event.is_trusted(),
+        default_prevented: event.default_prevented(),
+        key: text("key"),
+        code: text("code"),
+        button: mouse.map(|mouse| mouse.button()),
+        client_x: mouse.map(|mouse| mouse.client_x()),
+        client_y: mouse.map(|mouse| mouse.client_y()),
+        input_type: text("inputType"),
+        data: text("data"),
+        modifiers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse_value("click 2000"), Some((vec!["click"], 2000)));
+        assert_eq!(parse_value(" keydown, keyup,app:saved  500 "), Some((vec!["keydown", "keyup", "app:saved"], 500)));
+        assert_eq!(parse_value("click"), None, "A duration is required");
+        assert_eq!(parse_value("click 0"), None);
+        assert_eq!(parse_value("click 60001"), None, "Durations are capped");
+        assert_eq!(parse_value("click,,keyup 100"), None);
+        assert_eq!(parse_value("on<click> 100"), None);
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_listen_logs_events() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let form = document.create_element("form").unwrap();
+        form.set_id("listen-form");
+        form.set_inner_html(r#"<input id="listen-input" class="field"><button id="listen-other" type="button">Other</button>"#);
+        document.body().unwrap().append_child(&form).unwrap();
+        // A page handler that cancels Enter and stops its propagation.
+        let cancel_enter = js_sys::Function::new_with_args("event", "if (event.key === 'Enter') { event.preventDefault(); event.stopPropagation(); }");
+        document.get_element_by_id("listen-input").unwrap().add_event_listener_with_callback("keydown", &cancel_enter).unwrap();
+
+        wasm_bindgen_futures::spawn_local(async {
+            TimeoutFuture::new(30).await;
+            let document = web_sys::window().unwrap().document().unwrap();
+            let init = web_sys::KeyboardEventInit::new();
+            init.set_key("Enter");
+            init.set_code("Enter");
+            init.set_ctrl_key(true);
+            init.set_bubbles(true);
+            init.set_cancelable(true);
+            let keydown = web_sys::KeyboardEvent::new_with_keyboard_event_init_dict("keydown", &init).unwrap();
+            document.get_element_by_id("listen-input").unwrap().dispatch_event(&keydown).unwrap();
+            let init = web_sys::MouseEventInit::new();
+            init.set_button(2);
+            init.set_client_x(10);
+            init.set_client_y(20);
+            init.set_bubbles(true);
+            let click = web_sys::MouseEvent::new_with_mouse_event_init_dict("click", &init).unwrap();
+            document.get_element_by_id("listen-other").unwrap().dispatch_event(&click).unwrap();
+            // Not listened for.
+            document.get_element_by_id("listen-other").unwrap().dispatch_event(&web_sys::Event::new("focus").unwrap()).unwrap();
+        });
+        let report: serde_json::Value = serde_json::from_str(&listen("#listen-form", &["keydown", "click"], 200).await.unwrap()).unwrap();
+        assert_eq!(report["count"], 2);
+        let keydown = &report["events"][0];
+        assert_eq!(keydown["type"], "keydown");
+        assert_eq!(keydown["target"], "input#listen-input.field");
+        assert_eq!(keydown["key"], "Enter");
+        assert_eq!(keydown["modifiers"], serde_json::json!(["ctrl"]));
+        assert_eq!(keydown["default_prevented"], true, "Handlers that ran after the capture listener are reflected");
+        assert_eq!(keydown["trusted"], false);
+        assert!(keydown["time_ms"].as_f64().unwrap() >= 0.0);
+        let click = &report["events"][1];
+        assert_eq!(click["target"], "button#listen-other");
+        assert_eq!((click["button"].as_i64(), click["client_x"].as_i64(), click["client_y"].as_i64()), (Some(2), Some(10), Some(20)));
+        assert!(click.get("key").is_none());
+
+        let report: serde_json::Value = serde_json::from_str(&listen("#listen-form", &["click"], 1).await.unwrap()).unwrap();
+        assert_eq!(report["count"], 0, "Events dispatched before listening started are not logged");
+        assert!(matches!(listen("#no-such-form", &["click"], 1).await, Err(DomError::ElementNotFound { .. })));
+        form.remove();
+    }
+}
@@ -1,8 +1,15 @@
 use wasm_bindgen::prelude::*;
-use serde_json::json; // Used by both real and mock
+#[cfg(all(not(feature = "mock-llm"), feature = "http-llm"))]
+use serde_json::json; // Only used by the real (non-mock) implementation
 use web_sys::console; // Used by both real and mock
+use crate::cassette; // Recorded responses, used by both real and mock
+use crate::fixtures; // Runtime responses, used by both real and mock
+#[cfg(all(not(feature = "mock-llm"), feature = "http-llm"))]
+use crate::llm_error::{classify_empty_response, classify_response}; // Only used by the real (non-mock) implementation
+use crate::llm_error::LlmError; // Used by both real and mock
+use crate::provider; // LLM provider registered by the host, used by both real and mock
 
-#[cfg(not(feature = "mock-llm"))]
+#[cfg(all(not(feature = "mock-llm"), feature = "http-llm"))]
 use reqwest::Client; // Only used by the real (non-mock) implementation
 
 /// Calls a Large Language Model (LLM) API with the given prompt.
@@ -10,10 +17,19 @@ use reqwest::Client; // Only used by the real (non-mock) implementation
 /// This function has two implementations based on the "mock-llm" feature flag:
 /// 1.  **Real Implementation (default):** Makes an actual HTTP POST request to the specified LLM API.
 ///     It constructs a JSON payload with the model name and prompt, sends it, and parses
-///     the expected response structure to extract the LLM's content.
+///     the expected response structure to extract the LLM's content. The HTTP client is part of
+///     the `http-llm` feature (on by default); without it, calls fail unless a provider is registered.
 /// 2.  **Mock Implementation (`#[cfg(feature = "mock-llm")]`):** Does not make any network requests.
-///     Instead, it returns predefined string responses based on keywords found in the `prompt`.
-///     This is used for testing to simulate various LLM behaviors predictably and offline.
+///     Instead, it returns a response naming the task of the `prompt`. Tests script the responses
+///     they need with fixtures, to simulate various LLM behaviors predictably and offline.
+///
+/// In both, a response registered with `fixtures::add` (see `RustAgent::add_llm_fixture`) for a
+/// text the task or question of the prompt contains (see `prompt_subject`) is returned without
+/// calling the LLM.
+/// While a cassette is replayed (see `cassette::replay`), its recorded responses are returned
+/// instead, and a prompt it has no response for fails; while one is recorded, every response the
+/// LLM (or the mock) gives is added to it. Otherwise, a provider registered with
+/// `provider::set_loader` (see `RustAgent::set_llm_provider`) is called instead of either implementation.
 ///
 /// # Arguments
 /// * `prompt`: The prompt string to send to the LLM.
@@ -27,10 +43,36 @@ use reqwest::Client; // Only used by the real (non-mock) implementation
 ///     - (Real) The HTTP request fails (e.g., network error).
 ///     - (Real) The LLM API returns a non-successful status code.
 ///     - (Real) The LLM API response cannot be parsed as expected.
-///     - (Mock) The prompt triggers a specific mocked error scenario.
-#[cfg(not(feature = "mock-llm"))]
+///     - A fixture registered for the prompt is a failure (see `fixtures::add_failure`).
+///     - A replayed cassette has no response for the prompt.
+///
+///     The agents call `complete` instead, which reports why the call failed as an `LlmError`.
 #[wasm_bindgen]
 pub async fn call_llm_async(prompt: String, api_key: String, api_url: String, model_name: String) -> Result<String, JsValue> {
+    complete(prompt, api_key, api_url, model_name).await.map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Calls the LLM like `call_llm_async`, classifying failures (see `llm_error::classify_response`).
+pub async fn complete(prompt: String, api_key: String, api_url: String, model_name: String) -> Result<String, LlmError> {
+    if let Some(response) = fixtures::respond(&prompt) {
+        console::log_1(&"call_llm_async answered by an LLM fixture".into());
+        return response;
+    }
+    if let Some(replayed) = cassette::replay(&prompt, prompt_task) {
+        console::log_1(&"call_llm_async answered from the replayed cassette".into());
+        return replayed.map_err(LlmError::Other);
+    }
+    let response = match provider::complete(&prompt, &api_key, &api_url, &model_name).await {
+        Some(response) => response?,
+        None => request_llm(prompt.clone(), api_key, api_url, model_name.clone()).await?,
+    };
+    cassette::record(&model_name, &prompt, &response);
+    Ok(response)
+}
+
+// The real implementation of `call_llm_async`.
+#[cfg(all(not(feature = "mock-llm"), feature = "http-llm"))]
+async fn request_llm(prompt: String, api_key: String, api_url: String, model_name: String) -> Result<String, LlmError> {
     console::log_1(&"call_llm_async called (REAL)".into()); // Log that the real function is called
 
     let client = Client::new(); // Create a new reqwest client
@@ -55,21 +97,23 @@ pub async fn call_llm_async(prompt: String, api_key: String, api_url: String, mo
         .await
         .map_err(|e| {
             console::error_1(&format!("Request error (REAL): {}", e).into());
-            JsValue::from_str(&format!("Request error: {}", e.to_string()))
+            LlmError::Network(format!("Request error: {}", e))
         })?;
 
     console::log_1(&format!("Response status (REAL): {}", res.status()).into());
 
     if !res.status().is_success() {
+        let status = res.status().as_u16();
+        let retry_after = res.headers().get("retry-after").and_then(|value| value.to_str().ok()).map(str::to_string);
         let error_text = res.text().await.unwrap_or_else(|_| "Failed to get error text".to_string());
         console::error_1(&format!("API error (REAL): {}", error_text).into());
-        return Err(JsValue::from_str(&format!("API error: {}", error_text)));
+        return Err(classify_response(status, retry_after.as_deref(), &error_text));
     }
 
     let response_body: serde_json::Value = res.json().await.map_err(|e| {
         let error_message = format!("JSON parsing error (REAL): {}", e);
         console::error_1(&error_message.clone().into()); // Clone error_message for console
-        JsValue::from_str(&error_message)
+        LlmError::Other(error_message)
     })?;
 
     console::log_1(&format!("Response body (REAL raw): {}", response_body.to_string()).into());
@@ -86,132 +130,182 @@ pub async fn call_llm_async(prompt: String, api_key: String, api_url: String, mo
             let error_message = "Failed to extract content from LLM response (REAL): structure was not as expected.";
             console::error_1(&error_message.into());
             console::error_1(&format!("Full response body for debugging (REAL): {}", response_body.to_string()).into());
-            JsValue::from_str(error_message)
+            // A response cut off by the content filter has no content.
+            let finish_reason = response_body.pointer("/choices/0/finish_reason").and_then(|reason| reason.as_str());
+            classify_empty_response(finish_reason, &response_body.to_string())
         })?;
 
     Ok(content)
 }
 
+// The real implementation of `call_llm_async` in builds without the HTTP client.
+#[cfg(all(not(feature = "mock-llm"), not(feature = "http-llm")))]
+async fn request_llm(_prompt: String, _api_key: String, _api_url: String, _model_name: String) -> Result<String, LlmError> {
+    Err(LlmError::Other(
+        "This build has no LLM client (the `http-llm` feature is off). Register a provider with set_llm_provider.".to_string(),
+    ))
+}
+
+// The mock implementation of `call_llm_async`. Tests script its answers with fixtures (see
+// `add_mock_fixtures`); other prompts get a response naming their task.
 #[cfg(feature = "mock-llm")]
-#[wasm_bindgen]
-pub async fn call_llm_async(prompt: String, _api_key: String, _api_url: String, _model_name: String) -> Result<String, JsValue> {
+async fn request_llm(prompt: String, _api_key: String, _api_url: String, _model_name: String) -> Result<String, LlmError> {
     console::log_1(&format!("call_llm_async called (MOCK) for prompt containing task:\n\"{}\"", extract_task_from_prompt(&prompt)).into());
 
+    // Fallback for structured prompts (containing the task marker) no fixture answers.
+    if prompt.contains("The user wants to perform the following task:") {
+        let task_content = extract_task_from_prompt(&prompt);
+        Ok(format!("Mocked LLM response regarding task: '{}'", task_content))
+    }
+    // Default fallback for any other prompt, helps in identifying missing fixtures during testing.
+    else {
+        Ok(format!("Default Mocked LLM response for unhandled prompt structure: {}", prompt))
+    }
+}
+
+// The fixtures most tests of this crate share, as (matcher, response) pairs. Tests of a single
+// behaviour (judging, disambiguation, guardrails, ...) register their own instead.
+#[cfg(all(test, feature = "mock-llm"))]
+const MOCK_FIXTURES: &[(&str, &str)] = &[
     // --- Group: Mocks for specific DOM command JSON responses ---
     // These simulate the LLM successfully translating a natural language query into one or more structured DOM commands.
-    if prompt.contains("click the submit button") {
-        return Ok("[{\"action\": \"CLICK\", \"selector\": \"css:#submitBtn\"}]".to_string());
-    } else if prompt.contains("login with testuser and click login") { // Example of a multi-command sequence
-        return Ok("[{\"action\": \"TYPE\", \"selector\": \"css:#username\", \"value\": \"testuser\"}, {\"action\": \"CLICK\", \"selector\": \"css:#loginBtn\"}]".to_string());
-    } else if prompt.contains("get logo src") { // Example for GETATTRIBUTE
-        return Ok("[{\"action\": \"GETATTRIBUTE\", \"selector\": \"css:img#logo\", \"attribute_name\": \"src\"}]".to_string());
-    } else if prompt.contains("set alt text for myImage") { // Example for SETATTRIBUTE
-        return Ok("[{\"action\": \"SETATTRIBUTE\", \"selector\": \"id=myImage\", \"attribute_name\": \"alt\", \"value\": \"New alt text\"}]".to_string());
-
+    ("click the submit button", r#"[{"action": "CLICK", "selector": "css:#submitBtn"}]"#),
+    ("login with testuser and click login", r#"[{"action": "TYPE", "selector": "css:#username", "value": "testuser"}, {"action": "CLICK", "selector": "css:#loginBtn"}]"#),
+    ("get logo src", r#"[{"action": "GETATTRIBUTE", "selector": "css:img#logo", "attribute_name": "src"}]"#),
+    ("set alt text for myImage", r#"[{"action": "SETATTRIBUTE", "selector": "id=myImage", "attribute_name": "alt", "value": "New alt text"}]"#),
     // --- Group: Mocks for testing robust JSON parsing and error handling in agent.rs ---
     // These simulate various ways the LLM might return malformed or unexpected JSON.
-    } else if prompt.contains("task expected to return invalid json") { // Not a valid JSON string.
-        return Ok("This is not JSON.".to_string());
-    } else if prompt.contains("task expected to return json object not array") { // Valid JSON, but not an array.
-        return Ok("{\"message\": \"This is a JSON object, not an array.\"}".to_string());
-    } else if prompt.contains("task expected to return json array of non-commands") { // Valid JSON array, but objects don't match LlmDomCommandRequest.
-        return Ok("[{\"foo\": \"bar\"}]".to_string());
-    } else if prompt.contains("task expected to return empty command array") { // Valid empty JSON array.
-        return Ok("[]".to_string());
-    } else if prompt.contains("task with mixed valid and invalid commands") { // Array with one command having an unrecognized "action".
-        return Ok("[{\"action\": \"CLICK\", \"selector\": \"css:#ok\"}, {\"action\": \"INVALID_ACTION\", \"selector\": \"css:#bad\"}, {\"action\": \"TYPE\", \"selector\": \"css:#missingValue\"}]".to_string());
-    } else if prompt.contains("task with mixed valid and malformed json commands") { // Array with one command object being structurally malformed.
-        return Ok("[{\"action\": \"CLICK\", \"selector\": \"css:#valid\"}, {\"invalid_field\": \"some_value\", \"action\": \"EXTRA_INVALID_FIELD\"}, {\"action\": \"TYPE\", \"selector\": \"css:#anotherValid\", \"value\": \"test\"}]".to_string());
-    }
+    ("task expected to return invalid json", "This is not JSON."),
+    ("task expected to return json object not array", r#"{"message": "This is a JSON object, not an array."}"#),
+    ("task expected to return json array of non-commands", r#"[{"foo": "bar"}]"#),
+    ("task expected to return empty command array", "[]"),
+    ("task with mixed valid and invalid commands", r#"[{"action": "CLICK", "selector": "css:#ok"}, {"action": "INVALID_ACTION", "selector": "css:#bad"}, {"action": "TYPE", "selector": "css:#missingValue"}]"#),
+    ("task with mixed valid and malformed json commands", r#"[{"action": "CLICK", "selector": "css:#valid"}, {"invalid_field": "some_value", "action": "EXTRA_INVALID_FIELD"}, {"action": "TYPE", "selector": "css:#anotherValid", "value": "test"}]"#),
     // --- Group: Mocks for testing placeholder substitution and sequential task execution in lib.rs (automate function) ---
-    else if prompt.contains("get text from #element") { // Simulates LLM returning a simple string value.
-        return Ok("Text from #element".to_string());
-    }
-    else if prompt.contains("TYPE css:#input Text from #element") { // Task after {{PREVIOUS_RESULT}} substitution.
-        return Ok("[{\"action\": \"TYPE\", \"selector\": \"css:#input\", \"value\": \"Text from #element\"}]".to_string());
-    }
-    else if prompt.contains("TYPE css:#input ") && prompt.ends_with("{{PREVIOUS_RESULT}}") { // Task if {{PREVIOUS_RESULT}} was empty.
-        return Ok("[{\"action\": \"TYPE\", \"selector\": \"css:#input\", \"value\": \"\"}]".to_string());
-    }
-    else if prompt.contains("click #first_button") {
-        return Ok("Clicked #first_button".to_string());
-    }
-    else if prompt.contains("process Clicked #first_button for task B") {
-        return Ok("Processed Clicked #first_button".to_string());
-    }
-    else if prompt.contains("process Processed Clicked #first_button for task C") {
-        return Ok("Final result from C".to_string());
-    }
-    else if prompt.contains("get simple id") {
-        return Ok("element_id_123".to_string());
-    }
-    else if prompt.contains("LLM_ACTION_EXPECTING_JSON_CMDS element_id_123") { // Task after {{PREVIOUS_RESULT}} substitution.
-        return Ok("[{\"action\": \"CLICK\", \"selector\": \"#element_id_123\"}, {\"action\": \"READ\", \"selector\": \"#another_element\"}]".to_string());
-    }
+    ("get text from #element", "Text from #element"),
+    ("TYPE css:#input Text from #element", r#"[{"action": "TYPE", "selector": "css:#input", "value": "Text from #element"}]"#),
+    ("click #first_button", "Clicked #first_button"),
+    ("process Clicked #first_button for task B", "Processed Clicked #first_button"),
+    ("process Processed Clicked #first_button for task C", "Final result from C"),
+    ("get simple id", "element_id_123"),
+    ("LLM_ACTION_EXPECTING_JSON_CMDS element_id_123", r##"[{"action": "CLICK", "selector": "#element_id_123"}, {"action": "READ", "selector": "#another_element"}]"##),
     // --- Group: Mocks for specific commands, often used in integration tests (lib.rs) and agent.rs tests ---
-    else if prompt.contains("fill username and password and click login") { // Used in integration_test.rs (if it existed separately)
-        return Ok("[{\"action\": \"TYPE\", \"selector\": \"css:#testuser\", \"value\": \"testuser\"}, {\"action\": \"TYPE\", \"selector\": \"css:#testpass\", \"value\": \"testpass\"}, {\"action\": \"CLICK\", \"selector\": \"css:#testloginbtn\"}]".to_string());
-    }
-    else if prompt.contains("llm_get_url_task") || prompt.contains("What is the current page URL?") {
-        return Ok("[{\"action\": \"GET_URL\"}]".to_string());
-    } else if prompt.contains("llm_element_exists_true_task") || prompt.contains("Is the button #llm-exists present?") {
-        return Ok("[{\"action\": \"ELEMENT_EXISTS\", \"selector\": \"css:#llm-exists\"}]".to_string());
-    } else if prompt.contains("llm_element_exists_false_task") || prompt.contains("Is #llm-nonexistent present?") {
-        return Ok("[{\"action\": \"ELEMENT_EXISTS\", \"selector\": \"css:#llm-nonexistent\"}]".to_string());
-    } else if prompt.contains("llm_wait_for_element_immediate_task") || prompt.contains("Wait for #llm-wait-immediate for 100ms") {
-        return Ok("[{\"action\": \"WAIT_FOR_ELEMENT\", \"selector\": \"css:#llm-wait-immediate\", \"value\": \"100\"}]".to_string());
-    } else if prompt.contains("llm_wait_for_element_timeout_task") || prompt.contains("Wait for #llm-wait-timeout for 50ms") {
-        return Ok("[{\"action\": \"WAIT_FOR_ELEMENT\", \"selector\": \"css:#llm-wait-timeout\", \"value\": \"50\"}]".to_string());
-    }
+    ("fill username and password and click login", r#"[{"action": "TYPE", "selector": "css:#testuser", "value": "testuser"}, {"action": "TYPE", "selector": "css:#testpass", "value": "testpass"}, {"action": "CLICK", "selector": "css:#testloginbtn"}]"#),
+    ("llm_get_url_task", r#"[{"action": "GET_URL"}]"#),
+    ("What is the current page URL?", r#"[{"action": "GET_URL"}]"#),
+    ("llm_element_exists_true_task", r#"[{"action": "ELEMENT_EXISTS", "selector": "css:#llm-exists"}]"#),
+    ("Is the button #llm-exists present?", r#"[{"action": "ELEMENT_EXISTS", "selector": "css:#llm-exists"}]"#),
+    ("llm_element_exists_false_task", r#"[{"action": "ELEMENT_EXISTS", "selector": "css:#llm-nonexistent"}]"#),
+    ("Is #llm-nonexistent present?", r#"[{"action": "ELEMENT_EXISTS", "selector": "css:#llm-nonexistent"}]"#),
+    ("llm_wait_for_element_immediate_task", r#"[{"action": "WAIT_FOR_ELEMENT", "selector": "css:#llm-wait-immediate", "value": "100"}]"#),
+    ("Wait for #llm-wait-immediate for 100ms", r#"[{"action": "WAIT_FOR_ELEMENT", "selector": "css:#llm-wait-immediate", "value": "100"}]"#),
+    ("llm_wait_for_element_timeout_task", r#"[{"action": "WAIT_FOR_ELEMENT", "selector": "css:#llm-wait-timeout", "value": "50"}]"#),
+    ("Wait for #llm-wait-timeout for 50ms", r#"[{"action": "WAIT_FOR_ELEMENT", "selector": "css:#llm-wait-timeout", "value": "50"}]"#),
     // --- Group: Mocks for IS_VISIBLE and SCROLL_TO commands ---
-    else if prompt.contains("Is the #mainContent visible?") {
-        return Ok("[{\"action\": \"IS_VISIBLE\", \"selector\": \"css:#mainContent\"}]".to_string());
-    } else if prompt.contains("Is #sidebar hidden?") { // Example to test IS_VISIBLE, result depends on actual element state.
-        return Ok("[{\"action\": \"IS_VISIBLE\", \"selector\": \"css:#sidebar\"}]".to_string());
-    } else if prompt.contains("Scroll to the footer") {
-        return Ok("[{\"action\": \"SCROLL_TO\", \"selector\": \"css:footer\"}]".to_string());
-    } else if prompt.contains("scroll to #detailsSection") {
-        return Ok("[{\"action\": \"SCROLL_TO\", \"selector\": \"css:#detailsSection\"}]".to_string());
+    ("Is the #mainContent visible?", r#"[{"action": "IS_VISIBLE", "selector": "css:#mainContent"}]"#),
+    ("Is #sidebar hidden?", r#"[{"action": "IS_VISIBLE", "selector": "css:#sidebar"}]"#),
+    ("Scroll to the footer", r#"[{"action": "SCROLL_TO", "selector": "css:footer"}]"#),
+    ("scroll to #detailsSection", r#"[{"action": "SCROLL_TO", "selector": "css:#detailsSection"}]"#),
+    // --- Group: General Fallbacks ---
+    // These simulate natural language responses.
+    ("navigate to example.com", "Mocked LLM response for 'navigate to example.com'"),
+    ("fill the login form with my details", "Mocked LLM response for 'fill the login form with my details'"),
+    ("summarize this document for me", "Mocked LLM response for 'summarize this document for me'"),
+    ("navigate then CLICK xpath://button[@id='specificButtonXpath']", "Mocked LLM response for 'navigate then CLICK xpath://button[@id='specificButtonXpath']'"),
+    ("navigate then CLICK #myButtonDirect", "Mocked LLM response for 'navigate then CLICK #myButtonDirect'"),
+    ("error_test_prompt", "Mocked LLM response: This prompt triggers a success for error testing."),
+];
+
+/// Registers the fixtures of `MOCK_FIXTURES`, and a failure for tasks asking for one, so that
+/// tests relying on them pass whichever fixtures earlier tests registered or cleared.
+#[cfg(all(test, feature = "mock-llm"))]
+pub(crate) fn add_mock_fixtures() {
+    for (matcher, response) in MOCK_FIXTURES {
+        fixtures::add(matcher, response).unwrap();
     }
-    // --- Group: General Fallbacks & Error Simulation ---
-    else if prompt.contains("this task should fail_llm_call please") { // Simulates an LLM API error.
-        return Err(JsValue::from_str("Mocked LLM Error: LLM call failed as requested by prompt."));
-    } else if prompt.contains("navigate to example.com") { // Simulates a simple natural language response.
-        return Ok("Mocked LLM response for 'navigate to example.com'".to_string());
-    } else if prompt.contains("fill the login form with my details") { // Simulates a natural language response.
-        return Ok("Mocked LLM response for 'fill the login form with my details'".to_string());
-    } else if prompt.contains("summarize this document for me") { // Simulates a natural language response.
-        return Ok("Mocked LLM response for 'summarize this document for me'".to_string());
-    } else if prompt.contains("navigate then CLICK xpath://button[@id='specificButtonXpath']") { // Simulates a more complex natural language query.
-         return Ok("Mocked LLM response for 'navigate then CLICK xpath://button[@id='specificButtonXpath']'".to_string());
-    } else if prompt.contains("navigate then CLICK #myButtonDirect") { // Simulates another complex natural language query.
-        return Ok("Mocked LLM response for 'navigate then CLICK #myButtonDirect'".to_string());
+    // Simulates an LLM API error.
+    let error = LlmError::Other("Mocked LLM Error: LLM call failed as requested by prompt.".to_string());
+    fixtures::add_failure("this task should fail_llm_call please", error).unwrap();
+}
+
+/// Requests the embedding of `text` from an embeddings API (see `memory::EmbeddingEndpoint`).
+///
+/// Like `call_llm_async`, this function has a real implementation, which POSTs
+/// `{"model": model_name, "input": text}` to `api_url` and reads the embedding with
+/// `memory::parse_embedding`, and a mock one (`#[cfg(feature = "mock-llm")]`), which returns a
+/// vector counting the words of `text` by hash bucket, so that texts sharing words are similar.
+///
+/// # Returns
+/// * `Ok(Vec<f32>)`: The embedding.
+/// * `Err(JsValue)`: An error message if the request fails, the API returns a non-successful
+///   status code, or no embedding is found in the response, and always in builds without the
+///   `http-llm` feature.
+#[cfg(all(not(feature = "mock-llm"), feature = "http-llm"))]
+pub async fn call_embedding_async(text: &str, api_key: &str, api_url: &str, model_name: &str) -> Result<Vec<f32>, JsValue> {
+    let payload = json!({ "model": model_name, "input": text });
+    let mut request = Client::new().post(api_url).json(&payload);
+    if !api_key.is_empty() {
+        request = request.header("Authorization", format!("Bearer {}", api_key));
     }
-    // Fallback for other structured prompts (containing the task marker) not matching specific test cases.
-    else if prompt.contains("The user wants to perform the following task:") {
-        let task_content = extract_task_from_prompt(&prompt);
-        return Ok(format!("Mocked LLM response regarding task: '{}'", task_content));
+    let res = request
+        .send()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Embedding request error: {}", e)))?;
+    if !res.status().is_success() {
+        let error_text = res.text().await.unwrap_or_else(|_| "Failed to get error text".to_string());
+        console::error_1(&format!("Embedding API error (REAL): {}", error_text).into());
+        return Err(JsValue::from_str(&format!("Embedding API error: {}", error_text)));
     }
-    // Legacy fallback for a specific error test prompt (might be obsolete or used in older tests).
-    else if prompt.contains("error_test_prompt") {
-        return Ok("Mocked LLM response: This prompt triggers a success for error testing.".to_string());
+    let response_body: serde_json::Value = res
+        .json()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Embedding JSON parsing error: {}", e)))?;
+    crate::memory::parse_embedding(&response_body)
+        .ok_or_else(|| JsValue::from_str("Failed to extract the embedding from the response: structure was not as expected."))
+}
+
+#[cfg(all(not(feature = "mock-llm"), not(feature = "http-llm")))]
+pub async fn call_embedding_async(_text: &str, _api_key: &str, _api_url: &str, _model_name: &str) -> Result<Vec<f32>, JsValue> {
+    Err(JsValue::from_str("This build has no HTTP client for embedding requests (the `http-llm` feature is off)."))
+}
+
+#[cfg(feature = "mock-llm")]
+pub async fn call_embedding_async(text: &str, _api_key: &str, _api_url: &str, model_name: &str) -> Result<Vec<f32>, JsValue> {
+    if model_name.is_empty() {
+        return Err(JsValue::from_str("Mocked embedding API error: no model given"));
     }
-    // Default fallback for any unhandled prompt, helps in identifying missing mock conditions during testing.
-    else {
-        return Ok(format!("Default Mocked LLM response for unhandled prompt structure: {}", prompt));
+    let mut embedding = vec![0.0; 64];
+    for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        let bucket = word.bytes().fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte as usize)) % embedding.len();
+        embedding[bucket] += 1.0;
     }
+    Ok(embedding)
 }
 
 /// Helper function to extract the core task description from the full LLM prompt string.
 /// This is useful for logging and for creating generic mock responses.
-/// It looks for the pattern `The user wants to perform the following task: "{task}"`.
+/// It looks for the pattern `The user wants to perform the following task: "{task}"`, where the
+/// task is a JSON string.
 fn extract_task_from_prompt(prompt_str: &str) -> String {
-    let task_marker = "The user wants to perform the following task: \"";
-    if let Some(start_index) = prompt_str.find(task_marker) {
-        let actual_task_start = start_index + task_marker.len();
-        if let Some(end_index) = prompt_str[actual_task_start..].find("\"") {
-            return prompt_str[actual_task_start .. actual_task_start + end_index].to_string();
-        }
-    }
-    "Unknown or malformed task".to_string()
-}
\ No newline at end of file
+    prompt_task(prompt_str).unwrap_or_else(|| "Unknown or malformed task".to_string())
+}
+
+/// The task of a structured prompt (see `extract_task_from_prompt`), or `None` if the prompt has
+/// no task, like the yes/no questions of `judge`.
+pub(crate) fn prompt_task(prompt_str: &str) -> Option<String> {
+    let task_marker = "The user wants to perform the following task: ";
+    let start_index = prompt_str.find(task_marker)?;
+    let quoted_task = &prompt_str[start_index + task_marker.len()..];
+    serde_json::Deserializer::from_str(quoted_task).into_iter::<String>().next()?.ok()
+}
+/// What fixtures are matched against: the task of a structured prompt, the question of a yes/no
+/// prompt of `judge` (the last one, as the page content before it may contain anything), or else
+/// the whole prompt, e.g. when picking one of several matching elements.
+pub(crate) fn prompt_subject(prompt_str: &str) -> String {
+    prompt_task(prompt_str)
+        .or_else(|| {
+            let (_, question) = prompt_str.rsplit_once("\nQuestion: ")?;
+            question.lines().next().map(str::to_string)
+        })
+        .unwrap_or_else(|| prompt_str.to_string())
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+
+/// Why a call to the LLM failed, as far as the provider's response tells, so hosts can react to
+/// each case (e.g. ask for a new key when authentication fails).
+#[derive(Debug, Clone, PartialEq)]
+pub enum LlmError {
+    /// Too many requests (HTTP 429) or the provider is overloaded. `retry_after_ms` is how long the
+    /// provider asked to wait, if it said.
+    RateLimited { message: String, retry_after_ms: Option<u64> },
+    /// The prompt is longer than the model accepts.
+    ContextLengthExceeded(String),
+    /// The API key is missing, invalid or lacks permission for the model (HTTP 401 / 403).
+    AuthFailed(String),
+    /// The provider's content filter refused the prompt or withheld the response.
+    ContentFiltered(String),
+    /// The request never got a response, e.g. the network is down or the URL is wrong.
+    Network(String),
+    /// Any other failure: other error statuses and responses that could not be read.
+    Other(String),
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::RateLimited { message, retry_after_ms: Some(ms) } => write!(f, "Rate limited (retry after {}ms): {}", ms, message),
+            LlmError::RateLimited { message, retry_after_ms: None } => write!(f, "Rate limited: {}", message),
+            LlmError::ContextLengthExceeded(message) => write!(f, "Context length exceeded: {}", message),
+            LlmError::AuthFailed(message) => write!(f, "Authentication failed: {}", message),
+            LlmError::ContentFiltered(message) => write!(f, "Content filtered: {}", message),
+            LlmError::Network(message) => write!(f, "Network error: {}", message),
+            LlmError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+// Phrases of OpenAI-compatible, Anthropic, Gemini and Ollama error bodies, lowercased.
+#[cfg(not(feature = "dom-only"))]
+const CONTEXT_LENGTH_PHRASES: &[&str] = &["context_length_exceeded", "maximum context length", "context window", "prompt is too long", "too many tokens", "input is too long"];
+#[cfg(not(feature = "dom-only"))]
+const CONTENT_FILTER_PHRASES: &[&str] = &["content_filter", "content_policy", "content management policy", "safety system", "blocked by safety"];
+#[cfg(not(feature = "dom-only"))]
+const AUTH_PHRASES: &[&str] = &["invalid_api_key", "incorrect api key", "invalid api key", "authentication_error", "permission_error", "unauthorized"];
+#[cfg(not(feature = "dom-only"))]
+const RATE_LIMIT_PHRASES: &[&str] = &["rate_limit", "rate limit", "overloaded", "resource_exhausted"];
+
+/// Classifies an error response of the LLM API from its HTTP `status`, `Retry-After` header and
+/// `body`. The status decides where it is specific; the body is only read for the other client
+/// errors (4xx, mostly 400), as server errors may mention anything.
+#[cfg(not(feature = "dom-only"))]
+pub fn classify_response(status: u16, retry_after: Option<&str>, body: &str) -> LlmError {
+    let message = format!("API error {}: {}", status, body.trim());
+    let lowercase = body.to_lowercase();
+    let mentions = |phrases: &[&str]| (400..500).contains(&status) && phrases.iter().any(|phrase| lowercase.contains(phrase));
+    match status {
+        401 | 403 => LlmError::AuthFailed(message),
+        413 => LlmError::ContextLengthExceeded(message),
+        429 | 529 => rate_limited(message, retry_after, &lowercase),
+        _ if mentions(AUTH_PHRASES) => LlmError::AuthFailed(message),
+        _ if mentions(CONTEXT_LENGTH_PHRASES) => LlmError::ContextLengthExceeded(message),
+        _ if mentions(CONTENT_FILTER_PHRASES) => LlmError::ContentFiltered(message),
+        _ if mentions(RATE_LIMIT_PHRASES) => rate_limited(message, retry_after, &lowercase),
+        _ => LlmError::Other(message),
+    }
+}
+
+#[cfg(not(feature = "dom-only"))]
+fn rate_limited(message: String, retry_after: Option<&str>, lowercase_body: &str) -> LlmError {
+    let retry_after_ms = retry_after.and_then(parse_retry_after).or_else(|| retry_hint_ms(lowercase_body));
+    LlmError::RateLimited { message, retry_after_ms }
+}
+
+/// Classifies a response the API gave with a success status but no content: a response cut off by
+/// the content filter (`finish_reason` `content_filter`, or Gemini's `SAFETY`) is `ContentFiltered`.
+#[cfg_attr(any(feature = "mock-llm", not(feature = "http-llm")), allow(dead_code))] // Only the HTTP client gets responses
+#[cfg(not(feature = "dom-only"))]
+pub fn classify_empty_response(finish_reason: Option<&str>, body: &str) -> LlmError {
+    match finish_reason.map(str::to_lowercase).as_deref() {
+        Some("content_filter") | Some("safety") => LlmError::ContentFiltered(format!("The response was withheld by the content filter: {}", body)),
+        _ => LlmError::Other(format!("Failed to extract content from LLM response: structure was not as expected: {}", body)),
+    }
+}
+
+// The delay of a `Retry-After` header given in seconds. HTTP dates are not supported.
+#[cfg(not(feature = "dom-only"))]
+fn parse_retry_after(value: &str) -> Option<u64> {
+    value.trim().parse::<f64>().ok().filter(|seconds| *seconds >= 0.0).map(|seconds| (seconds * 1000.0).ceil() as u64)
+}
+
+// The delay suggested in an error message such as OpenAI's "Please try again in 1.5s" or "in 250ms".
+#[cfg(not(feature = "dom-only"))]
+fn retry_hint_ms(lowercase_body: &str) -> Option<u64> {
+    let hint = &lowercase_body[lowercase_body.find("try again in ")? + "try again in ".len()..];
+    let number_end = hint.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(hint.len());
+    let number: f64 = hint[..number_end].parse().ok()?;
+    let unit = &hint[number_end..];
+    let ms = if unit.starts_with("ms") {
+        number
+    } else if unit.starts_with('s') {
+        number * 1000.0
+    } else {
+        return None;
+    };
+    Some(ms.ceil() as u64)
+}
+
+#[cfg(test)]
+#[cfg(not(feature = "dom-only"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_response() {
+        let openai_key = r#"{"error": {"message": "Incorrect API key provided: sk-...", "type": "invalid_request_error", "code": "invalid_api_key"}}"#;
+        assert!(matches!(classify_response(401, None, openai_key), LlmError::AuthFailed(message) if message.contains("401")));
+        assert!(matches!(classify_response(400, None, r#"{"error": {"type": "authentication_error"}}"#), LlmError::AuthFailed(_)));
+
+        let context = r#"{"error": {"message": "This model's maximum context length is 128000 tokens.", "code": "context_length_exceeded"}}"#;
+        assert!(matches!(classify_response(400, None, context), LlmError::ContextLengthExceeded(_)));
+        assert!(matches!(classify_response(400, None, r#"{"type": "error", "error": {"message": "prompt is too long: 210000 tokens > 200000 maximum"}}"#), LlmError::ContextLengthExceeded(_)));
+
+        let filtered = r#"{"error": {"code": "content_filter", "message": "The response was filtered due to the prompt triggering Azure OpenAI's content management policy."}}"#;
+        assert!(matches!(classify_response(400, None, filtered), LlmError::ContentFiltered(_)));
+
+        assert_eq!(
+            classify_response(429, Some("20"), "Too Many Requests"),
+            LlmError::RateLimited { message: "API error 429: Too Many Requests".to_string(), retry_after_ms: Some(20_000) }
+        );
+        let hinted = r#"{"error": {"message": "Rate limit reached for gpt-4o. Please try again in 1.5s.", "code": "rate_limit_exceeded"}}"#;
+        assert!(matches!(classify_response(429, None, hinted), LlmError::RateLimited { retry_after_ms: Some(1500), .. }));
+        assert!(matches!(classify_response(529, Some("Wed, 21 Oct 2026 07:28:00 GMT"), r#"{"error": {"type": "overloaded_error"}}"#), LlmError::RateLimited { retry_after_ms: None, .. }));
+
+        assert_eq!(classify_response(500, None, " upstream failed "), LlmError::Other("API error 500: upstream failed".to_string()));
+
+        // The status decides over the phrases of the body.
+        assert!(matches!(classify_response(429, None, r#"{"error": {"message": "Too many requests for this API key"}}"#), LlmError::RateLimited { .. }));
+        assert!(matches!(classify_response(401, None, "Rate limit exceeded for anonymous requests"), LlmError::AuthFailed(_)));
+        assert!(matches!(classify_response(413, None, "Request Entity Too Large"), LlmError::ContextLengthExceeded(_)));
+        assert!(matches!(classify_response(503, None, "The model is overloaded, or the content_filter service is down"), LlmError::Other(_)));
+        let safety_setting = r#"{"error": {"message": "Invalid value for safety_settings[0].threshold"}}"#;
+        assert!(matches!(classify_response(400, None, safety_setting), LlmError::Other(_)), "Only refusals mention safety as a filter");
+        let refused = r#"{"error": {"message": "Your request was rejected as a result of our safety system.", "type": "invalid_request_error"}}"#;
+        assert!(matches!(classify_response(400, None, refused), LlmError::ContentFiltered(_)));
+    }
+
+    #[test]
+    fn test_classify_empty_response() {
+        assert!(matches!(classify_empty_response(Some("content_filter"), "{}"), LlmError::ContentFiltered(_)));
+        assert!(matches!(classify_empty_response(Some("SAFETY"), "{}"), LlmError::ContentFiltered(_)));
+        assert!(matches!(classify_empty_response(Some("stop"), "{}"), LlmError::Other(_)));
+        assert!(matches!(classify_empty_response(None, "{}"), LlmError::Other(_)));
+    }
+
+    #[test]
+    fn test_retry_hints() {
+        assert_eq!(parse_retry_after(" 2.5 "), Some(2500));
+        assert_eq!(parse_retry_after("-1"), None);
+        assert_eq!(retry_hint_ms("please try again in 250ms."), Some(250));
+        assert_eq!(retry_hint_ms("please try again in 7s"), Some(7000));
+        assert_eq!(retry_hint_ms("please try again in a minute"), None);
+        assert_eq!(retry_hint_ms("rate limit reached"), None);
+    }
+}
@@ -0,0 +1,140 @@
+use serde::Deserialize;
+
+/// Prefix of a task naming the LLM profile it runs with, e.g. `profile:cheap Summarize the reviews`.
+pub const PROFILE_HINT_PREFIX: &str = "profile:";
+
+/// A named LLM configuration added with `RustAgent::add_llm_profile`, e.g.
+/// `{"model": "gpt-4o-mini"}`. The API URL and key default to those of the configuration in use
+/// when the profile is, so that models of one provider only need a name.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct LlmProfile {
+    pub model: String,
+    #[serde(default)]
+    pub api_url: Option<String>,
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+impl LlmProfile {
+    /// Parses a profile given to `RustAgent::add_llm_profile`.
+    pub fn parse(config_json: &str) -> Result<Self, String> {
+        let profile: LlmProfile = serde_json::from_str(config_json)
+            .map_err(|e| format!("Invalid LLM profile: {}. Expected a {{\"model\", \"api_url\", \"api_key\"}} object", e))?;
+        if profile.model.trim().is_empty() {
+            return Err("The LLM profile has no model name".to_string());
+        }
+        Ok(profile)
+    }
+}
+
+/// The LLM profiles of an agent, in the order they were added, and the one in use instead of the
+/// configuration set with `set_llm_config`, if any.
+#[derive(Debug, Clone, Default)]
+pub struct LlmProfiles {
+    profiles: Vec<(String, LlmProfile)>,
+    active: Option<String>,
+}
+
+impl LlmProfiles {
+    /// Adds a profile, or replaces the one with the same name. Names cannot contain whitespace, so
+    /// that they can be given in task hints.
+    pub fn add(&mut self, name: &str, profile: LlmProfile) -> Result<(), String> {
+        if name.is_empty() || name.chars().any(char::is_whitespace) {
+            return Err(format!("Invalid LLM profile name '{}': names must be non-empty and contain no whitespace", name));
+        }
+        match self.profiles.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, existing)) => *existing = profile,
+            None => self.profiles.push((name.to_string(), profile)),
+        }
+        Ok(())
+    }
+
+    /// The profile named `name`.
+    pub fn get(&self, name: &str) -> Option<&LlmProfile> {
+        self.profiles.iter().find(|(existing, _)| existing == name).map(|(_, profile)| profile)
+    }
+
+    /// Uses the profile named `name` from now on, or the configuration set with `set_llm_config`
+    /// again while `None`.
+    pub fn select(&mut self, name: Option<&str>) -> Result<(), String> {
+        if let Some(name) = name {
+            if self.get(name).is_none() {
+                return Err(format!("Unknown LLM profile '{}'. Add it with add_llm_profile first", name));
+            }
+        }
+        self.active = name.map(str::to_string);
+        Ok(())
+    }
+
+    /// The profile in use, if one was selected.
+    pub fn active(&self) -> Option<&LlmProfile> {
+        self.active.as_deref().and_then(|name| self.get(name))
+    }
+
+    /// The profiles and the one in use as JSON, without their API keys, e.g.
+    /// `{"active":"strong","profiles":[{"name":"cheap","model":"gpt-4o-mini","api_url":null}, ...]}`.
+    pub fn summary(&self) -> serde_json::Value {
+        let profiles: Vec<serde_json::Value> = self
+            .profiles
+            .iter()
+            .map(|(name, profile)| serde_json::json!({ "name": name, "model": profile.model, "api_url": profile.api_url }))
+            .collect();
+        serde_json::json!({ "active": self.active, "profiles": profiles })
+    }
+}
+
+/// Splits a task starting with a profile hint, `profile:<name> <task>`, into the profile name and
+/// the task.
+///
+/// # Returns
+/// `None` if the task has no hint, or an error if the hint is not followed by a task.
+pub fn parse_hint(task: &str) -> Option<Result<(&str, &str), String>> {
+    let hinted = task.trim_start().strip_prefix(PROFILE_HINT_PREFIX)?;
+    let (name, rest) = hinted.split_once(char::is_whitespace).unwrap_or((hinted, ""));
+    let rest = rest.trim();
+    Some(if name.is_empty() || rest.is_empty() {
+        Err(format!("'{}' must be followed by a profile name and a task, e.g. '{}cheap Summarize the page'", task.trim(), PROFILE_HINT_PREFIX))
+    } else {
+        Ok((name, rest))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hint() {
+        assert_eq!(parse_hint("profile:cheap Summarize the reviews"), Some(Ok(("cheap", "Summarize the reviews"))));
+        assert_eq!(parse_hint("  profile:strong\tCLICK css:#buy "), Some(Ok(("strong", "CLICK css:#buy"))));
+        assert_eq!(parse_hint("Summarize the profile: page"), None);
+        assert!(matches!(parse_hint("profile:cheap"), Some(Err(_))));
+        assert!(matches!(parse_hint("profile: Summarize"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let mut profiles = LlmProfiles::default();
+        assert!(LlmProfile::parse(r#"{"model": " "}"#).is_err());
+        assert!(LlmProfile::parse(r#"{"api_url": "https://llm.example"}"#).is_err());
+        profiles.add("cheap", LlmProfile::parse(r#"{"model": "small"}"#).unwrap()).unwrap();
+        profiles.add("strong", LlmProfile::parse(r#"{"model": "large", "api_key": "sk-1"}"#).unwrap()).unwrap();
+        assert!(profiles.add("two words", LlmProfile::parse(r#"{"model": "small"}"#).unwrap()).is_err());
+        assert_eq!(profiles.active(), None);
+
+        assert!(profiles.select(Some("missing")).is_err());
+        profiles.select(Some("strong")).unwrap();
+        assert_eq!(profiles.active().unwrap().model, "large");
+        profiles.add("strong", LlmProfile::parse(r#"{"model": "larger"}"#).unwrap()).unwrap();
+        assert_eq!(profiles.active().unwrap().model, "larger", "A profile is replaced in place");
+        assert_eq!(
+            profiles.summary(),
+            serde_json::json!({"active": "strong", "profiles": [
+                {"name": "cheap", "model": "small", "api_url": null},
+                {"name": "strong", "model": "larger", "api_url": null},
+            ]})
+        );
+        profiles.select(None).unwrap();
+        assert_eq!(profiles.active(), None);
+    }
+}
@@ -0,0 +1,305 @@
+use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::dom_utils::DomError;
+
+/// Built-in translations of the English terms the `DISMISS_OVERLAYS` heuristics look for, in
+/// German, French, Spanish, Italian, Portuguese and Dutch.
+const BUILT_IN_TERMS: [(&str, &[&str]); 10] = [
+    ("accept", &["akzeptieren", "accepter", "j'accepte", "aceptar", "accetta", "aceitar", "accepteren"]),
+    ("agree", &["zustimmen", "einverstanden", "d'accord", "de acuerdo", "acconsento", "concordo", "akkoord"]),
+    ("allow", &["erlauben", "zulassen", "autoriser", "permitir", "consenti", "toestaan"]),
+    ("got it", &["verstanden", "compris", "entendido", "ho capito", "entendi", "begrepen"]),
+    ("close", &["schließen", "fermer", "cerrar", "chiudi", "fechar", "sluiten"]),
+    ("dismiss", &["ausblenden", "ignorer", "descartar", "ignora", "negeren"]),
+    ("no thanks", &["nein danke", "non merci", "no gracias", "no grazie", "não obrigado", "nee bedankt"]),
+    ("continue", &["weiter", "continuer", "continuar", "continua", "doorgaan"]),
+    ("reject", &["ablehnen", "refuser", "rechazar", "rifiuta", "rejeitar", "weigeren"]),
+    ("decline", &["verweigern", "décliner", "declinar", "declina", "recusar", "afwijzen"]),
+];
+
+/// Frequent short words of the languages `detect_language` tells apart by vocabulary, by ISO 639-1
+/// code. Words common to several of these languages are left out.
+const STOPWORDS: [(&str, &[&str]); 7] = [
+    ("en", &["the", "and", "of", "to", "is", "you", "for", "with", "your", "this", "are", "that"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "sie", "mit", "ein", "zu", "für", "auf"]),
+    ("fr", &["le", "les", "et", "des", "est", "vous", "pour", "une", "du", "avec", "nous", "sur"]),
+    ("es", &["el", "los", "las", "y", "es", "para", "con", "por", "del", "su", "más", "usted"]),
+    ("it", &["il", "di", "che", "per", "non", "sono", "gli", "della", "questo", "anche", "tutti", "nel"]),
+    ("pt", &["o", "os", "não", "com", "uma", "do", "da", "em", "você", "mais", "seu", "ao"]),
+    ("nl", &["het", "een", "en", "van", "niet", "voor", "met", "zijn", "u", "je", "ook", "wij"]),
+];
+
+/// Languages recognized by their script alone, with the ranges of the script's letters.
+const SCRIPTS: [(&str, &[(char, char)]); 10] = [
+    ("ja", &[('\u{3040}', '\u{30FF}')]), // Hiragana and Katakana; checked before Han
+    ("ko", &[('\u{1100}', '\u{11FF}'), ('\u{AC00}', '\u{D7AF}')]),
+    ("zh", &[('\u{4E00}', '\u{9FFF}')]),
+    ("ru", &[('\u{0400}', '\u{04FF}')]),
+    ("el", &[('\u{0370}', '\u{03FF}')]),
+    ("he", &[('\u{0590}', '\u{05FF}')]),
+    ("ar", &[('\u{0600}', '\u{06FF}')]),
+    ("hi", &[('\u{0900}', '\u{097F}')]),
+    ("th", &[('\u{0E00}', '\u{0E7F}')]),
+    ("hy", &[('\u{0530}', '\u{058F}')]),
+];
+
+/// Fewest stopwords `detect_language` needs to find before guessing a language written in Latin
+/// script.
+const MIN_STOPWORD_HITS: usize = 3;
+
+thread_local! {
+    // The built-in translations plus the ones added with `add_dictionary`, set up on first use.
+    static DICTIONARY: RefCell<Option<Dictionary>> = const { RefCell::new(None) };
+}
+
+/// Translations added with `RustAgent::add_locale_dictionary`, e.g.
+/// `{"terms": {"accept": ["godkänn"], "close": ["stäng"]}, "commands": {"KLICKA": "CLICK"}}`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Dictionary {
+    /// Translations of English terms matched against page text, by term.
+    pub terms: HashMap<String, Vec<String>>,
+    /// Translated command names, mapped to the command they stand for.
+    pub commands: HashMap<String, String>,
+}
+
+impl Dictionary {
+    fn built_in() -> Self {
+        let terms = BUILT_IN_TERMS
+            .iter()
+            .map(|(term, translations)| (term.to_string(), translations.iter().map(|t| t.to_string()).collect()))
+            .collect();
+        Dictionary { terms, commands: HashMap::new() }
+    }
+}
+
+/// Normalizes `text` to Unicode NFC, so that an accented letter typed or compared as a base letter
+/// plus a combining mark is the same as its precomposed form.
+pub fn nfc(text: &str) -> String {
+    // ASCII text is always in NFC; this also keeps the common case free of calls into JavaScript.
+    if text.is_ascii() {
+        return text.to_string();
+    }
+    js_sys::JsString::from(text)
+        .normalize("NFC")
+        .as_string()
+        .unwrap_or_else(|| text.to_string())
+}
+
+/// Unicode case folding, for case-insensitive comparisons beyond ASCII: lowercases, and folds the
+/// letters whose lowercase forms differ (`ß` and `ss`, final and medial sigma, long s).
+pub fn case_fold(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .fold(String::with_capacity(text.len()), |mut folded, c| {
+            match c {
+                'ß' | 'ẞ' => folded.push_str("ss"),
+                'ς' => folded.push('σ'),
+                'ſ' => folded.push('s'),
+                _ => folded.push(c),
+            }
+            folded
+        })
+}
+
+/// Prepares page or user text for matching: NFC normalization, case folding and whitespace
+/// collapsed to single spaces.
+pub fn fold(text: &str) -> String {
+    case_fold(&nfc(text)).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// How similar `text` is to `query`, from 0 to 1 (equal after `fold`): the mean of the share of
+/// the query's words found in the text and the overlap of their letter pairs (Dice coefficient),
+/// which tolerates typos and inflections.
+pub fn similarity(query: &str, text: &str) -> f64 {
+    let (query, text) = (fold(query), fold(text));
+    if query.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    if query == text {
+        return 1.0;
+    }
+    let words = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_string).collect()
+    };
+    let (query_words, text_words) = (words(&query), words(&text));
+    let found = query_words.iter().filter(|word| text_words.contains(word)).count();
+    let word_score = if query_words.is_empty() { 0.0 } else { found as f64 / query_words.len() as f64 };
+
+    let pairs = |text: &str| -> Vec<(char, char)> {
+        let letters: Vec<char> = text.chars().filter(|c| c.is_alphanumeric()).collect();
+        letters.windows(2).map(|pair| (pair[0], pair[1])).collect()
+    };
+    let (query_pairs, mut text_pairs) = (pairs(&query), pairs(&text));
+    let total = query_pairs.len() + text_pairs.len();
+    let mut shared = 0;
+    for pair in &query_pairs {
+        if let Some(index) = text_pairs.iter().position(|candidate| candidate == pair) {
+            text_pairs.swap_remove(index);
+            shared += 1;
+        }
+    }
+    let pair_score = if total == 0 { 0.0 } else { 2.0 * shared as f64 / total as f64 };
+    (word_score + pair_score) / 2.0
+}
+
+/// `term` and its translations, each folded with `fold`.
+pub fn term_variants(term: &str) -> Vec<String> {
+    DICTIONARY.with(|dictionary| {
+        let mut dictionary = dictionary.borrow_mut();
+        let dictionary = dictionary.get_or_insert_with(Dictionary::built_in);
+        let mut variants = vec![fold(term)];
+        let translations = dictionary.terms.get(&case_fold(term)).into_iter().flatten();
+        variants.extend(translations.map(|translation| fold(translation)));
+        variants
+    })
+}
+
+/// The command a translated command name stands for, e.g. `CLICK` for `KLICKEN` if the dictionary
+/// maps it. Command names are compared case-insensitively.
+pub fn command_alias(name: &str) -> Option<String> {
+    DICTIONARY.with(|dictionary| {
+        let dictionary = dictionary.borrow();
+        let folded = case_fold(name);
+        dictionary
+            .as_ref()?
+            .commands
+            .iter()
+            .find(|(alias, _)| case_fold(alias) == folded)
+            .map(|(_, command)| command.to_uppercase())
+    })
+}
+
+/// A language guessed from text by `detect_language`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LanguageGuess {
+    /// ISO 639-1 code, e.g. `de`.
+    pub language: String,
+    /// Share of the evidence pointing to `language`, from 0 to 1: of the letters for a language
+    /// told by its script, of the stopwords found otherwise.
+    pub confidence: f64,
+}
+
+/// Guesses the language of `text` from its script (Japanese, Korean, Chinese, Russian, Greek,
+/// Hebrew, Arabic, Hindi, Thai, Armenian) or, for Latin script, from its most frequent short words
+/// (English, German, French, Spanish, Italian, Portuguese, Dutch). Returns `None` if the text is
+/// too short or in none of these.
+pub fn detect_language(text: &str) -> Option<LanguageGuess> {
+    let mut script_letters = [0usize; SCRIPTS.len()];
+    let mut letters = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if let Some(index) = SCRIPTS.iter().position(|(_, ranges)| ranges.iter().any(|(low, high)| (*low..=*high).contains(&c))) {
+            script_letters[index] += 1;
+        }
+    }
+    // Kana is what tells Japanese apart from Chinese, but most Japanese text is Han.
+    let (mut best, mut best_letters) = script_letters.iter().copied().enumerate().max_by_key(|(_, count)| *count)?;
+    if script_letters[0] > 0 && SCRIPTS[best].0 == "zh" {
+        best_letters += script_letters[0];
+        best = 0;
+    }
+    if best_letters * 2 > letters {
+        return Some(LanguageGuess {
+            language: SCRIPTS[best].0.to_string(),
+            confidence: best_letters as f64 / letters as f64,
+        });
+    }
+
+    let mut hits = [0usize; STOPWORDS.len()];
+    for word in text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()) {
+        let word = case_fold(word);
+        for (index, (_, stopwords)) in STOPWORDS.iter().enumerate() {
+            if stopwords.contains(&word.as_str()) {
+                hits[index] += 1;
+            }
+        }
+    }
+    let total: usize = hits.iter().sum();
+    let (best, best_hits) = hits.iter().copied().enumerate().max_by_key(|(_, count)| *count)?;
+    if best_hits < MIN_STOPWORD_HITS {
+        return None;
+    }
+    Some(LanguageGuess {
+        language: STOPWORDS[best].0.to_string(),
+        confidence: best_hits as f64 / total as f64,
+    })
+}
+
+/// Adds the translations of a dictionary (see `Dictionary`) to the built-in ones and the ones
+/// added before.
+pub fn add_dictionary(dictionary_json: &str) -> Result<(), DomError> {
+    let added: Dictionary = serde_json::from_str(dictionary_json)
+        .map_err(|e| DomError::SerializationError { message: format!("Invalid locale dictionary: {}", e) })?;
+    DICTIONARY.with(|dictionary| {
+        let mut dictionary = dictionary.borrow_mut();
+        let dictionary = dictionary.get_or_insert_with(Dictionary::built_in);
+        for (term, translations) in added.terms {
+            dictionary.terms.entry(case_fold(&term)).or_default().extend(translations);
+        }
+        dictionary.commands.extend(added.commands);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_case_fold_and_command_aliases() {
+        assert_eq!(case_fold("SCHLIESSEN"), case_fold("Schließen"));
+        assert_eq!(case_fold("ΟΔΟΣ"), "οδοσ");
+        assert_eq!(fold("  Accept\n all "), "accept all");
+
+        add_dictionary(r#"{"commands": {"Klicka": "click"}}"#).unwrap();
+        assert_eq!(command_alias("KLICKA").as_deref(), Some("CLICK"));
+        assert_eq!(command_alias("CLICK"), None);
+        assert!(add_dictionary(r#"{"terms": ["accept"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let guess = |text: &str| detect_language(text).map(|guess| guess.language);
+        assert_eq!(guess("Bitte akzeptieren Sie die Cookies, damit wir Ihnen das beste Erlebnis bieten können und die Seite für Sie funktioniert.").as_deref(), Some("de"));
+        assert_eq!(guess("Nous utilisons des cookies pour vous offrir la meilleure expérience sur notre site et pour mesurer l'audience.").as_deref(), Some("fr"));
+        assert_eq!(guess("We use cookies to give you the best experience on this site and to measure the audience of your visits.").as_deref(), Some("en"));
+        assert_eq!(guess("Utilizamos cookies para ofrecerle la mejor experiencia y para medir la audiencia del sitio con su permiso.").as_deref(), Some("es"));
+        assert_eq!(guess("Wij gebruiken cookies om u de beste ervaring te bieden en om het gebruik van de site te meten.").as_deref(), Some("nl"));
+        assert_eq!(guess("クッキーを使用しています。同意してください。").as_deref(), Some("ja"));
+        assert_eq!(guess("我们使用cookie来改善您的体验。").as_deref(), Some("zh"));
+        assert_eq!(guess("Мы используем файлы cookie").as_deref(), Some("ru"));
+        assert_eq!(guess("אנו משתמשים בעוגיות").as_deref(), Some("he"));
+
+        let guess = detect_language("Accept all cookies and the terms of the site").unwrap();
+        assert_eq!(guess.language, "en");
+        assert!(guess.confidence > 0.5 && guess.confidence <= 1.0);
+        assert_eq!(detect_language("OK"), None, "Too little text");
+        assert_eq!(detect_language("12345 !!!"), None);
+    }
+
+    #[test]
+    fn test_similarity_ranks_closer_texts_higher() {
+        assert_eq!(similarity("Rust Book", "rust  book"), 1.0);
+        assert_eq!(similarity("rust book", ""), 0.0);
+        let exact = similarity("rust programming book", "The Rust Programming Language Book");
+        let partial = similarity("rust programming book", "Rust cookbook");
+        let unrelated = similarity("rust programming book", "Gardening for beginners");
+        assert!(exact > partial && partial > unrelated, "{} > {} > {}", exact, partial, unrelated);
+        assert!(similarity("wireless headphones", "Wireles Headphone X2") > 0.3, "Typos and inflections still score");
+    }
+
+    #[wasm_bindgen_test]
+    fn test_fold_and_term_translations() {
+        assert_eq!(nfc("Cafe\u{301}"), "Café");
+        assert_eq!(fold("CAFE\u{301}"), fold("café"));
+
+        assert!(term_variants("close").contains(&"schliessen".to_string()));
+        add_dictionary(r#"{"terms": {"Close": ["Stäng"]}}"#).unwrap();
+        assert!(term_variants("close").contains(&"stäng".to_string()));
+    }
+}
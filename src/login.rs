@@ -0,0 +1,174 @@
+use gloo_timers::future::TimeoutFuture;
+use serde::Serialize;
+use web_sys::console;
+use crate::dom_utils::{self, DomError, WaitConfig};
+
+/// Default time `login` waits for the success or failure element after submitting.
+pub const DEFAULT_LOGIN_TIMEOUT_MS: u32 = 10000;
+
+/// The elements of a login form, and those that tell whether a login worked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginForm<'a> {
+    pub username_selector: &'a str,
+    pub password_selector: &'a str,
+    /// The button that submits the form.
+    pub submit_selector: &'a str,
+    /// An element only shown once logged in, e.g. the account menu.
+    pub success_selector: &'a str,
+    /// An element shown when the login is refused, e.g. an error message.
+    pub failure_selector: Option<&'a str>,
+}
+
+/// The result of `login`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LoginOutcome {
+    pub success: bool,
+    /// Why the login is considered to have worked or failed, including the text of the failure
+    /// element when it appeared, e.g. "Invalid password".
+    pub reason: String,
+    /// How long the outcome took to appear after submitting, in milliseconds.
+    pub elapsed_ms: u32,
+}
+
+// Whether the element matching `selector` exists and is visible.
+fn shown(selector: &str) -> Result<bool, DomError> {
+    Ok(dom_utils::element_exists(selector)? && dom_utils::is_visible(selector)?)
+}
+
+// The trimmed visible text of the element matching `selector`.
+fn shown_text(selector: &str) -> Result<String, DomError> {
+    Ok(dom_utils::get_element_text(selector)?.trim().to_string())
+}
+
+/// Fills in a login form, submits it and waits for the outcome.
+///
+/// The username and password are typed at once, or one character at a time with
+/// `typing_delay_ms` (see `dom_utils::type_like_human`), and the submit button is clicked. The
+/// page is then polled until the success element or the failure element is visible. A failure
+/// element already visible before submitting (an error left by an earlier attempt) only counts
+/// once the wait is over. Neither credential is logged.
+///
+/// Logins that load a new page unload the agent with it, so the outcome can only be detected
+/// on pages that log in without reloading (single-page apps, login dialogs).
+///
+/// # Arguments
+/// * `form`: The selectors of the form and of the outcome elements.
+/// * `username`: The username to type.
+/// * `password`: The password to type.
+/// * `typing_delay_ms`: The delay between typed characters, in milliseconds, or `None` to type at once.
+/// * `timeout_ms`: How long to wait for the outcome. Defaults to `DEFAULT_LOGIN_TIMEOUT_MS`, or the
+///   timeout of `wait` if it is longer.
+/// * `wait`: The default timeout and the polling interval (see `WaitConfig`).
+///
+/// # Returns
+/// * `Ok(LoginOutcome)` telling whether the login worked and why.
+/// * `Err(DomError)` if a form element is not found or not an input, or a selector is invalid.
+pub async fn login(
+    form: &LoginForm<'_>,
+    username: &str,
+    password: &str,
+    typing_delay_ms: Option<u32>,
+    timeout_ms: Option<u32>,
+    wait: &WaitConfig,
+) -> Result<LoginOutcome, DomError> {
+    console::log_1(&format!("Attempting to log in with form {}", form.username_selector).into());
+    for (selector, text) in [(form.username_selector, username), (form.password_selector, password)] {
+        match typing_delay_ms {
+            Some(delay_ms) => dom_utils::type_like_human(selector, text, delay_ms).await?,
+            None => dom_utils::type_in_element(selector, text)?,
+        }
+    }
+    let failure_shown_before = match form.failure_selector {
+        Some(failure_selector) => shown(failure_selector)?,
+        None => false,
+    };
+    dom_utils::click_element(form.submit_selector)?;
+
+    let timeout = wait.timeout(timeout_ms, DEFAULT_LOGIN_TIMEOUT_MS);
+    let mut waited = 0;
+    let outcome = loop {
+        if shown(form.success_selector)? {
+            break LoginOutcome {
+                success: true,
+                reason: format!("Success element '{}' appeared", form.success_selector),
+                elapsed_ms: waited,
+            };
+        }
+        if let Some(failure_selector) = form.failure_selector {
+            if (!failure_shown_before || waited >= timeout) && shown(failure_selector)? {
+                break LoginOutcome {
+                    success: false,
+                    reason: format!("Failure element '{}' appeared: {}", failure_selector, shown_text(failure_selector)?),
+                    elapsed_ms: waited,
+                };
+            }
+        }
+        if waited >= timeout {
+            break LoginOutcome {
+                success: false,
+                reason: format!("Neither the success nor the failure element appeared within {}ms", timeout),
+                elapsed_ms: waited,
+            };
+        }
+        TimeoutFuture::new(wait.interval_ms).await;
+        waited += wait.interval_ms;
+    };
+    console::log_1(&format!("Login outcome: {}", outcome.reason).into());
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::*;
+    use web_sys::{Document, Element, HtmlInputElement};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // Renders a login form that accepts "ann" / "s3cret" after a short delay, showing `#welcome`
+    // on success and `#login-error` otherwise.
+    fn setup_login_form(document: &Document) -> Element {
+        let container = document.create_element("div").unwrap();
+        container.set_inner_html(
+            "<input id='login-user'><input id='login-pass' type='password'>\
+             <button id='login-submit' onclick=\"setTimeout(() => { \
+                 const ok = document.getElementById('login-user').value === 'ann' && document.getElementById('login-pass').value === 's3cret'; \
+                 document.getElementById(ok ? 'welcome' : 'login-error').style.display = 'block'; }, 50)\">Sign in</button>\
+             <div id='welcome' style='display: none'>Welcome, Ann</div>\
+             <div id='login-error' style='display: none'>Invalid password</div>",
+        );
+        document.body().unwrap().append_child(&container).unwrap();
+        container
+    }
+
+    const FORM: LoginForm<'static> = LoginForm {
+        username_selector: "css:#login-user",
+        password_selector: "css:#login-pass",
+        submit_selector: "css:#login-submit",
+        success_selector: "css:#welcome",
+        failure_selector: Some("css:#login-error"),
+    };
+
+    #[wasm_bindgen_test]
+    async fn test_login_detects_success_and_failure() {
+        let document = web_sys::window().unwrap().document().unwrap();
+
+        let container = setup_login_form(&document);
+        let outcome = login(&FORM, "ann", "s3cret", None, None, &WaitConfig::default()).await.unwrap();
+        assert!(outcome.success, "Unexpected outcome: {:?}", outcome);
+        let password = document.get_element_by_id("login-pass").unwrap().dyn_into::<HtmlInputElement>().unwrap();
+        assert_eq!(password.value(), "s3cret");
+        container.remove();
+
+        let container = setup_login_form(&document);
+        let outcome = login(&FORM, "ann", "wrong", Some(0), None, &WaitConfig::default()).await.unwrap();
+        assert!(!outcome.success);
+        assert!(outcome.reason.ends_with("Invalid password"), "Unexpected reason: {}", outcome.reason);
+
+        let outcome = login(&LoginForm { failure_selector: None, ..FORM }, "ann", "wrong", None, Some(200), &WaitConfig::default()).await.unwrap();
+        assert!(!outcome.success);
+        assert!(outcome.reason.contains("within 200ms"), "Unexpected reason: {}", outcome.reason);
+        container.remove();
+    }
+}
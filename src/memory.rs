@@ -0,0 +1,245 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::rc::Rc;
+use web_sys::console;
+use crate::llm::call_embedding_async;
+use crate::prompt_examples::PromptExample;
+
+/// At most this many tasks are remembered; the oldest are forgotten first.
+pub const MAX_ENTRIES: usize = 200;
+/// At most this many remembered tasks are recalled for a new one.
+pub const MAX_RECALLED: usize = 3;
+/// Remembered tasks whose embedding is less similar than this to the new task's (cosine
+/// similarity) are not recalled.
+pub const MIN_SIMILARITY: f32 = 0.8;
+
+/// An embeddings API, e.g. `{"api_url": "https://api.openai.com/v1/embeddings", "api_key": "sk-...", "model": "text-embedding-3-small"}`.
+/// It is sent `{"model": ..., "input": ...}` and may answer in the OpenAI (`data[0].embedding`) or
+/// Ollama / Cohere (`embedding` or `embeddings[0]`) format.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EmbeddingEndpoint {
+    pub api_url: String,
+    /// Sent as a bearer token, unless empty (e.g. for a local server).
+    #[serde(default)]
+    pub api_key: String,
+    pub model: String,
+}
+
+#[derive(Debug)]
+struct MemoryEntry {
+    task: String,
+    /// The command array, as compact JSON.
+    commands: String,
+    embedding: Vec<f32>,
+    /// The run the commands succeeded in, if they ran in one.
+    run_id: Option<String>,
+    /// Whether the host reported that run as successful (see `apply_feedback`).
+    confirmed: bool,
+}
+
+/// Tasks whose LLM-proposed commands all succeeded during the session, with the embeddings of the
+/// tasks, so that the commands can be shown to the LLM as examples when a similar task comes up.
+/// Nothing is remembered while no endpoint is set. Clones share the remembered tasks, so scheduled
+/// runs learn from and for the agent that scheduled them.
+///
+/// Feedback on a run (see `apply_feedback`) confirms or forgets the tasks remembered during it:
+/// confirmed tasks are recalled before others and forgotten last.
+#[derive(Debug, Clone, Default)]
+pub struct ExperienceMemory {
+    endpoint: Option<EmbeddingEndpoint>,
+    entries: Rc<RefCell<Vec<MemoryEntry>>>,
+    // The run being executed with this copy of the memory; not shared by clones, so that scheduled
+    // runs tag their own tasks.
+    run_id: RefCell<Option<String>>,
+}
+
+/// The cosine similarity of two embeddings, from -1 to 1, or 0 if their lengths differ or one is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Reads the embedding out of the response of an embeddings API, in the OpenAI
+/// (`{"data": [{"embedding": [...]}]}`), Ollama (`{"embedding": [...]}`) or Cohere / Ollama batch
+/// (`{"embeddings": [[...]]}`) format.
+#[cfg_attr(any(feature = "mock-llm", not(feature = "http-llm")), allow(dead_code))] // Only the HTTP client reads responses
+pub fn parse_embedding(response: &Value) -> Option<Vec<f32>> {
+    let vector = response
+        .pointer("/data/0/embedding")
+        .or_else(|| response.get("embedding"))
+        .or_else(|| response.pointer("/embeddings/0"))?
+        .as_array()?;
+    let embedding: Option<Vec<f32>> = vector.iter().map(|x| x.as_f64().map(|x| x as f32)).collect();
+    embedding.filter(|embedding| !embedding.is_empty())
+}
+
+impl ExperienceMemory {
+    /// Sets the endpoint embeddings are requested from, or `None` to stop remembering and forget
+    /// every task.
+    pub fn set_endpoint(&mut self, endpoint: Option<EmbeddingEndpoint>) {
+        if endpoint.is_none() {
+            self.clear();
+        }
+        self.endpoint = endpoint;
+    }
+
+    /// Forgets every task.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    /// Marks the start of the run with id `run_id`: tasks remembered from now on belong to it.
+    pub fn begin_run(&self, run_id: &str) {
+        *self.run_id.borrow_mut() = Some(run_id.to_string());
+    }
+
+    /// Applies the feedback reported on the run with id `run_id` to the tasks remembered during it:
+    /// they are confirmed if the run succeeded, and forgotten otherwise, since their commands ran
+    /// without errors but did not do what was meant.
+    ///
+    /// # Returns
+    /// The number of tasks confirmed or forgotten.
+    pub fn apply_feedback(&self, run_id: &str, success: bool) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        let in_run = |entry: &MemoryEntry| entry.run_id.as_deref() == Some(run_id);
+        let count = entries.iter().filter(|entry| in_run(entry)).count();
+        if success {
+            entries.iter_mut().filter(|entry| in_run(entry)).for_each(|entry| entry.confirmed = true);
+        } else {
+            entries.retain(|entry| !in_run(entry));
+        }
+        count
+    }
+
+    /// The number of remembered tasks.
+    pub fn count(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// The embedding of `task`, or `None` if no endpoint is set or the request fails. Failures are
+    /// logged but do not fail the task, which then runs without recalled examples.
+    pub async fn embed(&self, task: &str) -> Option<Vec<f32>> {
+        let endpoint = self.endpoint.as_ref()?;
+        match call_embedding_async(task, &endpoint.api_key, &endpoint.api_url, &endpoint.model).await {
+            Ok(embedding) => Some(embedding),
+            Err(e) => {
+                console::warn_1(&format!("Embedding request failed, no examples are recalled: {:?}", e).into());
+                None
+            }
+        }
+    }
+
+    /// Remembers that `commands` carried out `task`, replacing what was remembered for the same task.
+    pub fn remember(&self, task: &str, commands: &Value, embedding: Vec<f32>) {
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|entry| entry.task != task);
+        if entries.len() >= MAX_ENTRIES {
+            let oldest = entries.iter().position(|entry| !entry.confirmed).unwrap_or(0);
+            entries.remove(oldest);
+        }
+        let run_id = self.run_id.borrow().clone();
+        entries.push(MemoryEntry { task: task.to_string(), commands: commands.to_string(), embedding, run_id, confirmed: false });
+    }
+
+    /// The remembered tasks most similar to the task with `embedding`, as examples: confirmed tasks
+    /// first, then the others, each most similar first.
+    pub fn recall(&self, embedding: &[f32]) -> Vec<PromptExample> {
+        let entries = self.entries.borrow();
+        let mut scored: Vec<(f32, &MemoryEntry)> = entries
+            .iter()
+            .map(|entry| (cosine_similarity(embedding, &entry.embedding), entry))
+            .filter(|(score, _)| *score >= MIN_SIMILARITY)
+            .collect();
+        scored.sort_by(|(a, a_entry), (b, b_entry)| b_entry.confirmed.cmp(&a_entry.confirmed).then(b.total_cmp(a)));
+        scored
+            .into_iter()
+            .take(MAX_RECALLED)
+            .map(|(_, entry)| PromptExample { task: entry.task.clone(), commands: entry.commands.clone() })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_embedding() {
+        assert_eq!(parse_embedding(&json!({"data": [{"embedding": [0.5, -1], "index": 0}]})), Some(vec![0.5, -1.0]));
+        assert_eq!(parse_embedding(&json!({"embedding": [1, 2]})), Some(vec![1.0, 2.0]));
+        assert_eq!(parse_embedding(&json!({"embeddings": [[0.25]]})), Some(vec![0.25]));
+        assert_eq!(parse_embedding(&json!({"embedding": []})), None);
+        assert_eq!(parse_embedding(&json!({"embedding": ["a"]})), None);
+        assert_eq!(parse_embedding(&json!({"error": "quota"})), None);
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_remember_and_recall() {
+        let memory = ExperienceMemory::default();
+        memory.remember("Add the margherita to the cart", &json!([{"action": "CLICK", "selector": "css:#margherita .add"}]), vec![1.0, 0.1, 0.0]);
+        memory.remember("Open the order history", &json!([{"action": "CLICK", "selector": "text:Orders"}]), vec![0.0, 0.0, 1.0]);
+        memory.remember("Add the margherita to the cart", &json!([{"action": "CLICK", "selector": "css:#pizza-1 .add"}]), vec![1.0, 0.0, 0.0]);
+        assert_eq!(memory.count(), 2, "The same task is remembered once");
+
+        let recalled = memory.recall(&[0.9, 0.2, 0.0]);
+        assert_eq!(recalled.len(), 1);
+        assert_eq!(recalled[0].task, "Add the margherita to the cart");
+        assert!(recalled[0].commands.contains("css:#pizza-1 .add"));
+        assert!(memory.recall(&[0.0, 1.0, 0.0]).is_empty());
+
+        let clone = memory.clone();
+        clone.clear();
+        assert_eq!(memory.count(), 0, "Clones share the remembered tasks");
+    }
+
+    #[test]
+    fn test_feedback_confirms_or_forgets() {
+        let memory = ExperienceMemory::default();
+        memory.begin_run("run-1");
+        memory.remember("Order a large margherita", &json!([{"action": "CLICK", "selector": "css:#large"}]), vec![1.0, 0.0]);
+        memory.begin_run("run-2");
+        memory.remember("Order a large pepperoni", &json!([{"action": "CLICK", "selector": "css:#size-l"}]), vec![0.9, 0.1]);
+        memory.remember("Order a small pepperoni", &json!([{"action": "CLICK", "selector": "css:#size-s"}]), vec![0.0, 1.0]);
+        assert_eq!(memory.recall(&[1.0, 0.0])[0].task, "Order a large margherita");
+
+        assert_eq!(memory.apply_feedback("run-2", true), 2);
+        let recalled = memory.recall(&[1.0, 0.0]);
+        assert_eq!(recalled.iter().map(|example| example.task.as_str()).collect::<Vec<_>>(), ["Order a large pepperoni", "Order a large margherita"]);
+
+        assert_eq!(memory.apply_feedback("run-1", false), 1);
+        assert_eq!(memory.count(), 2);
+        assert_eq!(memory.apply_feedback("run-3", true), 0);
+    }
+
+    #[test]
+    fn test_oldest_tasks_are_forgotten() {
+        let memory = ExperienceMemory::default();
+        for index in 0..=MAX_ENTRIES {
+            memory.remember(&format!("task {}", index), &json!([]), vec![1.0]);
+        }
+        assert_eq!(memory.count(), MAX_ENTRIES);
+        assert_eq!(memory.entries.borrow()[0].task, "task 1");
+
+        memory.begin_run("run-1");
+        memory.remember("confirmed task", &json!([]), vec![1.0]);
+        memory.apply_feedback("run-1", true);
+        for index in 0..MAX_ENTRIES {
+            memory.remember(&format!("new task {}", index), &json!([]), vec![1.0]);
+        }
+        assert_eq!(memory.entries.borrow()[0].task, "confirmed task", "Confirmed tasks are forgotten last");
+    }
+}
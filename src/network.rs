@@ -0,0 +1,494 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::console;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+use gloo_timers::future::TimeoutFuture;
+use crate::dom_backend;
+use crate::dom_utils::{self, DomError, WaitConfig};
+
+/// Maximum number of WebSocket messages kept; the oldest are dropped first.
+pub const MAX_WS_MESSAGES: usize = 1000;
+/// Default time `wait_for_ws_message` waits for a matching message.
+pub const DEFAULT_WS_WAIT_TIMEOUT_MS: u32 = 5000;
+/// Characters of a response body returned by `fetch_url`; longer bodies are truncated.
+pub const MAX_FETCH_BODY_CHARS: usize = 100_000;
+/// The HTTP methods `fetch_url` accepts.
+pub const FETCH_METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"];
+
+/// A message received by a page WebSocket while the capture hook is installed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WsMessage {
+    /// Capture order, starting at 1.
+    pub seq: u64,
+    /// URL of the socket that received the message.
+    pub url: String,
+    /// The message text. Binary messages are summarized as `[binary <n> bytes]`.
+    pub data: String,
+    /// `Date.now()` when the message was received.
+    pub received_at_ms: f64,
+}
+
+// The installed hook: the original constructor and the callbacks the proxy uses, kept alive
+// for the lifetime of the page since sockets created through the proxy keep referring to them.
+struct WebSocketHook {
+    _original: JsValue,
+    _construct: Closure<dyn FnMut(JsValue, js_sys::Array) -> Result<JsValue, JsValue>>,
+    _on_message: Closure<dyn FnMut(web_sys::MessageEvent)>,
+}
+
+thread_local! {
+    static HOOK: RefCell<Option<WebSocketHook>> = const { RefCell::new(None) };
+    static MESSAGES: RefCell<VecDeque<WsMessage>> = const { RefCell::new(VecDeque::new()) };
+    static NEXT_SEQ: Cell<u64> = const { Cell::new(1) };
+    // Sequence number of the last message returned by `wait_for_ws_message`.
+    static LAST_AWAITED_SEQ: Cell<u64> = const { Cell::new(0) };
+}
+
+// Converts the data of a message event to text.
+fn message_text(data: &JsValue) -> String {
+    if let Some(text) = data.as_string() {
+        return text;
+    }
+    if let Some(buffer) = data.dyn_ref::<js_sys::ArrayBuffer>() {
+        return format!("[binary {} bytes]", buffer.byte_length());
+    }
+    let size = js_sys::Reflect::get(data, &JsValue::from_str("size")).ok().and_then(|size| size.as_f64());
+    format!("[binary {} bytes]", size.unwrap_or(0.0))
+}
+
+// Stores a received message, dropping the oldest one when the buffer is full.
+fn record(url: String, data: String) {
+    let seq = NEXT_SEQ.with(|next| next.replace(next.get() + 1));
+    MESSAGES.with(|messages| {
+        let mut messages = messages.borrow_mut();
+        if messages.len() == MAX_WS_MESSAGES {
+            messages.pop_front();
+        }
+        messages.push_back(WsMessage { seq, url, data, received_at_ms: js_sys::Date::now() });
+    });
+}
+
+/// `true` once `install_websocket_hook` has run.
+pub fn websocket_hook_installed() -> bool {
+    HOOK.with(|hook| hook.borrow().is_some())
+}
+
+/// Replaces the page's `WebSocket` constructor with a proxy that records the messages every new
+/// socket receives. Installing the hook again does nothing.
+///
+/// The proxy forwards everything else to the original constructor, so pages keep working
+/// (`instanceof WebSocket`, `WebSocket.OPEN`, ...). Sockets opened before the hook was installed
+/// are not observed, so it should be installed before the page connects.
+pub fn install_websocket_hook() -> Result<(), DomError> {
+    if websocket_hook_installed() {
+        return Ok(());
+    }
+    let window = dom_backend::window().ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?;
+    let original = js_sys::Reflect::get(&window, &JsValue::from_str("WebSocket"))?;
+    let original_constructor = original.dyn_ref::<js_sys::Function>().cloned().ok_or_else(|| DomError::JsError {
+        message: "WebSocket is not available on this page".to_string(),
+    })?;
+
+    let on_message = Closure::wrap(Box::new(|event: web_sys::MessageEvent| {
+        let url = event
+            .target()
+            .and_then(|socket| js_sys::Reflect::get(&socket, &JsValue::from_str("url")).ok())
+            .and_then(|url| url.as_string())
+            .unwrap_or_default();
+        record(url, message_text(&event.data()));
+    }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+    let listener: js_sys::Function = on_message.as_ref().unchecked_ref::<js_sys::Function>().clone();
+
+    // `construct` trap of the proxy: `new WebSocket(...)` builds the real socket and listens to it.
+    let construct = Closure::wrap(Box::new(move |target: JsValue, args: js_sys::Array| -> Result<JsValue, JsValue> {
+        let socket = js_sys::Reflect::construct(target.unchecked_ref::<js_sys::Function>(), &args)?;
+        socket.unchecked_ref::<web_sys::EventTarget>().add_event_listener_with_callback("message", &listener)?;
+        Ok(socket)
+    }) as Box<dyn FnMut(JsValue, js_sys::Array) -> Result<JsValue, JsValue>>);
+
+    let handler = js_sys::Object::new();
+    js_sys::Reflect::set(&handler, &JsValue::from_str("construct"), construct.as_ref())?;
+    let proxy = js_sys::Proxy::new(&original_constructor, &handler);
+    js_sys::Reflect::set(&window, &JsValue::from_str("WebSocket"), &proxy)?;
+
+    HOOK.with(|hook| *hook.borrow_mut() = Some(WebSocketHook { _original: original, _construct: construct, _on_message: on_message }));
+    console::log_1(&"WebSocket capture hook installed".into());
+    Ok(())
+}
+
+// Error returned by the commands while the hook is not installed.
+fn hook_not_installed() -> DomError {
+    DomError::JsError {
+        message: "WebSocket capture is not enabled. Call enable_websocket_capture before the page opens its sockets.".to_string(),
+    }
+}
+
+/// Returns the captured messages, oldest first, as a JSON array of `WsMessage`.
+///
+/// # Arguments
+/// * `url_filter`: If given, only messages of sockets whose URL contains this text are returned.
+pub fn get_ws_messages(url_filter: Option<&str>) -> Result<String, DomError> {
+    if !websocket_hook_installed() {
+        return Err(hook_not_installed());
+    }
+    let messages: Vec<WsMessage> = MESSAGES.with(|messages| {
+        messages
+            .borrow()
+            .iter()
+            .filter(|message| url_filter.is_none_or(|filter| message.url.contains(filter)))
+            .cloned()
+            .collect()
+    });
+    serde_json::to_string(&messages).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+/// Waits for a captured message whose text contains `pattern`.
+///
+/// Only messages captured after the one returned by the previous call are considered, so a
+/// sequence of waits consumes messages in order; a message that arrived before the wait started
+/// (e.g. right after the click that triggered it) is still found.
+///
+/// # Arguments
+/// * `pattern`: The text the message must contain.
+/// * `timeout_ms`: How long to wait. Defaults to `DEFAULT_WS_WAIT_TIMEOUT_MS`, or the timeout of
+///   `wait` if it is longer.
+/// * `wait`: The default timeout and the polling interval (see `WaitConfig`).
+///
+/// # Returns
+/// * `Ok(String)` with the matching message as a JSON `WsMessage`.
+/// * `Err(DomError::ElementNotFound)` if no matching message arrived within the timeout.
+pub async fn wait_for_ws_message(pattern: &str, timeout_ms: Option<u32>, wait: &WaitConfig) -> Result<String, DomError> {
+    if !websocket_hook_installed() {
+        return Err(hook_not_installed());
+    }
+    let timeout = wait.timeout(timeout_ms, DEFAULT_WS_WAIT_TIMEOUT_MS);
+    let mut waited = 0;
+    loop {
+        let after = LAST_AWAITED_SEQ.with(Cell::get);
+        let found = MESSAGES.with(|messages| {
+            messages.borrow().iter().find(|message| message.seq > after && message.data.contains(pattern)).cloned()
+        });
+        if let Some(message) = found {
+            LAST_AWAITED_SEQ.with(|last| last.set(message.seq));
+            return serde_json::to_string(&message).map_err(|e| DomError::SerializationError { message: e.to_string() });
+        }
+        if waited >= timeout {
+            return Err(DomError::ElementNotFound {
+                selector: pattern.to_string(),
+                message: Some(format!("No WebSocket message containing '{}' arrived within {}ms", pattern, timeout)),
+            });
+        }
+        TimeoutFuture::new(wait.interval_ms).await;
+        waited += wait.interval_ms;
+    }
+}
+
+/// Time given to the browser to deliver buffered `largest-contentful-paint` entries.
+const LCP_WAIT_MS: u32 = 100;
+
+/// Navigation and paint timing of the current page, in milliseconds since the navigation started.
+/// A metric is `None` if the browser does not report it or the page has not reached it yet.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PerfMetrics {
+    /// Time to first byte: when the first byte of the document response arrived.
+    pub ttfb_ms: Option<f64>,
+    /// When the `DOMContentLoaded` handlers finished.
+    pub dom_content_loaded_ms: Option<f64>,
+    /// When the `load` handlers finished.
+    pub load_ms: Option<f64>,
+    /// First paint, where supported.
+    pub first_paint_ms: Option<f64>,
+    /// First contentful paint.
+    pub first_contentful_paint_ms: Option<f64>,
+    /// Largest contentful paint, where supported (Chromium-based browsers).
+    pub largest_contentful_paint_ms: Option<f64>,
+    /// Size of the document response, headers included, or `0` if served from cache.
+    pub transfer_size_bytes: Option<f64>,
+    /// Number of resources (scripts, images, XHR, ...) loaded so far.
+    pub resource_count: u32,
+}
+
+// Timestamps that are not reached yet are reported as `0`.
+fn reached(timestamp: f64) -> Option<f64> {
+    Some(timestamp).filter(|&timestamp| timestamp > 0.0)
+}
+
+// Reads the latest buffered `largest-contentful-paint` entry. The entries are only available
+// through a `PerformanceObserver`, which delivers them asynchronously.
+async fn largest_contentful_paint() -> Option<f64> {
+    let latest: Rc<Cell<Option<f64>>> = Rc::new(Cell::new(None));
+    let record_latest = |latest: &Rc<Cell<Option<f64>>>, entries: js_sys::Array| {
+        if let Some(entry) = entries.iter().last() {
+            latest.set(Some(entry.unchecked_into::<web_sys::PerformanceEntry>().start_time()));
+        }
+    };
+    let callback_latest = latest.clone();
+    let callback = Closure::wrap(Box::new(move |list: web_sys::PerformanceObserverEntryList| {
+        record_latest(&callback_latest, list.get_entries());
+    }) as Box<dyn FnMut(web_sys::PerformanceObserverEntryList)>);
+    let observer = web_sys::PerformanceObserver::new(callback.as_ref().unchecked_ref()).ok()?;
+
+    // `buffered` only applies to the single-`type` form, which the generated init type lacks.
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &JsValue::from_str("type"), &JsValue::from_str("largest-contentful-paint")).ok()?;
+    js_sys::Reflect::set(&options, &JsValue::from_str("buffered"), &JsValue::TRUE).ok()?;
+    observer.observe(options.unchecked_ref());
+
+    TimeoutFuture::new(LCP_WAIT_MS).await;
+    record_latest(&latest, observer.take_records());
+    observer.disconnect();
+    latest.get()
+}
+
+/// Collects navigation and paint timing from the Performance API.
+///
+/// # Returns
+/// * `Ok(String)` with a JSON `PerfMetrics` object.
+/// * `Err(DomError::JsError)` if the Performance API is not available.
+pub async fn get_perf_metrics() -> Result<String, DomError> {
+    let window = dom_backend::window().ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?;
+    let performance = window.performance().ok_or_else(|| DomError::JsError {
+        message: "The Performance API is not available on this page".to_string(),
+    })?;
+
+    let mut metrics = PerfMetrics::default();
+    if let Ok(navigation) = performance.get_entries_by_type("navigation").get(0).dyn_into::<web_sys::PerformanceNavigationTiming>() {
+        metrics.ttfb_ms = reached(navigation.response_start());
+        metrics.dom_content_loaded_ms = reached(navigation.dom_content_loaded_event_end());
+        metrics.load_ms = reached(navigation.load_event_end());
+        metrics.transfer_size_bytes = Some(navigation.transfer_size());
+    }
+    for paint in performance.get_entries_by_type("paint").iter() {
+        let paint: web_sys::PerformanceEntry = paint.unchecked_into();
+        match paint.name().as_str() {
+            "first-paint" => metrics.first_paint_ms = Some(paint.start_time()),
+            "first-contentful-paint" => metrics.first_contentful_paint_ms = Some(paint.start_time()),
+            _ => {}
+        }
+    }
+    metrics.resource_count = performance.get_entries_by_type("resource").length();
+    metrics.largest_contentful_paint_ms = largest_contentful_paint().await;
+
+    serde_json::to_string(&metrics).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+/// The response to a request made by `fetch_url`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FetchResponse {
+    pub status: u16,
+    pub status_text: String,
+    /// The URL of the response, after redirects.
+    pub url: String,
+    /// The response headers, lowercased. Responses from other origins only expose the headers their
+    /// server lists in `Access-Control-Expose-Headers`, besides `Content-Type` and a few others.
+    pub headers: BTreeMap<String, String>,
+    /// The response body as text, cut to `MAX_FETCH_BODY_CHARS` characters.
+    pub body: String,
+    /// Whether `body` was cut.
+    pub truncated: bool,
+}
+
+// Calls the global `fetch` with `init` and awaits the response.
+async fn call_fetch(url: &str, init: &js_sys::Object) -> Result<JsValue, JsValue> {
+    let fetch: js_sys::Function = js_sys::Reflect::get(&js_sys::global(), &"fetch".into())?.dyn_into()?;
+    let response = fetch.call2(&JsValue::NULL, &url.into(), init)?;
+    wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&response)).await
+}
+
+// Tells why a request to another origin failed: the browser rejects requests refused by CORS and
+// requests that got no response alike, so a `no-cors` `HEAD` request, which CORS does not apply to
+// and which has no side effects, checks whether the server answers at all.
+async fn fetch_failure(url: &str, page_origin: &str, error: JsValue) -> DomError {
+    let message = match error.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => error.as_string().unwrap_or_else(|| format!("{:?}", error)),
+    };
+    if !error.is_instance_of::<js_sys::TypeError>() {
+        return DomError::NetworkError { url: url.to_string(), message };
+    }
+    let cross_origin = dom_utils::resolve_url(url).map(|url| url.origin() != page_origin).unwrap_or(false);
+    if cross_origin {
+        let probe = js_sys::Object::new();
+        let _ = js_sys::Reflect::set(&probe, &"method".into(), &"HEAD".into());
+        let _ = js_sys::Reflect::set(&probe, &"mode".into(), &"no-cors".into());
+        if call_fetch(url, &probe).await.is_ok() {
+            return DomError::CorsBlocked { url: url.to_string(), origin: page_origin.to_string() };
+        }
+    }
+    DomError::NetworkError { url: url.to_string(), message }
+}
+
+/// Makes an HTTP request from the page with `fetch`, with the page's cookies for its own origin.
+///
+/// # Arguments
+/// * `method`: One of `FETCH_METHODS`, in any case.
+/// * `url`: The URL, absolute or relative to the current page.
+/// * `body`: The request body, sent as `application/json` if it is valid JSON and as text
+///   otherwise. `GET` and `HEAD` requests cannot have one.
+///
+/// # Returns
+/// * `Ok(String)` with a JSON `FetchResponse`, whatever its status.
+/// * `Err(DomError::CorsBlocked)` if the server of another origin answered but does not allow the
+///   page to read its response (no `Access-Control-Allow-Origin` for the page's origin).
+/// * `Err(DomError::NetworkError)` if no response came back (unreachable server, offline, mixed
+///   content, request blocked by the browser or an extension).
+/// * `Err(DomError::JsTypeError)` if the method, URL or body is invalid.
+pub async fn fetch_url(method: &str, url: &str, body: Option<&str>) -> Result<String, DomError> {
+    let method = method.to_uppercase();
+    if !FETCH_METHODS.contains(&method.as_str()) {
+        return Err(DomError::JsTypeError { message: format!("Unsupported HTTP method '{}'; use one of {}", method, FETCH_METHODS.join(", ")) });
+    }
+    if body.is_some() && matches!(method.as_str(), "GET" | "HEAD") {
+        return Err(DomError::JsTypeError { message: format!("{} requests cannot have a body", method) });
+    }
+    let url = dom_utils::resolve_url(url)?.href();
+    let page_origin = dom_utils::resolve_url("/")?.origin();
+
+    let init = js_sys::Object::new();
+    js_sys::Reflect::set(&init, &"method".into(), &method.as_str().into())?;
+    if let Some(body) = body {
+        js_sys::Reflect::set(&init, &"body".into(), &body.into())?;
+        if serde_json::from_str::<serde_json::Value>(body).is_ok() {
+            let headers = js_sys::Object::new();
+            js_sys::Reflect::set(&headers, &"Content-Type".into(), &"application/json".into())?;
+            js_sys::Reflect::set(&init, &"headers".into(), &headers)?;
+        }
+    }
+    let response = match call_fetch(&url, &init).await {
+        Ok(response) => response,
+        Err(error) => return Err(fetch_failure(&url, &page_origin, error).await),
+    };
+
+    let field = |name: &str| js_sys::Reflect::get(&response, &name.into()).unwrap_or(JsValue::UNDEFINED);
+    let mut headers = BTreeMap::new();
+    if let Ok(Some(entries)) = js_sys::try_iter(&field("headers")) {
+        for entry in entries.flatten() {
+            let entry: js_sys::Array = entry.unchecked_into();
+            if let (Some(name), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string()) {
+                headers.insert(name.to_lowercase(), value);
+            }
+        }
+    }
+    let text: js_sys::Function = field("text").dyn_into()?;
+    let text = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&text.call0(&response)?)).await?;
+    let text = text.as_string().unwrap_or_default();
+    let (body, truncated) = match text.char_indices().nth(MAX_FETCH_BODY_CHARS) {
+        Some((cut, _)) => (text[..cut].to_string(), true),
+        None => (text, false),
+    };
+    let fetched = FetchResponse {
+        status: field("status").as_f64().unwrap_or_default() as u16,
+        status_text: field("statusText").as_string().unwrap_or_default(),
+        url: field("url").as_string().filter(|url| !url.is_empty()).unwrap_or(url),
+        headers,
+        body,
+        truncated,
+    };
+    serde_json::to_string(&fetched).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // Delivers a synthetic message to `socket`, as if the server had sent `data`.
+    fn deliver(socket: &JsValue, data: &str) {
+        let init = web_sys::MessageEventInit::new();
+        init.set_data(&JsValue::from_str(data));
+        let event = web_sys::MessageEvent::new_with_event_init_dict("message", &init).unwrap();
+        socket.unchecked_ref::<web_sys::EventTarget>().dispatch_event(&event).unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_websocket_hook_captures_messages() {
+        install_websocket_hook().unwrap();
+        install_websocket_hook().unwrap(); // Idempotent
+
+        let window = web_sys::window().unwrap();
+        let constructor: js_sys::Function = js_sys::Reflect::get(&window, &"WebSocket".into()).unwrap().unchecked_into();
+        let socket = js_sys::Reflect::construct(&constructor, &js_sys::Array::of1(&"ws://127.0.0.1:9/feed".into())).unwrap();
+        assert!(socket.is_instance_of::<js_sys::Object>());
+        assert_eq!(js_sys::Reflect::get(&constructor, &"OPEN".into()).unwrap(), JsValue::from(1), "Statics are forwarded");
+
+        deliver(&socket, r#"{"type":"price","value":42}"#);
+        let messages: serde_json::Value = serde_json::from_str(&get_ws_messages(Some("/feed")).unwrap()).unwrap();
+        let last = messages.as_array().unwrap().last().unwrap().clone();
+        assert_eq!(last["url"], "ws://127.0.0.1:9/feed");
+        assert_eq!(last["data"], r#"{"type":"price","value":42}"#);
+        assert_eq!(get_ws_messages(Some("no-such-socket")).unwrap(), "[]");
+
+        // A message arriving while waiting is found; an already awaited one is not returned again.
+        let socket_clone = socket.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            TimeoutFuture::new(50).await;
+            deliver(&socket_clone, r#"{"type":"order","status":"filled"}"#);
+        });
+        let found: serde_json::Value = serde_json::from_str(&wait_for_ws_message("filled", Some(1000), &WaitConfig::default()).await.unwrap()).unwrap();
+        assert_eq!(found["data"], r#"{"type":"order","status":"filled"}"#);
+        assert!(matches!(wait_for_ws_message("filled", Some(200), &WaitConfig::default()).await, Err(DomError::ElementNotFound { .. })));
+
+        js_sys::Reflect::get(&socket, &"close".into()).unwrap().unchecked_into::<js_sys::Function>().call0(&socket).unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_get_perf_metrics() {
+        let metrics: serde_json::Value = serde_json::from_str(&get_perf_metrics().await.unwrap()).unwrap();
+        assert!(metrics["ttfb_ms"].as_f64().unwrap() > 0.0, "The test page has a navigation entry");
+        assert!(metrics["dom_content_loaded_ms"].as_f64().unwrap() >= metrics["ttfb_ms"].as_f64().unwrap());
+        assert!(metrics.get("largest_contentful_paint_ms").is_some(), "Unsupported metrics are reported as null");
+        assert!(metrics["resource_count"].is_u64());
+    }
+
+    // Runs `fetch_url` with the global `fetch` replaced by a function with `body`, taking `(url, init)`.
+    async fn fetch_with_stub(body: &str, method: &str, url: &str, request_body: Option<&str>) -> Result<String, DomError> {
+        let global = js_sys::global();
+        let original_fetch = js_sys::Reflect::get(&global, &"fetch".into()).unwrap();
+        js_sys::Reflect::set(&global, &"fetch".into(), &js_sys::Function::new_with_args("url, init", body)).unwrap();
+        let result = fetch_url(method, url, request_body).await;
+        js_sys::Reflect::set(&global, &"fetch".into(), &original_fetch).unwrap();
+        result
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_fetch_url_returns_response() {
+        let echo = "return Promise.resolve(new Response(init.method + ' ' + init.headers['Content-Type'] + ' ' + init.body + 'x'.repeat(100000),
+            { status: 201, statusText: 'Created', headers: { 'X-Request-Id': 'abc' } }));";
+        let response: serde_json::Value = serde_json::from_str(&fetch_with_stub(echo, "post", "/items", Some(r#"{"a": 1}"#)).await.unwrap()).unwrap();
+        assert_eq!(response["status"], 201);
+        assert_eq!(response["status_text"], "Created");
+        assert!(response["url"].as_str().unwrap().ends_with("/items"), "Relative URLs are resolved against the page");
+        assert_eq!(response["headers"]["x-request-id"], "abc");
+        assert!(response["body"].as_str().unwrap().starts_with(r#"POST application/json {"a": 1}xxx"#));
+        assert_eq!(response["body"].as_str().unwrap().chars().count(), MAX_FETCH_BODY_CHARS);
+        assert_eq!(response["truncated"], true);
+
+        assert!(matches!(fetch_url("GET", "/items", Some("body")).await, Err(DomError::JsTypeError { .. })));
+        assert!(matches!(fetch_url("TRACE", "/items", None).await, Err(DomError::JsTypeError { .. })));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_fetch_url_reports_cors_and_network_failures() {
+        // The browser rejects both alike; only the server of the first answers no-cors requests.
+        let reject = "return init.mode === 'no-cors' && url.includes('other-origin')
+            ? Promise.resolve(new Response(null, { status: 200 }))
+            : Promise.reject(new TypeError('Failed to fetch'));";
+        let origin = web_sys::window().unwrap().location().origin().unwrap();
+        match fetch_with_stub(reject, "GET", "https://other-origin.example/config.json", None).await {
+            Err(DomError::CorsBlocked { url, origin: page_origin }) => {
+                assert_eq!(url, "https://other-origin.example/config.json");
+                assert_eq!(page_origin, origin);
+            }
+            other => panic!("Expected CorsBlocked, got {:?}", other),
+        }
+        let unreachable = fetch_with_stub(reject, "GET", "https://unreachable.example/", None).await;
+        assert!(matches!(&unreachable, Err(DomError::NetworkError { message, .. }) if message == "Failed to fetch"), "{:?}", unreachable);
+        let same_origin = fetch_with_stub(reject, "GET", "/other-origin", None).await;
+        assert!(matches!(same_origin, Err(DomError::NetworkError { .. })), "Same-origin requests are not subject to CORS");
+    }
+}
@@ -0,0 +1,633 @@
+use crate::agent::AgentError;
+use crate::secrets::SecretStore;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use wasm_bindgen::{JsCast, JsValue};
+
+/// Name of the `CALL_API` argument holding the request body; the others are parameters.
+pub const BODY_ARGUMENT: &str = "body";
+/// `$ref`s are followed this many times within one schema; deeper (recursive) schemas accept anything.
+const MAX_REF_DEPTH: usize = 16;
+/// Request body schemas are cut to this many characters in the prompt.
+#[cfg(not(feature = "dom-only"))]
+const MAX_PROMPT_SCHEMA_CHARS: usize = 400;
+/// The HTTP methods of OpenAPI path items.
+const METHODS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+/// Options of an API registered with `RustAgent::register_api`, e.g.
+/// `{"base_url": "https://api.example.com/v1", "headers": {"Authorization": "Bearer {{secret.api_token}}"}}`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiOptions {
+    /// URL the paths of the spec are appended to. Defaults to the URL of the spec's first server.
+    pub base_url: Option<String>,
+    /// Headers sent with every call. `{{secret.<name>}}` placeholders are substituted when the call
+    /// is made, so API keys never appear in the spec, the prompt or results.
+    pub headers: BTreeMap<String, String>,
+    /// Whether only `GET` and `HEAD` operations may be called; the others are left out.
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParameterLocation {
+    Path,
+    Query,
+    Header,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ApiParameter {
+    name: String,
+    location: ParameterLocation,
+    required: bool,
+    schema: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ApiOperation {
+    id: String,
+    method: String,
+    path: String,
+    summary: String,
+    parameters: Vec<ApiParameter>,
+    // The schema of the JSON request body, and whether the body is required.
+    body: Option<(Value, bool)>,
+}
+
+/// The operations of an OpenAPI 3 document that `CALL_API` may call, with the options they were
+/// registered with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiSpec {
+    base_url: String,
+    headers: BTreeMap<String, String>,
+    operations: Vec<ApiOperation>,
+}
+
+/// An HTTP request made by `CALL_API`, once its arguments were checked against the spec.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl ApiSpec {
+    /// Parses an OpenAPI 3 document, as JSON or YAML, and collects its operations.
+    ///
+    /// `$ref`s to other parts of the document are inlined. Operations without an `operationId` are
+    /// named after their method and path (e.g. `get_orders_id` for `GET /orders/{id}`); operations
+    /// whose request body is not JSON are left out, as are cookie parameters, which the browser sends.
+    ///
+    /// # Returns
+    /// The spec, or an error if the document is malformed, not OpenAPI 3, or has no operation left.
+    pub fn parse(spec: &str, options: ApiOptions) -> Result<Self, String> {
+        let document: Value = if spec.trim_start().starts_with('{') {
+            serde_json::from_str(spec).map_err(|e| format!("Invalid JSON OpenAPI document: {}", e))?
+        } else {
+            serde_yaml::from_str(spec).map_err(|e| format!("Invalid YAML OpenAPI document: {}", e))?
+        };
+        match document.get("openapi").and_then(Value::as_str) {
+            Some(version) if version.starts_with("3.") => {}
+            Some(version) => return Err(format!("OpenAPI {} is not supported; only OpenAPI 3 documents are", version)),
+            None => return Err("Not an OpenAPI 3 document: 'openapi' is missing (Swagger 2.0 is not supported)".to_string()),
+        }
+        let paths = document.get("paths").and_then(Value::as_object).ok_or("The OpenAPI document has no 'paths'")?;
+
+        let mut operations: Vec<ApiOperation> = Vec::new();
+        for (path, item) in paths {
+            let item = inline_refs(&document, item, 0);
+            let shared_parameters = item.get("parameters").and_then(Value::as_array).cloned().unwrap_or_default();
+            for method in METHODS {
+                let Some(operation) = item.get(method) else { continue };
+                if options.read_only && !matches!(method, "get" | "head") {
+                    continue;
+                }
+                // Operation parameters override path item parameters with the same name and location.
+                let mut parameters: Vec<ApiParameter> = Vec::new();
+                let own_parameters = operation.get("parameters").and_then(Value::as_array).cloned().unwrap_or_default();
+                for parameter in shared_parameters.iter().chain(own_parameters.iter()).filter_map(parse_parameter) {
+                    parameters.retain(|other| !(other.name == parameter.name && other.location == parameter.location));
+                    parameters.push(parameter);
+                }
+                let body = match operation.get("requestBody") {
+                    Some(request_body) => match json_body_schema(request_body) {
+                        Some(schema) => Some((schema, request_body.get("required").and_then(Value::as_bool).unwrap_or(false))),
+                        None => continue,
+                    },
+                    None => None,
+                };
+                let id = match operation.get("operationId").and_then(Value::as_str) {
+                    Some(id) => id.to_string(),
+                    None => generated_operation_id(method, path),
+                };
+                if operations.iter().any(|other| other.id == id) {
+                    return Err(format!("Several operations are named '{}'", id));
+                }
+                let summary = ["summary", "description"].iter().find_map(|key| operation.get(*key).and_then(Value::as_str)).unwrap_or_default();
+                operations.push(ApiOperation {
+                    id,
+                    method: method.to_uppercase(),
+                    path: path.clone(),
+                    summary: summary.split_whitespace().collect::<Vec<_>>().join(" "),
+                    parameters,
+                    body,
+                });
+            }
+        }
+        if operations.is_empty() {
+            return Err("The OpenAPI document has no operation that can be called".to_string());
+        }
+
+        let base_url = match options.base_url {
+            Some(base_url) => base_url,
+            None => document.pointer("/servers/0").map(server_url).unwrap_or_default(),
+        };
+        Ok(ApiSpec { base_url, headers: options.headers, operations })
+    }
+
+    /// Builds the request calling operation `operation_id` with `arguments`: its parameters by name,
+    /// and its JSON request body under `body`. Arguments are checked against the parameter and body
+    /// schemas (types, enums, required properties); parameters may be given as strings.
+    ///
+    /// # Returns
+    /// The request, or an error naming the first problem: unknown operation or argument, missing
+    /// required parameter or body, value not matching its schema, or unknown secret in a header.
+    pub fn request(&self, operation_id: &str, arguments: &Map<String, Value>, secrets: &SecretStore) -> Result<ApiRequest, String> {
+        let operation = self.operations.iter().find(|operation| operation.id == operation_id).ok_or_else(|| {
+            let ids: Vec<&str> = self.operations.iter().map(|operation| operation.id.as_str()).collect();
+            format!("Unknown operation '{}'; available operations: {}", operation_id, ids.join(", "))
+        })?;
+        for name in arguments.keys() {
+            if name != BODY_ARGUMENT && !operation.parameters.iter().any(|parameter| &parameter.name == name) {
+                let names: Vec<&str> = operation.parameters.iter().map(|parameter| parameter.name.as_str()).collect();
+                return Err(format!("Unknown argument '{}' for {}; its parameters are: {}", name, operation.id, names.join(", ")));
+            }
+        }
+
+        let mut path = operation.path.clone();
+        let mut query: Vec<(String, String)> = Vec::new();
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for parameter in &operation.parameters {
+            let Some(value) = arguments.get(&parameter.name) else {
+                if parameter.required || parameter.location == ParameterLocation::Path {
+                    return Err(format!("{} requires parameter '{}'", operation.id, parameter.name));
+                }
+                continue;
+            };
+            check_schema(&parameter.schema, &parameter_value(&parameter.schema, value), &parameter.name)?;
+            match parameter.location {
+                ParameterLocation::Path => {
+                    path = path.replace(&format!("{{{}}}", parameter.name), &percent_encode(&parameter_text(value)));
+                }
+                ParameterLocation::Query => match value {
+                    Value::Array(items) => query.extend(items.iter().map(|item| (parameter.name.clone(), parameter_text(item)))),
+                    value => query.push((parameter.name.clone(), parameter_text(value))),
+                },
+                ParameterLocation::Header => headers.push((parameter.name.clone(), parameter_text(value))),
+            }
+        }
+
+        let body = match (&operation.body, arguments.get(BODY_ARGUMENT)) {
+            (Some((schema, _)), Some(body)) => {
+                check_schema(schema, body, BODY_ARGUMENT)?;
+                headers.push(("Content-Type".to_string(), "application/json".to_string()));
+                Some(body.to_string())
+            }
+            (Some((_, true)), None) => return Err(format!("{} requires a request body, given as '{}'", operation.id, BODY_ARGUMENT)),
+            (None, Some(_)) => return Err(format!("{} takes no request body", operation.id)),
+            (_, None) => None,
+        };
+
+        for (name, value) in &self.headers {
+            headers.push((name.clone(), secrets.substitute(value)?));
+        }
+        headers.push(("Accept".to_string(), "application/json".to_string()));
+        let mut url = format!("{}{}", self.base_url.trim_end_matches('/'), path);
+        if !query.is_empty() {
+            let pairs: Vec<String> = query.iter().map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value))).collect();
+            url = format!("{}{}{}", url, if url.contains('?') { '&' } else { '?' }, pairs.join("&"));
+        }
+        Ok(ApiRequest { method: operation.method.clone(), url, headers, body })
+    }
+
+    // The lines of the prompt describing the operations of the API registered as `name`.
+    #[cfg(not(feature = "dom-only"))]
+    fn prompt_lines(&self, name: &str) -> Vec<String> {
+        self.operations
+            .iter()
+            .map(|operation| {
+                let mut line = format!("- {}.{}: {} {}", name, operation.id, operation.method, operation.path);
+                if !operation.summary.is_empty() {
+                    line.push_str(&format!(" ({})", operation.summary));
+                }
+                let parameters: Vec<String> = operation
+                    .parameters
+                    .iter()
+                    .map(|parameter| {
+                        let kind = parameter.schema.get("type").and_then(Value::as_str).unwrap_or("any");
+                        let location = format!("{:?}", parameter.location).to_lowercase();
+                        let required = if parameter.required || parameter.location == ParameterLocation::Path { ", required" } else { "" };
+                        format!("{} ({} {}{})", parameter.name, location, kind, required)
+                    })
+                    .collect();
+                if !parameters.is_empty() {
+                    line.push_str(&format!("; parameters: {}", parameters.join(", ")));
+                }
+                if let Some((schema, required)) = &operation.body {
+                    let mut schema = schema.to_string();
+                    if let Some((cut, _)) = schema.char_indices().nth(MAX_PROMPT_SCHEMA_CHARS) {
+                        schema = format!("{}...", &schema[..cut]);
+                    }
+                    line.push_str(&format!("; body{}: {}", if *required { " (required)" } else { "" }, schema));
+                }
+                line
+            })
+            .collect()
+    }
+}
+
+/// The APIs registered with `RustAgent::register_api`, by name, whose operations `CALL_API` calls.
+/// None by default.
+#[derive(Debug, Clone, Default)]
+pub struct ApiRegistry {
+    apis: BTreeMap<String, ApiSpec>,
+}
+
+impl ApiRegistry {
+    /// Registers `spec` under `name` (letters, digits, `_` and `-`), replacing any API of that name.
+    pub fn register(&mut self, name: &str, spec: ApiSpec) -> Result<(), String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return Err(format!("Invalid API name '{}': use letters, digits, '_' and '-'", name));
+        }
+        self.apis.insert(name.to_string(), spec);
+        Ok(())
+    }
+
+    /// Forgets the API registered under `name`. Returns `false` if there was none.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.apis.remove(name).is_some()
+    }
+
+    /// Builds the request of a `CALL_API` command, whose selector names the operation as
+    /// `<api>.<operation_id>` and whose value holds its arguments as a JSON object, if it has any.
+    pub fn request(&self, operation: &str, arguments: Option<&str>, secrets: &SecretStore) -> Result<ApiRequest, AgentError> {
+        let failed = |message: String| AgentError::ApiCallFailed { message, status: None };
+        let (api, operation_id) = operation.trim().split_once('.').ok_or_else(|| {
+            AgentError::CommandParseError(format!("CALL_API expects an operation as <api>.<operation_id>, got '{}'", operation))
+        })?;
+        let spec = self.apis.get(api).ok_or_else(|| failed(format!("No API registered under the name '{}'", api)))?;
+        let arguments = arguments.map(str::trim).filter(|arguments| !arguments.is_empty()).unwrap_or("{}");
+        // Secrets may only reach the API through the registered headers, which the LLM cannot change.
+        if SecretStore::has_placeholder(arguments) {
+            return Err(AgentError::PolicyViolation(
+                "Secrets cannot be used in CALL_API arguments; put them in the headers given to register_api.".to_string(),
+            ));
+        }
+        let arguments: Map<String, Value> = serde_json::from_str(arguments)
+            .map_err(|e| AgentError::CommandParseError(format!("CALL_API arguments must be a JSON object: {}", e)))?;
+        spec.request(operation_id, &arguments, secrets).map_err(failed)
+    }
+
+    /// The part of the prompt describing `CALL_API` and the registered operations, or an empty
+    /// string if no API is registered.
+    #[cfg(not(feature = "dom-only"))]
+    pub fn prompt_section(&self) -> String {
+        if self.apis.is_empty() {
+            return String::new();
+        }
+        let lines: Vec<String> = self.apis.iter().flat_map(|(name, spec)| spec.prompt_lines(name)).collect();
+        format!(
+            "The site's backend API can be called alongside DOM actions, e.g. with an id read from the page: \
+            {{\"action\": \"CALL_API\", \"selector\": \"<api>.<operation_id>\", \"value\": \"<JSON object of the parameters, with the JSON request body under \\\"{}\\\">\"}} \
+            (returns the HTTP status and the response body). Only these operations can be called:\n{}\n\n",
+            BODY_ARGUMENT,
+            lines.join("\n")
+        )
+    }
+}
+
+/// Calls the operation named by a `CALL_API` command with the global `fetch`.
+///
+/// # Returns
+/// A message with the HTTP status and the response body, with secrets masked, or `AgentError::ApiCallFailed` with the
+/// status if the response is not successful (2xx), or without one if the request was not made.
+pub async fn call(registry: &ApiRegistry, operation: &str, arguments: Option<&str>, secrets: &SecretStore) -> Result<String, AgentError> {
+    let request = registry.request(operation, arguments, secrets)?;
+    let (status, body) = fetch(&request, "same-origin").await.map_err(|message| AgentError::ApiCallFailed {
+        message: format!("CALL_API {} ({} {}) failed: {}", operation, request.method, request.url, message),
+        status: None,
+    })?;
+    // The response may echo the registered headers, and so their secrets.
+    let message = format!("CALL_API {} answered {}: {}", operation, status, secrets.mask(&body));
+    if (200..300).contains(&status) {
+        Ok(message)
+    } else {
+        Err(AgentError::ApiCallFailed { message, status: Some(status) })
+    }
+}
+
+/// Performs `request` with the global `fetch` (of the window, worker or Node) and returns the
+/// status and text of the response. `credentials` is the `credentials` mode of the request, e.g.
+/// `same-origin` (the default of `fetch`) or `include` to send cookies to other origins too.
+pub(crate) async fn fetch(request: &ApiRequest, credentials: &str) -> Result<(u16, String), String> {
+    let describe = |e: JsValue| e.as_string().unwrap_or_else(|| format!("{:?}", e));
+    let fetch = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("fetch"))
+        .ok()
+        .and_then(|fetch| fetch.dyn_into::<js_sys::Function>().ok())
+        .ok_or_else(|| "fetch is not available".to_string())?;
+    let headers = js_sys::Object::new();
+    for (name, value) in &request.headers {
+        js_sys::Reflect::set(&headers, &JsValue::from_str(name), &JsValue::from_str(value)).map_err(describe)?;
+    }
+    let init = js_sys::Object::new();
+    js_sys::Reflect::set(&init, &JsValue::from_str("method"), &JsValue::from_str(&request.method)).map_err(describe)?;
+    js_sys::Reflect::set(&init, &JsValue::from_str("headers"), &headers).map_err(describe)?;
+    js_sys::Reflect::set(&init, &JsValue::from_str("credentials"), &JsValue::from_str(credentials)).map_err(describe)?;
+    if let Some(body) = &request.body {
+        js_sys::Reflect::set(&init, &JsValue::from_str("body"), &JsValue::from_str(body)).map_err(describe)?;
+    }
+    let response = fetch.call2(&JsValue::NULL, &JsValue::from_str(&request.url), &init).map_err(describe)?;
+    let response = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&response)).await.map_err(describe)?;
+    let status = js_sys::Reflect::get(&response, &JsValue::from_str("status"))
+        .ok()
+        .and_then(|status| status.as_f64())
+        .ok_or_else(|| "fetch resolved to no response".to_string())?;
+    let text = js_sys::Reflect::get(&response, &JsValue::from_str("text"))
+        .ok()
+        .and_then(|text| text.dyn_into::<js_sys::Function>().ok())
+        .ok_or_else(|| "The response has no body".to_string())?;
+    let text = text.call0(&response).map_err(describe)?;
+    let text = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&text)).await.map_err(describe)?;
+    Ok((status as u16, text.as_string().unwrap_or_default()))
+}
+
+// Replaces the local `$ref`s (`#/components/...`) in `value` with what they point to.
+fn inline_refs(document: &Value, value: &Value, depth: usize) -> Value {
+    match value {
+        Value::Object(fields) => match fields.get("$ref").and_then(Value::as_str) {
+            Some(reference) => match reference.strip_prefix('#').and_then(|pointer| document.pointer(pointer)) {
+                Some(target) if depth < MAX_REF_DEPTH => inline_refs(document, target, depth + 1),
+                _ => Value::Object(Map::new()),
+            },
+            None => Value::Object(fields.iter().map(|(key, value)| (key.clone(), inline_refs(document, value, depth))).collect()),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(|item| inline_refs(document, item, depth)).collect()),
+        value => value.clone(),
+    }
+}
+
+// A parameter of an operation, or `None` for cookie parameters and malformed ones.
+fn parse_parameter(parameter: &Value) -> Option<ApiParameter> {
+    let location = match parameter.get("in")?.as_str()? {
+        "path" => ParameterLocation::Path,
+        "query" => ParameterLocation::Query,
+        "header" => ParameterLocation::Header,
+        _ => return None,
+    };
+    Some(ApiParameter {
+        name: parameter.get("name")?.as_str()?.to_string(),
+        location,
+        required: parameter.get("required").and_then(Value::as_bool).unwrap_or(false),
+        schema: parameter.get("schema").cloned().unwrap_or(Value::Object(Map::new())),
+    })
+}
+
+// The schema of a request body sent as JSON, or `None` if the body takes no JSON media type.
+fn json_body_schema(request_body: &Value) -> Option<Value> {
+    let content = request_body.get("content")?.as_object()?;
+    let (_, media) = content.iter().find(|(media_type, _)| *media_type == "application/json" || media_type.ends_with("+json"))?;
+    Some(media.get("schema").cloned().unwrap_or(Value::Object(Map::new())))
+}
+
+// The name given to an operation without `operationId`, e.g. `get_orders_id` for `GET /orders/{id}`.
+fn generated_operation_id(method: &str, path: &str) -> String {
+    let words = path.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty());
+    std::iter::once(method).chain(words).collect::<Vec<_>>().join("_")
+}
+
+// The URL of a server object, with its variables replaced by their defaults.
+fn server_url(server: &Value) -> String {
+    let mut url = server.get("url").and_then(Value::as_str).unwrap_or_default().to_string();
+    if let Some(variables) = server.get("variables").and_then(Value::as_object) {
+        for (name, variable) in variables {
+            let default = variable.get("default").and_then(Value::as_str).unwrap_or_default();
+            url = url.replace(&format!("{{{}}}", name), default);
+        }
+    }
+    url
+}
+
+// A parameter given as a string, converted to the number or boolean its schema expects (URLs
+// carry every parameter as text, and the LLM often quotes them).
+fn parameter_value(schema: &Value, value: &Value) -> Value {
+    let Value::String(text) = value else { return value.clone() };
+    let converted = match schema.get("type").and_then(Value::as_str) {
+        Some("integer" | "number") => serde_json::from_str::<serde_json::Number>(text.trim()).ok().map(Value::Number),
+        Some("boolean") => text.trim().parse::<bool>().ok().map(Value::Bool),
+        _ => None,
+    };
+    converted.unwrap_or_else(|| value.clone())
+}
+
+// The text of a parameter in a URL or header: strings as they are, other values as JSON.
+fn parameter_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Checks `value` against the parts of a JSON schema that catch most mistakes: `type` (with
+/// `nullable`), `enum`, and recursively the `required` and `properties` of objects and the `items`
+/// of arrays. `at` names the value in errors, e.g. `body.items[0].sku`.
+pub(crate) fn check_schema(schema: &Value, value: &Value, at: &str) -> Result<(), String> {
+    if value.is_null() && schema.get("nullable").and_then(Value::as_bool).unwrap_or(false) {
+        return Ok(());
+    }
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            return Err(format!("'{}' must be one of {}, got {}", at, allowed.join(", "), value));
+        }
+    }
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(kind)) => vec![kind.as_str()],
+        Some(Value::Array(kinds)) => kinds.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let matches_type = |kind: &str| match kind {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    };
+    if !types.is_empty() && !types.iter().any(|kind| matches_type(kind)) {
+        return Err(format!("'{}' must be of type {}, got {}", at, types.join(" or "), value));
+    }
+    match value {
+        Value::Object(fields) => {
+            let required = schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str);
+            for name in required {
+                if !fields.contains_key(name) {
+                    return Err(format!("'{}' requires property '{}'", at, name));
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, field) in fields {
+                if let Some(property) = properties.and_then(|properties| properties.get(name)) {
+                    check_schema(property, field, &format!("{}.{}", at, name))?;
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check_schema(item_schema, item, &format!("{}[{}]", at, index))?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// Percent-encodes `text` for a URL path segment or query component.
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SHOP_SPEC: &str = r##"{
+        "openapi": "3.0.3",
+        "servers": [{"url": "https://{region}.shop.example.com/v1", "variables": {"region": {"default": "eu"}}}],
+        "paths": {
+            "/orders/{orderId}": {
+                "parameters": [{"name": "orderId", "in": "path", "required": true, "schema": {"type": "integer"}}],
+                "get": {
+                    "operationId": "getOrder",
+                    "summary": "Get an order",
+                    "parameters": [
+                        {"name": "expand", "in": "query", "schema": {"type": "array", "items": {"type": "string", "enum": ["items", "customer"]}}},
+                        {"name": "session", "in": "cookie", "schema": {"type": "string"}}
+                    ]
+                }
+            },
+            "/orders/{orderId}/notes": {
+                "post": {
+                    "summary": "Add a note to an order",
+                    "parameters": [{"$ref": "#/components/parameters/OrderId"}],
+                    "requestBody": {"required": true, "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Note"}}}}
+                }
+            },
+            "/uploads": {
+                "put": {"operationId": "upload", "requestBody": {"content": {"application/octet-stream": {}}}}
+            }
+        },
+        "components": {
+            "parameters": {"OrderId": {"name": "orderId", "in": "path", "required": true, "schema": {"type": "integer"}}},
+            "schemas": {
+                "Note": {"type": "object", "required": ["text"], "properties": {"text": {"type": "string"}, "pinned": {"type": "boolean"}}}
+            }
+        }
+    }"##;
+
+    fn shop() -> ApiSpec {
+        ApiSpec::parse(SHOP_SPEC, ApiOptions { headers: BTreeMap::from([("X-Api-Key".to_string(), "static".to_string())]), ..ApiOptions::default() }).unwrap()
+    }
+
+    fn arguments(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_parse_collects_operations() {
+        let spec = shop();
+        assert_eq!(spec.base_url, "https://eu.shop.example.com/v1");
+        let ids: Vec<&str> = spec.operations.iter().map(|operation| operation.id.as_str()).collect();
+        // The upload takes no JSON body, and the cookie parameter is left to the browser.
+        assert_eq!(ids, vec!["getOrder", "post_orders_orderId_notes"]);
+        assert_eq!(spec.operations[0].parameters.len(), 2);
+        assert_eq!(spec.operations[1].body.as_ref().unwrap().0["required"], json!(["text"]));
+
+        let read_only = ApiSpec::parse(SHOP_SPEC, ApiOptions { read_only: true, ..ApiOptions::default() }).unwrap();
+        assert_eq!(read_only.operations.len(), 1);
+        assert!(ApiSpec::parse(r#"{"swagger": "2.0", "paths": {}}"#, ApiOptions::default()).unwrap_err().contains("Swagger 2.0"));
+        assert!(ApiSpec::parse(r#"{"openapi": "3.1.0", "paths": {}}"#, ApiOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_request_validates_arguments() {
+        let spec = shop();
+        let secrets = SecretStore::default();
+        let request = spec.request("getOrder", &arguments(json!({"orderId": "42", "expand": ["items", "customer"]})), &secrets).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.url, "https://eu.shop.example.com/v1/orders/42?expand=items&expand=customer");
+        assert!(request.headers.contains(&("X-Api-Key".to_string(), "static".to_string())));
+        assert_eq!(request.body, None);
+
+        let request = spec
+            .request("post_orders_orderId_notes", &arguments(json!({"orderId": 7, "body": {"text": "Leave at the door"}})), &secrets)
+            .unwrap();
+        assert_eq!(request.body.as_deref(), Some(r#"{"text":"Leave at the door"}"#));
+        assert!(request.headers.contains(&("Content-Type".to_string(), "application/json".to_string())));
+
+        let error = |operation: &str, value: Value| spec.request(operation, &arguments(value), &secrets).unwrap_err();
+        assert!(error("cancelOrder", json!({})).contains("available operations: getOrder, post_orders_orderId_notes"));
+        assert_eq!(error("getOrder", json!({})), "getOrder requires parameter 'orderId'");
+        assert_eq!(error("getOrder", json!({"orderId": "abc"})), "'orderId' must be of type integer, got \"abc\"");
+        assert!(error("getOrder", json!({"orderId": 1, "expand": ["price"]})).starts_with("'expand[0]' must be one of"));
+        assert!(error("getOrder", json!({"orderId": 1, "id": 1})).starts_with("Unknown argument 'id'"));
+        assert_eq!(error("post_orders_orderId_notes", json!({"orderId": 1})), "post_orders_orderId_notes requires a request body, given as 'body'");
+        assert_eq!(error("post_orders_orderId_notes", json!({"orderId": 1, "body": {"pinned": true}})), "'body' requires property 'text'");
+        assert_eq!(error("getOrder", json!({"orderId": 1, "body": {}})), "getOrder takes no request body");
+    }
+
+    #[test]
+    fn test_registry_requests() {
+        let mut registry = ApiRegistry::default();
+        assert!(registry.register("my shop", shop()).is_err());
+        registry.register("shop", shop()).unwrap();
+
+        let secrets = SecretStore::default();
+        let request = registry.request("shop.getOrder", Some(r#"{"orderId": "a/b"}"#), &secrets);
+        assert!(matches!(request, Err(AgentError::ApiCallFailed { status: None, .. })));
+        let request = registry.request("shop.getOrder", Some(r#"{"orderId": 5}"#), &secrets).unwrap();
+        assert_eq!(request.url, "https://eu.shop.example.com/v1/orders/5");
+        assert!(matches!(registry.request("shop.getOrder", Some("{{secret.key}}"), &secrets), Err(AgentError::PolicyViolation(_))));
+        assert!(matches!(registry.request("getOrder", None, &secrets), Err(AgentError::CommandParseError(_))));
+        assert!(matches!(registry.request("bank.getOrder", None, &secrets), Err(AgentError::ApiCallFailed { .. })));
+        assert!(registry.unregister("shop"));
+        assert!(!registry.unregister("shop"));
+    }
+
+    #[cfg(not(feature = "dom-only"))]
+    #[test]
+    fn test_prompt_section() {
+        let mut registry = ApiRegistry::default();
+        assert_eq!(registry.prompt_section(), "");
+        registry.register("shop", shop()).unwrap();
+        let section = registry.prompt_section();
+        assert!(section.contains("- shop.getOrder: GET /orders/{orderId} (Get an order); parameters: orderId (path integer, required), expand (query array)\n"));
+        assert!(section.contains("- shop.post_orders_orderId_notes: POST /orders/{orderId}/notes (Add a note to an order); parameters: orderId (path integer, required); body (required): {"));
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(percent_encode("a b/ç~"), "a%20b%2F%C3%A7~");
+    }
+}
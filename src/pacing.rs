@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use gloo_timers::future::TimeoutFuture;
+
+/// Length of the window `max_actions_per_minute` applies to.
+const MINUTE_MS: f64 = 60_000.0;
+
+/// Human-like pacing of DOM commands: a random delay between consecutive commands and a cap on
+/// the number of commands per minute. Commands run as fast as possible by default.
+///
+/// Clones share their command history, so runs started from the same agent (including scheduled
+/// and triggered runs) count against the same cap.
+#[derive(Debug, Clone, Default)]
+pub struct Pacing {
+    /// Minimum delay between the end of one command and the start of the next, in milliseconds.
+    pub min_delay_ms: u32,
+    /// Maximum delay; the actual delay is picked uniformly between the minimum and the maximum.
+    pub max_delay_ms: u32,
+    /// Maximum number of commands started in any 60 second window. Unlimited while `None`.
+    pub max_actions_per_minute: Option<u32>,
+    /// Start times (`Date.now()`) of the commands of the last minute, oldest first, and the end
+    /// time of the last command.
+    history: Rc<RefCell<PacingHistory>>,
+}
+
+#[derive(Debug, Default)]
+struct PacingHistory {
+    started_at_ms: VecDeque<f64>,
+    last_finished_at_ms: Option<f64>,
+}
+
+impl Pacing {
+    /// Creates a pacing policy with an empty history.
+    pub fn new(min_delay_ms: u32, max_delay_ms: u32, max_actions_per_minute: Option<u32>) -> Self {
+        Pacing { min_delay_ms, max_delay_ms, max_actions_per_minute, history: Rc::default() }
+    }
+
+    /// `true` if commands are delayed or capped at all.
+    pub fn is_enabled(&self) -> bool {
+        self.max_delay_ms > 0 || self.max_actions_per_minute.is_some()
+    }
+
+    /// Computes how long a command starting at `now_ms` has to wait. `random` (in `[0, 1)`) picks
+    /// the delay between `min_delay_ms` and `max_delay_ms`.
+    pub fn wait_ms(&self, now_ms: f64, random: f64) -> f64 {
+        let mut history = self.history.borrow_mut();
+        while history.started_at_ms.front().is_some_and(|&started| now_ms - started >= MINUTE_MS) {
+            history.started_at_ms.pop_front();
+        }
+
+        let delay = self.min_delay_ms as f64 + (self.max_delay_ms.saturating_sub(self.min_delay_ms) as f64) * random;
+        let delay_wait = history.last_finished_at_ms.map_or(0.0, |finished| finished + delay - now_ms);
+
+        let cap_wait = match self.max_actions_per_minute {
+            Some(cap) if history.started_at_ms.len() >= cap.max(1) as usize => {
+                // The oldest command that must leave the window before this one may start.
+                let blocking = history.started_at_ms[history.started_at_ms.len() - cap.max(1) as usize];
+                blocking + MINUTE_MS - now_ms
+            }
+            _ => 0.0,
+        };
+        delay_wait.max(cap_wait).max(0.0)
+    }
+
+    /// Waits until the next command may start, then records its start.
+    pub async fn before_command(&self) {
+        if !self.is_enabled() {
+            return;
+        }
+        let wait = self.wait_ms(js_sys::Date::now(), js_sys::Math::random());
+        if wait > 0.0 {
+            TimeoutFuture::new(wait.ceil() as u32).await;
+        }
+        self.history.borrow_mut().started_at_ms.push_back(js_sys::Date::now());
+    }
+
+    /// Records the end of a command, from which the delay before the next one is measured.
+    pub fn after_command(&self) {
+        if self.is_enabled() {
+            self.history.borrow_mut().last_finished_at_ms = Some(js_sys::Date::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records a command that started at `started` and finished at `finished`.
+    fn record(pacing: &Pacing, started: f64, finished: f64) {
+        let mut history = pacing.history.borrow_mut();
+        history.started_at_ms.push_back(started);
+        history.last_finished_at_ms = Some(finished);
+    }
+
+    #[test]
+    fn test_delay_between_commands() {
+        let pacing = Pacing::new(100, 400, None);
+        assert_eq!(pacing.wait_ms(1_000.0, 0.5), 0.0, "The first command does not wait");
+        record(&pacing, 1_000.0, 1_050.0);
+        assert_eq!(pacing.wait_ms(1_050.0, 0.0), 100.0);
+        assert_eq!(pacing.wait_ms(1_050.0, 0.5), 250.0);
+        assert_eq!(pacing.wait_ms(1_200.0, 0.0), 0.0, "Time already spent counts toward the delay");
+    }
+
+    #[test]
+    fn test_actions_per_minute_cap() {
+        let pacing = Pacing::new(0, 0, Some(2));
+        record(&pacing, 0.0, 10.0);
+        assert_eq!(pacing.wait_ms(20.0, 0.0), 0.0);
+        record(&pacing, 20.0, 30.0);
+        assert_eq!(pacing.wait_ms(30_000.0, 0.0), 30_000.0, "The third command waits for the first to leave the window");
+        assert_eq!(pacing.wait_ms(60_000.0, 0.0), 0.0);
+        assert_eq!(pacing.history.borrow().started_at_ms.len(), 1, "Commands older than a minute are forgotten");
+    }
+}
@@ -0,0 +1,270 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::console;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use crate::dom_backend;
+use crate::dom_utils::DomError;
+
+/// Maximum number of console entries kept; the oldest are dropped first.
+pub const MAX_CONSOLE_ENTRIES: usize = 500;
+/// Maximum number of page errors recorded per run; later ones are dropped.
+pub const MAX_PAGE_ERRORS: usize = 100;
+/// The `console` methods wrapped by `install_console_hook`.
+const CAPTURED_LEVELS: [&str; 2] = ["error", "warn"];
+
+/// A `console.error` or `console.warn` call made while the capture hook is installed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConsoleEntry {
+    /// `"error"` or `"warn"`.
+    pub level: String,
+    /// The arguments of the call, formatted and separated by spaces.
+    pub message: String,
+    /// `Date.now()` when the call was made.
+    pub logged_at_ms: f64,
+}
+
+type ApplyTrap = Closure<dyn FnMut(JsValue, JsValue, js_sys::Array) -> Result<JsValue, JsValue>>;
+
+thread_local! {
+    // The `apply` traps of the installed proxies, kept alive for the lifetime of the page.
+    static CONSOLE_HOOK: RefCell<Option<Vec<ApplyTrap>>> = const { RefCell::new(None) };
+    // Entries captured since the last `take_console_entries`.
+    static CONSOLE_ENTRIES: RefCell<VecDeque<ConsoleEntry>> = const { RefCell::new(VecDeque::new()) };
+}
+
+// Formats a console argument the way it would read in the devtools console, roughly: strings
+// as is, errors as `Name: message`, other values as JSON when possible.
+fn format_argument(value: &JsValue) -> String {
+    if let Some(text) = value.as_string() {
+        return text;
+    }
+    if let Some(error) = value.dyn_ref::<js_sys::Error>() {
+        return String::from(error.to_string());
+    }
+    js_sys::JSON::stringify(value)
+        .ok()
+        .and_then(|json| json.as_string())
+        .unwrap_or_else(|| format!("{:?}", value))
+}
+
+// Stores a console entry, dropping the oldest one when the buffer is full.
+fn record(level: &str, arguments: &js_sys::Array) {
+    let message = arguments.iter().map(|argument| format_argument(&argument)).collect::<Vec<_>>().join(" ");
+    CONSOLE_ENTRIES.with(|entries| {
+        let mut entries = entries.borrow_mut();
+        if entries.len() == MAX_CONSOLE_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(ConsoleEntry { level: level.to_string(), message, logged_at_ms: js_sys::Date::now() });
+    });
+}
+
+/// `true` once `install_console_hook` has run.
+pub fn console_hook_installed() -> bool {
+    CONSOLE_HOOK.with(|hook| hook.borrow().is_some())
+}
+
+/// Wraps `console.error` and `console.warn` in proxies that record every call before passing it
+/// on, so messages still reach the devtools console. Installing the hook again does nothing.
+///
+/// Messages logged by RustAgent itself (e.g. LLM request failures) are captured too.
+pub fn install_console_hook() -> Result<(), DomError> {
+    if console_hook_installed() {
+        return Ok(());
+    }
+    let console_object = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("console"))?;
+    let mut traps = Vec::with_capacity(CAPTURED_LEVELS.len());
+    for level in CAPTURED_LEVELS {
+        let original = js_sys::Reflect::get(&console_object, &JsValue::from_str(level))?
+            .dyn_into::<js_sys::Function>()
+            .map_err(|_| DomError::JsError { message: format!("console.{} is not a function", level) })?;
+
+        // `apply` trap of the proxy: `console.error(...)` records the call, then forwards it.
+        let apply = Closure::wrap(Box::new(move |target: JsValue, this: JsValue, arguments: js_sys::Array| {
+            record(level, &arguments);
+            js_sys::Reflect::apply(target.unchecked_ref(), &this, &arguments)
+        }) as Box<dyn FnMut(JsValue, JsValue, js_sys::Array) -> Result<JsValue, JsValue>>);
+        let handler = js_sys::Object::new();
+        js_sys::Reflect::set(&handler, &JsValue::from_str("apply"), apply.as_ref())?;
+        js_sys::Reflect::set(&console_object, &JsValue::from_str(level), &js_sys::Proxy::new(&original, &handler))?;
+        traps.push(apply);
+    }
+    CONSOLE_HOOK.with(|hook| *hook.borrow_mut() = Some(traps));
+    console::log_1(&"Console capture hook installed".into());
+    Ok(())
+}
+
+/// Returns the console entries captured since the previous call, oldest first, and forgets them,
+/// so each call only reports what happened since the last check.
+pub fn take_console_entries() -> Vec<ConsoleEntry> {
+    CONSOLE_ENTRIES.with(|entries| entries.borrow_mut().drain(..).collect())
+}
+
+/// Returns the console errors and warnings captured since the previous call as a JSON array of
+/// `ConsoleEntry`.
+///
+/// # Arguments
+/// * `fail_on_error`: If `true`, an `Err` is returned instead when at least one `console.error`
+///   call was captured, so that a workflow stops at the step that produced it.
+pub fn get_console_errors(fail_on_error: bool) -> Result<String, DomError> {
+    if !console_hook_installed() {
+        return Err(DomError::JsError {
+            message: "Console capture is not enabled. Call enable_console_capture first.".to_string(),
+        });
+    }
+    let entries = take_console_entries();
+    let errors: Vec<&ConsoleEntry> = entries.iter().filter(|entry| entry.level == "error").collect();
+    if fail_on_error && !errors.is_empty() {
+        return Err(DomError::JsError {
+            message: format!("The page logged {} console error(s), the first being: {}", errors.len(), errors[0].message),
+        });
+    }
+    serde_json::to_string(&entries).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+/// An uncaught exception or unhandled promise rejection raised by the page during a run.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PageError {
+    /// `"error"` for uncaught exceptions, `"unhandledrejection"` for rejected promises.
+    pub kind: String,
+    /// The error message, or the formatted rejection reason.
+    pub message: String,
+    /// Script URL, line and column of an uncaught exception, when the browser reports them.
+    pub source: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// `Date.now()` when the error was raised.
+    pub occurred_at_ms: f64,
+}
+
+/// Records the page's uncaught exceptions and unhandled promise rejections while it is alive.
+///
+/// A monitor is started for every run and its errors are attached to the run's result envelope,
+/// so that a run whose commands succeeded but crashed the app can be told apart. The listeners
+/// are removed when the monitor is dropped.
+pub struct PageErrorMonitor {
+    window: web_sys::Window,
+    errors: Rc<RefCell<Vec<PageError>>>,
+    on_error: Closure<dyn FnMut(web_sys::ErrorEvent)>,
+    on_rejection: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+// Stores a page error unless the run already recorded `MAX_PAGE_ERRORS`.
+fn push_page_error(errors: &RefCell<Vec<PageError>>, error: PageError) {
+    let mut errors = errors.borrow_mut();
+    if errors.len() < MAX_PAGE_ERRORS {
+        errors.push(error);
+    }
+}
+
+impl PageErrorMonitor {
+    /// Starts listening for `error` and `unhandledrejection` events on the window.
+    pub fn start() -> Result<Self, DomError> {
+        let window = dom_backend::window().ok_or_else(|| DomError::JsError { message: "Failed to get window object".to_string() })?;
+        let errors: Rc<RefCell<Vec<PageError>>> = Rc::default();
+
+        let error_list = errors.clone();
+        let on_error = Closure::wrap(Box::new(move |event: web_sys::ErrorEvent| {
+            push_page_error(&error_list, PageError {
+                kind: "error".to_string(),
+                message: event.message(),
+                source: Some(event.filename()).filter(|filename| !filename.is_empty()),
+                line: Some(event.lineno()).filter(|&line| line > 0),
+                column: Some(event.colno()).filter(|&column| column > 0),
+                occurred_at_ms: js_sys::Date::now(),
+            });
+        }) as Box<dyn FnMut(web_sys::ErrorEvent)>);
+
+        // `PromiseRejectionEvent` is read through `Reflect`, its reason being any value.
+        let rejection_list = errors.clone();
+        let on_rejection = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            let reason = js_sys::Reflect::get(&event, &JsValue::from_str("reason")).unwrap_or(JsValue::UNDEFINED);
+            push_page_error(&rejection_list, PageError {
+                kind: "unhandledrejection".to_string(),
+                message: format_argument(&reason),
+                source: None,
+                line: None,
+                column: None,
+                occurred_at_ms: js_sys::Date::now(),
+            });
+        }) as Box<dyn FnMut(web_sys::Event)>);
+
+        window.add_event_listener_with_callback("error", on_error.as_ref().unchecked_ref())?;
+        window.add_event_listener_with_callback("unhandledrejection", on_rejection.as_ref().unchecked_ref())?;
+        Ok(PageErrorMonitor { window, errors, on_error, on_rejection })
+    }
+
+    /// The errors recorded so far, in the order they were raised.
+    pub fn errors(&self) -> Vec<PageError> {
+        self.errors.borrow().clone()
+    }
+}
+
+impl Drop for PageErrorMonitor {
+    fn drop(&mut self) {
+        let _ = self.window.remove_event_listener_with_callback("error", self.on_error.as_ref().unchecked_ref());
+        let _ = self
+            .window
+            .remove_event_listener_with_callback("unhandledrejection", self.on_rejection.as_ref().unchecked_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_console_hook_captures_errors_and_warnings() {
+        install_console_hook().unwrap();
+        install_console_hook().unwrap(); // Idempotent
+        take_console_entries();
+
+        console::warn_2(&"Deprecated API".into(), &js_sys::JSON::parse(r#"{"id":7}"#).unwrap());
+        console::log_1(&"Not captured".into());
+        let entries: serde_json::Value = serde_json::from_str(&get_console_errors(true).unwrap()).unwrap();
+        assert_eq!(entries.as_array().unwrap().len(), 1, "Warnings do not fail the check");
+        assert_eq!(entries[0]["level"], "warn");
+        assert_eq!(entries[0]["message"], r#"Deprecated API {"id":7}"#);
+        assert_eq!(get_console_errors(false).unwrap(), "[]", "Entries are only reported once");
+
+        console::error_1(&js_sys::Error::new("Boom").into());
+        let error = get_console_errors(true).unwrap_err();
+        assert!(error.to_string().contains("1 console error(s), the first being: Error: Boom"), "Unexpected error: {}", error);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_page_error_monitor_records_errors_and_rejections() {
+        let window = web_sys::window().unwrap();
+        let monitor = PageErrorMonitor::start().unwrap();
+
+        let init = web_sys::ErrorEventInit::new();
+        init.set_message("Uncaught TypeError: cart is undefined");
+        init.set_filename("https://shop.example/app.js");
+        init.set_lineno(42);
+        window.dispatch_event(&web_sys::ErrorEvent::new_with_event_init_dict("error", &init).unwrap()).unwrap();
+
+        let rejection = web_sys::Event::new("unhandledrejection").unwrap();
+        js_sys::Reflect::set(&rejection, &"reason".into(), &js_sys::Error::new("Payment API down")).unwrap();
+        window.dispatch_event(&rejection).unwrap();
+
+        let errors = monitor.errors();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].kind, "error");
+        assert_eq!(errors[0].message, "Uncaught TypeError: cart is undefined");
+        assert_eq!(errors[0].source.as_deref(), Some("https://shop.example/app.js"));
+        assert_eq!((errors[0].line, errors[0].column), (Some(42), None));
+        assert_eq!(errors[1].kind, "unhandledrejection");
+        assert_eq!(errors[1].message, "Error: Payment API down");
+
+        let errors = monitor.errors.clone();
+        drop(monitor);
+        window.dispatch_event(&rejection).unwrap();
+        assert_eq!(errors.borrow().len(), 2, "Listeners are removed when the monitor is dropped");
+    }
+}
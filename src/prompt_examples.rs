@@ -0,0 +1,172 @@
+use serde_json::Value;
+use crate::locale;
+
+/// Characters of examples a prompt includes by default (see `PromptExamples::set_max_chars`).
+pub const DEFAULT_MAX_CHARS: usize = 2000;
+/// Examples less similar than this to the task (see `locale::similarity`) are never included.
+const MIN_SIMILARITY: f64 = 0.3;
+
+/// A task with the command array that carries it out on the embedding site, shown to the LLM as
+/// an example when it is given a similar task.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptExample {
+    pub task: String,
+    /// The command array, as compact JSON.
+    pub commands: String,
+}
+
+impl PromptExample {
+    // The example as written in the prompt, with the task quoted like the task being asked.
+    fn render(&self) -> String {
+        let quoted_task = serde_json::to_string(&self.task).unwrap_or_else(|_| format!("\"{}\"", self.task));
+        format!("Task: {}\nResponse: {}\n", quoted_task, self.commands)
+    }
+}
+
+/// The examples added with `RustAgent::add_prompt_example`, and how many characters of them a
+/// prompt may include.
+#[derive(Debug, Clone)]
+pub struct PromptExamples {
+    examples: Vec<PromptExample>,
+    max_chars: usize,
+}
+
+impl Default for PromptExamples {
+    fn default() -> Self {
+        PromptExamples { examples: Vec::new(), max_chars: DEFAULT_MAX_CHARS }
+    }
+}
+
+impl PromptExamples {
+    /// Adds an example, replacing any earlier one for the same task.
+    ///
+    /// # Returns
+    /// `Ok(())`, or an error if `task` is empty or `commands` is not a non-empty array.
+    pub fn add(&mut self, task: &str, commands: &Value) -> Result<(), String> {
+        let task = task.trim();
+        if task.is_empty() {
+            return Err("An example needs a task.".to_string());
+        }
+        if commands.as_array().is_none_or(Vec::is_empty) {
+            return Err(format!("The commands of the example for '{}' must be a non-empty JSON array.", task));
+        }
+        let example = PromptExample { task: task.to_string(), commands: commands.to_string() };
+        match self.examples.iter_mut().find(|existing| existing.task == example.task) {
+            Some(existing) => *existing = example,
+            None => self.examples.push(example),
+        }
+        Ok(())
+    }
+
+    /// Removes every example.
+    pub fn clear(&mut self) {
+        self.examples.clear();
+    }
+
+    /// Sets how many characters of examples a prompt may include. `0` leaves examples out.
+    pub fn set_max_chars(&mut self, max_chars: usize) {
+        self.max_chars = max_chars;
+    }
+
+    /// The examples to show with `task`: first those in `recalled` (see `memory::ExperienceMemory::recall`),
+    /// then the most similar added ones, as long as they fit in the character budget. Examples that
+    /// do not fit are skipped in favour of shorter, less similar ones.
+    pub fn select<'a>(&'a self, task: &str, recalled: &'a [PromptExample]) -> Vec<&'a PromptExample> {
+        let mut scored: Vec<(f64, &PromptExample)> = self
+            .examples
+            .iter()
+            .filter(|example| !recalled.iter().any(|remembered| remembered.task == example.task))
+            .map(|example| (locale::similarity(task, &example.task), example))
+            .filter(|(score, _)| *score >= MIN_SIMILARITY)
+            .collect();
+        // Stable, so that equally similar examples keep the order they were added in.
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        let mut remaining = self.max_chars;
+        recalled
+            .iter()
+            .chain(scored.into_iter().map(|(_, example)| example))
+            .filter(|example| {
+                let length = example.render().chars().count();
+                let fits = length <= remaining;
+                if fits {
+                    remaining -= length;
+                }
+                fits
+            })
+            .collect()
+    }
+
+    /// The part of the prompt showing the examples selected for `task`, or an empty string if none is.
+    pub fn prompt_section(&self, task: &str, recalled: &[PromptExample]) -> String {
+        let selected = self.select(task, recalled);
+        if selected.is_empty() {
+            return String::new();
+        }
+        let rendered: Vec<String> = selected.iter().map(|example| example.render()).collect();
+        format!("Examples of tasks on this site with the responses they need:\n{}\n", rendered.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn examples() -> PromptExamples {
+        let mut examples = PromptExamples::default();
+        examples.add("Add the first pizza to the cart", &json!([{"action": "CLICK", "selector": "css:.pizza:first-child .add"}])).unwrap();
+        examples.add("Open the order history", &json!([{"action": "CLICK", "selector": "css:#account"}, {"action": "CLICK", "selector": "text:Orders"}])).unwrap();
+        examples
+    }
+
+    #[test]
+    fn test_add_validates_and_replaces() {
+        let mut examples = examples();
+        assert!(examples.add(" ", &json!([{"action": "CLICK", "selector": "css:#a"}])).is_err());
+        assert!(examples.add("Open the menu", &json!([])).is_err());
+        assert!(examples.add("Open the menu", &json!({"action": "CLICK"})).is_err());
+        examples.add("Open the order history", &json!([{"action": "NAVIGATE", "selector": "", "value": "/orders"}])).unwrap();
+        assert_eq!(examples.examples.len(), 2);
+        assert!(examples.examples[1].commands.contains("/orders"));
+    }
+
+    #[test]
+    fn test_select_by_similarity() {
+        let examples = examples();
+        let selected = examples.select("Add the second pizza to the cart", &[]);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].task, "Add the first pizza to the cart");
+        assert!(examples.select("What is the weather like?", &[]).is_empty());
+        assert_eq!(examples.prompt_section("What is the weather like?", &[]), "");
+
+        let section = examples.prompt_section("open my order history", &[]);
+        assert!(section.starts_with("Examples of tasks on this site"));
+        assert!(section.contains("Task: \"Open the order history\"\nResponse: [{\"action\":\"CLICK\""));
+    }
+
+    #[test]
+    fn test_select_fits_the_budget() {
+        let mut examples = examples();
+        examples.add("Add the first pizza to the cart and check out", &json!([{"action": "CLICK", "selector": "css:.pizza:first-child .add"}, {"action": "CLICK", "selector": "css:#checkout-with-a-long-selector"}])).unwrap();
+        let task = "Add the first pizza to the cart and check out";
+        assert_eq!(examples.select(task, &[]).len(), 2);
+
+        // Only the shorter, less similar example fits.
+        let short = examples.examples[0].render().chars().count();
+        examples.set_max_chars(short);
+        assert_eq!(examples.select(task, &[]).iter().map(|example| example.task.as_str()).collect::<Vec<_>>(), ["Add the first pizza to the cart"]);
+        examples.set_max_chars(0);
+        assert!(examples.select(task, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_recalled_examples_come_first() {
+        let examples = examples();
+        let recalled = [
+            PromptExample { task: "Order a large pizza".to_string(), commands: r#"[{"action":"CLICK","selector":"css:#large"}]"#.to_string() },
+            PromptExample { task: "Add the first pizza to the cart".to_string(), commands: r#"[{"action":"CLICK","selector":"css:#first"}]"#.to_string() },
+        ];
+        let selected = examples.select("Add the second pizza to the cart", &recalled);
+        assert_eq!(selected, [&recalled[0], &recalled[1]], "Added examples for a recalled task are left out");
+    }
+}
@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use crate::llm_error::{classify_response, LlmError};
+
+// A provider registered with `set_loader`: the loader, and the promise of the provider function
+// once the loader was called.
+struct ProviderSlot {
+    loader: js_sys::Function,
+    loaded: Option<js_sys::Promise>,
+}
+
+thread_local! {
+    // Like fixtures, the provider applies to every agent of the page (each worker has its own).
+    static PROVIDER: RefCell<Option<ProviderSlot>> = const { RefCell::new(None) };
+}
+
+/// Registers the loader of the LLM provider, or removes the provider (`None`). The loader is
+/// called on the first LLM call, not now, and returns (or resolves to) the provider function, so
+/// that hosts can `import()` provider code only once it is needed.
+pub fn set_loader(loader: Option<js_sys::Function>) {
+    PROVIDER.with(|provider| *provider.borrow_mut() = loader.map(|loader| ProviderSlot { loader, loaded: None }));
+}
+
+/// Whether a provider is registered.
+pub fn is_registered() -> bool {
+    PROVIDER.with(|provider| provider.borrow().is_some())
+}
+
+// A readable message for a value thrown or rejected by host code.
+fn describe(value: &JsValue) -> String {
+    value
+        .as_string()
+        .or_else(|| value.dyn_ref::<js_sys::Error>().map(|error| String::from(error.message())))
+        .or_else(|| js_sys::JSON::stringify(value).ok().and_then(|json| json.as_string()))
+        .unwrap_or_else(|| format!("{:?}", value))
+}
+
+// Classifies a rejection of the provider like an error response of the built-in client when it
+// has a numeric `status` (and optionally `body` and `retry_after`), e.g. `{status: 429, body}`.
+fn classify_rejection(rejection: &JsValue) -> LlmError {
+    let field = |name: &str| js_sys::Reflect::get(rejection, &JsValue::from_str(name)).ok().filter(|value| !value.is_undefined());
+    match field("status").and_then(|status| status.as_f64()) {
+        Some(status) => {
+            let body = field("body").map(|body| describe(&body)).unwrap_or_else(|| describe(rejection));
+            let retry_after = field("retry_after").map(|retry_after| describe(&retry_after));
+            classify_response(status as u16, retry_after.as_deref(), &body)
+        }
+        None => LlmError::Other(format!("The LLM provider failed: {}", describe(rejection))),
+    }
+}
+
+// The provider function, loading it on first use. A failed load is retried by the next call.
+async fn provider() -> Option<Result<js_sys::Function, LlmError>> {
+    let loaded = PROVIDER.with(|provider| {
+        let mut provider = provider.borrow_mut();
+        let slot = provider.as_mut()?;
+        if let Some(loaded) = &slot.loaded {
+            return Some(Ok(loaded.clone()));
+        }
+        web_sys::console::log_1(&"Loading the LLM provider".into());
+        let loaded = slot.loader.call0(&JsValue::NULL).map(|value| js_sys::Promise::resolve(&value));
+        if let Ok(loaded) = &loaded {
+            slot.loaded = Some(loaded.clone());
+        }
+        Some(loaded)
+    })?;
+    let loaded = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => return Some(Err(LlmError::Other(format!("Loading the LLM provider failed: {}", describe(&e))))),
+    };
+    Some(match JsFuture::from(loaded.clone()).await {
+        Ok(function) => function
+            .dyn_into::<js_sys::Function>()
+            .map_err(|_| LlmError::Other("The LLM provider loader must return a function".to_string())),
+        Err(e) => {
+            PROVIDER.with(|provider| {
+                if let Some(slot) = provider.borrow_mut().as_mut().filter(|slot| slot.loaded.as_ref().is_some_and(|current| js_sys::Object::is(current, &loaded))) {
+                    slot.loaded = None;
+                }
+            });
+            Err(LlmError::Other(format!("Loading the LLM provider failed: {}", describe(&e))))
+        }
+    })
+}
+
+/// Sends `prompt` to the registered provider as `{prompt, model, api_url, api_key}`.
+///
+/// # Returns
+/// `None` if no provider is registered, otherwise the string the provider returned or resolved
+/// to, or why it failed (see `classify_rejection`).
+pub async fn complete(prompt: &str, api_key: &str, api_url: &str, model_name: &str) -> Option<Result<String, LlmError>> {
+    let provider = match provider().await? {
+        Ok(provider) => provider,
+        Err(e) => return Some(Err(e)),
+    };
+    let request = serde_json::json!({ "prompt": prompt, "model": model_name, "api_url": api_url, "api_key": api_key });
+    let response = match js_sys::JSON::parse(&request.to_string()).and_then(|request| provider.call1(&JsValue::NULL, &request)) {
+        Ok(response) => JsFuture::from(js_sys::Promise::resolve(&response)).await,
+        Err(e) => Err(e),
+    };
+    Some(match response {
+        Ok(response) => response
+            .as_string()
+            .ok_or_else(|| LlmError::Other(format!("The LLM provider must return a string, not {}", describe(&response)))),
+        Err(rejection) => Err(classify_rejection(&rejection)),
+    })
+}
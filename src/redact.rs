@@ -0,0 +1,203 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::collections::BTreeMap;
+use crate::dom_utils::DomError;
+
+/// Pattern matching email addresses.
+pub const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+/// Pattern matching candidate card numbers (13 to 19 digits, optionally grouped by spaces or
+/// dashes). Only candidates passing the Luhn check are redacted.
+pub const CREDIT_CARD_PATTERN: &str = r"\b\d(?:[ -]?\d){12,18}\b";
+
+/// Which values are replaced by placeholders before text is sent to the LLM.
+/// Nothing is redacted by default.
+///
+/// Parsed from JSON such as
+/// `{"emails": true, "credit_cards": true, "patterns": {"PHONE": "\\+?\\d[\\d ]{8,}\\d"}}`,
+/// where `patterns` maps placeholder labels to JavaScript regular expressions.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct RedactionConfig {
+    /// Whether email addresses are redacted (as `[EMAIL_1]`, `[EMAIL_2]`, ...).
+    pub emails: bool,
+    /// Whether card numbers are redacted (as `[CREDIT_CARD_1]`, ...).
+    pub credit_cards: bool,
+    /// Custom patterns, by label. Matches are redacted as `[<LABEL>_1]`, ...
+    pub patterns: BTreeMap<String, String>,
+}
+
+/// The placeholders substituted by `RedactionConfig::redact`, with the values they replace.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RedactionMap {
+    entries: Vec<(String, String)>,
+}
+
+impl RedactionMap {
+    /// Returns the placeholder for `original`, creating one labelled `label` the first time a
+    /// value is seen, so the same value always gets the same placeholder.
+    fn placeholder_for(&mut self, label: &str, original: &str) -> String {
+        if let Some((placeholder, _)) = self.entries.iter().find(|(_, value)| value == original) {
+            return placeholder.clone();
+        }
+        let prefix = format!("[{}_", label);
+        let number = self.entries.iter().filter(|(placeholder, _)| placeholder.starts_with(&prefix)).count() + 1;
+        let placeholder = format!("{}{}]", prefix, number);
+        self.entries.push((placeholder.clone(), original.to_string()));
+        placeholder
+    }
+
+    /// `true` if nothing was redacted.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Puts the original values back in place of the placeholders found in `text`.
+    pub fn restore(&self, text: &str) -> String {
+        self.entries
+            .iter()
+            .fold(text.to_string(), |text, (placeholder, original)| text.replace(placeholder, original))
+    }
+
+    /// Same as `restore`, for every string in a JSON value (e.g. an LLM command array), so that
+    /// restored values never need escaping.
+    pub fn restore_json(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.restore(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.restore_json(item)),
+            Value::Object(fields) => fields.values_mut().for_each(|field| self.restore_json(field)),
+            _ => {}
+        }
+    }
+}
+
+/// Luhn checksum used to tell card numbers from other long digit sequences.
+/// Spaces and dashes are ignored.
+pub fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if digits.len() < 13 {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+// Compiles `pattern` as a global JavaScript regular expression. `RegExp` is constructed through
+// `Reflect` so that an invalid pattern is reported as an error instead of throwing.
+fn compile(pattern: &str) -> Result<js_sys::RegExp, DomError> {
+    let constructor = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("RegExp"))?
+        .dyn_into::<js_sys::Function>()
+        .map_err(DomError::from)?;
+    let args = js_sys::Array::of2(&JsValue::from_str(pattern), &JsValue::from_str("g"));
+    Ok(js_sys::Reflect::construct(&constructor, &args)?.unchecked_into())
+}
+
+impl RedactionConfig {
+    /// `true` if any pattern is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.emails || self.credit_cards || !self.patterns.is_empty()
+    }
+
+    /// Checks that every custom pattern is a valid regular expression and every label is usable
+    /// in a placeholder.
+    pub fn validate(&self) -> Result<(), String> {
+        for (label, pattern) in &self.patterns {
+            if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("Invalid redaction label '{}'. Use letters, digits and underscores.", label));
+            }
+            compile(pattern).map_err(|e| format!("Invalid redaction pattern for '{}': {}", label, e))?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the configured values in `text` by placeholders, recording them in `map`.
+    /// Sharing `map` between texts keeps placeholders consistent across them.
+    pub fn redact(&self, text: &str, map: &mut RedactionMap) -> Result<String, DomError> {
+        let mut rules: Vec<(String, &str)> = Vec::new();
+        if self.emails {
+            rules.push(("EMAIL".to_string(), EMAIL_PATTERN));
+        }
+        if self.credit_cards {
+            rules.push(("CREDIT_CARD".to_string(), CREDIT_CARD_PATTERN));
+        }
+        rules.extend(self.patterns.iter().map(|(label, pattern)| (label.to_uppercase(), pattern.as_str())));
+
+        let shared_map = Rc::new(RefCell::new(std::mem::take(map)));
+        let mut text = js_sys::JsString::from(text);
+        for (label, pattern) in rules {
+            let regexp = compile(pattern)?;
+            let check_luhn = pattern == CREDIT_CARD_PATTERN;
+            let closure_map = shared_map.clone();
+            let replace = Closure::wrap(Box::new(move |matched: String| -> String {
+                if check_luhn && !luhn_valid(&matched) {
+                    return matched;
+                }
+                closure_map.borrow_mut().placeholder_for(&label, &matched)
+            }) as Box<dyn FnMut(String) -> String>);
+            text = text.replace_by_pattern_with_function(&regexp, replace.as_ref().unchecked_ref());
+        }
+        *map = shared_map.take();
+        Ok(text.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_luhn_valid() {
+        assert!(luhn_valid("4111 1111 1111 1111"));
+        assert!(luhn_valid("5500-0000-0000-0004"));
+        assert!(!luhn_valid("4111 1111 1111 1112"));
+        assert!(!luhn_valid("1234"), "Too short to be a card number");
+    }
+
+    #[test]
+    fn test_redaction_map_restores_text_and_json() {
+        let mut map = RedactionMap::default();
+        assert_eq!(map.placeholder_for("EMAIL", "ann@example.com"), "[EMAIL_1]");
+        assert_eq!(map.placeholder_for("EMAIL", "bob@example.com"), "[EMAIL_2]");
+        assert_eq!(map.placeholder_for("EMAIL", "ann@example.com"), "[EMAIL_1]", "Same value, same placeholder");
+        assert_eq!(map.restore("Mail [EMAIL_2] and [EMAIL_1]"), "Mail bob@example.com and ann@example.com");
+
+        let mut commands = json!([{"action": "TYPE", "selector": "#to", "value": "[EMAIL_1]"}]);
+        map.restore_json(&mut commands);
+        assert_eq!(commands[0]["value"], "ann@example.com");
+    }
+
+    #[test]
+    fn test_redaction_config_parsing() {
+        let config: RedactionConfig = serde_json::from_str(r#"{"emails": true}"#).unwrap();
+        assert!(config.emails && !config.credit_cards && config.is_enabled());
+        assert!(!RedactionConfig::default().is_enabled());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_redact_builtin_and_custom_patterns() {
+        let mut patterns = BTreeMap::new();
+        patterns.insert("order".to_string(), r"ORD-\d+".to_string());
+        let config = RedactionConfig { emails: true, credit_cards: true, patterns };
+        let mut map = RedactionMap::default();
+        let text = "Send ORD-42 to ann@example.com, pay with 4111 1111 1111 1111, ref 1234567890123";
+        let redacted = config.redact(text, &mut map).unwrap();
+        assert_eq!(redacted, "Send [ORDER_1] to [EMAIL_1], pay with [CREDIT_CARD_1], ref 1234567890123");
+        assert_eq!(map.restore(&redacted), text);
+
+        let invalid = RedactionConfig { patterns: [("X".to_string(), "(".to_string())].into_iter().collect(), ..Default::default() };
+        assert!(invalid.validate().is_err());
+        assert!(config.validate().is_ok());
+    }
+}
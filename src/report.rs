@@ -0,0 +1,210 @@
+use wasm_bindgen::prelude::*;
+use web_sys::console;
+use serde_json::{json, Value};
+use crate::dom_backend;
+use crate::history::RunHistory;
+use crate::page_errors::PageError;
+use crate::result_code::TaskResult;
+use crate::result_schema::{ResultSchema, RESULT_SCHEMA_VERSION};
+use crate::run_summary::RunSummary;
+use crate::soft_assert::AssertionReport;
+
+/// Value of the `source` field of every result envelope, so that `message` listeners can tell
+/// RustAgent reports apart from other messages.
+pub const ENVELOPE_SOURCE: &str = "rustagent";
+
+/// Where the result envelope of a finished run is reported. Reporting is disabled by default;
+/// finished runs are always recorded in the run history.
+#[derive(Debug, Clone, Default)]
+pub struct ResultReporting {
+    /// URL the envelope is POSTed to as JSON.
+    pub webhook_url: Option<String>,
+    /// Target origin used to `postMessage` the envelope to the parent frame (`"*"` for any origin).
+    pub post_message_origin: Option<String>,
+    /// The history of finished runs, shared by every copy of the reporting configuration.
+    pub history: RunHistory,
+    /// The shape results are returned and reported in.
+    pub schema: ResultSchema,
+}
+
+/// Builds the envelope reported when a run finishes, e.g.
+/// `{"source":"rustagent","schema_version":3,"id":"run-1718000000000-3","run":"workflow:login","success":true,"started_at_ms":...,"finished_at_ms":...,"results":[{"Ok":"...","code":"CLICK_OK"}],"summary":{...},"page_errors":[]}`,
+/// in the current result schema version (see `result_schema`).
+///
+/// `id` identifies the run, e.g. to report feedback on it (see `history::new_run_id`).
+/// `run` identifies what was run: `automate`, `workflow:<name>`, `schedule` or `trigger:<workflow>`.
+/// `success` is `true` if every task succeeded; `results` has the same shape as the result of `automate`.
+/// `summary` counts the results and skipped tasks, and gives the duration of the run, which ended at
+/// `finished_at_ms`, and its first error (see `run_summary::summarize`).
+/// `page_errors` lists the uncaught exceptions and unhandled rejections the page raised during the
+/// run; they do not affect `success`, since the commands themselves may have worked.
+/// `assertion_report` is added for runs in soft-assert mode; `success` is then also `false` if an
+/// assertion did not hold, although its task succeeded.
+pub fn envelope(
+    id: &str,
+    run: &str,
+    started_at_ms: f64,
+    results: &[TaskResult],
+    summary: &RunSummary,
+    page_errors: &[PageError],
+    assertion_report: Option<&AssertionReport>,
+) -> Value {
+    let assertions_held = assertion_report.is_none_or(|report| report.failed == 0);
+    let mut envelope = json!({
+        "source": ENVELOPE_SOURCE,
+        "schema_version": RESULT_SCHEMA_VERSION,
+        "id": id,
+        "run": run,
+        "success": assertions_held && summary.failed == 0,
+        "started_at_ms": started_at_ms,
+        "finished_at_ms": started_at_ms + summary.duration_ms,
+        "results": results,
+        "summary": summary,
+        "page_errors": page_errors,
+    });
+    if let Some(report) = assertion_report {
+        envelope["assertion_report"] = json!(report);
+    }
+    envelope
+}
+
+impl ResultReporting {
+    /// Sends `envelope` to the configured targets, in the pinned result schema version.
+    ///
+    /// Reporting never fails the run: errors are logged. The webhook request is sent in the
+    /// background, so a slow endpoint does not delay the result returned to the caller.
+    pub fn report(&self, envelope: &Value) {
+        let envelope = &self.schema.envelope(envelope);
+        if let Some(origin) = &self.post_message_origin {
+            if let Err(e) = post_to_parent(envelope, origin) {
+                console::warn_1(&format!("Failed to postMessage the result envelope: {:?}", e.as_string()).into());
+            }
+        }
+        if let Some(url) = &self.webhook_url {
+            let (url, body) = (url.clone(), envelope.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                match post_to_webhook(&url, &body).await {
+                    Ok(status) if (200..300).contains(&status) => {
+                        console::log_1(&format!("Result envelope posted to {}", url).into());
+                    }
+                    Ok(status) => console::warn_1(&format!("Result webhook {} answered {}", url, status).into()),
+                    Err(e) => console::warn_1(&format!("Failed to post the result envelope to {}: {}", url, e).into()),
+                }
+            });
+        }
+    }
+}
+
+// POSTs the envelope to the webhook as JSON and returns the HTTP status of the response.
+#[cfg(feature = "http-llm")]
+async fn post_to_webhook(url: &str, body: &Value) -> Result<u16, String> {
+    let response = reqwest::Client::new().post(url).json(body).send().await.map_err(|e| e.to_string())?;
+    Ok(response.status().as_u16())
+}
+
+// Same, through the global `fetch`, in builds without the HTTP client of the `http-llm` feature.
+#[cfg(not(feature = "http-llm"))]
+async fn post_to_webhook(url: &str, body: &Value) -> Result<u16, String> {
+    let describe = |e: JsValue| e.as_string().unwrap_or_else(|| format!("{:?}", e));
+    let fetch = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("fetch"))
+        .ok()
+        .and_then(|fetch| fetch.dyn_into::<js_sys::Function>().ok())
+        .ok_or_else(|| "fetch is not available".to_string())?;
+    let init = json!({ "method": "POST", "headers": { "Content-Type": "application/json" }, "body": body.to_string() });
+    let init = js_sys::JSON::parse(&init.to_string()).map_err(describe)?;
+    let response = fetch.call2(&JsValue::NULL, &JsValue::from_str(url), &init).map_err(describe)?;
+    let response = wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&response)).await.map_err(describe)?;
+    js_sys::Reflect::get(&response, &JsValue::from_str("status"))
+        .ok()
+        .and_then(|status| status.as_f64())
+        .map(|status| status as u16)
+        .ok_or_else(|| "fetch resolved to no response".to_string())
+}
+
+// Posts the envelope, as a structured object, to the parent frame (or to the page itself when
+// it is not framed, where the host page or an extension content script can listen for it).
+fn post_to_parent(envelope: &Value, target_origin: &str) -> Result<(), JsValue> {
+    let window = dom_backend::window().ok_or_else(|| JsValue::from_str("Failed to get window object"))?;
+    let target = window.parent()?.unwrap_or(window);
+    let message = js_sys::JSON::parse(&envelope.to_string())?;
+    target.post_message(&message, target_origin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_summary;
+    use crate::LibError;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_envelope_reports_overall_success() {
+        let results = vec![
+            TaskResult::ok("Clicked".to_string(), "CLICK_OK".to_string()),
+            TaskResult::err(LibError::LlmCall { message: "timeout".to_string() }),
+        ];
+        let page_errors = vec![PageError {
+            kind: "error".to_string(),
+            message: "Uncaught TypeError: cart is undefined".to_string(),
+            source: Some("https://shop.example/app.js".to_string()),
+            line: Some(42),
+            column: Some(7),
+            occurred_at_ms: 2.0,
+        }];
+        let summary = run_summary::summarize(&[], &results, &[], 1.5);
+        let value = envelope("run-1-1", "workflow:login", 1.0, &results, &summary, &page_errors, None);
+        assert_eq!(value["source"], "rustagent");
+        assert_eq!(value["schema_version"], RESULT_SCHEMA_VERSION);
+        assert_eq!(value["id"], "run-1-1");
+        assert_eq!(value["run"], "workflow:login");
+        assert_eq!(value["success"], false);
+        assert_eq!(value["finished_at_ms"], 2.5);
+        assert_eq!(value["results"][0]["Ok"], "Clicked");
+        assert_eq!(value["results"][1]["Err"]["error_type"], "LlmCall");
+        assert_eq!(value["results"][1]["code"], "LLM_CALL_FAILED");
+        assert_eq!(value["summary"]["failed"], 1);
+        assert_eq!(value["summary"]["first_error"]["code"], "LLM_CALL_FAILED");
+        assert_eq!(value["page_errors"][0]["message"], "Uncaught TypeError: cart is undefined");
+        assert_eq!(value["page_errors"][0]["line"], 42);
+        let summary = run_summary::summarize(&[], &results[..1], &[], 0.0);
+        let value = envelope("run-0-2", "automate", 0.0, &results[..1], &summary, &page_errors, None);
+        assert_eq!(value["success"], true, "Page errors do not make a run fail");
+        assert!(value.get("assertion_report").is_none());
+
+        let assertion_report = AssertionReport {
+            checked: 2,
+            failed: 1,
+            failures: vec![crate::soft_assert::AssertionFailure { assertion: "ASSERT_JSON $".to_string(), message: "...".to_string() }],
+        };
+        let value = envelope("run-0-4", "automate", 0.0, &results[..1], &summary, &[], Some(&assertion_report));
+        assert_eq!(value["success"], false, "A failed soft assertion makes the run fail");
+        assert_eq!(value["assertion_report"]["failures"][0]["assertion"], "ASSERT_JSON $");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_report_posts_message_to_parent() {
+        let window = web_sys::window().unwrap();
+        let received = Rc::new(RefCell::new(None));
+        let received_clone = received.clone();
+        let listener = Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
+            let data = js_sys::JSON::stringify(&event.data()).unwrap().as_string().unwrap();
+            *received_clone.borrow_mut() = Some(data);
+        }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+        window.add_event_listener_with_callback("message", listener.as_ref().unchecked_ref()).unwrap();
+
+        let reporting = ResultReporting { webhook_url: None, post_message_origin: Some("*".to_string()), ..Default::default() };
+        let results = [TaskResult::ok("done".to_string(), "CLICK_OK".to_string())];
+        reporting.report(&envelope("run-0-3", "automate", 0.0, &results, &run_summary::summarize(&[], &results, &[], 1.0), &[], None));
+        gloo_timers::future::TimeoutFuture::new(50).await;
+
+        let data: Value = serde_json::from_str(received.borrow().as_deref().expect("No message received")).unwrap();
+        assert_eq!(data["source"], ENVELOPE_SOURCE);
+        assert_eq!(data["results"][0]["Ok"], "done");
+        window.remove_event_listener_with_callback("message", listener.as_ref().unchecked_ref()).unwrap();
+    }
+}
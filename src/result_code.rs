@@ -0,0 +1,127 @@
+use crate::LibError;
+use serde::{Deserialize, Serialize};
+
+/// Code of an answer of the LLM to a task it planned no commands for.
+#[cfg(not(feature = "dom-only"))]
+pub const LLM_NATURAL_RESPONSE: &str = "LLM_NATURAL_RESPONSE";
+/// Code of a task planned by the LLM whose commands all succeeded.
+#[cfg(not(feature = "dom-only"))]
+pub const LLM_PLAN_OK: &str = "LLM_PLAN_OK";
+/// Code of a task planned by the LLM some of whose commands failed; its result says which.
+#[cfg(not(feature = "dom-only"))]
+pub const LLM_PLAN_PARTIAL: &str = "LLM_PLAN_PARTIAL";
+/// Code of an `LLM_DECIDE` question the LLM answered.
+#[cfg(not(feature = "dom-only"))]
+pub const LLM_DECIDE_OK: &str = "LLM_DECIDE_OK";
+/// Code of an `LLM_ASSERT` question the LLM answered with yes.
+#[cfg(not(feature = "dom-only"))]
+pub const LLM_ASSERT_OK: &str = "LLM_ASSERT_OK";
+/// Code of a task an assertion of which did not hold in soft-assert mode (see `soft_assert`).
+pub const SOFT_ASSERTION_FAILED: &str = "SOFT_ASSERTION_FAILED";
+
+/// The result of a task as hosts get it, with a stable code to branch on instead of the wording
+/// of its message: `{"Ok": "<message>", "code": "CLICK_OK"}` or
+/// `{"Err": {"error_type": ...}, "code": "ELEMENT_NOT_FOUND"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TaskResult {
+    #[serde(flatten)]
+    pub result: Result<String, LibError>,
+    /// `<COMMAND>_OK` for a command that succeeded (see `command_ok`), one of the constants of
+    /// this module for other successes, and the code of the error otherwise (see `error_code`).
+    /// Empty for results recorded before results had codes.
+    #[serde(default)]
+    pub code: String,
+}
+
+impl TaskResult {
+    /// A task that succeeded with `message`.
+    pub fn ok(message: String, code: String) -> Self {
+        TaskResult { result: Ok(message), code }
+    }
+
+    /// A task that failed with `error`, coded by `error_code`.
+    pub fn err(error: LibError) -> Self {
+        TaskResult { code: error_code(&error), result: Err(error) }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// The code of a command that succeeded: its name followed by `_OK`, e.g. `CLICK_OK` or `GETVALUE_OK`.
+pub fn command_ok(command_name: &str) -> String {
+    format!("{}_OK", command_name)
+}
+
+/// The code of `error`, e.g. `ELEMENT_NOT_FOUND` for a `DomOperation` error of kind
+/// `ElementNotFound`, `LLM_RATE_LIMITED` or `ASSERTION_FAILED`.
+pub fn error_code(error: &LibError) -> String {
+    let code = match error {
+        LibError::DomOperation { kind, .. } => return screaming_snake_case(kind),
+        LibError::LlmCall { .. } => "LLM_CALL_FAILED",
+        LibError::RateLimited { .. } => "LLM_RATE_LIMITED",
+        LibError::ContextLengthExceeded { .. } => "LLM_CONTEXT_LENGTH_EXCEEDED",
+        LibError::AuthFailed { .. } => "LLM_AUTH_FAILED",
+        LibError::ContentFiltered { .. } => "LLM_CONTENT_FILTERED",
+        LibError::Network { .. } => "LLM_NETWORK_ERROR",
+        LibError::InvalidLlmResponse { .. } => "INVALID_LLM_RESPONSE",
+        LibError::CommandParse { .. } => "COMMAND_PARSE_ERROR",
+        LibError::Serialization { .. } => "SERIALIZATION_ERROR",
+        LibError::InternalAgent { .. } => "INTERNAL_ERROR",
+        LibError::PolicyViolation { .. } => "POLICY_VIOLATION",
+        LibError::PlanValidation { .. } => "PLAN_VALIDATION_FAILED",
+        LibError::Workflow { .. } => "WORKFLOW_ERROR",
+        LibError::AssertionFailed { .. } => "ASSERTION_FAILED",
+        LibError::BudgetExceeded { .. } => "BUDGET_EXCEEDED",
+        LibError::UserInput { .. } => "USER_INPUT_UNAVAILABLE",
+        LibError::RunLimitExceeded { .. } => "RUN_LIMIT_EXCEEDED",
+        LibError::Export { .. } => "EXPORT_ERROR",
+        LibError::Api { .. } => "API_CALL_FAILED",
+        LibError::HostFunction { .. } => "HOST_FUNCTION_FAILED",
+    };
+    code.to_string()
+}
+
+// `ElementNotFound` as `ELEMENT_NOT_FOUND`.
+fn screaming_snake_case(name: &str) -> String {
+    let mut code = String::with_capacity(name.len() + 4);
+    for (index, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() && index > 0 {
+            code.push('_');
+        }
+        code.push(c.to_ascii_uppercase());
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_task_result_shape() {
+        let clicked = TaskResult::ok("Clicked".to_string(), command_ok("CLICK"));
+        assert_eq!(serde_json::to_value(&clicked).unwrap(), json!({"Ok": "Clicked", "code": "CLICK_OK"}));
+
+        let not_found = TaskResult::err(LibError::DomOperation { kind: "ElementNotFound".to_string(), details: "No #save".to_string() });
+        assert_eq!(
+            serde_json::to_value(&not_found).unwrap(),
+            json!({"Err": {"error_type": "DomOperation", "kind": "ElementNotFound", "details": "No #save"}, "code": "ELEMENT_NOT_FOUND"})
+        );
+        assert_eq!(serde_json::from_value::<TaskResult>(serde_json::to_value(&not_found).unwrap()).unwrap(), not_found);
+
+        let recorded_before_codes: TaskResult = serde_json::from_str(r#"{"Ok": "Clicked"}"#).unwrap();
+        assert_eq!(recorded_before_codes, TaskResult::ok("Clicked".to_string(), String::new()));
+    }
+
+    #[test]
+    fn test_error_code() {
+        let kind = |kind: &str| LibError::DomOperation { kind: kind.to_string(), details: String::new() };
+        assert_eq!(error_code(&kind("DomSerializationError")), "DOM_SERIALIZATION_ERROR");
+        assert_eq!(error_code(&kind("JsTypeError")), "JS_TYPE_ERROR");
+        assert_eq!(error_code(&LibError::RateLimited { message: String::new(), retry_after_ms: None }), "LLM_RATE_LIMITED");
+        assert_eq!(error_code(&LibError::AssertionFailed { message: String::new() }), "ASSERTION_FAILED");
+    }
+}
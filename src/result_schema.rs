@@ -0,0 +1,163 @@
+use crate::result_code::TaskResult;
+use crate::run_summary::RunSummary;
+use serde_json::{json, Value};
+
+/// Version of the shape of task results, as returned by `automate` and streamed runs and listed
+/// in result envelopes. Version 1: the bare array of `{"Ok": "<message>"}` or `{"Err": {...}}`
+/// results `automate` resolved to before results were versioned. Version 2: results with the
+/// `code` of the result (see `result_code`), in `{"schema_version": 2, "results": [...]}`.
+/// Version 3: with the `summary` of the run (see `run_summary`) next to the results, in
+/// `automate`'s output and in result envelopes.
+pub const RESULT_SCHEMA_VERSION: u32 = 3;
+
+/// The shape results are returned in, pinned with `RustAgent.set_result_schema_version`. Results
+/// come in the current version unless one is pinned; while a version is pinned, they keep its shape
+/// when later versions change it: `automate` resolves to exactly what it did in that version, e.g.
+/// `{"schema_version": 3, "results": [...], "summary": {...}}`, or the bare array of version 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResultSchema {
+    /// The version pinned by the host, or `None` for the current version.
+    pub pinned: Option<u32>,
+}
+
+/// The schema with `version` pinned, or with none pinned for `None`.
+///
+/// # Returns
+/// The schema, or an error if `version` is not one of the versions up to `RESULT_SCHEMA_VERSION`.
+pub fn pin(version: Option<u32>) -> Result<ResultSchema, String> {
+    match version {
+        Some(version) if !(1..=RESULT_SCHEMA_VERSION).contains(&version) => Err(format!(
+            "Unknown result schema version {}; the versions are 1 to {}",
+            version, RESULT_SCHEMA_VERSION
+        )),
+        pinned => Ok(ResultSchema { pinned }),
+    }
+}
+
+impl ResultSchema {
+    /// The version results are returned in.
+    pub fn version(&self) -> u32 {
+        self.pinned.unwrap_or(RESULT_SCHEMA_VERSION)
+    }
+
+    /// One result, serialized in the shape of the schema's version, e.g. for `automate_stream`.
+    pub fn result_json(&self, result: &TaskResult) -> serde_json::Result<String> {
+        if self.version() < 2 {
+            serde_json::to_string(&result.result)
+        } else {
+            serde_json::to_string(result)
+        }
+    }
+
+    /// What `automate` resolves to for `results`, serialized in the schema's version:
+    /// `{"schema_version": <version>, "results": [...]}`, with the `summary` of the run from
+    /// version 3 on, if the results are those of a run, or for version 1, the bare array of its
+    /// results, byte for byte.
+    pub fn output_json(&self, results: &[TaskResult], summary: Option<&RunSummary>) -> serde_json::Result<String> {
+        match self.version() {
+            // Serialized without going through `Value`, which would sort the fields of errors.
+            1 => serde_json::to_string(&results.iter().map(|result| &result.result).collect::<Vec<_>>()),
+            version => {
+                let mut output = json!({ "schema_version": version, "results": results });
+                if let Some(summary) = summary.filter(|_| version >= 3) {
+                    output["summary"] = json!(summary);
+                }
+                serde_json::to_string(&output)
+            }
+        }
+    }
+
+    /// A result envelope built in the current version (see `report::envelope`), in the shape of the
+    /// schema's version, with its `schema_version`.
+    pub fn envelope(&self, envelope: &Value) -> Value {
+        let mut envelope = envelope.clone();
+        let version = self.version();
+        envelope["schema_version"] = json!(version);
+        if let Some(results) = envelope.get_mut("results").and_then(Value::as_array_mut) {
+            results.iter_mut().for_each(|result| downgrade(result, version));
+        }
+        if version < 3 {
+            if let Some(envelope) = envelope.as_object_mut() {
+                envelope.remove("summary");
+            }
+        }
+        envelope
+    }
+}
+
+// Turns a result serialized in the current version into one of `version`.
+fn downgrade(result: &mut Value, version: u32) {
+    if version < 2 {
+        if let Some(result) = result.as_object_mut() {
+            result.remove("code");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LibError;
+
+    fn results() -> Vec<TaskResult> {
+        vec![
+            TaskResult::ok("Clicked".to_string(), "CLICK_OK".to_string()),
+            TaskResult::err(LibError::AssertionFailed { message: "Not saved".to_string() }),
+        ]
+    }
+
+    fn output(schema: &ResultSchema, results: &[TaskResult], summary: Option<&RunSummary>) -> Value {
+        serde_json::from_str(&schema.output_json(results, summary).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_output() {
+        let current = ResultSchema::default();
+        assert_eq!(output(&current, &results(), None)["schema_version"], RESULT_SCHEMA_VERSION);
+        assert_eq!(output(&current, &results(), None)["results"][0], json!({"Ok": "Clicked", "code": "CLICK_OK"}));
+
+        let pinned = pin(Some(RESULT_SCHEMA_VERSION)).unwrap();
+        assert_eq!(output(&pinned, &results(), None), output(&current, &results(), None));
+
+        let summary = crate::run_summary::summarize(&[], &results(), &[], 10.0);
+        assert_eq!(output(&pinned, &results(), Some(&summary))["summary"]["failed"], 1);
+        assert_eq!(output(&current, &results(), Some(&summary))["summary"]["failed"], 1);
+        let version_2 = output(&pin(Some(2)).unwrap(), &results(), Some(&summary));
+        assert_eq!(version_2["results"], output(&current, &results(), None)["results"]);
+        assert!(version_2.get("summary").is_none(), "Summaries came with version 3: {}", version_2);
+        assert_eq!(pin(None).unwrap(), current);
+        assert!(pin(Some(0)).is_err());
+        assert!(pin(Some(RESULT_SCHEMA_VERSION + 1)).unwrap_err().contains("Unknown result schema version"));
+    }
+
+    #[test]
+    fn test_version_1_output_is_the_legacy_array() {
+        let results = vec![
+            TaskResult::ok("Clicked".to_string(), "CLICK_OK".to_string()),
+            TaskResult::err(LibError::DomOperation { kind: "ElementNotFound".to_string(), details: "No element found for selector '#save'".to_string() }),
+        ];
+        // What `automate` resolved to before results had codes: `serde_json::to_string` of the
+        // `Vec<Result<String, LibError>>` of the run.
+        let legacy: Vec<Result<String, LibError>> = results.iter().map(|result| result.result.clone()).collect();
+        let expected = r#"[{"Ok":"Clicked"},{"Err":{"error_type":"DomOperation","kind":"ElementNotFound","details":"No element found for selector '#save'"}}]"#;
+        assert_eq!(serde_json::to_string(&legacy).unwrap(), expected);
+
+        let version_1 = pin(Some(1)).unwrap();
+        let summary = crate::run_summary::summarize(&[], &results, &[], 10.0);
+        assert_eq!(version_1.output_json(&results, Some(&summary)).unwrap(), expected);
+        assert_eq!(version_1.result_json(&results[0]).unwrap(), r#"{"Ok":"Clicked"}"#);
+        assert_eq!(ResultSchema::default().result_json(&results[0]).unwrap(), r#"{"Ok":"Clicked","code":"CLICK_OK"}"#);
+    }
+
+    #[test]
+    fn test_envelope() {
+        let envelope = json!({"source": "rustagent", "schema_version": RESULT_SCHEMA_VERSION, "results": results(), "summary": {"failed": 1}});
+        assert_eq!(ResultSchema::default().envelope(&envelope), envelope);
+        let version_2 = pin(Some(2)).unwrap().envelope(&envelope);
+        assert_eq!(version_2["results"], envelope["results"]);
+        assert!(version_2.get("summary").is_none());
+        let version_1 = pin(Some(1)).unwrap().envelope(&envelope);
+        assert_eq!(version_1["schema_version"], 1);
+        assert_eq!(version_1["results"][0], json!({"Ok": "Clicked"}));
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fmt;
+
+/// Caps on what a single run (one `automate` call, workflow or scheduled run) may do, set with
+/// `RustAgent.set_run_limits`, so that an autonomous loop left unattended cannot do unbounded
+/// damage. Every cap is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct RunLimits {
+    /// Commands that act on the page (clicks, typing, attribute changes, scrolling, `EVAL_JS`...);
+    /// reads, waits and `NAVIGATE` are not counted.
+    pub max_dom_mutations: Option<u32>,
+    /// `NAVIGATE` commands.
+    pub max_navigations: Option<u32>,
+    /// Calls to the LLM, including `LLM_DECIDE` / `LLM_ASSERT` questions and disambiguation.
+    pub max_llm_calls: Option<u32>,
+}
+
+/// What a run has done so far, counted against its `RunLimits`.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunUsage {
+    pub dom_mutations: u32,
+    pub navigations: u32,
+    pub llm_calls: u32,
+}
+
+/// What a run is about to do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunAction {
+    DomMutation,
+    Navigation,
+    LlmCall,
+}
+
+impl RunAction {
+    /// The name of the cap on the action, as in `RunLimits`.
+    pub fn limit_name(&self) -> &'static str {
+        match self {
+            RunAction::DomMutation => "max_dom_mutations",
+            RunAction::Navigation => "max_navigations",
+            RunAction::LlmCall => "max_llm_calls",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            RunAction::DomMutation => "DOM mutations",
+            RunAction::Navigation => "navigations",
+            RunAction::LlmCall => "LLM calls",
+        }
+    }
+}
+
+/// The error of an action refused because the run already did as many as its cap allows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunLimitExceeded {
+    pub action: RunAction,
+    pub max: u32,
+}
+
+impl fmt::Display for RunLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The run reached its limit of {} {} ({}); the rest of the run was aborted",
+            self.max,
+            self.action.description(),
+            self.action.limit_name()
+        )
+    }
+}
+
+/// The caps of the runs of an agent and what the current run has done. Unlike the limits, the
+/// usage is not shared by clones, so that scheduled and streamed runs count their own actions.
+#[derive(Debug, Clone, Default)]
+pub struct RunGuard {
+    pub limits: RunLimits,
+    usage: RefCell<RunUsage>,
+}
+
+impl RunGuard {
+    /// Marks the start of a run: nothing has been done yet.
+    pub fn begin_run(&self) {
+        *self.usage.borrow_mut() = RunUsage::default();
+    }
+
+    /// What the current run has done so far.
+    pub fn usage(&self) -> RunUsage {
+        *self.usage.borrow()
+    }
+
+    /// Counts `action` against its cap, or fails without counting it if the run already did as
+    /// many as the cap allows.
+    pub fn charge(&self, action: RunAction) -> Result<(), RunLimitExceeded> {
+        let mut usage = self.usage.borrow_mut();
+        let (count, max) = match action {
+            RunAction::DomMutation => (&mut usage.dom_mutations, self.limits.max_dom_mutations),
+            RunAction::Navigation => (&mut usage.navigations, self.limits.max_navigations),
+            RunAction::LlmCall => (&mut usage.llm_calls, self.limits.max_llm_calls),
+        };
+        if let Some(max) = max {
+            if *count >= max {
+                return Err(RunLimitExceeded { action, max });
+            }
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charge_stops_at_each_cap() {
+        let guard = RunGuard {
+            limits: serde_json::from_str(r#"{"max_dom_mutations": 2, "max_llm_calls": 0}"#).unwrap(),
+            ..RunGuard::default()
+        };
+        assert!(guard.charge(RunAction::DomMutation).is_ok());
+        assert!(guard.charge(RunAction::DomMutation).is_ok());
+        let exceeded = guard.charge(RunAction::DomMutation).unwrap_err();
+        assert_eq!(exceeded, RunLimitExceeded { action: RunAction::DomMutation, max: 2 });
+        assert!(exceeded.to_string().contains("limit of 2 DOM mutations (max_dom_mutations)"), "{}", exceeded);
+        assert!(guard.charge(RunAction::LlmCall).is_err());
+        for _ in 0..5 {
+            guard.charge(RunAction::Navigation).unwrap();
+        }
+        assert_eq!(guard.usage(), RunUsage { dom_mutations: 2, navigations: 5, llm_calls: 0 });
+
+        let scheduled = guard.clone();
+        guard.begin_run();
+        assert_eq!(guard.usage(), RunUsage::default());
+        assert_eq!(scheduled.usage().navigations, 5, "Clones keep their own usage");
+        assert!(guard.charge(RunAction::DomMutation).is_ok());
+    }
+}
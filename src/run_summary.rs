@@ -0,0 +1,83 @@
+use crate::result_code::TaskResult;
+use crate::{goto, try_catch, while_loop, LibError};
+use serde::{Deserialize, Serialize};
+
+/// The first task of a run that failed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FirstError {
+    /// The task as written in the task list, before `{{PREVIOUS_RESULT}}` substitution.
+    pub task: String,
+    /// The position of its result among the results of the run.
+    pub result_index: usize,
+    pub code: String,
+    pub error: LibError,
+}
+
+/// The basic figures of a finished run, so that dashboards need not compute them from its results.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunSummary {
+    /// `succeeded + failed + skipped`. A task run several times by a loop counts each time it ran.
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Tasks of the list that never ran: after a failure in a TRY part, jumped over by a GOTO, in a
+    /// loop or CATCH part that did not run, or left when the run was aborted or its stream stopped.
+    pub skipped: usize,
+    pub duration_ms: f64,
+    pub first_error: Option<FirstError>,
+}
+
+/// Summarizes a run of `tasks` that took `duration_ms`. `results` are its results, and
+/// `result_steps` the index in `tasks` of the step each of them is for. Control steps (TRY / CATCH
+/// markers, labels, GOTO and WHILE steps) are not tasks, and are not counted as skipped.
+pub fn summarize(tasks: &[String], results: &[TaskResult], result_steps: &[usize], duration_ms: f64) -> RunSummary {
+    let succeeded = results.iter().filter(|result| result.is_ok()).count();
+    let failed = results.len() - succeeded;
+    let skipped = tasks
+        .iter()
+        .enumerate()
+        .filter(|(index, task)| !result_steps.contains(index) && !is_control_step(task))
+        .count();
+    let first_error = results.iter().enumerate().find_map(|(result_index, result)| {
+        let error = result.result.as_ref().err()?;
+        Some(FirstError {
+            task: result_steps.get(result_index).and_then(|&step| tasks.get(step)).cloned().unwrap_or_default(),
+            result_index,
+            code: result.code.clone(),
+            error: error.clone(),
+        })
+    });
+    RunSummary { total: results.len() + skipped, succeeded, failed, skipped, duration_ms, first_error }
+}
+
+// Whether `task` is a step of the control flow of a task list rather than a task.
+fn is_control_step(task: &str) -> bool {
+    try_catch::marker(task).is_some() || goto::parse_step(task).is_some() || while_loop::parse_step(task).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let tasks: Vec<String> = ["TRY", "CLICK css:#save", "READ css:#toast", "CATCH", "READ css:#error", "END_TRY", "GET_URL"]
+            .iter()
+            .map(|task| task.to_string())
+            .collect();
+        let results = vec![
+            TaskResult::err(LibError::DomOperation { kind: "ElementNotFound".to_string(), details: "No #save".to_string() }),
+            TaskResult::ok("Text: Oops".to_string(), "READ_OK".to_string()),
+            TaskResult::ok("https://shop.example/".to_string(), "GET_URL_OK".to_string()),
+        ];
+        let summary = summarize(&tasks, &results, &[1, 4, 6], 1250.0);
+        assert_eq!((summary.total, summary.succeeded, summary.failed, summary.skipped), (4, 2, 1, 1));
+        assert_eq!(summary.duration_ms, 1250.0);
+        let first_error = summary.first_error.unwrap();
+        assert_eq!((first_error.task.as_str(), first_error.result_index), ("CLICK css:#save", 0));
+        assert_eq!(first_error.code, "ELEMENT_NOT_FOUND");
+
+        let nothing_ran = summarize(&tasks[..3], &[], &[], 0.0);
+        assert_eq!((nothing_ran.total, nothing_ran.skipped, nothing_ran.first_error), (2, 2, None));
+    }
+}
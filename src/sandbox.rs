@@ -0,0 +1,182 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, Event};
+use serde::Serialize;
+use std::cell::RefCell;
+use crate::dom_diff::{self, DomDiff, DomFingerprint};
+use crate::dom_utils::{self, DomError};
+use crate::result_code::TaskResult;
+
+/// Id of the hidden element that holds the copy of the container while a preview runs.
+pub const SANDBOX_HOST_ID: &str = "rustagent-sandbox";
+
+thread_local! {
+    // The sandbox of the preview being run. Only one preview runs at a time on a page.
+    static SANDBOX: RefCell<Option<Sandbox>> = const { RefCell::new(None) };
+}
+
+struct Sandbox {
+    host: Element,
+    // Commands that succeeded in the sandbox, as serialized `DomCommand`s, in the order they ran.
+    performed: Vec<serde_json::Value>,
+    // Cancels link navigation and form submission, which would leave the live page.
+    _on_click: Closure<dyn FnMut(Event)>,
+    _on_submit: Closure<dyn FnMut(Event)>,
+}
+
+/// A deep copy of a container, in a hidden element at the end of the `<body>`, that selectors are
+/// resolved in instead of the page while the scope is alive (see `dom_utils::get_element`), so
+/// that commands act on the copy. The copy is removed when the scope is dropped.
+///
+/// The copy keeps the attributes, form state and inline event handlers of the container, but not
+/// the listeners scripts attached to it, so a preview shows what the commands themselves change,
+/// not how the site's scripts would react. Links and form submissions in the copy do nothing.
+pub(crate) struct SandboxScope {
+    clone: Element,
+    container_selector: String,
+    before: DomFingerprint,
+}
+
+impl SandboxScope {
+    /// Copies the element matching `container_selector` into a new sandbox.
+    ///
+    /// # Returns
+    /// * `Err(DomError)` if the container cannot be found or copied, or another preview is running.
+    pub(crate) fn start(container_selector: &str) -> Result<Self, DomError> {
+        if is_active() {
+            return Err(DomError::JsError { message: "Another preview is already running on this page".to_string() });
+        }
+        let (_window, document) = dom_utils::get_window_document()?;
+        let container = dom_utils::get_element(&document, container_selector)?;
+        let clone: Element = container.clone_node_with_deep(true)?.unchecked_into();
+        let host = document.create_element("div")?;
+        host.set_id(SANDBOX_HOST_ID);
+        host.set_attribute("aria-hidden", "true")?;
+        // Laid out like the container but out of sight, so that visibility checks behave as on the page.
+        host.set_attribute(
+            "style",
+            &format!("position: absolute; left: -100000px; top: 0; width: {}px;", container.client_width().max(1)),
+        )?;
+        host.append_child(&clone)?;
+
+        let on_click = Closure::wrap(Box::new(|event: Event| {
+            let on_link = event
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+                .and_then(|target| target.closest("a[href]").ok().flatten());
+            if on_link.is_some() {
+                event.prevent_default();
+            }
+        }) as Box<dyn FnMut(Event)>);
+        let on_submit = Closure::wrap(Box::new(|event: Event| event.prevent_default()) as Box<dyn FnMut(Event)>);
+        host.add_event_listener_with_callback_and_bool("click", on_click.as_ref().unchecked_ref(), true)?;
+        host.add_event_listener_with_callback_and_bool("submit", on_submit.as_ref().unchecked_ref(), true)?;
+        document
+            .body()
+            .ok_or_else(|| DomError::JsError { message: "Document has no body".to_string() })?
+            .append_child(&host)?;
+
+        let before = dom_diff::fingerprint_subtree(&clone, container_selector);
+        SANDBOX.with(|sandbox| {
+            *sandbox.borrow_mut() = Some(Sandbox { host, performed: Vec::new(), _on_click: on_click, _on_submit: on_submit });
+        });
+        Ok(SandboxScope { clone, container_selector: container_selector.to_string(), before })
+    }
+
+    /// What the commands run so far changed in the copy. Paths start with the container's selector.
+    pub(crate) fn diff(&self) -> DomDiff {
+        dom_diff::diff(&self.before, &dom_diff::fingerprint_subtree(&self.clone, &self.container_selector))
+    }
+
+    /// The commands that succeeded in the sandbox, in the order they ran.
+    pub(crate) fn performed(&self) -> Vec<serde_json::Value> {
+        SANDBOX.with(|sandbox| sandbox.borrow().as_ref().map(|sandbox| sandbox.performed.clone()).unwrap_or_default())
+    }
+}
+
+impl Drop for SandboxScope {
+    fn drop(&mut self) {
+        if let Some(sandbox) = SANDBOX.with(|sandbox| sandbox.borrow_mut().take()) {
+            sandbox.host.remove();
+        }
+    }
+}
+
+/// The element selectors are resolved in while a preview runs, or `None` outside previews.
+pub(crate) fn root() -> Option<Element> {
+    SANDBOX.with(|sandbox| sandbox.borrow().as_ref().map(|sandbox| sandbox.host.clone()))
+}
+
+/// Whether a preview is running.
+pub(crate) fn is_active() -> bool {
+    SANDBOX.with(|sandbox| sandbox.borrow().is_some())
+}
+
+/// Records a command that succeeded in the sandbox, to be applied to the page once approved.
+pub(crate) fn record(command: serde_json::Value) {
+    SANDBOX.with(|sandbox| {
+        if let Some(sandbox) = sandbox.borrow_mut().as_mut() {
+            sandbox.performed.push(command);
+        }
+    });
+}
+
+/// What `RustAgent::preview` reports for approval.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PreviewReport {
+    /// The selector of the copied container.
+    pub container: String,
+    /// The results of the tasks in the sandbox, as `automate` would return them.
+    pub results: Vec<TaskResult>,
+    /// What the tasks changed in the copy of the container.
+    pub diff: DomDiff,
+    /// The number of commands `apply_preview` will run on the page.
+    pub commands: usize,
+}
+
+/// The commands of a preview, run on the page by `RustAgent::apply_preview` once approved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingPreview {
+    pub container_selector: String,
+    pub commands: Vec<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_selectors_resolve_in_the_copy() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let container = document.create_element("div").unwrap();
+        container.set_id("sandbox-container");
+        container.set_inner_html("<button id=\"sandbox-button\">Save</button><a id=\"sandbox-link\" href=\"#elsewhere\">Leave</a>");
+        document.body().unwrap().append_child(&container).unwrap();
+        let live_button = document.get_element_by_id("sandbox-button").unwrap();
+
+        let scope = SandboxScope::start("css:#sandbox-container").unwrap();
+        assert!(SandboxScope::start("css:#sandbox-container").is_err(), "One preview at a time");
+        let host = root().unwrap();
+        for selector in ["css:#sandbox-button", "xpath://button[@id='sandbox-button']", "text:Save"] {
+            let found = dom_utils::get_element(&document, selector).unwrap();
+            assert_ne!(found, live_button, "{} resolved on the page", selector);
+            assert!(host.contains(Some(&found)), "{} resolved outside the copy", selector);
+        }
+        dom_utils::get_element(&document, "css:#sandbox-link").unwrap().unchecked_into::<web_sys::HtmlElement>().click();
+        assert_eq!(web_sys::window().unwrap().location().hash().unwrap(), "", "Links in the copy do nothing");
+
+        dom_utils::get_element(&document, "css:#sandbox-button").unwrap().set_attribute("disabled", "").unwrap();
+        let diff = scope.diff();
+        assert_eq!(diff.changed, vec!["css:#sandbox-container > button#sandbox-button".to_string()]);
+        assert!(!live_button.has_attribute("disabled"));
+
+        drop(scope);
+        assert!(!is_active());
+        assert!(document.get_element_by_id(SANDBOX_HOST_ID).is_none());
+        assert_eq!(dom_utils::get_element(&document, "css:#sandbox-button").unwrap(), live_button);
+        container.remove();
+    }
+}
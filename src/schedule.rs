@@ -0,0 +1,111 @@
+use wasm_bindgen::prelude::*;
+use web_sys::console;
+use std::cell::Cell;
+use std::future::Future;
+use std::rc::Rc;
+use gloo_timers::future::TimeoutFuture;
+
+/// Handle to a scheduled run, returned by `RustAgent::schedule` and `RustAgent::schedule_recurring`.
+///
+/// Cancelling only prevents future runs; a run that has already started is allowed to finish.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct ScheduledRun {
+    active: Rc<Cell<bool>>,
+    run_count: Rc<Cell<u32>>,
+}
+
+#[wasm_bindgen]
+impl ScheduledRun {
+    /// Stops any further runs. Cancelling a finished or already cancelled schedule does nothing.
+    #[wasm_bindgen]
+    pub fn cancel(&self) {
+        if self.active.replace(false) {
+            console::log_1(&format!("Scheduled run cancelled after {} runs", self.run_count.get()).into());
+        }
+    }
+
+    /// `true` until the schedule is cancelled or, for a one-off schedule, its run has finished.
+    #[wasm_bindgen]
+    pub fn is_active(&self) -> bool {
+        self.active.get()
+    }
+
+    /// The number of runs that have finished so far.
+    #[wasm_bindgen]
+    pub fn run_count(&self) -> u32 {
+        self.run_count.get()
+    }
+}
+
+/// Starts running `run` in the background: first after `delay_ms`, then, if `interval_ms` is
+/// given, again every `interval_ms` until the returned handle is cancelled.
+///
+/// The interval is measured from the end of the previous run, so slow runs (e.g. waiting for
+/// the LLM) never overlap.
+pub fn start<F, Fut>(delay_ms: u32, interval_ms: Option<u32>, run: F) -> ScheduledRun
+where
+    F: Fn() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let handle = ScheduledRun { active: Rc::new(Cell::new(true)), run_count: Rc::new(Cell::new(0)) };
+    let state = handle.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        TimeoutFuture::new(delay_ms).await;
+        while state.active.get() {
+            run().await;
+            state.run_count.set(state.run_count.get() + 1);
+            match interval_ms {
+                Some(interval) => TimeoutFuture::new(interval).await,
+                None => state.active.set(false),
+            }
+        }
+    });
+    handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // Returns a run function that counts its calls in `calls`.
+    fn counting_run(calls: &Rc<Cell<u32>>) -> impl Fn() -> std::future::Ready<()> + 'static {
+        let calls = calls.clone();
+        move || {
+            calls.set(calls.get() + 1);
+            std::future::ready(())
+        }
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_one_off_schedule_runs_once_after_delay() {
+        let calls = Rc::new(Cell::new(0));
+        let handle = start(50, None, counting_run(&calls));
+        assert_eq!(calls.get(), 0, "Nothing should run before the delay");
+        TimeoutFuture::new(150).await;
+        assert_eq!(calls.get(), 1);
+        assert_eq!(handle.run_count(), 1);
+        assert!(!handle.is_active(), "A one-off schedule should finish after its run");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_recurring_schedule_stops_when_cancelled() {
+        let calls = Rc::new(Cell::new(0));
+        let handle = start(10, Some(30), counting_run(&calls));
+        TimeoutFuture::new(200).await;
+        assert!(calls.get() >= 2, "Expected several runs, got {}", calls.get());
+        handle.cancel();
+        assert!(!handle.is_active());
+        let runs_at_cancel = calls.get();
+        TimeoutFuture::new(150).await;
+        assert_eq!(calls.get(), runs_at_cancel, "No run should start after cancel");
+
+        let calls = Rc::new(Cell::new(0));
+        start(50, None, counting_run(&calls)).cancel();
+        TimeoutFuture::new(150).await;
+        assert_eq!(calls.get(), 0, "Cancelling before the delay should prevent the run");
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Opening marker of a secret placeholder, e.g. `{{secret.password}}`.
+const SECRET_PREFIX: &str = "{{secret.";
+/// Closing marker of a secret placeholder.
+const SECRET_SUFFIX: &str = "}}";
+
+/// Named secrets (passwords, tokens) set with `RustAgent::set_secret`.
+///
+/// Tasks refer to a secret with `{{secret.<name>}}`; the value is only substituted when the
+/// command runs, so tasks, logs and LLM prompts only ever contain the placeholder. `Debug`
+/// lists the names without the values.
+#[derive(Clone, Default)]
+pub struct SecretStore {
+    values: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretStore").field("names", &self.values.keys().collect::<Vec<_>>()).finish()
+    }
+}
+
+impl SecretStore {
+    /// Stores `value` under `name`, replacing any previous value. An empty value removes the secret.
+    pub fn set(&mut self, name: &str, value: &str) {
+        if value.is_empty() {
+            self.values.remove(name);
+        } else {
+            self.values.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// `true` if no secret is set.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// `true` if `text` contains a `{{secret.<name>}}` placeholder.
+    pub fn has_placeholder(text: &str) -> bool {
+        text.contains(SECRET_PREFIX)
+    }
+
+    /// Replaces every `{{secret.<name>}}` placeholder in `text` with the secret's value.
+    ///
+    /// # Returns
+    /// The text with the values substituted, or an error naming the first unknown secret.
+    pub fn substitute(&self, text: &str) -> Result<String, String> {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(SECRET_PREFIX) {
+            let after_prefix = &rest[start + SECRET_PREFIX.len()..];
+            let end = after_prefix
+                .find(SECRET_SUFFIX)
+                .ok_or_else(|| format!("Unterminated secret placeholder in '{}'", text))?;
+            let name = after_prefix[..end].trim();
+            let value = self
+                .values
+                .get(name)
+                .ok_or_else(|| format!("Unknown secret '{}'. Set it with set_secret.", name))?;
+            output.push_str(&rest[..start]);
+            output.push_str(value);
+            rest = &after_prefix[end + SECRET_SUFFIX.len()..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Replaces every occurrence of a secret value in `text` with its placeholder, so that
+    /// results and messages never echo secrets.
+    pub fn mask(&self, text: &str) -> String {
+        // Longer values first, so a secret containing another one is masked as a whole.
+        let mut secrets: Vec<(&String, &String)> = self.values.iter().collect();
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.1.len()));
+        secrets.into_iter().fold(text.to_string(), |text, (name, value)| {
+            text.replace(value.as_str(), &format!("{}{}{}", SECRET_PREFIX, name, SECRET_SUFFIX))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> SecretStore {
+        let mut secrets = SecretStore::default();
+        secrets.set("password", "hunter2");
+        secrets.set("token", "abc-123");
+        secrets
+    }
+
+    #[test]
+    fn test_substitute_and_mask() {
+        let secrets = store();
+        assert_eq!(secrets.substitute("{{secret.password}}").unwrap(), "hunter2");
+        assert_eq!(secrets.substitute("Bearer {{secret.token}}!").unwrap(), "Bearer abc-123!");
+        assert_eq!(secrets.substitute("no placeholders").unwrap(), "no placeholders");
+        assert!(secrets.substitute("{{secret.pin}}").unwrap_err().contains("Unknown secret 'pin'"));
+        assert!(secrets.substitute("{{secret.password").is_err());
+
+        assert_eq!(
+            secrets.mask("Successfully typed 'hunter2' in element with selector: '#pw'"),
+            "Successfully typed '{{secret.password}}' in element with selector: '#pw'"
+        );
+    }
+
+    #[test]
+    fn test_empty_value_removes_secret_and_debug_hides_values() {
+        let mut secrets = store();
+        assert!(!format!("{:?}", secrets).contains("hunter2"));
+        secrets.set("password", "");
+        secrets.set("token", "");
+        assert!(secrets.is_empty());
+        assert!(SecretStore::has_placeholder("TYPE css:#pw {{secret.password}}"));
+    }
+}
@@ -0,0 +1,386 @@
+use wasm_bindgen::JsCast;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::dom_utils::{self, DomError};
+
+/// Title lengths, in characters, search engines show without truncating.
+pub const TITLE_LENGTH_RANGE: (usize, usize) = (30, 60);
+/// Meta description lengths, in characters, search engines show without truncating.
+pub const DESCRIPTION_LENGTH_RANGE: (usize, usize) = (70, 160);
+
+/// The structured data found in the page.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StructuredData {
+    /// The `@type`s of the JSON-LD items, e.g. `Product` or `BreadcrumbList`, including those in
+    /// `@graph` arrays.
+    pub json_ld_types: Vec<String>,
+    /// `<script type="application/ld+json">` blocks that are not valid JSON.
+    pub json_ld_errors: usize,
+    /// Microdata items, i.e. elements with `itemscope`, not nested in another item.
+    pub microdata_items: usize,
+    /// RDFa resources, i.e. elements with `typeof`.
+    pub rdfa_items: usize,
+}
+
+/// A microdata item (an element with `itemscope`) with its properties.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct MicrodataItem {
+    /// The `itemtype` URLs, e.g. `https://schema.org/Product`.
+    #[serde(rename = "type")]
+    pub item_type: Vec<String>,
+    /// The `itemid`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The values of each property, by name, in document order. A value is text, or an object
+    /// like this one for a nested item.
+    pub properties: BTreeMap<String, Vec<serde_json::Value>>,
+}
+
+/// The result of `EXTRACT_STRUCTURED_DATA`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ExtractedData {
+    /// The parsed `<script type="application/ld+json">` blocks, in document order.
+    pub json_ld: Vec<serde_json::Value>,
+    /// Why the blocks that are not valid JSON could not be parsed, e.g. "Block 2: EOF while parsing".
+    pub json_ld_errors: Vec<String>,
+    /// The top-level microdata items, i.e. those not a property of another item.
+    pub microdata: Vec<MicrodataItem>,
+}
+
+/// The result of `AUDIT_SEO`.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SeoAudit {
+    pub title: Option<String>,
+    pub title_length: usize,
+    /// The `content` of `<meta name="description">`.
+    pub meta_description: Option<String>,
+    pub meta_description_length: usize,
+    /// The `href` of `<link rel="canonical">`, resolved to an absolute URL.
+    pub canonical: Option<String>,
+    /// The `content` of `<meta name="robots">`, e.g. `noindex, nofollow`.
+    pub robots: Option<String>,
+    pub h1_count: usize,
+    /// The text of each `<h1>`.
+    pub h1: Vec<String>,
+    pub structured_data: StructuredData,
+    /// Problems found, in plain words, e.g. "Title is 72 characters long; 30 to 60 are shown in full".
+    pub issues: Vec<String>,
+}
+
+impl SeoAudit {
+    // Lists the problems of the audited page, for `issues`.
+    fn find_issues(&self) -> Vec<String> {
+        let check_length = |name: &str, text: &Option<String>, length: usize, (min, max): (usize, usize)| match text {
+            Some(_) if length < min || length > max => {
+                Some(format!("{} is {} characters long; {} to {} are shown in full", name, length, min, max))
+            }
+            Some(_) => None,
+            None => Some(format!("{} is missing", name)),
+        };
+        let mut issues: Vec<String> = [
+            check_length("Title", &self.title, self.title_length, TITLE_LENGTH_RANGE),
+            check_length("Meta description", &self.meta_description, self.meta_description_length, DESCRIPTION_LENGTH_RANGE),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if self.canonical.is_none() {
+            issues.push("Canonical link is missing".to_string());
+        }
+        if self.robots.as_deref().is_some_and(|robots| robots.to_lowercase().contains("noindex")) {
+            issues.push("Page is excluded from search results (robots noindex)".to_string());
+        }
+        match self.h1_count {
+            0 => issues.push("No <h1> heading".to_string()),
+            1 => {}
+            count => issues.push(format!("{} <h1> headings; one is recommended", count)),
+        }
+        let data = &self.structured_data;
+        if data.json_ld_errors > 0 {
+            issues.push(format!("{} JSON-LD blocks are not valid JSON", data.json_ld_errors));
+        }
+        if data.json_ld_types.is_empty() && data.microdata_items == 0 && data.rdfa_items == 0 {
+            issues.push("No structured data".to_string());
+        }
+        issues
+    }
+}
+
+// Collapses whitespace and returns `None` for empty text.
+fn clean(text: Option<String>) -> Option<String> {
+    let text = text?.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+// Adds the `@type`s of a JSON-LD value to `types`: of an item, each item of an array, and the
+// items of a `@graph`.
+fn collect_json_ld_types(value: &serde_json::Value, types: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Array(items) => items.iter().for_each(|item| collect_json_ld_types(item, types)),
+        serde_json::Value::Object(item) => {
+            match item.get("@type") {
+                Some(serde_json::Value::String(item_type)) => types.push(item_type.clone()),
+                Some(serde_json::Value::Array(item_types)) => {
+                    types.extend(item_types.iter().filter_map(|item_type| item_type.as_str().map(str::to_string)))
+                }
+                _ => {}
+            }
+            if let Some(graph) = item.get("@graph") {
+                collect_json_ld_types(graph, types);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The value of a microdata property, following the HTML rules: `content` for `<meta>`, the URL
+// for media and links, `value` for `<data>` and `<meter>`, `datetime` for `<time>`, text otherwise.
+fn property_value(element: &web_sys::Element) -> Result<serde_json::Value, DomError> {
+    let attribute = |name: &str| element.get_attribute(name).unwrap_or_default();
+    let url = |name: &str| -> Result<String, DomError> {
+        let url = attribute(name);
+        Ok(if url.is_empty() { url } else { dom_utils::resolve_url(url.trim())?.href() })
+    };
+    let value = match element.tag_name().to_lowercase().as_str() {
+        "meta" => attribute("content"),
+        "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => url("src")?,
+        "a" | "area" | "link" => url("href")?,
+        "object" => url("data")?,
+        "data" | "meter" => attribute("value"),
+        "time" if element.has_attribute("datetime") => attribute("datetime"),
+        _ => clean(element.text_content()).unwrap_or_default(),
+    };
+    Ok(serde_json::Value::String(value))
+}
+
+// Reads the microdata item of an element with `itemscope`: the properties among its descendants,
+// down to nested items, which become the values of their `itemprop`.
+fn microdata_item(element: &web_sys::Element) -> Result<MicrodataItem, DomError> {
+    let mut item = MicrodataItem {
+        item_type: element.get_attribute("itemtype").unwrap_or_default().split_whitespace().map(str::to_string).collect(),
+        id: element.get_attribute("itemid"),
+        properties: BTreeMap::new(),
+    };
+    let mut pending: Vec<web_sys::Element> = children(element).into_iter().rev().collect();
+    while let Some(child) = pending.pop() {
+        let nested = child.has_attribute("itemscope");
+        if let Some(names) = child.get_attribute("itemprop") {
+            let value = if nested {
+                serde_json::to_value(microdata_item(&child)?).map_err(|e| DomError::SerializationError { message: e.to_string() })?
+            } else {
+                property_value(&child)?
+            };
+            for name in names.split_whitespace() {
+                item.properties.entry(name.to_string()).or_default().push(value.clone());
+            }
+        }
+        // The properties inside a nested item belong to it.
+        if !nested {
+            pending.extend(children(&child).into_iter().rev());
+        }
+    }
+    Ok(item)
+}
+
+// The child elements of an element, in document order.
+fn children(element: &web_sys::Element) -> Vec<web_sys::Element> {
+    std::iter::successors(element.first_element_child(), |child| child.next_element_sibling()).collect()
+}
+
+/// Collects the page's structured data: the JSON-LD blocks, parsed, and the microdata items
+/// with their properties. `itemref` is not followed.
+pub fn extract_structured_data() -> Result<ExtractedData, DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    let mut data = ExtractedData::default();
+    let scripts = elements(&document, "script[type='application/ld+json' i]")?;
+    for (index, script) in scripts.iter().enumerate() {
+        match serde_json::from_str::<serde_json::Value>(&script.text_content().unwrap_or_default()) {
+            Ok(value) => data.json_ld.push(value),
+            Err(e) => data.json_ld_errors.push(format!("Block {}: {}", index + 1, e)),
+        }
+    }
+    for element in elements(&document, "[itemscope]:not([itemprop])")? {
+        data.microdata.push(microdata_item(&element)?);
+    }
+    Ok(data)
+}
+
+// The elements matching a CSS selector.
+fn elements(document: &web_sys::Document, selector: &str) -> Result<Vec<web_sys::Element>, DomError> {
+    let list = document.query_selector_all(selector).map_err(DomError::from)?;
+    Ok((0..list.length())
+        .filter_map(|index| list.item(index).and_then(|node| node.dyn_into::<web_sys::Element>().ok()))
+        .collect())
+}
+
+/// Audits the page's search engine metadata: title, meta description, canonical link, robots
+/// directives, `<h1>` headings and structured data (JSON-LD, microdata, RDFa), with the problems
+/// found. Returns the `SeoAudit` as JSON.
+pub fn audit_seo() -> Result<String, DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    let meta_content = |name: &str| -> Result<Option<String>, DomError> {
+        let meta = document.query_selector(&format!("meta[name='{}' i]", name)).map_err(DomError::from)?;
+        Ok(clean(meta.and_then(|meta| meta.get_attribute("content"))))
+    };
+
+    let title = clean(Some(document.title()));
+    let meta_description = meta_content("description")?;
+    let canonical = match document.query_selector("link[rel~='canonical' i]").map_err(DomError::from)? {
+        Some(link) => match link.get_attribute("href") {
+            Some(href) => Some(dom_utils::resolve_url(href.trim())?.href()),
+            None => None,
+        },
+        None => None,
+    };
+    let h1: Vec<String> = elements(&document, "h1")?
+        .iter()
+        .map(|heading| clean(heading.text_content()).unwrap_or_default())
+        .collect();
+
+    let extracted = extract_structured_data()?;
+    let mut structured_data = StructuredData {
+        json_ld_errors: extracted.json_ld_errors.len(),
+        microdata_items: extracted.microdata.len(),
+        rdfa_items: elements(&document, "[typeof]")?.len(),
+        ..Default::default()
+    };
+    collect_json_ld_types(&serde_json::Value::Array(extracted.json_ld), &mut structured_data.json_ld_types);
+
+    let mut audit = SeoAudit {
+        title_length: title.as_deref().map_or(0, |title| title.chars().count()),
+        title,
+        meta_description_length: meta_description.as_deref().map_or(0, |description| description.chars().count()),
+        meta_description,
+        canonical,
+        robots: meta_content("robots")?,
+        h1_count: h1.len(),
+        h1,
+        structured_data,
+        issues: Vec::new(),
+    };
+    audit.issues = audit.find_issues();
+    serde_json::to_string(&audit).map_err(|e| DomError::SerializationError { message: e.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_find_issues_and_json_ld_types() {
+        let mut audit = SeoAudit {
+            title: Some("Red running shoes for trail and road | Shop".to_string()),
+            title_length: 43,
+            meta_description: Some("Short".to_string()),
+            meta_description_length: 5,
+            h1_count: 2,
+            ..Default::default()
+        };
+        let json_ld: serde_json::Value = serde_json::from_str(
+            r#"{"@context": "https://schema.org", "@graph": [{"@type": "Product"}, {"@type": ["Organization", "Brand"]}]}"#,
+        ).unwrap();
+        collect_json_ld_types(&json_ld, &mut audit.structured_data.json_ld_types);
+        assert_eq!(audit.structured_data.json_ld_types, vec!["Product", "Organization", "Brand"]);
+
+        assert_eq!(audit.find_issues(), vec![
+            "Meta description is 5 characters long; 70 to 160 are shown in full",
+            "Canonical link is missing",
+            "2 <h1> headings; one is recommended",
+        ]);
+
+        audit.title = None;
+        audit.robots = Some("NOINDEX, follow".to_string());
+        audit.structured_data = StructuredData::default();
+        let issues = audit.find_issues();
+        assert_eq!(issues[0], "Title is missing");
+        assert!(issues.contains(&"Page is excluded from search results (robots noindex)".to_string()));
+        assert!(issues.contains(&"No structured data".to_string()));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_audit_seo_reads_page_metadata() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let head = document.query_selector("head").unwrap().unwrap();
+        let body = document.body().unwrap();
+        let previous_title = document.title();
+        document.set_title("Trail shoes");
+        let added: Vec<web_sys::Element> = [
+            ("meta", vec![("name", "Description"), ("content", "Light trail shoes with a grippy sole, for runners who leave the road behind on weekends.")]),
+            ("link", vec![("rel", "canonical"), ("href", "/shoes/trail")]),
+            ("script", vec![("type", "application/ld+json")]),
+            ("script", vec![("type", "application/ld+json")]),
+            ("h1", vec![]),
+            ("div", vec![("itemscope", ""), ("itemtype", "https://schema.org/Offer")]),
+        ]
+        .into_iter()
+        .map(|(tag, attributes)| {
+            let element = document.create_element(tag).unwrap();
+            for (name, value) in attributes {
+                element.set_attribute(name, value).unwrap();
+            }
+            let parent: &web_sys::Node = if tag == "h1" || tag == "div" { &body } else { &head };
+            parent.append_child(&element).unwrap();
+            element
+        })
+        .collect();
+        added[2].set_text_content(Some(r#"{"@type": "Product", "name": "Trail shoes"}"#));
+        added[3].set_text_content(Some("{not json"));
+        added[4].set_text_content(Some("  Trail\n shoes "));
+
+        let audit: serde_json::Value = serde_json::from_str(&audit_seo().unwrap()).unwrap();
+        assert_eq!(audit["title"], "Trail shoes");
+        assert_eq!(audit["title_length"], 11);
+        assert_eq!(audit["meta_description_length"], 88);
+        assert!(audit["canonical"].as_str().unwrap().ends_with("/shoes/trail"), "The canonical link is resolved");
+        assert!(audit["h1"].as_array().unwrap().contains(&serde_json::json!("Trail shoes")));
+        assert!(audit["structured_data"]["json_ld_types"].as_array().unwrap().contains(&serde_json::json!("Product")));
+        assert!(audit["structured_data"]["json_ld_errors"].as_u64().unwrap() >= 1);
+        assert!(audit["structured_data"]["microdata_items"].as_u64().unwrap() >= 1);
+        assert!(audit["issues"].as_array().unwrap().contains(&serde_json::json!("Title is 11 characters long; 30 to 60 are shown in full")));
+
+        for element in added {
+            element.remove();
+        }
+        document.set_title(&previous_title);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_extract_structured_data() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let container = document.create_element("div").unwrap();
+        container.set_inner_html(r#"
+            <script type="application/ld+json">{"@type": "Recipe", "name": "Pancakes"}</script>
+            <script type="application/ld+json">{"@type": </script>
+            <div itemscope itemtype="https://schema.org/Product" itemid="urn:sku:42">
+              <h2 itemprop="name"> Trail   shoes </h2>
+              <img itemprop="image" src="/img/shoes.png">
+              <meta itemprop="sku" content="42">
+              <div itemprop="offers" itemscope itemtype="https://schema.org/Offer">
+                <data itemprop="price" value="89.90">89,90 €</data>
+                <span itemprop="name">Offer name, not the product's</span>
+              </div>
+              <span itemprop="color material">Red</span>
+            </div>"#);
+        document.body().unwrap().append_child(&container).unwrap();
+
+        let data = extract_structured_data().unwrap();
+        assert!(data.json_ld.contains(&serde_json::json!({"@type": "Recipe", "name": "Pancakes"})));
+        assert!(data.json_ld_errors.iter().any(|error| error.starts_with("Block ")));
+
+        let product = data.microdata.iter().find(|item| item.id.as_deref() == Some("urn:sku:42")).expect("The product item is extracted");
+        assert_eq!(product.item_type, vec!["https://schema.org/Product"]);
+        assert_eq!(product.properties["name"], vec![serde_json::json!("Trail shoes")]);
+        assert!(product.properties["image"][0].as_str().unwrap().ends_with("/img/shoes.png"), "URLs are resolved");
+        assert_eq!(product.properties["sku"], vec![serde_json::json!("42")]);
+        assert_eq!(product.properties["color"], product.properties["material"]);
+        let offer = &product.properties["offers"][0];
+        assert_eq!(offer["type"], serde_json::json!(["https://schema.org/Offer"]));
+        assert_eq!(offer["properties"]["price"], serde_json::json!(["89.90"]));
+        assert!(!data.microdata.iter().any(|item| item.item_type == vec!["https://schema.org/Offer"]), "Nested items are not top-level items");
+
+        container.remove();
+    }
+}
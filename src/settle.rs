@@ -0,0 +1,121 @@
+use crate::dom_utils::{self, DomError};
+use gloo_timers::future::TimeoutFuture;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{MutationObserver, MutationObserverInit};
+
+/// Default time the page must go without DOM mutations to count as settled.
+pub const DEFAULT_SETTLE_QUIET_MS: u32 = 100;
+/// Default longest wait for the page to settle.
+pub const DEFAULT_SETTLE_TIMEOUT_MS: u32 = 2000;
+
+/// How long the page is waited for after interactions while auto-settle is enabled (see
+/// `RustAgent::set_auto_settle`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SettleConfig {
+    /// How long the page must go without DOM mutations to count as settled.
+    pub quiet_ms: u32,
+    /// How long the wait lasts at most; the page is then left as it is.
+    pub timeout_ms: u32,
+}
+
+impl Default for SettleConfig {
+    fn default() -> Self {
+        SettleConfig { quiet_ms: DEFAULT_SETTLE_QUIET_MS, timeout_ms: DEFAULT_SETTLE_TIMEOUT_MS }
+    }
+}
+
+/// Waits until the document has gone `quiet_ms` without mutations (nodes, attributes or text), or
+/// `timeout_ms` has elapsed. The wait sleeps on timers, so the promise callbacks and other
+/// microtasks an interaction queued run, and the mutations they make are seen, before the page can
+/// count as settled.
+///
+/// # Returns
+/// `true` if the page settled, `false` if it was still changing when the timeout elapsed (e.g. a
+/// clock or an animation updating it continuously).
+pub async fn wait_for_settle(config: &SettleConfig) -> Result<bool, DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    let root = document
+        .document_element()
+        .ok_or_else(|| DomError::JsError { message: "Document has no root element to observe".to_string() })?;
+    let started = js_sys::Date::now();
+    let last_mutation = Rc::new(Cell::new(started));
+    let on_mutation = {
+        let last_mutation = last_mutation.clone();
+        Closure::wrap(Box::new(move || last_mutation.set(js_sys::Date::now())) as Box<dyn FnMut()>)
+    };
+    let observer = MutationObserver::new(on_mutation.as_ref().unchecked_ref())?;
+    let options = MutationObserverInit::new();
+    options.set_child_list(true);
+    options.set_subtree(true);
+    options.set_attributes(true);
+    options.set_character_data(true);
+    observer.observe_with_options(&root, &options)?;
+
+    let (quiet_ms, timeout_ms) = (config.quiet_ms as f64, config.timeout_ms as f64);
+    let settled = loop {
+        let now = js_sys::Date::now();
+        if observer.take_records().length() > 0 {
+            last_mutation.set(now);
+        }
+        let quiet_for = now - last_mutation.get();
+        if quiet_for >= quiet_ms {
+            break true;
+        }
+        let elapsed = now - started;
+        if elapsed >= timeout_ms {
+            break false;
+        }
+        TimeoutFuture::new((quiet_ms - quiet_for).min(timeout_ms - elapsed).ceil().max(1.0) as u32).await;
+    };
+    observer.disconnect();
+    Ok(settled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // Runs `script` as the body of a function, e.g. to schedule changes of the page.
+    fn run_script(script: &str) {
+        js_sys::Function::new_no_args(script).call0(&JsValue::NULL).unwrap();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_wait_for_settle_waits_for_quiet() {
+        run_script(
+            "const status = document.createElement('p');
+             status.id = 'settle-status';
+             document.body.appendChild(status);
+             [40, 80, 120].forEach((delay, step) => setTimeout(() => { status.textContent = 'Step ' + step; }, delay));
+             Promise.resolve().then(() => status.setAttribute('data-loading', 'true'));",
+        );
+        let started = js_sys::Date::now();
+        let settled = wait_for_settle(&SettleConfig { quiet_ms: 70, timeout_ms: 2000 }).await.unwrap();
+        assert!(settled);
+        assert!(js_sys::Date::now() - started >= 190.0, "The wait lasts until 70ms after the last change");
+        let status = web_sys::window().unwrap().document().unwrap().get_element_by_id("settle-status").unwrap();
+        assert_eq!(status.text_content().as_deref(), Some("Step 2"));
+        status.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_wait_for_settle_is_bounded() {
+        run_script(
+            "const clock = document.createElement('span');
+             clock.id = 'settle-clock';
+             document.body.appendChild(clock);
+             globalThis.__settleClock = setInterval(() => { clock.textContent = String(Date.now()); }, 20);",
+        );
+        let started = js_sys::Date::now();
+        let settled = wait_for_settle(&SettleConfig { quiet_ms: 100, timeout_ms: 300 }).await.unwrap();
+        assert!(!settled, "A page changing every 20ms never goes 100ms without mutations");
+        assert!(js_sys::Date::now() - started < 1000.0);
+        run_script("clearInterval(globalThis.__settleClock); document.getElementById('settle-clock').remove();");
+    }
+}
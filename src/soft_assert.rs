@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+/// An assertion that did not hold during a run in soft-assert mode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AssertionFailure {
+    /// The assertion, e.g. `ASSERT_JSON $.items` or `LLM_ASSERT css:#status Is the order confirmed?`.
+    pub assertion: String,
+    pub message: String,
+}
+
+/// What the assertions of a run found, reported as the `assertion_report` of its result envelope.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AssertionReport {
+    /// Assertions that ran to a verdict, whether they held or not.
+    pub checked: u32,
+    pub failed: u32,
+    /// The assertions that did not hold, in the order they ran.
+    pub failures: Vec<AssertionFailure>,
+}
+
+/// Soft-assert mode, set with `RustAgent.set_soft_assertions`, and what the assertions of the
+/// current run found. While enabled, an assertion that does not hold is recorded instead of failing
+/// its task, so that a run audits the whole page instead of stopping at the first problem. Like the
+/// usage of `RunGuard`, the report is not shared by clones, so that scheduled and streamed runs
+/// report their own assertions.
+#[derive(Debug, Clone, Default)]
+pub struct SoftAssertions {
+    pub enabled: bool,
+    report: RefCell<AssertionReport>,
+}
+
+impl SoftAssertions {
+    /// Marks the start of a run: no assertion has been checked yet.
+    pub fn begin_run(&self) {
+        *self.report.borrow_mut() = AssertionReport::default();
+    }
+
+    /// Records the verdict of `assertion`: `None` if it held, or the message saying why it did not.
+    pub fn record(&self, assertion: &str, failure: Option<&str>) {
+        let mut report = self.report.borrow_mut();
+        report.checked += 1;
+        if let Some(message) = failure {
+            report.failed += 1;
+            report.failures.push(AssertionFailure { assertion: assertion.to_string(), message: message.to_string() });
+        }
+    }
+
+    /// Assertions of the current run that did not hold so far.
+    pub fn failed(&self) -> u32 {
+        self.report.borrow().failed
+    }
+
+    /// What the assertions of the current run found, or `None` while soft-assert mode is disabled.
+    pub fn report(&self) -> Option<AssertionReport> {
+        self.enabled.then(|| self.report.borrow().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_are_collected_per_run() {
+        let assertions = SoftAssertions { enabled: true, ..SoftAssertions::default() };
+        assertions.record("ASSERT_JSON $", None);
+        assertions.record("ASSERT_VISUAL_MATCH css:#cart cart 1%", Some("3.20% of pixels differ"));
+        let report = assertions.report().unwrap();
+        assert_eq!((report.checked, report.failed), (2, 1));
+        assert_eq!(report.failures[0].assertion, "ASSERT_VISUAL_MATCH css:#cart cart 1%");
+        assert_eq!(serde_json::to_value(&report).unwrap()["failures"][0]["message"], "3.20% of pixels differ");
+
+        assertions.begin_run();
+        assert_eq!(assertions.report(), Some(AssertionReport::default()));
+        assert_eq!(SoftAssertions::default().report(), None, "Nothing is reported while disabled");
+    }
+}
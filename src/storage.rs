@@ -0,0 +1,195 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransactionMode};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::dom_backend;
+use crate::dom_utils::DomError;
+
+/// Prefix of the keys written to `localStorage`, so that they don't clash with the page's own.
+pub const LOCAL_STORAGE_PREFIX: &str = "rustagent:";
+
+// IndexedDB object store holding the entries, keyed by the storage keys.
+const OBJECT_STORE: &str = "storage";
+
+/// Where persistent data, i.e. the run history and the baselines of `ASSERT_VISUAL_MATCH`, is
+/// kept: a key-value store of strings. Clones share the same data.
+#[derive(Debug, Clone)]
+pub enum Storage {
+    /// Kept in memory, until the page is unloaded.
+    Memory(Rc<RefCell<HashMap<String, String>>>),
+    /// The page's `localStorage`, under keys prefixed with `LOCAL_STORAGE_PREFIX`. Survives
+    /// reloads, but browsers limit it to a few megabytes per origin.
+    Local(web_sys::Storage),
+    /// An IndexedDB database, for larger data such as screenshots. Also available in workers.
+    IndexedDb(IdbDatabase),
+    /// Host-provided callbacks: `load(key)` returns the stored string, or `null`/`undefined` if
+    /// there is none; `save(key, value)` stores one. Both may return a `Promise`.
+    Custom { load: js_sys::Function, save: js_sys::Function },
+}
+
+impl Storage {
+    pub fn memory() -> Self {
+        Storage::Memory(Rc::default())
+    }
+
+    pub fn local_storage() -> Result<Self, DomError> {
+        let window = dom_backend::window().ok_or_else(|| DomError::JsError {
+            message: "localStorage is only available on the main thread".to_string(),
+        })?;
+        let local_storage = window.local_storage()?.ok_or_else(|| DomError::JsError {
+            message: "localStorage is not available".to_string(),
+        })?;
+        Ok(Storage::Local(local_storage))
+    }
+
+    /// Opens (creating it if needed) the IndexedDB database `database_name`.
+    pub async fn indexed_db(database_name: &str) -> Result<Self, DomError> {
+        Ok(Storage::IndexedDb(open_database(database_name).await?))
+    }
+
+    /// Opens the storage named by `kind`: `memory`, `local_storage` or `indexed_db`. `name` is the
+    /// IndexedDB database name and is ignored by the others.
+    pub async fn open(kind: &str, name: &str) -> Result<Self, DomError> {
+        match kind {
+            "memory" => Ok(Storage::memory()),
+            "local_storage" => Storage::local_storage(),
+            "indexed_db" => Storage::indexed_db(name).await,
+            _ => Err(DomError::JsError {
+                message: format!("Unknown storage '{}'. Expected memory, local_storage or indexed_db.", kind),
+            }),
+        }
+    }
+
+    /// Reads the value stored under `key`.
+    pub async fn get(&self, key: &str) -> Result<Option<String>, DomError> {
+        let value = match self {
+            Storage::Memory(entries) => return Ok(entries.borrow().get(key).cloned()),
+            Storage::Local(local_storage) => {
+                return Ok(local_storage.get_item(&format!("{}{}", LOCAL_STORAGE_PREFIX, key))?)
+            }
+            Storage::IndexedDb(database) => {
+                let store = database.transaction_with_str(OBJECT_STORE)?.object_store(OBJECT_STORE)?;
+                request_result(&store.get(&JsValue::from_str(key))?).await?
+            }
+            Storage::Custom { load, .. } => resolve(load.call1(&JsValue::NULL, &JsValue::from_str(key))?).await?,
+        };
+        if value.is_null() || value.is_undefined() {
+            return Ok(None);
+        }
+        value.as_string().map(Some).ok_or_else(|| DomError::JsTypeError {
+            message: format!("Storage returned a non-string value for '{}'", key),
+        })
+    }
+
+    /// Stores `value` under `key`.
+    ///
+    /// The write is issued as soon as the future is first polled, so writes are applied in the
+    /// order their futures start, e.g. the order they were passed to `spawn_local`.
+    pub async fn set(&self, key: &str, value: &str) -> Result<(), DomError> {
+        match self {
+            Storage::Memory(entries) => {
+                entries.borrow_mut().insert(key.to_string(), value.to_string());
+            }
+            Storage::Local(local_storage) => {
+                // Fails with a `QuotaExceededError` once the origin's quota is used up.
+                local_storage.set_item(&format!("{}{}", LOCAL_STORAGE_PREFIX, key), value)?;
+            }
+            Storage::IndexedDb(database) => {
+                let request = database
+                    .transaction_with_str_and_mode(OBJECT_STORE, IdbTransactionMode::Readwrite)?
+                    .object_store(OBJECT_STORE)?
+                    .put_with_key(&JsValue::from_str(value), &JsValue::from_str(key))?;
+                request_result(&request).await?;
+            }
+            Storage::Custom { save, .. } => {
+                resolve(save.call2(&JsValue::NULL, &JsValue::from_str(key), &JsValue::from_str(value))?).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Awaits `value` if it is a `Promise`, so storage callbacks may be synchronous or asynchronous.
+async fn resolve(value: JsValue) -> Result<JsValue, DomError> {
+    match value.dyn_into::<js_sys::Promise>() {
+        Ok(promise) => Ok(JsFuture::from(promise).await?),
+        Err(value) => Ok(value),
+    }
+}
+
+// Waits for an IndexedDB request to finish and returns its result.
+async fn request_result(request: &IdbRequest) -> Result<JsValue, DomError> {
+    let finished = js_sys::Promise::new(&mut |resolve, reject| {
+        request.set_onsuccess(Some(&resolve));
+        request.set_onerror(Some(&reject));
+    });
+    let outcome = JsFuture::from(finished).await;
+    request.set_onsuccess(None);
+    request.set_onerror(None);
+    match outcome {
+        Ok(_) => Ok(request.result()?),
+        Err(_) => Err(DomError::JsError {
+            message: match request.error() {
+                Ok(Some(error)) => format!("IndexedDB request failed: {}", error.message()),
+                _ => "IndexedDB request failed".to_string(),
+            },
+        }),
+    }
+}
+
+// Opens (creating it if needed) a storage database. `indexedDB` is looked up on the global
+// object, so this works in workers too.
+async fn open_database(name: &str) -> Result<IdbDatabase, DomError> {
+    let factory: IdbFactory = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("indexedDB"))?
+        .dyn_into()
+        .map_err(|_| DomError::JsError { message: "IndexedDB is not available".to_string() })?;
+    let request = factory.open_with_u32(name, 1)?;
+    let upgraded_request = request.clone();
+    let on_upgrade_needed = Closure::<dyn FnMut()>::new(move || {
+        if let Ok(database) = upgraded_request.result() {
+            if let Err(e) = database.unchecked_into::<IdbDatabase>().create_object_store(OBJECT_STORE) {
+                web_sys::console::warn_1(&format!("Failed to create the storage object store: {:?}", e.as_string()).into());
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+    let database = request_result(&request).await;
+    request.set_onupgradeneeded(None);
+    Ok(database?.unchecked_into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    async fn assert_round_trip(storage: &Storage) {
+        assert_eq!(storage.get("missing").await.unwrap(), None);
+        storage.set("greeting", "hello").await.unwrap();
+        storage.set("greeting", "hello again").await.unwrap();
+        assert_eq!(storage.get("greeting").await.unwrap().as_deref(), Some("hello again"));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_storages_round_trip() {
+        assert_round_trip(&Storage::open("memory", "").await.unwrap()).await;
+
+        let local_storage = Storage::open("local_storage", "").await.unwrap();
+        assert_round_trip(&local_storage).await;
+        let page_storage = web_sys::window().unwrap().local_storage().unwrap().unwrap();
+        assert_eq!(page_storage.get_item("rustagent:greeting").unwrap().as_deref(), Some("hello again"));
+        page_storage.remove_item("rustagent:greeting").unwrap();
+
+        let database_name = format!("rustagent-storage-test-{}", js_sys::Date::now());
+        assert_round_trip(&Storage::open("indexed_db", &database_name).await.unwrap()).await;
+        let reopened = Storage::open("indexed_db", &database_name).await.unwrap();
+        assert_eq!(reopened.get("greeting").await.unwrap().as_deref(), Some("hello again"));
+
+        assert!(Storage::open("cookies", "").await.is_err());
+    }
+}
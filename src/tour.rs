@@ -0,0 +1,293 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, Document, Element, MouseEvent};
+use serde::Deserialize;
+use std::cell::Cell;
+use std::rc::Rc;
+use gloo_timers::future::{TimeoutFuture, IntervalStream};
+use futures_util::stream::StreamExt; // For IntervalStream.next()
+use crate::dom_utils::{self, DomError};
+
+/// Id of the overlay container rendered while a tour step is shown.
+/// Only one tour step is displayed at a time; rendering a new step replaces the old overlay.
+const TOUR_OVERLAY_ID: &str = "rustagent-tour-overlay";
+/// Id of the "Next" button rendered inside the tour tooltip.
+const TOUR_NEXT_BUTTON_ID: &str = "rustagent-tour-next";
+/// Polling interval used while waiting for the user to press "Next".
+const POLL_INTERVAL_MS: u32 = 100;
+/// How long a non-blocking step stays on screen in `run_tour` before advancing.
+const DEFAULT_STEP_DURATION_MS: u32 = 3000;
+/// Vertical space reserved for the tooltip when deciding whether it fits below the element.
+const TOOLTIP_RESERVED_HEIGHT_PX: f64 = 140.0;
+
+/// A single step of a guided tour.
+///
+/// Tours are usually supplied by the host page as a JSON array, e.g.:
+/// `[{"selector": "css:#search", "text": "Search for anything here."},
+///   {"selector": "css:#profile", "text": "Your account lives here.", "wait_for_next": false, "duration_ms": 2000}]`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TourStep {
+    /// The CSS selector or XPath expression of the element to highlight.
+    pub selector: String,
+    /// The explanatory text shown in the tooltip next to the highlighted element.
+    pub text: String,
+    /// If `true` (the default), the step renders a "Next" button and waits for the user to press it.
+    #[serde(default = "default_wait_for_next")]
+    pub wait_for_next: bool,
+    /// For steps that do not wait for the user, how long the step stays visible (in milliseconds).
+    /// Defaults to 3000ms when not provided.
+    #[serde(default)]
+    pub duration_ms: Option<u32>,
+}
+
+fn default_wait_for_next() -> bool {
+    true
+}
+
+// Builds an absolutely positioned child element of the overlay with the given inline style.
+fn create_styled_element(document: &Document, tag: &str, style: &str) -> Result<Element, DomError> {
+    let element = document.create_element(tag)?;
+    element.set_attribute("style", style)?;
+    Ok(element)
+}
+
+/// Renders a tour step: highlights the element identified by `selector` and shows `text`
+/// in a tooltip next to it.
+///
+/// Any previously rendered tour step is removed first. The element is scrolled into view
+/// before it is measured so the highlight lines up with what the user sees.
+///
+/// # Arguments
+/// * `selector`: A string representing a CSS selector or an XPath expression.
+///   If no prefix is provided, it defaults to a CSS selector.
+/// * `text`: The explanatory text displayed in the tooltip.
+/// * `wait_for_next`: If `true`, a "Next" button is rendered and this function only resolves
+///   once the user presses it (or the overlay is removed, e.g. by `end_tour`).
+///   If `false`, the overlay is left on screen and the function resolves immediately.
+///
+/// # Returns
+/// * `Ok(true)` if the step was shown and (when waiting) the user pressed "Next".
+/// * `Ok(false)` if the overlay was removed before the user pressed "Next".
+/// * `Err(DomError)` if the element is not found or the overlay could not be rendered.
+pub async fn show_tour_step(selector: &str, text: &str, wait_for_next: bool) -> Result<bool, DomError> {
+    console::log_1(&format!("Attempting to show tour step for element with selector: {}", selector).into());
+    let (window, document) = dom_utils::get_window_document()?;
+    let element = dom_utils::get_element(&document, selector)?;
+
+    end_tour()?;
+    element.scroll_into_view();
+    let rect = element.get_bounding_client_rect();
+    let viewport_height = window.inner_height()?.as_f64().unwrap_or(0.0);
+
+    // The overlay itself ignores pointer events so the page stays usable underneath;
+    // only the tooltip (and its button) receives clicks.
+    let overlay = create_styled_element(
+        &document,
+        "div",
+        "position:fixed;top:0;left:0;width:100%;height:100%;z-index:2147483646;pointer-events:none;",
+    )?;
+    overlay.set_id(TOUR_OVERLAY_ID);
+
+    // The highlight box dims everything except the target element via a large box-shadow.
+    let highlight = create_styled_element(
+        &document,
+        "div",
+        &format!(
+            "position:fixed;top:{}px;left:{}px;width:{}px;height:{}px;border:2px solid #4a90e2;border-radius:4px;box-shadow:0 0 0 9999px rgba(0,0,0,0.5);",
+            rect.top() - 4.0, rect.left() - 4.0, rect.width() + 8.0, rect.height() + 8.0
+        ),
+    )?;
+    overlay.append_child(&highlight)?;
+
+    // Place the tooltip below the element, or above it when there is not enough room.
+    let vertical_position = if rect.bottom() + TOOLTIP_RESERVED_HEIGHT_PX > viewport_height && viewport_height > 0.0 {
+        format!("bottom:{}px;", viewport_height - rect.top() + 12.0)
+    } else {
+        format!("top:{}px;", rect.bottom() + 12.0)
+    };
+    let tooltip = create_styled_element(
+        &document,
+        "div",
+        &format!(
+            "position:fixed;{}left:{}px;max-width:320px;padding:12px 16px;background:#fff;color:#222;border-radius:6px;box-shadow:0 2px 12px rgba(0,0,0,0.3);font:14px sans-serif;pointer-events:auto;",
+            vertical_position, rect.left().max(8.0)
+        ),
+    )?;
+    let text_element = document.create_element("p")?;
+    text_element.set_text_content(Some(text));
+    tooltip.append_child(&text_element)?;
+    overlay.append_child(&tooltip)?;
+
+    if !wait_for_next {
+        document
+            .body()
+            .ok_or_else(|| DomError::JsError { message: "Failed to get document body".to_string() })?
+            .append_child(&overlay)?;
+        console::log_1(&format!("Successfully showed tour step for element with selector: {}", selector).into());
+        return Ok(true);
+    }
+
+    let next_button = document.create_element("button")?;
+    next_button.set_id(TOUR_NEXT_BUTTON_ID);
+    next_button.set_text_content(Some("Next"));
+    tooltip.append_child(&next_button)?;
+    document
+        .body()
+        .ok_or_else(|| DomError::JsError { message: "Failed to get document body".to_string() })?
+        .append_child(&overlay)?;
+
+    let next_pressed = Rc::new(Cell::new(false));
+    let next_pressed_in_handler = next_pressed.clone();
+    let on_next = Closure::wrap(Box::new(move |_event: MouseEvent| {
+        next_pressed_in_handler.set(true);
+    }) as Box<dyn FnMut(MouseEvent)>);
+    next_button.add_event_listener_with_callback("click", on_next.as_ref().unchecked_ref())?;
+
+    console::log_1(&format!("Waiting for user to continue tour step for element with selector: {}", selector).into());
+    let mut interval = IntervalStream::new(POLL_INTERVAL_MS);
+    let acknowledged = loop {
+        if next_pressed.get() {
+            break true;
+        }
+        if !overlay.is_connected() {
+            // The overlay was removed by someone else (e.g. `end_tour`), so stop waiting.
+            break false;
+        }
+        StreamExt::next(&mut interval).await;
+    };
+
+    next_button.remove_event_listener_with_callback("click", on_next.as_ref().unchecked_ref())?;
+    overlay.remove();
+
+    console::log_1(&format!("Tour step for element with selector '{}' finished. Acknowledged: {}", selector, acknowledged).into());
+    Ok(acknowledged)
+}
+
+/// Removes the currently displayed tour step, if any.
+///
+/// # Returns
+/// * `Ok(())` whether or not a tour step was displayed.
+/// * `Err(DomError)` if the document could not be accessed.
+#[wasm_bindgen]
+pub fn end_tour() -> Result<(), DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    if let Some(overlay) = document.get_element_by_id(TOUR_OVERLAY_ID) {
+        overlay.remove();
+        console::log_1(&"Successfully removed tour overlay".into());
+    }
+    Ok(())
+}
+
+/// Runs a full guided tour, showing each step in order.
+///
+/// Steps with `wait_for_next` wait for the user to press "Next"; other steps stay on screen
+/// for their `duration_ms` (3000ms by default). If the user dismisses a step (the overlay is
+/// removed before "Next" is pressed), the tour stops early. The overlay is always removed
+/// once the tour finishes.
+///
+/// # Arguments
+/// * `steps`: The tour steps to show, in order.
+///
+/// # Returns
+/// * `Ok(usize)`: The number of steps that were completed.
+/// * `Err(DomError)` if a step's element is not found or the overlay could not be rendered.
+pub async fn run_tour(steps: &[TourStep]) -> Result<usize, DomError> {
+    let mut completed = 0;
+    for step in steps {
+        if step.wait_for_next {
+            if !show_tour_step(&step.selector, &step.text, true).await? {
+                console::log_1(&format!("Tour dismissed at step {} of {}", completed + 1, steps.len()).into());
+                break;
+            }
+        } else {
+            show_tour_step(&step.selector, &step.text, false).await?;
+            TimeoutFuture::new(step.duration_ms.unwrap_or(DEFAULT_STEP_DURATION_MS)).await;
+        }
+        completed += 1;
+    }
+    end_tour()?;
+    Ok(completed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+    use web_sys::HtmlElement;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn setup_target(document: &Document, id: &str) -> Element {
+        let el = document.create_element("div").unwrap();
+        el.set_id(id);
+        el.set_text_content(Some("tour target"));
+        document.body().unwrap().append_child(&el).unwrap();
+        el
+    }
+
+    #[test]
+    fn test_tour_step_deserialize_defaults() {
+        let steps: Vec<TourStep> = serde_json::from_str(
+            r#"[{"selector": "css:#a", "text": "First"}, {"selector": "css:#b", "text": "Second", "wait_for_next": false, "duration_ms": 500}]"#,
+        ).unwrap();
+        assert_eq!(steps[0], TourStep { selector: "css:#a".to_string(), text: "First".to_string(), wait_for_next: true, duration_ms: None });
+        assert_eq!(steps[1], TourStep { selector: "css:#b".to_string(), text: "Second".to_string(), wait_for_next: false, duration_ms: Some(500) });
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_show_tour_step_without_wait_renders_overlay() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let target = setup_target(&document, "tour-target-show");
+
+        let result = show_tour_step("css:#tour-target-show", "Look here <b>now</b>", false).await;
+        assert_eq!(result, Ok(true));
+        let overlay = document.get_element_by_id(TOUR_OVERLAY_ID).expect("Overlay should be rendered");
+        // Text is rendered as text content, not markup.
+        assert!(overlay.text_content().unwrap_or_default().contains("Look here <b>now</b>"));
+        assert!(document.get_element_by_id(TOUR_NEXT_BUTTON_ID).is_none());
+
+        end_tour().unwrap();
+        assert!(document.get_element_by_id(TOUR_OVERLAY_ID).is_none());
+        target.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_show_tour_step_waits_for_next() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let target = setup_target(&document, "tour-target-wait");
+
+        // Press "Next" shortly after the step is rendered.
+        wasm_bindgen_futures::spawn_local(async {
+            TimeoutFuture::new(200).await;
+            let (_window, document) = dom_utils::get_window_document().unwrap();
+            let button = document.get_element_by_id(TOUR_NEXT_BUTTON_ID).unwrap();
+            button.dyn_into::<HtmlElement>().unwrap().click();
+        });
+
+        let result = show_tour_step("css:#tour-target-wait", "Press next", true).await;
+        assert_eq!(result, Ok(true));
+        assert!(document.get_element_by_id(TOUR_OVERLAY_ID).is_none());
+        target.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_show_tour_step_dismissed_by_end_tour() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let target = setup_target(&document, "tour-target-dismiss");
+
+        wasm_bindgen_futures::spawn_local(async {
+            TimeoutFuture::new(200).await;
+            end_tour().unwrap();
+        });
+
+        let result = show_tour_step("css:#tour-target-dismiss", "Dismiss me", true).await;
+        assert_eq!(result, Ok(false));
+        target.remove();
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_show_tour_step_element_not_found() {
+        let result = show_tour_step("css:#tour-target-missing", "Missing", false).await;
+        assert_eq!(result, Err(DomError::ElementNotFound { selector: "css:#tour-target-missing".to_string(), message: None }));
+    }
+}
@@ -0,0 +1,143 @@
+use wasm_bindgen::JsCast;
+use web_sys::{console, Element, HtmlInputElement, HtmlSelectElement};
+use std::cell::RefCell;
+use crate::dom_utils;
+
+/// A change about to be made by a DOM command, with what is needed to undo it.
+///
+/// Entries keep the element itself rather than its selector, since the change being
+/// recorded (e.g. setting a `class`) may stop the selector from matching.
+#[derive(Debug, Clone)]
+enum UndoEntry {
+    /// An attribute is set by `SETATTRIBUTE`; `previous` is `None` if it was absent.
+    Attribute { element: Element, attribute_name: String, previous: Option<String> },
+    /// The value of an `<input>` is changed by `TYPE` (or `TYPE_IF_EXISTS`).
+    InputValue { element: HtmlInputElement, previous: String },
+    /// The selected value of a `<select>` is changed by `SELECTOPTION`.
+    SelectValue { element: HtmlSelectElement, previous: String },
+}
+
+/// Records the original attribute and form values of elements before commands change them,
+/// so that the changes made during the last run can be undone with `rollback`.
+///
+/// Recording is best effort: if the element cannot be found (or has the wrong type), nothing is
+/// recorded, since the command itself will then fail without changing the page.
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: RefCell<Vec<UndoEntry>>,
+}
+
+impl Journal {
+    /// Forgets every recorded change, e.g. at the start of a new run.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    /// Records the current value of an attribute before it is set.
+    pub fn record_attribute(&self, selector: &str, attribute_name: &str) {
+        if let Some(element) = find_element(selector) {
+            let previous = element.get_attribute(attribute_name);
+            self.entries.borrow_mut().push(UndoEntry::Attribute {
+                element,
+                attribute_name: attribute_name.to_string(),
+                previous,
+            });
+        }
+    }
+
+    /// Records the current value of an `<input>` before text is typed into it.
+    pub fn record_input_value(&self, selector: &str) {
+        if let Some(element) = find_element(selector).and_then(|e| e.dyn_into::<HtmlInputElement>().ok()) {
+            let previous = element.value();
+            self.entries.borrow_mut().push(UndoEntry::InputValue { element, previous });
+        }
+    }
+
+    /// Records the current value of a `<select>` before another option is selected.
+    pub fn record_select_value(&self, selector: &str) {
+        if let Some(element) = find_element(selector).and_then(|e| e.dyn_into::<HtmlSelectElement>().ok()) {
+            let previous = element.value();
+            self.entries.borrow_mut().push(UndoEntry::SelectValue { element, previous });
+        }
+    }
+
+    /// Restores every recorded original value, newest first, then clears the journal.
+    ///
+    /// Restoring in reverse order means an element changed several times ends up with the
+    /// value it had before the first change. Failures to restore a single entry are logged
+    /// and do not stop the rollback.
+    ///
+    /// # Returns
+    /// The number of changes that were restored.
+    pub fn rollback(&self) -> usize {
+        let entries: Vec<UndoEntry> = self.entries.borrow_mut().drain(..).collect();
+        let mut restored = 0;
+        for entry in entries.into_iter().rev() {
+            let outcome = match entry {
+                UndoEntry::Attribute { element, attribute_name, previous } => match previous {
+                    Some(value) => element.set_attribute(&attribute_name, &value),
+                    None => element.remove_attribute(&attribute_name),
+                },
+                UndoEntry::InputValue { element, previous } => {
+                    element.set_value(&previous);
+                    Ok(())
+                }
+                UndoEntry::SelectValue { element, previous } => {
+                    element.set_value(&previous);
+                    Ok(())
+                }
+            };
+            match outcome {
+                Ok(()) => restored += 1,
+                Err(e) => console::warn_1(&format!("Failed to restore a recorded change during rollback: {:?}", e.as_string()).into()),
+            }
+        }
+        console::log_1(&format!("Rolled back {} recorded changes", restored).into());
+        restored
+    }
+}
+
+// Resolves a selector against the current document, or `None` if it does not match.
+fn find_element(selector: &str) -> Option<Element> {
+    let (_window, document) = dom_utils::get_window_document().ok()?;
+    dom_utils::get_element(&document, selector).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    fn test_rollback_restores_attributes_and_values() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let input = document.create_element("input").unwrap();
+        input.set_id("journal-input");
+        input.set_attribute("class", "original").unwrap();
+        document.body().unwrap().append_child(&input).unwrap();
+        let input_element = input.clone().dyn_into::<HtmlInputElement>().unwrap();
+        input_element.set_value("before");
+
+        let journal = Journal::default();
+        journal.record_attribute("css:#journal-input", "class");
+        input.set_attribute("class", "changed").unwrap();
+        journal.record_attribute("css:#journal-input", "data-added");
+        input.set_attribute("data-added", "yes").unwrap();
+        journal.record_input_value("css:#journal-input");
+        input_element.set_value("first");
+        journal.record_input_value("css:#journal-input");
+        input_element.set_value("second");
+        journal.record_select_value("css:#journal-input"); // Not a <select>: nothing recorded
+        journal.record_attribute("css:#journal-missing", "class"); // Not found: nothing recorded
+
+        assert_eq!(journal.rollback(), 4);
+        assert_eq!(input.get_attribute("class"), Some("original".to_string()));
+        assert_eq!(input.get_attribute("data-added"), None);
+        assert_eq!(input_element.value(), "before");
+        assert_eq!(journal.rollback(), 0, "A rollback should clear the journal");
+
+        input.remove();
+    }
+}
@@ -0,0 +1,371 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, EventTarget, MutationObserver, MutationObserverInit};
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use crate::dom_backend;
+use crate::dom_utils::{self, DomError};
+
+/// Wildcard matching any run of characters in a `url_matches` pattern.
+const URL_WILDCARD: char = '*';
+
+/// The page change that starts a trigger's workflow.
+///
+/// Given as a single-key JSON object, e.g. `{"selector_appears": "css:.cookie-banner"}`,
+/// `{"url_matches": "https://shop.example/checkout*"}` or `{"event": "cart:updated"}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerCondition {
+    /// Fires when an element matching the selector is added to the page (or already present
+    /// when the trigger is added). Fires again only after the element has disappeared.
+    SelectorAppears(String),
+    /// Fires when the page URL starts matching the pattern, where `*` matches any characters.
+    /// Checked on `popstate`, `hashchange` and DOM changes, so client-side routing is noticed too.
+    UrlMatches(String),
+    /// Fires every time a DOM event with this name (e.g. a `CustomEvent`) reaches the document.
+    Event(String),
+}
+
+/// A trigger registered by the host page with `RustAgent::add_trigger`, e.g.:
+/// `{"workflow": "dismiss_cookies", "when": {"selector_appears": "css:#cookie-banner"}, "once": true}`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TriggerDefinition {
+    /// The name of the registered workflow to run.
+    pub workflow: String,
+    /// Parameter values for the workflow; non-string values are converted to their JSON text.
+    #[serde(default)]
+    pub params: serde_json::Map<String, serde_json::Value>,
+    /// The page change that runs the workflow.
+    pub when: TriggerCondition,
+    /// If `true`, the trigger is removed after it fires once. Defaults to `false`.
+    #[serde(default)]
+    pub once: bool,
+}
+
+/// The work started when a trigger fires.
+pub type TriggerRun = Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()>>>>;
+
+// An event listener of a trigger: its target, the event name and the closure it calls.
+type Listener = (EventTarget, String, Closure<dyn FnMut()>);
+
+// Shared state of an active trigger. Listeners only hold weak references to it, so the
+// handle returned to JavaScript is what keeps a trigger alive until it is cancelled.
+struct TriggerState {
+    condition: TriggerCondition,
+    once: bool,
+    run: TriggerRun,
+    active: Cell<bool>,
+    // Whether the condition held at the last check; conditions fire on the change to `true`.
+    matched: Cell<bool>,
+    running: Cell<bool>,
+    fire_count: Cell<u32>,
+    observer: RefCell<Option<MutationObserver>>,
+    mutation_callback: RefCell<Option<Closure<dyn FnMut()>>>,
+    listeners: RefCell<Vec<Listener>>,
+}
+
+impl Drop for TriggerState {
+    fn drop(&mut self) {
+        // Listeners must not outlive the closures they call.
+        detach(self);
+    }
+}
+
+/// Handle to a trigger added with `RustAgent::add_trigger`.
+///
+/// The trigger stays active as long as this handle is alive; call `cancel()` to remove it.
+#[wasm_bindgen]
+pub struct TriggerHandle {
+    state: Rc<TriggerState>,
+}
+
+impl fmt::Debug for TriggerHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TriggerHandle")
+            .field("condition", &self.state.condition)
+            .field("active", &self.state.active.get())
+            .field("fire_count", &self.state.fire_count.get())
+            .finish()
+    }
+}
+
+#[wasm_bindgen]
+impl TriggerHandle {
+    /// Removes the trigger's observers and listeners. A workflow run already started is allowed to finish.
+    #[wasm_bindgen]
+    pub fn cancel(&self) {
+        detach(&self.state);
+    }
+
+    /// `true` until the trigger is cancelled or, for a `once` trigger, has fired.
+    #[wasm_bindgen]
+    pub fn is_active(&self) -> bool {
+        self.state.active.get()
+    }
+
+    /// The number of times the trigger has fired so far.
+    #[wasm_bindgen]
+    pub fn fire_count(&self) -> u32 {
+        self.state.fire_count.get()
+    }
+}
+
+/// Returns `true` if `url` matches `pattern`, where `*` matches any run of characters
+/// (including none) and every other character must match exactly.
+pub fn url_matches(pattern: &str, url: &str) -> bool {
+    let parts: Vec<&str> = pattern.split(URL_WILDCARD).collect();
+    if parts.len() == 1 {
+        return pattern == url; // No wildcard: the whole URL must equal the pattern
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !url.starts_with(first) {
+        return false;
+    }
+    // Match the parts between wildcards left to right, each as early as possible, leaving
+    // the rest of the URL for the last part, which must end it.
+    let mut rest = &url[first.len()..];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Starts watching the page for `condition`, calling `run` every time it fires.
+///
+/// A firing while the previous run is still in progress is skipped, so a workflow that
+/// changes the page cannot pile up runs of itself.
+///
+/// # Returns
+/// * `Ok(TriggerHandle)` once the observers and listeners are installed. Conditions that
+///   already hold (a present element, a matching URL) fire right away.
+/// * `Err(DomError)` if the selector is invalid or the listeners could not be installed.
+pub fn start(condition: TriggerCondition, once: bool, run: TriggerRun) -> Result<TriggerHandle, DomError> {
+    let (window, document) = dom_utils::get_window_document()?;
+    if let TriggerCondition::SelectorAppears(selector) = &condition {
+        if let Err(e @ DomError::InvalidSelector { .. }) = dom_utils::get_element(&document, selector) {
+            return Err(e);
+        }
+    }
+
+    let state = Rc::new(TriggerState {
+        condition,
+        once,
+        run,
+        active: Cell::new(true),
+        matched: Cell::new(false),
+        running: Cell::new(false),
+        fire_count: Cell::new(0),
+        observer: RefCell::new(None),
+        mutation_callback: RefCell::new(None),
+        listeners: RefCell::new(Vec::new()),
+    });
+
+    match &state.condition {
+        TriggerCondition::SelectorAppears(_) => observe_mutations(&state, true)?,
+        TriggerCondition::UrlMatches(_) => {
+            observe_mutations(&state, false)?;
+            for event_name in ["popstate", "hashchange"] {
+                let weak = Rc::downgrade(&state);
+                listen(&state, window.clone().into(), event_name, move || with_state(&weak, check))?;
+            }
+        }
+        TriggerCondition::Event(event_name) => {
+            let weak = Rc::downgrade(&state);
+            listen(&state, document.into(), event_name, move || with_state(&weak, fire))?;
+        }
+    }
+    console::log_1(&format!("Trigger added: {:?}", state.condition).into());
+    check(&state);
+    Ok(TriggerHandle { state })
+}
+
+// Runs `f` with the trigger state if the trigger has not been dropped.
+fn with_state(weak: &Weak<TriggerState>, f: fn(&Rc<TriggerState>)) {
+    if let Some(state) = weak.upgrade() {
+        f(&state);
+    }
+}
+
+// Registers a listener for `event_name` on `target`, kept until the trigger is detached.
+fn listen(state: &Rc<TriggerState>, target: EventTarget, event_name: &str, callback: impl FnMut() + 'static) -> Result<(), DomError> {
+    let closure = Closure::wrap(Box::new(callback) as Box<dyn FnMut()>);
+    target.add_event_listener_with_callback(event_name, closure.as_ref().unchecked_ref())?;
+    state.listeners.borrow_mut().push((target, event_name.to_string(), closure));
+    Ok(())
+}
+
+// Re-checks the condition whenever the document changes. Attribute changes are only observed
+// for selector conditions, since they can make a selector like `.modal.open` start matching.
+fn observe_mutations(state: &Rc<TriggerState>, attributes: bool) -> Result<(), DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    let root = document
+        .document_element()
+        .ok_or_else(|| DomError::JsError { message: "Document has no root element to observe".to_string() })?;
+    let weak = Rc::downgrade(state);
+    let closure = Closure::wrap(Box::new(move || with_state(&weak, check)) as Box<dyn FnMut()>);
+    let observer = MutationObserver::new(closure.as_ref().unchecked_ref())?;
+    let options = MutationObserverInit::new();
+    options.set_child_list(true);
+    options.set_subtree(true);
+    options.set_attributes(attributes);
+    observer.observe_with_options(&root, &options)?;
+    *state.observer.borrow_mut() = Some(observer);
+    *state.mutation_callback.borrow_mut() = Some(closure);
+    Ok(())
+}
+
+// Evaluates a selector or URL condition and fires on its change from not holding to holding.
+fn check(state: &Rc<TriggerState>) {
+    if !state.active.get() {
+        return;
+    }
+    let holds = match &state.condition {
+        TriggerCondition::SelectorAppears(selector) => dom_utils::get_window_document()
+            .map(|(_window, document)| dom_utils::get_element(&document, selector).is_ok())
+            .unwrap_or(false),
+        TriggerCondition::UrlMatches(pattern) => dom_backend::window()
+            .and_then(|window| window.location().href().ok())
+            .map(|url| url_matches(pattern, &url))
+            .unwrap_or(false),
+        TriggerCondition::Event(_) => return,
+    };
+    if holds && !state.matched.get() {
+        fire(state);
+    }
+    state.matched.set(holds);
+}
+
+// Starts a run of the trigger's work, unless one is already in progress.
+fn fire(state: &Rc<TriggerState>) {
+    if !state.active.get() {
+        return;
+    }
+    if state.running.get() {
+        console::log_1(&format!("Trigger {:?} fired while its previous run is in progress; skipping", state.condition).into());
+        return;
+    }
+    state.running.set(true);
+    state.fire_count.set(state.fire_count.get() + 1);
+    if state.once {
+        state.active.set(false);
+    }
+    console::log_1(&format!("Trigger {:?} fired", state.condition).into());
+    let state = state.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        (state.run)().await;
+        state.running.set(false);
+        if state.once {
+            detach(&state);
+        }
+    });
+}
+
+// Disconnects the observer and removes the listeners of a trigger.
+fn detach(state: &TriggerState) {
+    state.active.set(false);
+    if let Some(observer) = state.observer.borrow_mut().take() {
+        observer.disconnect();
+    }
+    state.mutation_callback.borrow_mut().take();
+    for (target, event_name, closure) in state.listeners.borrow_mut().drain(..) {
+        let _ = target.remove_event_listener_with_callback(&event_name, closure.as_ref().unchecked_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gloo_timers::future::TimeoutFuture;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    // Returns a trigger run that counts its calls in `calls`.
+    fn counting_run(calls: &Rc<Cell<u32>>) -> TriggerRun {
+        let calls = calls.clone();
+        Box::new(move || {
+            calls.set(calls.get() + 1);
+            Box::pin(std::future::ready(()))
+        })
+    }
+
+    #[test]
+    fn test_url_matches() {
+        assert!(url_matches("https://shop.test/checkout", "https://shop.test/checkout"));
+        assert!(!url_matches("https://shop.test/checkout", "https://shop.test/checkout/pay"));
+        assert!(url_matches("https://shop.test/checkout*", "https://shop.test/checkout/pay"));
+        assert!(url_matches("*/checkout*", "https://shop.test/checkout?step=2"));
+        assert!(url_matches("*#/orders/*/edit", "https://app.test/#/orders/42/edit"));
+        assert!(!url_matches("*#/orders/*/edit", "https://app.test/#/orders/42"));
+        assert!(url_matches("*", ""));
+        assert!(!url_matches("*a*a", "xa"), "The same character cannot match two pattern parts");
+    }
+
+    #[test]
+    fn test_trigger_definition_parsing() {
+        let definition: TriggerDefinition = serde_json::from_str(
+            r#"{"workflow": "dismiss", "when": {"selector_appears": "css:#banner"}, "params": {"retries": 2}}"#,
+        ).unwrap();
+        assert_eq!(definition.when, TriggerCondition::SelectorAppears("css:#banner".to_string()));
+        assert!(!definition.once);
+        assert_eq!(crate::workflow::params_from_json(&definition.params).get("retries").map(String::as_str), Some("2"));
+        assert!(serde_json::from_str::<TriggerDefinition>(r#"{"workflow": "w", "when": {"on_hover": "css:a"}}"#).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_selector_trigger_fires_when_element_appears() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let handle = start(TriggerCondition::SelectorAppears("css:#trigger-banner".to_string()), false, counting_run(&calls)).unwrap();
+        TimeoutFuture::new(20).await;
+        assert_eq!(calls.get(), 0);
+
+        let banner = document.create_element("div").unwrap();
+        banner.set_id("trigger-banner");
+        document.body().unwrap().append_child(&banner).unwrap();
+        TimeoutFuture::new(50).await;
+        assert_eq!(calls.get(), 1);
+        banner.set_attribute("class", "still-there").unwrap();
+        TimeoutFuture::new(50).await;
+        assert_eq!(calls.get(), 1, "Should not fire again while the element stays present");
+
+        banner.remove();
+        TimeoutFuture::new(50).await;
+        document.body().unwrap().append_child(&banner).unwrap();
+        TimeoutFuture::new(50).await;
+        assert_eq!(handle.fire_count(), 2);
+
+        handle.cancel();
+        banner.remove();
+        TimeoutFuture::new(50).await;
+        document.body().unwrap().append_child(&banner).unwrap();
+        TimeoutFuture::new(50).await;
+        assert_eq!(calls.get(), 2, "A cancelled trigger should not fire");
+        banner.remove();
+
+        assert!(matches!(
+            start(TriggerCondition::SelectorAppears("css:[[".to_string()), false, counting_run(&calls)),
+            Err(DomError::InvalidSelector { .. })
+        ));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_event_trigger_fires_once() {
+        let (_window, document) = dom_utils::get_window_document().unwrap();
+        let calls = Rc::new(Cell::new(0));
+        let handle = start(TriggerCondition::Event("rustagent:test".to_string()), true, counting_run(&calls)).unwrap();
+        document.dispatch_event(&web_sys::Event::new("rustagent:test").unwrap()).unwrap();
+        TimeoutFuture::new(20).await;
+        document.dispatch_event(&web_sys::Event::new("rustagent:test").unwrap()).unwrap();
+        TimeoutFuture::new(20).await;
+        assert_eq!(calls.get(), 1);
+        assert!(!handle.is_active());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+/// Placeholder replaced, in the steps of a `CATCH` block, by the error that led to them.
+pub const ERROR_PLACEHOLDER: &str = "{{error}}";
+
+/// A line of a task list that groups tasks instead of running anything.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockMarker {
+    /// `TRY`: starts the tasks whose failure is handled.
+    Try,
+    /// `CATCH`: starts the tasks run when a task of the `TRY` part fails.
+    Catch,
+    /// `END_TRY`: ends the block.
+    EndTry,
+}
+
+/// The marker a task stands for, if any. Markers are matched case-insensitively and take no arguments.
+pub fn marker(task: &str) -> Option<BlockMarker> {
+    match task.trim().to_uppercase().as_str() {
+        "TRY" => Some(BlockMarker::Try),
+        "CATCH" => Some(BlockMarker::Catch),
+        "END_TRY" => Some(BlockMarker::EndTry),
+        _ => None,
+    }
+}
+
+/// Checks that the `TRY` blocks of a task list are well formed: every `TRY` is closed by an
+/// `END_TRY`, with at most one `CATCH` in between. Blocks may be nested.
+///
+/// # Returns
+/// `Ok(())`, or an error naming the (1-based) task at fault.
+pub fn check_blocks(tasks: &[String]) -> Result<(), String> {
+    // Whether each open block has reached its `CATCH`, innermost last.
+    let mut open: Vec<bool> = Vec::new();
+    for (index, task) in tasks.iter().enumerate() {
+        match marker(task) {
+            Some(BlockMarker::Try) => open.push(false),
+            Some(BlockMarker::Catch) => match open.last_mut() {
+                Some(in_catch) if !*in_catch => *in_catch = true,
+                Some(_) => return Err(format!("Task {} is a second CATCH in the same TRY block", index + 1)),
+                None => return Err(format!("Task {} is a CATCH outside a TRY block", index + 1)),
+            },
+            Some(BlockMarker::EndTry) => {
+                open.pop().ok_or_else(|| format!("Task {} is an END_TRY without a TRY", index + 1))?;
+            }
+            None => {}
+        }
+    }
+    match open.len() {
+        0 => Ok(()),
+        unclosed => Err(format!("{} TRY block(s) are not closed with END_TRY", unclosed)),
+    }
+}
+
+#[derive(Debug)]
+struct TryFrame {
+    // `None` while the `TRY` part runs; the error being handled once the `CATCH` part runs.
+    caught: Option<String>,
+}
+
+/// The `TRY` blocks a run is inside of, innermost last.
+#[derive(Debug, Default)]
+pub struct TryStack {
+    frames: Vec<TryFrame>,
+}
+
+// Removes tasks from the front of `tasks` up to and including the `CATCH` or `END_TRY` of the
+// current block, skipping nested blocks, and returns that marker.
+fn skip_block(tasks: &mut VecDeque<String>) -> Option<BlockMarker> {
+    let mut depth = 0;
+    while let Some(task) = tasks.pop_front() {
+        match marker(&task) {
+            Some(BlockMarker::Try) => depth += 1,
+            Some(BlockMarker::EndTry) if depth > 0 => depth -= 1,
+            Some(found @ (BlockMarker::Catch | BlockMarker::EndTry)) if depth == 0 => return Some(found),
+            _ => {}
+        }
+    }
+    None
+}
+
+impl TryStack {
+    /// The stack of a run about to continue after `tasks[index]`, which must be outside every
+    /// `CATCH` part: one frame for each `TRY` block enclosing that task.
+    pub fn at(tasks: &[String], index: usize) -> TryStack {
+        let mut frames = Vec::new();
+        for task in &tasks[..index] {
+            match marker(task) {
+                Some(BlockMarker::Try) => frames.push(TryFrame { caught: None }),
+                Some(BlockMarker::EndTry) => {
+                    frames.pop();
+                }
+                _ => {}
+            }
+        }
+        TryStack { frames }
+    }
+
+    /// Handles a marker reached by running the tasks in order: `TRY` enters a block, and reaching
+    /// its `CATCH` means the `TRY` part succeeded, so the `CATCH` part is skipped.
+    pub fn enter(&mut self, marker: BlockMarker, tasks: &mut VecDeque<String>) {
+        match marker {
+            BlockMarker::Try => self.frames.push(TryFrame { caught: None }),
+            BlockMarker::Catch => {
+                skip_block(tasks);
+                self.frames.pop();
+            }
+            BlockMarker::EndTry => {
+                self.frames.pop();
+            }
+        }
+    }
+
+    /// Handles the failure of a task. Inside the `TRY` part of a block, the rest of that part is
+    /// skipped and its `CATCH` steps run next, with `error` bound to `{{error}}`; a block without
+    /// `CATCH` just ends. A failure inside a `CATCH` part ends that block and goes to the
+    /// enclosing one, if any.
+    ///
+    /// # Returns
+    /// `true` if a block handled the failure, `false` if the task was outside every `TRY` part.
+    pub fn fail(&mut self, error: &str, tasks: &mut VecDeque<String>) -> bool {
+        while let Some(frame) = self.frames.last_mut() {
+            if frame.caught.is_none() {
+                if skip_block(tasks) == Some(BlockMarker::Catch) {
+                    frame.caught = Some(error.to_string());
+                } else {
+                    self.frames.pop();
+                }
+                return true;
+            }
+            skip_block(tasks);
+            self.frames.pop();
+        }
+        false
+    }
+
+    /// The error handled by the innermost `CATCH` part being run, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.frames.iter().rev().find_map(|frame| frame.caught.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks(list: &[&str]) -> Vec<String> {
+        list.iter().map(|task| task.to_string()).collect()
+    }
+
+    // Runs `list`, failing the tasks starting with "FAIL", and returns the tasks that ran, with
+    // `{{error}}` substituted.
+    fn run(list: &[&str]) -> Vec<String> {
+        let mut queue: VecDeque<String> = tasks(list).into();
+        let mut stack = TryStack::default();
+        let mut ran = Vec::new();
+        while let Some(task) = queue.pop_front() {
+            if let Some(marker) = marker(&task) {
+                stack.enter(marker, &mut queue);
+                continue;
+            }
+            ran.push(task.replace(ERROR_PLACEHOLDER, stack.error().unwrap_or("")));
+            if task.starts_with("FAIL") {
+                stack.fail(&format!("{} failed", task), &mut queue);
+            }
+        }
+        ran
+    }
+
+    #[test]
+    fn test_check_blocks() {
+        assert!(check_blocks(&tasks(&["TRY", "CLICK a", "try", "CLICK b", "END_TRY", "CATCH", "READ c", "END_TRY"])).is_ok());
+        assert!(check_blocks(&tasks(&["TRY", "CLICK a", "END_TRY"])).is_ok(), "CATCH is optional");
+        assert_eq!(check_blocks(&tasks(&["CLICK a", "CATCH"])).unwrap_err(), "Task 2 is a CATCH outside a TRY block");
+        assert!(check_blocks(&tasks(&["TRY", "CATCH", "CATCH", "END_TRY"])).unwrap_err().contains("second CATCH"));
+        assert!(check_blocks(&tasks(&["END_TRY"])).is_err());
+        assert!(check_blocks(&tasks(&["TRY", "CLICK a"])).unwrap_err().contains("not closed"));
+    }
+
+    #[test]
+    fn test_failure_jumps_to_catch() {
+        assert_eq!(
+            run(&["TRY", "CLICK a", "CATCH", "READ fallback", "END_TRY", "READ after"]),
+            ["CLICK a", "READ after"],
+            "CATCH is skipped when the TRY part succeeds"
+        );
+        assert_eq!(
+            run(&["TRY", "FAIL a", "CLICK b", "CATCH", "LOG {{error}}", "END_TRY", "READ after"]),
+            ["FAIL a", "LOG FAIL a failed", "READ after"]
+        );
+        assert_eq!(run(&["TRY", "FAIL a", "CLICK b", "END_TRY", "READ after"]), ["FAIL a", "READ after"]);
+    }
+
+    #[test]
+    fn test_nested_blocks() {
+        let list = [
+            "TRY",
+            "TRY", "FAIL inner", "CATCH", "FAIL handler", "READ skipped", "END_TRY",
+            "READ skipped",
+            "CATCH", "LOG {{error}}", "END_TRY",
+        ];
+        assert_eq!(run(&list), ["FAIL inner", "FAIL handler", "LOG FAIL handler failed"], "A failing CATCH goes to the enclosing block");
+
+        let list = ["TRY", "FAIL a", "TRY", "READ skipped", "CATCH", "READ skipped", "END_TRY", "CATCH", "LOG {{error}}", "END_TRY"];
+        assert_eq!(run(&list), ["FAIL a", "LOG FAIL a failed"], "Nested blocks are skipped whole");
+        assert_eq!(run(&["FAIL a", "READ b"]), ["FAIL a", "READ b"], "Failures outside TRY do not skip anything");
+    }
+
+    #[test]
+    fn test_stack_at_task() {
+        let list = tasks(&["TRY", "CLICK a", "CATCH", "READ b", "END_TRY", "TRY", "TRY", "CLICK c", "END_TRY", "CLICK d"]);
+        assert_eq!(TryStack::at(&list, 1).frames.len(), 1);
+        assert_eq!(TryStack::at(&list, 5).frames.len(), 0);
+        assert_eq!(TryStack::at(&list, 7).frames.len(), 2);
+        assert_eq!(TryStack::at(&list, 9).frames.len(), 1);
+
+        // A failure after jumping into a TRY part goes to its CATCH.
+        let mut stack = TryStack::at(&list, 1);
+        let mut queue: VecDeque<String> = list[2..].to_vec().into();
+        assert!(stack.fail("a failed", &mut queue));
+        assert_eq!(stack.error(), Some("a failed"));
+        assert_eq!(queue.front().map(String::as_str), Some("READ b"));
+    }
+}
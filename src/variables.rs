@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Opening marker of a variable placeholder, e.g. `{{var.otp}}`.
+const VARIABLE_PREFIX: &str = "{{var.";
+/// Closing marker of a variable placeholder.
+const VARIABLE_SUFFIX: &str = "}}";
+
+/// Named values set with `RustAgent::set_variable` or entered by the user for `REQUEST_USER_INPUT`.
+///
+/// Commands refer to a variable with `{{var.<name>}}`; the value is substituted when the command
+/// runs, so a command planned before the value was entered (e.g. by the LLM, in the same command
+/// array) still gets it. Unlike secrets, values are not masked in results. Clones share the
+/// values, so a value stored while a command runs is seen by the following ones.
+#[derive(Debug, Clone, Default)]
+pub struct VariableStore {
+    values: Rc<RefCell<BTreeMap<String, String>>>,
+}
+
+impl VariableStore {
+    /// Stores `value` under `name`, replacing any previous value. An empty value removes the variable.
+    pub fn set(&self, name: &str, value: &str) {
+        if value.is_empty() {
+            self.values.borrow_mut().remove(name);
+        } else {
+            self.values.borrow_mut().insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// The value of the variable `name`, if it is set.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.values.borrow().get(name).cloned()
+    }
+
+    /// `true` if `name` can be used in a placeholder: letters, digits, `_` and `-` only.
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    }
+
+    /// `true` if `text` contains a `{{var.<name>}}` placeholder.
+    pub fn has_placeholder(text: &str) -> bool {
+        text.contains(VARIABLE_PREFIX)
+    }
+
+    /// Replaces every `{{var.<name>}}` placeholder in `text` with the variable's value.
+    ///
+    /// # Returns
+    /// The text with the values substituted, or an error naming the first unknown variable.
+    pub fn substitute(&self, text: &str) -> Result<String, String> {
+        let values = self.values.borrow();
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(VARIABLE_PREFIX) {
+            let after_prefix = &rest[start + VARIABLE_PREFIX.len()..];
+            let end = after_prefix
+                .find(VARIABLE_SUFFIX)
+                .ok_or_else(|| format!("Unterminated variable placeholder in '{}'", text))?;
+            let name = after_prefix[..end].trim();
+            let value = values
+                .get(name)
+                .ok_or_else(|| format!("Unknown variable '{}'. Set it with set_variable or REQUEST_USER_INPUT.", name))?;
+            output.push_str(&rest[..start]);
+            output.push_str(value);
+            rest = &after_prefix[end + VARIABLE_SUFFIX.len()..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute_shared_variables() {
+        let variables = VariableStore::default();
+        let shared = variables.clone();
+        shared.set("otp", "123456");
+        assert_eq!(variables.get("otp").as_deref(), Some("123456"), "Clones share the values");
+        assert_eq!(variables.substitute("TYPE css:#code {{var.otp}}").unwrap(), "TYPE css:#code 123456");
+        assert!(variables.substitute("{{var.pin}}").unwrap_err().contains("Unknown variable 'pin'"));
+        assert!(variables.substitute("{{var.otp").is_err());
+
+        variables.set("otp", "");
+        assert_eq!(shared.get("otp"), None);
+        assert!(VariableStore::is_valid_name("otp_code-2"));
+        assert!(!VariableStore::is_valid_name("otp code") && !VariableStore::is_valid_name("otp}}"));
+    }
+}
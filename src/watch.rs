@@ -0,0 +1,302 @@
+use crate::dom_utils::{self, DomError};
+use gloo_timers::future::TimeoutFuture;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, MutationObserver, MutationObserverInit, MutationRecord, Node};
+
+/// Longest time `WATCH` may watch for.
+pub const MAX_WATCH_DURATION_MS: u32 = 60_000;
+/// Mutations listed by one `WATCH`, once summarized; later ones are only counted.
+pub const MAX_WATCHED_MUTATIONS: usize = 200;
+/// Characters of text kept in the description of a text node or a value.
+const MAX_WATCHED_TEXT_CHARS: usize = 100;
+/// Mutation records kept while watching, so that a busy page cannot exhaust memory.
+const MAX_MUTATION_RECORDS: usize = 10_000;
+
+/// A change observed by `WATCH` under the element it watched.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WatchedMutation {
+    /// `added` or `removed` for nodes, `attribute` or `text` for changes of existing nodes.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The element the change happened in: the parent of added and removed nodes, the element of a
+    /// changed attribute, or the parent of changed text.
+    pub target: String,
+    /// The added or removed node: an element (tag, id and first class) or `text "<text>"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attribute: Option<String>,
+    /// The attribute value or text before the first change, `None` if the attribute was absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<String>,
+    /// The attribute value or text when watching ended, `None` if the attribute was removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<String>,
+}
+
+/// What `WATCH` returns.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct WatchReport {
+    pub selector: String,
+    pub duration_ms: u32,
+    pub added: usize,
+    pub removed: usize,
+    pub attribute_changes: usize,
+    pub text_changes: usize,
+    /// The first `MAX_WATCHED_MUTATIONS` mutations, in the order they happened.
+    pub mutations: Vec<WatchedMutation>,
+    /// Whether mutations were left out, beyond `MAX_WATCHED_MUTATIONS` or because the page changed
+    /// too much to record them all.
+    pub truncated: bool,
+}
+
+/// Parses the duration of `WATCH`, in milliseconds.
+///
+/// # Returns
+/// The duration, or `None` if it is not a number between 1 and `MAX_WATCH_DURATION_MS`.
+pub fn parse_duration(value: &str) -> Option<u32> {
+    value.trim().parse::<u32>().ok().filter(|duration| (1..=MAX_WATCH_DURATION_MS).contains(duration))
+}
+
+/// Observes the element matched by `selector` and its descendants for `duration_ms`, then returns
+/// a JSON `WatchReport` of the nodes added and removed and the attributes and text changed, e.g. to
+/// confirm that a click opened a panel or updated a counter.
+///
+/// Repeated changes of the same attribute or text are summarized as one, from the value before the
+/// first change to the value when watching ended; whitespace-only text nodes and comments are left
+/// out.
+pub async fn watch(selector: &str, duration_ms: u32) -> Result<String, DomError> {
+    let (_window, document) = dom_utils::get_window_document()?;
+    let element = dom_utils::get_element(&document, selector)?;
+
+    let records: Rc<RefCell<Vec<MutationRecord>>> = Rc::new(RefCell::new(Vec::new()));
+    let truncated = Rc::new(Cell::new(false));
+    let on_mutation = {
+        let (records, truncated) = (records.clone(), truncated.clone());
+        Closure::wrap(Box::new(move |batch: js_sys::Array| {
+            keep_records(&mut records.borrow_mut(), &truncated, batch);
+        }) as Box<dyn FnMut(js_sys::Array)>)
+    };
+    let observer = MutationObserver::new(on_mutation.as_ref().unchecked_ref())?;
+    let options = MutationObserverInit::new();
+    options.set_child_list(true);
+    options.set_subtree(true);
+    options.set_attributes(true);
+    options.set_attribute_old_value(true);
+    options.set_character_data(true);
+    options.set_character_data_old_value(true);
+    observer.observe_with_options(&element, &options)?;
+    TimeoutFuture::new(duration_ms).await;
+    keep_records(&mut records.borrow_mut(), &truncated, observer.take_records());
+    observer.disconnect();
+
+    let mutations = summarize(records.borrow().iter().flat_map(describe_record).collect());
+    let count = |kind: &str| mutations.iter().filter(|mutation| mutation.kind == kind).count();
+    let report = WatchReport {
+        selector: selector.to_string(),
+        duration_ms,
+        added: count("added"),
+        removed: count("removed"),
+        attribute_changes: count("attribute"),
+        text_changes: count("text"),
+        truncated: truncated.get() || mutations.len() > MAX_WATCHED_MUTATIONS,
+        mutations: mutations.into_iter().take(MAX_WATCHED_MUTATIONS).collect(),
+    };
+    serde_json::to_string(&report).map_err(|e| DomError::JsError { message: format!("Failed to serialize mutations: {}", e) })
+}
+
+// Appends the records of `batch` to `records`, up to `MAX_MUTATION_RECORDS`.
+fn keep_records(records: &mut Vec<MutationRecord>, truncated: &Cell<bool>, batch: js_sys::Array) {
+    for record in batch.iter() {
+        if records.len() >= MAX_MUTATION_RECORDS {
+            truncated.set(true);
+            return;
+        }
+        records.push(record.unchecked_into());
+    }
+}
+
+// Describes a mutation record: one mutation per added or removed node worth listing, or the
+// attribute or text change, each with the node the record is about.
+fn describe_record(record: &MutationRecord) -> Vec<(Node, WatchedMutation)> {
+    let Some(target) = record.target() else { return Vec::new() };
+    let mutation = |kind: &str, target: String| WatchedMutation {
+        kind: kind.to_string(),
+        target,
+        node: None,
+        attribute: None,
+        old_value: None,
+        new_value: None,
+    };
+    match record.type_().as_str() {
+        "childList" => {
+            let nodes = |kind: &'static str, list: web_sys::NodeList| {
+                let description = describe_node(&target).unwrap_or_default();
+                (0..list.length())
+                    .filter_map(|index| list.item(index))
+                    .filter_map(|node| describe_node(&node))
+                    .map(|node| (target.clone(), WatchedMutation { node: Some(node), ..mutation(kind, description.clone()) }))
+                    .collect::<Vec<_>>()
+            };
+            let mut mutations = nodes("added", record.added_nodes());
+            mutations.extend(nodes("removed", record.removed_nodes()));
+            mutations
+        }
+        "attributes" => {
+            let Some(element) = target.dyn_ref::<Element>() else { return Vec::new() };
+            let attribute = record.attribute_name().unwrap_or_default();
+            let change = WatchedMutation {
+                new_value: element.get_attribute(&attribute).map(|value| truncate(&value)),
+                old_value: record.old_value().map(|value| truncate(&value)),
+                attribute: Some(attribute),
+                ..mutation("attribute", dom_utils::describe_element(element))
+            };
+            vec![(target, change)]
+        }
+        "characterData" => {
+            let parent = target.parent_element().map(|parent| dom_utils::describe_element(&parent)).unwrap_or_default();
+            let change = WatchedMutation {
+                new_value: target.text_content().map(|text| truncate(&text)),
+                old_value: record.old_value().map(|text| truncate(&text)),
+                ..mutation("text", parent)
+            };
+            vec![(target, change)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Describes an added or removed node, or `None` for whitespace-only text, comments and other nodes.
+fn describe_node(node: &Node) -> Option<String> {
+    if let Some(element) = node.dyn_ref::<Element>() {
+        return Some(dom_utils::describe_element(element));
+    }
+    let text = node.text_content().filter(|_| node.node_type() == Node::TEXT_NODE)?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| format!("text \"{}\"", truncate(text)))
+}
+
+// Cuts `text` to `MAX_WATCHED_TEXT_CHARS` characters, marking the cut with `…`.
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_WATCHED_TEXT_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text.to_string(),
+    }
+}
+
+// Merges repeated changes of the same attribute of the same node, or of the text of the same node,
+// into the first one, keeping its old value; the new value is already the final one. Mutations
+// come with the node they are about.
+fn summarize<N: PartialEq>(mutations: Vec<(N, WatchedMutation)>) -> Vec<WatchedMutation> {
+    let mut summarized: Vec<(N, WatchedMutation)> = Vec::new();
+    for (node, mutation) in mutations {
+        let is_repeat = matches!(mutation.kind.as_str(), "attribute" | "text")
+            && summarized.iter().any(|(earlier_node, earlier)| {
+                *earlier_node == node && earlier.kind == mutation.kind && earlier.attribute == mutation.attribute
+            });
+        if !is_repeat {
+            summarized.push((node, mutation));
+        }
+    }
+    summarized.into_iter().map(|(_, mutation)| mutation).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    fn change(kind: &str, target: &str, attribute: Option<&str>, old_value: &str, new_value: &str) -> WatchedMutation {
+        WatchedMutation {
+            kind: kind.to_string(),
+            target: target.to_string(),
+            node: None,
+            attribute: attribute.map(str::to_string),
+            old_value: Some(old_value.to_string()),
+            new_value: Some(new_value.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration(" 2000 "), Some(2000));
+        assert_eq!(parse_duration("0"), None);
+        assert_eq!(parse_duration("60001"), None, "Durations are capped");
+        assert_eq!(parse_duration("2s"), None);
+    }
+
+    #[test]
+    fn test_summarize_merges_repeated_changes() {
+        let mutations = vec![
+            (1, change("attribute", "div#panel", Some("class"), "closed", "open")),
+            (2, change("text", "span#count", None, "1", "3")),
+            (1, change("attribute", "div#panel", Some("aria-hidden"), "true", "false")),
+            (1, change("attribute", "div#panel", Some("class"), "opening", "open")),
+            (3, change("attribute", "div", Some("class"), "a", "b")),
+            (4, change("attribute", "div", Some("class"), "a", "b")),
+            (2, change("text", "span#count", None, "2", "3")),
+        ];
+        let summarized = summarize(mutations.clone());
+        let expected: Vec<WatchedMutation> = [0, 1, 2, 4, 5].iter().map(|&index| mutations[index].1.clone()).collect();
+        assert_eq!(summarized, expected, "Changes of different elements described alike are kept apart");
+        assert_eq!(summarized[0].old_value.as_deref(), Some("closed"), "The value before the first change is kept");
+
+        let added = WatchedMutation { node: Some("li".to_string()), ..change("added", "ul#list", None, "", "") };
+        assert_eq!(summarize(vec![(1, added.clone()), (1, added.clone())]).len(), 2, "Added and removed nodes are all listed");
+        assert_eq!(truncate(&"a".repeat(101)), format!("{}…", "a".repeat(100)));
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_watch_reports_mutations() {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let panel = document.create_element("div").unwrap();
+        panel.set_id("watch-panel");
+        panel.set_inner_html(r#"<ul id="watch-list"><li class="old">Old</li></ul><span id="watch-count">1</span>"#);
+        document.body().unwrap().append_child(&panel).unwrap();
+
+        wasm_bindgen_futures::spawn_local(async {
+            TimeoutFuture::new(30).await;
+            let document = web_sys::window().unwrap().document().unwrap();
+            let list = document.get_element_by_id("watch-list").unwrap();
+            let item = document.create_element("li").unwrap();
+            item.set_class_name("new");
+            list.append_child(&item).unwrap();
+            list.append_child(&document.create_text_node("\n  ")).unwrap();
+            list.first_element_child().unwrap().remove();
+            let panel = document.get_element_by_id("watch-panel").unwrap();
+            panel.set_attribute("class", "opening").unwrap();
+            panel.set_attribute("class", "open").unwrap();
+            let count = document.get_element_by_id("watch-count").unwrap().first_child().unwrap();
+            count.set_node_value(Some("2"));
+            count.set_node_value(Some("3"));
+        });
+        let report: serde_json::Value = serde_json::from_str(&watch("#watch-panel", 200).await.unwrap()).unwrap();
+        assert_eq!((report["added"].as_u64(), report["removed"].as_u64()), (Some(1), Some(1)), "Whitespace text is left out");
+        assert_eq!((report["attribute_changes"].as_u64(), report["text_changes"].as_u64()), (Some(1), Some(1)));
+        let mutations = report["mutations"].as_array().unwrap();
+        assert_eq!(mutations[0]["type"], "added");
+        assert_eq!(mutations[0]["target"], "ul#watch-list");
+        assert_eq!(mutations[0]["node"], "li.new");
+        assert_eq!(mutations[1]["node"], "li.old");
+        assert_eq!(mutations[2]["attribute"], "class");
+        assert!(mutations[2].get("old_value").is_none(), "The panel had no class before");
+        assert_eq!(mutations[2]["new_value"], "open");
+        assert_eq!((mutations[3]["target"].as_str(), mutations[3]["old_value"].as_str(), mutations[3]["new_value"].as_str()), (Some("span#watch-count"), Some("1"), Some("3")));
+        assert_eq!(report["truncated"], false);
+
+        wasm_bindgen_futures::spawn_local(async {
+            TimeoutFuture::new(30).await;
+            let document = web_sys::window().unwrap().document().unwrap();
+            document.get_element_by_id("watch-panel").unwrap().set_attribute("class", "closed").unwrap();
+        });
+        let report: serde_json::Value = serde_json::from_str(&watch("#watch-list", 200).await.unwrap()).unwrap();
+        assert_eq!(report["mutations"], serde_json::json!([]), "Changes outside the watched element are not reported");
+        panel.remove();
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::{HashMap, VecDeque};
+use crate::try_catch::{self, BlockMarker};
+
+/// Number of times the body of a `WHILE` loop runs before the loop fails, unless it gives its own limit.
+pub const DEFAULT_MAX_ITERATIONS: u32 = 20;
+/// Commands whose result is a condition, run as they are. Other conditions are asked to the LLM.
+const PREDICATE_COMMANDS: [&str; 6] = ["ELEMENT_EXISTS", "IS_VISIBLE", "IS_ENABLED", "IS_EDITABLE", "IS_SELECTED", "LLM_DECIDE"];
+
+/// A line of a task list that starts or ends a `WHILE` loop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoopStep<'a> {
+    /// `WHILE [NOT] <condition> [MAX <n>]`: runs the tasks up to `END_WHILE` as long as the condition holds.
+    While(Condition<'a>),
+    /// `END_WHILE`: goes back to the `WHILE`.
+    EndWhile,
+}
+
+/// The condition of a `WHILE` loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition<'a> {
+    /// A predicate command, e.g. `IS_VISIBLE css:.load-more`, or a question for the LLM about the page.
+    pub text: &'a str,
+    /// `true` if the loop runs while the condition does not hold.
+    pub negated: bool,
+    pub max_iterations: u32,
+}
+
+impl Condition<'_> {
+    /// The task run to evaluate the condition: the predicate command itself, or an `LLM_DECIDE`
+    /// question about the whole page.
+    pub fn task(&self) -> String {
+        let keyword = self.text.split_whitespace().next().unwrap_or("").to_uppercase();
+        if PREDICATE_COMMANDS.contains(&keyword.as_str()) {
+            self.text.to_string()
+        } else {
+            format!("LLM_DECIDE css:body {}", self.text)
+        }
+    }
+
+    /// Whether the loop goes on, given the result of the condition's task.
+    ///
+    /// # Returns
+    /// `Some(bool)`, or `None` if the result says neither true nor false.
+    pub fn holds(&self, result: &str) -> Option<bool> {
+        truth(result).map(|value| value != self.negated)
+    }
+}
+
+// The last `true` / `false` (or `yes` / `no`) word of `result`: predicate commands, including
+// `LLM_DECIDE`, end their result with it.
+pub(crate) fn truth(result: &str) -> Option<bool> {
+    result.split(|c: char| !c.is_alphanumeric()).rev().find_map(|word| match word.to_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    })
+}
+
+/// Parses a `WHILE` or `END_WHILE` step. Keywords are matched case-insensitively.
+///
+/// # Returns
+/// * `None` if `task` is neither.
+/// * `Some(Ok(LoopStep))` with the parsed step.
+/// * `Some(Err(String))` if the condition is missing or the iteration limit is zero.
+pub fn parse_step(task: &str) -> Option<Result<LoopStep<'_>, String>> {
+    let task = task.trim();
+    let (keyword, rest) = task.split_once(char::is_whitespace).unwrap_or((task, ""));
+    match keyword.to_uppercase().as_str() {
+        "END_WHILE" if rest.trim().is_empty() => Some(Ok(LoopStep::EndWhile)),
+        "WHILE" => Some(parse_condition(rest.trim()).map(LoopStep::While)),
+        _ => None,
+    }
+}
+
+fn parse_condition(text: &str) -> Result<Condition<'_>, String> {
+    let (negated, text) = match text.split_once(char::is_whitespace) {
+        Some((not, rest)) if not.eq_ignore_ascii_case("NOT") => (true, rest.trim_start()),
+        _ => (false, text),
+    };
+    // A trailing `MAX <n>` sets the iteration limit.
+    let mut words = text.rsplitn(3, char::is_whitespace);
+    let (text, max_iterations) = match (words.next(), words.next(), words.next()) {
+        (Some(limit), Some(max), Some(condition)) if max.eq_ignore_ascii_case("MAX") => {
+            let limit = limit.parse::<u32>().ok().filter(|&limit| limit > 0).ok_or_else(|| {
+                format!("The iteration limit of 'WHILE {}' must be a positive number", text)
+            })?;
+            (condition.trim_end(), limit)
+        }
+        _ => (text, DEFAULT_MAX_ITERATIONS),
+    };
+    if text.is_empty() {
+        return Err("WHILE needs a condition, e.g. WHILE IS_VISIBLE css:.load-more".to_string());
+    }
+    Ok(Condition { text, negated, max_iterations })
+}
+
+/// Checks that the `WHILE` loops of a task list are well formed: every `WHILE` has a condition
+/// and is closed by an `END_WHILE`, and loops and `TRY` blocks nest inside each other.
+///
+/// # Returns
+/// `Ok(())`, or an error naming the (1-based) task at fault.
+pub fn check_loops(tasks: &[String]) -> Result<(), String> {
+    // `true` for each open WHILE loop, `false` for each open TRY block, innermost last.
+    let mut open: Vec<bool> = Vec::new();
+    for (index, task) in tasks.iter().enumerate() {
+        match parse_step(task) {
+            Some(Err(e)) => return Err(format!("Task {}: {}", index + 1, e)),
+            Some(Ok(LoopStep::While(_))) => open.push(true),
+            Some(Ok(LoopStep::EndWhile)) => match open.pop() {
+                Some(true) => {}
+                Some(false) => return Err(format!("Task {} is an END_WHILE inside a TRY block opened in the loop", index + 1)),
+                None => return Err(format!("Task {} is an END_WHILE without a WHILE", index + 1)),
+            },
+            None => match try_catch::marker(task) {
+                Some(BlockMarker::Try) => open.push(false),
+                Some(BlockMarker::Catch) if open.last() == Some(&true) => {
+                    return Err(format!("Task {} is a CATCH inside a WHILE loop opened in the TRY block", index + 1));
+                }
+                // The block is closed (popped) whether or not the guard holds.
+                Some(BlockMarker::EndTry) if open.pop() == Some(true) => {
+                    return Err(format!("Task {} is an END_TRY inside a WHILE loop opened in the TRY block", index + 1));
+                }
+                _ => {}
+            },
+        }
+    }
+    match open.iter().filter(|&&is_loop| is_loop).count() {
+        0 => Ok(()),
+        unclosed => Err(format!("{} WHILE loop(s) are not closed with END_WHILE", unclosed)),
+    }
+}
+
+/// Removes tasks from the front of `tasks` up to and including the `END_WHILE` of the loop whose
+/// `WHILE` was just taken from them, skipping nested loops.
+pub fn skip_loop(tasks: &mut VecDeque<String>) {
+    let mut depth = 0;
+    while let Some(task) = tasks.pop_front() {
+        match parse_step(&task) {
+            Some(Ok(LoopStep::While(_))) => depth += 1,
+            Some(Ok(LoopStep::EndWhile)) if depth == 0 => return,
+            Some(Ok(LoopStep::EndWhile)) => depth -= 1,
+            _ => {}
+        }
+    }
+}
+
+/// Index of the `WHILE` that the `END_WHILE` at `end_index` closes.
+pub fn loop_start(tasks: &[String], end_index: usize) -> Option<usize> {
+    let mut depth = 0;
+    for index in (0..end_index).rev() {
+        match parse_step(&tasks[index]) {
+            Some(Ok(LoopStep::EndWhile)) => depth += 1,
+            Some(Ok(LoopStep::While(_))) if depth == 0 => return Some(index),
+            Some(Ok(LoopStep::While(_))) => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Counts the iterations of the `WHILE` loops of a run, to stop loops that never end.
+#[derive(Debug, Default)]
+pub struct LoopCounter {
+    iterations: HashMap<usize, u32>,
+    // The `WHILE` an `END_WHILE` just went back to, whose count goes on.
+    repeating: Option<usize>,
+}
+
+impl LoopCounter {
+    /// Records that an `END_WHILE` goes back to the `WHILE` at `index`.
+    pub fn repeat(&mut self, index: usize) {
+        self.repeating = Some(index);
+    }
+
+    /// Records an iteration of the loop whose `WHILE` is at `index`. A loop entered from before
+    /// its `WHILE`, rather than from its `END_WHILE`, starts counting again.
+    ///
+    /// # Returns
+    /// `Ok(())` if the iteration may run, or an error once the loop has run `max_iterations` times.
+    pub fn iterate(&mut self, index: usize, max_iterations: u32) -> Result<(), String> {
+        let repeating = self.repeating.take() == Some(index);
+        let iterations = self.iterations.entry(index).or_insert(0);
+        if !repeating {
+            *iterations = 0;
+        }
+        if *iterations >= max_iterations {
+            return Err(format!(
+                "WHILE (task {}) still held after {} iterations; stopping the loop",
+                index + 1,
+                max_iterations
+            ));
+        }
+        *iterations += 1;
+        Ok(())
+    }
+
+    /// Records that the loop whose `WHILE` is at `index` ended without running again.
+    pub fn end(&mut self, index: usize) {
+        self.repeating = None;
+        self.iterations.remove(&index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tasks(list: &[&str]) -> Vec<String> {
+        list.iter().map(|task| task.to_string()).collect()
+    }
+
+    fn condition(task: &str) -> Condition<'_> {
+        match parse_step(task) {
+            Some(Ok(LoopStep::While(condition))) => condition,
+            other => panic!("Expected a WHILE step, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_step() {
+        let parsed = condition("WHILE IS_VISIBLE text:\"Load more\" MAX 5");
+        assert_eq!(parsed, Condition { text: "IS_VISIBLE text:\"Load more\"", negated: false, max_iterations: 5 });
+        assert_eq!(parsed.task(), "IS_VISIBLE text:\"Load more\"");
+
+        let parsed = condition("while not element_exists css:#done");
+        assert_eq!(parsed, Condition { text: "element_exists css:#done", negated: true, max_iterations: DEFAULT_MAX_ITERATIONS });
+        assert_eq!(parsed.task(), "element_exists css:#done", "Predicate commands are recognized case-insensitively");
+
+        let parsed = condition("WHILE there are unread messages");
+        assert_eq!(parsed.task(), "LLM_DECIDE css:body there are unread messages", "Other conditions are asked to the LLM");
+        assert_eq!(condition("WHILE LLM_DECIDE css:#inbox Are there unread messages?").task(), "LLM_DECIDE css:#inbox Are there unread messages?");
+
+        assert_eq!(parse_step("end_while"), Some(Ok(LoopStep::EndWhile)));
+        assert!(matches!(parse_step("WHILE"), Some(Err(_))));
+        assert!(matches!(parse_step("WHILE IS_VISIBLE css:#a MAX 0"), Some(Err(_))));
+        assert_eq!(parse_step("WHILEX"), None);
+        assert_eq!(parse_step("CLICK css:#while"), None);
+    }
+
+    #[test]
+    fn test_condition_holds() {
+        let visible = condition("WHILE IS_VISIBLE css:#true-button");
+        assert_eq!(visible.holds("Element 'css:#true-button' is visible: false"), Some(false), "The last word counts");
+        assert_eq!(visible.holds("Agent 3 (Generic) completed task via LLM: Yes."), Some(true));
+        assert_eq!(visible.holds("Element 'css:#a' appeared."), None);
+        let absent = condition("WHILE NOT ELEMENT_EXISTS css:#done");
+        assert_eq!(absent.holds("Element 'css:#done' exists: false"), Some(true));
+    }
+
+    #[test]
+    fn test_check_loops() {
+        assert!(check_loops(&tasks(&["WHILE IS_VISIBLE css:#a", "TRY", "CLICK css:#a", "CATCH", "END_TRY", "END_WHILE"])).is_ok());
+        assert!(check_loops(&tasks(&["TRY", "WHILE IS_VISIBLE css:#a", "END_WHILE", "CATCH", "END_TRY"])).is_ok());
+        assert!(check_loops(&tasks(&["WHILE IS_VISIBLE css:#a", "CLICK css:#a"])).unwrap_err().contains("not closed"));
+        assert!(check_loops(&tasks(&["END_WHILE"])).unwrap_err().contains("without a WHILE"));
+        assert!(check_loops(&tasks(&["WHILE IS_VISIBLE css:#a", "TRY", "END_WHILE", "END_TRY"])).is_err());
+        assert!(check_loops(&tasks(&["TRY", "WHILE IS_VISIBLE css:#a", "CATCH", "END_WHILE", "END_TRY"])).is_err());
+        assert!(check_loops(&tasks(&["WHILE", "END_WHILE"])).unwrap_err().starts_with("Task 1"));
+    }
+
+    #[test]
+    fn test_skip_loop_and_loop_start() {
+        let list = tasks(&["WHILE IS_VISIBLE a", "WHILE IS_VISIBLE b", "CLICK b", "END_WHILE", "CLICK a", "END_WHILE", "READ c"]);
+        let mut queue: VecDeque<String> = list[1..].to_vec().into();
+        skip_loop(&mut queue);
+        assert_eq!(queue, ["READ c"]);
+        assert_eq!(loop_start(&list, 5), Some(0));
+        assert_eq!(loop_start(&list, 3), Some(1));
+    }
+
+    #[test]
+    fn test_loop_counter() {
+        let mut counter = LoopCounter::default();
+        assert!(counter.iterate(0, 2).is_ok());
+        counter.repeat(0);
+        assert!(counter.iterate(0, 2).is_ok());
+        counter.repeat(0);
+        assert!(counter.iterate(0, 2).unwrap_err().contains("after 2 iterations"));
+        assert!(counter.iterate(0, 2).is_ok(), "Entering the loop again starts a new count");
+    }
+}
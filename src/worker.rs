@@ -0,0 +1,298 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, EventTarget, MessageEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use futures::future::{select, Either};
+use gloo_timers::future::TimeoutFuture;
+use wasm_bindgen_futures::JsFuture;
+use crate::dom_utils::DomError;
+use crate::LibError;
+
+/// Value of the `source` field of every DOM proxy message, so that other messages exchanged with
+/// the worker are left alone.
+pub const DOM_PROXY_SOURCE: &str = "rustagent-dom";
+/// Default time a worker waits for the main thread to answer a DOM request.
+pub const DEFAULT_DOM_PROXY_TIMEOUT_MS: u32 = 30_000;
+
+/// A message of the DOM proxy protocol, posted as a JSON string, e.g.
+/// `{"source":"rustagent-dom","type":"request","id":1,"command":{"action":"CLICK","selector":"css:#buy","value":null,"attribute_name":null}}`
+/// and `{"source":"rustagent-dom","type":"response","id":1,"result":{"Ok":"Successfully clicked ..."}}`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomProxyMessage {
+    /// A DOM command the worker asks the main thread to perform.
+    Request { id: u32, command: Value },
+    /// The outcome of the request with the same `id`.
+    Response { id: u32, result: Result<String, LibError> },
+}
+
+impl DomProxyMessage {
+    /// Encodes the message, adding the `source` field.
+    pub fn encode(&self) -> Result<String, DomError> {
+        let mut value = serde_json::to_value(self).map_err(|e| DomError::SerializationError { message: e.to_string() })?;
+        value["source"] = Value::from(DOM_PROXY_SOURCE);
+        Ok(value.to_string())
+    }
+
+    /// Decodes the data of a `message` event, returning `None` for anything that is not a DOM proxy message.
+    pub fn decode(data: &JsValue) -> Option<Self> {
+        let value: Value = serde_json::from_str(&data.as_string()?).ok()?;
+        if value["source"] != DOM_PROXY_SOURCE {
+            return None;
+        }
+        serde_json::from_value(value).ok()
+    }
+}
+
+type MessageListener = Closure<dyn FnMut(MessageEvent)>;
+
+// Posts a message through `endpoint` (a worker, a worker's global scope or a `MessagePort`).
+fn post(endpoint: &JsValue, message: &DomProxyMessage) -> Result<(), DomError> {
+    let post_message = js_sys::Reflect::get(endpoint, &JsValue::from_str("postMessage"))?
+        .dyn_into::<js_sys::Function>()
+        .map_err(|_| DomError::JsTypeError { message: "The endpoint has no postMessage method".to_string() })?;
+    post_message.call1(endpoint, &JsValue::from_str(&message.encode()?))?;
+    Ok(())
+}
+
+// Listens for `message` events on `endpoint`, starting it if it is a `MessagePort`.
+fn listen(endpoint: &JsValue, listener: &MessageListener) -> Result<(), DomError> {
+    endpoint
+        .unchecked_ref::<EventTarget>()
+        .add_event_listener_with_callback("message", listener.as_ref().unchecked_ref())?;
+    if let Ok(start) = js_sys::Reflect::get(endpoint, &JsValue::from_str("start"))?.dyn_into::<js_sys::Function>() {
+        start.call0(endpoint)?;
+    }
+    Ok(())
+}
+
+/// The worker side of the DOM proxy: an agent running in a Web Worker, which has no DOM, sends its
+/// DOM commands to the main thread through it. LLM calls, planning and parsing stay in the worker.
+#[derive(Clone)]
+pub struct DomProxy {
+    state: Rc<DomProxyState>,
+}
+
+struct DomProxyState {
+    endpoint: JsValue,
+    timeout_ms: u32,
+    next_id: Cell<u32>,
+    // `resolve` functions of the promises awaiting a response, by request id.
+    pending: RefCell<HashMap<u32, js_sys::Function>>,
+    on_message: RefCell<Option<MessageListener>>,
+}
+
+impl Drop for DomProxyState {
+    fn drop(&mut self) {
+        if let Some(listener) = self.on_message.borrow_mut().take() {
+            let _ = self
+                .endpoint
+                .unchecked_ref::<EventTarget>()
+                .remove_event_listener_with_callback("message", listener.as_ref().unchecked_ref());
+        }
+    }
+}
+
+impl fmt::Debug for DomProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DomProxy")
+            .field("timeout_ms", &self.state.timeout_ms)
+            .field("pending", &self.state.pending.borrow().len())
+            .finish()
+    }
+}
+
+impl DomProxy {
+    /// Connects to the main thread through `endpoint`: the worker's global scope (`self`) or a `MessagePort`.
+    ///
+    /// # Arguments
+    /// * `timeout_ms`: How long a command may take on the main thread. Defaults to `DEFAULT_DOM_PROXY_TIMEOUT_MS`.
+    pub fn connect(endpoint: JsValue, timeout_ms: Option<u32>) -> Result<Self, DomError> {
+        let state = Rc::new(DomProxyState {
+            endpoint,
+            timeout_ms: timeout_ms.unwrap_or(DEFAULT_DOM_PROXY_TIMEOUT_MS),
+            next_id: Cell::new(1),
+            pending: RefCell::default(),
+            on_message: RefCell::default(),
+        });
+        // The listener only holds a weak reference, so that dropping the proxy removes it.
+        let weak: Weak<DomProxyState> = Rc::downgrade(&state);
+        let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let (Some(state), Some(DomProxyMessage::Response { id, result })) = (weak.upgrade(), DomProxyMessage::decode(&event.data())) else {
+                return;
+            };
+            let Some(resolve) = state.pending.borrow_mut().remove(&id) else {
+                return; // Timed out already
+            };
+            match serde_json::to_string(&result) {
+                Ok(json) => {
+                    let _ = resolve.call1(&JsValue::NULL, &JsValue::from_str(&json));
+                }
+                Err(e) => console::warn_1(&format!("Failed to pass on DOM proxy response {}: {}", id, e).into()),
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        listen(&state.endpoint, &on_message)?;
+        *state.on_message.borrow_mut() = Some(on_message);
+        Ok(DomProxy { state })
+    }
+
+    /// Sends a command to the main thread and waits for its outcome.
+    ///
+    /// # Returns
+    /// * `Ok(result)` with the outcome of the command on the main thread.
+    /// * `Err(DomError)` if the request could not be sent or no response arrived in time.
+    pub async fn perform(&self, command: Value) -> Result<Result<String, LibError>, DomError> {
+        let id = self.state.next_id.replace(self.state.next_id.get() + 1);
+        let mut resolve_slot = None;
+        let response = js_sys::Promise::new(&mut |resolve, _reject| resolve_slot = Some(resolve));
+        if let Some(resolve) = resolve_slot {
+            self.state.pending.borrow_mut().insert(id, resolve);
+        }
+        if let Err(e) = post(&self.state.endpoint, &DomProxyMessage::Request { id, command }) {
+            self.state.pending.borrow_mut().remove(&id);
+            return Err(e);
+        }
+
+        let timeout = TimeoutFuture::new(self.state.timeout_ms);
+        match select(JsFuture::from(response), timeout).await {
+            Either::Left((Ok(json), _)) => serde_json::from_str(&json.as_string().unwrap_or_default())
+                .map_err(|e| DomError::SerializationError { message: e.to_string() }),
+            Either::Left((Err(e), _)) => Err(DomError::from(e)),
+            Either::Right(_) => {
+                self.state.pending.borrow_mut().remove(&id);
+                Err(DomError::JsError {
+                    message: format!("The main thread did not answer DOM request {} within {}ms", id, self.state.timeout_ms),
+                })
+            }
+        }
+    }
+}
+
+/// Performs the command of a `DomProxyMessage::Request` on the main thread.
+pub type DomRequestHandler = Rc<dyn Fn(Value) -> Pin<Box<dyn Future<Output = Result<String, LibError>>>>>;
+
+/// Handle to the main thread side of the DOM proxy, returned by `RustAgent::serve_worker`.
+///
+/// The worker's DOM requests are served as long as this handle is alive; call `stop()` to stop.
+#[wasm_bindgen]
+pub struct WorkerHost {
+    endpoint: JsValue,
+    active: Rc<Cell<bool>>,
+    served_count: Rc<Cell<u32>>,
+    on_message: RefCell<Option<MessageListener>>,
+}
+
+#[wasm_bindgen]
+impl WorkerHost {
+    /// Stops serving requests. Requests already being performed still get their response.
+    #[wasm_bindgen]
+    pub fn stop(&self) {
+        self.active.set(false);
+        if let Some(listener) = self.on_message.borrow_mut().take() {
+            let _ = self
+                .endpoint
+                .unchecked_ref::<EventTarget>()
+                .remove_event_listener_with_callback("message", listener.as_ref().unchecked_ref());
+        }
+    }
+
+    /// `true` until `stop()` is called.
+    #[wasm_bindgen]
+    pub fn is_active(&self) -> bool {
+        self.active.get()
+    }
+
+    /// The number of requests answered so far.
+    #[wasm_bindgen]
+    pub fn served_count(&self) -> u32 {
+        self.served_count.get()
+    }
+}
+
+impl Drop for WorkerHost {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Serves the DOM requests arriving through `endpoint` (a `Worker` or a `MessagePort`) with
+/// `handler`, posting each outcome back. Requests are handled concurrently, in arrival order.
+pub fn serve(endpoint: JsValue, handler: DomRequestHandler) -> Result<WorkerHost, DomError> {
+    let active = Rc::new(Cell::new(true));
+    let served_count = Rc::new(Cell::new(0));
+    let (reply_to, still_active, counter) = (endpoint.clone(), active.clone(), served_count.clone());
+    let on_message = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let Some(DomProxyMessage::Request { id, command }) = DomProxyMessage::decode(&event.data()) else {
+            return;
+        };
+        let (handler, reply_to, still_active, counter) = (handler.clone(), reply_to.clone(), still_active.clone(), counter.clone());
+        wasm_bindgen_futures::spawn_local(async move {
+            if !still_active.get() {
+                return;
+            }
+            let result = handler(command).await;
+            counter.set(counter.get() + 1);
+            if let Err(e) = post(&reply_to, &DomProxyMessage::Response { id, result }) {
+                console::warn_1(&format!("Failed to answer DOM request {}: {}", id, e).into());
+            }
+        });
+    }) as Box<dyn FnMut(MessageEvent)>);
+    listen(&endpoint, &on_message)?;
+    console::log_1(&"Serving DOM requests of a worker".into());
+    Ok(WorkerHost { endpoint, active, served_count, on_message: RefCell::new(Some(on_message)) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[test]
+    fn test_dom_proxy_message_encoding() {
+        let request = DomProxyMessage::Request { id: 7, command: serde_json::json!({"action": "CLICK", "selector": "css:#buy"}) };
+        let encoded: Value = serde_json::from_str(&request.encode().unwrap()).unwrap();
+        assert_eq!(encoded["source"], DOM_PROXY_SOURCE);
+        assert_eq!(encoded["type"], "request");
+        assert_eq!(encoded["id"], 7);
+        assert_eq!(DomProxyMessage::decode(&JsValue::from_str(&encoded.to_string())), Some(request));
+
+        let response = DomProxyMessage::Response { id: 7, result: Err(LibError::CommandParse { message: "bad".to_string() }) };
+        let encoded: Value = serde_json::from_str(&response.encode().unwrap()).unwrap();
+        assert_eq!(encoded["result"]["Err"]["error_type"], "CommandParse");
+        assert_eq!(DomProxyMessage::decode(&JsValue::from_str(r#"{"type":"request","id":1,"command":null}"#)), None, "The source is required");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_dom_proxy_round_trip_over_message_channel() {
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let handler: DomRequestHandler = Rc::new(|command: Value| {
+            Box::pin(async move {
+                match command["selector"].as_str() {
+                    Some(selector) => Ok(format!("Handled {}", selector)),
+                    None => Err(LibError::CommandParse { message: "No selector".to_string() }),
+                }
+            })
+        });
+        let host = serve(channel.port2().into(), handler).unwrap();
+        let proxy = DomProxy::connect(channel.port1().into(), Some(1000)).unwrap();
+
+        let result = proxy.perform(serde_json::json!({"selector": "css:#buy"})).await.unwrap();
+        assert_eq!(result, Ok("Handled css:#buy".to_string()));
+        let result = proxy.perform(serde_json::json!({})).await.unwrap();
+        assert_eq!(result, Err(LibError::CommandParse { message: "No selector".to_string() }));
+        assert_eq!(host.served_count(), 2);
+
+        host.stop();
+        let proxy = DomProxy::connect(channel.port1().into(), Some(50)).unwrap();
+        assert!(proxy.perform(serde_json::json!({"selector": "css:#buy"})).await.is_err(), "A stopped host does not answer");
+    }
+}
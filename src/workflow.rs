@@ -0,0 +1,512 @@
+use web_sys::console;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use crate::goto;
+use crate::try_catch;
+use crate::while_loop;
+
+/// Opening marker of a placeholder in a workflow step, e.g. `{{params.email}}`.
+const PLACEHOLDER_PREFIX: &str = "{{";
+/// Closing marker of a placeholder.
+const PLACEHOLDER_SUFFIX: &str = "}}";
+/// Namespace of placeholders that refer to workflow parameters.
+const PARAM_NAMESPACE: &str = "params.";
+/// Keyword of a step that runs another registered workflow, e.g. `CALL login {"email": "{{params.email}}"}`.
+const CALL_KEYWORD: &str = "CALL";
+
+/// Represents errors that can occur while registering or expanding workflows.
+#[derive(Debug, PartialEq)]
+pub enum WorkflowError {
+    /// The workflow definition is malformed (invalid JSON, no steps, undeclared placeholders...).
+    InvalidDefinition(String),
+    /// No workflow is registered under the given name.
+    UnknownWorkflow(String),
+    /// A declared parameter without a default was not supplied.
+    MissingParameter { workflow: String, parameter: String },
+    /// A supplied parameter is not declared by the workflow (usually a typo).
+    UnknownParameter { workflow: String, parameter: String },
+    /// Workflows call each other in a loop; `chain` lists the calls, ending with the repeated workflow.
+    CallCycle { chain: Vec<String> },
+}
+
+impl fmt::Display for WorkflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkflowError::InvalidDefinition(s) => write!(f, "Invalid workflow definition: {}", s),
+            WorkflowError::UnknownWorkflow(name) => write!(f, "No workflow registered under the name '{}'", name),
+            WorkflowError::MissingParameter { workflow, parameter } => {
+                write!(f, "Workflow '{}' requires parameter '{}'", workflow, parameter)
+            }
+            WorkflowError::UnknownParameter { workflow, parameter } => {
+                write!(f, "Workflow '{}' does not declare parameter '{}'", workflow, parameter)
+            }
+            WorkflowError::CallCycle { chain } => write!(f, "Workflow call cycle detected: {}", chain.join(" -> ")),
+        }
+    }
+}
+
+impl std::error::Error for WorkflowError {}
+
+/// A parameter declared by a workflow.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkflowParam {
+    /// The name used in placeholders, e.g. `email` for `{{params.email}}`.
+    pub name: String,
+    /// The value used when the parameter is not supplied. Parameters without a default are required.
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+/// A step of a workflow or task list: either a bare task string, or a task with a readable name.
+///
+/// The name is only used in log and error messages, e.g. `{"name": "Accept cookies", "task": "CLICK css:#accept"}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkflowStep {
+    Task(String),
+    Named { name: String, task: String },
+}
+
+impl WorkflowStep {
+    /// The task string of the step.
+    pub fn task(&self) -> &str {
+        match self {
+            WorkflowStep::Task(task) => task,
+            WorkflowStep::Named { task, .. } => task,
+        }
+    }
+
+    // Describes the step for error messages, using its name when it has one.
+    fn describe(&self, index: usize) -> String {
+        match self {
+            WorkflowStep::Task(_) => format!("Step {}", index),
+            WorkflowStep::Named { name, .. } => format!("Step {} ('{}')", index, name),
+        }
+    }
+}
+
+/// A named, reusable sequence of tasks with declared parameters.
+///
+/// Workflows are usually registered by the host page as JSON or YAML, e.g.:
+/// `{"name": "login", "params": [{"name": "email"}, {"name": "remember", "default": "on"}],
+///   "steps": ["TYPE css:#email {{params.email}}", "SETATTRIBUTE css:#remember value {{params.remember}}", "CLICK css:#submit"]}`
+///
+/// Steps use the same task syntax as `automate`, so `{{PREVIOUS_RESULT}}` also works in them.
+/// A step may also run another registered workflow with `CALL <name> [params]`, where the
+/// optional params are a JSON object whose values may use the caller's placeholders, e.g.
+/// `CALL login {"email": "{{params.email}}"}`.
+/// Steps may be given readable names with `{"name": ..., "task": ...}` (see `WorkflowStep`).
+/// Unknown keys (e.g. a `"description"`) are ignored.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkflowDefinition {
+    /// The name the workflow is registered and run under.
+    pub name: String,
+    /// The parameters steps may reference with `{{params.<name>}}`.
+    #[serde(default)]
+    pub params: Vec<WorkflowParam>,
+    /// The tasks to run, in order.
+    pub steps: Vec<WorkflowStep>,
+}
+
+// Finds every `{{params.<name>}}` placeholder in `template` (whitespace inside the braces is
+// allowed), returning its byte range and the parameter name, in order of appearance.
+// Other placeholders, such as `{{PREVIOUS_RESULT}}`, are skipped.
+fn param_placeholders(template: &str) -> Vec<(Range<usize>, &str)> {
+    let mut placeholders = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find(PLACEHOLDER_PREFIX).map(|i| offset + i) {
+        let inner_start = start + PLACEHOLDER_PREFIX.len();
+        let end = match template[inner_start..].find(PLACEHOLDER_SUFFIX) {
+            Some(i) => inner_start + i + PLACEHOLDER_SUFFIX.len(),
+            None => break,
+        };
+        let inner = template[inner_start..end - PLACEHOLDER_SUFFIX.len()].trim();
+        if let Some(name) = inner.strip_prefix(PARAM_NAMESPACE) {
+            placeholders.push((start..end, name.trim()));
+        }
+        offset = end;
+    }
+    placeholders
+}
+
+// Replaces every `{{params.<name>}}` placeholder in `template` with its value.
+// All referenced names must be present in `values` (checked at registration and run time).
+fn render_template(template: &str, values: &HashMap<String, String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut copied_up_to = 0;
+    for (range, name) in param_placeholders(template) {
+        rendered.push_str(&template[copied_up_to..range.start]);
+        rendered.push_str(values.get(name).map(String::as_str).unwrap_or_default());
+        copied_up_to = range.end;
+    }
+    rendered.push_str(&template[copied_up_to..]);
+    rendered
+}
+
+/// Converts a JSON object of parameter values into the strings workflows are rendered with.
+/// String values are used as-is; other values are converted to their JSON text (e.g. `2`, `true`).
+pub fn params_from_json(params: &serde_json::Map<String, serde_json::Value>) -> HashMap<String, String> {
+    params
+        .iter()
+        .map(|(key, value)| match value {
+            serde_json::Value::String(text) => (key.clone(), text.clone()),
+            other => (key.clone(), other.to_string()),
+        })
+        .collect()
+}
+
+// The callee name of a `CALL` step and its (unrendered) parameter templates.
+type CallStep<'a> = (&'a str, HashMap<String, String>);
+
+// Parses a `CALL <name> [params]` step into the callee name and its (unrendered) parameter
+// templates. Returns `Ok(None)` for any other step.
+fn parse_call(step: &str) -> Result<Option<CallStep<'_>>, WorkflowError> {
+    let parts: Vec<&str> = step.trim().splitn(2, ' ').collect();
+    if !parts[0].eq_ignore_ascii_case(CALL_KEYWORD) {
+        return Ok(None);
+    }
+    let args = parts.get(1).map(|args| args.trim()).unwrap_or_default();
+    let (name, params_json) = match args.find(char::is_whitespace) {
+        Some(split) => (&args[..split], args[split..].trim()),
+        None => (args, ""),
+    };
+    if name.is_empty() {
+        return Err(WorkflowError::InvalidDefinition(format!("'{}' does not name a workflow to call", step)));
+    }
+    if params_json.is_empty() {
+        return Ok(Some((name, HashMap::new())));
+    }
+    let params: serde_json::Map<String, serde_json::Value> = serde_json::from_str(params_json).map_err(|e| {
+        WorkflowError::InvalidDefinition(format!("Parameters of '{}' must be a JSON object: {}", step, e))
+    })?;
+    Ok(Some((name, params_from_json(&params))))
+}
+
+// Parses a task list given either as a JSON array or as a YAML sequence. Items are task strings
+// or named steps (`{name, task}`).
+fn parse_steps(text: &str) -> Result<Vec<WorkflowStep>, String> {
+    if text.trim_start().starts_with('[') {
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON task list: {}", e))
+    } else {
+        serde_yaml::from_str(text).map_err(|e| format!("Invalid YAML task list: {}", e))
+    }
+}
+
+/// Parses the task list given to `automate`, as a JSON array or a YAML sequence, into task strings.
+///
+/// A leading `[` selects JSON; anything else is parsed as YAML, which allows comments and
+/// block scalars for long tasks. Items may be plain task strings or named steps (`{name, task}`).
+pub fn parse_task_list(text: &str) -> Result<Vec<String>, String> {
+    let steps = parse_steps(text)?;
+    Ok(steps.iter().map(|step| step.task().to_string()).collect())
+}
+
+impl WorkflowDefinition {
+    /// Parses a workflow definition from JSON and checks it is usable.
+    pub fn from_json(workflow_json: &str) -> Result<Self, WorkflowError> {
+        let definition: WorkflowDefinition = serde_json::from_str(workflow_json)
+            .map_err(|e| WorkflowError::InvalidDefinition(format!("Invalid JSON workflow: {}", e)))?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    /// Parses a workflow definition from YAML and checks it is usable.
+    pub fn from_yaml(workflow_yaml: &str) -> Result<Self, WorkflowError> {
+        let definition: WorkflowDefinition = serde_yaml::from_str(workflow_yaml)
+            .map_err(|e| WorkflowError::InvalidDefinition(format!("Invalid YAML workflow: {}", e)))?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    /// Parses a workflow definition given as either JSON or YAML.
+    ///
+    /// Text starting with `{` is parsed as JSON, so JSON definitions keep their precise error
+    /// messages; anything else is parsed as YAML.
+    pub fn parse(definition: &str) -> Result<Self, WorkflowError> {
+        if definition.trim_start().starts_with('{') {
+            Self::from_json(definition)
+        } else {
+            Self::from_yaml(definition)
+        }
+    }
+
+    // Checks the definition has a name and steps, that its TRY blocks are well formed, and that every
+    // placeholder refers to a declared parameter.
+    fn validate(&self) -> Result<(), WorkflowError> {
+        if self.name.trim().is_empty() {
+            return Err(WorkflowError::InvalidDefinition("Workflow name must not be empty".to_string()));
+        }
+        if self.steps.is_empty() {
+            return Err(WorkflowError::InvalidDefinition(format!("Workflow '{}' has no steps", self.name)));
+        }
+        let tasks: Vec<String> = self.steps.iter().map(|step| step.task().to_string()).collect();
+        try_catch::check_blocks(&tasks)
+            .and_then(|_| goto::check_labels(&tasks))
+            .and_then(|_| while_loop::check_loops(&tasks))
+            .map_err(|e| WorkflowError::InvalidDefinition(format!("Workflow '{}': {}", self.name, e)))?;
+        for (index, step) in self.steps.iter().enumerate() {
+            parse_call(step.task())?;
+            for (_, name) in param_placeholders(step.task()) {
+                if !self.params.iter().any(|param| param.name == name) {
+                    return Err(WorkflowError::InvalidDefinition(format!(
+                        "{} of workflow '{}' references undeclared parameter '{}'",
+                        step.describe(index), self.name, name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Combines the supplied parameter values with declared defaults, checking that every
+    // required parameter is supplied and that no undeclared one is.
+    fn resolve_params(&self, params: &HashMap<String, String>) -> Result<HashMap<String, String>, WorkflowError> {
+        if let Some(unknown) = params.keys().find(|key| !self.params.iter().any(|param| &param.name == *key)) {
+            return Err(WorkflowError::UnknownParameter { workflow: self.name.clone(), parameter: unknown.clone() });
+        }
+        let mut values = HashMap::new();
+        for param in &self.params {
+            let value = params.get(&param.name).or(param.default.as_ref()).ok_or_else(|| {
+                WorkflowError::MissingParameter { workflow: self.name.clone(), parameter: param.name.clone() }
+            })?;
+            values.insert(param.name.clone(), value.clone());
+        }
+        Ok(values)
+    }
+}
+
+/// Holds the workflows registered by the host page, by name.
+#[derive(Debug, Default)]
+pub struct WorkflowRegistry {
+    workflows: HashMap<String, WorkflowDefinition>,
+}
+
+impl WorkflowRegistry {
+    /// Registers a workflow, replacing any workflow previously registered under the same name.
+    pub fn register(&mut self, definition: WorkflowDefinition) {
+        if self.workflows.contains_key(&definition.name) {
+            console::log_1(&format!("Replacing previously registered workflow '{}'", definition.name).into());
+        }
+        self.workflows.insert(definition.name.clone(), definition);
+    }
+
+    /// Looks up the workflow registered under `name` and renders its steps with `params`,
+    /// replacing `CALL` steps with the rendered steps of the called workflows.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<String>)` with the ready-to-run task strings.
+    /// * `Err(WorkflowError)` if a workflow is unknown, its parameters don't match its
+    ///   declaration, workflows call each other in a cycle, or the rendered steps define a
+    ///   label twice (e.g. by calling the same workflow twice).
+    pub fn render(&self, name: &str, params: &HashMap<String, String>) -> Result<Vec<String>, WorkflowError> {
+        let mut steps = Vec::new();
+        self.expand(name, params, &mut vec![name.to_string()], &mut steps)?;
+        goto::check_labels(&steps).map_err(|e| WorkflowError::InvalidDefinition(format!("Workflow '{}': {}", name, e)))?;
+        Ok(steps)
+    }
+
+    // Appends the rendered steps of workflow `name` to `steps`. `call_stack` holds the names of
+    // the workflows currently being expanded, starting with the one run by the host page.
+    fn expand(
+        &self,
+        name: &str,
+        params: &HashMap<String, String>,
+        call_stack: &mut Vec<String>,
+        steps: &mut Vec<String>,
+    ) -> Result<(), WorkflowError> {
+        let definition = self.workflows.get(name).ok_or_else(|| WorkflowError::UnknownWorkflow(name.to_string()))?;
+        let values = definition.resolve_params(params)?;
+        for step in &definition.steps {
+            match parse_call(step.task())? {
+                Some((callee, param_templates)) => {
+                    if call_stack.iter().any(|caller| caller == callee) {
+                        let mut chain = call_stack.clone();
+                        chain.push(callee.to_string());
+                        return Err(WorkflowError::CallCycle { chain });
+                    }
+                    let callee_params = param_templates
+                        .iter()
+                        .map(|(key, template)| (key.clone(), render_template(template, &values)))
+                        .collect();
+                    call_stack.push(callee.to_string());
+                    self.expand(callee, &callee_params, call_stack, steps)?;
+                    call_stack.pop();
+                }
+                None => steps.push(render_template(step.task(), &values)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login_workflow() -> WorkflowDefinition {
+        WorkflowDefinition::from_json(
+            r#"{"name": "login", "params": [{"name": "email"}, {"name": "plan", "default": "free"}],
+                "steps": ["TYPE css:#email {{params.email}}", "SELECTOPTION css:#plan {{ params.plan }}", "CLICK css:#submit"]}"#,
+        ).unwrap()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn registry_with(workflows: &[&str]) -> WorkflowRegistry {
+        let mut registry = WorkflowRegistry::default();
+        registry.register(login_workflow());
+        for workflow_json in workflows {
+            registry.register(WorkflowDefinition::from_json(workflow_json).unwrap());
+        }
+        registry
+    }
+
+    #[test]
+    fn test_render_substitutes_params_and_defaults() {
+        let registry = registry_with(&[]);
+        let steps = registry.render("login", &params(&[("email", "a@b.co")])).unwrap();
+        assert_eq!(steps, vec!["TYPE css:#email a@b.co", "SELECTOPTION css:#plan free", "CLICK css:#submit"]);
+
+        let steps = registry.render("login", &params(&[("email", "a@b.co"), ("plan", "pro")])).unwrap();
+        assert_eq!(steps[1], "SELECTOPTION css:#plan pro");
+    }
+
+    #[test]
+    fn test_render_rejects_missing_and_unknown_params() {
+        let registry = registry_with(&[]);
+        assert_eq!(
+            registry.render("login", &HashMap::new()),
+            Err(WorkflowError::MissingParameter { workflow: "login".to_string(), parameter: "email".to_string() })
+        );
+        assert_eq!(
+            registry.render("login", &params(&[("email", "a@b.co"), ("emial", "typo")])),
+            Err(WorkflowError::UnknownParameter { workflow: "login".to_string(), parameter: "emial".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_definition_validation() {
+        assert!(matches!(
+            WorkflowDefinition::from_json(r#"{"name": "w", "steps": ["TYPE css:#q {{params.query}}"]}"#),
+            Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("undeclared parameter 'query'")
+        ));
+        assert!(matches!(WorkflowDefinition::from_json(r#"{"name": "w", "steps": []}"#), Err(WorkflowError::InvalidDefinition(_))));
+        assert!(matches!(WorkflowDefinition::from_json(r#"{"steps": ["CLICK css:#a"]}"#), Err(WorkflowError::InvalidDefinition(_))));
+        // Other placeholders, such as {{PREVIOUS_RESULT}}, are left for the task runner.
+        let registry = registry_with(&[r#"{"name": "w", "steps": ["READ css:#a", "TYPE css:#b {{PREVIOUS_RESULT}}"]}"#]);
+        assert_eq!(registry.render("w", &HashMap::new()).unwrap()[1], "TYPE css:#b {{PREVIOUS_RESULT}}");
+        assert!(matches!(
+            WorkflowDefinition::from_json(r#"{"name": "w", "steps": ["CALL login email=a@b.co"]}"#),
+            Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("must be a JSON object")
+        ));
+        assert!(matches!(
+            WorkflowDefinition::from_json(r#"{"name": "w", "steps": ["WHILE IS_VISIBLE css:.more", "CLICK css:.more"]}"#),
+            Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("not closed with END_WHILE")
+        ));
+    }
+
+    #[test]
+    fn test_call_expands_sub_workflows_with_params() {
+        let registry = registry_with(&[
+            r#"{"name": "dismiss_cookies", "steps": ["CLICK_IF_EXISTS css:#accept-cookies"]}"#,
+            r#"{"name": "checkout", "params": [{"name": "user"}],
+                "steps": ["call dismiss_cookies", "CALL login {\"email\": \"{{params.user}}@shop.test\", \"plan\": \"pro\"}", "CLICK css:#buy"]}"#,
+        ]);
+        let steps = registry.render("checkout", &params(&[("user", "ann")])).unwrap();
+        assert_eq!(steps, vec![
+            "CLICK_IF_EXISTS css:#accept-cookies",
+            "TYPE css:#email ann@shop.test",
+            "SELECTOPTION css:#plan pro",
+            "CLICK css:#submit",
+            "CLICK css:#buy",
+        ]);
+
+        // Calling a workflow twice in sequence is not a cycle.
+        let registry = registry_with(&[r#"{"name": "twice", "steps": ["CALL login {\"email\": \"a\"}", "CALL login {\"email\": \"b\"}"]}"#]);
+        assert_eq!(registry.render("twice", &HashMap::new()).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_call_detects_cycles_and_unknown_callees() {
+        let registry = registry_with(&[
+            r#"{"name": "a", "steps": ["CALL b"]}"#,
+            r#"{"name": "b", "steps": ["READ css:h1", "CALL a"]}"#,
+            r#"{"name": "c", "steps": ["CALL missing"]}"#,
+        ]);
+        assert_eq!(
+            registry.render("a", &HashMap::new()),
+            Err(WorkflowError::CallCycle { chain: vec!["a".to_string(), "b".to_string(), "a".to_string()] })
+        );
+        assert_eq!(registry.render("c", &HashMap::new()), Err(WorkflowError::UnknownWorkflow("missing".to_string())));
+    }
+
+    #[test]
+    fn test_labels_are_checked() {
+        assert!(matches!(
+            WorkflowDefinition::from_json(r#"{"name": "w", "steps": ["CLICK css:#a", "GOTO start"]}"#),
+            Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("unknown label 'start'")
+        ));
+        // Each workflow is valid alone, but calling `retry` twice defines its label twice.
+        let registry = registry_with(&[
+            r#"{"name": "retry", "steps": ["LABEL again", "TRY", "CLICK css:#a", "CATCH", "GOTO again 3", "END_TRY"]}"#,
+            r#"{"name": "twice", "steps": ["CALL retry", "CALL retry"]}"#,
+        ]);
+        assert_eq!(registry.render("retry", &HashMap::new()).unwrap().len(), 6);
+        assert!(matches!(
+            registry.render("twice", &HashMap::new()),
+            Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("label 'again' is defined twice")
+        ));
+    }
+
+    #[test]
+    fn test_yaml_definitions_with_named_steps() {
+        let definition = WorkflowDefinition::parse(r#"
+# Signs in, then opens the billing page
+name: billing
+params:
+  - name: email
+steps:
+  - 'CALL login {"email": "{{params.email}}"}'
+  - name: Open billing
+    task: >-
+      CLICK
+      css:#billing-link
+"#).unwrap();
+        assert_eq!(definition.steps[1], WorkflowStep::Named {
+            name: "Open billing".to_string(),
+            task: "CLICK css:#billing-link".to_string(),
+        });
+        let mut registry = registry_with(&[]);
+        registry.register(definition);
+        let steps = registry.render("billing", &params(&[("email", "a@b.co")])).unwrap();
+        assert_eq!(steps.first().map(String::as_str), Some("TYPE css:#email a@b.co"));
+        assert_eq!(steps.last().map(String::as_str), Some("CLICK css:#billing-link"));
+
+        assert!(matches!(
+            WorkflowDefinition::parse("name: w\nsteps:\n  - name: Search\n    task: TYPE css:#q {{params.query}}\n"),
+            Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("Step 0 ('Search')")
+        ));
+        assert!(matches!(WorkflowDefinition::parse("name: [w"), Err(WorkflowError::InvalidDefinition(msg)) if msg.contains("YAML")));
+    }
+
+    #[test]
+    fn test_parse_task_list_accepts_json_and_yaml() {
+        let expected = vec!["CLICK css:#a", "READ css:#b"];
+        assert_eq!(parse_task_list(r#"["CLICK css:#a", "READ css:#b"]"#).unwrap(), expected);
+        assert_eq!(parse_task_list("- CLICK css:#a # the first button\n- name: Read label\n  task: READ css:#b\n").unwrap(), expected);
+        assert!(parse_task_list("[\"CLICK css:#a\"").unwrap_err().contains("JSON"));
+        assert!(parse_task_list("task: CLICK css:#a").is_err(), "A mapping is not a task list");
+    }
+
+    #[test]
+    fn test_registry_render_by_name() {
+        let mut registry = WorkflowRegistry::default();
+        registry.register(login_workflow());
+        assert_eq!(registry.render("login", &params(&[("email", "x@y.z")])).unwrap().len(), 3);
+        assert_eq!(registry.render("signup", &HashMap::new()), Err(WorkflowError::UnknownWorkflow("signup".to_string())));
+    }
+}